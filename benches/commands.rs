@@ -0,0 +1,169 @@
+//! Benchmarks for the full command path.
+//!
+//! Each iteration drives `handle_client` over an in-memory duplex stream,
+//! so the numbers cover command parsing, dispatch and the handlers against
+//! an in-memory SQLite database without any socket overhead.
+//!
+//! Run with `cargo bench --bench commands`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use renews::auth::DynAuth;
+use renews::auth::sqlite::SqliteAuth;
+use renews::config::Config;
+use renews::handle_client;
+use renews::limits::UsageTracker;
+use renews::queue::{ArticleQueue, WorkerPool};
+use renews::storage::DynStorage;
+use renews::storage::sqlite::SqliteStorage;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime::Runtime;
+use tokio::sync::RwLock;
+
+const GROUP: &str = "bench.group";
+const ARTICLE_COUNT: u64 = 1000;
+
+/// Shared server state reused by every simulated connection.
+struct Harness {
+    storage: DynStorage,
+    auth: DynAuth,
+    cfg: Arc<RwLock<Config>>,
+    queue: ArticleQueue,
+    usage_tracker: Arc<UsageTracker>,
+}
+
+impl Harness {
+    async fn new() -> Self {
+        let storage: DynStorage = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+        let auth: DynAuth = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+        let mut cfg: Config = toml::from_str("addr=\":119\"").unwrap();
+        cfg.allow_anonymous_posting = true;
+        let usage_tracker = Arc::new(UsageTracker::new(auth.clone(), cfg.user_limits.clone()));
+        let cfg = Arc::new(RwLock::new(cfg));
+
+        storage.add_group(GROUP, false).await.unwrap();
+        for n in 1..=ARTICLE_COUNT {
+            let (_, msg) = renews::parse_message(&article_text(&format!("<seed{n}@bench>")))
+                .expect("valid seed article");
+            storage.store_article(&msg).await.unwrap();
+        }
+
+        let queue = ArticleQueue::new(1024);
+        let pool = WorkerPool::new(queue.clone(), storage.clone(), auth.clone(), cfg.clone(), 2);
+        let _handles = pool.start().await;
+
+        Self {
+            storage,
+            auth,
+            cfg,
+            queue,
+            usage_tracker,
+        }
+    }
+
+    /// Run one client session sending `script` and return the bytes received.
+    async fn session(&self, script: &[u8]) -> usize {
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let server_task = tokio::spawn(handle_client(
+            server,
+            self.storage.clone(),
+            self.auth.clone(),
+            self.cfg.clone(),
+            false,
+            self.queue.clone(),
+            self.usage_tracker.clone(),
+        ));
+
+        let (mut rd, mut wr) = tokio::io::split(&mut client);
+        let write = async {
+            wr.write_all(script).await.unwrap();
+            wr.write_all(b"QUIT\r\n").await.unwrap();
+        };
+        let mut received = Vec::new();
+        let read = rd.read_to_end(&mut received);
+        let (_, read) = tokio::join!(write, read);
+        read.unwrap();
+        drop(client);
+        let _ = server_task.await;
+        received.len()
+    }
+}
+
+fn article_text(message_id: &str) -> String {
+    format!(
+        "From: bench@example.com\r\nSubject: Benchmark article\r\nNewsgroups: {GROUP}\r\n\
+         Date: Mon, 1 Jan 2024 12:00:00 +0000\r\nMessage-ID: {message_id}\r\n\r\n\
+         Benchmark body line one.\r\nBenchmark body line two.\r\n"
+    )
+}
+
+fn bench_group(c: &mut Criterion, rt: &Runtime, harness: &Harness) {
+    let script = format!("GROUP {GROUP}\r\n");
+    c.bench_function("group", |b| {
+        b.to_async(rt).iter(|| harness.session(script.as_bytes()))
+    });
+}
+
+fn bench_over(c: &mut Criterion, rt: &Runtime, harness: &Harness) {
+    let mut group = c.benchmark_group("over");
+    for count in [10u64, 100, ARTICLE_COUNT] {
+        let script = format!("GROUP {GROUP}\r\nOVER 1-{count}\r\n");
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &script, |b, script| {
+            b.to_async(rt).iter(|| harness.session(script.as_bytes()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_article(c: &mut Criterion, rt: &Runtime, harness: &Harness) {
+    let mut script = format!("GROUP {GROUP}\r\n");
+    for n in 1..=50 {
+        script.push_str(&format!("ARTICLE {n}\r\n"));
+    }
+    let mut group = c.benchmark_group("article");
+    group.throughput(Throughput::Elements(50));
+    group.bench_function("by_number", |b| {
+        b.to_async(rt).iter(|| harness.session(script.as_bytes()))
+    });
+    group.finish();
+}
+
+fn bench_pipelined(c: &mut Criterion, rt: &Runtime, harness: &Harness) {
+    let mut script = format!("GROUP {GROUP}\r\n");
+    for n in 1..=50 {
+        script.push_str(&format!("OVER {n}\r\nHDR Subject {n}\r\n"));
+    }
+    let mut group = c.benchmark_group("pipelined");
+    group.throughput(Throughput::Elements(100));
+    group.bench_function("over_hdr", |b| {
+        b.to_async(rt).iter(|| harness.session(script.as_bytes()))
+    });
+    group.finish();
+}
+
+fn bench_post(c: &mut Criterion, rt: &Runtime, harness: &Harness) {
+    let counter = AtomicU64::new(0);
+    c.bench_function("post", |b| {
+        b.to_async(rt).iter(|| {
+            let n = counter.fetch_add(1, Ordering::Relaxed);
+            let script = format!("POST\r\n{}.\r\n", article_text(&format!("<post{n}@bench>")));
+            async move { harness.session(script.as_bytes()).await }
+        })
+    });
+}
+
+fn commands(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let harness = rt.block_on(Harness::new());
+
+    bench_group(c, &rt, &harness);
+    bench_over(c, &rt, &harness);
+    bench_article(c, &rt, &harness);
+    bench_pipelined(c, &rt, &harness);
+    bench_post(c, &rt, &harness);
+}
+
+criterion_group!(benches, commands);
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+//! Benchmarks for the parsers on the hot path of every connection.
+//!
+//! `parse_command` runs for each line a client sends, wildmat matching for
+//! each group a `LIST` or `NEWNEWS` pattern is checked against, and
+//! `parse_message` for each posted or fed article. None of them touch
+//! storage, so regressions show here before they are lost in the noise of
+//! the `commands` benchmark.
+//!
+//! Run with `cargo bench --bench parsing`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use renews::wildmat::{wildmat, wildmat_list};
+use renews::{parse_command, parse_message};
+use std::hint::black_box;
+
+/// A mix of the lines a newsreader sends while reading a group.
+const COMMAND_LINES: &[&str] = &[
+    "GROUP comp.lang.rust\r\n",
+    "OVER 3000-3100\r\n",
+    "HDR Subject 3000-3100\r\n",
+    "ARTICLE <abc.123@example.com>\r\n",
+    "LIST ACTIVE comp.lang.*\r\n",
+    "NEWNEWS comp.*,!comp.binaries.* 20240101 000000 GMT\r\n",
+    "AUTHINFO USER reader\r\n",
+    "QUIT\r\n",
+];
+
+const PATTERNS: &[&str] = &[
+    "comp.lang.rust",
+    "comp.*",
+    "*.rust",
+    "comp.lang.[cr]*",
+    "?omp.*.r?st",
+];
+
+fn group_names() -> Vec<String> {
+    let hierarchies = ["comp", "sci", "rec", "alt", "misc", "news"];
+    (0..1000)
+        .map(|n| {
+            let top = hierarchies[n % hierarchies.len()];
+            format!("{top}.group{}.sub{}", n / 10, n % 10)
+        })
+        .collect()
+}
+
+fn article_text(body_lines: usize) -> String {
+    let mut text = String::from(
+        "Path: news.example.com!not-for-mail\r\nFrom: bench@example.com\r\n\
+         Newsgroups: comp.lang.rust,comp.lang.c\r\nSubject: Benchmark article\r\n\
+         Date: Mon, 1 Jan 2024 12:00:00 +0000\r\nMessage-ID: <parse@bench>\r\n\
+         References: <a@bench> <b@bench> <c@bench>\r\n\r\n",
+    );
+    for n in 0..body_lines {
+        text.push_str(&format!(
+            "Benchmark body line {n} with some text to parse.\r\n"
+        ));
+    }
+    text
+}
+
+fn bench_parse_command(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_command");
+    group.throughput(Throughput::Elements(COMMAND_LINES.len() as u64));
+    group.bench_function("reader_mix", |b| {
+        b.iter(|| {
+            for line in COMMAND_LINES {
+                black_box(parse_command(black_box(line)).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_wildmat(c: &mut Criterion) {
+    let names = group_names();
+    let mut group = c.benchmark_group("wildmat");
+    group.throughput(Throughput::Elements(names.len() as u64));
+    for pattern in PATTERNS {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(pattern),
+            pattern,
+            |b, pattern| b.iter(|| names.iter().filter(|name| wildmat(pattern, name)).count()),
+        );
+    }
+    let list = ["comp.*", "!comp.binaries.*", "sci.*", "!*.test"];
+    group.bench_function("list", |b| {
+        b.iter(|| {
+            names
+                .iter()
+                .filter(|name| wildmat_list(&list, name))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn bench_parse_message(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_message");
+    for lines in [10usize, 1000] {
+        let text = article_text(lines);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(lines), &text, |b, text| {
+            b.iter(|| black_box(parse_message(black_box(text)).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_parse_command,
+    bench_wildmat,
+    bench_parse_message
+);
+criterion_main!(benches);
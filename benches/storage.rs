@@ -0,0 +1,116 @@
+//! Benchmarks for concurrent article storage.
+//!
+//! Each iteration stores a batch of articles into a fresh file-backed SQLite
+//! database from several tasks at once, as the article queue's workers do,
+//! so journal modes and numbers of writers can be compared. A second group
+//! stores the same articles through `store_articles` in batches of several
+//! sizes, as a worker does when articles are waiting in the queue.
+//!
+//! Run with `cargo bench --bench storage`.
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use renews::config::{SqliteConfig, SqliteJournal};
+use renews::storage::Storage;
+use renews::storage::sqlite::SqliteStorage;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+const ARTICLES: usize = 200;
+
+fn article_text(n: usize) -> String {
+    format!(
+        "From: bench@example.com\r\nSubject: Benchmark article\r\n\
+         Newsgroups: bench.one,bench.two\r\nDate: Mon, 1 Jan 2024 12:00:00 +0000\r\n\
+         Message-ID: <store{n}@bench>\r\n\r\n\
+         Benchmark body line one.\r\nBenchmark body line two.\r\n"
+    )
+}
+
+/// Open an empty database in a new directory.
+async fn fresh(journal_mode: SqliteJournal) -> (TempDir, Arc<SqliteStorage>) {
+    let dir = tempfile::tempdir().unwrap();
+    let uri = format!("sqlite://{}/news.db", dir.path().display());
+    let cfg = SqliteConfig {
+        journal_mode,
+        ..SqliteConfig::default()
+    };
+    let storage = SqliteStorage::with_config(&uri, &cfg).await.unwrap();
+    (dir, Arc::new(storage))
+}
+
+/// Store `ARTICLES` articles split between `writers` tasks.
+async fn store_concurrently(storage: Arc<SqliteStorage>, writers: usize) {
+    let tasks: Vec<_> = (0..writers)
+        .map(|writer| {
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                for n in (writer..ARTICLES).step_by(writers) {
+                    let (_, msg) = renews::parse_message(&article_text(n)).unwrap();
+                    storage.store_article(&msg).await.unwrap();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+/// Store `ARTICLES` articles from one task, `batch` articles per transaction.
+async fn store_batched(storage: Arc<SqliteStorage>, batch: usize) {
+    let articles: Vec<_> = (0..ARTICLES)
+        .map(|n| renews::parse_message(&article_text(n)).unwrap().1)
+        .collect();
+    for chunk in articles.chunks(batch) {
+        storage.store_articles(chunk).await.unwrap();
+    }
+}
+
+fn store_article(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("store_article");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(ARTICLES as u64));
+    for (name, mode) in [
+        ("wal", SqliteJournal::Wal),
+        ("delete", SqliteJournal::Delete),
+    ] {
+        for writers in [1, 4, 16] {
+            group.bench_with_input(BenchmarkId::new(name, writers), &writers, |b, &writers| {
+                b.iter_batched(
+                    || rt.block_on(fresh(mode)),
+                    |(dir, storage)| {
+                        rt.block_on(store_concurrently(storage, writers));
+                        dir
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+fn store_articles(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("store_articles");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(ARTICLES as u64));
+    for batch in [1, 16, 64] {
+        group.bench_with_input(BenchmarkId::new("wal", batch), &batch, |b, &batch| {
+            b.iter_batched(
+                || rt.block_on(fresh(SqliteJournal::Wal)),
+                |(dir, storage)| {
+                    rt.block_on(store_batched(storage, batch));
+                    dir
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, store_article, store_articles);
+criterion_main!(benches);
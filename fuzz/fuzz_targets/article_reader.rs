@@ -0,0 +1,39 @@
+//! Feed arbitrary bytes to the article reader, split at arbitrary points.
+//!
+//! Run with `cargo +nightly fuzz run article_reader` from the repository
+//! root. The reader must never buffer more than the size limit, and how the
+//! input is split into reads must not change the result.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use renews::article_reader::{ArticleLimits, ArticleReader};
+
+const MAX_BYTES: u64 = 4096;
+
+fn read(data: &[u8], chunk: usize) -> (Option<usize>, String) {
+    let limits = ArticleLimits {
+        max_bytes: Some(MAX_BYTES),
+        max_headers: 16,
+        max_header_bytes: 256,
+    };
+    let mut reader = ArticleReader::new(limits);
+    let mut offset = 0;
+    for piece in data.chunks(chunk.max(1)) {
+        let used = reader.feed(piece);
+        assert!(reader.buffered() as u64 <= MAX_BYTES);
+        if let Some(used) = used {
+            offset += used;
+            return (Some(offset), format!("{:?}", reader.finish().ok()));
+        }
+        offset += piece.len();
+    }
+    (None, String::new())
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, data)) = data.split_first() else {
+        return;
+    };
+    assert_eq!(read(data, data.len()), read(data, usize::from(split)));
+});
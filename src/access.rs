@@ -0,0 +1,353 @@
+//! Address ban and allow lists.
+//!
+//! Administrators ban networks, given in CIDR notation, with
+//! `renews admin ban-address`, and exempt networks from bans with
+//! `renews admin allow-address`. The rules are kept in the authentication
+//! database so every listener and every server sharing the database sees
+//! them; each server keeps a copy in memory, read again from the database
+//! every [`REFRESH_INTERVAL`], and checks it when a connection is accepted.
+//! A banned address is answered `502` and disconnected; an allowed address
+//! is never banned and is not held to `max_connections_per_ip`.
+//!
+//! Failed `AUTHINFO` logins are recorded by address in the authentication
+//! database as well, where the login throttle of
+//! [`crate::auth::throttle`] counts them too. With `[address_bans]
+//! auth_failures` set, an address that fails that many times within
+//! `auth_failure_window` is banned for `ban_duration`, like fail2ban would.
+
+use crate::auth::DynAuth;
+use crate::config::AddressBanConfig;
+use anyhow::{Result, anyhow};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// How long the rules read from the database are used before they are read
+/// again.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A network in CIDR notation, or a single address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// The network of `addr` with a `prefix`-bit mask; host bits are
+    /// cleared.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `prefix` is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self> {
+        let addr = canonical(addr);
+        let bits = max_prefix(addr);
+        if prefix > bits {
+            return Err(anyhow!("prefix /{prefix} is longer than {bits} bits"));
+        }
+        Ok(Self {
+            addr: mask(addr, prefix),
+            prefix,
+        })
+    }
+
+    /// Whether `ip` lies in the network.
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        ip.is_ipv4() == self.addr.is_ipv4() && mask(ip, self.prefix) == self.addr
+    }
+}
+
+impl From<IpAddr> for IpNetwork {
+    fn from(addr: IpAddr) -> Self {
+        let addr = canonical(addr);
+        Self {
+            addr,
+            prefix: max_prefix(addr),
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| anyhow!("invalid address '{addr}'"))?;
+        match prefix {
+            Some(prefix) => {
+                let prefix = prefix
+                    .parse()
+                    .map_err(|_| anyhow!("invalid prefix length '{prefix}'"))?;
+                Self::new(addr, prefix)
+            }
+            None => Ok(Self::from(addr)),
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// IPv4 clients reaching a dual-stack socket appear as IPv4-mapped IPv6
+/// addresses; they are matched as the IPv4 addresses they are.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+fn max_prefix(ip: IpAddr) -> u8 {
+    if ip.is_ipv4() { 32 } else { 128 }
+}
+
+fn mask(ip: IpAddr, prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let bits = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V4((u32::from(v4) & bits).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V6((u128::from(v6) & bits).into())
+        }
+    }
+}
+
+/// What a rule does to the addresses it covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressAction {
+    /// Connections are refused
+    Ban,
+    /// Connections are never banned nor limited by address
+    Allow,
+}
+
+impl AddressAction {
+    /// Name of the action as stored in the database
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ban => "ban",
+            Self::Allow => "allow",
+        }
+    }
+}
+
+impl FromStr for AddressAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ban" => Ok(Self::Ban),
+            "allow" => Ok(Self::Allow),
+            _ => Err(anyhow!("unknown address action '{s}'")),
+        }
+    }
+}
+
+/// One entry of the ban and allow lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressRule {
+    /// Addresses the rule covers
+    pub network: IpNetwork,
+    /// Whether they are banned or allowed
+    pub action: AddressAction,
+    /// Why the rule was added
+    pub reason: Option<String>,
+    /// Unix timestamp after which the rule no longer applies, or `None` if
+    /// it is permanent
+    pub expires_at: Option<i64>,
+}
+
+impl fmt::Display for AddressRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.action.as_str(), self.network)?;
+        match self
+            .expires_at
+            .and_then(|at| chrono::DateTime::from_timestamp(at, 0))
+        {
+            Some(at) => write!(f, "\t{}", at.to_rfc3339())?,
+            None => write!(f, "\tpermanent")?,
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, "\t{reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// How the lists treat an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressStatus {
+    /// The address is covered by an allow rule
+    Allowed,
+    /// The address is covered by a ban and no allow rule
+    Banned,
+    /// No rule covers the address
+    Unlisted,
+}
+
+/// In-memory copy of the ban and allow lists of the authentication
+/// database.
+pub struct AccessList {
+    auth: DynAuth,
+    rules: RwLock<Option<(Instant, Vec<AddressRule>)>>,
+}
+
+impl AccessList {
+    #[must_use]
+    pub fn new(auth: DynAuth) -> Self {
+        Self {
+            auth,
+            rules: RwLock::new(None),
+        }
+    }
+
+    /// How the lists treat `ip` now. If the database cannot be read, the
+    /// rules last read are used.
+    pub async fn status(&self, ip: IpAddr) -> AddressStatus {
+        let now = chrono::Utc::now().timestamp();
+        let fresh = matches!(
+            &*self.rules.read().await,
+            Some((read_at, _)) if read_at.elapsed() < REFRESH_INTERVAL
+        );
+        if !fresh {
+            self.refresh().await;
+        }
+        let rules = self.rules.read().await;
+        let live = rules
+            .iter()
+            .flat_map(|(_, rules)| rules)
+            .filter(|rule| rule.expires_at.is_none_or(|at| at > now))
+            .filter(|rule| rule.network.contains(ip));
+        let mut status = AddressStatus::Unlisted;
+        for rule in live {
+            match rule.action {
+                AddressAction::Allow => return AddressStatus::Allowed,
+                AddressAction::Ban => status = AddressStatus::Banned,
+            }
+        }
+        status
+    }
+
+    /// Read the rules from the database again.
+    pub async fn refresh(&self) {
+        let now = chrono::Utc::now().timestamp();
+        match self.auth.list_address_rules(now).await {
+            Ok(rules) => *self.rules.write().await = Some((Instant::now(), rules)),
+            Err(e) => {
+                warn!(error = %e, "Failed to read address rules");
+                // Keep the rules last read, and try again at the next
+                // interval rather than at every connection
+                let mut guard = self.rules.write().await;
+                let rules = guard.take().map(|(_, rules)| rules).unwrap_or_default();
+                *guard = Some((Instant::now(), rules));
+            }
+        }
+    }
+
+    /// Record a failed login from `ip` at `now`, forgetting the failures of
+    /// the address older than `keep` seconds, or none if `keep` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be written.
+    pub async fn record_auth_failure(&self, ip: IpAddr, keep: Option<u64>, now: i64) -> Result<()> {
+        let address = IpNetwork::from(ip).to_string();
+        self.auth
+            .record_auth_failure(&address, since(keep, now), now)
+            .await?;
+        Ok(())
+    }
+
+    /// Ban the address of `ip` for `cfg.ban_duration` if it has failed to log
+    /// in `cfg.auth_failures` times within `cfg.auth_failure_window`.
+    /// Returns whether the address is now banned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read or written.
+    pub async fn ban_after_failures(
+        &self,
+        ip: IpAddr,
+        cfg: &AddressBanConfig,
+        now: i64,
+    ) -> Result<bool> {
+        if cfg.auth_failures == 0 || self.status(ip).await == AddressStatus::Allowed {
+            return Ok(false);
+        }
+        let network = IpNetwork::from(ip);
+        let failures = self
+            .auth
+            .count_auth_failures(&network.to_string(), since(cfg.auth_failure_window, now))
+            .await?;
+        if failures < cfg.auth_failures {
+            return Ok(false);
+        }
+        let rule = AddressRule {
+            network,
+            action: AddressAction::Ban,
+            reason: Some(format!("{failures} failed logins")),
+            expires_at: cfg
+                .ban_duration
+                .map(|secs| now.saturating_add(i64::try_from(secs).unwrap_or(i64::MAX))),
+        };
+        self.auth.add_address_rule(&rule).await?;
+        self.auth
+            .clear_auth_failures(&rule.network.to_string())
+            .await?;
+        info!(network = %rule.network, failures, "Address banned after failed logins");
+        self.refresh().await;
+        Ok(true)
+    }
+}
+
+/// Start of a window of `secs` seconds ending at `now`; a window of `None`
+/// has no start.
+fn since(secs: Option<u64>, now: i64) -> i64 {
+    secs.map_or(i64::MIN, |secs| {
+        now.saturating_sub(i64::try_from(secs).unwrap_or(i64::MAX))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn networks_match_their_addresses() {
+        let net: IpNetwork = "192.0.2.77/24".parse().unwrap();
+        assert_eq!(net.to_string(), "192.0.2.0/24");
+        assert!(net.contains("192.0.2.1".parse().unwrap()));
+        assert!(!net.contains("192.0.3.1".parse().unwrap()));
+        // IPv4 clients of a dual-stack socket
+        assert!(net.contains("::ffff:192.0.2.9".parse().unwrap()));
+
+        let single: IpNetwork = "2001:db8::1".parse().unwrap();
+        assert_eq!(single.to_string(), "2001:db8::1/128");
+        assert!(single.contains("2001:db8::1".parse().unwrap()));
+        assert!(!single.contains("2001:db8::2".parse().unwrap()));
+
+        let everything: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains("203.0.113.5".parse().unwrap()));
+        assert!(!everything.contains("2001:db8::1".parse().unwrap()));
+
+        assert!("192.0.2.0/33".parse::<IpNetwork>().is_err());
+        assert!("example.org".parse::<IpNetwork>().is_err());
+    }
+}
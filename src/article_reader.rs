@@ -0,0 +1,419 @@
+//! Streaming reader for dot-terminated articles.
+//!
+//! POST, IHAVE and TAKETHIS receive articles as a block of CRLF-terminated
+//! lines ended by a line holding a single dot. [`ArticleReader`] is a state
+//! machine fed the bytes of such a block as they arrive. It removes
+//! dot-stuffing as it goes and checks the article against [`ArticleLimits`]
+//! byte by byte, so that no line is ever buffered beyond the limits: a client
+//! sending one endless header line is cut off as soon as the header is too
+//! long rather than once the line ends. Articles with bare CR or LF
+//! characters or NUL bytes are refused.
+//!
+//! A refused article is still read up to its terminating line, without
+//! buffering it, so the connection stays in sync and the client can be
+//! answered. The reader needs no I/O of its own; [`read_article_block`]
+//! drives it from a connection.
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Most header fields an article may have
+pub const MAX_HEADERS: usize = 1000;
+
+/// Most bytes one header field may take, including its continuation lines
+pub const MAX_HEADER_BYTES: usize = 64 * 1024;
+
+/// Limits an article is checked against while it is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArticleLimits {
+    /// Largest article in bytes after dot-stuffing is removed, if limited
+    pub max_bytes: Option<u64>,
+    /// Most header fields
+    pub max_headers: usize,
+    /// Most bytes of a header field, including continuation lines
+    pub max_header_bytes: usize,
+}
+
+impl Default for ArticleLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: None,
+            max_headers: MAX_HEADERS,
+            max_header_bytes: MAX_HEADER_BYTES,
+        }
+    }
+}
+
+impl ArticleLimits {
+    /// The default header limits with an article size limit of `max_bytes`.
+    #[must_use]
+    pub fn with_max_bytes(max_bytes: Option<u64>) -> Self {
+        Self {
+            max_bytes,
+            ..Self::default()
+        }
+    }
+}
+
+/// Why an article was refused while it was read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MalformedArticle {
+    /// More header fields than [`ArticleLimits::max_headers`]
+    TooManyHeaders,
+    /// A header field longer than [`ArticleLimits::max_header_bytes`]
+    HeaderTooLong,
+    /// A CR not followed by LF
+    BareCr,
+    /// An LF not preceded by CR
+    BareLf,
+    /// A NUL byte
+    Nul,
+}
+
+impl fmt::Display for MalformedArticle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::TooManyHeaders => "too many header fields",
+            Self::HeaderTooLong => "header field too long",
+            Self::BareCr => "bare CR in article",
+            Self::BareLf => "bare LF in article",
+            Self::Nul => "NUL byte in article",
+        })
+    }
+}
+
+impl std::error::Error for MalformedArticle {}
+
+/// Metadata computed incrementally while reading a dot-terminated article.
+///
+/// Produced by [`read_article_block`] so that handlers and filters do not need
+/// to rescan the article text to find its size, line count or digest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArticleMetadata {
+    /// Size of the article in bytes after dot-stuffing has been removed
+    pub size: u64,
+    /// Number of lines in the article body (after the blank separator line)
+    pub lines: u64,
+    /// SHA-256 digest of the article as received
+    pub sha256: [u8; 32],
+}
+
+impl ArticleMetadata {
+    /// Compute the metadata of an article received in one piece rather than
+    /// as a dot-terminated block, such as through the HTTP API.
+    pub fn from_text(text: &str) -> Self {
+        let lines = text
+            .split_once("\r\n\r\n")
+            .or_else(|| text.split_once("\n\n"))
+            .map_or(0, |(_, body)| body.lines().count() as u64);
+        Self {
+            size: text.len() as u64,
+            lines,
+            sha256: Sha256::digest(text.as_bytes()).into(),
+        }
+    }
+
+    /// Hex-encoded SHA-256 digest of the article.
+    pub fn sha256_hex(&self) -> String {
+        use std::fmt::Write;
+        self.sha256
+            .iter()
+            .fold(String::with_capacity(64), |mut out, b| {
+                let _ = write!(out, "{b:02x}");
+                out
+            })
+    }
+}
+
+/// A dot-terminated block read from a client.
+#[derive(Debug)]
+pub enum ArticleBlock {
+    /// The block was read in full and fits within the limits.
+    Complete {
+        text: String,
+        metadata: ArticleMetadata,
+    },
+    /// The block exceeded the size limit. Its contents were drained from the
+    /// connection and discarded; `size` is the number of bytes that were read.
+    TooLarge { size: u64 },
+    /// The block broke one of the other limits or was not well formed. Its
+    /// contents were drained from the connection and discarded.
+    Malformed { reason: MalformedArticle },
+}
+
+/// Where the reader is within the current line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Nothing of the line has been read
+    LineStart,
+    /// The line so far is a single dot, which may be stuffing or the
+    /// terminating line
+    Dot,
+    /// The line so far is a dot and a CR
+    DotCr,
+    /// Within the line
+    InLine,
+    /// After a CR within the line
+    Cr,
+    /// The terminating line has been read
+    Done,
+}
+
+/// Streaming parser of a dot-terminated article.
+///
+/// Feed it the bytes of the connection with [`ArticleReader::feed`] until it
+/// reports the end of the block, then take the result with
+/// [`ArticleReader::finish`].
+#[derive(Debug)]
+pub struct ArticleReader {
+    limits: ArticleLimits,
+    state: State,
+    /// The article without dot-stuffing, dropped once it is refused
+    buf: Vec<u8>,
+    size: u64,
+    lines: u64,
+    in_body: bool,
+    /// Bytes of the current line so far, after dot-stuffing
+    line_len: usize,
+    /// Whether the current line holds nothing but its line ending so far
+    line_blank: bool,
+    headers: usize,
+    /// Bytes of the current header field so far
+    header_len: usize,
+    too_large: bool,
+    malformed: Option<MalformedArticle>,
+}
+
+impl ArticleReader {
+    /// A reader for one article checked against `limits`.
+    #[must_use]
+    pub fn new(limits: ArticleLimits) -> Self {
+        Self {
+            limits,
+            state: State::LineStart,
+            buf: Vec::new(),
+            size: 0,
+            lines: 0,
+            in_body: false,
+            line_len: 0,
+            line_blank: true,
+            headers: 0,
+            header_len: 0,
+            too_large: false,
+            malformed: None,
+        }
+    }
+
+    /// Read the bytes of `data`. Returns the number of bytes used once the
+    /// terminating line has been read, leaving the rest for the next
+    /// command, or `None` if the whole of `data` was used and more is needed.
+    pub fn feed(&mut self, data: &[u8]) -> Option<usize> {
+        if self.is_done() {
+            return Some(0);
+        }
+        for (i, &byte) in data.iter().enumerate() {
+            self.step(byte);
+            if self.state == State::Done {
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+
+    /// True once the terminating line has been read.
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Bytes of the article held so far. Never more than the size limit.
+    #[must_use]
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// The article read, or why it was refused.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the terminating line has not been read or the
+    /// article is not valid UTF-8.
+    pub fn finish(self) -> Result<ArticleBlock> {
+        if !self.is_done() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed before end of article (missing '.' terminator)",
+            )
+            .into());
+        }
+        if self.too_large {
+            return Ok(ArticleBlock::TooLarge { size: self.size });
+        }
+        if let Some(reason) = self.malformed {
+            return Ok(ArticleBlock::Malformed { reason });
+        }
+        let sha256 = Sha256::digest(&self.buf).into();
+        let text = String::from_utf8(self.buf)
+            .map_err(|e| anyhow::anyhow!("article is not valid UTF-8: {e}"))?;
+        Ok(ArticleBlock::Complete {
+            text,
+            metadata: ArticleMetadata {
+                size: self.size,
+                lines: self.lines,
+                sha256,
+            },
+        })
+    }
+
+    fn step(&mut self, byte: u8) {
+        if byte == 0 {
+            self.refuse(MalformedArticle::Nul);
+        }
+        match (self.state, byte) {
+            (State::Done, _) => {}
+            (State::LineStart, b'.') => self.state = State::Dot,
+            (State::Dot, b'\r') => self.state = State::DotCr,
+            (State::Dot, b'\n') => {
+                self.refuse(MalformedArticle::BareLf);
+                self.state = State::Done;
+            }
+            (State::DotCr, b'\n') => self.state = State::Done,
+            (State::Dot, b'.') => {
+                // A stuffed dot
+                self.emit(b'.');
+                self.state = State::InLine;
+            }
+            (State::Dot, _) => {
+                // A dot that was not stuffed is kept
+                self.emit(b'.');
+                self.state = State::InLine;
+                self.step(byte);
+            }
+            (State::DotCr, _) => {
+                self.refuse(MalformedArticle::BareCr);
+                self.emit(b'.');
+                self.emit(b'\r');
+                self.state = State::InLine;
+                self.step(byte);
+            }
+            (State::Cr, b'\n') => {
+                self.emit(b'\n');
+                self.end_line();
+            }
+            (State::Cr, _) => {
+                self.refuse(MalformedArticle::BareCr);
+                self.state = State::InLine;
+                self.step(byte);
+            }
+            (_, b'\r') => {
+                self.emit(b'\r');
+                self.state = State::Cr;
+            }
+            (_, b'\n') => {
+                self.refuse(MalformedArticle::BareLf);
+                self.emit(b'\n');
+                self.end_line();
+            }
+            (State::LineStart | State::InLine, _) => {
+                self.emit(byte);
+                self.state = State::InLine;
+            }
+        }
+    }
+
+    /// Count a header line starting with `byte`: a new field, or a
+    /// continuation of the last one.
+    fn start_header_line(&mut self, byte: u8) {
+        if byte == b' ' || byte == b'\t' {
+            return;
+        }
+        self.headers += 1;
+        self.header_len = 0;
+        if self.headers > self.limits.max_headers {
+            self.refuse(MalformedArticle::TooManyHeaders);
+        }
+    }
+
+    /// Add a byte of the article after dot-stuffing.
+    fn emit(&mut self, byte: u8) {
+        if byte != b'\r' && byte != b'\n' {
+            if self.line_len == 0 && !self.in_body {
+                self.start_header_line(byte);
+            }
+            self.line_blank = false;
+        }
+        self.size += 1;
+        self.line_len += 1;
+        if !self.in_body {
+            self.header_len += 1;
+            if self.header_len > self.limits.max_header_bytes {
+                self.refuse(MalformedArticle::HeaderTooLong);
+            }
+        }
+        if self.limits.max_bytes.is_some_and(|max| self.size > max) {
+            self.too_large = true;
+        }
+        if self.too_large || self.malformed.is_some() {
+            if !self.buf.is_empty() {
+                self.buf = Vec::new();
+            }
+            return;
+        }
+        self.buf.push(byte);
+    }
+
+    fn end_line(&mut self) {
+        if self.in_body {
+            self.lines += 1;
+        } else if self.line_blank {
+            self.in_body = true;
+        }
+        self.line_len = 0;
+        self.line_blank = true;
+        self.state = State::LineStart;
+    }
+
+    fn refuse(&mut self, reason: MalformedArticle) {
+        self.malformed.get_or_insert(reason);
+    }
+}
+
+/// Read a dot-terminated article block, undoing dot-stuffing as it goes.
+///
+/// Size, body line count and a SHA-256 digest are computed while the block is
+/// streamed in, so the article text is never rescanned. When `max_bytes` is
+/// set and the block grows beyond it, buffering stops and the remainder is
+/// drained so the connection stays in sync; [`ArticleBlock::TooLarge`] is
+/// returned in that case. Blocks breaking the default [`ArticleLimits`] on
+/// headers, or holding bare CR or LF characters or NUL bytes, are drained
+/// the same way and returned as [`ArticleBlock::Malformed`].
+///
+/// # Errors
+///
+/// Returns an error if the connection is closed before the terminating line
+/// is received, if reading fails, or if the article is not valid UTF-8.
+pub async fn read_article_block<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_bytes: Option<u64>,
+) -> Result<ArticleBlock> {
+    let mut article = ArticleReader::new(ArticleLimits::with_max_bytes(max_bytes));
+    loop {
+        let data = reader.fill_buf().await?;
+        if data.is_empty() {
+            break;
+        }
+        match article.feed(data) {
+            Some(used) => {
+                reader.consume(used);
+                break;
+            }
+            None => {
+                let used = data.len();
+                reader.consume(used);
+            }
+        }
+    }
+    article.finish()
+}
@@ -0,0 +1,232 @@
+//! Audit log of administrative and posting actions.
+//!
+//! Posts, cancels, moderation decisions and changes to groups and users are
+//! recorded in the storage database with who made them, from where and
+//! when, so that abuse can be investigated after the tracing logs have
+//! rotated away. Network clients are identified by their user name, or
+//! posting-account token when anonymous, and their address; the admin CLI
+//! by the local account that ran it. `renews admin audit --since` prints the
+//! log, and the retention cleanup forgets entries older than
+//! `audit_retention_days`.
+
+use crate::Message;
+use crate::handlers::utils::{get_header_value, session_poster};
+use crate::session::Session;
+use crate::storage::Storage;
+use anyhow::Result;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use tracing::warn;
+
+/// Source recorded for actions taken through the admin CLI.
+pub const SOURCE_CLI: &str = "cli";
+
+/// An action recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    /// An article was posted by a client
+    Post,
+    /// An article was cancelled
+    Cancel,
+    /// An article was replaced by one naming it in Supersedes
+    Supersede,
+    /// A held article was approved by a moderator
+    Approve,
+    /// A held article was rejected by a moderator
+    Reject,
+    /// An article refused by a filter was injected by an administrator
+    Reinject,
+    /// A newsgroup was created
+    AddGroup,
+    /// Newsgroups were removed
+    RemoveGroup,
+    /// The articles of a newsgroup were numbered again
+    RenumberGroup,
+    /// A newsgroup was archived and stopped taking new articles
+    ArchiveGroup,
+    /// An archived newsgroup was opened for new articles again
+    UnarchiveGroup,
+    /// A user was added
+    AddUser,
+    /// A user was removed
+    RemoveUser,
+    /// A user was made an administrator
+    AddAdmin,
+    /// A user's administrator privileges were revoked
+    RemoveAdmin,
+    /// A user was made moderator of a group
+    AddModerator,
+    /// A user was removed as moderator of a group
+    RemoveModerator,
+    /// Connections from a network were banned
+    BanAddress,
+    /// A network was exempted from bans and per-address limits
+    AllowAddress,
+    /// The ban or allow rule of a network was removed
+    RemoveAddress,
+}
+
+impl AuditAction {
+    const ALL: [Self; 20] = [
+        Self::Post,
+        Self::Cancel,
+        Self::Supersede,
+        Self::Approve,
+        Self::Reject,
+        Self::Reinject,
+        Self::AddGroup,
+        Self::RemoveGroup,
+        Self::RenumberGroup,
+        Self::ArchiveGroup,
+        Self::UnarchiveGroup,
+        Self::AddUser,
+        Self::RemoveUser,
+        Self::AddAdmin,
+        Self::RemoveAdmin,
+        Self::AddModerator,
+        Self::RemoveModerator,
+        Self::BanAddress,
+        Self::AllowAddress,
+        Self::RemoveAddress,
+    ];
+
+    /// Name of the action as stored in the audit log
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Post => "post",
+            Self::Cancel => "cancel",
+            Self::Supersede => "supersede",
+            Self::Approve => "approve",
+            Self::Reject => "reject",
+            Self::Reinject => "reinject",
+            Self::AddGroup => "add-group",
+            Self::RemoveGroup => "remove-group",
+            Self::RenumberGroup => "renumber-group",
+            Self::ArchiveGroup => "archive-group",
+            Self::UnarchiveGroup => "unarchive-group",
+            Self::AddUser => "add-user",
+            Self::RemoveUser => "remove-user",
+            Self::AddAdmin => "add-admin",
+            Self::RemoveAdmin => "remove-admin",
+            Self::AddModerator => "add-moderator",
+            Self::RemoveModerator => "remove-moderator",
+            Self::BanAddress => "ban-address",
+            Self::AllowAddress => "allow-address",
+            Self::RemoveAddress => "remove-address",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("unknown audit action '{s}'"))
+    }
+}
+
+/// One entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Unix timestamp of when the action was taken
+    pub recorded_at: i64,
+    /// User who took the action, if known
+    pub actor: Option<String>,
+    /// Client address the action came from, or [`SOURCE_CLI`]
+    pub source: Option<String>,
+    /// What was done
+    pub action: AuditAction,
+    /// What it was done to: a Message-ID, group, wildmat or user
+    pub target: String,
+    /// Further details, such as the groups an article was posted to
+    pub detail: Option<String>,
+}
+
+impl AuditEntry {
+    /// An entry for `action` on `target`, taken now by an unknown actor.
+    #[must_use]
+    pub fn new(action: AuditAction, target: impl Into<String>) -> Self {
+        Self {
+            recorded_at: chrono::Utc::now().timestamp(),
+            actor: None,
+            source: None,
+            action,
+            target: target.into(),
+            detail: None,
+        }
+    }
+
+    /// Record the action as taken through the admin CLI by the local
+    /// account running it
+    #[must_use]
+    pub fn by_cli(mut self) -> Self {
+        self.actor = std::env::var("SUDO_USER")
+            .or_else(|_| std::env::var("USER"))
+            .or_else(|_| std::env::var("LOGNAME"))
+            .ok();
+        self.source = Some(SOURCE_CLI.to_string());
+        self
+    }
+
+    /// Record the user who took the action
+    #[must_use]
+    pub fn by(mut self, actor: Option<&str>) -> Self {
+        self.actor = actor.map(str::to_string);
+        self
+    }
+
+    /// Record the client address the action came from
+    #[must_use]
+    pub fn from_addr(mut self, addr: Option<IpAddr>) -> Self {
+        self.source = addr.map(|ip| ip.to_string());
+        self
+    }
+
+    /// Record the client of `session` as having taken the action: its user,
+    /// or posting-account token when anonymous, and its address
+    #[must_use]
+    pub fn by_session(self, session: &Session) -> Self {
+        self.by(session_poster(session).user.or(session.posting_account()))
+            .from_addr(session.peer_ip())
+    }
+
+    /// Record further details of the action
+    #[must_use]
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// An entry for `action` on `article`, naming the groups it was posted
+    /// to.
+    #[must_use]
+    pub fn for_article(action: AuditAction, article: &Message) -> Self {
+        let entry = Self::new(
+            action,
+            get_header_value(article, "Message-ID").unwrap_or_default(),
+        );
+        match get_header_value(article, "Newsgroups") {
+            Some(groups) => entry.with_detail(groups),
+            None => entry,
+        }
+    }
+}
+
+/// Add `entry` to the audit log. Failures are logged rather than returned,
+/// as the action has already been taken.
+pub async fn record(storage: &dyn Storage, entry: AuditEntry) {
+    if let Err(e) = storage.record_audit(&entry).await {
+        warn!(action = %entry.action, target = %entry.target, error = %e, "Failed to record audit log entry");
+    }
+}
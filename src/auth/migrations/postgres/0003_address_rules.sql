@@ -0,0 +1,17 @@
+-- Networks banned from connecting or exempt from bans, and the failed
+-- logins counted towards automatic bans
+
+CREATE TABLE IF NOT EXISTS address_rules (
+    network TEXT PRIMARY KEY,
+    action TEXT NOT NULL,
+    reason TEXT,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS auth_failures (
+    address TEXT NOT NULL,
+    failed_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_failures_address ON auth_failures(address, failed_at);
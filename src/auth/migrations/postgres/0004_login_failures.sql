@@ -0,0 +1,11 @@
+-- Failed logins counted per user name and per client address, and the
+-- lockouts they led to
+
+CREATE TABLE IF NOT EXISTS login_failures (
+    kind TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    failures BIGINT NOT NULL,
+    first_failed_at BIGINT NOT NULL,
+    locked_until BIGINT,
+    PRIMARY KEY (kind, subject)
+);
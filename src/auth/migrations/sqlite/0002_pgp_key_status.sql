@@ -0,0 +1,4 @@
+-- Why a user's stored PGP key can no longer be used, set by the periodic
+-- key refresh; NULL while the key is valid
+
+ALTER TABLE users ADD COLUMN key_problem TEXT;
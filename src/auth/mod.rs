@@ -2,7 +2,9 @@ use anyhow::Result;
 use async_trait::async_trait;
 use std::sync::Arc;
 
+use crate::access::AddressRule;
 use crate::limits::{UserLimits, UserUsage};
+use throttle::{LoginFailures, LoginSubject};
 
 #[async_trait]
 pub trait AuthProvider: Send + Sync {
@@ -16,12 +18,21 @@ pub trait AuthProvider: Send + Sync {
     async fn update_password(&self, username: &str, new_password: &str) -> Result<()>;
     async fn remove_user(&self, username: &str) -> Result<()>;
     async fn verify_user(&self, username: &str, password: &str) -> Result<bool>;
+    /// Whether `username` is a known user, regardless of password.
+    async fn user_exists(&self, username: &str) -> Result<bool>;
     async fn is_admin(&self, username: &str) -> Result<bool>;
     async fn add_admin(&self, username: &str, key: &str) -> Result<()>;
     async fn add_admin_without_key(&self, username: &str) -> Result<()>;
     async fn remove_admin(&self, username: &str) -> Result<()>;
     async fn update_pgp_key(&self, username: &str, key: &str) -> Result<()>;
     async fn get_pgp_key(&self, username: &str) -> Result<Option<String>>;
+    /// Every user with a stored PGP key, with the key.
+    async fn list_pgp_keys(&self) -> Result<Vec<(String, String)>>;
+    /// Flag the stored PGP key of `username` as unusable for `problem`, or
+    /// clear the flag with `None`. Storing a new key clears it too.
+    async fn set_pgp_key_problem(&self, username: &str, problem: Option<&str>) -> Result<()>;
+    /// Why the stored PGP key of `username` is flagged as unusable, if it is.
+    async fn get_pgp_key_problem(&self, username: &str) -> Result<Option<String>>;
     async fn add_moderator(&self, username: &str, pattern: &str) -> Result<()>;
     async fn remove_moderator(&self, username: &str, pattern: &str) -> Result<()>;
     async fn is_moderator(&self, username: &str, group: &str) -> Result<bool>;
@@ -49,14 +60,81 @@ pub trait AuthProvider: Send + Sync {
 
     /// Reset usage counters for a user.
     async fn reset_user_usage(&self, username: &str) -> Result<()>;
+
+    // Address ban list methods
+
+    /// Add `rule` to the ban and allow lists, replacing any rule for the
+    /// same network.
+    async fn add_address_rule(&self, rule: &AddressRule) -> Result<()>;
+
+    /// Remove the rule for `network`. Returns `false` if there was none.
+    async fn remove_address_rule(&self, network: &str) -> Result<bool>;
+
+    /// Every rule of the ban and allow lists that has not expired at `now`.
+    async fn list_address_rules(&self, now: i64) -> Result<Vec<AddressRule>>;
+
+    /// Record a failed login from `address` at `now`, forgetting its
+    /// failures before `since`, and return how many are left.
+    async fn record_auth_failure(&self, address: &str, since: i64, now: i64) -> Result<u32>;
+
+    /// How many failed logins from `address` were recorded at or after
+    /// `since`.
+    async fn count_auth_failures(&self, address: &str, since: i64) -> Result<u32>;
+
+    /// Forget the failed logins from `address`.
+    async fn clear_auth_failures(&self, address: &str) -> Result<()>;
+
+    // Login throttling methods
+
+    /// Failed logins counted against `subject`, if any.
+    async fn get_login_failures(&self, subject: &LoginSubject<'_>)
+    -> Result<Option<LoginFailures>>;
+
+    /// Store the failed logins counted against `subject`.
+    async fn set_login_failures(
+        &self,
+        subject: &LoginSubject<'_>,
+        failures: &LoginFailures,
+    ) -> Result<()>;
+
+    /// Forget the failed logins of `subject`, lifting any lockout.
+    async fn clear_login_failures(&self, subject: &LoginSubject<'_>) -> Result<()>;
+
+    /// Check that the database can be reached.
+    async fn ping(&self) -> Result<()>;
 }
 
 pub type DynAuth = Arc<dyn AuthProvider>;
 
+/// Build an address rule from its stored columns, skipping rows that do not
+/// parse.
+pub(crate) fn address_rule(
+    network: &str,
+    action: &str,
+    reason: Option<String>,
+    expires_at: Option<i64>,
+) -> Option<AddressRule> {
+    match (network.parse(), action.parse()) {
+        (Ok(network), Ok(action)) => Some(AddressRule {
+            network,
+            action,
+            reason,
+            expires_at,
+        }),
+        _ => {
+            tracing::warn!(network, action, "Ignoring unreadable address rule");
+            None
+        }
+    }
+}
+
 pub mod pgp_discovery;
+pub mod pgp_refresh;
 #[cfg(feature = "postgres")]
 pub mod postgres;
+pub mod program;
 pub mod sqlite;
+pub mod throttle;
 
 /// Create an authentication backend from a connection URI.
 pub async fn open(uri: &str) -> Result<DynAuth> {
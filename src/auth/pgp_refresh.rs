@@ -0,0 +1,191 @@
+//! Periodic refresh of stored PGP keys.
+//!
+//! Keys stored for control message signers expire and get rotated or
+//! revoked upstream. A refresh walks every stored key, fetches the current
+//! copy from the key servers and adopts it when it is the same key with
+//! newer signatures. Keys that are expired, revoked or unreadable
+//! afterwards are flagged on the user so that signature checks stop
+//! trusting them until a new key is stored.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use pgp::native::types::KeyTrait;
+use pgp::native::{Deserializable, SignedPublicKey};
+
+use super::DynAuth;
+use super::pgp_discovery::PgpKeyDiscovery;
+
+/// Usability of a stored key at a point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyStatus {
+    Valid,
+    Expired(DateTime<Utc>),
+    Revoked,
+    Unreadable,
+}
+
+impl KeyStatus {
+    /// Reason recorded on the user for a key that is not valid.
+    pub fn problem(&self) -> Option<String> {
+        match self {
+            KeyStatus::Valid => None,
+            KeyStatus::Expired(at) => Some(format!("expired {}", at.to_rfc3339())),
+            KeyStatus::Revoked => Some("revoked".to_string()),
+            KeyStatus::Unreadable => Some("unreadable".to_string()),
+        }
+    }
+}
+
+/// Outcome of a refresh pass.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RefreshReport {
+    /// Number of stored keys examined.
+    pub checked: usize,
+    /// Number of stored keys replaced by a newer copy from a key server.
+    pub updated: usize,
+    /// Users whose key is unusable after the refresh, with the reason.
+    pub invalid: Vec<(String, String)>,
+}
+
+fn parse_key(key_text: &str) -> Option<SignedPublicKey> {
+    SignedPublicKey::from_string(key_text)
+        .ok()
+        .map(|(key, _)| key)
+}
+
+fn status_of(key: &SignedPublicKey, now: DateTime<Utc>) -> KeyStatus {
+    let revoked = key
+        .details
+        .revocation_signatures
+        .iter()
+        .any(|sig| sig.verify_key(&key.primary_key).is_ok());
+    if revoked {
+        return KeyStatus::Revoked;
+    }
+    match key.expires_at() {
+        Some(at) if at <= now => KeyStatus::Expired(at),
+        _ => KeyStatus::Valid,
+    }
+}
+
+/// Determine whether an armored public key is usable at `now`.
+pub fn key_status(key_text: &str, now: DateTime<Utc>) -> KeyStatus {
+    match parse_key(key_text) {
+        Some(key) => status_of(&key, now),
+        None => KeyStatus::Unreadable,
+    }
+}
+
+/// Refresh every stored key from `discovery` and flag the unusable ones.
+///
+/// A discovered key only replaces the stored one when it has the same
+/// primary fingerprint and is itself valid, so a key server can extend or
+/// revoke a key but never substitute a different one.
+pub async fn refresh_keys(
+    auth: &DynAuth,
+    discovery: &dyn PgpKeyDiscovery,
+    now: DateTime<Utc>,
+) -> Result<RefreshReport> {
+    let mut report = RefreshReport::default();
+    for (user, stored) in auth.list_pgp_keys().await? {
+        report.checked += 1;
+        let mut status = key_status(&stored, now);
+
+        if let Some(stored_key) = parse_key(&stored) {
+            match discovery.discover_key(&user).await {
+                Ok(Some(text)) => {
+                    if let Some(found) = parse_key(&text)
+                        && found.fingerprint() == stored_key.fingerprint()
+                    {
+                        let found_status = status_of(&found, now);
+                        if found_status == KeyStatus::Revoked {
+                            status = found_status;
+                        } else if found_status == KeyStatus::Valid && text != stored {
+                            auth.update_pgp_key(&user, &text).await?;
+                            report.updated += 1;
+                            status = found_status;
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::info!(error = %e, "PGP key refresh lookup failed");
+                    tracing::debug!(user = %user, error = %e, "PGP key refresh lookup failed");
+                }
+            }
+        }
+
+        let problem = status.problem();
+        if let Some(reason) = &problem {
+            tracing::info!(reason = %reason, "Stored PGP key is no longer valid");
+            tracing::debug!(user = %user, reason = %reason, "Stored PGP key is no longer valid");
+            report.invalid.push((user.clone(), reason.clone()));
+        }
+        auth.set_pgp_key_problem(&user, problem.as_deref()).await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::sqlite::SqliteAuth;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    const ADMIN_KEY: &str = include_str!("../../tests/data/admin.pub.asc");
+
+    struct FixedDiscovery(Option<String>);
+
+    #[async_trait]
+    impl PgpKeyDiscovery for FixedDiscovery {
+        async fn discover_key(&self, _user: &str) -> Result<Option<String>> {
+            Ok(self.0.clone())
+        }
+
+        async fn validate_key(&self, key_text: &str) -> Result<bool> {
+            Ok(parse_key(key_text).is_some())
+        }
+    }
+
+    #[test]
+    fn key_status_reports_valid_and_unreadable() {
+        assert_eq!(key_status(ADMIN_KEY, Utc::now()), KeyStatus::Valid);
+        assert_eq!(key_status("not a key", Utc::now()), KeyStatus::Unreadable);
+        assert_eq!(
+            KeyStatus::Unreadable.problem().as_deref(),
+            Some("unreadable")
+        );
+        assert!(KeyStatus::Valid.problem().is_none());
+    }
+
+    #[tokio::test]
+    async fn refresh_flags_unusable_keys_and_clears_valid_ones() {
+        let auth: DynAuth = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+        auth.add_user("good", "pw").await.unwrap();
+        auth.add_user("bad", "pw").await.unwrap();
+        auth.update_pgp_key("good", ADMIN_KEY).await.unwrap();
+        auth.update_pgp_key("bad", "garbage").await.unwrap();
+        auth.set_pgp_key_problem("good", Some("stale"))
+            .await
+            .unwrap();
+
+        let discovery = FixedDiscovery(Some(ADMIN_KEY.to_string()));
+        let report = refresh_keys(&auth, &discovery, Utc::now()).await.unwrap();
+
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.updated, 0);
+        assert_eq!(
+            report.invalid,
+            vec![("bad".to_string(), "unreadable".to_string())]
+        );
+        assert_eq!(
+            auth.get_pgp_key_problem("bad").await.unwrap().as_deref(),
+            Some("unreadable")
+        );
+        assert!(auth.get_pgp_key_problem("good").await.unwrap().is_none());
+
+        auth.update_pgp_key("bad", ADMIN_KEY).await.unwrap();
+        assert!(auth.get_pgp_key_problem("bad").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,281 @@
+//! Authentication by an external program.
+//!
+//! With `[auth_program]` configured, the credentials given with AUTHINFO
+//! USER and PASS are checked by running a site-provided program instead of
+//! against the authentication database, so that single sign-on and other
+//! custom schemes can be plugged in without changing the server. The
+//! protocol follows INN's authenticators: the program is given
+//!
+//! ```text
+//! ClientAuthname: <user>
+//! ClientPassword: <password>
+//! .
+//! ```
+//!
+//! on standard input, with CRLF line endings, and accepts the user by
+//! printing `User:<user>` and exiting with status 0. Any other status, or
+//! no `User:` line, refuses the login. Sessions keep the name given with
+//! AUTHINFO USER, so a program naming a different user is refused too.
+//! Anything the program writes to standard error is logged. A program that
+//! cannot be started or does not answer in time refuses the login too.
+//!
+//! Everything else, including administrators, moderators and limits, is
+//! still looked up in the authentication database, so users given those
+//! roles need an entry there; its password is not used.
+
+use super::throttle::{LoginFailures, LoginSubject};
+use super::{AuthProvider, DynAuth};
+use crate::access::AddressRule;
+use crate::config::AuthProgramConfig;
+use crate::limits::{UserLimits, UserUsage};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, error, warn};
+
+/// Authentication backend checking credentials with an external program.
+pub struct ProgramAuth {
+    inner: DynAuth,
+    path: String,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl ProgramAuth {
+    /// Check credentials with the program described by `cfg`, and look
+    /// everything else up in `inner`.
+    #[must_use]
+    pub fn new(inner: DynAuth, cfg: &AuthProgramConfig) -> Self {
+        Self {
+            inner,
+            path: cfg.path.clone(),
+            args: cfg.args.clone(),
+            timeout: Duration::from_secs(cfg.timeout_secs),
+        }
+    }
+
+    /// Run the program for one login and return its verdict.
+    async fn run(&self, username: &str, password: &str) -> Result<bool> {
+        let mut child = Command::new(&self.path)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run authentication program '{}': {e}", self.path))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            let request =
+                format!("ClientAuthname: {username}\r\nClientPassword: {password}\r\n.\r\n");
+            // A program that decides without reading its input closes the
+            // pipe early, which is not an error
+            let _ = stdin.write_all(request.as_bytes()).await;
+        }
+        let output = tokio::time::timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Authentication program '{}' did not answer within {} seconds",
+                    self.path,
+                    self.timeout.as_secs()
+                )
+            })??;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        for line in stderr.lines().filter(|l| !l.trim().is_empty()) {
+            warn!(program = %self.path, "Authentication program: {line}");
+        }
+        if !output.status.success() {
+            debug!(program = %self.path, status = %output.status, "Authentication program refused login");
+            return Ok(false);
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let named = stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("User:"))
+            .map(str::trim);
+        match named {
+            Some(name) if name == username => Ok(true),
+            Some(_) => {
+                warn!(program = %self.path, "Authentication program named a different user; login refused");
+                Ok(false)
+            }
+            None => {
+                warn!(program = %self.path, "Authentication program exited without a User: line; login refused");
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ProgramAuth {
+    async fn add_user(&self, username: &str, password: &str) -> Result<()> {
+        self.inner.add_user(username, password).await
+    }
+
+    async fn add_user_with_key(
+        &self,
+        username: &str,
+        password: &str,
+        key: Option<&str>,
+    ) -> Result<()> {
+        self.inner.add_user_with_key(username, password, key).await
+    }
+
+    async fn update_password(&self, username: &str, new_password: &str) -> Result<()> {
+        self.inner.update_password(username, new_password).await
+    }
+
+    async fn remove_user(&self, username: &str) -> Result<()> {
+        self.inner.remove_user(username).await
+    }
+
+    async fn verify_user(&self, username: &str, password: &str) -> Result<bool> {
+        // Refuse rather than fail the login, so the client is still answered
+        match self.run(username, password).await {
+            Ok(accepted) => Ok(accepted),
+            Err(e) => {
+                error!(error = %e, "Authentication program failed");
+                Ok(false)
+            }
+        }
+    }
+
+    async fn user_exists(&self, username: &str) -> Result<bool> {
+        self.inner.user_exists(username).await
+    }
+
+    async fn is_admin(&self, username: &str) -> Result<bool> {
+        self.inner.is_admin(username).await
+    }
+
+    async fn add_admin(&self, username: &str, key: &str) -> Result<()> {
+        self.inner.add_admin(username, key).await
+    }
+
+    async fn add_admin_without_key(&self, username: &str) -> Result<()> {
+        self.inner.add_admin_without_key(username).await
+    }
+
+    async fn remove_admin(&self, username: &str) -> Result<()> {
+        self.inner.remove_admin(username).await
+    }
+
+    async fn update_pgp_key(&self, username: &str, key: &str) -> Result<()> {
+        self.inner.update_pgp_key(username, key).await
+    }
+
+    async fn get_pgp_key(&self, username: &str) -> Result<Option<String>> {
+        self.inner.get_pgp_key(username).await
+    }
+
+    async fn list_pgp_keys(&self) -> Result<Vec<(String, String)>> {
+        self.inner.list_pgp_keys().await
+    }
+
+    async fn set_pgp_key_problem(&self, username: &str, problem: Option<&str>) -> Result<()> {
+        self.inner.set_pgp_key_problem(username, problem).await
+    }
+
+    async fn get_pgp_key_problem(&self, username: &str) -> Result<Option<String>> {
+        self.inner.get_pgp_key_problem(username).await
+    }
+
+    async fn add_moderator(&self, username: &str, pattern: &str) -> Result<()> {
+        self.inner.add_moderator(username, pattern).await
+    }
+
+    async fn remove_moderator(&self, username: &str, pattern: &str) -> Result<()> {
+        self.inner.remove_moderator(username, pattern).await
+    }
+
+    async fn is_moderator(&self, username: &str, group: &str) -> Result<bool> {
+        self.inner.is_moderator(username, group).await
+    }
+
+    async fn get_user_limits(&self, username: &str) -> Result<Option<UserLimits>> {
+        self.inner.get_user_limits(username).await
+    }
+
+    async fn set_user_limits(&self, username: &str, limits: &UserLimits) -> Result<()> {
+        self.inner.set_user_limits(username, limits).await
+    }
+
+    async fn clear_user_limits(&self, username: &str) -> Result<()> {
+        self.inner.clear_user_limits(username).await
+    }
+
+    async fn get_user_usage(&self, username: &str) -> Result<UserUsage> {
+        self.inner.get_user_usage(username).await
+    }
+
+    async fn set_user_usage(&self, username: &str, usage: &UserUsage) -> Result<()> {
+        self.inner.set_user_usage(username, usage).await
+    }
+
+    async fn reset_user_usage(&self, username: &str) -> Result<()> {
+        self.inner.reset_user_usage(username).await
+    }
+
+    async fn add_address_rule(&self, rule: &AddressRule) -> Result<()> {
+        self.inner.add_address_rule(rule).await
+    }
+
+    async fn remove_address_rule(&self, network: &str) -> Result<bool> {
+        self.inner.remove_address_rule(network).await
+    }
+
+    async fn list_address_rules(&self, now: i64) -> Result<Vec<AddressRule>> {
+        self.inner.list_address_rules(now).await
+    }
+
+    async fn record_auth_failure(&self, address: &str, since: i64, now: i64) -> Result<u32> {
+        self.inner.record_auth_failure(address, since, now).await
+    }
+
+    async fn count_auth_failures(&self, address: &str, since: i64) -> Result<u32> {
+        self.inner.count_auth_failures(address, since).await
+    }
+
+    async fn clear_auth_failures(&self, address: &str) -> Result<()> {
+        self.inner.clear_auth_failures(address).await
+    }
+
+    async fn get_login_failures(
+        &self,
+        subject: &LoginSubject<'_>,
+    ) -> Result<Option<LoginFailures>> {
+        self.inner.get_login_failures(subject).await
+    }
+
+    async fn set_login_failures(
+        &self,
+        subject: &LoginSubject<'_>,
+        failures: &LoginFailures,
+    ) -> Result<()> {
+        self.inner.set_login_failures(subject, failures).await
+    }
+
+    async fn clear_login_failures(&self, subject: &LoginSubject<'_>) -> Result<()> {
+        self.inner.clear_login_failures(subject).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+}
+
+/// Wrap `auth` so that credentials are checked by `program` when one is
+/// configured.
+#[must_use]
+pub fn for_config(auth: DynAuth, program: Option<&AuthProgramConfig>) -> DynAuth {
+    match program {
+        Some(cfg) => Arc::new(ProgramAuth::new(auth, cfg)),
+        None => auth,
+    }
+}
@@ -1,8 +1,11 @@
+use super::throttle::{LoginFailures, LoginSubject};
 use super::{AuthProvider, async_trait};
+use crate::access::AddressRule;
 use crate::limits::{UserLimits, UserUsage};
 use anyhow::Result;
 use argon2::password_hash::{SaltString, rand_core::OsRng};
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use sqlx::migrate::Migrator;
 use sqlx::{
     Row, SqlitePool,
     sqlite::{SqliteConnectOptions, SqlitePoolOptions},
@@ -14,6 +17,9 @@ pub struct SqliteAuth {
     pool: SqlitePool,
 }
 
+/// Schema migrations of the SQLite authentication database.
+pub(crate) static MIGRATOR: Migrator = sqlx::migrate!("src/auth/migrations/sqlite");
+
 impl SqliteAuth {
     /// Create a new `SQLite` authentication provider.
     ///
@@ -53,12 +59,12 @@ Possible causes:
             })?;
 
         // Run migrations using sqlx's built-in migration system
-        sqlx::migrate!("src/auth/migrations/sqlite")
-            .run(&pool)
-            .await
-            .map_err(|e| {
-                anyhow::anyhow!("Failed to run auth migrations for SQLite database '{path}': {e}")
-            })?;
+        MIGRATOR.run(&pool).await.map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to run auth migrations for SQLite database '{path}': {}",
+                crate::migrations::describe_failure(&e)
+            )
+        })?;
 
         tracing::info!("SQLite authentication database ready at '{}'", path);
 
@@ -136,6 +142,14 @@ impl AuthProvider for SqliteAuth {
         }
     }
 
+    async fn user_exists(&self, username: &str) -> Result<bool> {
+        let row = sqlx::query("SELECT 1 FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.is_some())
+    }
+
     async fn is_admin(&self, username: &str) -> Result<bool> {
         let row = sqlx::query("SELECT 1 FROM admins WHERE username = ?")
             .bind(username)
@@ -149,7 +163,7 @@ impl AuthProvider for SqliteAuth {
             .bind(username)
             .execute(&self.pool)
             .await?;
-        sqlx::query("UPDATE users SET key = ? WHERE username = ?")
+        sqlx::query("UPDATE users SET key = ?, key_problem = NULL WHERE username = ?")
             .bind(key)
             .bind(username)
             .execute(&self.pool)
@@ -174,7 +188,7 @@ impl AuthProvider for SqliteAuth {
     }
 
     async fn update_pgp_key(&self, username: &str, key: &str) -> Result<()> {
-        sqlx::query("UPDATE users SET key = ? WHERE username = ?")
+        sqlx::query("UPDATE users SET key = ?, key_problem = NULL WHERE username = ?")
             .bind(key)
             .bind(username)
             .execute(&self.pool)
@@ -195,6 +209,34 @@ impl AuthProvider for SqliteAuth {
         }
     }
 
+    async fn list_pgp_keys(&self) -> Result<Vec<(String, String)>> {
+        let rows =
+            sqlx::query("SELECT username, key FROM users WHERE key IS NOT NULL ORDER BY username")
+                .fetch_all(&self.pool)
+                .await?;
+        rows.iter()
+            .map(|row| Ok((row.try_get("username")?, row.try_get("key")?)))
+            .collect()
+    }
+
+    async fn set_pgp_key_problem(&self, username: &str, problem: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE users SET key_problem = ? WHERE username = ?")
+            .bind(problem)
+            .bind(username)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_pgp_key_problem(&self, username: &str) -> Result<Option<String>> {
+        let problem: Option<Option<String>> =
+            sqlx::query_scalar("SELECT key_problem FROM users WHERE username = ?")
+                .bind(username)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(problem.flatten())
+    }
+
     async fn add_moderator(&self, username: &str, pattern: &str) -> Result<()> {
         sqlx::query("INSERT OR REPLACE INTO moderators (username, pattern) VALUES (?, ?)")
             .bind(username)
@@ -359,4 +401,148 @@ impl AuthProvider for SqliteAuth {
         .await?;
         Ok(())
     }
+
+    async fn add_address_rule(&self, rule: &AddressRule) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO address_rules (network, action, reason, created_at, expires_at) VALUES (?, ?, ?, ?, ?) \
+             ON CONFLICT(network) DO UPDATE SET action = excluded.action, reason = excluded.reason, \
+             created_at = excluded.created_at, expires_at = excluded.expires_at",
+        )
+        .bind(rule.network.to_string())
+        .bind(rule.action.as_str())
+        .bind(rule.reason.as_deref())
+        .bind(chrono::Utc::now().timestamp())
+        .bind(rule.expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_address_rule(&self, network: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM address_rules WHERE network = ?")
+            .bind(network)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_address_rules(&self, now: i64) -> Result<Vec<AddressRule>> {
+        let rows = sqlx::query(
+            "SELECT network, action, reason, expires_at FROM address_rules \
+             WHERE expires_at IS NULL OR expires_at > ? ORDER BY network",
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut rules = Vec::with_capacity(rows.len());
+        for row in rows {
+            let network: String = row.try_get("network")?;
+            let action: String = row.try_get("action")?;
+            rules.extend(super::address_rule(
+                &network,
+                &action,
+                row.try_get("reason")?,
+                row.try_get("expires_at")?,
+            ));
+        }
+        Ok(rules)
+    }
+
+    async fn record_auth_failure(&self, address: &str, since: i64, now: i64) -> Result<u32> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM auth_failures WHERE address = ? AND failed_at < ?")
+            .bind(address)
+            .bind(since)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO auth_failures (address, failed_at) VALUES (?, ?)")
+            .bind(address)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        let failures: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM auth_failures WHERE address = ?")
+                .bind(address)
+                .fetch_one(&mut *tx)
+                .await?;
+        tx.commit().await?;
+        Ok(u32::try_from(failures).unwrap_or(u32::MAX))
+    }
+
+    async fn count_auth_failures(&self, address: &str, since: i64) -> Result<u32> {
+        let failures: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM auth_failures WHERE address = ? AND failed_at >= ?",
+        )
+        .bind(address)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(u32::try_from(failures).unwrap_or(u32::MAX))
+    }
+
+    async fn clear_auth_failures(&self, address: &str) -> Result<()> {
+        sqlx::query("DELETE FROM auth_failures WHERE address = ?")
+            .bind(address)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_login_failures(
+        &self,
+        subject: &LoginSubject<'_>,
+    ) -> Result<Option<LoginFailures>> {
+        let row = sqlx::query(
+            "SELECT failures, first_failed_at, locked_until FROM login_failures \
+             WHERE kind = ? AND subject = ?",
+        )
+        .bind(subject.kind())
+        .bind(subject.name())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| {
+            let failures: i64 = row.try_get("failures")?;
+            Ok(LoginFailures {
+                failures: u32::try_from(failures).unwrap_or(u32::MAX),
+                first_failed_at: row.try_get("first_failed_at")?,
+                locked_until: row.try_get("locked_until")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn set_login_failures(
+        &self,
+        subject: &LoginSubject<'_>,
+        failures: &LoginFailures,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO login_failures (kind, subject, failures, first_failed_at, locked_until) \
+             VALUES (?, ?, ?, ?, ?) \
+             ON CONFLICT (kind, subject) DO UPDATE SET failures = excluded.failures, \
+             first_failed_at = excluded.first_failed_at, locked_until = excluded.locked_until",
+        )
+        .bind(subject.kind())
+        .bind(subject.name())
+        .bind(i64::from(failures.failures))
+        .bind(failures.first_failed_at)
+        .bind(failures.locked_until)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn clear_login_failures(&self, subject: &LoginSubject<'_>) -> Result<()> {
+        sqlx::query("DELETE FROM login_failures WHERE kind = ? AND subject = ?")
+            .bind(subject.kind())
+            .bind(subject.name())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,247 @@
+//! Throttling of failed logins.
+//!
+//! Every failed `AUTHINFO PASS` is counted against the user name tried and
+//! against the client address in the authentication database, so that the
+//! counts hold across connections, listeners and servers sharing the
+//! database. The failures of an address are those recorded for the address
+//! bans of [`crate::access`], so each address has a single count that both
+//! read, each over its own window. The answer to a failed attempt is held
+//! back for
+//! `[login_throttle] failure_delay`, doubled for every further failure of the
+//! same user or address up to `max_failure_delay`, and once either has failed
+//! `lockout_failures` times within `failure_window` it is locked out for
+//! `lockout_duration`: logins are refused without the password being checked.
+//! A successful login clears the counts of its user and address, and
+//! `renews admin unlock-user` and `unlock-address` those of a user or an
+//! address.
+
+use anyhow::Result;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use super::AuthProvider;
+use crate::access::IpNetwork;
+use crate::config::LoginThrottleConfig;
+
+/// What failed logins are counted against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginSubject<'a> {
+    /// The user name tried
+    User(&'a str),
+    /// The address of the client
+    Address(IpAddr),
+}
+
+impl LoginSubject<'_> {
+    /// Kind of subject as stored in the database
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::User(_) => "user",
+            Self::Address(_) => "address",
+        }
+    }
+
+    /// Name of the subject as stored in the database
+    #[must_use]
+    pub fn name(&self) -> String {
+        match self {
+            Self::User(user) => (*user).to_string(),
+            Self::Address(ip) => IpNetwork::from(*ip).to_string(),
+        }
+    }
+}
+
+/// Failed logins counted against one subject. For addresses only the
+/// lockout is kept; their failures are counted with the address bans.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoginFailures {
+    /// Failures since `first_failed_at`
+    pub failures: u32,
+    /// Unix timestamp of the first failure counted
+    pub first_failed_at: i64,
+    /// Unix timestamp until which logins are refused, if locked out
+    pub locked_until: Option<i64>,
+}
+
+/// Unix timestamp until which logins as `username` from `ip` are refused, if
+/// either is locked out at `now`.
+///
+/// # Errors
+///
+/// Returns an error if the database cannot be read.
+pub async fn locked_until(
+    auth: &dyn AuthProvider,
+    username: &str,
+    ip: Option<IpAddr>,
+    now: i64,
+) -> Result<Option<i64>> {
+    let mut until = None;
+    for subject in subjects(username, ip) {
+        let locked = auth
+            .get_login_failures(&subject)
+            .await?
+            .and_then(|failures| failures.locked_until)
+            .filter(|&at| at > now);
+        until = until.max(locked);
+    }
+    Ok(until)
+}
+
+/// Count a failed login as `username` from `ip` at `now`, locking either out
+/// once it reaches `cfg.lockout_failures`, and return how long to hold back
+/// the answer.
+///
+/// The failure must already have been recorded against `ip` with
+/// [`crate::access::AccessList::record_auth_failure`]; it is only counted
+/// here.
+///
+/// # Errors
+///
+/// Returns an error if the database cannot be read or written.
+pub async fn record_failure(
+    auth: &dyn AuthProvider,
+    cfg: &LoginThrottleConfig,
+    username: &str,
+    ip: Option<IpAddr>,
+    now: i64,
+) -> Result<Duration> {
+    let window = secs_i64(cfg.failure_window);
+    let subject = LoginSubject::User(username);
+    let mut counted = auth
+        .get_login_failures(&subject)
+        .await?
+        .filter(|counted| window.is_none_or(|window| now - counted.first_failed_at < window))
+        .unwrap_or(LoginFailures {
+            failures: 0,
+            first_failed_at: now,
+            locked_until: None,
+        });
+    counted.failures = counted.failures.saturating_add(1);
+    let mut most = counted.failures;
+    if locks_out(cfg, counted.failures) {
+        // The lockout starts a new count
+        lock_out(auth, cfg, &subject, counted.failures, now).await?;
+    } else {
+        auth.set_login_failures(&subject, &counted).await?;
+    }
+
+    if let Some(ip) = ip {
+        let subject = LoginSubject::Address(ip);
+        let lockout = auth
+            .get_login_failures(&subject)
+            .await?
+            .and_then(|counted| counted.locked_until);
+        // Failures up to the end of the last lockout were counted towards it
+        let since = window
+            .map_or(i64::MIN, |window| now.saturating_sub(window))
+            .max(lockout.unwrap_or(i64::MIN));
+        let failures = auth.count_auth_failures(&subject.name(), since).await?;
+        if locks_out(cfg, failures) {
+            lock_out(auth, cfg, &subject, failures, now).await?;
+        }
+        most = most.max(failures);
+    }
+    Ok(failure_delay(cfg, most))
+}
+
+fn locks_out(cfg: &LoginThrottleConfig, failures: u32) -> bool {
+    cfg.lockout_failures > 0 && failures >= cfg.lockout_failures
+}
+
+async fn lock_out(
+    auth: &dyn AuthProvider,
+    cfg: &LoginThrottleConfig,
+    subject: &LoginSubject<'_>,
+    failures: u32,
+    now: i64,
+) -> Result<()> {
+    let duration = secs_i64(cfg.lockout_duration).unwrap_or(0);
+    tracing::info!(
+        kind = subject.kind(),
+        failures,
+        "Login locked out after failed attempts"
+    );
+    auth.set_login_failures(
+        subject,
+        &LoginFailures {
+            failures: 0,
+            first_failed_at: now,
+            locked_until: Some(now.saturating_add(duration)),
+        },
+    )
+    .await
+}
+
+/// Forget the failed logins of `username` and `ip` after a successful login.
+///
+/// # Errors
+///
+/// Returns an error if the database cannot be written.
+pub async fn clear(auth: &dyn AuthProvider, username: &str, ip: Option<IpAddr>) -> Result<()> {
+    for subject in subjects(username, ip) {
+        unlock(auth, &subject).await?;
+    }
+    Ok(())
+}
+
+/// Forget the failed logins of `subject`, lifting any lockout. The failures
+/// of an address are forgotten by its address bans as well.
+///
+/// # Errors
+///
+/// Returns an error if the database cannot be written.
+pub async fn unlock(auth: &dyn AuthProvider, subject: &LoginSubject<'_>) -> Result<()> {
+    auth.clear_login_failures(subject).await?;
+    if let LoginSubject::Address(_) = subject {
+        auth.clear_auth_failures(&subject.name()).await?;
+    }
+    Ok(())
+}
+
+/// How long to hold back the answer to the `failures`th failed login:
+/// `failure_delay`, doubled for every failure after the first, and at most
+/// `max_failure_delay`.
+#[must_use]
+pub fn failure_delay(cfg: &LoginThrottleConfig, failures: u32) -> Duration {
+    let Some(base) = cfg.failure_delay else {
+        return Duration::ZERO;
+    };
+    let factor = 1u64
+        .checked_shl(failures.saturating_sub(1))
+        .unwrap_or(u64::MAX);
+    let secs = base.saturating_mul(factor);
+    let secs = cfg.max_failure_delay.map_or(secs, |max| secs.min(max));
+    Duration::from_secs(secs)
+}
+
+fn subjects(username: &str, ip: Option<IpAddr>) -> impl Iterator<Item = LoginSubject<'_>> {
+    std::iter::once(LoginSubject::User(username)).chain(ip.map(LoginSubject::Address))
+}
+
+fn secs_i64(secs: Option<u64>) -> Option<i64> {
+    secs.map(|secs| i64::try_from(secs).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_double_up_to_the_maximum() {
+        let cfg = LoginThrottleConfig {
+            failure_delay: Some(1),
+            max_failure_delay: Some(10),
+            ..LoginThrottleConfig::default()
+        };
+        let delays: Vec<u64> = (1..=6).map(|n| failure_delay(&cfg, n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        assert_eq!(failure_delay(&cfg, 200).as_secs(), 10);
+
+        let off = LoginThrottleConfig {
+            failure_delay: None,
+            ..cfg
+        };
+        assert_eq!(failure_delay(&off, 3), Duration::ZERO);
+    }
+}
@@ -0,0 +1,527 @@
+//! Read-only mirrors of groups outside NNTP.
+//!
+//! Each configured [`BridgeRule`] mirrors the groups matching its wildmat on
+//! the bridge schedule, so that communities can follow them on the web or
+//! in a mail client without separate gateway software. Every run looks at
+//! the newest `max_items` articles of each group:
+//!
+//! - `rss` and `atom` bridges write a feed file per group to `output_dir`,
+//!   replacing the previous one, named after the group with an `.rss` or
+//!   `.atom` extension.
+//! - `imap` bridges append the articles a mailbox does not hold yet, found
+//!   by searching it for their Message-ID, so a run that is repeated or
+//!   overlaps the previous one appends nothing twice.
+//!
+//! Groups that receive more than `max_items` articles between two runs lose
+//! the older ones from the IMAP mirror.
+
+use crate::Message;
+use crate::config::{BridgeFormat, BridgeRule, Config};
+use crate::export::message_lines;
+use crate::handlers::utils::get_header_value;
+use crate::storage::Storage;
+use crate::transport::ClientStream;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::str::FromStr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::{Instrument, info, info_span, warn};
+
+/// Bring every configured bridge up to date.
+///
+/// # Errors
+///
+/// Errors from individual bridges are logged rather than returned, so one
+/// unreachable mirror does not hold back the others.
+pub async fn run_bridges(storage: &dyn Storage, cfg: &Config) -> Result<()> {
+    let span = info_span!(
+        "bridge.run",
+        bridges = cfg.bridges.len(),
+        articles_mirrored = tracing::field::Empty,
+    );
+
+    async {
+        let mut mirrored = 0u64;
+        for rule in &cfg.bridges {
+            match run_bridge(storage, cfg, rule).await {
+                Ok(count) => mirrored += count,
+                Err(e) => warn!(groups = rule.groups.as_str(), error = %e, "Bridge failed"),
+            }
+        }
+        tracing::Span::current().record("articles_mirrored", mirrored);
+        info!(articles_mirrored = mirrored, "Bridge run complete");
+        Ok(())
+    }
+    .instrument(span)
+    .await
+}
+
+/// Mirror the groups of one rule, returning the number of articles written
+/// to feeds or appended to mailboxes.
+async fn run_bridge(storage: &dyn Storage, cfg: &Config, rule: &BridgeRule) -> Result<u64> {
+    let groups = matching_groups(storage, &rule.groups).await?;
+    match rule.format {
+        BridgeFormat::Rss | BridgeFormat::Atom => {
+            let dir = rule
+                .output_dir
+                .as_deref()
+                .ok_or_else(|| anyhow!("output_dir is not set"))?;
+            let mut written = 0;
+            for group in &groups {
+                let articles = newest_articles(storage, group, rule.max_items).await?;
+                let (feed, extension) = if rule.format == BridgeFormat::Rss {
+                    (rss_feed(group, &cfg.site_name, rule, &articles), "rss")
+                } else {
+                    let feed = atom_feed(group, &cfg.site_name, rule, &articles, Utc::now());
+                    (feed, "atom")
+                };
+                write_feed(Path::new(dir), &format!("{group}.{extension}"), &feed)?;
+                written += articles.len() as u64;
+            }
+            Ok(written)
+        }
+        BridgeFormat::Imap => {
+            let target: ImapTarget = rule
+                .imap_url
+                .as_deref()
+                .ok_or_else(|| anyhow!("imap_url is not set"))?
+                .parse()?;
+            let mut client = ImapClient::connect(&target).await?;
+            client
+                .login(
+                    rule.imap_user.as_deref().unwrap_or_default(),
+                    rule.imap_password.as_deref().unwrap_or_default(),
+                )
+                .await?;
+            let mut appended = 0;
+            for group in &groups {
+                let mailbox = target.mailbox.replace("{group}", group);
+                client.select_or_create(&mailbox).await?;
+                let mut articles = newest_articles(storage, group, rule.max_items).await?;
+                // Oldest first, so that the mailbox keeps the order of arrival
+                articles.reverse();
+                for article in &articles {
+                    let Some(message_id) = get_header_value(article, "Message-ID") else {
+                        continue;
+                    };
+                    if client.contains(&message_id).await? {
+                        continue;
+                    }
+                    client.append(&mailbox, &article_text(article)).await?;
+                    appended += 1;
+                }
+            }
+            client.logout().await;
+            Ok(appended)
+        }
+    }
+}
+
+/// The groups whose name matches `pattern`.
+async fn matching_groups(storage: &dyn Storage, pattern: &str) -> Result<Vec<String>> {
+    let mut groups = Vec::new();
+    let mut stream = storage.list_groups();
+    while let Some(group) = stream.next().await {
+        let group = group?;
+        if crate::wildmat::wildmat(pattern, &group) {
+            groups.push(group);
+        }
+    }
+    Ok(groups)
+}
+
+/// The newest `max` articles of `group`, newest first.
+async fn newest_articles(storage: &dyn Storage, group: &str, max: usize) -> Result<Vec<Message>> {
+    let mut numbers = Vec::new();
+    let mut stream = storage.list_article_numbers(group);
+    while let Some(number) = stream.next().await {
+        numbers.push(number?);
+    }
+    drop(stream);
+    numbers.sort_unstable();
+
+    let mut articles = Vec::with_capacity(max.min(numbers.len()));
+    for number in numbers.into_iter().rev().take(max) {
+        // The article may have been removed since it was listed
+        if let Some(article) = storage.get_article_by_number(group, number).await? {
+            articles.push(article);
+        }
+    }
+    Ok(articles)
+}
+
+/// Build the RSS 2.0 feed of `group` from `articles`, newest first.
+#[must_use]
+pub fn rss_feed(group: &str, site_name: &str, rule: &BridgeRule, articles: &[Message]) -> String {
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<rss version=\"2.0\">\n<channel>\n");
+    let _ = writeln!(feed, "<title>{}</title>", escape(group));
+    let _ = writeln!(feed, "<link>{}</link>", escape(&format!("news:{group}")));
+    let _ = writeln!(
+        feed,
+        "<description>{}</description>",
+        escape(&format!("Articles in {group} on {site_name}"))
+    );
+    for article in articles {
+        let field = |name| get_header_value(article, name).unwrap_or_default();
+        let message_id = field("Message-ID");
+        feed.push_str("<item>\n");
+        let _ = writeln!(feed, "<title>{}</title>", escape(&field("Subject")));
+        let _ = writeln!(
+            feed,
+            "<link>{}</link>",
+            escape(&entry_link(rule, group, &message_id))
+        );
+        let _ = writeln!(feed, "<author>{}</author>", escape(&field("From")));
+        let _ = writeln!(
+            feed,
+            "<guid isPermaLink=\"false\">{}</guid>",
+            escape(&message_id)
+        );
+        if let Some(date) = article_date(article) {
+            let _ = writeln!(feed, "<pubDate>{}</pubDate>", date.to_rfc2822());
+        }
+        // Readers show the description as HTML; keep the line breaks
+        let body = format!("<pre>{}</pre>", escape(&plain_body(article)));
+        let _ = writeln!(feed, "<description>{}</description>", escape(&body));
+        feed.push_str("</item>\n");
+    }
+    feed.push_str("</channel>\n</rss>\n");
+    feed
+}
+
+/// Build the Atom feed of `group` from `articles`, newest first.
+#[must_use]
+pub fn atom_feed(
+    group: &str,
+    site_name: &str,
+    rule: &BridgeRule,
+    articles: &[Message],
+    now: DateTime<Utc>,
+) -> String {
+    let updated = articles
+        .iter()
+        .filter_map(article_date)
+        .max()
+        .unwrap_or(now);
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(feed, "<id>{}</id>", escape(&format!("news:{group}")));
+    let _ = writeln!(feed, "<title>{}</title>", escape(group));
+    let _ = writeln!(
+        feed,
+        "<subtitle>{}</subtitle>",
+        escape(&format!("Articles in {group} on {site_name}"))
+    );
+    let _ = writeln!(feed, "<updated>{}</updated>", updated.to_rfc3339());
+    for article in articles {
+        let field = |name| get_header_value(article, name).unwrap_or_default();
+        let message_id = field("Message-ID");
+        feed.push_str("<entry>\n");
+        let _ = writeln!(feed, "<id>{}</id>", escape(&news_uri(&message_id)));
+        let _ = writeln!(feed, "<title>{}</title>", escape(&field("Subject")));
+        let _ = writeln!(
+            feed,
+            "<link href=\"{}\"/>",
+            escape(&entry_link(rule, group, &message_id))
+        );
+        let _ = writeln!(
+            feed,
+            "<author><name>{}</name></author>",
+            escape(&field("From"))
+        );
+        let _ = writeln!(
+            feed,
+            "<updated>{}</updated>",
+            article_date(article).unwrap_or(updated).to_rfc3339()
+        );
+        let _ = writeln!(
+            feed,
+            "<content type=\"text\">{}</content>",
+            escape(&plain_body(article))
+        );
+        feed.push_str("</entry>\n");
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+/// Replace the feed `name` in `dir` with `feed`. The feed is written under
+/// a temporary name first, so readers never see a partial one.
+fn write_feed(dir: &Path, name: &str, feed: &str) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow!("Failed to create '{}': {e}", dir.display()))?;
+    let tmp = dir.join(format!(".{name}.tmp"));
+    std::fs::write(&tmp, feed).map_err(|e| anyhow!("Failed to write '{}': {e}", tmp.display()))?;
+    std::fs::rename(&tmp, dir.join(name))?;
+    Ok(())
+}
+
+/// Link of the entry for `message_id` in `group`.
+fn entry_link(rule: &BridgeRule, group: &str, message_id: &str) -> String {
+    match &rule.link {
+        Some(link) => link
+            .replace("{group}", group)
+            .replace("{message_id}", &percent_encode(bare_id(message_id))),
+        None => news_uri(message_id),
+    }
+}
+
+/// The RFC 5538 `news:` URI of `message_id`.
+fn news_uri(message_id: &str) -> String {
+    format!("news:{}", percent_encode(bare_id(message_id)))
+}
+
+fn bare_id(message_id: &str) -> &str {
+    message_id
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+}
+
+/// Percent-encode everything but unreserved characters and `@`.
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~@".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Escape `text` for XML, dropping characters XML 1.0 does not allow.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The article body with plain line feeds.
+fn plain_body(article: &Message) -> String {
+    article.body.replace("\r\n", "\n")
+}
+
+fn article_date(article: &Message) -> Option<DateTime<Utc>> {
+    let date = get_header_value(article, "Date")?;
+    DateTime::parse_from_rfc2822(date.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// The article as an RFC 5322 message with CRLF line endings.
+fn article_text(article: &Message) -> String {
+    let mut text = String::new();
+    for line in message_lines(article) {
+        text.push_str(&line);
+        text.push_str("\r\n");
+    }
+    text
+}
+
+/// Mailbox named by an `imap://` or `imaps://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImapTarget {
+    /// Whether TLS is negotiated on connect
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// Mailbox name, possibly with a `{group}` placeholder; `INBOX` when
+    /// the URL has no path
+    pub mailbox: String,
+}
+
+impl FromStr for ImapTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(url: &str) -> Result<Self> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("imaps://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("imap://") {
+            (false, rest)
+        } else {
+            anyhow::bail!("'{url}' is not an imap:// or imaps:// URL");
+        };
+        let (authority, mailbox) = rest.split_once('/').unwrap_or((rest, ""));
+        let default_port = if tls { 993 } else { 143 };
+        // The colons of a bracketed IPv6 address separate no port
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.ends_with(']') => (
+                host,
+                port.parse()
+                    .map_err(|_| anyhow!("invalid port in '{url}'"))?,
+            ),
+            _ => (authority, default_port),
+        };
+        if host.is_empty() {
+            anyhow::bail!("'{url}' names no host");
+        }
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            mailbox: if mailbox.is_empty() {
+                "INBOX".to_string()
+            } else {
+                mailbox.to_string()
+            },
+        })
+    }
+}
+
+/// The few IMAP4rev1 (RFC 3501) commands a bridge needs.
+struct ImapClient {
+    stream: BufReader<Box<dyn ClientStream>>,
+    tag: u32,
+}
+
+impl ImapClient {
+    /// Connect to `target` and read the server greeting.
+    async fn connect(target: &ImapTarget) -> Result<Self> {
+        let addr = format!("{}:{}", target.host, target.port);
+        let tcp = TcpStream::connect(&addr)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to {addr}: {e}"))?;
+        let stream: Box<dyn ClientStream> = if target.tls {
+            let connector = crate::peers::create_tls_connector(None)?;
+            let server_name = tokio_rustls::rustls::ServerName::try_from(target.host.as_str())
+                .map_err(|e| anyhow!("Invalid server name '{}': {e}", target.host))?;
+            Box::new(
+                connector
+                    .connect(server_name, tcp)
+                    .await
+                    .map_err(|e| anyhow!("TLS handshake failed for {addr}: {e}"))?,
+            )
+        } else {
+            Box::new(tcp)
+        };
+        let mut client = Self {
+            stream: BufReader::new(stream),
+            tag: 0,
+        };
+        let greeting = client.read_line().await?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            anyhow::bail!("IMAP server {addr} refused the connection: {greeting}");
+        }
+        Ok(client)
+    }
+
+    async fn login(&mut self, user: &str, password: &str) -> Result<()> {
+        self.command(&format!("LOGIN {} {}", quote(user)?, quote(password)?))
+            .await
+            .map_err(|e| anyhow!("IMAP login as {user} failed: {e}"))?;
+        Ok(())
+    }
+
+    /// Select `mailbox`, creating it first if it does not exist.
+    async fn select_or_create(&mut self, mailbox: &str) -> Result<()> {
+        let quoted = quote(mailbox)?;
+        if self.command(&format!("SELECT {quoted}")).await.is_ok() {
+            return Ok(());
+        }
+        self.command(&format!("CREATE {quoted}")).await?;
+        self.command(&format!("SELECT {quoted}")).await?;
+        Ok(())
+    }
+
+    /// Whether the selected mailbox holds a message with `message_id`.
+    async fn contains(&mut self, message_id: &str) -> Result<bool> {
+        let untagged = self
+            .command(&format!("SEARCH HEADER Message-ID {}", quote(message_id)?))
+            .await?;
+        Ok(untagged.iter().any(|line| {
+            line.strip_prefix("* SEARCH")
+                .is_some_and(|found| !found.trim().is_empty())
+        }))
+    }
+
+    /// Append `text` to `mailbox` as a literal.
+    async fn append(&mut self, mailbox: &str, text: &str) -> Result<()> {
+        let tag = self.next_tag();
+        let command = format!("{tag} APPEND {} {{{}}}\r\n", quote(mailbox)?, text.len());
+        self.stream.get_mut().write_all(command.as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        let line = self.read_line().await?;
+        if !line.starts_with('+') {
+            anyhow::bail!("APPEND refused: {line}");
+        }
+        let writer = self.stream.get_mut();
+        writer.write_all(text.as_bytes()).await?;
+        writer.write_all(b"\r\n").await?;
+        writer.flush().await?;
+        self.finish(&tag).await?;
+        Ok(())
+    }
+
+    /// Log out, ignoring failures as nothing is left to do.
+    async fn logout(mut self) {
+        let _ = self.command("LOGOUT").await;
+    }
+
+    /// Send `command` and wait for its completion, returning the untagged
+    /// responses.
+    async fn command(&mut self, command: &str) -> Result<Vec<String>> {
+        let tag = self.next_tag();
+        let line = format!("{tag} {command}\r\n");
+        self.stream.get_mut().write_all(line.as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        self.finish(&tag).await
+    }
+
+    /// Read responses up to the completion of `tag`, failing unless it is
+    /// `OK`.
+    async fn finish(&mut self, tag: &str) -> Result<Vec<String>> {
+        let mut untagged = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            if let Some(status) = line.strip_prefix(tag).and_then(|s| s.strip_prefix(' ')) {
+                if status.starts_with("OK") {
+                    return Ok(untagged);
+                }
+                anyhow::bail!("{status}");
+            }
+            untagged.push(line);
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            anyhow::bail!("IMAP server closed the connection");
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    fn next_tag(&mut self) -> String {
+        self.tag += 1;
+        format!("r{}", self.tag)
+    }
+}
+
+/// `text` as an IMAP quoted string.
+fn quote(text: &str) -> Result<String> {
+    if text.contains(['\r', '\n']) {
+        anyhow::bail!("line break in IMAP string");
+    }
+    Ok(format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
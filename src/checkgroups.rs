@@ -0,0 +1,291 @@
+//! Keeping hierarchies in line with checkgroups messages.
+//!
+//! A checkgroups control message lists every group of one or more
+//! hierarchies (RFC 5537 section 5.2.3). Comparing the list with the groups
+//! carried gives a [`CheckgroupsDiff`]: groups to create, groups to remove
+//! and groups whose moderation changes. Depending on `checkgroups_mode` the
+//! diff is applied when the message arrives or held until an administrator
+//! applies it with `renews admin apply-checkgroups`.
+
+use crate::audit::{self, AuditAction, AuditEntry};
+use crate::config::Config;
+use crate::storage::Storage;
+use anyhow::{Result, anyhow};
+use futures_util::TryStreamExt;
+use std::fmt;
+use std::str::FromStr;
+
+/// A group listed in a checkgroups message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedGroup {
+    pub name: String,
+    pub description: String,
+    pub moderated: bool,
+}
+
+/// Changes that bring the groups carried in line with a checkgroups
+/// message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckgroupsDiff {
+    /// Listed groups that are not carried
+    pub add: Vec<ListedGroup>,
+    /// Carried groups in the scope of the message that it does not list
+    pub remove: Vec<String>,
+    /// Carried groups whose moderation flag differs from the listed one
+    pub moderate: Vec<(String, bool)>,
+}
+
+impl CheckgroupsDiff {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty() && self.moderate.is_empty()
+    }
+
+    /// Keep only the changes to groups for which `keep` holds.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.add.retain(|g| keep(&g.name));
+        self.remove.retain(|g| keep(g));
+        self.moderate.retain(|(g, _)| keep(g));
+    }
+
+    /// Every group the diff changes.
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.add
+            .iter()
+            .map(|g| g.name.as_str())
+            .chain(self.remove.iter().map(String::as_str))
+            .chain(self.moderate.iter().map(|(g, _)| g.as_str()))
+    }
+}
+
+/// One change per line: `newgroup <name> [moderated]<TAB><description>`,
+/// `rmgroup <name>`, `moderate <name>` or `unmoderate <name>`.
+impl fmt::Display for CheckgroupsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for group in &self.add {
+            let flag = if group.moderated { " moderated" } else { "" };
+            writeln!(f, "newgroup {}{flag}\t{}", group.name, group.description)?;
+        }
+        for group in &self.remove {
+            writeln!(f, "rmgroup {group}")?;
+        }
+        for (group, moderated) in &self.moderate {
+            let verb = if *moderated { "moderate" } else { "unmoderate" };
+            writeln!(f, "{verb} {group}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CheckgroupsDiff {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut diff = CheckgroupsDiff::default();
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let (change, description) = line.split_once('\t').unwrap_or((line, ""));
+            let mut words = change.split_whitespace();
+            let (Some(verb), Some(name)) = (words.next(), words.next()) else {
+                return Err(anyhow!("malformed checkgroups change '{line}'"));
+            };
+            let name = name.to_string();
+            match verb {
+                "newgroup" => diff.add.push(ListedGroup {
+                    name,
+                    description: description.to_string(),
+                    moderated: words.next() == Some("moderated"),
+                }),
+                "rmgroup" => diff.remove.push(name),
+                "moderate" => diff.moderate.push((name, true)),
+                "unmoderate" => diff.moderate.push((name, false)),
+                _ => return Err(anyhow!("malformed checkgroups change '{line}'")),
+            }
+        }
+        Ok(diff)
+    }
+}
+
+/// The hierarchies a checkgroups message covers: the scope given after the
+/// command in its Control header, such as `checkgroups comp !comp.lang
+/// #42`, or else the top-level hierarchies of the groups it lists. Negated
+/// hierarchies keep their `!`; the serial number is dropped.
+#[must_use]
+pub fn scope(control: &str, listed: &[ListedGroup]) -> Vec<String> {
+    let given: Vec<String> = control
+        .split_whitespace()
+        .skip(1)
+        .filter(|word| !word.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if !given.is_empty() {
+        return given;
+    }
+    let mut hierarchies: Vec<String> = listed
+        .iter()
+        .filter_map(|g| g.name.split('.').next())
+        .map(str::to_string)
+        .collect();
+    hierarchies.sort();
+    hierarchies.dedup();
+    hierarchies
+}
+
+fn contains(hierarchy: &str, group: &str) -> bool {
+    group == hierarchy
+        || group
+            .strip_prefix(hierarchy)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// Whether `group` falls in `scope`. The most specific hierarchy containing
+/// the group decides, so `comp !comp.lang` covers `comp.os` but not
+/// `comp.lang.c`.
+#[must_use]
+pub fn in_scope(scope: &[String], group: &str) -> bool {
+    scope
+        .iter()
+        .map(|h| match h.strip_prefix('!') {
+            Some(h) => (h, false),
+            None => (h.as_str(), true),
+        })
+        .filter(|(h, _)| contains(h, group))
+        .max_by_key(|(h, _)| h.len())
+        .is_some_and(|(_, included)| included)
+}
+
+/// Compare the groups `listed` by a checkgroups message for `scope` with
+/// the groups carried. Moderation declared in the configuration wins over
+/// the listed one.
+///
+/// # Errors
+///
+/// Returns an error if the storage fails.
+pub async fn diff(
+    storage: &dyn Storage,
+    config: &Config,
+    scope: &[String],
+    listed: &[ListedGroup],
+) -> Result<CheckgroupsDiff> {
+    let mut diff = CheckgroupsDiff::default();
+    let carried: Vec<String> = storage.list_groups().try_collect().await?;
+    for group in listed.iter().filter(|g| in_scope(scope, &g.name)) {
+        let moderated = config
+            .group_moderation(&group.name)
+            .unwrap_or(group.moderated);
+        if !carried.contains(&group.name) {
+            diff.add.push(ListedGroup {
+                moderated,
+                ..group.clone()
+            });
+        } else if storage.is_group_moderated(&group.name).await? != moderated {
+            diff.moderate.push((group.name.clone(), moderated));
+        }
+    }
+    for group in carried {
+        if in_scope(scope, &group) && !listed.iter().any(|g| g.name == group) {
+            diff.remove.push(group);
+        }
+    }
+    Ok(diff)
+}
+
+/// Carry out `diff` on behalf of `signer`, recording the groups created and
+/// removed in the audit log.
+///
+/// # Errors
+///
+/// Returns an error if the storage fails.
+pub async fn apply(storage: &dyn Storage, diff: &CheckgroupsDiff, signer: &str) -> Result<()> {
+    let entry = |action, group: &str| {
+        AuditEntry::new(action, group)
+            .by(Some(signer))
+            .with_detail("checkgroups")
+    };
+    for group in &diff.add {
+        if group.description.is_empty() {
+            storage.add_group(&group.name, group.moderated).await?;
+        } else {
+            storage
+                .add_group_with_description(&group.name, group.moderated, &group.description)
+                .await?;
+        }
+        audit::record(storage, entry(AuditAction::AddGroup, &group.name)).await;
+    }
+    for group in &diff.remove {
+        storage.remove_group(group).await?;
+        audit::record(storage, entry(AuditAction::RemoveGroup, group)).await;
+    }
+    for (group, moderated) in &diff.moderate {
+        storage.set_group_moderated(group, *moderated).await?;
+    }
+    Ok(())
+}
+
+/// Apply the held checkgroups changes `id` on behalf of their signer and
+/// discard them, returning what was applied, or `None` if nothing is held
+/// under `id`.
+///
+/// # Errors
+///
+/// Returns an error if the held changes cannot be read or storage fails.
+pub async fn apply_pending(storage: &dyn Storage, id: u64) -> Result<Option<CheckgroupsDiff>> {
+    let Some(pending) = storage
+        .list_pending_checkgroups()
+        .await?
+        .into_iter()
+        .find(|p| p.id == id)
+    else {
+        return Ok(None);
+    };
+    let diff: CheckgroupsDiff = pending.diff.parse()?;
+    apply(storage, &diff, &pending.signer).await?;
+    storage.remove_pending_checkgroups(id).await?;
+    Ok(Some(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed(name: &str, moderated: bool) -> ListedGroup {
+        ListedGroup {
+            name: name.to_string(),
+            description: format!("About {name}."),
+            moderated,
+        }
+    }
+
+    #[test]
+    fn scope_comes_from_the_control_header_or_the_listed_groups() {
+        let groups = [listed("comp.lang.c", false), listed("sci.math", false)];
+        assert_eq!(
+            scope("checkgroups comp !comp.lang #42", &groups),
+            vec!["comp", "!comp.lang"]
+        );
+        assert_eq!(scope("checkgroups #42", &groups), vec!["comp", "sci"]);
+
+        let given = scope("checkgroups comp !comp.lang comp.lang.rust", &groups);
+        assert!(in_scope(&given, "comp.os.linux"));
+        assert!(!in_scope(&given, "comp.lang.c"));
+        assert!(in_scope(&given, "comp.lang.rust.announce"));
+        assert!(!in_scope(&given, "company.news"));
+    }
+
+    #[test]
+    fn diffs_round_trip_through_their_text() {
+        let diff = CheckgroupsDiff {
+            add: vec![listed("comp.lang.rust", true)],
+            remove: vec!["comp.lang.obsolete".to_string()],
+            moderate: vec![("comp.lang.c".to_string(), false)],
+        };
+        let text = diff.to_string();
+        assert_eq!(
+            text,
+            "newgroup comp.lang.rust moderated\tAbout comp.lang.rust.\n\
+             rmgroup comp.lang.obsolete\n\
+             unmoderate comp.lang.c\n"
+        );
+        assert_eq!(text.parse::<CheckgroupsDiff>().unwrap(), diff);
+        assert!("frobnicate comp.lang.c".parse::<CheckgroupsDiff>().is_err());
+    }
+}
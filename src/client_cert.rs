@@ -0,0 +1,195 @@
+//! Reader authentication by TLS client certificate
+//!
+//! When `tls_client_ca` is set, TLS connections ask the client for a
+//! certificate issued by one of the authorities in that bundle. Presenting
+//! one is optional, so readers without a certificate still log in with
+//! AUTHINFO. A verified certificate is mapped to a user by its names: the
+//! subject common name, then the e-mail addresses and DNS names of its
+//! subject alternative names. The first name that is a known user
+//! authenticates the session, so machine feeds need no password.
+
+use anyhow::{Result, anyhow};
+use rustls_pemfile::certs;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, ClientCertVerifier};
+use tokio_rustls::rustls::{Certificate, RootCertStore};
+
+const SEQUENCE: u8 = 0x30;
+const OID: u8 = 0x06;
+const OCTET_STRING: u8 = 0x04;
+const VERSION: u8 = 0xa0;
+const EXTENSIONS: u8 = 0xa3;
+const RFC822_NAME: u8 = 0x81;
+const DNS_NAME: u8 = 0x82;
+
+/// id-at-commonName (2.5.4.3)
+const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+/// id-ce-subjectAltName (2.5.29.17)
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Build a verifier that accepts clients with or without a certificate,
+/// checking any certificate presented against the CA bundle at `path`.
+///
+/// # Errors
+///
+/// Returns an error if the bundle cannot be read or holds no usable
+/// certificate.
+pub fn load_verifier(path: &str) -> Result<Arc<dyn ClientCertVerifier>> {
+    let file =
+        File::open(path).map_err(|e| anyhow!("Failed to open TLS client CA file '{path}': {e}"))?;
+    let ders = certs(&mut BufReader::new(file))
+        .map_err(|e| anyhow!("Failed to parse TLS client CA file '{path}': {e}"))?;
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(&ders);
+    if added == 0 {
+        return Err(anyhow!(
+            "No valid CA certificate found in TLS client CA file '{path}'"
+        ));
+    }
+    Ok(AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed())
+}
+
+/// Names of the verified client certificate of `stream`, if one was
+/// presented.
+pub fn peer_names<S>(stream: &tokio_rustls::server::TlsStream<S>) -> Vec<String> {
+    stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(<[Certificate]>::first)
+        .map(|cert| certificate_names(&cert.0))
+        .unwrap_or_default()
+}
+
+/// Names a certificate may be mapped to a user by, in order of preference:
+/// the subject common name, then the e-mail addresses and DNS names of the
+/// subject alternative names.
+#[must_use]
+pub fn certificate_names(der: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    for name in parse_names(der).unwrap_or_default() {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn parse_names(der: &[u8]) -> Option<Vec<String>> {
+    let (_, certificate) = Elements(der).next()?;
+    let (_, tbs) = Elements(certificate).next()?;
+    let mut fields = Elements(tbs).peekable();
+    if fields.peek()?.0 == VERSION {
+        fields.next();
+    }
+    // serialNumber, signature, issuer and validity precede the subject
+    let (_, subject) = fields.nth(4)?;
+
+    let mut names = Vec::new();
+    for (_, rdn) in Elements(subject) {
+        for (_, attribute) in Elements(rdn) {
+            let mut parts = Elements(attribute);
+            if parts.next() == Some((OID, COMMON_NAME))
+                && let Some((_, value)) = parts.next()
+                && let Ok(value) = std::str::from_utf8(value)
+            {
+                names.push(value.to_string());
+            }
+        }
+    }
+
+    let extensions = fields
+        .find(|(tag, _)| *tag == EXTENSIONS)
+        .and_then(|(_, body)| Elements(body).next());
+    for (_, extension) in extensions
+        .map(|(_, list)| Elements(list))
+        .into_iter()
+        .flatten()
+    {
+        let mut parts = Elements(extension);
+        if parts.next() != Some((OID, SUBJECT_ALT_NAME)) {
+            continue;
+        }
+        // Skip the optional critical flag
+        let Some((_, value)) = parts.find(|(tag, _)| *tag == OCTET_STRING) else {
+            continue;
+        };
+        let Some((SEQUENCE, general_names)) = Elements(value).next() else {
+            continue;
+        };
+        for (tag, name) in Elements(general_names) {
+            if (tag == RFC822_NAME || tag == DNS_NAME)
+                && let Ok(name) = std::str::from_utf8(name)
+            {
+                names.push(name.to_string());
+            }
+        }
+    }
+    Some(names)
+}
+
+/// The DER elements in a byte string, as tag and contents. Iteration stops
+/// at the first malformed element.
+struct Elements<'a>(&'a [u8]);
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&tag, rest) = self.0.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (len, rest) = if first < 0x80 {
+            (usize::from(first), rest)
+        } else {
+            let count = usize::from(first & 0x7f);
+            if count == 0 || count > 4 || rest.len() < count {
+                return None;
+            }
+            let (len, rest) = rest.split_at(count);
+            (
+                len.iter().fold(0, |acc, &b| (acc << 8) | usize::from(b)),
+                rest,
+            )
+        };
+        if rest.len() < len {
+            self.0 = &[];
+            return None;
+        }
+        let (contents, rest) = rest.split_at(len);
+        self.0 = rest;
+        Some((tag, contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{CertificateParams, DnType, KeyPair, SanType};
+
+    #[test]
+    fn names_come_from_the_common_name_then_alt_names() {
+        let mut params = CertificateParams::new(vec!["feed.example.org".to_string()]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, "feeder");
+        params
+            .subject_alt_names
+            .push(SanType::Rfc822Name("news@example.org".try_into().unwrap()));
+        params
+            .subject_alt_names
+            .push(SanType::IpAddress("192.0.2.1".parse().unwrap()));
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+
+        assert_eq!(
+            certificate_names(cert.der()),
+            vec!["feeder", "feed.example.org", "news@example.org"]
+        );
+    }
+
+    #[test]
+    fn malformed_certificates_have_no_names() {
+        assert!(certificate_names(&[]).is_empty());
+        assert!(certificate_names(&[0x30, 0x82, 0xff]).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+//! Compression helpers for the XZVER/XZHDR and XFEATURE COMPRESS extensions.
+//!
+//! Binary-downloader clients fetch overview data for very large groups and
+//! expect it either as a raw zlib stream (XFEATURE COMPRESS GZIP) or as a
+//! zlib stream wrapped in yEnc (XZVER/XZHDR).
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use std::io::Write;
+
+/// Line length used when yEnc-encoding compressed overview data.
+pub const YENC_LINE_LENGTH: usize = 128;
+
+/// Compress `data` into a zlib stream.
+///
+/// # Errors
+///
+/// Returns an error if the encoder fails to write or finish the stream.
+pub fn deflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::with_capacity(data.len() / 2), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Encode `data` as a single-part yEnc block named `name`.
+///
+/// The output contains the `=ybegin` and `=yend` lines and uses CRLF line
+/// endings so it can be written directly to an NNTP connection. Lines never
+/// begin with a dot, so no dot-stuffing is required.
+pub fn yenc_encode(data: &[u8], name: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 32 + 128);
+    out.extend_from_slice(
+        format!(
+            "=ybegin line={YENC_LINE_LENGTH} size={} name={name}\r\n",
+            data.len()
+        )
+        .as_bytes(),
+    );
+
+    let mut col = 0;
+    for &byte in data {
+        let encoded = byte.wrapping_add(42);
+        let escape = match encoded {
+            0x00 | b'\n' | b'\r' | b'=' => true,
+            b'\t' | b' ' => col == 0 || col >= YENC_LINE_LENGTH - 1,
+            b'.' => col == 0,
+            _ => false,
+        };
+        if escape {
+            out.push(b'=');
+            out.push(encoded.wrapping_add(64));
+            col += 2;
+        } else {
+            out.push(encoded);
+            col += 1;
+        }
+        if col >= YENC_LINE_LENGTH {
+            out.extend_from_slice(b"\r\n");
+            col = 0;
+        }
+    }
+    if col > 0 {
+        out.extend_from_slice(b"\r\n");
+    }
+
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    out.extend_from_slice(
+        format!("=yend size={} crc32={:08x}\r\n", data.len(), crc.sum()).as_bytes(),
+    );
+    out
+}
+
+/// Decode a single-part yEnc block produced by [`yenc_encode`].
+///
+/// Returns `None` if the `=ybegin`/`=yend` framing is missing.
+pub fn yenc_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut lines = encoded.split(|&b| b == b'\n');
+    if !lines.next()?.starts_with(b"=ybegin ") {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(encoded.len());
+    for line in lines {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.starts_with(b"=yend") {
+            return Some(out);
+        }
+        let mut bytes = line.iter();
+        while let Some(&b) = bytes.next() {
+            let b = if b == b'=' {
+                bytes.next()?.wrapping_sub(64)
+            } else {
+                b
+            };
+            out.push(b.wrapping_sub(42));
+        }
+    }
+    None
+}
+
+/// Compress overview text for an XZVER/XZHDR response.
+///
+/// # Errors
+///
+/// Returns an error if zlib compression fails.
+pub fn compress_overview(text: &[u8]) -> std::io::Result<Vec<u8>> {
+    Ok(yenc_encode(&deflate(text)?, "overview"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn yenc_round_trip_covers_every_byte() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let encoded = yenc_encode(&data, "test");
+        assert!(encoded.starts_with(b"=ybegin line=128 size=1000 name=test\r\n"));
+        assert!(!encoded.split(|&b| b == b'\n').any(|l| l.starts_with(b".")));
+        assert_eq!(yenc_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn compressed_overview_inflates_to_original() {
+        let text = b"1\tSubject\tfrom@example.com\tdate\t<a@b>\t\t10\t1\r\n";
+        let encoded = compress_overview(text).unwrap();
+        let compressed = yenc_decode(&encoded).unwrap();
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, text);
+    }
+}
@@ -4,7 +4,13 @@ use chrono::Duration;
 use regex::Regex;
 use serde::Deserialize;
 use serde::de::{self, Deserializer, Visitor};
+use std::collections::HashMap;
 use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Maximum nesting depth of `include` directives
+const MAX_INCLUDE_DEPTH: usize = 8;
 
 fn default_db_path() -> String {
     "sqlite:///var/lib/renews/news.db".into()
@@ -22,10 +28,34 @@ fn default_peer_sync_schedule() -> String {
     "0 0 * * * *".to_string() // Every hour
 }
 
+fn default_digest_schedule() -> String {
+    "0 0 0 * * *".to_string() // Every day at midnight
+}
+
+fn default_bridge_schedule() -> String {
+    "0 */15 * * * *".to_string() // Every quarter of an hour
+}
+
+fn default_bridge_max_items() -> usize {
+    50
+}
+
+fn default_sendmail_path() -> String {
+    "/usr/sbin/sendmail".into()
+}
+
 fn default_idle_timeout_secs() -> u64 {
     600
 }
 
+fn default_clock_skew_secs() -> u64 {
+    300
+}
+
+fn default_max_message_bytes() -> Option<u64> {
+    Some(64 * 1024 * 1024)
+}
+
 fn default_article_queue_capacity() -> usize {
     1000
 }
@@ -55,7 +85,51 @@ fn default_bandwidth_period_secs() -> Option<u64> {
     Some(30 * 24 * 60 * 60) // 30 days
 }
 
+/// Default posting-account token rotation (1 day in seconds)
+fn default_posting_account_rotation_secs() -> Option<u64> {
+    Some(24 * 60 * 60)
+}
+
+/// Default window in which failed logins are counted (10 minutes)
+fn default_auth_failure_window_secs() -> Option<u64> {
+    Some(10 * 60)
+}
+
+/// Default length of automatic address bans (1 hour)
+fn default_ban_duration_secs() -> Option<u64> {
+    Some(60 * 60)
+}
+
+/// Default delay of the answer to a first failed login (1 second)
+fn default_failure_delay_secs() -> Option<u64> {
+    Some(1)
+}
+
+/// Default longest delay of the answer to a failed login (30 seconds)
+fn default_max_failure_delay_secs() -> Option<u64> {
+    Some(30)
+}
+
+/// Default failed logins after which a user or address is locked out
+fn default_lockout_failures() -> u32 {
+    10
+}
+
+/// Default window in which failed logins count towards a lockout, and
+/// length of a lockout (15 minutes)
+fn default_lockout_secs() -> Option<u64> {
+    Some(15 * 60)
+}
+
 /// Default allow_posting value
+fn default_history_retention_days() -> u64 {
+    10
+}
+
+fn default_audit_retention_days() -> u64 {
+    365
+}
+
 fn default_true() -> bool {
     true
 }
@@ -231,6 +305,103 @@ fn expand_placeholders(text: &str) -> Result<String> {
     Ok(out)
 }
 
+/// Merge the files named by the `include` key of `table` into it.
+///
+/// Patterns are resolved relative to `dir`, the directory of the file that
+/// names them, and may use wildcards in their final component. Matching
+/// files are merged in name order, each after its own includes: tables are
+/// merged key by key, arrays such as `[[group]]` are appended, and other
+/// values replace the ones already set.
+fn apply_includes(table: &mut toml::Table, dir: &Path, depth: usize) -> Result<()> {
+    let Some(include) = table.remove("include") else {
+        return Ok(());
+    };
+    if depth >= MAX_INCLUDE_DEPTH {
+        return Err(anyhow::anyhow!(
+            "Configuration includes are nested more than {MAX_INCLUDE_DEPTH} levels deep; \
+             check for a file that includes itself"
+        ));
+    }
+    let patterns: Vec<String> = match include {
+        toml::Value::String(pattern) => vec![pattern],
+        other => other
+            .try_into()
+            .map_err(|e| anyhow::anyhow!("'include' must be a list of file patterns: {e}"))?,
+    };
+
+    for pattern in patterns {
+        for file in expand_include(dir, &pattern)? {
+            let display = file.display();
+            let text = std::fs::read_to_string(&file).map_err(|e| {
+                anyhow::anyhow!("Failed to read included configuration file '{display}': {e}")
+            })?;
+            let text = expand_placeholders(&text).map_err(|e| {
+                anyhow::anyhow!("Failed to process configuration placeholders in '{display}': {e}")
+            })?;
+            let mut included: toml::Table = toml::from_str(&text).map_err(|e| {
+                anyhow::anyhow!("Failed to parse included configuration file '{display}': {e}")
+            })?;
+            apply_includes(&mut included, file.parent().unwrap_or(dir), depth + 1)?;
+            merge_tables(table, included);
+        }
+    }
+    Ok(())
+}
+
+/// List the files matching an include pattern, sorted by name.
+///
+/// A pattern without wildcards names a single file, which must exist. A
+/// wildcard pattern may match nothing, so an empty `conf.d` is not an error.
+fn expand_include(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let path = dir.join(pattern);
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return Err(anyhow::anyhow!("Invalid include pattern '{pattern}'"));
+    };
+    if !name.contains(['*', '?', '[']) {
+        return Ok(vec![path]);
+    }
+
+    let parent = path.parent().unwrap_or(dir);
+    let entries = match std::fs::read_dir(parent) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(anyhow::anyhow!(
+                "Failed to list configuration directory '{}': {e}",
+                parent.display()
+            ));
+        }
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| wildmat(name, n))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Merge `overlay` into `base`: nested tables are merged, arrays appended
+/// and any other value replaced.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay);
+            }
+            (Some(toml::Value::Array(base)), toml::Value::Array(overlay)) => base.extend(overlay),
+            (Some(slot), value) => *slot = value,
+            (None, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
 /// Parse a size string with optional K/M/G suffix into bytes.
 /// Returns None for empty string.
 /// Returns Some(bytes) for valid size strings.
@@ -302,11 +473,69 @@ where
     deserializer.deserialize_any(SizeVisitor)
 }
 
+/// Deserialize `addr` from a single address or a list of addresses.
+fn deserialize_addrs<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct AddrsVisitor;
+
+    impl<'de> Visitor<'de> for AddrsVisitor {
+        type Value = Vec<String>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an address or a list of addresses")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(vec![v.to_string()])
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: de::SeqAccess<'de>,
+        {
+            let mut addrs = Vec::new();
+            while let Some(addr) = seq.next_element::<String>()? {
+                addrs.push(addr);
+            }
+            if addrs.is_empty() {
+                return Err(de::Error::custom("addr must name at least one address"));
+            }
+            Ok(addrs)
+        }
+    }
+
+    deserializer.deserialize_any(AddrsVisitor)
+}
+
+/// Normalise a listen address into a form accepted by `TcpListener::bind`.
+///
+/// `:port` and a bare `port` listen on all IPv4 interfaces. IPv6 literals
+/// must be bracketed (`[::]:119`); they and `host:port` forms are used as
+/// given.
+#[must_use]
+pub fn listen_addr(raw: &str) -> String {
+    let raw = raw.trim();
+    let port = raw.strip_prefix(':').unwrap_or(raw);
+    if !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) {
+        format!("0.0.0.0:{port}")
+    } else {
+        raw.to_string()
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct Config {
-    pub addr: String,
+    /// Addresses of the plain NNTP listeners
+    #[serde(deserialize_with = "deserialize_addrs")]
+    pub addr: Vec<String>,
     #[serde(default = "default_site_name")]
     pub site_name: String,
+    /// Other names of this server in the `Path` of incoming articles, such
+    /// as names it was known by before `site_name` changed
+    #[serde(default)]
+    pub path_aliases: Vec<String>,
     #[serde(default = "default_db_path")]
     pub db_path: String,
     #[serde(default = "default_auth_db_path")]
@@ -318,6 +547,29 @@ pub struct Config {
     pub peer_sync_schedule: String,
     #[serde(default = "default_idle_timeout_secs")]
     pub idle_timeout_secs: u64,
+    /// How far ahead of the server's clock a NEWGROUPS or NEWNEWS date may
+    /// be before it is refused, allowing for clients whose clocks run fast
+    #[serde(default = "default_clock_skew_secs")]
+    pub clock_skew_secs: u64,
+    /// Largest number of simultaneous connections from one client address
+    /// across all listeners (0 = unlimited)
+    #[serde(default)]
+    pub max_connections_per_ip: u32,
+    /// Hard limit on the size of an article received from a client, in bytes.
+    /// Articles larger than this are discarded while being read.
+    #[serde(
+        default = "default_max_message_bytes",
+        deserialize_with = "deserialize_size"
+    )]
+    pub max_message_bytes: Option<u64>,
+    /// Most article numbers one OVER or XOVER command covers; a longer
+    /// range is answered with its start and the range to continue with
+    /// (unset = unlimited)
+    #[serde(default)]
+    pub max_over_range: Option<u64>,
+    /// Headers added to the overview after `Xref`, as full fields
+    #[serde(default)]
+    pub overview_headers: Vec<String>,
     #[serde(default, alias = "peer")]
     pub peers: Vec<PeerRule>,
     #[serde(default)]
@@ -326,28 +578,140 @@ pub struct Config {
     pub tls_cert: Option<String>,
     #[serde(default)]
     pub tls_key: Option<String>,
+    /// CA bundle used to verify client certificates on TLS connections
+    #[serde(default)]
+    pub tls_client_ca: Option<String>,
+    /// Protocol settings and limits of TLS connections
+    #[serde(default)]
+    pub tls: TlsConfig,
     #[serde(default)]
     pub ws_addr: Option<String>,
+    /// Listen address of the HTTP API (requires the `http-api` feature)
+    #[serde(default)]
+    pub http_addr: Option<String>,
+    /// Path of the Unix domain socket accepting control commands
+    #[serde(default)]
+    pub control_socket: Option<String>,
+    /// Listen address of the `/healthz` and `/readyz` endpoints
+    #[serde(default)]
+    pub health_addr: Option<String>,
+    /// Listen address of the SMTP and LMTP server taking mail for the
+    /// `[[gateway]]` addresses
+    #[serde(default)]
+    pub gateway_addr: Option<String>,
+    /// Additional listeners with their own address, TLS and policy settings
+    #[serde(default, alias = "listener")]
+    pub listeners: Vec<ListenerConfig>,
     #[serde(default = "default_article_queue_capacity")]
     pub article_queue_capacity: usize,
     #[serde(default = "default_article_worker_count")]
     pub article_worker_count: usize,
+    /// Most article workers to run while articles are left waiting; the
+    /// pool shrinks back to `article_worker_count` once the queue is idle.
+    /// Unset keeps the worker count fixed.
+    #[serde(default)]
+    pub article_worker_max_count: Option<usize>,
+    /// Optional path of a write-ahead journal that keeps queued articles
+    /// across restarts.
+    #[serde(default)]
+    pub article_queue_journal: Option<String>,
+    /// When articles offered with IHAVE or TAKETHIS are checked against the
+    /// filters: all before the peer is answered, or only the fast filters,
+    /// leaving the rest to the queue workers
+    #[serde(default)]
+    pub transit_validation: TransitValidation,
+    /// Total size of articles kept in the in-memory cache of articles
+    /// fetched by Message-ID. The cache is disabled when unset.
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub article_cache_bytes: Option<u64>,
+    /// Seconds an article is served from the article cache after it was
+    /// fetched. Articles stay until evicted when unset.
+    #[serde(default)]
+    pub article_cache_ttl_secs: Option<u64>,
+    /// Total size of rendered `OVER` ranges kept in the in-memory overview
+    /// cache. The cache is disabled when unset.
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub overview_cache_bytes: Option<u64>,
     #[serde(default = "default_runtime_threads")]
     pub runtime_threads: usize,
     #[serde(default, alias = "group")]
     pub group_settings: Vec<GroupRule>,
+    /// Wildmat patterns of the groups recommended to new readers by
+    /// LIST SUBSCRIPTIONS
+    #[serde(default)]
+    pub default_subscriptions: Vec<String>,
     #[serde(default, alias = "filter")]
     pub filters: Vec<FilterConfig>,
 
+    /// Daily activity digests
+    #[serde(default, alias = "digest")]
+    pub digests: Vec<DigestRule>,
+    /// Cron schedule on which digests are generated
+    #[serde(default = "default_digest_schedule")]
+    pub digest_schedule: String,
+    /// Read-only mirrors of groups as feeds or IMAP mailboxes
+    #[serde(default, alias = "bridge")]
+    pub bridges: Vec<BridgeRule>,
+    /// Cron schedule on which the bridges are brought up to date
+    #[serde(default = "default_bridge_schedule")]
+    pub bridge_schedule: String,
+    /// Gateways between mailing lists and groups
+    #[serde(default, alias = "gateway")]
+    pub gateways: Vec<GatewayRule>,
+    /// sendmail-compatible program used to email digests and gatewayed
+    /// articles
+    #[serde(default = "default_sendmail_path")]
+    pub sendmail_path: String,
+
     #[serde(default = "default_pgp_key_servers")]
     pub pgp_key_servers: Vec<String>,
+    /// Seconds between refreshes of stored PGP keys from the key servers.
+    /// Zero disables the refresh.
+    #[serde(default)]
+    pub pgp_key_refresh_secs: u64,
 
     #[serde(default)]
     pub allow_auth_insecure_connections: bool,
+    /// Refuse AUTHINFO on every plaintext connection, overriding
+    /// `allow_auth_insecure_connections` on all listeners, and tell clients
+    /// where TLS is offered
+    #[serde(default)]
+    pub require_tls_for_auth: bool,
 
     #[serde(default)]
     pub allow_anonymous_posting: bool,
 
+    /// Secret from which the Cancel-Lock and Cancel-Key headers of local
+    /// posts are derived. No headers are added when unset.
+    #[serde(default)]
+    pub cancel_lock_secret: Option<String>,
+
+    /// Report yEnc and uuencoded binaries through the `:binary` metadata
+    /// item of HDR and LIST HEADERS.
+    #[serde(default)]
+    pub detect_binaries: bool,
+
+    /// Days for which the Message-IDs of removed and refused articles are
+    /// remembered, so that peers cannot feed them again.
+    #[serde(default = "default_history_retention_days")]
+    pub history_retention_days: u64,
+
+    /// Days for which audit log entries are kept. Entries are kept forever
+    /// when set to 0.
+    #[serde(default = "default_audit_retention_days")]
+    pub audit_retention_days: u64,
+
+    /// Days for which articles refused by filters are kept with the reason,
+    /// for `renews admin rejected`. Refused articles are not kept when set
+    /// to 0.
+    #[serde(default)]
+    pub rejected_retention_days: u64,
+
+    /// Cron schedule on which orphaned messages are purged and the storage
+    /// database compacted. No maintenance is run when unset.
+    #[serde(default)]
+    pub maintenance_schedule: Option<String>,
+
     /// Logging configuration
     #[serde(default)]
     pub logging: LoggingConfig,
@@ -355,6 +719,66 @@ pub struct Config {
     /// Default user limits configuration
     #[serde(default)]
     pub user_limits: UserLimitsConfig,
+
+    /// Posting-account tokens for anonymous posters
+    #[serde(default)]
+    pub posting_accounts: PostingAccountConfig,
+
+    /// Automatic bans of addresses that keep failing to log in
+    #[serde(default)]
+    pub address_bans: AddressBanConfig,
+
+    /// Delays and lockouts after failed logins
+    #[serde(default)]
+    pub login_throttle: LoginThrottleConfig,
+
+    /// Localized status texts
+    #[serde(default)]
+    pub responses: ResponsesConfig,
+
+    /// NoCeM notice processing
+    #[serde(default)]
+    pub nocem: NocemConfig,
+
+    /// Protocol traces of selected client connections
+    #[serde(default)]
+    pub trace: TraceConfig,
+
+    /// Who may send which group control messages for which groups. The
+    /// last rule matching a command and group decides; without rules any
+    /// administrator's signed control message is carried out
+    #[serde(default)]
+    pub control_policy: Vec<ControlPolicyRule>,
+
+    /// Whether the groups a checkgroups message adds, removes or changes
+    /// the moderation of are changed at once or held for an administrator
+    #[serde(default)]
+    pub checkgroups_mode: CheckgroupsMode,
+
+    /// Tuning of a SQLite storage database
+    #[serde(default)]
+    pub sqlite: SqliteConfig,
+
+    /// Further article databases, each holding the groups it matches
+    /// instead of `db_path`
+    #[serde(default, alias = "shard")]
+    pub shards: Vec<ShardConfig>,
+
+    /// Hot standby replication of the article store
+    #[serde(default)]
+    pub replication: ReplicationConfig,
+
+    /// Mode and ownership of the `unix://` listener sockets
+    #[serde(default)]
+    pub unix_socket: UnixSocketConfig,
+
+    /// External program checking AUTHINFO credentials
+    #[serde(default)]
+    pub auth_program: Option<AuthProgramConfig>,
+
+    /// Further news sites served by this server, each with its own groups
+    #[serde(default, alias = "vhost")]
+    pub vhosts: Vec<VhostConfig>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -367,126 +791,1430 @@ pub struct GroupRule {
     pub retention_days: Option<i64>,
     #[serde(default, deserialize_with = "deserialize_size")]
     pub max_article_bytes: Option<u64>,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-pub struct PeerRule {
-    pub sitename: String,
+    /// Users allowed to post to the group, as wildmat patterns matched
+    /// against the user name. Anyone may post when unset.
     #[serde(default)]
-    pub patterns: Vec<String>,
+    pub post_users: Option<Vec<String>>,
+    /// Users allowed to see and read the group, as wildmat patterns.
+    /// Anyone may read when unset.
     #[serde(default)]
-    pub sync_schedule: Option<String>,
+    pub read_users: Option<Vec<String>>,
+    /// Store the bodies of articles posted to the group compressed with
+    /// zstd. Articles already stored are left as they are.
+    #[serde(default)]
+    pub compress: Option<bool>,
+    /// Whether the group is moderated. Groups created by control messages
+    /// or the admin CLI take this flag, and `renews admin sync-groups`
+    /// applies it to groups already carried.
+    #[serde(default)]
+    pub moderated: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct FilterConfig {
-    pub name: String,
-    #[serde(flatten)]
-    pub parameters: serde_json::Map<String, serde_json::Value>,
+/// Whether `user` matches one of the wildmat `patterns`. Anonymous clients
+/// match nothing.
+fn user_matches(patterns: &[String], user: Option<&str>) -> bool {
+    user.is_some_and(|user| patterns.iter().any(|p| wildmat(p, user)))
 }
 
-/// Default user limits configuration
-///
-/// These defaults are applied when no per-user limits are configured in the database.
-#[derive(Debug, Deserialize, Clone)]
-pub struct UserLimitsConfig {
-    /// Whether users can post by default
-    #[serde(default = "default_true")]
-    pub allow_posting: bool,
-
-    /// Maximum simultaneous connections per user (0 = unlimited)
-    #[serde(default)]
-    pub max_connections: u32,
-
-    /// Combined bandwidth limit in bytes (None/0 = unlimited)
-    #[serde(default, deserialize_with = "deserialize_bandwidth_limit")]
-    pub bandwidth_limit: Option<u64>,
+/// Find the setting that applies to `group`: that of the rule naming the
+/// group exactly, else that of the most specific matching pattern. Rules
+/// without the setting are skipped.
+fn group_setting<'a, T>(
+    rules: &'a [GroupRule],
+    group: &str,
+    get: impl Fn(&'a GroupRule) -> Option<T>,
+) -> Option<T> {
+    if let Some(value) = rules
+        .iter()
+        .filter(|r| r.group.as_deref() == Some(group))
+        .find_map(&get)
+    {
+        return Some(value);
+    }
+    rules
+        .iter()
+        .filter(|r| r.group.is_none())
+        .filter_map(|r| {
+            let pattern = r.pattern.as_deref().filter(|p| wildmat(p, group))?;
+            Some((pattern, get(r)?))
+        })
+        // Fewer wildcards, then longer patterns, are more specific
+        .min_by_key(|(pattern, _)| {
+            let wildcard_count = pattern.chars().filter(|c| *c == '*' || *c == '?').count();
+            (wildcard_count, std::cmp::Reverse(pattern.len()))
+        })
+        .map(|(_, value)| value)
+}
 
-    /// Bandwidth period in seconds (None = absolute, Some = rolling window)
-    /// Default is 30 days
-    #[serde(
-        default = "default_bandwidth_period_secs",
-        deserialize_with = "deserialize_duration_secs"
-    )]
-    pub bandwidth_period: Option<u64>,
+/// Which groups have their article bodies stored compressed, taken from the
+/// `compress` setting of the group rules so the storage can decide without
+/// the configuration.
+#[derive(Debug, Clone, Default)]
+pub struct ArticleCompression {
+    rules: Vec<GroupRule>,
 }
 
-impl Default for UserLimitsConfig {
-    fn default() -> Self {
-        Self {
-            allow_posting: true,
-            max_connections: 0,
-            bandwidth_limit: None,
-            bandwidth_period: default_bandwidth_period_secs(),
-        }
+impl ArticleCompression {
+    /// Whether articles posted to `group` are compressed.
+    #[must_use]
+    pub fn compresses(&self, group: &str) -> bool {
+        group_setting(&self.rules, group, |r| r.compress).unwrap_or(false)
     }
 }
 
-impl UserLimitsConfig {
-    /// Check if max_connections represents unlimited
+/// Which groups a client may read, taken from the `read_users` of the
+/// group rules so that many groups can be checked without holding the
+/// configuration lock.
+#[derive(Debug, Clone, Default)]
+pub struct ReadAccess {
+    rules: Vec<GroupRule>,
+    user: Option<String>,
+}
+
+impl ReadAccess {
+    /// Whether the client may see and read `group`.
     #[must_use]
-    pub fn is_connections_unlimited(&self) -> bool {
-        self.max_connections == 0
+    pub fn allows(&self, group: &str) -> bool {
+        group_setting(&self.rules, group, |r| r.read_users.as_deref())
+            .is_none_or(|users| user_matches(users, self.user.as_deref()))
     }
 
-    /// Check if bandwidth is unlimited
+    /// Whether the client may read every group.
     #[must_use]
-    pub fn is_bandwidth_unlimited(&self) -> bool {
-        self.bandwidth_limit.is_none()
+    pub fn allows_all(&self) -> bool {
+        self.rules.is_empty()
     }
 }
 
-/// Logging configuration
 #[derive(Debug, Deserialize, Clone)]
-pub struct LoggingConfig {
-    /// Log format: "text" (human-readable) or "json" (structured)
-    #[serde(default = "default_log_format")]
-    pub format: String,
+pub struct PeerRule {
+    pub sitename: String,
+    #[serde(flatten)]
+    pub filter: PeerFilter,
+    #[serde(default)]
+    pub sync_schedule: Option<String>,
+    #[serde(default)]
+    pub mode: PeerMode,
+    /// Feed new articles continuously over a streaming connection
+    #[serde(default)]
+    pub stream: bool,
+    /// CHECK and TAKETHIS commands kept in flight on a streaming feed
+    #[serde(default = "default_stream_window")]
+    pub stream_window: usize,
+    #[serde(flatten)]
+    pub transport: PeerTransport,
+}
 
-    /// Log level filter (e.g., "renews=debug,sqlx=warn")
-    /// If not set, uses RUST_LOG env var or default
+/// How outgoing connections to a peer are secured and authenticated.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct PeerTransport {
+    /// Negotiate TLS on connect; the default port is 563 with TLS and 119
+    /// without
+    #[serde(default = "default_true")]
+    pub tls: bool,
+    /// AUTHINFO user, overriding credentials in `sitename`
     #[serde(default)]
-    pub level: Option<String>,
+    pub username: Option<String>,
+    /// AUTHINFO password for `username`
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Certificate presented to the peer, in PEM format
+    #[serde(default)]
+    pub tls_client_cert: Option<String>,
+    /// PKCS#8 private key of `tls_client_cert`
+    #[serde(default)]
+    pub tls_client_key: Option<String>,
 }
 
-impl Default for LoggingConfig {
+impl Default for PeerTransport {
     fn default() -> Self {
         Self {
-            format: default_log_format(),
-            level: None,
+            tls: true,
+            username: None,
+            password: None,
+            tls_client_cert: None,
+            tls_client_key: None,
         }
     }
 }
 
-impl Config {
-    /// Load configuration from a TOML file.
+impl PeerTransport {
+    /// Check that a client certificate comes with its key and is only used
+    /// over TLS.
     ///
     /// # Errors
     ///
-    /// Returns an error if the file cannot be read or parsed.
-    pub fn from_file(path: &str) -> Result<Self> {
-        let text = match std::fs::read_to_string(path) {
-            Ok(content) => content,
-            Err(e) => {
-                return match e.kind() {
-                    std::io::ErrorKind::NotFound => Err(anyhow::anyhow!(
-                        "Configuration file not found: '{path}'
+    /// Returns an error describing the first inconsistent setting.
+    pub fn validate(&self) -> Result<()> {
+        match (&self.tls_client_cert, &self.tls_client_key) {
+            (Some(_), None) => anyhow::bail!("tls_client_cert is set without tls_client_key"),
+            (None, Some(_)) => anyhow::bail!("tls_client_key is set without tls_client_cert"),
+            (Some(_), Some(_)) if !self.tls => {
+                anyhow::bail!("a client certificate requires tls = true")
+            }
+            _ => {}
+        }
+        if self.password.is_some() && self.username.is_none() {
+            anyhow::bail!("password is set without username");
+        }
+        Ok(())
+    }
+}
 
-Please ensure the configuration file exists at the specified path.
-You can:
-- Create a configuration file at '{path}'
-- Use --config <path> to specify a different location
-- Set the RENEWS_CONFIG environment variable
-- See the example configuration at 'examples/config.toml'"
-                    )),
-                    std::io::ErrorKind::PermissionDenied => Err(anyhow::anyhow!(
-                        "Permission denied reading configuration file: '{path}'
+/// Which articles are sent to a peer.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct PeerFilter {
+    /// Wildmat patterns of the groups exchanged with the peer. The last
+    /// pattern matching a group decides: `!pattern` excludes the group and
+    /// `@pattern` keeps any article posted to it from the peer, even when
+    /// cross-posted to groups the peer takes
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Wildmat patterns of the `Distribution` values the peer takes, with
+    /// `!` excluding. Articles without a `Distribution` header are always
+    /// sent; all are sent when this is empty
+    #[serde(default)]
+    pub distributions: Vec<String>,
+    /// Articles larger than this are not sent to the peer
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_size_bytes: Option<u64>,
+}
 
-Please ensure the file is readable by the current user.
-You may need to check file permissions or run with appropriate privileges."
-                    )),
-                    _ => Err(anyhow::anyhow!(
-                        "Failed to read configuration file '{path}': {e}
+/// How a peer's patterns treat one group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupVerdict {
+    Send,
+    Skip,
+    Poison,
+}
+
+impl PeerFilter {
+    fn verdict(&self, group: &str) -> GroupVerdict {
+        self.patterns
+            .iter()
+            .rev()
+            .find_map(|pattern| {
+                let (verdict, pattern) = if let Some(p) = pattern.strip_prefix('!') {
+                    (GroupVerdict::Skip, p)
+                } else if let Some(p) = pattern.strip_prefix('@') {
+                    (GroupVerdict::Poison, p)
+                } else {
+                    (GroupVerdict::Send, pattern.as_str())
+                };
+                wildmat(pattern, group).then_some(verdict)
+            })
+            .unwrap_or(GroupVerdict::Skip)
+    }
+
+    /// Whether articles in `group` are exchanged with the peer.
+    #[must_use]
+    pub fn wants_group(&self, group: &str) -> bool {
+        self.verdict(group) == GroupVerdict::Send
+    }
+
+    /// Patterns naming the groups the peer takes, without exclusions.
+    pub fn included_patterns(&self) -> impl Iterator<Item = &str> {
+        self.patterns
+            .iter()
+            .map(String::as_str)
+            .filter(|p| !p.starts_with(['!', '@']))
+    }
+
+    /// Whether `article` is sent to the peer: it is posted to a group the
+    /// peer takes and to none it refuses, has a distribution the peer takes
+    /// and is no larger than `max_size_bytes`.
+    #[must_use]
+    pub fn accepts(&self, article: &crate::Message) -> bool {
+        let groups = crate::handlers::utils::extract_newsgroups(article);
+        let verdicts: Vec<GroupVerdict> = groups.iter().map(|g| self.verdict(g)).collect();
+        if verdicts.contains(&GroupVerdict::Poison) || !verdicts.contains(&GroupVerdict::Send) {
+            return false;
+        }
+        if let Some(max) = self.max_size_bytes
+            && crate::overview::article_size(article) > max
+        {
+            return false;
+        }
+        if self.distributions.is_empty() {
+            return true;
+        }
+        crate::handlers::utils::get_header_value(article, "Distribution").is_none_or(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|d| !d.is_empty())
+                .any(|d| crate::wildmat::wildmat_list(&self.distributions, d))
+        })
+    }
+}
+
+fn default_stream_window() -> usize {
+    16
+}
+
+/// Direction in which articles are exchanged with a peer.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerMode {
+    /// Offer local articles to the peer with IHAVE
+    #[default]
+    Push,
+    /// Fetch new articles from the peer with NEWNEWS and ARTICLE
+    Pull,
+    /// Push and pull
+    Both,
+}
+
+impl PeerMode {
+    /// Whether local articles are offered to the peer.
+    #[must_use]
+    pub fn pushes(self) -> bool {
+        matches!(self, PeerMode::Push | PeerMode::Both)
+    }
+
+    /// Whether new articles are fetched from the peer.
+    #[must_use]
+    pub fn pulls(self) -> bool {
+        matches!(self, PeerMode::Pull | PeerMode::Both)
+    }
+}
+
+/// Activity digest for a single group.
+///
+/// Each run summarises the articles the group received in the preceding
+/// day and posts the summary to `post_to`, emails it to `email_to`, or both.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DigestRule {
+    /// Group whose new articles are summarised
+    pub group: String,
+    /// Companion group the digest article is posted to
+    #[serde(default)]
+    pub post_to: Option<String>,
+    /// Addresses the digest is emailed to
+    #[serde(default)]
+    pub email_to: Vec<String>,
+}
+
+/// Format in which a bridge mirrors its groups.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BridgeFormat {
+    /// An RSS 2.0 feed file per group in `output_dir`
+    Rss,
+    /// An Atom feed file per group in `output_dir`
+    Atom,
+    /// Articles appended to the IMAP mailbox of `imap_url`
+    Imap,
+}
+
+/// Read-only mirror of the groups matching `groups`.
+///
+/// Each run looks at the newest `max_items` articles of every matching
+/// group. Feeds are written anew with them; articles not yet in the IMAP
+/// mailbox are appended to it.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BridgeRule {
+    /// Wildmat pattern of the mirrored groups
+    pub groups: String,
+    /// What the groups are mirrored as
+    pub format: BridgeFormat,
+    /// Newest articles of each group that are mirrored
+    #[serde(default = "default_bridge_max_items")]
+    pub max_items: usize,
+    /// Directory the feed files are written to
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    /// Link of feed entries, in which `{group}` and `{message_id}` are
+    /// replaced. Entries link to `news:` URIs by default.
+    #[serde(default)]
+    pub link: Option<String>,
+    /// `imap://` or `imaps://` URL of the mailbox articles are appended to,
+    /// in which `{group}` is replaced
+    #[serde(default)]
+    pub imap_url: Option<String>,
+    /// User logged in as on the IMAP server
+    #[serde(default)]
+    pub imap_user: Option<String>,
+    /// Password of `imap_user`
+    #[serde(default)]
+    pub imap_password: Option<String>,
+}
+
+impl BridgeRule {
+    /// Check that the settings the format needs are present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the missing or invalid setting.
+    pub fn validate(&self) -> Result<()> {
+        match self.format {
+            BridgeFormat::Rss | BridgeFormat::Atom => {
+                if self.output_dir.is_none() {
+                    anyhow::bail!("output_dir must be set for feeds");
+                }
+            }
+            BridgeFormat::Imap => {
+                let Some(url) = &self.imap_url else {
+                    anyhow::bail!("imap_url must be set for imap");
+                };
+                url.parse::<crate::bridge::ImapTarget>()?;
+                if self.imap_user.is_none() || self.imap_password.is_none() {
+                    anyhow::bail!("imap_user and imap_password must be set for imap");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Gateway between a mailing list and a group.
+///
+/// Mail delivered to `address` is posted to `group`, and new articles in
+/// `group` are mailed to `list`. Either direction may be left out.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GatewayRule {
+    /// Group the list is gatewayed with
+    pub group: String,
+    /// Local address the list delivers to, whose mail is posted to `group`
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Posting address of the list new articles are mailed to
+    #[serde(default)]
+    pub list: Option<String>,
+}
+
+impl GatewayRule {
+    /// Check that the gateway works in at least one direction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the missing setting.
+    pub fn validate(&self) -> Result<()> {
+        if self.group.is_empty() {
+            anyhow::bail!("group is empty");
+        }
+        if self.address.is_none() && self.list.is_none() {
+            anyhow::bail!("address or list must be set");
+        }
+        Ok(())
+    }
+
+    /// Whether mail for `recipient` is posted through this gateway.
+    #[must_use]
+    pub fn receives(&self, recipient: &str) -> bool {
+        self.address
+            .as_deref()
+            .is_some_and(|address| address.eq_ignore_ascii_case(recipient.trim()))
+    }
+}
+
+/// An additional client listener.
+///
+/// Settings left unset fall back to the global values, so a listener only
+/// needs to name what differs from the main one.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ListenerConfig {
+    /// Address to listen on, in the same forms as `addr`
+    pub addr: String,
+    /// Negotiate TLS immediately on connect instead of offering STARTTLS
+    #[serde(default)]
+    pub tls: bool,
+    /// Certificate for this listener, overriding `tls_cert`
+    #[serde(default)]
+    pub tls_cert: Option<String>,
+    /// Private key for this listener, overriding `tls_key`
+    #[serde(default)]
+    pub tls_key: Option<String>,
+    #[serde(flatten)]
+    pub policy: ListenerPolicy,
+}
+
+/// Per-listener overrides of the connection policy.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ListenerPolicy {
+    /// Which commands clients of this listener may use
+    #[serde(default)]
+    pub role: ListenerRole,
+    /// Overrides `idle_timeout_secs`
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    /// Overrides `allow_auth_insecure_connections`
+    #[serde(default)]
+    pub allow_auth_insecure_connections: Option<bool>,
+    /// Overrides `allow_anonymous_posting`
+    #[serde(default)]
+    pub allow_anonymous_posting: Option<bool>,
+    /// Name of the `[[vhost]]` served to clients of this listener
+    #[serde(default)]
+    pub vhost: Option<String>,
+    /// Overrides `[responses] locale`
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+/// Commands a listener accepts.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerRole {
+    /// Both reader and transit commands
+    #[default]
+    All,
+    /// Reading and posting, but no IHAVE or streaming feeds
+    Reader,
+    /// Peer feeds only: IHAVE, CHECK and TAKETHIS
+    Transit,
+}
+
+impl ListenerRole {
+    /// Commands that only transit clients use
+    const TRANSIT_COMMANDS: &'static [&'static str] = &["IHAVE", "CHECK", "TAKETHIS"];
+
+    /// Commands that only reader clients use
+    const READER_COMMANDS: &'static [&'static str] = &[
+        "ARTICLE",
+        "HEAD",
+        "BODY",
+        "STAT",
+        "GROUP",
+        "LIST",
+        "LISTGROUP",
+        "NEXT",
+        "LAST",
+        "NEWGROUPS",
+        "NEWNEWS",
+        "HDR",
+        "XPAT",
+        "OVER",
+        "XOVER",
+        "XZVER",
+        "XZHDR",
+        "XTHREAD",
+        "POST",
+        "XPOSTCHECK",
+        "XMODERATE",
+    ];
+
+    /// Whether the upper-case `command` feeds articles from a peer.
+    #[must_use]
+    pub fn is_transit_command(command: &str) -> bool {
+        Self::TRANSIT_COMMANDS.contains(&command)
+    }
+
+    /// Check whether the upper-case `command` may be used under this role.
+    /// Commands shared by both kinds of client, such as AUTHINFO and
+    /// CAPABILITIES, are always allowed.
+    #[must_use]
+    pub fn permits(self, command: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Reader => !Self::TRANSIT_COMMANDS.contains(&command),
+            Self::Transit => !Self::READER_COMMANDS.contains(&command),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FilterConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Default user limits configuration
+///
+/// These defaults are applied when no per-user limits are configured in the database.
+#[derive(Debug, Deserialize, Clone)]
+pub struct UserLimitsConfig {
+    /// Whether users can post by default
+    #[serde(default = "default_true")]
+    pub allow_posting: bool,
+
+    /// Maximum simultaneous connections per user (0 = unlimited)
+    #[serde(default)]
+    pub max_connections: u32,
+
+    /// Combined bandwidth limit in bytes (None/0 = unlimited)
+    #[serde(default, deserialize_with = "deserialize_bandwidth_limit")]
+    pub bandwidth_limit: Option<u64>,
+
+    /// Bandwidth period in seconds (None = absolute, Some = rolling window)
+    /// Default is 30 days
+    #[serde(
+        default = "default_bandwidth_period_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    pub bandwidth_period: Option<u64>,
+}
+
+impl Default for UserLimitsConfig {
+    fn default() -> Self {
+        Self {
+            allow_posting: true,
+            max_connections: 0,
+            bandwidth_limit: None,
+            bandwidth_period: default_bandwidth_period_secs(),
+        }
+    }
+}
+
+impl UserLimitsConfig {
+    /// Check if max_connections represents unlimited
+    #[must_use]
+    pub fn is_connections_unlimited(&self) -> bool {
+        self.max_connections == 0
+    }
+
+    /// Check if bandwidth is unlimited
+    #[must_use]
+    pub fn is_bandwidth_unlimited(&self) -> bool {
+        self.bandwidth_limit.is_none()
+    }
+}
+
+/// Posting-account token configuration
+///
+/// Anonymous posts carry a token derived from the client address in their
+/// `Injection-Info` header, so abuse can be correlated and banned without
+/// storing the address itself.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PostingAccountConfig {
+    /// Secret mixed into every token. A random salt is generated at startup
+    /// when unset, so tokens change whenever the server restarts.
+    #[serde(default)]
+    pub salt: Option<String>,
+
+    /// How often tokens change, in seconds (None = never)
+    /// Default is 1 day
+    #[serde(
+        default = "default_posting_account_rotation_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    pub rotation: Option<u64>,
+
+    /// Tokens that may not post
+    #[serde(default)]
+    pub banned: Vec<String>,
+}
+
+impl Default for PostingAccountConfig {
+    fn default() -> Self {
+        Self {
+            salt: None,
+            rotation: default_posting_account_rotation_secs(),
+            banned: Vec::new(),
+        }
+    }
+}
+
+/// Automatic address ban configuration
+///
+/// Addresses that fail `AUTHINFO` too often are added to the ban list kept
+/// in the authentication database, where `renews admin ban-address` puts
+/// its bans as well.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AddressBanConfig {
+    /// Failed logins after which an address is banned (0 = never)
+    #[serde(default)]
+    pub auth_failures: u32,
+
+    /// Window in which failed logins are counted, in seconds
+    /// Default is 10 minutes
+    #[serde(
+        default = "default_auth_failure_window_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    pub auth_failure_window: Option<u64>,
+
+    /// How long an address stays banned, in seconds (None = forever)
+    /// Default is 1 hour
+    #[serde(
+        default = "default_ban_duration_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    pub ban_duration: Option<u64>,
+}
+
+impl Default for AddressBanConfig {
+    fn default() -> Self {
+        Self {
+            auth_failures: 0,
+            auth_failure_window: default_auth_failure_window_secs(),
+            ban_duration: default_ban_duration_secs(),
+        }
+    }
+}
+
+/// Failed login throttling configuration
+///
+/// Failed `AUTHINFO PASS` attempts are counted per user name and per client
+/// address in the authentication database; see [`crate::auth::throttle`].
+#[derive(Debug, Deserialize, Clone)]
+pub struct LoginThrottleConfig {
+    /// Delay of the answer to a first failed login, doubled for every
+    /// further failure, in seconds (None = no delay)
+    /// Default is 1 second
+    #[serde(
+        default = "default_failure_delay_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    pub failure_delay: Option<u64>,
+
+    /// Longest delay of the answer to a failed login, in seconds
+    /// Default is 30 seconds
+    #[serde(
+        default = "default_max_failure_delay_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    pub max_failure_delay: Option<u64>,
+
+    /// Failed logins after which a user or address is locked out (0 = never)
+    #[serde(default = "default_lockout_failures")]
+    pub lockout_failures: u32,
+
+    /// Window in which failed logins are counted, in seconds
+    /// Default is 15 minutes
+    #[serde(
+        default = "default_lockout_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    pub failure_window: Option<u64>,
+
+    /// How long a lockout lasts, in seconds
+    /// Default is 15 minutes
+    #[serde(
+        default = "default_lockout_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    pub lockout_duration: Option<u64>,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            failure_delay: default_failure_delay_secs(),
+            max_failure_delay: default_max_failure_delay_secs(),
+            lockout_failures: default_lockout_failures(),
+            failure_window: default_lockout_secs(),
+            lockout_duration: default_lockout_secs(),
+        }
+    }
+}
+
+/// Localized status text configuration
+///
+/// Every table under `[responses]` is named after a locale and maps response
+/// codes to the human-readable text sent after the code, for example
+/// `[responses.de]` with `411 = "Newsgroup unbekannt"`. `locale` selects the
+/// table to use; a regional locale such as `de-AT` falls back to `de` for
+/// codes it does not override. Codes are never changed.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ResponsesConfig {
+    /// Locale whose texts are served (None = built-in English texts)
+    #[serde(default)]
+    pub locale: Option<String>,
+
+    /// TOML files with further tables of status texts by locale, read when
+    /// the configuration is loaded. Texts given inline take precedence
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+
+    /// Status texts by locale and response code
+    #[serde(flatten)]
+    pub locales: HashMap<String, HashMap<String, String>>,
+}
+
+impl ResponsesConfig {
+    /// Merge the tables of every file in `files`, resolved against `dir`.
+    /// Texts already present are kept, so inline tables override files and
+    /// earlier files override later ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be read or parsed.
+    pub fn load_files(&mut self, dir: &Path) -> Result<()> {
+        for file in &self.files {
+            let path = dir.join(file);
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("cannot read '{}': {e}", path.display()))?;
+            let tables: HashMap<String, HashMap<String, String>> = toml::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("cannot parse '{}': {e}", path.display()))?;
+            for (locale, texts) in tables {
+                let merged = self.locales.entry(locale).or_default();
+                for (code, text) in texts {
+                    merged.entry(code).or_insert(text);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every key is a response code and every text fits on a
+    /// status line.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first invalid entry.
+    pub fn validate(&self) -> Result<()> {
+        for (locale, texts) in &self.locales {
+            for (code, text) in texts {
+                if code == crate::responses::HELP_KEY {
+                    continue;
+                }
+                if code.len() != 3 || code.parse::<u16>().map_or(true, |c| c < 100) {
+                    anyhow::bail!("[responses.{locale}]: '{code}' is not a response code");
+                }
+                if text.contains(['\r', '\n']) {
+                    anyhow::bail!("[responses.{locale}]: text for {code} spans several lines");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// When articles received from peers are validated
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitValidation {
+    /// Run every filter before answering the peer, and store the article
+    /// before it is acknowledged
+    #[default]
+    Inline,
+    /// Run the fast filters before answering the peer and leave the others
+    /// to the queue workers, which store the article if it passes
+    Deferred,
+}
+
+/// Protocol settings and limits of TLS connections (`[tls]`)
+///
+/// These apply to the TLS listener, TLS `[[listener]]` blocks and STARTTLS,
+/// and are reloaded on SIGHUP together with the certificate.
+/// `reload_on_change` is read at startup.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// Oldest protocol version accepted
+    #[serde(default)]
+    pub min_version: TlsVersion,
+
+    /// Cipher suites offered
+    #[serde(default)]
+    pub cipher_policy: CipherPolicy,
+
+    /// Seconds for which a session ticket can resume a session; 0 issues
+    /// no tickets
+    #[serde(default = "default_tls_ticket_lifetime_secs")]
+    pub ticket_lifetime_secs: u32,
+
+    /// Sessions remembered for resumption by session ID; 0 remembers none
+    #[serde(default = "default_tls_session_cache_size")]
+    pub session_cache_size: usize,
+
+    /// TLS handshakes one address may start per minute; 0 is unlimited
+    #[serde(default)]
+    pub handshakes_per_minute: u32,
+
+    /// Load the certificate again when its files change, as after a
+    /// Let's Encrypt renewal, instead of waiting for SIGHUP
+    #[serde(default = "default_true")]
+    pub reload_on_change: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            min_version: TlsVersion::default(),
+            cipher_policy: CipherPolicy::default(),
+            ticket_lifetime_secs: default_tls_ticket_lifetime_secs(),
+            session_cache_size: default_tls_session_cache_size(),
+            handshakes_per_minute: 0,
+            reload_on_change: true,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Longest ticket lifetime: ticket keys are replaced every six hours
+    /// and tickets are refused once two replacements old.
+    pub const MAX_TICKET_LIFETIME_SECS: u32 = 12 * 60 * 60;
+
+    /// Check the settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ticket lifetime is too long.
+    pub fn validate(&self) -> Result<()> {
+        if self.ticket_lifetime_secs > Self::MAX_TICKET_LIFETIME_SECS {
+            anyhow::bail!(
+                "ticket_lifetime_secs must be at most {}",
+                Self::MAX_TICKET_LIFETIME_SECS
+            );
+        }
+        Ok(())
+    }
+}
+
+fn default_tls_ticket_lifetime_secs() -> u32 {
+    6 * 60 * 60
+}
+
+fn default_tls_session_cache_size() -> usize {
+    256
+}
+
+/// TLS protocol version
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[default]
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// Choice of TLS cipher suites
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CipherPolicy {
+    /// The suites recommended by rustls: AES-GCM and ChaCha20-Poly1305 with
+    /// forward secrecy
+    #[default]
+    Default,
+    /// Only suites with 256-bit keys: AES-256-GCM and ChaCha20-Poly1305
+    Strict,
+}
+
+/// Mode and ownership of the Unix domain sockets of `unix://` listeners,
+/// set when a socket is created.
+#[derive(Debug, Deserialize, Clone)]
+pub struct UnixSocketConfig {
+    /// Permission bits, such as `0o660`
+    #[serde(default = "default_unix_socket_mode")]
+    pub mode: u32,
+
+    /// User owning the socket, by name or id
+    #[serde(default)]
+    pub owner: Option<String>,
+
+    /// Group owning the socket, by name or id
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+impl Default for UnixSocketConfig {
+    fn default() -> Self {
+        Self {
+            mode: default_unix_socket_mode(),
+            owner: None,
+            group: None,
+        }
+    }
+}
+
+impl UnixSocketConfig {
+    /// Check that the mode holds permission bits only.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the invalid mode.
+    pub fn validate(&self) -> Result<()> {
+        if self.mode > 0o777 {
+            anyhow::bail!(
+                "mode {:#o} is not a permission mode; write it in octal, such as 0o660",
+                self.mode
+            );
+        }
+        Ok(())
+    }
+}
+
+fn default_unix_socket_mode() -> u32 {
+    0o660
+}
+
+/// Tuning of a SQLite storage database
+///
+/// These settings are read when the database is opened and are ignored for
+/// PostgreSQL.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SqliteConfig {
+    /// Journal mode of the database
+    #[serde(default)]
+    pub journal_mode: SqliteJournal,
+
+    /// How long a statement waits for a lock held by another connection
+    #[serde(default = "default_sqlite_busy_timeout_ms")]
+    pub busy_timeout_ms: u64,
+
+    /// Largest number of pooled connections
+    #[serde(default = "default_sqlite_max_connections")]
+    pub max_connections: u32,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        Self {
+            journal_mode: SqliteJournal::default(),
+            busy_timeout_ms: default_sqlite_busy_timeout_ms(),
+            max_connections: default_sqlite_max_connections(),
+        }
+    }
+}
+
+fn default_sqlite_busy_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_sqlite_max_connections() -> u32 {
+    5
+}
+
+/// SQLite journal mode
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SqliteJournal {
+    /// Write-ahead log; readers and snapshots run alongside the writer
+    #[default]
+    Wal,
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Off,
+}
+
+/// Hot standby replication configuration
+///
+/// A primary records every change to its article store and streams the
+/// changes to standbys connecting to `listen`; a standby follows the primary
+/// at `primary` and applies them to its own store. Both sides must share
+/// `secret`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReplicationConfig {
+    /// Address on which standbys are served the change log
+    #[serde(default)]
+    pub listen: Option<String>,
+
+    /// Replication address of the primary this server follows as a standby
+    #[serde(default)]
+    pub primary: Option<String>,
+
+    /// Secret standbys present to the primary
+    #[serde(default)]
+    pub secret: Option<String>,
+
+    /// Days for which changes are kept for standbys that fall behind (0 =
+    /// keep forever)
+    #[serde(default = "default_replication_log_days")]
+    pub log_retention_days: u64,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            primary: None,
+            secret: None,
+            log_retention_days: default_replication_log_days(),
+        }
+    }
+}
+
+impl ReplicationConfig {
+    /// Whether this server follows a primary.
+    #[must_use]
+    pub fn is_standby(&self) -> bool {
+        self.primary.is_some()
+    }
+
+    /// Check that a secret is set whenever replication is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the missing setting.
+    pub fn validate(&self) -> Result<()> {
+        if (self.listen.is_some() || self.primary.is_some())
+            && self.secret.as_deref().is_none_or(str::is_empty)
+        {
+            anyhow::bail!("secret must be set when listen or primary is");
+        }
+        Ok(())
+    }
+}
+
+fn default_replication_log_days() -> u64 {
+    7
+}
+
+/// Protocol trace configuration
+///
+/// The sessions of clients connecting from one of `addresses`, or
+/// authenticating as one of `users`, are written to `file`. Nothing is
+/// traced without a file.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TraceConfig {
+    /// File the traces are written to
+    #[serde(default)]
+    pub file: Option<String>,
+
+    /// Size in bytes after which the file is rotated
+    #[serde(default = "default_trace_max_file_bytes")]
+    pub max_file_bytes: u64,
+
+    /// Rotated files kept besides the current one
+    #[serde(default = "default_trace_keep_files")]
+    pub keep_files: usize,
+
+    /// Client addresses whose sessions are traced
+    #[serde(default)]
+    pub addresses: Vec<IpAddr>,
+
+    /// Users whose sessions are traced once they authenticate
+    #[serde(default)]
+    pub users: Vec<String>,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            max_file_bytes: default_trace_max_file_bytes(),
+            keep_files: default_trace_keep_files(),
+            addresses: Vec::new(),
+            users: Vec::new(),
+        }
+    }
+}
+
+fn default_trace_max_file_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_trace_keep_files() -> usize {
+    5
+}
+
+/// External authentication program configuration
+///
+/// Credentials given with AUTHINFO USER and PASS are checked by running
+/// `path` with `args` instead of against the authentication database, which
+/// still holds administrators, moderators and limits.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthProgramConfig {
+    /// Path of the program
+    pub path: String,
+
+    /// Arguments passed to the program
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// Seconds the program may take before the login fails
+    #[serde(default = "default_auth_program_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl AuthProgramConfig {
+    /// Check that a program is named and given time to answer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the invalid setting.
+    pub fn validate(&self) -> Result<()> {
+        if self.path.trim().is_empty() {
+            anyhow::bail!("path must name the authentication program");
+        }
+        if self.timeout_secs == 0 {
+            anyhow::bail!("timeout_secs must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+fn default_auth_program_timeout_secs() -> u64 {
+    10
+}
+
+/// A virtual news site.
+///
+/// The site keeps its groups and articles in its own article database and
+/// names itself `site_name` in the headers it adds. Everything else,
+/// including users, group rules and filters, is shared with the main site.
+#[derive(Debug, Deserialize, Clone)]
+pub struct VhostConfig {
+    /// Name of the site, selected by listeners and XHOST
+    pub name: String,
+    /// Host names selecting the site through TLS SNI or XHOST
+    #[serde(default)]
+    pub hostnames: Vec<String>,
+    /// Name of the site in `Path`, `Xref` and generated Message-IDs,
+    /// defaulting to `name`
+    #[serde(default)]
+    pub site_name: Option<String>,
+    /// Article database of the site
+    pub db_path: String,
+}
+
+impl VhostConfig {
+    /// Check that the site is named and has a database of its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the invalid setting.
+    pub fn validate(&self, main_db_path: &str) -> Result<()> {
+        if self.name.trim().is_empty() {
+            anyhow::bail!("name must not be empty");
+        }
+        if self.db_path.trim().is_empty() {
+            anyhow::bail!("db_path must name the article database of the site");
+        }
+        if self.db_path == main_db_path {
+            anyhow::bail!("db_path must differ from the main db_path");
+        }
+        Ok(())
+    }
+
+    /// Whether the site is known by `host`, its name or one of its host
+    /// names, compared without regard to case.
+    #[must_use]
+    pub fn answers_to(&self, host: &str) -> bool {
+        std::iter::once(&self.name)
+            .chain(&self.hostnames)
+            .any(|name| name.eq_ignore_ascii_case(host))
+    }
+}
+
+/// An article database holding some of the groups of the main site.
+///
+/// Groups matched by `groups` and the articles posted to them are kept in
+/// `db_path` rather than in the main database, so that busy hierarchies do
+/// not wait on each other's writes.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShardConfig {
+    /// Name of the shard, used in log messages and snapshot file names
+    pub name: String,
+    /// Wildmat patterns of the groups held in the shard; the last matching
+    /// pattern decides, and the first shard matching a group holds it
+    pub groups: Vec<String>,
+    /// Article database of the shard
+    pub db_path: String,
+}
+
+impl ShardConfig {
+    /// Whether the shard holds `group`.
+    #[must_use]
+    pub fn holds(&self, group: &str) -> bool {
+        crate::wildmat::wildmat_list(&self.groups, group)
+    }
+
+    /// Check that the shard is named, holds some groups and has a database
+    /// of its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the invalid setting.
+    pub fn validate(&self, main_db_path: &str) -> Result<()> {
+        if self.name.trim().is_empty() {
+            anyhow::bail!("name must not be empty");
+        }
+        if self.groups.is_empty() {
+            anyhow::bail!("groups must match at least one group");
+        }
+        if self.db_path == main_db_path {
+            anyhow::bail!("db_path must differ from the main db_path");
+        }
+        Ok(())
+    }
+}
+
+/// Group control messages governed by the control policy.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlKind {
+    Newgroup,
+    Rmgroup,
+    Checkgroups,
+}
+
+impl ControlKind {
+    /// Name of the control command
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ControlKind::Newgroup => "newgroup",
+            ControlKind::Rmgroup => "rmgroup",
+            ControlKind::Checkgroups => "checkgroups",
+        }
+    }
+}
+
+/// What is done with a control message matched by a policy rule, as the
+/// actions of INN's control.ctl.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlAction {
+    /// Carry the command out if the signature is allowed by the rule
+    #[default]
+    Doit,
+    /// Only log the command
+    Log,
+    /// Ignore the command silently
+    Drop,
+    /// Carry out an rmgroup by archiving the group instead of removing it,
+    /// if the signature is allowed by the rule; other commands as `doit`
+    Archive,
+}
+
+/// What is done with the changes a checkgroups message asks for.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckgroupsMode {
+    /// Hold the changes until `renews admin apply-checkgroups` applies them
+    #[default]
+    Pending,
+    /// Apply the changes when the message arrives
+    Apply,
+}
+
+/// A rule of the control message policy
+#[derive(Debug, Deserialize, Clone)]
+pub struct ControlPolicyRule {
+    /// Commands the rule applies to (default: all group commands)
+    #[serde(default = "default_control_kinds")]
+    pub commands: Vec<ControlKind>,
+
+    /// Wildmat patterns of the groups the rule applies to, `!` excluding
+    pub groups: Vec<String>,
+
+    /// PGP key IDs or fingerprints whose signatures are accepted. When
+    /// empty the message must be signed by an administrator
+    #[serde(default)]
+    pub key_ids: Vec<String>,
+
+    /// File holding the armored public key signatures are checked against
+    /// instead of the sender's stored key
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub action: ControlAction,
+}
+
+fn default_control_kinds() -> Vec<ControlKind> {
+    vec![
+        ControlKind::Newgroup,
+        ControlKind::Rmgroup,
+        ControlKind::Checkgroups,
+    ]
+}
+
+impl ControlPolicyRule {
+    /// Whether the rule applies to `kind` for `group`.
+    #[must_use]
+    pub fn matches(&self, kind: ControlKind, group: &str) -> bool {
+        self.commands.contains(&kind) && crate::wildmat::wildmat_list(&self.groups, group)
+    }
+}
+
+/// NoCeM notice configuration
+///
+/// Notices posted to `groups` are applied when they come from one of the
+/// `issuers` and carry a valid signature from that issuer's key. Nothing is
+/// processed while no issuer is configured.
+#[derive(Debug, Deserialize, Clone)]
+pub struct NocemConfig {
+    /// Groups NoCeM notices are read from
+    #[serde(default = "default_nocem_groups")]
+    pub groups: Vec<String>,
+
+    /// Issuers whose notices are acted on
+    #[serde(default, alias = "issuer")]
+    pub issuers: Vec<NocemIssuer>,
+}
+
+impl Default for NocemConfig {
+    fn default() -> Self {
+        Self {
+            groups: default_nocem_groups(),
+            issuers: Vec::new(),
+        }
+    }
+}
+
+fn default_nocem_groups() -> Vec<String> {
+    vec![
+        "alt.nocem.misc".to_string(),
+        "news.lists.filters".to_string(),
+    ]
+}
+
+/// A NoCeM issuer whose notices are trusted
+#[derive(Debug, Deserialize, Clone)]
+pub struct NocemIssuer {
+    /// Issuer name as given in the `Issuer` header of its notices
+    pub name: String,
+
+    /// File holding the issuer's armored PGP public key
+    pub key_file: PathBuf,
+
+    /// Notice types acted on (default: spam)
+    #[serde(default = "default_nocem_types")]
+    pub types: Vec<String>,
+}
+
+fn default_nocem_types() -> Vec<String> {
+    vec!["spam".to_string()]
+}
+
+/// Logging configuration
+#[derive(Debug, Deserialize, Clone)]
+pub struct LoggingConfig {
+    /// Log format: "text" (human-readable) or "json" (structured)
+    #[serde(default = "default_log_format")]
+    pub format: String,
+
+    /// Log level filter (e.g., "renews=debug,sqlx=warn")
+    /// If not set, uses RUST_LOG env var or default
+    #[serde(default)]
+    pub level: Option<String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: default_log_format(),
+            level: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from a TOML file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn from_file(path: &str) -> Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                return match e.kind() {
+                    std::io::ErrorKind::NotFound => Err(anyhow::anyhow!(
+                        "Configuration file not found: '{path}'
+
+Please ensure the configuration file exists at the specified path.
+You can:
+- Create a configuration file at '{path}'
+- Use --config <path> to specify a different location
+- Set the RENEWS_CONFIG environment variable
+- See the example configuration at 'examples/config.toml'"
+                    )),
+                    std::io::ErrorKind::PermissionDenied => Err(anyhow::anyhow!(
+                        "Permission denied reading configuration file: '{path}'
+
+Please ensure the file is readable by the current user.
+You may need to check file permissions or run with appropriate privileges."
+                    )),
+                    _ => Err(anyhow::anyhow!(
+                        "Failed to read configuration file '{path}': {e}
 
 Please ensure the file exists and is readable."
                     )),
@@ -502,7 +2230,7 @@ Please check that all $ENV{{...}} and $FILE{{...}} placeholders are valid."
             )
         })?;
 
-        let mut cfg: Config = toml::from_str(&text).map_err(|e| {
+        let parse_error = |e: &dyn fmt::Display| {
             anyhow::anyhow!(
                 "Failed to parse configuration file '{path}': {e}
 
@@ -513,11 +2241,116 @@ Please check the TOML syntax. Common issues:
 
 See 'examples/config.toml' for a valid configuration example."
             )
-        })?;
+        };
+
+        let mut table: toml::Table = toml::from_str(&text).map_err(|e| parse_error(&e))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        apply_includes(&mut table, dir, 0)?;
+        let mut cfg: Config = table.try_into().map_err(|e| parse_error(&e))?;
 
         // Enforce minimum values for queue configuration
         cfg.article_queue_capacity = cfg.article_queue_capacity.max(1);
         cfg.article_worker_count = cfg.article_worker_count.max(1);
+        if let Some(max) = &mut cfg.article_worker_max_count {
+            *max = (*max).max(cfg.article_worker_count);
+        }
+
+        cfg.responses
+            .load_files(dir)
+            .and_then(|()| cfg.responses.validate())
+            .map_err(|e| {
+                anyhow::anyhow!("Invalid localized responses in configuration file '{path}': {e}")
+            })?;
+        cfg.replication.validate().map_err(|e| {
+            anyhow::anyhow!("Invalid [replication] section in configuration file '{path}': {e}")
+        })?;
+        cfg.tls.validate().map_err(|e| {
+            anyhow::anyhow!("Invalid [tls] section in configuration file '{path}': {e}")
+        })?;
+        cfg.unix_socket.validate().map_err(|e| {
+            anyhow::anyhow!("Invalid [unix_socket] section in configuration file '{path}': {e}")
+        })?;
+        if let Some(i) = cfg.control_policy.iter().position(|r| r.groups.is_empty()) {
+            anyhow::bail!(
+                "Invalid [[control_policy]] entry {} in configuration file '{path}': groups is empty",
+                i + 1
+            );
+        }
+        if let Some(program) = &cfg.auth_program {
+            program.validate().map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid [auth_program] section in configuration file '{path}': {e}"
+                )
+            })?;
+        }
+        for (i, vhost) in cfg.vhosts.iter().enumerate() {
+            vhost.validate(&cfg.db_path).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid [[vhost]] '{}' in configuration file '{path}': {e}",
+                    vhost.name
+                )
+            })?;
+            if cfg.vhosts[..i].iter().any(|other| {
+                other.answers_to(&vhost.name)
+                    || vhost.hostnames.iter().any(|host| other.answers_to(host))
+            }) {
+                anyhow::bail!(
+                    "Invalid [[vhost]] '{}' in configuration file '{path}': its name or a host name is used by another virtual host",
+                    vhost.name
+                );
+            }
+        }
+        for (i, shard) in cfg.shards.iter().enumerate() {
+            shard.validate(&cfg.db_path).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid [[shard]] '{}' in configuration file '{path}': {e}",
+                    shard.name
+                )
+            })?;
+            if cfg.shards[..i]
+                .iter()
+                .any(|other| other.name == shard.name || other.db_path == shard.db_path)
+            {
+                anyhow::bail!(
+                    "Invalid [[shard]] '{}' in configuration file '{path}': its name or db_path is used by another shard",
+                    shard.name
+                );
+            }
+        }
+        for listener in &cfg.listeners {
+            if let Some(name) = &listener.policy.vhost
+                && !cfg.vhosts.iter().any(|vhost| &vhost.name == name)
+            {
+                anyhow::bail!(
+                    "Listener '{}' in configuration file '{path}' names unknown virtual host '{name}'",
+                    listener.addr
+                );
+            }
+        }
+        for gateway in &cfg.gateways {
+            gateway.validate().map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid [[gateway]] for group '{}' in configuration file '{path}': {e}",
+                    gateway.group
+                )
+            })?;
+        }
+        for (i, bridge) in cfg.bridges.iter().enumerate() {
+            bridge.validate().map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid [[bridge]] entry {} in configuration file '{path}': {e}",
+                    i + 1
+                )
+            })?;
+        }
+        for peer in &cfg.peers {
+            peer.transport.validate().map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid peer '{}' in configuration file '{path}': {e}",
+                    peer.sitename
+                )
+            })?;
+        }
 
         Ok(cfg)
     }
@@ -606,6 +2439,93 @@ See 'examples/config.toml' for a valid configuration example."
         matches.first().and_then(|r| r.max_article_bytes)
     }
 
+    /// Whether `user` may post to `group`. `user` is `None` for anonymous
+    /// posters, who may only post to groups without `post_users`.
+    #[must_use]
+    pub fn may_post(&self, group: &str, user: Option<&str>) -> bool {
+        group_setting(&self.group_settings, group, |r| r.post_users.as_deref())
+            .is_none_or(|users| user_matches(users, user))
+    }
+
+    /// Whether the configuration declares `group` moderated or unmoderated,
+    /// or `None` if no rule says.
+    #[must_use]
+    pub fn group_moderation(&self, group: &str) -> Option<bool> {
+        group_setting(&self.group_settings, group, |r| r.moderated)
+    }
+
+    /// Groups named exactly by a group rule, in the order of the rules.
+    #[must_use]
+    pub fn declared_groups(&self) -> Vec<&str> {
+        let mut groups: Vec<&str> = Vec::new();
+        for group in self
+            .group_settings
+            .iter()
+            .filter_map(|r| r.group.as_deref())
+        {
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        groups
+    }
+
+    /// The groups `user` may read. Administrators, who may read every
+    /// group, are passed as `admin`.
+    #[must_use]
+    pub fn read_access(&self, user: Option<&str>, admin: bool) -> ReadAccess {
+        if admin {
+            return ReadAccess::default();
+        }
+        ReadAccess {
+            rules: self
+                .group_settings
+                .iter()
+                .filter(|r| r.read_users.is_some())
+                .cloned()
+                .collect(),
+            user: user.map(str::to_string),
+        }
+    }
+
+    /// Headers of the extra overview fields, in order
+    #[must_use]
+    pub fn extra_overview_headers(&self) -> Vec<String> {
+        crate::overview::extra_headers(&self.overview_headers)
+    }
+
+    /// Which groups have their article bodies stored compressed.
+    #[must_use]
+    pub fn article_compression(&self) -> ArticleCompression {
+        ArticleCompression {
+            rules: self
+                .group_settings
+                .iter()
+                .filter(|r| r.compress.is_some())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Names this server appears under in `Path` headers: the site name and
+    /// its aliases.
+    #[must_use]
+    pub fn path_identities(&self) -> Vec<&str> {
+        std::iter::once(self.site_name.as_str())
+            .chain(self.path_aliases.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Port clients are told to use for TLS: that of `tls_addr`, or else of
+    /// the first listener negotiating TLS on connect.
+    #[must_use]
+    pub fn tls_port(&self) -> Option<u16> {
+        self.tls_addr
+            .iter()
+            .chain(self.listeners.iter().filter(|l| l.tls).map(|l| &l.addr))
+            .find_map(|addr| listen_addr(addr).rsplit_once(':')?.1.parse().ok())
+    }
+
     /// Get the actual number of runtime threads, handling the special case where 0 means "use all cores".
     ///
     /// # Errors
@@ -625,37 +2545,85 @@ See 'examples/config.toml' for a valid configuration example."
     /// Only retention, group, filter pipeline, and TLS settings are changed.
     pub fn update_runtime(&mut self, other: Config) {
         self.group_settings = other.group_settings;
+        self.default_subscriptions = other.default_subscriptions;
         self.filters = other.filters;
+        self.digests = other.digests;
+        self.bridges = other.bridges;
+        self.gateways = other.gateways;
+        self.sendmail_path = other.sendmail_path;
+        self.path_aliases = other.path_aliases;
 
         self.peer_sync_schedule = other.peer_sync_schedule;
         self.idle_timeout_secs = other.idle_timeout_secs;
+        self.clock_skew_secs = other.clock_skew_secs;
+        self.max_connections_per_ip = other.max_connections_per_ip;
+        self.max_message_bytes = other.max_message_bytes;
+        self.max_over_range = other.max_over_range;
+        self.overview_headers = other.overview_headers;
+        self.transit_validation = other.transit_validation;
         self.peers = other.peers;
         self.tls_cert = other.tls_cert;
         self.tls_key = other.tls_key;
+        self.tls_client_ca = other.tls_client_ca;
+        self.tls = other.tls;
         self.ws_addr = other.ws_addr;
         self.runtime_threads = other.runtime_threads;
         self.pgp_key_servers = other.pgp_key_servers;
+        self.pgp_key_refresh_secs = other.pgp_key_refresh_secs;
         self.allow_auth_insecure_connections = other.allow_auth_insecure_connections;
+        self.require_tls_for_auth = other.require_tls_for_auth;
         self.allow_anonymous_posting = other.allow_anonymous_posting;
+        self.cancel_lock_secret = other.cancel_lock_secret;
+        self.detect_binaries = other.detect_binaries;
+        self.history_retention_days = other.history_retention_days;
+        self.audit_retention_days = other.audit_retention_days;
+        self.rejected_retention_days = other.rejected_retention_days;
         self.user_limits = other.user_limits;
+        self.posting_accounts = other.posting_accounts;
+        self.address_bans = other.address_bans;
+        self.login_throttle = other.login_throttle;
+        self.responses = other.responses;
+        self.nocem = other.nocem;
+        self.trace = other.trace;
+        self.control_policy = other.control_policy;
+        self.checkgroups_mode = other.checkgroups_mode;
     }
 }
 
 /// Configuration that cannot change after server startup
 #[derive(Debug, Clone)]
 pub struct StaticConfig {
-    pub addr: String,
+    pub addr: Vec<String>,
     pub tls_addr: Option<String>,
     pub tls_cert: Option<String>,
     pub tls_key: Option<String>,
+    pub tls_client_ca: Option<String>,
     pub db_path: String,
     pub auth_db_path: String,
     pub peer_db_path: String,
     pub article_queue_capacity: usize,
     pub article_worker_count: usize,
+    pub article_worker_max_count: Option<usize>,
+    pub article_queue_journal: Option<String>,
+    pub article_cache_bytes: Option<u64>,
+    pub article_cache_ttl_secs: Option<u64>,
+    pub overview_cache_bytes: Option<u64>,
+    pub sqlite: SqliteConfig,
+    pub replication: ReplicationConfig,
+    pub unix_socket: UnixSocketConfig,
+    pub auth_program: Option<AuthProgramConfig>,
     pub runtime_threads: usize,
+    pub digest_schedule: String,
+    pub bridge_schedule: String,
+    pub maintenance_schedule: Option<String>,
+    pub listeners: Vec<ListenerConfig>,
     #[cfg(feature = "websocket")]
     pub ws_addr: Option<String>,
+    #[cfg(feature = "http-api")]
+    pub http_addr: Option<String>,
+    pub control_socket: Option<String>,
+    pub health_addr: Option<String>,
+    pub gateway_addr: Option<String>,
 }
 
 /// Configuration that can be hot-reloaded via SIGHUP
@@ -663,14 +2631,35 @@ pub struct StaticConfig {
 pub struct DynamicConfig {
     pub site_name: String,
     pub idle_timeout_secs: u64,
+    pub clock_skew_secs: u64,
+    pub max_connections_per_ip: u32,
+    pub max_over_range: Option<u64>,
+    pub overview_headers: Vec<String>,
     pub allow_auth_insecure_connections: bool,
+    pub require_tls_for_auth: bool,
     pub allow_anonymous_posting: bool,
+    pub cancel_lock_secret: Option<String>,
+    pub detect_binaries: bool,
+    pub history_retention_days: u64,
+    pub audit_retention_days: u64,
+    pub rejected_retention_days: u64,
     pub group_settings: Vec<GroupRule>,
+    pub default_subscriptions: Vec<String>,
     pub filters: Vec<FilterConfig>,
+    pub digests: Vec<DigestRule>,
+    pub bridges: Vec<BridgeRule>,
+    pub gateways: Vec<GatewayRule>,
+    pub sendmail_path: String,
     pub peers: Vec<PeerRule>,
     pub peer_sync_schedule: Option<String>,
     pub pgp_key_servers: Vec<String>,
     pub user_limits: UserLimitsConfig,
+    pub posting_accounts: PostingAccountConfig,
+    pub address_bans: AddressBanConfig,
+    pub login_throttle: LoginThrottleConfig,
+    pub responses: ResponsesConfig,
+    pub nocem: NocemConfig,
+    pub trace: TraceConfig,
 }
 
 /// Combined server configuration
@@ -686,14 +2675,33 @@ impl From<&Config> for StaticConfig {
             tls_addr: cfg.tls_addr.clone(),
             tls_cert: cfg.tls_cert.clone(),
             tls_key: cfg.tls_key.clone(),
+            tls_client_ca: cfg.tls_client_ca.clone(),
             db_path: cfg.db_path.clone(),
             auth_db_path: cfg.auth_db_path.clone(),
             peer_db_path: cfg.peer_db_path.clone(),
             article_queue_capacity: cfg.article_queue_capacity,
             article_worker_count: cfg.article_worker_count,
+            article_worker_max_count: cfg.article_worker_max_count,
+            article_queue_journal: cfg.article_queue_journal.clone(),
+            article_cache_bytes: cfg.article_cache_bytes,
+            article_cache_ttl_secs: cfg.article_cache_ttl_secs,
+            overview_cache_bytes: cfg.overview_cache_bytes,
+            sqlite: cfg.sqlite.clone(),
+            replication: cfg.replication.clone(),
+            unix_socket: cfg.unix_socket.clone(),
+            auth_program: cfg.auth_program.clone(),
             runtime_threads: cfg.runtime_threads,
+            digest_schedule: cfg.digest_schedule.clone(),
+            bridge_schedule: cfg.bridge_schedule.clone(),
+            maintenance_schedule: cfg.maintenance_schedule.clone(),
+            listeners: cfg.listeners.clone(),
             #[cfg(feature = "websocket")]
             ws_addr: cfg.ws_addr.clone(),
+            #[cfg(feature = "http-api")]
+            http_addr: cfg.http_addr.clone(),
+            control_socket: cfg.control_socket.clone(),
+            health_addr: cfg.health_addr.clone(),
+            gateway_addr: cfg.gateway_addr.clone(),
         }
     }
 }
@@ -703,14 +2711,35 @@ impl From<&Config> for DynamicConfig {
         Self {
             site_name: cfg.site_name.clone(),
             idle_timeout_secs: cfg.idle_timeout_secs,
+            clock_skew_secs: cfg.clock_skew_secs,
+            max_connections_per_ip: cfg.max_connections_per_ip,
+            max_over_range: cfg.max_over_range,
+            overview_headers: cfg.overview_headers.clone(),
             allow_auth_insecure_connections: cfg.allow_auth_insecure_connections,
+            require_tls_for_auth: cfg.require_tls_for_auth,
             allow_anonymous_posting: cfg.allow_anonymous_posting,
+            cancel_lock_secret: cfg.cancel_lock_secret.clone(),
+            detect_binaries: cfg.detect_binaries,
+            history_retention_days: cfg.history_retention_days,
+            audit_retention_days: cfg.audit_retention_days,
+            rejected_retention_days: cfg.rejected_retention_days,
             group_settings: cfg.group_settings.clone(),
+            default_subscriptions: cfg.default_subscriptions.clone(),
             filters: cfg.filters.clone(),
+            digests: cfg.digests.clone(),
+            bridges: cfg.bridges.clone(),
+            gateways: cfg.gateways.clone(),
+            sendmail_path: cfg.sendmail_path.clone(),
             peers: cfg.peers.clone(),
             peer_sync_schedule: Some(cfg.peer_sync_schedule.clone()),
             pgp_key_servers: cfg.pgp_key_servers.clone(),
             user_limits: cfg.user_limits.clone(),
+            posting_accounts: cfg.posting_accounts.clone(),
+            address_bans: cfg.address_bans.clone(),
+            login_throttle: cfg.login_throttle.clone(),
+            responses: cfg.responses.clone(),
+            nocem: cfg.nocem.clone(),
+            trace: cfg.trace.clone(),
         }
     }
 }
@@ -745,6 +2774,57 @@ impl ServerConfig {
 mod tests {
     use super::*;
 
+    #[test]
+    fn listen_addr_forms() {
+        assert_eq!(listen_addr(":119"), "0.0.0.0:119");
+        assert_eq!(listen_addr("119"), "0.0.0.0:119");
+        assert_eq!(listen_addr("127.0.0.1:119"), "127.0.0.1:119");
+        assert_eq!(listen_addr("[::]:119"), "[::]:119");
+        assert_eq!(listen_addr("[::1]:563"), "[::1]:563");
+        assert_eq!(listen_addr("localhost:119"), "localhost:119");
+    }
+
+    #[test]
+    fn group_access_follows_the_most_specific_rule() {
+        let config: Config = toml::from_str(
+            r#"
+            addr = ":119"
+            site_name = "test.com"
+
+            [[group]]
+            pattern = "staff.*"
+            post_users = ["alice", "ops.*"]
+            read_users = ["alice", "bob"]
+
+            [[group]]
+            pattern = "staff.announce"
+            post_users = ["carol"]
+
+            [[group]]
+            group = "staff.open"
+            retention_days = 7
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.may_post("misc", None));
+        assert!(config.may_post("staff.internal", Some("alice")));
+        assert!(config.may_post("staff.internal", Some("ops.night")));
+        assert!(!config.may_post("staff.internal", Some("bob")));
+        assert!(!config.may_post("staff.internal", None));
+        // The more specific pattern wins; a rule without the setting is skipped
+        assert!(config.may_post("staff.announce", Some("carol")));
+        assert!(!config.may_post("staff.announce", Some("alice")));
+        assert!(!config.may_post("staff.open", Some("carol")));
+
+        let bob = config.read_access(Some("bob"), false);
+        assert!(bob.allows("misc"));
+        assert!(bob.allows("staff.announce"));
+        let anonymous = config.read_access(None, false);
+        assert!(!anonymous.allows("staff.internal"));
+        assert!(config.read_access(None, true).allows("staff.internal"));
+    }
+
     #[test]
     fn test_default_pgp_key_servers() {
         let servers = default_pgp_key_servers();
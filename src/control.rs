@@ -1,25 +1,49 @@
 use crate::{
     Message,
+    audit::{self, AuditAction, AuditEntry},
     auth::{
         DynAuth,
         pgp_discovery::{DefaultPgpKeyDiscovery, PgpKeyDiscovery},
+        pgp_refresh::{KeyStatus, key_status},
     },
+    checkgroups::{self, ListedGroup},
+    config::{CheckgroupsMode, ControlAction, ControlKind, ControlPolicyRule},
+    handlers::utils::get_header_value,
     storage::DynStorage,
 };
 use anyhow::Result;
-use base64::{Engine as _, engine::general_purpose::STANDARD};
+use pgp::native::types::KeyTrait;
 use pgp::native::{Deserializable, SignedPublicKey, StandaloneSignature};
-use sha2::{Digest, Sha256, Sha512};
 use std::io::Cursor;
+use tracing::{debug, info};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ControlCommand {
     Cancel(String),
-    NewGroup { group: String, moderated: bool },
+    NewGroup {
+        group: String,
+        moderated: bool,
+    },
     RmGroup(String),
+    /// The authoritative list of the groups of a hierarchy with their
+    /// descriptions, carried in the body
+    CheckGroups,
 }
 
-fn parse_command(val: &str) -> Option<ControlCommand> {
+impl ControlCommand {
+    /// The kind of a group control command, `None` for a cancel.
+    #[must_use]
+    pub fn kind(&self) -> Option<ControlKind> {
+        match self {
+            ControlCommand::Cancel(_) => None,
+            ControlCommand::NewGroup { .. } => Some(ControlKind::Newgroup),
+            ControlCommand::RmGroup(_) => Some(ControlKind::Rmgroup),
+            ControlCommand::CheckGroups => Some(ControlKind::Checkgroups),
+        }
+    }
+}
+
+pub(crate) fn parse_command(val: &str) -> Option<ControlCommand> {
     let mut parts = val.split_whitespace();
     match parts.next()?.to_ascii_lowercase().as_str() {
         "cancel" => parts
@@ -36,42 +60,66 @@ fn parse_command(val: &str) -> Option<ControlCommand> {
             })
         }
         "rmgroup" => parts.next().map(|g| ControlCommand::RmGroup(g.to_string())),
+        "checkgroups" => Some(ControlCommand::CheckGroups),
         _ => None,
     }
 }
 
-fn parse_elements(val: &str) -> Vec<(String, String)> {
-    val.split_whitespace()
-        .filter_map(|p| {
-            let p = p.trim_matches(',');
-            p.split_once(':')
-                .map(|(s, v)| (s.to_ascii_lowercase(), v.to_string()))
-        })
+/// Parse the newsgroups-file lines in the body of a newgroup or checkgroups
+/// message (RFC 5537 section 5.2): a group name, whitespace and the group's
+/// description. When the body has a "For your newsgroups file:" line only
+/// the lines after it are read. A trailing "(Moderated)" is dropped, as
+/// moderation is set by the control command itself. Lines that are not
+/// entries are ignored.
+#[must_use]
+pub fn newsgroups_entries(body: &str) -> Vec<(String, String)> {
+    listed_groups(body)
+        .into_iter()
+        .map(|group| (group.name, group.description))
         .collect()
 }
 
-fn hash_key(scheme: &str, key: &str) -> Option<String> {
-    let bytes = key.as_bytes();
-    let digest = match scheme {
-        "sha256" => Sha256::digest(bytes).to_vec(),
-        "sha512" => Sha512::digest(bytes).to_vec(),
-        "sha1" => sha1::Sha1::digest(bytes).to_vec(),
-        _ => return None,
-    };
-    Some(STANDARD.encode(digest))
+/// Parse the newsgroups-file lines of a control message body as
+/// [`newsgroups_entries`] does, keeping whether each group is marked
+/// "(Moderated)".
+#[must_use]
+pub fn listed_groups(body: &str) -> Vec<ListedGroup> {
+    let start = body
+        .lines()
+        .position(|line| {
+            line.trim()
+                .eq_ignore_ascii_case("For your newsgroups file:")
+        })
+        .map_or(0, |marker| marker + 1);
+    body.lines()
+        .skip(start)
+        .filter_map(|line| {
+            let (name, description) = line.trim_end().split_once(['\t', ' '])?;
+            if !is_group_name(name) {
+                return None;
+            }
+            let description = description.trim();
+            let moderated_at = description
+                .len()
+                .checked_sub(11)
+                .filter(|&end| description[end..].eq_ignore_ascii_case("(moderated)"));
+            let description = moderated_at.map_or(description, |end| description[..end].trim_end());
+            Some(ListedGroup {
+                name: name.to_string(),
+                description: description.to_string(),
+                moderated: moderated_at.is_some(),
+            })
+        })
+        .collect()
 }
 
-fn verify_cancel(keys: &[(String, String)], locks: &[(String, String)]) -> bool {
-    for (scheme, key) in keys {
-        if let Some(hash) = hash_key(scheme, key) {
-            for (ls, lv) in locks {
-                if scheme.eq_ignore_ascii_case(ls) && *lv == hash {
-                    return true;
-                }
-            }
-        }
-    }
-    false
+fn is_group_name(name: &str) -> bool {
+    name.contains('.')
+        && !name.starts_with('.')
+        && !name.ends_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"+-_.".contains(&b))
 }
 
 /// Build the canonical text that was signed according to the pgpcontrol format.
@@ -117,7 +165,8 @@ pub fn is_control_message(msg: &Message) -> bool {
 /// This function attempts to verify a PGP signature using a stored key.
 /// If no key is stored or verification fails, it will attempt to discover
 /// the key from key servers and update the stored key if discovery succeeds
-/// and verification with the new key is successful.
+/// and verification with the new key is successful. The armored key that
+/// verified the signature is returned.
 ///
 /// # Errors
 ///
@@ -131,25 +180,34 @@ pub async fn verify_pgp(
     signed_headers: &str,
     sig_data: &str,
     key_servers: &[String],
-) -> Result<()> {
+) -> Result<String> {
     // Create PGP key discovery instance with configured servers
     let discovery = DefaultPgpKeyDiscovery::with_key_servers(key_servers.to_vec());
 
-    // First try with existing stored key
-    let stored_key = auth.get_pgp_key(user).await?;
+    // First try with existing stored key, unless it has been flagged or
+    // has expired or been revoked since it was stored
+    let mut stored_key = auth.get_pgp_key(user).await?;
+    if let Some(key_text) = &stored_key
+        && (auth.get_pgp_key_problem(user).await?.is_some()
+            || key_status(key_text, chrono::Utc::now()) != KeyStatus::Valid)
+    {
+        stored_key = None;
+    }
 
     if let Some(key_text) = &stored_key
         && let Ok(verification_result) =
             try_verify_with_key(msg, key_text, version, signed_headers, sig_data).await
         && verification_result.is_ok()
     {
-        return Ok(());
+        return Ok(key_text.clone());
     }
     // If verification failed with stored key, try discovery
 
     // Attempt key discovery if no key exists or verification failed
     match discovery.discover_key(user).await? {
-        Some(discovered_key) => {
+        Some(discovered_key)
+            if key_status(&discovered_key, chrono::Utc::now()) == KeyStatus::Valid =>
+        {
             // Try verification with discovered key
             match try_verify_with_key(msg, &discovered_key, version, signed_headers, sig_data)
                 .await?
@@ -162,7 +220,7 @@ pub async fn verify_pgp(
                         tracing::debug!(user = user, error = %e, "Failed to update PGP key details");
                         // Continue anyway since verification succeeded
                     }
-                    Ok(())
+                    Ok(discovered_key)
                 }
                 Err(e) => {
                     // Discovery found a key but verification still failed
@@ -173,8 +231,8 @@ pub async fn verify_pgp(
                 }
             }
         }
-        None => {
-            // No key could be discovered
+        _ => {
+            // No usable key could be discovered
             if stored_key.is_some() {
                 Err(anyhow::anyhow!(
                     "Signature verification failed with stored key and no alternative key could be discovered"
@@ -196,19 +254,274 @@ async fn try_verify_with_key(
     signed_headers: &str,
     sig_data: &str,
 ) -> Result<Result<()>> {
-    let (key, _) = SignedPublicKey::from_string(key_text)?;
     let armor = format!(
         "-----BEGIN PGP SIGNATURE-----\nVersion: {version}\n\n{sig_data}\n-----END PGP SIGNATURE-----\n"
     );
-    let (sig, _) = StandaloneSignature::from_armor_single(Cursor::new(armor.as_bytes()))?;
     let data = canonical_text(msg, signed_headers);
+    verify_detached(key_text, &armor, data.as_bytes())
+}
 
-    match sig.verify(&key, data.as_bytes()) {
+/// Verify an armored detached signature over `data` with an armored public
+/// key.
+///
+/// The outer result fails if the key or signature cannot be parsed, the
+/// inner one if the signature does not match.
+pub(crate) fn verify_detached(key_text: &str, armor: &str, data: &[u8]) -> Result<Result<()>> {
+    let (key, _) = SignedPublicKey::from_string(key_text)?;
+    let (sig, _) = StandaloneSignature::from_armor_single(Cursor::new(armor.as_bytes()))?;
+    match sig.verify(&key, data) {
         Ok(()) => Ok(Ok(())),
         Err(e) => Ok(Err(e.into())),
     }
 }
 
+/// Whether the Cancel-Key of `msg` opens the Cancel-Lock of `orig`.
+fn cancel_key_opens(msg: &Message, orig: &Message) -> bool {
+    match (
+        get_header_value(msg, "Cancel-Key"),
+        get_header_value(orig, "Cancel-Lock"),
+    ) {
+        (Some(key), Some(lock)) => crate::cancel_lock::verify(&key, &lock),
+        _ => false,
+    }
+}
+
+fn has_header(msg: &Message, name: &str) -> bool {
+    msg.headers
+        .iter()
+        .any(|(k, _)| k.eq_ignore_ascii_case(name))
+}
+
+fn sender(msg: &Message) -> &str {
+    msg.headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("From"))
+        .map_or("", |(_, v)| v.as_str())
+}
+
+/// The X-PGP-Sig of `msg` split into its version, signed headers and
+/// signature data.
+fn pgp_sig(msg: &Message) -> Result<(String, String, String)> {
+    let sig_header = msg
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("X-PGP-Sig"))
+        .map(|(_, v)| v.clone())
+        .ok_or_else(|| anyhow::anyhow!("missing signature"))?;
+    let mut words = sig_header.split_whitespace();
+    let version = words
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("bad signature"))?;
+    let signed = words
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("bad signature"))?;
+    let sig_rest = words.collect::<Vec<_>>().join("\n");
+    Ok((version.to_string(), signed.to_string(), sig_rest))
+}
+
+/// Check the X-PGP-Sig of `msg` against the key of the administrator named
+/// in its From header, returning that administrator.
+async fn verify_admin_signature<'a>(
+    msg: &'a Message,
+    auth: &DynAuth,
+    config: &crate::config::Config,
+) -> Result<&'a str> {
+    let from = sender(msg);
+    let (version, signed, sig_rest) = pgp_sig(msg)?;
+    if !auth.is_admin(from).await? {
+        return Err(anyhow::anyhow!("not admin"));
+    }
+    verify_pgp(
+        msg,
+        auth,
+        from,
+        &version,
+        &signed,
+        &sig_rest,
+        &config.pgp_key_servers,
+    )
+    .await?;
+    Ok(from)
+}
+
+/// Whether a key ID or fingerprint from the control policy names the key
+/// with `fingerprint`, given in upper case hex. Short and long key IDs are
+/// the low-order digits of the fingerprint.
+fn key_id_matches(key_id: &str, fingerprint: &str) -> bool {
+    let id: String = key_id
+        .trim()
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    id.len() >= 8 && fingerprint.ends_with(&id)
+}
+
+/// Check the X-PGP-Sig of `msg` for a `doit` rule of the control policy.
+///
+/// The signature is verified with the rule's key file, or else with the
+/// sender's stored or discovered key, and that key must be one of the
+/// rule's key IDs. A rule without key IDs accepts administrators only.
+async fn verify_policy_signature(
+    msg: &Message,
+    rule: &ControlPolicyRule,
+    auth: &DynAuth,
+    config: &crate::config::Config,
+) -> Result<()> {
+    if rule.key_ids.is_empty() {
+        verify_admin_signature(msg, auth, config).await?;
+        return Ok(());
+    }
+    let (version, signed, sig_rest) = pgp_sig(msg)?;
+    let key_text = match &rule.key_file {
+        Some(path) => {
+            let key_text = tokio::fs::read_to_string(path).await.map_err(|e| {
+                anyhow::anyhow!(
+                    "cannot read control policy key from '{}': {e}",
+                    path.display()
+                )
+            })?;
+            try_verify_with_key(msg, &key_text, &version, &signed, &sig_rest).await??;
+            key_text
+        }
+        None => {
+            verify_pgp(
+                msg,
+                auth,
+                sender(msg),
+                &version,
+                &signed,
+                &sig_rest,
+                &config.pgp_key_servers,
+            )
+            .await?
+        }
+    };
+    let (key, _) = SignedPublicKey::from_string(&key_text)?;
+    let fingerprint: String = key
+        .fingerprint()
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect();
+    if rule
+        .key_ids
+        .iter()
+        .any(|id| key_id_matches(id, &fingerprint))
+    {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "key {fingerprint} may not sign this control message"
+        ))
+    }
+}
+
+/// The last control policy rule matching `kind` for `group`, with its index.
+fn matching_rule<'a>(
+    config: &'a crate::config::Config,
+    kind: ControlKind,
+    group: &str,
+) -> Option<(usize, &'a ControlPolicyRule)> {
+    config
+        .control_policy
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, rule)| rule.matches(kind, group))
+}
+
+/// Decide under the control policy whether `kind` is carried out for
+/// `group`. Whether each rule admitted the signature is remembered in
+/// `verified`, so a checkgroups message is checked once per rule. A
+/// signature the rule does not admit is logged and the command ignored.
+async fn policy_allows(
+    msg: &Message,
+    kind: ControlKind,
+    group: &str,
+    auth: &DynAuth,
+    config: &crate::config::Config,
+    verified: &mut Vec<(usize, bool)>,
+) -> Result<bool> {
+    let Some((index, rule)) = matching_rule(config, kind, group) else {
+        debug!(
+            command = kind.as_str(),
+            group, "no control policy rule matches"
+        );
+        return Ok(false);
+    };
+    match rule.action {
+        ControlAction::Drop => Ok(false),
+        ControlAction::Log => {
+            info!(
+                command = kind.as_str(),
+                group,
+                sender = sender(msg),
+                "control message logged by policy"
+            );
+            Ok(false)
+        }
+        ControlAction::Doit | ControlAction::Archive => {
+            if let Some(&(_, admitted)) = verified.iter().find(|(i, _)| *i == index) {
+                return Ok(admitted);
+            }
+            let admitted = match verify_policy_signature(msg, rule, auth, config).await {
+                Ok(()) => true,
+                Err(e) => {
+                    info!(
+                        command = kind.as_str(),
+                        group,
+                        error = %e,
+                        "control message refused by policy"
+                    );
+                    false
+                }
+            };
+            verified.push((index, admitted));
+            Ok(admitted)
+        }
+    }
+}
+
+/// The Message-ID of the article `msg` may replace through its Supersedes
+/// header (RFC 5536 section 3.2.12), if that article is carried.
+///
+/// Superseding is authorized as a cancel is: by a Cancel-Key opening the
+/// Cancel-Lock of the old article, or by the signature of an administrator.
+/// The authorization is returned with the Message-ID, for the audit log.
+///
+/// # Errors
+///
+/// Returns an error if the storage fails.
+pub async fn superseded_article(
+    msg: &Message,
+    storage: &DynStorage,
+    auth: &DynAuth,
+    config: &crate::config::Config,
+) -> Result<Option<(String, &'static str)>> {
+    let Some(id) = get_header_value(msg, "Supersedes")
+        .and_then(|value| value.split_whitespace().next().map(str::to_string))
+    else {
+        return Ok(None);
+    };
+    if get_header_value(msg, "Message-ID").as_deref() == Some(id.as_str()) {
+        return Ok(None);
+    }
+    let Some(orig) = storage.get_article_by_id(&id).await? else {
+        return Ok(None);
+    };
+    if cancel_key_opens(msg, &orig) {
+        return Ok(Some((id, "Cancel-Key")));
+    }
+    if has_header(msg, "X-PGP-Sig") {
+        match verify_admin_signature(msg, auth, config).await {
+            Ok(_) => return Ok(Some((id, "administrator signature"))),
+            Err(e) => debug!(error = %e, superseded = %id, "Supersedes not authorized"),
+        }
+    }
+    Ok(None)
+}
+
 /// Handle control messages for newsgroup management.
 ///
 /// # Errors
@@ -231,73 +544,238 @@ pub async fn handle_control(
     };
     let cmd = parse_command(&control_val).ok_or_else(|| anyhow::anyhow!("unknown control"))?;
 
-    if let ControlCommand::Cancel(ref id) = cmd {
+    if let ControlCommand::Cancel(ref id) = cmd
+        && has_header(msg, "Cancel-Key")
+    {
         // try Cancel-Key authentication first
-        if let Some((_, key_val)) = msg
-            .headers
-            .iter()
-            .find(|(k, _)| k.eq_ignore_ascii_case("Cancel-Key"))
+        if let Some(orig) = storage.get_article_by_id(id).await?
+            && cancel_key_opens(msg, &orig)
         {
-            if let Some(orig) = storage.get_article_by_id(id).await?
-                && let Some((_, lock_val)) = orig
-                    .headers
-                    .iter()
-                    .find(|(k, _)| k.eq_ignore_ascii_case("Cancel-Lock"))
-            {
-                let keys = parse_elements(key_val);
-                let locks = parse_elements(lock_val);
-                if verify_cancel(&keys, &locks) {
-                    storage.delete_article_by_id(id).await?;
-                }
-                return Ok(true);
-            }
+            storage.delete_article_by_id(id).await?;
+            let poster = get_header_value(msg, "From");
+            let entry = AuditEntry::new(AuditAction::Cancel, id.as_str())
+                .by(poster.as_deref())
+                .with_detail("Cancel-Key");
+            audit::record(&**storage, entry).await;
+            return Ok(true);
+        }
+        // a key that opens no lock is ignored unless an administrator
+        // signed the cancel as well
+        if !has_header(msg, "X-PGP-Sig") {
             return Ok(true);
         }
     }
 
-    // fall back to admin-signed control message
-    let from = msg
-        .headers
-        .iter()
-        .find(|(k, _)| k.eq_ignore_ascii_case("From"))
-        .map_or("", |(_, v)| v.as_str());
-    let sig_header = msg
-        .headers
-        .iter()
-        .find(|(k, _)| k.eq_ignore_ascii_case("X-PGP-Sig"))
-        .map(|(_, v)| v.clone())
-        .ok_or_else(|| anyhow::anyhow!("missing signature"))?;
-    if !auth.is_admin(from).await? {
-        return Err(anyhow::anyhow!("not admin"));
+    // group commands go by the control policy when one is configured
+    if let Some(kind) = cmd.kind()
+        && !config.control_policy.is_empty()
+    {
+        return apply_under_policy(msg, &control_val, cmd, kind, storage, auth, config).await;
     }
-    let mut words = sig_header.split_whitespace();
-    let version = words
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("bad signature"))?;
-    let signed = words
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("bad signature"))?;
-    let sig_rest = words.collect::<Vec<_>>().join("\n");
-    verify_pgp(
-        msg,
-        auth,
-        from,
-        version,
-        signed,
-        &sig_rest,
-        &config.pgp_key_servers,
-    )
-    .await?;
+
+    // fall back to admin-signed control message
+    let from = verify_admin_signature(msg, auth, config).await?;
     match cmd {
         ControlCommand::Cancel(id) => {
             storage.delete_article_by_id(&id).await?;
+            let entry = AuditEntry::new(AuditAction::Cancel, id.as_str())
+                .by(Some(from))
+                .with_detail("control message");
+            audit::record(&**storage, entry).await;
+        }
+        ControlCommand::CheckGroups => {
+            let listed = listed_groups(&msg.body);
+            let scope = checkgroups::scope(&control_val, &listed);
+            let diff = checkgroups::diff(&**storage, config, &scope, &listed).await?;
+            process_checkgroups(&listed, &diff, from, storage, config).await?;
+        }
+        cmd => {
+            let entries = newsgroups_entries(&msg.body);
+            apply_group_command(cmd, &entries, from, storage, config).await?;
         }
+    }
+    Ok(true)
+}
+
+/// Carry out a newgroup or rmgroup command from `from`, with the
+/// newsgroups entries of its body.
+async fn apply_group_command(
+    cmd: ControlCommand,
+    entries: &[(String, String)],
+    from: &str,
+    storage: &DynStorage,
+    config: &crate::config::Config,
+) -> Result<()> {
+    let audit_entry = |action, target: &str| {
+        AuditEntry::new(action, target)
+            .by(Some(from))
+            .with_detail("control message")
+    };
+    match cmd {
         ControlCommand::NewGroup { group, moderated } => {
-            storage.add_group(&group, moderated).await?;
+            // A moderation flag declared in the configuration wins over the
+            // one asked for
+            let moderated = config.group_moderation(&group).unwrap_or(moderated);
+            match entries.iter().find(|(name, _)| *name == group) {
+                Some((_, description)) => {
+                    storage
+                        .add_group_with_description(&group, moderated, description)
+                        .await?;
+                }
+                None => storage.add_group(&group, moderated).await?,
+            }
+            audit::record(&**storage, audit_entry(AuditAction::AddGroup, &group)).await;
         }
         ControlCommand::RmGroup(group) => {
             storage.remove_group(&group).await?;
+            audit::record(&**storage, audit_entry(AuditAction::RemoveGroup, &group)).await;
+        }
+        // cancels and checkgroups are carried out by the callers
+        ControlCommand::Cancel(_) | ControlCommand::CheckGroups => {}
+    }
+    Ok(())
+}
+
+/// Describe the carried groups `listed` by a checkgroups message from
+/// `signer`, then apply its other changes or hold them for an
+/// administrator, as `checkgroups_mode` says.
+async fn process_checkgroups(
+    listed: &[ListedGroup],
+    diff: &checkgroups::CheckgroupsDiff,
+    signer: &str,
+    storage: &DynStorage,
+    config: &crate::config::Config,
+) -> Result<()> {
+    for group in listed {
+        storage
+            .set_group_description(&group.name, &group.description)
+            .await?;
+    }
+    if diff.is_empty() {
+        return Ok(());
+    }
+    match config.checkgroups_mode {
+        CheckgroupsMode::Apply => checkgroups::apply(&**storage, diff, signer).await?,
+        CheckgroupsMode::Pending => {
+            let id = storage
+                .add_pending_checkgroups(signer, &diff.to_string())
+                .await?;
+            info!(
+                id,
+                added = diff.add.len(),
+                removed = diff.remove.len(),
+                moderation = diff.moderate.len(),
+                "checkgroups changes held for renews admin apply-checkgroups"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Carry out a group control command as far as the control policy allows.
+/// A checkgroups message only changes and describes the groups the policy
+/// lets its signer manage.
+async fn apply_under_policy(
+    msg: &Message,
+    control: &str,
+    cmd: ControlCommand,
+    kind: ControlKind,
+    storage: &DynStorage,
+    auth: &DynAuth,
+    config: &crate::config::Config,
+) -> Result<bool> {
+    let mut verified = Vec::new();
+    match &cmd {
+        ControlCommand::RmGroup(group)
+            if matching_rule(config, kind, group)
+                .is_some_and(|(_, rule)| rule.action == ControlAction::Archive) =>
+        {
+            if policy_allows(msg, kind, group, auth, config, &mut verified).await?
+                && storage.group_exists(group).await?
+            {
+                storage.set_group_archived(group, true).await?;
+                let entry = AuditEntry::new(AuditAction::ArchiveGroup, group.as_str())
+                    .by(Some(sender(msg)))
+                    .with_detail("control message");
+                audit::record(&**storage, entry).await;
+            }
+        }
+        ControlCommand::NewGroup { group, .. } | ControlCommand::RmGroup(group) => {
+            if policy_allows(msg, kind, group, auth, config, &mut verified).await? {
+                let entries = newsgroups_entries(&msg.body);
+                apply_group_command(cmd, &entries, sender(msg), storage, config).await?;
+            }
+        }
+        _ => {
+            let mut listed = listed_groups(&msg.body);
+            let scope = checkgroups::scope(control, &listed);
+            let mut diff = checkgroups::diff(&**storage, config, &scope, &listed).await?;
+            let mut allowed = Vec::new();
+            let groups: Vec<String> = listed
+                .iter()
+                .map(|g| g.name.as_str())
+                .chain(diff.groups())
+                .map(str::to_string)
+                .collect();
+            for group in groups {
+                if !allowed.contains(&group)
+                    && policy_allows(msg, kind, &group, auth, config, &mut verified).await?
+                {
+                    allowed.push(group);
+                }
+            }
+            listed.retain(|g| allowed.contains(&g.name));
+            diff.retain(|g| allowed.iter().any(|a| a == g));
+            process_checkgroups(&listed, &diff, sender(msg), storage, config).await?;
         }
     }
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newsgroups_entries_are_parsed_from_control_bodies() {
+        let body = "comp.lang.rust is a new unmoderated group.\n\n\
+                    For your newsgroups file:\n\
+                    comp.lang.rust\tThe Rust programming language.\n\
+                    comp.lang.rust.announce  Announcements. (Moderated)\n\
+                    comp.lang.rust.empty\n";
+        assert_eq!(
+            newsgroups_entries(body),
+            vec![
+                (
+                    "comp.lang.rust".to_string(),
+                    "The Rust programming language.".to_string()
+                ),
+                (
+                    "comp.lang.rust.announce".to_string(),
+                    "Announcements.".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn key_ids_match_the_end_of_fingerprints() {
+        let fingerprint = "0123456789ABCDEF0123456789ABCDEF01234567";
+        assert!(key_id_matches("89abcdef01234567", fingerprint));
+        assert!(key_id_matches("0x01234567", fingerprint));
+        assert!(key_id_matches(
+            "0123 4567 89AB CDEF 0123  4567 89AB CDEF 0123 4567",
+            fingerprint
+        ));
+        assert!(!key_id_matches("4567", fingerprint));
+        assert!(!key_id_matches("DEADBEEF", fingerprint));
+    }
+
+    #[test]
+    fn checkgroups_is_a_control_command() {
+        assert_eq!(
+            parse_command("checkgroups #!comp.* 42"),
+            Some(ControlCommand::CheckGroups)
+        );
+    }
+}
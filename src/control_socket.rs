@@ -0,0 +1,300 @@
+//! Local control socket.
+//!
+//! When `control_socket` is set, the server listens on a Unix domain socket
+//! at that path for management commands, so that a running server can be
+//! reloaded and inspected without signals, which are awkward to deliver in
+//! containers. The socket is created readable and writable by its owner
+//! only, as anyone who can connect to it can manage the server.
+//!
+//! Clients send one command per line. Each reply is zero or more data lines
+//! followed by a line reading `ok` or `error <reason>`:
+//!
+//! - `reload` re-reads the configuration file, as SIGHUP does;
+//! - `status` reports the uptime, connection count, queue length,
+//!   whether the server is draining and any trace lines dropped as
+//!   `name value` lines;
+//! - `list-connections` lists client connections as tab-separated id,
+//!   client address, transport and age in seconds;
+//! - `close-connection <id>` disconnects a client;
+//! - `drain` turns new connections away with `400` while existing ones
+//!   carry on, and `resume` accepts them again;
+//! - `trace <address|user>` writes the sessions of a client address or user
+//!   to the protocol trace file, `untrace <address|user>` stops doing so and
+//!   `list-traces` lists what is traced;
+//! - `help` lists the commands and `quit` closes the control connection.
+//!
+//! `renews control <command>` sends a single command and prints the reply.
+
+use crate::protocol_trace::{ProtocolTracer, TraceTarget};
+use crate::queue::ArticleQueue;
+use crate::server::ConnectionTracker;
+use anyhow::{Result, anyhow};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+/// A request to reload the configuration, answered with the outcome.
+pub type ReloadRequest = oneshot::Sender<Result<()>>;
+
+/// Commands understood by the control socket, for `help`.
+const COMMANDS: &[&str] = &[
+    "reload",
+    "status",
+    "list-connections",
+    "close-connection <id>",
+    "drain",
+    "resume",
+    "trace <address|user>",
+    "untrace <address|user>",
+    "list-traces",
+    "quit",
+];
+
+/// Server state reachable through the control socket.
+pub struct ControlSocket {
+    tracker: Arc<ConnectionTracker>,
+    queue: ArticleQueue,
+    reload: mpsc::Sender<ReloadRequest>,
+    tracer: Option<Arc<ProtocolTracer>>,
+}
+
+impl ControlSocket {
+    #[must_use]
+    pub fn new(
+        tracker: Arc<ConnectionTracker>,
+        queue: ArticleQueue,
+        reload: mpsc::Sender<ReloadRequest>,
+    ) -> Self {
+        Self {
+            tracker,
+            queue,
+            reload,
+            tracer: None,
+        }
+    }
+
+    /// Manage the protocol traces written by `tracer`
+    #[must_use]
+    pub fn with_tracer(mut self, tracer: Arc<ProtocolTracer>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Run one command line, returning the reply lines including the final
+    /// `ok` or `error` line.
+    pub async fn execute(&self, line: &str) -> Vec<String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default().to_ascii_lowercase();
+        let args: Vec<&str> = words.collect();
+        let mut reply = Vec::new();
+        let outcome = match (command.as_str(), args.as_slice()) {
+            ("reload", []) => self.request_reload().await,
+            ("status", []) => {
+                reply.push(format!("version {}", env!("CARGO_PKG_VERSION")));
+                reply.push(format!("uptime {}", self.tracker.uptime().as_secs()));
+                reply.push(format!("connections {}", self.tracker.active_connections()));
+                reply.push(format!("queue {}", self.queue.len()));
+                reply.push(format!(
+                    "draining {}",
+                    if self.tracker.is_draining() {
+                        "yes"
+                    } else {
+                        "no"
+                    }
+                ));
+                if let Some(tracer) = &self.tracer {
+                    reply.push(format!("trace-lines-dropped {}", tracer.dropped()));
+                }
+                Ok(())
+            }
+            ("list-connections", []) => {
+                for conn in self.tracker.connections() {
+                    reply.push(format!(
+                        "{}\t{}\t{}\t{}",
+                        conn.id,
+                        conn.peer_ip
+                            .map_or_else(|| "-".to_string(), |ip| ip.to_string()),
+                        if conn.is_tls { "tls" } else { "plain" },
+                        conn.started.elapsed().as_secs()
+                    ));
+                }
+                Ok(())
+            }
+            ("close-connection", [id]) => match id.parse() {
+                Ok(id) if self.tracker.close(id) => {
+                    info!(connection = id, "Connection closed from control socket");
+                    Ok(())
+                }
+                Ok(_) => Err(anyhow!("no such connection")),
+                Err(_) => Err(anyhow!("invalid connection id '{id}'")),
+            },
+            ("drain", []) => {
+                self.tracker.set_draining(true);
+                info!("Draining: new connections are refused");
+                Ok(())
+            }
+            ("resume", []) => {
+                self.tracker.set_draining(false);
+                info!("Accepting new connections again");
+                Ok(())
+            }
+            ("trace", [target]) => self.tracer().map(|tracer| {
+                let target: TraceTarget = target.parse().unwrap_or_else(|e| match e {});
+                info!(%target, "Protocol trace started from control socket");
+                tracer.add(target);
+            }),
+            ("untrace", [target]) => self.tracer().and_then(|tracer| {
+                let target: TraceTarget = target.parse().unwrap_or_else(|e| match e {});
+                if tracer.remove(&target) {
+                    info!(%target, "Protocol trace stopped from control socket");
+                    Ok(())
+                } else {
+                    Err(anyhow!("{target} is not traced"))
+                }
+            }),
+            ("list-traces", []) => self.tracer().map(|tracer| {
+                reply.extend(tracer.targets().iter().map(ToString::to_string));
+            }),
+            ("help", []) => {
+                reply.extend(COMMANDS.iter().map(ToString::to_string));
+                Ok(())
+            }
+            (
+                "reload" | "status" | "list-connections" | "close-connection" | "drain" | "resume"
+                | "trace" | "untrace" | "list-traces" | "help",
+                _,
+            ) => Err(anyhow!("wrong number of arguments for '{command}'")),
+            _ => Err(anyhow!("unknown command '{command}'")),
+        };
+        reply.push(match outcome {
+            Ok(()) => "ok".to_string(),
+            // Keep multi-line messages, such as parse errors, on one line
+            Err(e) => format!(
+                "error {}",
+                e.to_string()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        });
+        reply
+    }
+
+    fn tracer(&self) -> Result<&ProtocolTracer> {
+        self.tracer
+            .as_deref()
+            .ok_or_else(|| anyhow!("protocol tracing needs a [trace] file"))
+    }
+
+    async fn request_reload(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.reload
+            .send(tx)
+            .await
+            .map_err(|_| anyhow!("configuration reload is unavailable"))?;
+        rx.await
+            .map_err(|_| anyhow!("configuration reload is unavailable"))?
+    }
+
+    /// Serve commands from one control connection until it sends `quit` or
+    /// closes.
+    async fn handle(&self, stream: UnixStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.eq_ignore_ascii_case("quit") {
+                writer.write_all(b"ok\n").await?;
+                break;
+            }
+            debug!(command = line, "Control command");
+            let mut reply = self.execute(line).await.join("\n");
+            reply.push('\n');
+            writer.write_all(reply.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Listen for control connections on the Unix domain socket at `path`.
+///
+/// A socket left behind by a server that is no longer running is replaced.
+///
+/// # Errors
+///
+/// Returns an error if another server is listening on `path`, if `path` is
+/// not a socket, or if the socket cannot be created.
+pub fn serve(path: &str, control: Arc<ControlSocket>) -> Result<JoinHandle<()>> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if !meta.file_type().is_socket() => {
+            return Err(anyhow!(
+                "Control socket path '{path}' exists and is not a socket"
+            ));
+        }
+        Ok(_) => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(anyhow!(
+                    "Control socket '{path}' is in use by another running server"
+                ));
+            }
+            std::fs::remove_file(path)
+                .map_err(|e| anyhow!("Failed to remove stale control socket '{path}': {e}"))?;
+        }
+        Err(_) => {}
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|e| anyhow!("Failed to bind control socket '{path}': {e}"))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| anyhow!("Failed to restrict control socket '{path}': {e}"))?;
+    info!(path = path, "Control socket listening");
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let control = control.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = control.handle(stream).await {
+                            debug!(error = %e, "Control connection failed");
+                        }
+                    });
+                }
+                Err(e) => error!(error = %e, "Failed to accept control connection"),
+            }
+        }
+    }))
+}
+
+/// Send `command` to the control socket at `path` and return the data lines
+/// of the reply.
+///
+/// # Errors
+///
+/// Returns an error if the socket cannot be reached or the server reports
+/// an error.
+pub async fn send_command(path: &str, command: &str) -> Result<Vec<String>> {
+    let stream = UnixStream::connect(path)
+        .await
+        .map_err(|e| anyhow!("Failed to connect to control socket '{path}': {e}"))?;
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(format!("{command}\n").as_bytes()).await?;
+    let mut lines = BufReader::new(reader).lines();
+    let mut data = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        if line == "ok" {
+            return Ok(data);
+        }
+        if let Some(reason) = line.strip_prefix("error ") {
+            return Err(anyhow!("{reason}"));
+        }
+        data.push(line);
+    }
+    Err(anyhow!("Control socket closed the connection"))
+}
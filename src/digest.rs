@@ -0,0 +1,188 @@
+//! Daily activity digests.
+//!
+//! For each configured [`DigestRule`] the digest job lists the articles a
+//! group received during the preceding day and summarises the subject,
+//! author and Message-ID overview fields of each. The summary is posted to a
+//! companion group, emailed to subscribers through a sendmail-compatible
+//! program, or both. Groups without new articles get no digest.
+
+use crate::Message;
+use crate::config::{Config, DigestRule};
+use crate::handlers::utils::get_header_value;
+use crate::storage::Storage;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::StreamExt;
+use smallvec::smallvec;
+use std::fmt::Write as _;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tracing::{Instrument, info, info_span, warn};
+
+/// Overview fields listed for each article in a digest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestEntry {
+    pub subject: String,
+    pub from: String,
+    pub message_id: String,
+}
+
+/// Generate the digests for every configured rule.
+///
+/// Each digest covers the day before `now`. Failures are logged per rule so
+/// one broken rule does not prevent the others from running.
+///
+/// # Errors
+///
+/// Errors from individual rules are logged rather than returned.
+pub async fn run_digests(storage: &dyn Storage, cfg: &Config, now: DateTime<Utc>) -> Result<()> {
+    let span = info_span!(
+        "digest.run",
+        rules = cfg.digests.len(),
+        digests_sent = tracing::field::Empty,
+    );
+
+    async {
+        let mut sent = 0u64;
+        for rule in &cfg.digests {
+            match run_digest(storage, cfg, rule, now).await {
+                Ok(true) => sent += 1,
+                Ok(false) => {}
+                Err(e) => warn!(group = rule.group.as_str(), error = %e, "Digest failed"),
+            }
+        }
+        tracing::Span::current().record("digests_sent", sent);
+        info!(digests_sent = sent, "Digest run complete");
+        Ok(())
+    }
+    .instrument(span)
+    .await
+}
+
+/// Build and deliver the digest for one rule. Returns false if the group
+/// received no articles.
+async fn run_digest(
+    storage: &dyn Storage,
+    cfg: &Config,
+    rule: &DigestRule,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    let entries = collect_entries(storage, &rule.group, now - Duration::days(1)).await?;
+    if entries.is_empty() {
+        return Ok(false);
+    }
+
+    let mut digest = build_digest(&rule.group, &cfg.site_name, &entries, now);
+    if let Some(target) = &rule.post_to {
+        if !storage.group_exists(target).await? {
+            return Err(anyhow!("digest group {target} does not exist"));
+        }
+        let message_id = get_header_value(&digest, "Message-ID").unwrap_or_default();
+        if storage.get_article_by_id(&message_id).await?.is_some() {
+            info!(group = rule.group.as_str(), "Digest already posted today");
+            return Ok(false);
+        }
+        digest.headers.push(("Newsgroups".into(), target.clone()));
+        storage.store_article(&digest).await?;
+        digest.headers.retain(|(k, _)| k != "Newsgroups");
+    }
+    if !rule.email_to.is_empty() {
+        send_mail(&cfg.sendmail_path, &rule.email_to, &digest).await?;
+    }
+    Ok(true)
+}
+
+/// List the overview fields of articles `group` received after `since`.
+///
+/// # Errors
+///
+/// Returns an error if the storage backend fails.
+pub async fn collect_entries(
+    storage: &dyn Storage,
+    group: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<DigestEntry>> {
+    let mut ids = Vec::new();
+    let mut stream = storage.list_article_ids_since(group, since);
+    while let Some(id) = stream.next().await {
+        ids.push(id?);
+    }
+    drop(stream);
+
+    let mut entries = Vec::with_capacity(ids.len());
+    let mut articles = storage.get_articles_by_ids(&ids);
+    while let Some(article) = articles.next().await {
+        let (message_id, article) = article?;
+        entries.push(DigestEntry {
+            subject: get_header_value(&article, "Subject").unwrap_or_default(),
+            from: get_header_value(&article, "From").unwrap_or_default(),
+            message_id,
+        });
+    }
+    Ok(entries)
+}
+
+/// Build the digest message for `group` without a destination header.
+///
+/// The Message-ID is derived from the group and date, which lets a rerun on
+/// the same day recognise a digest that was already posted.
+pub fn build_digest(
+    group: &str,
+    site_name: &str,
+    entries: &[DigestEntry],
+    now: DateTime<Utc>,
+) -> Message {
+    let yesterday = now - Duration::days(1);
+    let day = yesterday.format("%Y-%m-%d");
+    let mut body = format!("{} new articles in {group} on {day}:\r\n", entries.len());
+    for entry in entries {
+        let _ = write!(
+            body,
+            "\r\nSubject: {}\r\nFrom: {}\r\nMessage-ID: {}\r\n",
+            entry.subject, entry.from, entry.message_id
+        );
+    }
+
+    Message {
+        headers: smallvec![
+            ("From".into(), format!("digest@{site_name}")),
+            ("Subject".into(), format!("Daily digest for {group}, {day}")),
+            (
+                "Message-ID".into(),
+                format!(
+                    "<digest.{group}.{}@{site_name}>",
+                    yesterday.format("%Y%m%d")
+                ),
+            ),
+            ("Date".into(), now.to_rfc2822()),
+        ],
+        body,
+    }
+}
+
+/// Email `message` to `recipients` by piping it to `sendmail -t -i`.
+async fn send_mail(sendmail: &str, recipients: &[String], message: &Message) -> Result<()> {
+    let mut text = format!("To: {}\n", recipients.join(", "));
+    for (name, value) in &message.headers {
+        let _ = writeln!(text, "{name}: {value}");
+    }
+    text.push_str("Content-Type: text/plain; charset=utf-8\n\n");
+    text.push_str(&message.body.replace("\r\n", "\n"));
+
+    let mut child = tokio::process::Command::new(sendmail)
+        .args(["-t", "-i"])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("sendmail stdin unavailable"))?;
+    stdin.write_all(text.as_bytes()).await?;
+    drop(stdin);
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow!("{sendmail} exited with {status}"));
+    }
+    Ok(())
+}
@@ -0,0 +1,287 @@
+//! A news server embedded in another program.
+//!
+//! [`Server::builder`] starts a complete NNTP server inside the calling
+//! process, by default listening on an ephemeral port of 127.0.0.1 with
+//! in-memory storage, for integration tests and programs that want a news
+//! server of their own. Connections are served exactly as by the `renews`
+//! binary, and posted articles pass through the same queue and filters.
+//! What an embedded server leaves out are the services of a long-running
+//! process: peers, TLS listeners, scheduled retention and digests, signal
+//! handling and the control socket.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let server = renews::embedded::Server::builder()
+//!     .group("misc.test", false)
+//!     .start()
+//!     .await?;
+//! println!("NNTP server listening on {}", server.local_addr());
+//! server.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::auth::DynAuth;
+use crate::auth::sqlite::SqliteAuth;
+use crate::config::Config;
+use crate::limits::UsageTracker;
+use crate::queue::{ArticleQueue, WorkerPool};
+use crate::storage::DynStorage;
+use crate::storage::sqlite::SqliteStorage;
+use crate::{ConnectionInfo, handle_client_with_info};
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::{RwLock, watch};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::debug;
+
+/// Longest time [`Server::shutdown`] waits for queued articles to be stored
+const QUEUE_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Article workers of an embedded server
+const WORKERS: usize = 2;
+
+/// Builds and starts an embedded [`Server`].
+pub struct ServerBuilder {
+    config: Option<Config>,
+    storage: Option<DynStorage>,
+    auth: Option<DynAuth>,
+    bind: SocketAddr,
+    groups: Vec<(String, bool)>,
+}
+
+impl ServerBuilder {
+    /// Use `config` rather than the defaults. Its listener settings, such
+    /// as `addr` and `tls_addr`, are not used.
+    #[must_use]
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Keep articles in `storage` rather than in an in-memory database.
+    #[must_use]
+    pub fn storage(mut self, storage: DynStorage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Keep users in `auth` rather than in an in-memory database.
+    #[must_use]
+    pub fn auth(mut self, auth: DynAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Listen on `addr` rather than on an ephemeral port of 127.0.0.1.
+    #[must_use]
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind = addr;
+        self
+    }
+
+    /// Create the newsgroup `name` when the server starts.
+    #[must_use]
+    pub fn group(mut self, name: impl Into<String>, moderated: bool) -> Self {
+        self.groups.push((name.into(), moderated));
+        self
+    }
+
+    /// Open the databases, create the groups and start listening.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database cannot be opened, a group cannot be
+    /// created or the address cannot be bound.
+    pub async fn start(self) -> Result<Server> {
+        let config = match self.config {
+            Some(config) => config,
+            None => toml::from_str("addr = \"127.0.0.1:0\"")?,
+        };
+        let storage: DynStorage = match self.storage {
+            Some(storage) => storage,
+            None => Arc::new(SqliteStorage::new("sqlite::memory:").await?),
+        };
+        let auth: DynAuth = match self.auth {
+            Some(auth) => auth,
+            None => Arc::new(SqliteAuth::new("sqlite::memory:").await?),
+        };
+        for (group, moderated) in &self.groups {
+            storage.add_group(group, *moderated).await?;
+        }
+
+        let listener = TcpListener::bind(self.bind).await?;
+        let addr = listener.local_addr()?;
+        let usage_tracker = Arc::new(UsageTracker::new(auth.clone(), config.user_limits.clone()));
+        let queue = ArticleQueue::new(config.article_queue_capacity);
+        let config = Arc::new(RwLock::new(config));
+        let workers = WorkerPool::new(
+            queue.clone(),
+            storage.clone(),
+            auth.clone(),
+            config.clone(),
+            WORKERS,
+        )
+        .start()
+        .await;
+
+        let (shutdown, stopping) = watch::channel(false);
+        let accept = tokio::spawn(accept_loop(
+            listener,
+            Site {
+                storage: storage.clone(),
+                auth: auth.clone(),
+                config: config.clone(),
+                queue: queue.clone(),
+                usage_tracker,
+            },
+            stopping,
+        ));
+        debug!(%addr, "embedded server started");
+
+        Ok(Server {
+            addr,
+            storage,
+            auth,
+            config,
+            queue,
+            shutdown,
+            accept: Some(accept),
+            workers,
+        })
+    }
+}
+
+/// What each connection of an embedded server is served with
+#[derive(Clone)]
+struct Site {
+    storage: DynStorage,
+    auth: DynAuth,
+    config: Arc<RwLock<Config>>,
+    queue: ArticleQueue,
+    usage_tracker: Arc<UsageTracker>,
+}
+
+/// Serve connections on `listener` until `stopping` turns true, then close
+/// the open ones.
+async fn accept_loop(listener: TcpListener, site: Site, mut stopping: watch::Receiver<bool>) {
+    let mut connections = JoinSet::new();
+    let signal = stopping.clone();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((socket, peer)) = accepted else {
+                    continue;
+                };
+                let site = site.clone();
+                let mut stopping = signal.clone();
+                connections.spawn(async move {
+                    let info = ConnectionInfo {
+                        peer_ip: Some(peer.ip()),
+                        ..ConnectionInfo::default()
+                    };
+                    let client = handle_client_with_info(
+                        socket,
+                        site.storage,
+                        site.auth,
+                        site.config,
+                        info,
+                        site.queue,
+                        site.usage_tracker,
+                    );
+                    tokio::select! {
+                        result = client => {
+                            if let Err(e) = result {
+                                debug!(error = %e, "embedded client connection failed");
+                            }
+                        }
+                        _ = stopping.wait_for(|stop| *stop) => {}
+                    }
+                });
+            }
+            // Reap finished connections as they go
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = stopping.wait_for(|stop| *stop) => break,
+        }
+    }
+    while connections.join_next().await.is_some() {}
+}
+
+/// A running embedded server.
+///
+/// Dropping it stops the server without waiting; [`Server::shutdown`]
+/// also waits for the articles already accepted to be stored.
+pub struct Server {
+    addr: SocketAddr,
+    storage: DynStorage,
+    auth: DynAuth,
+    config: Arc<RwLock<Config>>,
+    queue: ArticleQueue,
+    shutdown: watch::Sender<bool>,
+    accept: Option<JoinHandle<()>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Start building an embedded server.
+    #[must_use]
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            config: None,
+            storage: None,
+            auth: None,
+            bind: SocketAddr::from(([127, 0, 0, 1], 0)),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Address the server listens on
+    #[must_use]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Storage of the server's groups and articles
+    #[must_use]
+    pub fn storage(&self) -> &DynStorage {
+        &self.storage
+    }
+
+    /// Users of the server
+    #[must_use]
+    pub fn auth(&self) -> &DynAuth {
+        &self.auth
+    }
+
+    /// Configuration of the server; changes apply to the next command
+    #[must_use]
+    pub fn config(&self) -> &Arc<RwLock<Config>> {
+        &self.config
+    }
+
+    /// Stop listening, close the open connections and wait for the
+    /// articles already accepted to be stored.
+    pub async fn shutdown(mut self) {
+        let _ = self.shutdown.send(true);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.await;
+        }
+        let start = Instant::now();
+        while !self.queue.is_idle() && start.elapsed() < QUEUE_DRAIN_TIMEOUT {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        debug!(addr = %self.addr, "embedded server stopped");
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.shutdown.send(true);
+        for worker in &self.workers {
+            worker.abort();
+        }
+    }
+}
@@ -102,6 +102,36 @@ pub enum ConfigError {
     FileNotFound(String),
 }
 
+/// Malformed command arguments, reported to the client with a 501 naming
+/// the offending argument.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ArgumentError {
+    #[error("too many arguments: {0}")]
+    TooMany(String),
+
+    /// `arg` is not a valid `kind`, refused with the 501 line `status`
+    #[error("invalid {kind}: {arg}")]
+    Invalid {
+        kind: &'static str,
+        status: &'static str,
+        arg: String,
+    },
+}
+
+impl ArgumentError {
+    /// Format as a 501 response line.
+    pub fn to_response(&self) -> String {
+        match self {
+            ArgumentError::TooMany(arg) => {
+                crate::responses::with_argument(crate::responses::RESP_501_TOO_MANY_ARGS, arg)
+            }
+            ArgumentError::Invalid { status, arg, .. } => {
+                crate::responses::with_argument(status, arg)
+            }
+        }
+    }
+}
+
 impl NntpError {
     /// Get the NNTP response code for this error
     pub fn response_code(&self) -> u16 {
@@ -117,7 +147,7 @@ impl NntpError {
 
             NntpError::Limit(LimitError::PostingDisabled) => 440,
             NntpError::Limit(LimitError::BandwidthExceeded) => 403,
-            NntpError::Limit(LimitError::ConnectionLimitExceeded) => 481,
+            NntpError::Limit(LimitError::ConnectionLimitExceeded) => 502,
 
             NntpError::Config(_) => 403,
             NntpError::Io(_) => 403,
@@ -152,3 +182,126 @@ impl NntpError {
         format!("{} {}\r\n", self.response_code(), self.client_message())
     }
 }
+
+/// Why a command handler failed, and what the client is told.
+///
+/// Handlers return `anyhow` errors; [`dispatch_command`] classifies them
+/// with [`HandlerError::from`] and answers the command with the matching
+/// status line instead of leaving the client waiting for one.
+///
+/// [`dispatch_command`]: crate::handlers::dispatch_command
+#[derive(Error, Debug)]
+pub enum HandlerError {
+    /// The server failed, typically its storage: `403 internal fault`
+    #[error("Internal fault: {0:#}")]
+    Internal(anyhow::Error),
+
+    /// The command needs a feature this server or session lacks:
+    /// `503 feature not supported`
+    #[error("Feature not supported: {0}")]
+    Unsupported(String),
+
+    /// The command needs an authenticated session: `480`
+    #[error("Authentication required")]
+    AuthRequired,
+
+    /// The command needs an encrypted connection: `483`
+    #[error("Encryption required")]
+    EncryptionRequired,
+
+    /// An error with a status line of its own
+    #[error(transparent)]
+    Nntp(NntpError),
+
+    /// The connection to the client failed; no response can be given and
+    /// the session ends.
+    #[error("Connection failed: {0}")]
+    Connection(#[source] std::io::Error),
+
+    /// The client ended the session with QUIT, which has been answered.
+    #[error("Connection closed by QUIT command")]
+    Quit,
+}
+
+impl HandlerError {
+    /// Format as an NNTP response line, or `None` when the client cannot
+    /// be answered.
+    pub fn to_response(&self) -> Option<String> {
+        use crate::responses::{
+            RESP_403_INTERNAL_FAULT, RESP_480_AUTH_REQUIRED, RESP_483_SECURE_REQ,
+            RESP_503_NOT_SUPPORTED,
+        };
+        match self {
+            HandlerError::Internal(_) => Some(RESP_403_INTERNAL_FAULT.to_string()),
+            HandlerError::Unsupported(_) => Some(RESP_503_NOT_SUPPORTED.to_string()),
+            HandlerError::AuthRequired => Some(RESP_480_AUTH_REQUIRED.to_string()),
+            HandlerError::EncryptionRequired => Some(RESP_483_SECURE_REQ.to_string()),
+            HandlerError::Nntp(err) => Some(err.to_response()),
+            HandlerError::Connection(_) | HandlerError::Quit => None,
+        }
+    }
+
+    /// Whether the session must end after this error.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, HandlerError::Connection(_) | HandlerError::Quit)
+    }
+}
+
+impl From<anyhow::Error> for HandlerError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<HandlerError>() {
+            Ok(handler) => return handler,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<NntpError>() {
+            Ok(NntpError::Auth(AuthError::Required)) => return HandlerError::AuthRequired,
+            Ok(NntpError::Io(io)) => return HandlerError::Connection(io),
+            Ok(nntp) => return HandlerError::Nntp(nntp),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<AuthError>() {
+            Ok(AuthError::Required) => return HandlerError::AuthRequired,
+            Ok(auth) => return HandlerError::Nntp(NntpError::Auth(auth)),
+            Err(err) => err,
+        };
+        match err.downcast::<std::io::Error>() {
+            Ok(io) => HandlerError::Connection(io),
+            Err(err) => HandlerError::Internal(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handler_errors_map_to_responses() {
+        let storage = anyhow::anyhow!("database is locked");
+        assert_eq!(
+            HandlerError::from(storage).to_response().as_deref(),
+            Some("403 internal fault\r\n")
+        );
+        let auth = anyhow::Error::from(NntpError::Auth(AuthError::Required));
+        assert!(matches!(
+            HandlerError::from(auth),
+            HandlerError::AuthRequired
+        ));
+        let unsupported = anyhow::Error::from(HandlerError::Unsupported("XZVER".into()));
+        assert_eq!(
+            HandlerError::from(unsupported).to_response().as_deref(),
+            Some("503 feature not supported\r\n")
+        );
+        let group = anyhow::Error::from(NntpError::Storage(StorageError::GroupNotFound(
+            "misc".into(),
+        )));
+        assert_eq!(
+            HandlerError::from(group).to_response().as_deref(),
+            Some("411 No such group\r\n")
+        );
+        let lost = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        let lost = HandlerError::from(lost);
+        assert!(lost.is_fatal());
+        assert!(lost.to_response().is_none());
+    }
+}
@@ -0,0 +1,241 @@
+//! Export of stored articles to mbox files and maildirs.
+//!
+//! `renews admin export` walks the groups matching a wildmat and writes
+//! each article to an [`ExportSink`], one at a time, so groups of any size
+//! can be exported for backups, archiving in other systems or answering
+//! data export requests. Articles cross-posted to several of the groups are
+//! written once.
+
+use crate::Message;
+use crate::handlers::utils::get_header_value;
+use crate::storage::Storage;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::StreamExt;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Destination of exported articles.
+pub trait ExportSink {
+    /// Write one article.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the article cannot be written.
+    fn write_article(&mut self, article: &Message) -> Result<()>;
+
+    /// Finish the export, flushing anything still buffered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if buffered output cannot be written.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Articles written to a single mbox, in the mboxrd variant: body lines
+/// starting with any number of `>` followed by `From ` gain another `>`,
+/// so the export can be split into the original articles again.
+pub struct Mbox<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Mbox<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    /// The underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> ExportSink for Mbox<W> {
+    fn write_article(&mut self, article: &Message) -> Result<()> {
+        let sender = get_header_value(article, "From")
+            .and_then(|from| envelope_sender(&from))
+            .unwrap_or_else(|| "MAILER-DAEMON".to_string());
+        let date = article_date(article).unwrap_or_else(Utc::now);
+        writeln!(
+            self.out,
+            "From {sender} {}",
+            date.format("%a %b %e %H:%M:%S %Y")
+        )?;
+        for line in message_lines(article) {
+            if line.trim_start_matches('>').starts_with("From ") {
+                self.out.write_all(b">")?;
+            }
+            self.out.write_all(line.as_bytes())?;
+            self.out.write_all(b"\n")?;
+        }
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Articles written as files in the `new` directory of a maildir, which is
+/// created if needed. Each file is written under `tmp` first and moved into
+/// place once complete, so readers never see a partial article.
+pub struct Maildir {
+    root: PathBuf,
+    host: String,
+    stamp: i64,
+    count: u64,
+}
+
+impl Maildir {
+    /// Open the maildir at `root`, creating it if needed. `host` names the
+    /// delivering host in the file names.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the maildir directories cannot be created.
+    pub fn create(root: &Path, host: &str) -> Result<Self> {
+        for dir in ["tmp", "new", "cur"] {
+            let path = root.join(dir);
+            std::fs::create_dir_all(&path)
+                .map_err(|e| anyhow!("Failed to create '{}': {e}", path.display()))?;
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            host: host.replace(['/', ':'], "_"),
+            stamp: Utc::now().timestamp(),
+            count: 0,
+        })
+    }
+}
+
+impl ExportSink for Maildir {
+    fn write_article(&mut self, article: &Message) -> Result<()> {
+        self.count += 1;
+        let name = format!(
+            "{}.P{}Q{}.{}",
+            self.stamp,
+            std::process::id(),
+            self.count,
+            self.host
+        );
+        let tmp = self.root.join("tmp").join(&name);
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+        for line in message_lines(article) {
+            file.write_all(line.as_bytes())?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()?;
+        drop(file);
+        std::fs::rename(&tmp, self.root.join("new").join(&name))?;
+        Ok(())
+    }
+}
+
+/// Outcome of an export.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub groups: u64,
+    pub articles: u64,
+}
+
+/// Write the articles of every group matching `wildmat` to `sink`, only
+/// those that arrived after `since` when given.
+///
+/// # Errors
+///
+/// Returns an error if the storage cannot be read or an article cannot be
+/// written.
+pub async fn export_articles(
+    storage: &dyn Storage,
+    wildmat: &str,
+    since: Option<DateTime<Utc>>,
+    sink: &mut dyn ExportSink,
+) -> Result<ExportSummary> {
+    let mut groups = Vec::new();
+    let mut stream = storage.list_groups();
+    while let Some(group) = stream.next().await {
+        let group = group?;
+        if crate::wildmat::wildmat(wildmat, &group) {
+            groups.push(group);
+        }
+    }
+    drop(stream);
+
+    let mut summary = ExportSummary::default();
+    let mut exported = HashSet::new();
+    for group in groups {
+        summary.groups += 1;
+        let mut ids = match since {
+            Some(since) => storage.list_article_ids_since(&group, since),
+            None => storage.list_article_ids(&group),
+        };
+        while let Some(id) = ids.next().await {
+            let id = id?;
+            if exported.contains(&id) {
+                continue;
+            }
+            // The article may have been removed since it was listed
+            let Some(article) = storage.get_article_by_id(&id).await? else {
+                continue;
+            };
+            sink.write_article(&article)?;
+            exported.insert(id);
+            summary.articles += 1;
+        }
+    }
+    sink.finish()?;
+    Ok(summary)
+}
+
+/// Parse the `--since` argument: an RFC 3339 time or a `YYYY-MM-DD` date,
+/// taken as midnight UTC.
+///
+/// # Errors
+///
+/// Returns an error if `value` is neither.
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|time| time.and_utc())
+        .ok_or_else(|| anyhow!("Invalid date '{value}': expected YYYY-MM-DD or RFC 3339"))
+}
+
+/// The article's header and body lines, without line endings.
+pub(crate) fn message_lines(article: &Message) -> impl Iterator<Item = String> + '_ {
+    article
+        .headers
+        .iter()
+        .flat_map(|(name, val)| {
+            crate::rewrite::fold_header(name, val)
+                .split("\r\n")
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .chain(std::iter::once(String::new()))
+        .chain(article.body.lines().map(str::to_string))
+}
+
+/// The bare address of a `From` header, for the mbox separator line.
+fn envelope_sender(from: &str) -> Option<String> {
+    let addr = match (from.find('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from.split_whitespace().find(|word| word.contains('@'))?,
+    };
+    (!addr.is_empty() && !addr.contains(char::is_whitespace)).then(|| addr.to_string())
+}
+
+fn article_date(article: &Message) -> Option<DateTime<Utc>> {
+    let date = get_header_value(article, "Date")?;
+    DateTime::parse_from_rfc2822(date.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
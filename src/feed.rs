@@ -0,0 +1,344 @@
+//! Streaming outgoing feeds.
+//!
+//! Peers configured with `stream = true` are fed continuously rather than
+//! on the peer sync schedule, in the manner of INN's `innfeed`. Every
+//! article accepted through the article queue is offered to the
+//! [`Feeder`], which adds it to the backlog of each streaming peer whose
+//! filter accepts it. The backlog is kept in the peer database, so
+//! articles waiting for a slow or unreachable peer survive a restart.
+//!
+//! Each streaming peer has a task that drains its backlog over a connection
+//! in streaming mode (RFC 4644). Up to `stream_window` `CHECK` and
+//! `TAKETHIS` commands are kept in flight, so the feed is not held up by
+//! the round trip of every article. Articles the peer defers with `431`,
+//! whether offered or sent, are retried later; articles it has or refuses
+//! leave the backlog. Connections that fail are retried with exponential
+//! backoff, and idle connections are closed.
+//!
+//! The scheduled push still runs for streaming peers and picks up articles
+//! that did not pass through the queue, such as those pulled from other
+//! peers; the peer history keeps it from sending anything twice.
+
+use crate::Message;
+use crate::config::{Config, PeerFilter, PeerRule};
+use crate::handlers::utils::extract_message_id;
+use crate::peers::{
+    PeerConnection, PeerConnectionInfo, PeerDb, PeerTraffic, Transfer, article_size,
+    create_peer_article, log_traffic, peer_connection_info, should_skip_article,
+};
+use crate::storage::DynStorage;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, debug, info_span, warn};
+
+/// Backlog entries taken per round.
+const BATCH_SIZE: usize = 500;
+
+/// How long an idle feed waits for new articles before closing its
+/// connection and looking for deferred articles that have become due.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long an article deferred by the peer with `431` waits.
+const DEFER_SECS: i64 = 60;
+
+/// First and largest delay between connection attempts.
+const MIN_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Settings of one streaming peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FeedConfig {
+    sitename: String,
+    filter: PeerFilter,
+    connection: PeerConnectionInfo,
+    window: usize,
+    /// Local site name prepended to the Path of fed articles
+    site_name: String,
+}
+
+impl FeedConfig {
+    fn new(rule: &PeerRule, site_name: &str) -> Self {
+        Self {
+            sitename: rule.sitename.clone(),
+            filter: rule.filter.clone(),
+            connection: peer_connection_info(&rule.sitename, &rule.transport),
+            window: rule.stream_window.max(1),
+            site_name: site_name.to_string(),
+        }
+    }
+}
+
+/// A running streaming feed.
+struct Feed {
+    config: FeedConfig,
+    wake: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+/// Outcome of one round of a streaming feed.
+#[derive(Debug, Default)]
+struct FeedStats {
+    traffic: PeerTraffic,
+    /// Articles gone from storage before they could be sent
+    dropped: u64,
+}
+
+/// An outstanding streaming command.
+enum InFlight {
+    Check(String),
+    /// An article sent with its size in bytes
+    TakeThis(String, u64),
+}
+
+/// Streaming feeds to every peer configured with `stream = true`.
+pub struct Feeder {
+    db: PeerDb,
+    storage: DynStorage,
+    feeds: Mutex<HashMap<String, Feed>>,
+}
+
+impl Feeder {
+    /// Create a feeder with no running feeds; [`Feeder::update`] starts them.
+    #[must_use]
+    pub fn new(db: PeerDb, storage: DynStorage) -> Self {
+        Self {
+            db,
+            storage,
+            feeds: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start, restart or stop feeds to match the streaming peers in `cfg`.
+    ///
+    /// Backlogs are kept when a feed is restarted, so a reload does not lose
+    /// queued articles.
+    pub fn update(&self, cfg: &Config) {
+        let wanted: HashMap<String, FeedConfig> = cfg
+            .peers
+            .iter()
+            .filter(|rule| rule.stream && rule.mode.pushes())
+            .map(|rule| (rule.sitename.clone(), FeedConfig::new(rule, &cfg.site_name)))
+            .collect();
+
+        let mut feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        feeds.retain(|name, feed| {
+            let keep = wanted.get(name) == Some(&feed.config);
+            if !keep {
+                feed.task.abort();
+            }
+            keep
+        });
+        for (name, config) in wanted {
+            if feeds.contains_key(&name) {
+                continue;
+            }
+            let wake = Arc::new(Notify::new());
+            let span = info_span!("peer.feed", peer_name = name.as_str());
+            let task = tokio::spawn(
+                run_feed(
+                    config.clone(),
+                    self.db.clone(),
+                    self.storage.clone(),
+                    wake.clone(),
+                )
+                .instrument(span),
+            );
+            feeds.insert(name, Feed { config, wake, task });
+        }
+    }
+
+    /// Queue `article` for every streaming peer that takes it.
+    ///
+    /// Articles whose Path already names the peer, or that the peer is known
+    /// to have, are not queued.
+    pub async fn offer(&self, article: &Message) {
+        let targets: Vec<(String, Arc<Notify>)> = {
+            let feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+            if feeds.is_empty() {
+                return;
+            }
+            feeds
+                .values()
+                .filter(|feed| {
+                    feed.config.filter.accepts(article)
+                        && !should_skip_article(article, &feed.config.sitename)
+                })
+                .map(|feed| (feed.config.sitename.clone(), feed.wake.clone()))
+                .collect()
+        };
+        let Some(message_id) = extract_message_id(article) else {
+            return;
+        };
+        for (peer, wake) in targets {
+            let queued = async {
+                if !self.db.peer_has(&peer, &message_id).await? {
+                    self.db.push_backlog(&peer, &message_id).await?;
+                }
+                Ok::<_, anyhow::Error>(())
+            }
+            .await;
+            match queued {
+                Ok(()) => wake.notify_one(),
+                Err(e) => {
+                    warn!(peer_name = peer.as_str(), error = %e, "Failed to queue article for feed")
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Feeder {
+    fn drop(&mut self) {
+        let feeds = self.feeds.get_mut().unwrap_or_else(|e| e.into_inner());
+        for feed in feeds.values() {
+            feed.task.abort();
+        }
+    }
+}
+
+/// Drain the backlog of one peer for as long as the feed runs.
+async fn run_feed(config: FeedConfig, db: PeerDb, storage: DynStorage, wake: Arc<Notify>) {
+    let mut connection: Option<PeerConnection> = None;
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let batch = match db.due_backlog(&config.sitename, BATCH_SIZE).await {
+            Ok(batch) => batch,
+            Err(e) => {
+                warn!(error = %e, "Failed to read feed backlog");
+                tokio::time::sleep(backoff).await;
+                continue;
+            }
+        };
+        if batch.is_empty() {
+            if tokio::time::timeout(IDLE_TIMEOUT, wake.notified())
+                .await
+                .is_err()
+                && let Some(idle) = connection.take()
+            {
+                let _ = idle.close().await;
+            }
+            continue;
+        }
+
+        if connection.is_none() {
+            match connect(&config).await {
+                Ok(conn) => connection = Some(conn),
+                Err(e) => {
+                    warn!(error = %e, retry_secs = backoff.as_secs(), "Feed connection failed");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            }
+        }
+        let Some(conn) = connection.as_mut() else {
+            continue;
+        };
+
+        let round_start = std::time::Instant::now();
+        match stream_batch(conn, &config, &db, &storage, batch).await {
+            Ok(stats) => {
+                backoff = MIN_BACKOFF;
+                let traffic = &stats.traffic;
+                debug!(
+                    articles_sent = traffic.accepted,
+                    articles_refused = traffic.refused + traffic.rejected,
+                    articles_deferred = traffic.deferred,
+                    articles_dropped = stats.dropped,
+                    "Feed round completed"
+                );
+                log_traffic(&config.sitename, round_start.elapsed().as_secs(), traffic);
+                if let Err(e) = db.record_traffic(&config.sitename, traffic).await {
+                    warn!(error = %e, "Failed to record peer statistics");
+                }
+            }
+            Err(e) => {
+                warn!(error = %e, retry_secs = backoff.as_secs(), "Feed interrupted");
+                if let Some(broken) = connection.take() {
+                    let _ = broken.close().await;
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Connect to the peer and switch to streaming mode.
+async fn connect(config: &FeedConfig) -> Result<PeerConnection> {
+    let mut connection = PeerConnection::connect(&config.connection).await?;
+    connection.mode_stream().await?;
+    Ok(connection)
+}
+
+/// Offer `ids` to the peer, keeping up to the window of commands in flight,
+/// and settle each backlog entry according to the peer's answer.
+async fn stream_batch(
+    conn: &mut PeerConnection,
+    config: &FeedConfig,
+    db: &PeerDb,
+    storage: &DynStorage,
+    ids: Vec<String>,
+) -> Result<FeedStats> {
+    let peer = config.sitename.as_str();
+    let mut stats = FeedStats::default();
+    let mut ids = ids.into_iter();
+    let mut in_flight = VecDeque::with_capacity(config.window);
+    loop {
+        while in_flight.len() < config.window
+            && let Some(id) = ids.next()
+        {
+            conn.send_command(&format!("CHECK {id}\r\n")).await?;
+            in_flight.push_back(InFlight::Check(id));
+            stats.traffic.offered += 1;
+        }
+        let Some(command) = in_flight.pop_front() else {
+            return Ok(stats);
+        };
+
+        let response = conn.read_response().await?.trim_end().to_string();
+        if response.is_empty() {
+            return Err(anyhow!("{peer} closed the connection"));
+        }
+        match (command, response.get(..3).unwrap_or_default()) {
+            (InFlight::Check(id), "238") => match storage.get_article_by_id(&id).await? {
+                Some(article) => {
+                    let article = create_peer_article(&article, &config.site_name)?;
+                    conn.send_command(&format!("TAKETHIS {id}\r\n")).await?;
+                    conn.send_article_content(&article).await?;
+                    in_flight.push_back(InFlight::TakeThis(id, article_size(&article)));
+                }
+                None => {
+                    // Cancelled or expired since it was queued
+                    db.remove_backlog(peer, &id).await?;
+                    stats.dropped += 1;
+                }
+            },
+            (InFlight::Check(id), "438") => {
+                db.record_peer_has(peer, &id).await?;
+                db.remove_backlog(peer, &id).await?;
+                stats.traffic.count(Transfer::Refused, 0);
+            }
+            (InFlight::Check(id) | InFlight::TakeThis(id, _), "431") => {
+                let until = Utc::now() + chrono::Duration::seconds(DEFER_SECS);
+                db.defer_backlog(peer, &id, until).await?;
+                stats.traffic.count(Transfer::Deferred, 0);
+            }
+            (InFlight::TakeThis(id, size), "239") => {
+                db.record_peer_has(peer, &id).await?;
+                db.remove_backlog(peer, &id).await?;
+                stats.traffic.count(Transfer::Accepted, size);
+            }
+            (InFlight::TakeThis(id, _), "439") => {
+                db.remove_backlog(peer, &id).await?;
+                stats.traffic.count(Transfer::Rejected, 0);
+            }
+            _ => return Err(anyhow!("unexpected response from {peer}: {response}")),
+        }
+    }
+}
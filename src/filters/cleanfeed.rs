@@ -0,0 +1,333 @@
+//! Cleanfeed-style flood filter
+//!
+//! Rejects the usual signs of spam floods, after the rules of the classic
+//! Cleanfeed filter for INN:
+//!
+//! - excessive multi-posting (EMP): the same body posted more than
+//!   `max_duplicates` times within `window_secs`, whatever the headers say;
+//! - excessive cross-posting: more than `max_crosspost` groups in
+//!   `Newsgroups`;
+//! - Supersedes abuse: superseding an article written by someone else, or
+//!   more than `max_supersedes` superseding articles from one sender within
+//!   `window_secs`.
+//!
+//! Sightings are kept in a [`FloodState`] that lives as long as the filter
+//! chain, so it is shared by every connection and queue worker. When
+//! `state_file` is set the counters are saved there periodically and
+//! reloaded on start, so a restart does not reset them.
+
+use super::{ArticleFilter, FilterContext, PolicyRejection};
+use crate::Message;
+use crate::handlers::utils::{extract_newsgroups, get_header_value};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// How often the state is written to `state_file`, in seconds.
+const SAVE_INTERVAL_SECS: i64 = 60;
+
+/// How often expired sightings of other keys are dropped, in seconds.
+const SWEEP_INTERVAL_SECS: i64 = 60;
+
+/// Configuration of a [`CleanfeedFilter`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct CleanfeedConfig {
+    /// Copies of one body accepted within the window
+    #[serde(default = "default_max_duplicates")]
+    pub max_duplicates: usize,
+    /// Length of the rolling window in seconds
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// Maximum number of groups an article may be posted to
+    #[serde(default = "default_max_crosspost")]
+    pub max_crosspost: usize,
+    /// Superseding articles accepted from one sender within the window
+    #[serde(default = "default_max_supersedes")]
+    pub max_supersedes: usize,
+    /// File the counters are saved to and restored from
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+}
+
+fn default_max_duplicates() -> usize {
+    3
+}
+
+fn default_window_secs() -> u64 {
+    3600
+}
+
+fn default_max_crosspost() -> usize {
+    10
+}
+
+fn default_max_supersedes() -> usize {
+    5
+}
+
+/// Timestamps of recent sightings, keyed by body hash or sender.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Sightings {
+    bodies: HashMap<String, VecDeque<i64>>,
+    supersedes: HashMap<String, VecDeque<i64>>,
+    /// When expired sightings were last dropped
+    #[serde(skip)]
+    last_sweep: i64,
+}
+
+/// Rolling counters of a [`CleanfeedFilter`].
+#[derive(Debug)]
+pub struct FloodState {
+    window_secs: i64,
+    sightings: Mutex<Sightings>,
+    last_saved: Mutex<i64>,
+    state_file: Option<PathBuf>,
+}
+
+impl FloodState {
+    /// Create the state for a window of `window_secs`, restoring the
+    /// counters saved in `state_file` if there are any.
+    pub fn new(window_secs: u64, state_file: Option<PathBuf>) -> Self {
+        let sightings = state_file
+            .as_ref()
+            .and_then(|path| match std::fs::read(path) {
+                Ok(data) => serde_json::from_slice(&data)
+                    .inspect_err(|e| {
+                        tracing::warn!(path = %path.display(), error = %e, "Ignoring unreadable flood state");
+                    })
+                    .ok(),
+                Err(_) => None,
+            })
+            .unwrap_or_default();
+        Self {
+            window_secs: i64::try_from(window_secs).unwrap_or(i64::MAX),
+            sightings: Mutex::new(sightings),
+            last_saved: Mutex::new(chrono::Utc::now().timestamp()),
+            state_file,
+        }
+    }
+
+    /// Record a copy of `body` seen at `now` and return how many copies were
+    /// seen within the window, including this one.
+    pub fn record_body(&self, body: &str, now: i64) -> usize {
+        let mut sightings = self.sightings.lock().unwrap_or_else(|e| e.into_inner());
+        self.sweep(&mut sightings, now);
+        record(
+            &mut sightings.bodies,
+            body_hash(body),
+            now,
+            self.window_secs,
+        )
+    }
+
+    /// Record a superseding article from `sender` seen at `now` and return
+    /// how many were seen within the window, including this one.
+    pub fn record_supersedes(&self, sender: &str, now: i64) -> usize {
+        let mut sightings = self.sightings.lock().unwrap_or_else(|e| e.into_inner());
+        self.sweep(&mut sightings, now);
+        let sender = sender.trim().to_ascii_lowercase();
+        record(&mut sightings.supersedes, sender, now, self.window_secs)
+    }
+
+    /// Drop keys whose sightings have all left the window, at most once per
+    /// sweep interval.
+    fn sweep(&self, sightings: &mut Sightings, now: i64) {
+        if now - sightings.last_sweep < SWEEP_INTERVAL_SECS {
+            return;
+        }
+        sightings.last_sweep = now;
+        let cutoff = now.saturating_sub(self.window_secs);
+        for entries in [&mut sightings.bodies, &mut sightings.supersedes] {
+            entries.retain(|_, times| times.back().is_some_and(|&t| t > cutoff));
+        }
+    }
+
+    /// Write the counters to the state file, if one is configured.
+    pub async fn save(&self) -> Result<()> {
+        let Some(path) = &self.state_file else {
+            return Ok(());
+        };
+        let data = {
+            let sightings = self.sightings.lock().unwrap_or_else(|e| e.into_inner());
+            serde_json::to_vec(&*sightings)?
+        };
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    /// Save the counters if the last save is older than the save interval.
+    async fn save_periodically(&self, now: i64) {
+        if self.state_file.is_none() {
+            return;
+        }
+        {
+            let mut last_saved = self.last_saved.lock().unwrap_or_else(|e| e.into_inner());
+            if now - *last_saved < SAVE_INTERVAL_SECS {
+                return;
+            }
+            *last_saved = now;
+        }
+        if let Err(e) = self.save().await {
+            tracing::warn!(error = %e, "Failed to save flood state");
+        }
+    }
+}
+
+/// Add a sighting of `key` at `now`, drop its sightings older than the
+/// window and return the sightings left.
+fn record(
+    entries: &mut HashMap<String, VecDeque<i64>>,
+    key: String,
+    now: i64,
+    window_secs: i64,
+) -> usize {
+    let cutoff = now.saturating_sub(window_secs);
+    let times = entries.entry(key).or_default();
+    while times.front().is_some_and(|&t| t <= cutoff) {
+        times.pop_front();
+    }
+    times.push_back(now);
+    times.len()
+}
+
+/// Hash of a body with whitespace differences and letter case ignored, so
+/// trivially varied copies count as the same body.
+fn body_hash(body: &str) -> String {
+    let mut hasher = Sha256::new();
+    for word in body.split_whitespace() {
+        hasher.update(word.to_lowercase().as_bytes());
+        hasher.update(b" ");
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Address part of a From header, compared case-insensitively.
+fn sender(article: &Message) -> Option<String> {
+    let from = get_header_value(article, "From")?;
+    let address = match (from.find('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from.split_whitespace().next().unwrap_or_default(),
+    };
+    Some(address.to_ascii_lowercase())
+}
+
+/// Filter that rejects duplicate floods, excessive cross-posts and
+/// Supersedes abuse
+pub struct CleanfeedFilter {
+    config: CleanfeedConfig,
+    state: FloodState,
+}
+
+impl CleanfeedFilter {
+    pub fn new(config: CleanfeedConfig) -> Self {
+        let state = FloodState::new(config.window_secs, config.state_file.clone());
+        Self { config, state }
+    }
+
+    async fn check_supersedes(
+        &self,
+        ctx: &FilterContext<'_>,
+        target: &str,
+        now: i64,
+    ) -> Result<()> {
+        let from = sender(ctx.article).unwrap_or_default();
+        if let Some(original) = ctx.storage.get_article_by_id(target.trim()).await?
+            && sender(&original).unwrap_or_default() != from
+        {
+            return Err(PolicyRejection::error(
+                "Supersedes names an article by another sender",
+            ));
+        }
+        if self.state.record_supersedes(&from, now) > self.config.max_supersedes {
+            return Err(PolicyRejection::error(format!(
+                "too many superseding articles from {from}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ArticleFilter for CleanfeedFilter {
+    async fn validate(&self, ctx: &FilterContext<'_>) -> Result<()> {
+        let groups = extract_newsgroups(ctx.article).len();
+        if groups > self.config.max_crosspost {
+            return Err(PolicyRejection::error(format!(
+                "excessive cross-posting to {groups} groups"
+            )));
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let result = async {
+            if let Some(target) = get_header_value(ctx.article, "Supersedes") {
+                self.check_supersedes(ctx, &target, now).await?;
+            }
+            if !ctx.article.body.trim().is_empty() {
+                let copies = self.state.record_body(&ctx.article.body, now);
+                if copies > self.config.max_duplicates {
+                    return Err(PolicyRejection::error(format!(
+                        "body posted {copies} times"
+                    )));
+                }
+            }
+            Ok(())
+        }
+        .await;
+        self.state.save_periodically(now).await;
+        result
+    }
+
+    fn name(&self) -> &'static str {
+        "CleanfeedFilter"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_bodies_expire_after_the_window() {
+        let state = FloodState::new(60, None);
+        assert_eq!(state.record_body("Buy  now\r\n", 0), 1);
+        assert_eq!(state.record_body("buy now", 30), 2);
+        assert_eq!(state.record_body("something else", 30), 1);
+        // The first copy has left the window
+        assert_eq!(state.record_body("BUY NOW", 60), 2);
+        assert_eq!(state.record_body("buy now", 200), 1);
+    }
+
+    #[tokio::test]
+    async fn state_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flood.json");
+        let state = FloodState::new(3600, Some(path.clone()));
+        let now = chrono::Utc::now().timestamp();
+        state.record_body("flood", now);
+        state.record_supersedes("Spammer@Example.com", now);
+        state.save().await.unwrap();
+
+        let restored = FloodState::new(3600, Some(path));
+        assert_eq!(restored.record_body("flood", now), 2);
+        assert_eq!(restored.record_supersedes("spammer@example.com", now), 2);
+    }
+
+    #[test]
+    fn sender_uses_the_address() {
+        let article = Message {
+            headers: smallvec::smallvec![(
+                "From".to_string(),
+                "Some One <Some.One@Example.com>".to_string()
+            )],
+            body: String::new(),
+        };
+        assert_eq!(sender(&article).as_deref(), Some("some.one@example.com"));
+    }
+}
@@ -0,0 +1,345 @@
+//! External filter protocol
+//!
+//! Hands articles to a filter daemon over TCP or a Unix socket so filters
+//! can be written in any language. Each message is a 4-byte big-endian
+//! length followed by that many bytes of JSON. For every article the server
+//! sends a request
+//!
+//! ```json
+//! {"message_id": "<1@example>", "headers": [["From", "a@example"]],
+//!  "body": "...", "size": 1234, "user": "alice"}
+//! ```
+//!
+//! where `user` is the local poster, or null for articles from peers and
+//! anonymous posters, and the daemon answers with
+//!
+//! ```json
+//! {"action": "accept", "reason": null, "add_headers": [["X-Spam-Score", "0.1"]]}
+//! ```
+//!
+//! `action` is one of `accept`, `reject`, `quarantine` or `tempfail`;
+//! `add_headers` is only applied to accepted articles. Connections are kept
+//! open and reused for later articles.
+//!
+//! ```toml
+//! [[filters]]
+//! name = "ExternalFilter"
+//! address = "tcp://127.0.0.1:9100"
+//! timeout_ms = 2000
+//! on_failure = "tempfail"
+//! pool_size = 4
+//! ```
+
+use super::{ArticleFilter, FilterContext, FilterVerdict, PolicyRejection, TemporaryFailure};
+use crate::handlers::utils::get_header_value;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+
+/// Largest response accepted from a filter daemon
+const MAX_RESPONSE_BYTES: u32 = 1024 * 1024;
+
+/// What an [`ExternalFilter`] does when the daemon cannot be reached or
+/// does not answer in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// Refuse the article for now so the sender tries again later
+    Tempfail,
+    /// Let the article through unfiltered
+    Accept,
+}
+
+/// Configuration of an [`ExternalFilter`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExternalFilterConfig {
+    /// Address of the filter daemon, as `tcp://host:port` or
+    /// `unix:///path/to/socket`
+    pub address: String,
+    /// Time allowed for connecting and for each exchange, in milliseconds
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// What to do when the daemon fails
+    #[serde(default = "default_on_failure")]
+    pub on_failure: FailurePolicy,
+    /// Idle connections kept open for later articles
+    #[serde(default = "default_pool_size")]
+    pub pool_size: usize,
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_on_failure() -> FailurePolicy {
+    FailurePolicy::Tempfail
+}
+
+fn default_pool_size() -> usize {
+    4
+}
+
+/// Article sent to the filter daemon
+#[derive(Debug, Serialize)]
+pub struct FilterRequest<'a> {
+    pub message_id: Option<String>,
+    pub headers: &'a [(String, String)],
+    pub body: &'a str,
+    pub size: u64,
+    pub user: Option<&'a str>,
+}
+
+/// Decision of the filter daemon on an article
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    Accept,
+    Reject,
+    Quarantine,
+    Tempfail,
+}
+
+/// Answer of the filter daemon
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilterResponse {
+    pub action: FilterAction,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub add_headers: Vec<(String, String)>,
+}
+
+trait FilterStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> FilterStream for T {}
+
+/// Where the filter daemon listens
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Tcp(String),
+    Unix(String),
+}
+
+/// Filter that asks an external daemon about each article
+pub struct ExternalFilter {
+    endpoint: Endpoint,
+    timeout: Duration,
+    on_failure: FailurePolicy,
+    pool_size: usize,
+    idle: Mutex<Vec<Box<dyn FilterStream>>>,
+}
+
+impl ExternalFilter {
+    /// Create a filter for the daemon at `config.address`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the address is not a
+    /// `tcp://` or `unix://` address.
+    pub fn new(config: ExternalFilterConfig) -> Result<Self, String> {
+        let endpoint = match config.address.split_once("://") {
+            Some(("tcp", addr)) if !addr.is_empty() => Endpoint::Tcp(addr.to_string()),
+            Some(("unix", path)) if !path.is_empty() => Endpoint::Unix(path.to_string()),
+            _ => {
+                return Err(format!(
+                    "invalid address {}: expected tcp://host:port or unix:///path",
+                    config.address
+                ));
+            }
+        };
+        if config.timeout_ms == 0 {
+            return Err("timeout_ms must be greater than 0".to_string());
+        }
+        Ok(Self {
+            endpoint,
+            timeout: Duration::from_millis(config.timeout_ms),
+            on_failure: config.on_failure,
+            pool_size: config.pool_size,
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    async fn connect(&self) -> std::io::Result<Box<dyn FilterStream>> {
+        Ok(match &self.endpoint {
+            Endpoint::Tcp(addr) => Box::new(TcpStream::connect(addr).await?),
+            Endpoint::Unix(path) => Box::new(UnixStream::connect(path).await?),
+        })
+    }
+
+    fn take_idle(&self) -> Option<Box<dyn FilterStream>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop()
+    }
+
+    fn put_idle(&self, stream: Box<dyn FilterStream>) {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < self.pool_size {
+            idle.push(stream);
+        }
+    }
+
+    /// Send `request` and read the answer, on an idle connection if there
+    /// is one. A pooled connection the daemon has since closed is replaced
+    /// by a new one once.
+    async fn ask(&self, request: &[u8]) -> std::io::Result<FilterResponse> {
+        if let Some(mut stream) = self.take_idle()
+            && let Ok(response) = exchange(&mut stream, request).await
+        {
+            self.put_idle(stream);
+            return Ok(response);
+        }
+        let mut stream = self.connect().await?;
+        let response = exchange(&mut stream, request).await?;
+        self.put_idle(stream);
+        Ok(response)
+    }
+
+    async fn query(&self, ctx: &FilterContext<'_>) -> Result<Option<FilterResponse>> {
+        let request = serde_json::to_vec(&FilterRequest {
+            message_id: get_header_value(ctx.article, "Message-ID"),
+            headers: &ctx.article.headers,
+            body: &ctx.article.body,
+            size: ctx.size,
+            user: ctx.poster.and_then(|p| p.user),
+        })?;
+        let error = match tokio::time::timeout(self.timeout, self.ask(&request)).await {
+            Ok(Ok(response)) => return Ok(Some(response)),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+        tracing::warn!(error = %error, "External filter failed");
+        match self.on_failure {
+            FailurePolicy::Accept => Ok(None),
+            FailurePolicy::Tempfail => {
+                Err(TemporaryFailure(format!("external filter failed: {error}")).into())
+            }
+        }
+    }
+}
+
+/// Write one length-prefixed request to `stream` and read the answer.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin + ?Sized>(
+    stream: &mut S,
+    request: &[u8],
+) -> std::io::Result<FilterResponse> {
+    let len = u32::try_from(request.len())
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "article too large"))?;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(request).await?;
+    stream.flush().await?;
+
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_RESPONSE_BYTES {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("response of {len} bytes is too large"),
+        ));
+    }
+    let mut response = vec![0u8; len as usize];
+    stream.read_exact(&mut response).await?;
+    serde_json::from_slice(&response)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[async_trait::async_trait]
+impl ArticleFilter for ExternalFilter {
+    async fn validate(&self, _ctx: &FilterContext<'_>) -> Result<()> {
+        // The daemon is asked once, in `inspect`, which can both refuse the
+        // article and record headers and quarantine
+        Ok(())
+    }
+
+    async fn inspect(&self, ctx: &FilterContext<'_>, verdict: &mut FilterVerdict) -> Result<()> {
+        let Some(response) = self.query(ctx).await? else {
+            return Ok(());
+        };
+        let reason = response.reason.unwrap_or_default();
+        match response.action {
+            FilterAction::Accept => verdict.add_headers.extend(response.add_headers),
+            FilterAction::Reject => {
+                return Err(PolicyRejection::error(format!(
+                    "rejected by external filter: {reason}"
+                )));
+            }
+            FilterAction::Tempfail => {
+                return Err(TemporaryFailure(format!("external filter deferred: {reason}")).into());
+            }
+            FilterAction::Quarantine => {
+                if verdict.quarantine.is_none() {
+                    verdict.quarantine = Some(format!("external filter: {reason}"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "ExternalFilter"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(address: &str) -> ExternalFilterConfig {
+        serde_json::from_value(serde_json::json!({ "address": address })).unwrap()
+    }
+
+    #[test]
+    fn test_address_schemes() {
+        assert!(ExternalFilter::new(config("tcp://127.0.0.1:9100")).is_ok());
+        assert!(ExternalFilter::new(config("unix:///run/filter.sock")).is_ok());
+        assert!(ExternalFilter::new(config("tls://127.0.0.1:9100")).is_err());
+        assert!(ExternalFilter::new(config("127.0.0.1:9100")).is_err());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let cfg = config("tcp://127.0.0.1:9100");
+        assert_eq!(cfg.timeout_ms, 5000);
+        assert_eq!(cfg.on_failure, FailurePolicy::Tempfail);
+        assert_eq!(cfg.pool_size, 4);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_frames() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let daemon = tokio::spawn(async move {
+            let mut len = [0u8; 4];
+            server.read_exact(&mut len).await.unwrap();
+            let mut request = vec![0u8; u32::from_be_bytes(len) as usize];
+            server.read_exact(&mut request).await.unwrap();
+            let answer = br#"{"action":"reject","reason":"spam"}"#;
+            server
+                .write_all(&(answer.len() as u32).to_be_bytes())
+                .await
+                .unwrap();
+            server.write_all(answer).await.unwrap();
+            request
+        });
+        let response = exchange(&mut client, b"{}").await.unwrap();
+        assert_eq!(response.action, FilterAction::Reject);
+        assert_eq!(response.reason.as_deref(), Some("spam"));
+        assert_eq!(daemon.await.unwrap(), b"{}");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_is_refused() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let mut buf = [0u8; 6];
+            server.read_exact(&mut buf).await.unwrap();
+            server
+                .write_all(&(MAX_RESPONSE_BYTES + 1).to_be_bytes())
+                .await
+                .unwrap();
+        });
+        let err = exchange(&mut client, b"{}").await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -5,7 +5,9 @@
 
 use super::{ArticleFilter, FilterChain};
 use crate::config::FilterConfig;
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::{Arc, Mutex, OnceLock};
 
 /// Errors that can occur when creating filters from configuration
 #[derive(Debug, Clone)]
@@ -49,6 +51,48 @@ pub fn create_filter(config: &FilterConfig) -> Result<Box<dyn ArticleFilter>, Fi
                     })?;
             Ok(Box::new(super::milter::MilterFilter::new(milter_config)))
         }
+        "RegexFilter" => {
+            let regex_config: super::regex::RegexFilterConfig =
+                serde_json::from_value(serde_json::Value::Object(config.parameters.clone()))
+                    .map_err(|e| {
+                        FilterFactoryError::InvalidParameters(format!(
+                            "RegexFilter configuration error: {e}"
+                        ))
+                    })?;
+            let filter = super::regex::RegexFilter::new(regex_config).map_err(|e| {
+                FilterFactoryError::InvalidParameters(format!(
+                    "RegexFilter configuration error: {e}"
+                ))
+            })?;
+            Ok(Box::new(filter))
+        }
+        "CleanfeedFilter" => {
+            let cleanfeed_config: super::cleanfeed::CleanfeedConfig =
+                serde_json::from_value(serde_json::Value::Object(config.parameters.clone()))
+                    .map_err(|e| {
+                        FilterFactoryError::InvalidParameters(format!(
+                            "CleanfeedFilter configuration error: {e}"
+                        ))
+                    })?;
+            Ok(Box::new(super::cleanfeed::CleanfeedFilter::new(
+                cleanfeed_config,
+            )))
+        }
+        "ExternalFilter" => {
+            let external_config: super::external::ExternalFilterConfig =
+                serde_json::from_value(serde_json::Value::Object(config.parameters.clone()))
+                    .map_err(|e| {
+                        FilterFactoryError::InvalidParameters(format!(
+                            "ExternalFilter configuration error: {e}"
+                        ))
+                    })?;
+            let filter = super::external::ExternalFilter::new(external_config).map_err(|e| {
+                FilterFactoryError::InvalidParameters(format!(
+                    "ExternalFilter configuration error: {e}"
+                ))
+            })?;
+            Ok(Box::new(filter))
+        }
         _ => Err(FilterFactoryError::UnknownFilter(config.name.clone())),
     }
 }
@@ -71,6 +115,30 @@ pub fn create_filter_chain(configs: &[FilterConfig]) -> Result<FilterChain, Filt
     Ok(chain)
 }
 
+/// Return the filter chain for `configs`, building it only the first time
+/// this configuration is seen.
+///
+/// Filters that keep state between articles, such as the rolling counters of
+/// [`super::cleanfeed::CleanfeedFilter`], rely on their chain living as long
+/// as the configuration does rather than being rebuilt for every article.
+pub fn cached_filter_chain(
+    configs: &[FilterConfig],
+) -> Result<Arc<FilterChain>, FilterFactoryError> {
+    static CHAINS: OnceLock<Mutex<HashMap<String, Arc<FilterChain>>>> = OnceLock::new();
+    let key = format!("{configs:?}");
+    let chains = CHAINS.get_or_init(Default::default);
+    if let Some(chain) = chains.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return Ok(chain.clone());
+    }
+    let chain = Arc::new(create_filter_chain(configs)?);
+    Ok(chains
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(key)
+        .or_insert(chain)
+        .clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,6 +203,47 @@ mod tests {
         assert_eq!(filter.name(), "MilterFilter");
     }
 
+    #[test]
+    fn test_create_regex_filter() {
+        let mut parameters = serde_json::Map::new();
+        parameters.insert("target".to_string(), json!("header"));
+        parameters.insert("header".to_string(), json!("X-Mailer"));
+        parameters.insert("patterns".to_string(), json!(["(?i)spambot", "^bulk"]));
+        parameters.insert("action".to_string(), json!("add-header"));
+        parameters.insert("add_header".to_string(), json!("X-Spam-Flag: YES"));
+
+        let config = FilterConfig {
+            name: "RegexFilter".to_string(),
+            parameters,
+        };
+
+        let filter = create_filter(&config).unwrap();
+        assert_eq!(filter.name(), "RegexFilter");
+    }
+
+    #[test]
+    fn test_regex_filter_invalid_parameters() {
+        for parameters in [
+            json!({ "target": "subject", "patterns": ["(unclosed"] }),
+            json!({ "target": "header", "patterns": ["x"] }),
+            json!({ "target": "body", "patterns": [] }),
+            json!({ "target": "body", "patterns": ["x"], "action": "add-header" }),
+            json!({ "target": "body", "patterns": ["x"], "action": "discard" }),
+        ] {
+            let config = FilterConfig {
+                name: "RegexFilter".to_string(),
+                parameters: parameters.as_object().unwrap().clone(),
+            };
+            assert!(
+                matches!(
+                    create_filter(&config),
+                    Err(FilterFactoryError::InvalidParameters(_))
+                ),
+                "{parameters}"
+            );
+        }
+    }
+
     #[test]
     fn test_unknown_filter() {
         let config = FilterConfig {
@@ -1,13 +1,16 @@
 //! Group existence validation filter
 //!
-//! Validates that all newsgroups in an article exist in the server.
+//! Validates that all newsgroups in an article exist in the server and are
+//! not archived and, for articles posted by local clients, that the poster
+//! may post to them.
 
-use super::{ArticleFilter, FilterContext};
+use super::{ArchivedGroup, ArticleFilter, FilterContext, PolicyRejection};
 use crate::handlers::utils::extract_newsgroups;
 use anyhow::Result;
 use futures_util::TryStreamExt;
 
-/// Filter that validates newsgroups exist in the server
+/// Filter that validates newsgroups exist in the server, take new articles
+/// and are open to the poster
 pub struct GroupExistenceFilter;
 
 #[async_trait::async_trait]
@@ -19,9 +22,25 @@ impl ArticleFilter for GroupExistenceFilter {
         // Check that all groups exist
         let stream = ctx.storage.list_groups();
         let all_groups = stream.try_collect::<Vec<String>>().await?;
+        let archived = ctx
+            .storage
+            .list_archived_groups()
+            .try_collect::<Vec<String>>()
+            .await?;
         for group in &newsgroups {
             if !all_groups.contains(group) {
-                return Err(anyhow::anyhow!("group does not exist"));
+                return Err(PolicyRejection::error("group does not exist"));
+            }
+            if archived.contains(group) {
+                return Err(ArchivedGroup(group.clone()).into());
+            }
+            if let Some(poster) = ctx.poster
+                && !poster.admin
+                && !ctx.cfg.may_post(group, poster.user)
+            {
+                return Err(PolicyRejection::error(format!(
+                    "posting to {group} is not permitted"
+                )));
             }
         }
 
@@ -31,4 +50,8 @@ impl ArticleFilter for GroupExistenceFilter {
     fn name(&self) -> &'static str {
         "GroupExistenceFilter"
     }
+
+    fn is_fast(&self) -> bool {
+        true
+    }
 }
@@ -2,7 +2,7 @@
 //!
 //! Validates that articles have required headers (From, Subject, Newsgroups).
 
-use super::{ArticleFilter, FilterContext};
+use super::{ArticleFilter, FilterContext, PolicyRejection};
 use crate::handlers::utils::{extract_newsgroups, has_header};
 use anyhow::Result;
 
@@ -18,7 +18,7 @@ impl ArticleFilter for HeaderFilter {
         let newsgroups = extract_newsgroups(ctx.article);
 
         if !has_from || !has_subject || newsgroups.is_empty() {
-            return Err(anyhow::anyhow!("missing required headers"));
+            return Err(PolicyRejection::error("missing required headers"));
         }
 
         Ok(())
@@ -27,4 +27,8 @@ impl ArticleFilter for HeaderFilter {
     fn name(&self) -> &'static str {
         "HeaderFilter"
     }
+
+    fn is_fast(&self) -> bool {
+        true
+    }
 }
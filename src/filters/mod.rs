@@ -3,18 +3,24 @@
 //! This module provides a composable filter system for article validation.
 //! Each filter implements the `ArticleFilter` trait and can be combined
 //! into a validation chain that must all pass for an article to be accepted.
+//! Filters may also ask for an accepted article to be quarantined or to have
+//! headers added; these requests are collected in a [`FilterVerdict`].
 
 use crate::Message;
 use crate::auth::DynAuth;
 use crate::config::Config;
+use crate::handlers::utils::ArticleMetadata;
 use crate::storage::DynStorage;
 use anyhow::Result;
 
+pub mod cleanfeed;
+pub mod external;
 pub mod factory;
 pub mod groups;
 pub mod header;
 pub mod milter;
 pub mod moderation;
+pub mod regex;
 pub mod size;
 
 /// Context passed to article filters containing all validation inputs.
@@ -32,6 +38,89 @@ pub struct FilterContext<'a> {
     pub article: &'a Message,
     /// Size of the article in bytes
     pub size: u64,
+    /// Metadata computed while the article was read from the client, when
+    /// available (size, body line count and content digest)
+    pub metadata: Option<&'a ArticleMetadata>,
+    /// The local client posting the article; `None` for articles received
+    /// from peers, to which posting restrictions do not apply
+    pub poster: Option<Poster<'a>>,
+}
+
+/// A local client posting an article.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Poster<'a> {
+    /// Authenticated user name, or `None` for an anonymous poster
+    pub user: Option<&'a str>,
+    /// Administrators may post to every group
+    pub admin: bool,
+}
+
+/// Error of a filter that could not decide on an article for now.
+///
+/// Peers offering such an article are asked to send it again later rather
+/// than told it was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemporaryFailure(pub String);
+
+impl std::fmt::Display for TemporaryFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TemporaryFailure {}
+
+impl TemporaryFailure {
+    /// Whether `err` is a temporary failure of a filter
+    #[must_use]
+    pub fn is(err: &anyhow::Error) -> bool {
+        err.downcast_ref::<Self>().is_some()
+    }
+}
+
+/// Error of a filter refusing an article by the site's policy.
+///
+/// Its text names the rule the article broke and may be shown to the poster,
+/// unlike other filter errors, which may carry storage or backend details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyRejection(pub String);
+
+impl std::fmt::Display for PolicyRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PolicyRejection {}
+
+impl PolicyRejection {
+    /// The error refusing an article for `reason`
+    pub fn error(reason: impl Into<String>) -> anyhow::Error {
+        Self(reason.into()).into()
+    }
+}
+
+/// Error of a filter refusing an article posted to an archived group.
+///
+/// Archived groups stay readable, so posters and peers are told why the
+/// article was refused rather than given a generic rejection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivedGroup(pub String);
+
+impl std::fmt::Display for ArchivedGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "group {} is archived", self.0)
+    }
+}
+
+impl std::error::Error for ArchivedGroup {}
+
+impl ArchivedGroup {
+    /// Whether `err` refuses an article for an archived group
+    #[must_use]
+    pub fn is(err: &anyhow::Error) -> bool {
+        err.downcast_ref::<Self>().is_some()
+    }
 }
 
 /// Trait for article validation filters
@@ -42,8 +131,51 @@ pub trait ArticleFilter: Send + Sync {
     /// Returns Ok(()) if the article passes validation, Err if it fails.
     async fn validate(&self, ctx: &FilterContext<'_>) -> Result<()>;
 
+    /// Record what should happen to an article that passed validation
+    ///
+    /// Filters that only accept or reject keep the default, which leaves the
+    /// verdict unchanged.
+    async fn inspect(&self, _ctx: &FilterContext<'_>, _verdict: &mut FilterVerdict) -> Result<()> {
+        Ok(())
+    }
+
     /// Get a descriptive name for this filter (for logging/debugging)
     fn name(&self) -> &'static str;
+
+    /// Whether the filter is cheap enough to run before a peer is answered
+    /// when `transit_validation = "deferred"`
+    fn is_fast(&self) -> bool {
+        false
+    }
+}
+
+/// Actions requested by filters for an article they accepted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterVerdict {
+    /// Reason for holding the article in the moderation queue instead of
+    /// storing it
+    pub quarantine: Option<String>,
+    /// Headers to add to the article before it is stored
+    pub add_headers: Vec<(String, String)>,
+}
+
+impl FilterVerdict {
+    /// Carry out the verdict on `article`.
+    ///
+    /// Requested headers are added to the article, and a quarantined article
+    /// is added to the moderation queue. Returns `true` if the article was
+    /// quarantined and must not be stored.
+    pub async fn apply(self, storage: &DynStorage, article: &mut Message) -> Result<bool> {
+        for (name, value) in self.add_headers {
+            article.headers.push((name, value));
+        }
+        let Some(reason) = self.quarantine else {
+            return Ok(false);
+        };
+        let id = storage.add_pending_article(article).await?;
+        tracing::info!(pending_id = id, reason = %reason, "Article quarantined by filter");
+        Ok(true)
+    }
 }
 
 /// A chain of filters that all must pass for validation to succeed
@@ -73,6 +205,7 @@ impl FilterChain {
         cfg: &Config,
         article: &Message,
         size: u64,
+        poster: Option<Poster<'_>>,
     ) -> Result<()> {
         let ctx = FilterContext {
             storage,
@@ -80,13 +213,116 @@ impl FilterChain {
             cfg,
             article,
             size,
+            metadata: None,
+            poster,
         };
-        for filter in &self.filters {
+        self.run(&ctx).await.map(|_| ())
+    }
+
+    /// Run all filters in the chain like [`Self::validate`] on an article
+    /// received from a peer, returning the verdict of the filters on the
+    /// accepted article
+    pub async fn evaluate(
+        &self,
+        storage: &DynStorage,
+        auth: &DynAuth,
+        cfg: &Config,
+        article: &Message,
+        size: u64,
+    ) -> Result<FilterVerdict> {
+        let ctx = FilterContext {
+            storage,
+            auth,
+            cfg,
+            article,
+            size,
+            metadata: None,
+            poster: None,
+        };
+        self.run(&ctx).await
+    }
+
+    /// Run all filters in the chain using metadata gathered while the article
+    /// was read, returning on first failure. `poster` is the local client
+    /// posting the article, or `None` for an article from a peer
+    pub async fn validate_with_metadata(
+        &self,
+        storage: &DynStorage,
+        auth: &DynAuth,
+        cfg: &Config,
+        article: &Message,
+        metadata: &ArticleMetadata,
+        poster: Option<Poster<'_>>,
+    ) -> Result<()> {
+        let ctx = FilterContext {
+            storage,
+            auth,
+            cfg,
+            article,
+            size: metadata.size,
+            metadata: Some(metadata),
+            poster,
+        };
+        self.run(&ctx).await.map(|_| ())
+    }
+
+    /// Run all filters in the chain like [`Self::validate_with_metadata`],
+    /// returning the verdict of the filters on the accepted article
+    pub async fn evaluate_with_metadata(
+        &self,
+        storage: &DynStorage,
+        auth: &DynAuth,
+        cfg: &Config,
+        article: &Message,
+        metadata: &ArticleMetadata,
+        poster: Option<Poster<'_>>,
+    ) -> Result<FilterVerdict> {
+        let ctx = FilterContext {
+            storage,
+            auth,
+            cfg,
+            article,
+            size: metadata.size,
+            metadata: Some(metadata),
+            poster,
+        };
+        self.run(&ctx).await
+    }
+
+    /// Run only the fast filters of the chain on an article received from a
+    /// peer, leaving the others for later
+    pub async fn validate_fast(
+        &self,
+        storage: &DynStorage,
+        auth: &DynAuth,
+        cfg: &Config,
+        article: &Message,
+        metadata: &ArticleMetadata,
+    ) -> Result<()> {
+        let ctx = FilterContext {
+            storage,
+            auth,
+            cfg,
+            article,
+            size: metadata.size,
+            metadata: Some(metadata),
+            poster: None,
+        };
+        for filter in self.filters.iter().filter(|f| f.is_fast()) {
             filter.validate(&ctx).await?;
         }
         Ok(())
     }
 
+    async fn run(&self, ctx: &FilterContext<'_>) -> Result<FilterVerdict> {
+        let mut verdict = FilterVerdict::default();
+        for filter in &self.filters {
+            filter.validate(ctx).await?;
+            filter.inspect(ctx, &mut verdict).await?;
+        }
+        Ok(verdict)
+    }
+
     /// Get a list of filter names in the chain
     pub fn filter_names(&self) -> Vec<&'static str> {
         self.filters.iter().map(|f| f.name()).collect()
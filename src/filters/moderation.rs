@@ -2,7 +2,7 @@
 //!
 //! Validates moderated group approval and PGP signatures.
 
-use super::{ArticleFilter, FilterContext};
+use super::{ArticleFilter, FilterContext, PolicyRejection};
 use crate::handlers::utils::{extract_newsgroups, get_header_values};
 use anyhow::Result;
 use smallvec::SmallVec;
@@ -37,26 +37,28 @@ impl ArticleFilter for ModerationFilter {
                 }
 
                 if group_moderators.is_empty() {
-                    return Err(anyhow::anyhow!("missing approval for moderated group"));
+                    return Err(PolicyRejection::error(
+                        "missing approval for moderated group",
+                    ));
                 }
 
                 if group_signatures.len() < group_moderators.len() {
-                    return Err(anyhow::anyhow!("missing signature for moderator"));
+                    return Err(PolicyRejection::error("missing signature for moderator"));
                 }
 
                 // Verify signatures for this group's moderators
                 for (i, approved) in group_moderators.iter().enumerate() {
                     let sig_header = group_signatures
                         .get(i)
-                        .ok_or_else(|| anyhow::anyhow!("missing signature"))?
+                        .ok_or_else(|| PolicyRejection::error("missing signature"))?
                         .clone();
                     let mut words = sig_header.split_whitespace();
                     let version = words
                         .next()
-                        .ok_or_else(|| anyhow::anyhow!("bad signature"))?;
+                        .ok_or_else(|| PolicyRejection::error("bad signature"))?;
                     let signed = words
                         .next()
-                        .ok_or_else(|| anyhow::anyhow!("bad signature"))?;
+                        .ok_or_else(|| PolicyRejection::error("bad signature"))?;
                     let sig_rest = words.collect::<Vec<_>>().join("\n");
 
                     let mut tmp_headers: SmallVec<[(String, String); 8]> = ctx
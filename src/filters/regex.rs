@@ -0,0 +1,167 @@
+//! Regular expression content filter
+//!
+//! Matches a configurable part of the article (a named header, the subject
+//! or the body) against a list of regular expressions and rejects,
+//! quarantines or tags articles that match any of them. This is the usual
+//! building block for spam blacklists:
+//!
+//! ```toml
+//! [[filters]]
+//! name = "RegexFilter"
+//! target = "subject"
+//! patterns = ["(?i)make money fast"]
+//! action = "reject"
+//! ```
+
+use super::{ArticleFilter, FilterContext, FilterVerdict, PolicyRejection};
+use anyhow::Result;
+use regex::RegexSet;
+use serde::Deserialize;
+
+/// Part of the article a [`RegexFilter`] matches against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegexTarget {
+    /// Every value of the header named by `header`
+    Header,
+    /// The Subject header
+    Subject,
+    /// The article body
+    Body,
+}
+
+/// What a [`RegexFilter`] does with a matching article
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegexAction {
+    /// Refuse the article
+    Reject,
+    /// Hold the article in the moderation queue
+    Quarantine,
+    /// Accept the article with the header given by `add_header`
+    AddHeader,
+}
+
+/// Configuration of a [`RegexFilter`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegexFilterConfig {
+    /// Part of the article to match
+    pub target: RegexTarget,
+    /// Header name when `target` is `header`
+    #[serde(default)]
+    pub header: Option<String>,
+    /// Regular expressions, any of which triggers the action
+    pub patterns: Vec<String>,
+    /// Action taken on a match
+    #[serde(default = "default_action")]
+    pub action: RegexAction,
+    /// Header added by the `add-header` action, as `Name: value`
+    #[serde(default)]
+    pub add_header: Option<String>,
+}
+
+fn default_action() -> RegexAction {
+    RegexAction::Reject
+}
+
+/// Filter that matches article content against regular expressions
+pub struct RegexFilter {
+    target: RegexTarget,
+    header: String,
+    patterns: RegexSet,
+    action: RegexAction,
+    add_header: Option<(String, String)>,
+}
+
+impl RegexFilter {
+    /// Compile the patterns of `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if a pattern does not compile,
+    /// the `header` target has no header name, or the `add-header` action
+    /// has no well-formed header.
+    pub fn new(config: RegexFilterConfig) -> Result<Self, String> {
+        let header = match config.target {
+            RegexTarget::Header => config
+                .header
+                .filter(|h| !h.is_empty())
+                .ok_or("target \"header\" requires a header name")?,
+            RegexTarget::Subject => "Subject".to_string(),
+            RegexTarget::Body => String::new(),
+        };
+        if config.patterns.is_empty() {
+            return Err("at least one pattern is required".to_string());
+        }
+        let patterns = RegexSet::new(&config.patterns).map_err(|e| e.to_string())?;
+        let add_header = match (config.action, config.add_header) {
+            (RegexAction::AddHeader, None) => {
+                return Err("action \"add-header\" requires add_header".to_string());
+            }
+            (_, Some(line)) => {
+                let (name, value) = line
+                    .split_once(':')
+                    .map(|(n, v)| (n.trim(), v.trim()))
+                    .filter(|(n, _)| !n.is_empty() && !n.contains(char::is_whitespace))
+                    .ok_or_else(|| format!("invalid add_header: {line}"))?;
+                Some((name.to_string(), value.to_string()))
+            }
+            (_, None) => None,
+        };
+        Ok(Self {
+            target: config.target,
+            header,
+            patterns,
+            action: config.action,
+            add_header,
+        })
+    }
+
+    /// Whether the configured part of `ctx.article` matches any pattern
+    fn matches(&self, ctx: &FilterContext<'_>) -> bool {
+        match self.target {
+            RegexTarget::Body => self.patterns.is_match(&ctx.article.body),
+            RegexTarget::Header | RegexTarget::Subject => ctx
+                .article
+                .headers
+                .iter()
+                .filter(|(k, _)| k.eq_ignore_ascii_case(&self.header))
+                .any(|(_, v)| self.patterns.is_match(v)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ArticleFilter for RegexFilter {
+    async fn validate(&self, ctx: &FilterContext<'_>) -> Result<()> {
+        if self.action == RegexAction::Reject && self.matches(ctx) {
+            return Err(PolicyRejection::error(
+                "article matches a blacklisted pattern",
+            ));
+        }
+        Ok(())
+    }
+
+    async fn inspect(&self, ctx: &FilterContext<'_>, verdict: &mut FilterVerdict) -> Result<()> {
+        match self.action {
+            RegexAction::Reject => {}
+            RegexAction::Quarantine => {
+                if verdict.quarantine.is_none() && self.matches(ctx) {
+                    verdict.quarantine = Some("article matches a quarantined pattern".into());
+                }
+            }
+            RegexAction::AddHeader => {
+                if let Some(header) = &self.add_header
+                    && self.matches(ctx)
+                {
+                    verdict.add_headers.push(header.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "RegexFilter"
+    }
+}
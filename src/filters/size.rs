@@ -2,7 +2,7 @@
 //!
 //! Validates that articles are within configured size limits.
 
-use super::{ArticleFilter, FilterContext};
+use super::{ArticleFilter, FilterContext, PolicyRejection};
 use crate::handlers::utils::extract_newsgroups;
 use anyhow::Result;
 
@@ -20,7 +20,9 @@ impl ArticleFilter for SizeFilter {
             if let Some(max_size) = ctx.cfg.max_size_for_group(group)
                 && ctx.size > max_size
             {
-                return Err(anyhow::anyhow!("article too large for group {group}"));
+                return Err(PolicyRejection::error(format!(
+                    "article too large for group {group}"
+                )));
             }
         }
 
@@ -30,4 +32,8 @@ impl ArticleFilter for SizeFilter {
     fn name(&self) -> &'static str {
         "SizeFilter"
     }
+
+    fn is_fast(&self) -> bool {
+        true
+    }
 }
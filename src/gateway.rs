@@ -0,0 +1,533 @@
+//! Gateway between mailing lists and newsgroups.
+//!
+//! Each [`GatewayRule`] ties a group to a mailing list, in the manner of
+//! INN's `mailpost` and `news2mail`:
+//!
+//! - Mail delivered to the gateway's `address`, through the SMTP and LMTP
+//!   server on `gateway_addr` or piped to `renews admin receive-mail`, is
+//!   posted to the group. Transport headers such as `Received` and `To` are
+//!   dropped, `In-Reply-To` becomes `References` when the mail has none,
+//!   and the article passes the configured filters like a local post.
+//!   Posts to moderated groups are held for a moderator.
+//! - New articles in the group are mailed to the list's posting address
+//!   through `sendmail_path`, keeping their Message-ID so that replies on
+//!   either side thread together.
+//!
+//! Loops are broken with an `X-Gateway` header naming this site and the
+//! direction. Mail carrying this site's header came back from a list and is
+//! dropped, articles that came from mail are not mailed again, and mail
+//! whose Message-ID is already stored or in the history is not posted a
+//! second time.
+
+use crate::Message;
+use crate::article_reader::ArticleMetadata;
+use crate::auth::DynAuth;
+use crate::config::{Config, GatewayRule};
+use crate::handlers::utils::{extract_newsgroups, get_header_value};
+use crate::local_post::{self, Screened};
+use crate::storage::DynStorage;
+use anyhow::{Result, anyhow};
+use smallvec::SmallVec;
+use std::fmt::Write as _;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+/// Header marking articles and mail that passed through a gateway.
+pub const GATEWAY_HEADER: &str = "X-Gateway";
+
+/// Mail headers carried over to articles and article headers carried over
+/// to mail. Everything else describes the transport it arrived by.
+const KEPT_HEADERS: &[&str] = &[
+    "From",
+    "Reply-To",
+    "Subject",
+    "Date",
+    "Message-ID",
+    "References",
+    "In-Reply-To",
+    "MIME-Version",
+    "Content-Type",
+    "Content-Transfer-Encoding",
+    "Content-Disposition",
+    "Content-Language",
+    "Organization",
+    "User-Agent",
+    "Keywords",
+    "Summary",
+];
+
+/// How long an SMTP client may stay silent.
+const SMTP_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// What became of a received mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Received {
+    /// Posted as the article with this Message-ID
+    Posted(String),
+    /// Held in the moderation queue of a moderated group
+    Held(String),
+    /// Already posted; the list sent the mail again
+    Duplicate(String),
+    /// Mailed to the list by this site and sent back by it
+    Looped,
+}
+
+/// Post `mail`, delivered to `recipient`, to the group of the gateway
+/// taking mail for that address.
+///
+/// # Errors
+///
+/// Returns an error if no gateway takes mail for `recipient`, the mail
+/// cannot be parsed or has no `From`, a filter refuses it, or storage
+/// fails.
+pub async fn receive(
+    storage: &DynStorage,
+    auth: &DynAuth,
+    cfg: &Config,
+    recipient: &str,
+    mail: &str,
+) -> Result<Received> {
+    let rule = cfg
+        .gateways
+        .iter()
+        .find(|rule| rule.receives(recipient))
+        .ok_or_else(|| anyhow!("no gateway takes mail for {recipient}"))?;
+    let text = if mail.contains("\r\n") {
+        mail.to_string()
+    } else {
+        mail.replace('\n', "\r\n")
+    };
+    let (_, mail) = crate::parse_message(&text).map_err(|e| anyhow!("cannot parse mail: {e:?}"))?;
+    if sent_by(&mail, &cfg.site_name) {
+        return Ok(Received::Looped);
+    }
+
+    let mut article = mail_to_article(&mail, rule, &cfg.site_name)?;
+    local_post::prepare(&mut article, cfg, None, None);
+    let message_id = get_header_value(&article, "Message-ID").unwrap_or_default();
+    if crate::history::seen(&**storage, &message_id).await? {
+        return Ok(Received::Duplicate(message_id));
+    }
+
+    // Mail is injected by the site itself rather than a local poster
+    let metadata = ArticleMetadata::from_text(&text);
+    match local_post::screen(storage, auth, cfg, &mut article, &metadata, None).await? {
+        Screened::Accepted => {}
+        Screened::Held | Screened::Quarantined => return Ok(Received::Held(message_id)),
+        Screened::Refused(e) => return Err(e),
+    }
+    storage.store_article(&article).await?;
+    Ok(Received::Posted(message_id))
+}
+
+/// Build the article posting `mail` to the group of `rule`.
+///
+/// # Errors
+///
+/// Returns an error if the mail has no `From` header.
+pub fn mail_to_article(mail: &Message, rule: &GatewayRule, site_name: &str) -> Result<Message> {
+    if get_header_value(mail, "From").is_none() {
+        return Err(anyhow!("mail has no From header"));
+    }
+    let mut headers: SmallVec<[(String, String); 8]> = mail
+        .headers
+        .iter()
+        .filter(|(name, _)| is_kept(name))
+        .cloned()
+        .collect();
+    if get_header_value(mail, "References").is_none()
+        && let Some(parent) = get_header_value(mail, "In-Reply-To")
+            .as_deref()
+            .and_then(first_message_id)
+    {
+        headers.push(("References".into(), parent.to_string()));
+    }
+    if get_header_value(mail, "Subject").is_none() {
+        headers.push(("Subject".into(), "(no subject)".into()));
+    }
+    headers.push(("Newsgroups".into(), rule.group.clone()));
+    let address = rule.address.as_deref().unwrap_or_default();
+    headers.push((
+        GATEWAY_HEADER.into(),
+        format!("{site_name} mail-to-news {address}"),
+    ));
+    Ok(Message {
+        headers,
+        body: mail.body.clone(),
+    })
+}
+
+/// Build the mail sending `article` to the list of `rule`.
+#[must_use]
+pub fn article_to_mail(article: &Message, rule: &GatewayRule, site_name: &str) -> Message {
+    let mut headers: SmallVec<[(String, String); 8]> = article
+        .headers
+        .iter()
+        .filter(|(name, _)| is_kept(name))
+        .cloned()
+        .collect();
+    if let Some(list) = &rule.list {
+        headers.push(("To".into(), list.clone()));
+    }
+    headers.push((
+        GATEWAY_HEADER.into(),
+        format!("{site_name} news-to-mail {}", rule.group),
+    ));
+    Message {
+        headers,
+        body: article.body.clone(),
+    }
+}
+
+/// Mail `article` to the list of every gateway of its groups, unless it
+/// came from mail itself. Mail is sent in the background, so that storing
+/// articles does not wait for it.
+pub async fn forward(config: &Arc<RwLock<Config>>, article: &Message) {
+    let (sendmail, mails) = {
+        let cfg = config.read().await;
+        if cfg.gateways.is_empty() || get_header_value(article, GATEWAY_HEADER).is_some() {
+            return;
+        }
+        let groups = extract_newsgroups(article);
+        let mut mails: Vec<(String, Message)> = Vec::new();
+        for rule in &cfg.gateways {
+            let Some(list) = &rule.list else {
+                continue;
+            };
+            // Cross-posts reach each list once
+            if !groups.contains(&rule.group) || mails.iter().any(|(to, _)| to == list) {
+                continue;
+            }
+            mails.push((list.clone(), article_to_mail(article, rule, &cfg.site_name)));
+        }
+        (cfg.sendmail_path.clone(), mails)
+    };
+    for (list, mail) in mails {
+        let sendmail = sendmail.clone();
+        tokio::spawn(async move {
+            match send_mail(&sendmail, &list, &mail).await {
+                Ok(()) => debug!(list = list.as_str(), "Article mailed to list"),
+                Err(e) => warn!(list = list.as_str(), error = %e, "Failed to mail article to list"),
+            }
+        });
+    }
+}
+
+/// Pipe `mail` to `sendmail -i -- recipient`.
+async fn send_mail(sendmail: &str, recipient: &str, mail: &Message) -> Result<()> {
+    let mut text = String::new();
+    for (name, value) in &mail.headers {
+        let _ = writeln!(text, "{name}: {value}");
+    }
+    text.push('\n');
+    text.push_str(&mail.body.replace("\r\n", "\n"));
+
+    let mut child = tokio::process::Command::new(sendmail)
+        .args(["-i", "--", recipient])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("sendmail stdin unavailable"))?;
+    stdin.write_all(text.as_bytes()).await?;
+    drop(stdin);
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow!("{sendmail} exited with {status}"));
+    }
+    Ok(())
+}
+
+fn is_kept(name: &str) -> bool {
+    KEPT_HEADERS
+        .iter()
+        .any(|kept| kept.eq_ignore_ascii_case(name))
+}
+
+/// Whether `mail` was sent by the gateway of `site_name`.
+fn sent_by(mail: &Message, site_name: &str) -> bool {
+    mail.headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(GATEWAY_HEADER))
+        .any(|(_, value)| value.split_whitespace().next() == Some(site_name))
+}
+
+/// The first `<...>` Message-ID in `value`.
+fn first_message_id(value: &str) -> Option<&str> {
+    let start = value.find('<')?;
+    let end = start + value[start..].find('>')?;
+    Some(&value[start..=end])
+}
+
+/// Bind the SMTP and LMTP listener of `gateway_addr` `raw`.
+///
+/// # Errors
+///
+/// Returns an error if the address cannot be bound.
+pub async fn bind(raw: &str) -> Result<TcpListener> {
+    let addr = crate::config::listen_addr(raw);
+    TcpListener::bind(&addr).await.map_err(|e| {
+        anyhow!(
+            "Failed to bind to mail gateway address '{addr}': {e}
+
+You can change the mail gateway listen address in your configuration file using the 'gateway_addr' setting
+or disable the listener by removing the 'gateway_addr' configuration."
+        )
+    })
+}
+
+/// SMTP (RFC 5321) and LMTP (RFC 2033) server taking mail for the
+/// gateway addresses. It offers no authentication or TLS and should only
+/// listen where the local mail server can reach it.
+pub struct MailServer {
+    storage: DynStorage,
+    auth: DynAuth,
+    config: Arc<RwLock<Config>>,
+}
+
+impl MailServer {
+    #[must_use]
+    pub fn new(storage: DynStorage, auth: DynAuth, config: Arc<RwLock<Config>>) -> Self {
+        Self {
+            storage,
+            auth,
+            config,
+        }
+    }
+
+    /// Accept mail on `listener` until it fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting a connection fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle(stream).await {
+                    debug!(peer = %peer, error = %e, "Mail gateway session failed");
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, stream: TcpStream) -> Result<()> {
+        let (read, write) = stream.into_split();
+        self.session(read, write).await
+    }
+
+    /// Run one SMTP or LMTP session.
+    async fn session<R, W>(&self, read: R, mut write: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let (site_name, max_bytes) = {
+            let cfg = self.config.read().await;
+            (cfg.site_name.clone(), cfg.max_message_bytes)
+        };
+        let mut reader = BufReader::new(read);
+        reply(&mut write, &format!("220 {site_name} renews mail gateway")).await?;
+
+        let mut lmtp = false;
+        let mut sender: Option<String> = None;
+        let mut recipients: Vec<String> = Vec::new();
+        loop {
+            let Some(line) = read_command(&mut reader).await? else {
+                return Ok(());
+            };
+            let verb = line
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_ascii_uppercase();
+            match verb.as_str() {
+                "EHLO" | "HELO" | "LHLO" => {
+                    lmtp = verb == "LHLO";
+                    sender = None;
+                    recipients.clear();
+                    if verb == "HELO" {
+                        reply(&mut write, &format!("250 {site_name}")).await?;
+                    } else {
+                        let mut text = format!("250-{site_name}\r\n250-8BITMIME\r\n");
+                        if let Some(max) = max_bytes {
+                            let _ = write!(text, "250-SIZE {max}\r\n");
+                        }
+                        text.push_str("250 PIPELINING");
+                        reply(&mut write, &text).await?;
+                    }
+                }
+                "MAIL" => {
+                    if sender.is_some() {
+                        reply(&mut write, "503 5.5.1 sender already given").await?;
+                    } else {
+                        sender = Some(path_argument(&line, "FROM:").unwrap_or_default());
+                        recipients.clear();
+                        reply(&mut write, "250 2.1.0 ok").await?;
+                    }
+                }
+                "RCPT" => {
+                    let Some(recipient) = path_argument(&line, "TO:") else {
+                        reply(&mut write, "501 5.5.4 syntax: RCPT TO:<address>").await?;
+                        continue;
+                    };
+                    if sender.is_none() {
+                        reply(&mut write, "503 5.5.1 need MAIL first").await?;
+                    } else if self
+                        .config
+                        .read()
+                        .await
+                        .gateways
+                        .iter()
+                        .any(|rule| rule.receives(&recipient))
+                    {
+                        recipients.push(recipient);
+                        reply(&mut write, "250 2.1.5 ok").await?;
+                    } else {
+                        reply(&mut write, "550 5.1.1 no gateway for this address").await?;
+                    }
+                }
+                "DATA" => {
+                    if recipients.is_empty() {
+                        reply(&mut write, "503 5.5.1 need RCPT first").await?;
+                        continue;
+                    }
+                    reply(&mut write, "354 end data with <CR><LF>.<CR><LF>").await?;
+                    let mail = read_data(&mut reader, max_bytes).await?;
+                    let answers = match &mail {
+                        Some(mail) => {
+                            let mut answers = Vec::with_capacity(recipients.len());
+                            for recipient in &recipients {
+                                answers.push(self.deliver(recipient, mail).await);
+                            }
+                            answers
+                        }
+                        None => vec!["552 5.3.4 message too big"; recipients.len()],
+                    };
+                    if lmtp {
+                        for answer in &answers {
+                            reply(&mut write, answer).await?;
+                        }
+                    } else {
+                        // One answer for all recipients: the first failure,
+                        // if any
+                        let answer = answers
+                            .iter()
+                            .find(|answer| !answer.starts_with('2'))
+                            .unwrap_or(&answers[0]);
+                        reply(&mut write, answer).await?;
+                    }
+                    sender = None;
+                    recipients.clear();
+                }
+                "RSET" => {
+                    sender = None;
+                    recipients.clear();
+                    reply(&mut write, "250 2.0.0 ok").await?;
+                }
+                "NOOP" => reply(&mut write, "250 2.0.0 ok").await?,
+                "VRFY" => reply(&mut write, "252 2.5.2 cannot verify").await?,
+                "QUIT" => {
+                    reply(&mut write, "221 2.0.0 bye").await?;
+                    return Ok(());
+                }
+                _ => reply(&mut write, "502 5.5.2 command not implemented").await?,
+            }
+        }
+    }
+
+    /// Post `mail` for `recipient`, returning the reply for it.
+    async fn deliver(&self, recipient: &str, mail: &str) -> &'static str {
+        let cfg = self.config.read().await.clone();
+        match receive(&self.storage, &self.auth, &cfg, recipient, mail).await {
+            Ok(received) => {
+                info!(recipient, outcome = ?received, "Mail received by gateway");
+                "250 2.0.0 ok"
+            }
+            Err(e) if crate::filters::TemporaryFailure::is(&e) => {
+                warn!(recipient, error = %e, "Mail deferred by gateway");
+                "451 4.3.0 try again later"
+            }
+            Err(e) => {
+                warn!(recipient, error = %e, "Mail refused by gateway");
+                "554 5.6.0 mail refused"
+            }
+        }
+    }
+}
+
+async fn reply<W: AsyncWrite + Unpin>(write: &mut W, text: &str) -> Result<()> {
+    write.write_all(text.as_bytes()).await?;
+    write.write_all(b"\r\n").await?;
+    write.flush().await?;
+    Ok(())
+}
+
+/// Read a command line, or `None` once the client has gone.
+async fn read_command<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> Result<Option<String>> {
+    let mut line = String::new();
+    let read = tokio::time::timeout(
+        SMTP_IDLE_TIMEOUT,
+        (&mut *reader).take(4096).read_line(&mut line),
+    )
+    .await
+    .map_err(|_| anyhow!("client idle"))??;
+    if read == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Read the mail following DATA up to the lone dot, undoing dot-stuffing.
+/// Returns `None` if the mail is larger than `max_bytes`; it is read to the
+/// end all the same.
+async fn read_data<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    max_bytes: Option<u64>,
+) -> Result<Option<String>> {
+    let mut mail = String::new();
+    let mut too_big = false;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = tokio::time::timeout(SMTP_IDLE_TIMEOUT, reader.read_until(b'\n', &mut line))
+            .await
+            .map_err(|_| anyhow!("client idle"))??;
+        if read == 0 {
+            return Err(anyhow!("connection closed during DATA"));
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        if text == "." {
+            break;
+        }
+        if too_big {
+            continue;
+        }
+        mail.push_str(text.strip_prefix('.').unwrap_or(text));
+        mail.push_str("\r\n");
+        if max_bytes.is_some_and(|max| mail.len() as u64 > max) {
+            too_big = true;
+            mail.clear();
+        }
+    }
+    Ok((!too_big).then_some(mail))
+}
+
+/// The address in `<...>` after `keyword` in an SMTP command.
+fn path_argument(line: &str, keyword: &str) -> Option<String> {
+    let upper = line.to_ascii_uppercase();
+    let start = upper.find(keyword)? + keyword.len();
+    let rest = line[start..].trim_start();
+    let rest = rest.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    Some(rest[..end].to_string())
+}
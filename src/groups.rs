@@ -0,0 +1,62 @@
+//! Newsgroups declared in the configuration.
+//!
+//! Group rules naming a group exactly declare it, and rules may declare
+//! groups moderated or unmoderated by name or pattern. Groups created by
+//! control messages or `renews admin add-group` take the declared flag when
+//! they are created; [`sync_groups`] brings the groups already carried into
+//! line with the configuration on demand.
+
+use crate::audit::{self, AuditAction, AuditEntry};
+use crate::config::Config;
+use crate::storage::Storage;
+use anyhow::Result;
+use futures_util::TryStreamExt;
+
+/// What [`sync_groups`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupSync {
+    /// Declared groups that were created
+    pub created: Vec<String>,
+    /// Groups whose moderation flag was changed to the declared one
+    pub moderation_changed: Vec<String>,
+}
+
+/// Create the groups declared in `cfg` that `storage` does not carry yet,
+/// and set the moderation flag of every carried group to the one declared
+/// for it. Groups without a declared flag are left alone, and no group is
+/// removed.
+///
+/// # Errors
+///
+/// Returns an error if the storage fails.
+pub async fn sync_groups(storage: &dyn Storage, cfg: &Config) -> Result<GroupSync> {
+    let mut sync = GroupSync::default();
+    for group in cfg.declared_groups() {
+        if storage.group_exists(group).await? {
+            continue;
+        }
+        storage
+            .add_group(group, cfg.group_moderation(group).unwrap_or(false))
+            .await?;
+        audit::record(
+            storage,
+            AuditEntry::new(AuditAction::AddGroup, group)
+                .by_cli()
+                .with_detail("sync-groups"),
+        )
+        .await;
+        sync.created.push(group.to_string());
+    }
+
+    let groups: Vec<String> = storage.list_groups().try_collect().await?;
+    for group in groups {
+        let Some(moderated) = cfg.group_moderation(&group) else {
+            continue;
+        };
+        if storage.is_group_moderated(&group).await? != moderated {
+            storage.set_group_moderated(&group, moderated).await?;
+            sync.moderation_changed.push(group);
+        }
+    }
+    Ok(sync)
+}
@@ -0,0 +1,259 @@
+//! Command argument validation.
+//!
+//! Each command has an [`ArgSchema`] describing the arguments RFC 3977 and
+//! its extensions allow. Arguments are checked against the schema before
+//! the handler runs, so malformed input gets a 501 naming the offending
+//! argument instead of command-specific fallbacks. Missing arguments are
+//! left to the handlers, which already answer with command-specific
+//! responses.
+
+use crate::error::ArgumentError;
+use crate::responses::*;
+use crate::resume;
+
+/// The syntax an argument must follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Anything, checked by the handler
+    Any,
+    /// A decimal number
+    Number,
+    /// A message-id in angle brackets
+    MessageId,
+    /// An article number range (`n`, `n-` or `n-m`); a range with `m < n`
+    /// is well formed and selects no articles (RFC 3977 Section 6.1.2)
+    Range,
+    /// A message-id or an article number, as ARTICLE, HEAD and STAT take
+    SingleArticle,
+    /// A message-id or an article number range
+    Article,
+    /// A message-id, article number range or resume token
+    ArticleOrToken,
+    /// A byte range for resumable BODY downloads
+    ByteRange,
+    /// A `yymmdd` or `yyyymmdd` date
+    Date,
+    /// A `hhmmss` time
+    Time,
+    /// The literal `GMT`
+    Gmt,
+}
+
+impl ArgKind {
+    /// Name used in the 501 diagnostic.
+    fn name(self) -> &'static str {
+        match self {
+            ArgKind::Any | ArgKind::Gmt => "argument",
+            ArgKind::Number => "number",
+            ArgKind::MessageId => "message-id",
+            ArgKind::Range => "range",
+            ArgKind::SingleArticle | ArgKind::Article | ArgKind::ArticleOrToken => "article",
+            ArgKind::ByteRange => "byte range",
+            ArgKind::Date => "date",
+            ArgKind::Time => "time",
+        }
+    }
+
+    /// The 501 line refusing a malformed argument of this kind.
+    fn status(self) -> &'static str {
+        match self {
+            ArgKind::Any | ArgKind::Gmt => RESP_501_INVALID_ARG,
+            ArgKind::Number => RESP_501_INVALID_NUMBER,
+            ArgKind::MessageId => RESP_501_INVALID_MSGID,
+            ArgKind::Range => RESP_501_INVALID_ARTICLE_RANGE,
+            ArgKind::SingleArticle | ArgKind::Article | ArgKind::ArticleOrToken => {
+                RESP_501_INVALID_ARTICLE
+            }
+            ArgKind::ByteRange => RESP_501_INVALID_RANGE,
+            ArgKind::Date => RESP_501_INVALID_DATE,
+            ArgKind::Time => RESP_501_INVALID_TIME,
+        }
+    }
+
+    fn accepts(self, arg: &str) -> bool {
+        match self {
+            ArgKind::Any => true,
+            ArgKind::Number => is_number(arg),
+            ArgKind::MessageId => is_message_id(arg),
+            ArgKind::Range => is_range(arg),
+            ArgKind::SingleArticle => is_message_id(arg) || is_number(arg),
+            ArgKind::Article => is_message_id(arg) || is_range(arg),
+            ArgKind::ArticleOrToken => is_message_id(arg) || is_range(arg) || resume::is_token(arg),
+            ArgKind::ByteRange => resume::parse_byte_range(arg).is_some(),
+            ArgKind::Date => (arg.len() == 6 || arg.len() == 8) && is_number(arg),
+            ArgKind::Time => arg.len() == 6 && is_number(arg),
+            ArgKind::Gmt => arg.eq_ignore_ascii_case("GMT"),
+        }
+    }
+}
+
+/// Arguments accepted by a command.
+#[derive(Debug, Clone, Copy)]
+pub struct ArgSchema {
+    /// Syntax of each positional argument
+    pub kinds: &'static [ArgKind],
+    /// Maximum number of arguments, or `None` if trailing arguments are
+    /// unchecked
+    pub max: Option<usize>,
+}
+
+impl ArgSchema {
+    const fn exactly(kinds: &'static [ArgKind]) -> Self {
+        Self {
+            kinds,
+            max: Some(kinds.len()),
+        }
+    }
+
+    const fn open(kinds: &'static [ArgKind]) -> Self {
+        Self { kinds, max: None }
+    }
+}
+
+/// Look up the argument schema of `command`, whose first argument is
+/// `keyword`. Commands without a schema are passed to their handler
+/// unchecked.
+#[must_use]
+pub fn schema(command: &str, keyword: Option<&str>) -> Option<ArgSchema> {
+    use ArgKind::*;
+    let schema = match command {
+        "ARTICLE" | "HEAD" | "STAT" => ArgSchema::exactly(&[SingleArticle]),
+        "OVER" | "XOVER" | "XZVER" => ArgSchema::exactly(&[Article]),
+        "BODY" => ArgSchema::exactly(&[ArticleOrToken, ByteRange]),
+        "GROUP" | "CAPABILITIES" | "MODE" => ArgSchema::exactly(&[Any]),
+        "LISTGROUP" => ArgSchema::exactly(&[Any, Range]),
+        "LIST" => ArgSchema::exactly(&[Any, Any]),
+        "NEXT" | "LAST" | "POST" | "DATE" | "HELP" => ArgSchema::exactly(&[]),
+        "NEWGROUPS" => ArgSchema::exactly(&[Date, Time, Gmt]),
+        "NEWNEWS" => ArgSchema::exactly(&[Any, Date, Time, Gmt]),
+        "HDR" | "XZHDR" => ArgSchema::exactly(&[Any, Article]),
+        "XPAT" => ArgSchema::open(&[Any, Article]),
+        "XTHREAD" => ArgSchema::exactly(&[Article]),
+        "IHAVE" | "CHECK" => ArgSchema::exactly(&[MessageId]),
+        "XPOSTCHECK" => ArgSchema::exactly(&[Number]),
+        "XMODERATE" => match keyword {
+            Some(k) if k.eq_ignore_ascii_case("LIST") => ArgSchema::exactly(&[Any, Any]),
+            Some(k) if k.eq_ignore_ascii_case("PIN") || k.eq_ignore_ascii_case("UNPIN") => {
+                ArgSchema::exactly(&[Any, Any, MessageId])
+            }
+            _ => ArgSchema::exactly(&[Any, Number]),
+        },
+        "XFEATURE" => ArgSchema::exactly(&[Any, Any, Any]),
+        // TAKETHIS is always followed by an article that must be read, and
+        // AUTHINFO arguments are credentials that should not be echoed. QUIT
+        // always closes the connection.
+        _ => return None,
+    };
+    Some(schema)
+}
+
+/// Check `args` against the schema of `command`.
+///
+/// # Errors
+///
+/// Returns the first argument that is superfluous or malformed.
+pub fn validate(command: &str, args: &[String]) -> Result<(), ArgumentError> {
+    let Some(schema) = schema(command, args.first().map(String::as_str)) else {
+        return Ok(());
+    };
+    if let Some(max) = schema.max
+        && let Some(extra) = args.get(max)
+    {
+        return Err(ArgumentError::TooMany(extra.clone()));
+    }
+    for (kind, arg) in schema.kinds.iter().zip(args) {
+        if !kind.accepts(arg) {
+            return Err(ArgumentError::Invalid {
+                kind: kind.name(),
+                status: kind.status(),
+                arg: arg.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn is_number(arg: &str) -> bool {
+    !arg.is_empty() && arg.bytes().all(|b| b.is_ascii_digit()) && arg.parse::<u64>().is_ok()
+}
+
+/// RFC 3977 Section 3.6: 3 to 250 octets in angle brackets.
+pub(crate) fn is_message_id(arg: &str) -> bool {
+    (3..=250).contains(&arg.len())
+        && arg.starts_with('<')
+        && arg.ends_with('>')
+        && !arg[1..arg.len() - 1].contains('>')
+}
+
+/// A reversed range such as `5-2` is well formed; RFC 3977 Section 6.1.2
+/// says it selects no articles, so it is answered as an empty range rather
+/// than refused.
+fn is_range(arg: &str) -> bool {
+    match arg.split_once('-') {
+        None => is_number(arg),
+        Some((low, "")) => is_number(low),
+        Some((low, high)) => is_number(low) && is_number(high),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(line: &str) -> Result<(), ArgumentError> {
+        let mut words = line.split(' ');
+        let command = words.next().unwrap();
+        let args: Vec<String> = words.map(str::to_string).collect();
+        validate(command, &args)
+    }
+
+    #[test]
+    fn accepts_well_formed_arguments() {
+        for line in [
+            "ARTICLE <a@b>",
+            "ARTICLE 3",
+            "HEAD <a@b>",
+            "BODY r0123456789abcdef0123456789abcdef 5-",
+            "LISTGROUP misc 1-10",
+            // Reversed ranges are empty, not malformed (RFC 3977 6.1.2)
+            "OVER 5-2",
+            "NEWNEWS * 20241201 000000 GMT",
+            "NEWGROUPS 241201 000000",
+            "XPAT Subject 1- *a* *b*",
+            "XMODERATE APPROVE 7",
+            "XMODERATE LIST mod.*",
+            "XMODERATE PIN mod.test <a@b>",
+            "XPOSTCHECK 1048576",
+            "AUTHINFO PASS secret with spaces",
+        ] {
+            assert_eq!(check(line), Ok(()), "{line}");
+        }
+    }
+
+    #[test]
+    fn names_the_offending_argument() {
+        assert_eq!(check("NEXT 1"), Err(ArgumentError::TooMany("1".into())));
+        assert_eq!(
+            check("OVER 5-x").unwrap_err().to_response(),
+            "501 invalid article: 5-x\r\n"
+        );
+        // ARTICLE, HEAD and STAT take a single article, never a range
+        for line in ["ARTICLE 3-", "HEAD 1-2", "STAT 2-1"] {
+            let arg = line.split_once(' ').unwrap().1;
+            assert_eq!(
+                check(line).unwrap_err().to_response(),
+                format!("501 invalid article: {arg}\r\n"),
+            );
+        }
+        assert_eq!(
+            check("NEWGROUPS 20241201 000000 UTC")
+                .unwrap_err()
+                .to_response(),
+            "501 invalid argument: UTC\r\n"
+        );
+        assert_eq!(
+            check("XMODERATE APPROVE x").unwrap_err().to_response(),
+            "501 invalid number: x\r\n"
+        );
+    }
+}
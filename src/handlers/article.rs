@@ -1,11 +1,16 @@
 //! Article retrieval command handlers.
 
+use super::utils::ArticleQueryError;
 use super::utils::{
-    ArticleOperation, BandwidthContext, get_header_value, handle_article_operation, metadata_value,
-    resolve_articles, write_response_with_values, write_simple,
+    ArticleOperation, BandwidthContext, add_xref_header, article_by_id, check_bandwidth_rejected,
+    get_header_value, handle_article_operation, may_read_message_id, metadata_value,
+    resolve_articles, write_response_with_values, write_simple, xref_value,
 };
 use super::{CommandHandler, HandlerContext, HandlerResult};
 use crate::responses::*;
+use crate::resume;
+use crate::session::OverviewCompression;
+use crate::storage::cache::{OverviewKey, RenderedOverview};
 use tokio::io::AsyncWriteExt;
 
 /// Macro to create simple article command handlers.
@@ -15,36 +20,134 @@ macro_rules! article_handler {
 
         impl CommandHandler for $name {
             async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
-                // Create bandwidth context for authenticated non-admin users
-                let bandwidth_ctx = if ctx.session.is_authenticated() && !ctx.session.is_admin() {
-                    ctx.session.username().map(|username| BandwidthContext {
-                        tracker: ctx.usage_tracker.clone(),
-                        username: username.to_string(),
-                    })
-                } else {
-                    None
-                };
-
-                handle_article_operation(
-                    &mut ctx.writer,
-                    &ctx.storage,
-                    &mut ctx.session,
-                    args,
-                    $operation,
-                    bandwidth_ctx,
-                )
-                .await
+                run_article_operation(ctx, args, $operation).await
             }
         }
     };
 }
 
+/// Run an ARTICLE, HEAD, BODY or STAT command.
+async fn run_article_operation(
+    ctx: &mut HandlerContext,
+    args: &[String],
+    operation: ArticleOperation,
+) -> HandlerResult {
+    // Create bandwidth context for authenticated non-admin users
+    let bandwidth_ctx = if ctx.session.is_authenticated() && !ctx.session.is_admin() {
+        ctx.session.username().map(|username| BandwidthContext {
+            tracker: ctx.usage_tracker.clone(),
+            username: username.to_string(),
+        })
+    } else {
+        None
+    };
+
+    handle_article_operation(
+        &mut ctx.writer,
+        &ctx.storage,
+        &mut ctx.session,
+        &ctx.config,
+        args,
+        operation,
+        bandwidth_ctx,
+    )
+    .await
+}
+
 // Generate handlers for basic article operations
 article_handler!(ArticleHandler, ArticleOperation::Full);
 article_handler!(HeadHandler, ArticleOperation::Headers);
-article_handler!(BodyHandler, ArticleOperation::Body);
 article_handler!(StatHandler, ArticleOperation::Stat);
 
+/// Handler for the BODY command.
+///
+/// A second argument of the form `<first>-[<last>]` requests a byte range
+/// of the body, and the article may be named by a resume token; see
+/// [`crate::resume`].
+pub struct BodyHandler;
+
+impl CommandHandler for BodyHandler {
+    async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
+        match args {
+            [article, range] => handle_body_range(ctx, article, range).await,
+            _ => run_article_operation(ctx, args, ArticleOperation::Body).await,
+        }
+    }
+}
+
+/// Send a byte range of an article body along with a resume token.
+async fn handle_body_range(ctx: &mut HandlerContext, article: &str, range: &str) -> HandlerResult {
+    let Some(range) = resume::parse_byte_range(range) else {
+        return write_simple(&mut ctx.writer, RESP_501_INVALID_RANGE).await;
+    };
+
+    // Resolve the article to its number and Message-ID without loading it
+    // where possible
+    let (num, message_id) = if resume::is_token(article) {
+        let since = chrono::Utc::now() - resume::TOKEN_LIFETIME;
+        match ctx.storage.get_resume_token(article, since).await? {
+            Some(id) => (0, id),
+            None => return write_simple(&mut ctx.writer, RESP_430_NO_ARTICLE).await,
+        }
+    } else if article.starts_with('<') && article.ends_with('>') {
+        (0, article.to_string())
+    } else {
+        match resolve_articles(&ctx.storage, &ctx.config, &mut ctx.session, Some(article)).await {
+            Ok(articles) => match articles.into_iter().next() {
+                Some((num, msg)) => (
+                    num,
+                    get_header_value(&msg, "Message-ID").unwrap_or_default(),
+                ),
+                None => return write_simple(&mut ctx.writer, RESP_423_NO_ARTICLE_NUM).await,
+            },
+            Err(error) => {
+                use super::utils::handle_article_error;
+                return handle_article_error(&mut ctx.writer, error).await;
+            }
+        }
+    };
+
+    if !may_read_message_id(&ctx.storage, &ctx.config, &ctx.session, &message_id).await? {
+        return write_simple(&mut ctx.writer, RESP_430_NO_ARTICLE).await;
+    }
+    let Some((total, chunk)) = ctx
+        .storage
+        .get_body_range(&message_id, range.first, range.byte_count())
+        .await?
+    else {
+        return write_simple(&mut ctx.writer, RESP_430_NO_ARTICLE).await;
+    };
+    if chunk.is_empty() {
+        return write_simple(&mut ctx.writer, RESP_501_INVALID_RANGE).await;
+    }
+
+    let size = chunk.len() as u64 + 5; // +5 for .\r\n
+    if check_bandwidth_rejected(&mut ctx.writer, &ctx.session, &ctx.usage_tracker, size).await? {
+        return Ok(());
+    }
+
+    // Reuse the session's token for this article, or the one presented,
+    // refreshing its lifetime either way
+    let token = match ctx.session.resume_token(&message_id) {
+        Some(token) => token.to_string(),
+        None if resume::is_token(article) => article.to_string(),
+        None => resume::new_token(),
+    };
+    ctx.storage.add_resume_token(&token, &message_id).await?;
+    ctx.session
+        .set_resume_token(message_id.clone(), token.clone());
+
+    let last = range.first + chunk.len() as u64 - 1;
+    let response = format!(
+        "222 {num} {message_id} {}-{last}/{total} {token} body range follows\r\n",
+        range.first
+    );
+    ctx.writer.write_all(response.as_bytes()).await?;
+    ctx.writer.write_all(&resume::encode_chunk(&chunk)).await?;
+    ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+    Ok(())
+}
+
 /// Handler for the HDR command.
 pub struct HdrHandler;
 
@@ -65,6 +168,7 @@ impl CommandHandler for HdrHandler {
         match collect_header_values(
             &ctx.storage,
             &ctx.session,
+            &ctx.config,
             field,
             args.get(1).map(|s| s.as_str()),
         )
@@ -95,17 +199,22 @@ impl CommandHandler for XPatHandler {
         let range_or_msgid = &args[1];
         let patterns: Vec<&str> = args[2..].iter().map(String::as_str).collect();
 
-        let values =
-            match collect_header_values(&ctx.storage, &ctx.session, field, Some(range_or_msgid))
-                .await
-            {
-                Ok(values) => values,
-                Err(error) => {
-                    use super::utils::handle_article_error;
-                    handle_article_error(&mut ctx.writer, error).await?;
-                    return Ok(());
-                }
-            };
+        let values = match collect_header_values(
+            &ctx.storage,
+            &ctx.session,
+            &ctx.config,
+            field,
+            Some(range_or_msgid),
+        )
+        .await
+        {
+            Ok(values) => values,
+            Err(error) => {
+                use super::utils::handle_article_error;
+                handle_article_error(&mut ctx.writer, error).await?;
+                return Ok(());
+            }
+        };
 
         write_simple(&mut ctx.writer, RESP_221_HEADER_FOLLOWS).await?;
 
@@ -125,46 +234,348 @@ impl CommandHandler for XPatHandler {
 }
 
 /// Handler for the OVER command.
+///
+/// With `max_over_range` set, a range covering more article numbers is
+/// answered with its first page and a 224 line naming the range to
+/// continue with, so one command cannot hold the connection for the whole
+/// of a huge group.
 pub struct OverHandler;
 
 impl CommandHandler for OverHandler {
     async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
-        match resolve_articles(
+        let max_range = ctx.config.read().await.max_over_range;
+        let (spec, next) = match (args.first(), max_range) {
+            (Some(spec), Some(max)) => {
+                let group = ctx.session.current_group();
+                over_page(&ctx.storage, group, spec, max).await?
+            }
+            (spec, _) => (spec.cloned(), None),
+        };
+        let status = match &next {
+            Some(next) => overview_continues(next),
+            None => localize(RESP_224_OVERVIEW).into_owned(),
+        };
+        match render_overview(ctx, spec.as_deref()).await? {
+            Ok(text) => write_overview(ctx, &status, text).await?,
+            // A page holding only expired articles still leads on
+            Err(ArticleQueryError::RangeEmpty | ArticleQueryError::NotFoundByNumber)
+                if next.is_some() =>
+            {
+                write_overview(ctx, &status, String::new()).await?;
+            }
+            Err(error) => {
+                use super::utils::handle_article_error;
+                handle_article_error(&mut ctx.writer, error).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Cut the OVER range `spec` in `group` to its first `max` article
+/// numbers when articles lie beyond them. Returns the range to serve and,
+/// if it was cut, the range the client continues with.
+async fn over_page(
+    storage: &crate::storage::DynStorage,
+    group: Option<&str>,
+    spec: &str,
+    max: u64,
+) -> anyhow::Result<(Option<String>, Option<String>)> {
+    let whole = (Some(spec.to_string()), None);
+    let (Some(group), Some((start, end))) = (group, spec.split_once('-')) else {
+        return Ok(whole);
+    };
+    let Ok(start) = start.parse::<u64>() else {
+        return Ok(whole);
+    };
+    let last = match end {
+        "" => None,
+        end => match end.parse::<u64>() {
+            Ok(last) => Some(last),
+            Err(_) => return Ok(whole),
+        },
+    };
+    let Some(marks) = storage.get_group_watermarks(group).await? else {
+        return Ok(whole);
+    };
+    let page_end = start.saturating_add(max.max(1) - 1);
+    if last.map_or(marks.high, |last| last.min(marks.high)) <= page_end {
+        return Ok(whole);
+    }
+    Ok((
+        Some(format!("{start}-{page_end}")),
+        Some(format!("{}-{end}", page_end + 1)),
+    ))
+}
+
+/// Send the overview lines `text` after the 224 line `status`, compressed
+/// if the client asked for it with XFEATURE COMPRESS GZIP.
+async fn write_overview(ctx: &mut HandlerContext, status: &str, text: String) -> HandlerResult {
+    ctx.writer.write_all(status.as_bytes()).await?;
+    match ctx.session.overview_compression() {
+        OverviewCompression::None => {
+            ctx.writer.write_all(text.as_bytes()).await?;
+            ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+        }
+        OverviewCompression::Gzip => {
+            let compressed = crate::compress::deflate(text.as_bytes())?;
+            ctx.writer.write_all(&compressed).await?;
+            ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+        }
+        OverviewCompression::GzipTerminator => {
+            let mut text = text;
+            text.push_str(RESP_DOT_CRLF);
+            let compressed = crate::compress::deflate(text.as_bytes())?;
+            ctx.writer.write_all(&compressed).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handler for the XTHREAD command.
+///
+/// Returns the overview lines of the articles of the current group in the
+/// thread containing the article given by number or message-id, or the
+/// current article, ordered by number.
+pub struct XThreadHandler;
+
+impl CommandHandler for XThreadHandler {
+    async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
+        use super::utils::handle_article_error;
+
+        let Some(group) = ctx.session.current_group().map(str::to_string) else {
+            return handle_article_error(&mut ctx.writer, ArticleQueryError::NoGroup).await;
+        };
+        let article = match args.first() {
+            Some(id) if id.starts_with('<') => {
+                article_by_id(&ctx.storage, &ctx.config, &ctx.session, id).await
+            }
+            Some(number) => match number.parse() {
+                Ok(number) => ctx
+                    .storage
+                    .get_article_by_number(&group, number)
+                    .await?
+                    .ok_or(ArticleQueryError::NotFoundByNumber),
+                Err(_) => return write_simple(&mut ctx.writer, RESP_501_INVALID_ARG).await,
+            },
+            None => match ctx.session.current_article() {
+                Some(number) => ctx
+                    .storage
+                    .get_article_by_number(&group, number)
+                    .await?
+                    .ok_or(ArticleQueryError::NoCurrentArticle),
+                None => Err(ArticleQueryError::NoCurrentArticle),
+            },
+        };
+        let article = match article {
+            Ok(article) => article,
+            Err(error) => return handle_article_error(&mut ctx.writer, error).await,
+        };
+
+        let mut articles = Vec::new();
+        for number in crate::thread::thread_numbers(&*ctx.storage, &group, &article).await? {
+            if let Some(article) = ctx.storage.get_article_by_number(&group, number).await? {
+                articles.push((number, article));
+            }
+        }
+        add_xref_headers(&ctx.storage, &ctx.config, &mut articles).await?;
+        let extra = ctx.config.read().await.extra_overview_headers();
+        let status = localize(RESP_224_OVERVIEW);
+        write_overview(ctx, &status, overview_text(&articles, &extra)).await
+    }
+}
+
+/// Handler for the XZVER command.
+///
+/// Returns the same data as OVER, zlib-compressed and yEnc-encoded.
+pub struct XzverHandler;
+
+impl CommandHandler for XzverHandler {
+    async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
+        match render_overview(ctx, args.first().map(String::as_str)).await? {
+            Ok(text) => {
+                let encoded = crate::compress::compress_overview(text.as_bytes())?;
+                ctx.writer
+                    .write_all(localize(RESP_224_COMPRESSED_OVERVIEW).as_bytes())
+                    .await?;
+                ctx.writer.write_all(&encoded).await?;
+                ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+            }
+            Err(error) => {
+                use super::utils::handle_article_error;
+                handle_article_error(&mut ctx.writer, error).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handler for the XZHDR command.
+///
+/// Returns the same data as HDR, zlib-compressed and yEnc-encoded.
+pub struct XzhdrHandler;
+
+impl CommandHandler for XzhdrHandler {
+    async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
+        if args.is_empty() {
+            return write_simple(&mut ctx.writer, RESP_501_NOT_ENOUGH).await;
+        }
+
+        match collect_header_values(
             &ctx.storage,
-            &mut ctx.session,
-            args.first().map(String::as_str),
+            &ctx.session,
+            &ctx.config,
+            &args[0],
+            args.get(1).map(|s| s.as_str()),
         )
         .await
         {
-            Ok(articles) => {
-                ctx.writer.write_all(RESP_224_OVERVIEW.as_bytes()).await?;
-                for (num, article) in articles {
-                    let overview_line = crate::overview::generate_overview_line(
-                        ctx.storage.as_ref(),
-                        num,
-                        &article,
-                    )
-                    .await?;
-                    ctx.writer
-                        .write_all(format!("{overview_line}\r\n").as_bytes())
-                        .await?;
+            Ok(values) => {
+                let mut text = String::new();
+                for (n, val) in values {
+                    match val {
+                        Some(v) => text.push_str(&format!("{n} {v}\r\n")),
+                        None => text.push_str(&format!("{n}\r\n")),
+                    }
                 }
+                let encoded = crate::compress::compress_overview(text.as_bytes())?;
+                ctx.writer
+                    .write_all(localize(RESP_225_COMPRESSED_HEADERS).as_bytes())
+                    .await?;
+                ctx.writer.write_all(&encoded).await?;
                 ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+                Ok(())
             }
             Err(error) => {
                 use super::utils::handle_article_error;
-                handle_article_error(&mut ctx.writer, error).await?;
+                handle_article_error(&mut ctx.writer, error).await
             }
         }
-        Ok(())
     }
 }
 
+/// Add `Xref` headers to articles listed in overview output.
+async fn add_xref_headers(
+    storage: &crate::storage::DynStorage,
+    config: &tokio::sync::RwLock<crate::config::Config>,
+    articles: &mut [(u64, crate::Message)],
+) -> anyhow::Result<()> {
+    let site_name = config.read().await.site_name.clone();
+    // One lookup for the whole range rather than one per article
+    let ids: Vec<String> = articles
+        .iter()
+        .filter_map(|(_, article)| get_header_value(article, "Message-ID"))
+        .collect();
+    let numbers = storage.get_article_numbers_batch(&ids).await?;
+    for (_, article) in articles {
+        article
+            .headers
+            .retain(|(k, _)| !k.eq_ignore_ascii_case("Xref"));
+        let xref = get_header_value(article, "Message-ID")
+            .and_then(|id| numbers.get(&id))
+            .and_then(|numbers| xref_value(&site_name, numbers));
+        if let Some(xref) = xref {
+            article.headers.push(("Xref".into(), xref));
+        }
+    }
+    Ok(())
+}
+
+/// Render the overview of the articles selected by `arg` as CRLF-terminated
+/// lines. Ranges in the current group are served from the storage's
+/// overview cache when it has one; entries are keyed by the group's
+/// high-water mark, so a range rendered before new articles arrived is not
+/// reused.
+async fn render_overview(
+    ctx: &mut HandlerContext,
+    arg: Option<&str>,
+) -> anyhow::Result<Result<String, ArticleQueryError>> {
+    let key = match (
+        ctx.storage.overview_cache(),
+        ctx.session.current_group(),
+        arg,
+    ) {
+        (Some(_), Some(group), Some(spec)) => overview_key(&ctx.storage, group, spec).await?,
+        _ => None,
+    };
+    if let Some(key) = &key
+        && let Some(cached) = ctx.storage.overview_cache().and_then(|c| c.get(key))
+    {
+        ctx.session.set_current_article(cached.last_article);
+        return Ok(Ok(cached.text.clone()));
+    }
+
+    let mut articles =
+        match resolve_articles(&ctx.storage, &ctx.config, &mut ctx.session, arg).await {
+            Ok(articles) => articles,
+            Err(error) => return Ok(Err(error)),
+        };
+    add_xref_headers(&ctx.storage, &ctx.config, &mut articles).await?;
+    let extra = ctx.config.read().await.extra_overview_headers();
+    let text = overview_text(&articles, &extra);
+    if let (Some(key), Some(cache), Some((last_article, _))) =
+        (key, ctx.storage.overview_cache(), articles.last())
+    {
+        let rendered = RenderedOverview {
+            text: text.clone(),
+            last_article: *last_article,
+        };
+        cache.insert(key, rendered);
+    }
+    Ok(Ok(text))
+}
+
+/// The overview cache key of the article number or range `spec` in
+/// `group`, or `None` if `spec` is not one.
+async fn overview_key(
+    storage: &crate::storage::DynStorage,
+    group: &str,
+    spec: &str,
+) -> anyhow::Result<Option<OverviewKey>> {
+    let (start, end) = match spec.split_once('-') {
+        Some((start, end)) => (start.parse().ok(), Some(end)),
+        None => (spec.parse().ok(), None),
+    };
+    let Some(start) = start else {
+        return Ok(None);
+    };
+    let high = storage
+        .get_group_watermarks(group)
+        .await?
+        .map_or(0, |marks| marks.high);
+    let end = match end {
+        None => start,
+        Some("") => high,
+        Some(end) => match end.parse() {
+            Ok(end) => end,
+            Err(_) => return Ok(None),
+        },
+    };
+    Ok(Some(OverviewKey {
+        group: group.to_string(),
+        start,
+        end,
+        high,
+    }))
+}
+
+/// Render overview lines for a set of articles as CRLF-terminated text,
+/// with the `extra` headers as further fields.
+fn overview_text(articles: &[(u64, crate::Message)], extra: &[String]) -> String {
+    let mut text = String::new();
+    for (num, article) in articles {
+        text.push_str(&crate::overview::format_overview_line(*num, article, extra));
+        text.push_str("\r\n");
+    }
+    text
+}
+
 /// Handle the special case of HDR with ":" for all headers.
 async fn handle_all_headers(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
     // Use the existing resolve_articles function to handle the complex logic
     let articles = match resolve_articles(
         &ctx.storage,
+        &ctx.config,
         &mut ctx.session,
         args.get(1).map(String::as_str),
     )
@@ -178,7 +589,9 @@ async fn handle_all_headers(ctx: &mut HandlerContext, args: &[String]) -> Handle
         }
     };
 
-    ctx.writer.write_all(RESP_225_HEADERS.as_bytes()).await?;
+    ctx.writer
+        .write_all(localize(RESP_225_HEADERS).as_bytes())
+        .await?;
     for (n, article) in articles {
         for (name, val) in &article.headers {
             let sanitized_val = sanitize_header_value(val);
@@ -198,43 +611,159 @@ fn sanitize_header_value(val: &str) -> String {
     v
 }
 
+/// Settings that metadata values depend on, read once per command.
+struct MetadataOptions {
+    site_name: String,
+    detect_binaries: bool,
+}
+
+impl MetadataOptions {
+    async fn load(config: &tokio::sync::RwLock<crate::config::Config>) -> Self {
+        let cfg = config.read().await;
+        Self {
+            site_name: cfg.site_name.clone(),
+            detect_binaries: cfg.detect_binaries,
+        }
+    }
+}
+
 /// Extract header value for a field (handles both standard headers and metadata).
 async fn get_field_value(
     storage: &crate::storage::DynStorage,
-    article: &crate::Message,
+    mut article: crate::Message,
     field: &str,
+    options: &MetadataOptions,
 ) -> Option<String> {
+    if field == ":bytes" {
+        // Count the Xref header ARTICLE would send, as OVER does
+        add_xref_header(storage, &options.site_name, &mut article)
+            .await
+            .ok()?;
+    }
     if field.starts_with(':') {
-        metadata_value(storage, article, field).await
+        metadata_value(&article, field, options.detect_binaries)
     } else {
-        get_header_value(article, field)
+        get_header_value(&article, field)
     }
 }
 
+/// Overview field holding `field`, for fields the overview stores: the
+/// standard headers, `:bytes`, `:lines` and the `extra` headers.
+fn overview_field(field: &str, extra: &[String]) -> Option<usize> {
+    const FIELDS: [&str; 7] = [
+        "Subject",
+        "From",
+        "Date",
+        "Message-ID",
+        "References",
+        ":bytes",
+        ":lines",
+    ];
+    // Field 0 is the article number and field 8 the Xref, which is added
+    // when the article is read
+    FIELDS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(field))
+        .map(|i| i + 1)
+        .or_else(|| {
+            extra
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(field))
+                .map(|i| i + 9)
+        })
+}
+
+/// Values of `field` for the articles numbered `nums` in `group`, read
+/// from the overview rather than from the articles, so that header scans
+/// of large ranges do not load every body.
+///
+/// Returns `None` when the overview does not store `field`, or a row does
+/// not have the configured fields, for example after `overview_headers`
+/// changed; the articles are read then.
+async fn overview_values(
+    storage: &crate::storage::DynStorage,
+    config: &tokio::sync::RwLock<crate::config::Config>,
+    group: &str,
+    nums: &[u64],
+    field: &str,
+    options: &MetadataOptions,
+) -> Option<Vec<(u64, Option<String>)>> {
+    let extra = config.read().await.extra_overview_headers();
+    let index = overview_field(field, &extra)?;
+    let (first, last) = (*nums.iter().min()?, *nums.iter().max()?);
+    let rows = storage.get_overview_range(group, first, last).await.ok()?;
+    let numbers = if field.eq_ignore_ascii_case(":bytes") {
+        // The groups of every article of the range, looked up at once
+        let ids: Vec<String> = rows
+            .iter()
+            .filter_map(|row| row.split('\t').nth(4).map(str::to_string))
+            .collect();
+        storage.get_article_numbers_batch(&ids).await.ok()?
+    } else {
+        std::collections::HashMap::new()
+    };
+
+    let mut values = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let fields: Vec<&str> = row.split('\t').collect();
+        if fields.len() != 9 + extra.len() {
+            return None;
+        }
+        let number: u64 = fields[0].parse().ok()?;
+        let value = fields[index];
+        let value = if index > 8 {
+            // Extra fields are stored as `Name: value`
+            let name = &extra[index - 9];
+            match value.get(..name.len() + 2) {
+                None if value.is_empty() => None,
+                Some(prefix) if prefix.eq_ignore_ascii_case(&format!("{name}: ")) => {
+                    Some(value[prefix.len()..].to_string())
+                }
+                _ => return None,
+            }
+        } else if field.eq_ignore_ascii_case(":bytes") {
+            // Count the Xref header ARTICLE would send for cross-posts
+            let bytes: u64 = value.parse().ok()?;
+            let xref = numbers
+                .get(fields[4])
+                .map_or(0, |numbers| xref_len(&options.site_name, numbers));
+            Some((bytes + xref).to_string())
+        } else {
+            (!value.is_empty()).then(|| value.to_string())
+        };
+        values.push((number, value));
+    }
+    Some(values)
+}
+
+/// Octets of the Xref header line ARTICLE adds to an article stored with
+/// `numbers`, or 0 if it is in one group only.
+fn xref_len(site_name: &str, numbers: &[(String, u64)]) -> u64 {
+    xref_value(site_name, numbers).map_or(0, |xref| {
+        crate::rewrite::fold_header("Xref", &xref).len() as u64 + 2
+    })
+}
+
 /// Collect header values for HDR/XPAT commands.
 async fn collect_header_values(
     storage: &crate::storage::DynStorage,
     session: &crate::session::Session,
+    config: &tokio::sync::RwLock<crate::config::Config>,
     field: &str,
     range_or_msgid: Option<&str>,
 ) -> std::result::Result<Vec<(u64, Option<String>)>, super::utils::ArticleQueryError> {
     use super::utils::ArticleQueryError;
 
+    let options = MetadataOptions::load(config).await;
+
     let mut values = Vec::new();
 
     if let Some(arg) = range_or_msgid {
         if arg.starts_with('<') && arg.ends_with('>') {
             // Message-ID lookup
-            if let Some(article) = storage
-                .get_article_by_id(arg)
-                .await
-                .map_err(|_| ArticleQueryError::MessageIdNotFound)?
-            {
-                let val = get_field_value(storage, &article, field).await;
-                values.push((0, val));
-            } else {
-                return Err(ArticleQueryError::MessageIdNotFound);
-            }
+            let article = article_by_id(storage, config, session, arg).await?;
+            let val = get_field_value(storage, article, field, &options).await;
+            values.push((0, val));
         } else if let Some(group) = session.current_group() {
             // Range lookup - check if it's an article number first
             let nums = crate::parse_range(storage, group, arg)
@@ -245,13 +774,22 @@ async fn collect_header_values(
                 return Err(ArticleQueryError::RangeEmpty);
             }
 
+            if let Some(found) =
+                overview_values(storage, config, group, &nums, field, &options).await
+            {
+                if found.is_empty() {
+                    return Err(ArticleQueryError::NotFoundByNumber);
+                }
+                return Ok(found);
+            }
+
             for n in nums {
                 if let Some(article) = storage
                     .get_article_by_number(group, n)
                     .await
                     .map_err(|_| ArticleQueryError::NotFoundByNumber)?
                 {
-                    let val = get_field_value(storage, &article, field).await;
+                    let val = get_field_value(storage, article, field, &options).await;
                     values.push((n, val));
                 }
             }
@@ -279,7 +817,7 @@ async fn collect_header_values(
             .await
             .map_err(|_| ArticleQueryError::NoCurrentArticle)?
         {
-            let val = get_field_value(storage, &article, field).await;
+            let val = get_field_value(storage, article, field, &options).await;
             values.push((num, val));
         } else {
             return Err(ArticleQueryError::NoCurrentArticle);
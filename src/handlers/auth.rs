@@ -2,9 +2,12 @@
 
 use super::utils::write_simple;
 use super::{CommandHandler, HandlerContext, HandlerResult};
-use crate::error::AuthError;
-use crate::limits::LimitCheckResult;
+use crate::audit::{AuditAction, AuditEntry};
+use crate::auth::throttle;
+use crate::error::{AuthError, HandlerError};
 use crate::responses::*;
+use crate::session::OverviewCompression;
+use std::time::Duration;
 use tracing::Span;
 
 /// Handler for the AUTHINFO command.
@@ -15,7 +18,16 @@ impl CommandHandler for AuthInfoHandler {
         // Reject authentication on insecure connections unless explicitly allowed
         if !ctx.session.can_authenticate() {
             Span::current().record("outcome", "rejected_insecure");
-            write_simple(&mut ctx.writer, RESP_483_SECURE_REQ).await?;
+            if ctx.session.tls_required() {
+                let response = with_tls_hint(
+                    RESP_483_SECURE_REQ,
+                    ctx.session.tls_port(),
+                    ctx.session.can_starttls(),
+                );
+                write_simple(&mut ctx.writer, &response).await?;
+            } else {
+                write_simple(&mut ctx.writer, RESP_483_SECURE_REQ).await?;
+            }
             return Ok(());
         }
 
@@ -41,32 +53,24 @@ impl CommandHandler for AuthInfoHandler {
 
                 if let Some(username) = ctx.session.pending_username() {
                     let username = username.to_string(); // Clone to avoid borrow issues
+                    if let Some(retry) = locked_out(ctx, &username).await {
+                        // The password is not even checked while locked out
+                        Span::current().record("outcome", "rejected_locked_out");
+                        let response = with_retry(RESP_481_LOCKED_OUT, retry);
+                        write_simple(&mut ctx.writer, &response).await?;
+                        return Ok(());
+                    }
                     if ctx.auth.verify_user(&username, &args[1]).await? {
-                        // Check if user is admin
-                        let is_admin = ctx.auth.is_admin(&username).await.unwrap_or(false);
-
-                        // Check connection limits (admins bypass)
-                        if !is_admin {
-                            let limit_result = ctx.usage_tracker.try_connect(&username).await;
-                            if limit_result == LimitCheckResult::ConnectionLimitExceeded {
-                                Span::current().record("outcome", "rejected_connection_limit");
-                                tracing::debug!(username = %username, "Connection limit exceeded");
-                                write_simple(&mut ctx.writer, RESP_481_CONN_LIMIT).await?;
-                                return Ok(());
-                            }
-
-                            // Load usage data from database for this user
-                            if let Err(e) = ctx.usage_tracker.load_user(&username).await {
-                                tracing::warn!(username = %username, error = %e, "Failed to load user usage");
-                            }
+                        if !log_in(ctx, &username).await {
+                            Span::current().record("outcome", "rejected_connection_limit");
+                            write_simple(&mut ctx.writer, RESP_502_CONN_LIMIT).await?;
+                            return Ok(());
+                        }
+                        let ip = ctx.session.peer_ip();
+                        if let Err(e) = throttle::clear(&*ctx.auth, &username, ip).await {
+                            tracing::warn!(error = %e, "Failed to clear failed logins");
                         }
-
-                        // Set session state with admin status
-                        ctx.session
-                            .authenticate_with_admin(username.clone(), is_admin);
                         Span::current().record("outcome", "success");
-                        // Log username only at debug level for GDPR compliance
-                        tracing::debug!(username = %username, is_admin = is_admin, "User authenticated");
                         write_simple(&mut ctx.writer, RESP_281_AUTH_OK).await?;
                     } else {
                         let err = AuthError::InvalidCredentials(username.clone());
@@ -74,6 +78,12 @@ impl CommandHandler for AuthInfoHandler {
                         tracing::info!("Authentication failed");
                         tracing::debug!(username = %username, error = %err, "Authentication failed details");
                         Span::current().record("outcome", "rejected_invalid");
+                        let (delay, banned) = count_failure(ctx, &username).await;
+                        if banned {
+                            write_simple(&mut ctx.writer, RESP_502_ADDRESS_BANNED).await?;
+                            return Err(HandlerError::Quit.into());
+                        }
+                        tokio::time::sleep(delay).await;
                         write_simple(&mut ctx.writer, RESP_481_AUTH_REJECTED).await?;
                     }
                 } else {
@@ -89,7 +99,123 @@ impl CommandHandler for AuthInfoHandler {
     }
 }
 
+/// How long logins as `username` from the client address stay locked out,
+/// if they are.
+async fn locked_out(ctx: &mut HandlerContext, username: &str) -> Option<Duration> {
+    let now = chrono::Utc::now().timestamp();
+    let ip = ctx.session.peer_ip();
+    match throttle::locked_until(&*ctx.auth, username, ip, now).await {
+        Ok(until) => until.map(|until| Duration::from_secs((until - now).unsigned_abs())),
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to read failed logins");
+            None
+        }
+    }
+}
+
+/// Count a failed login as `username` against the user and the client
+/// address, returning how long to hold back the answer and whether the
+/// address has now been banned.
+///
+/// The failure is recorded once for the address, and both the login
+/// throttle and the address bans count from that record.
+async fn count_failure(ctx: &mut HandlerContext, username: &str) -> (Duration, bool) {
+    let (throttle_cfg, ban_cfg) = {
+        let cfg = ctx.config.read().await;
+        (cfg.login_throttle.clone(), cfg.address_bans.clone())
+    };
+    let now = chrono::Utc::now().timestamp();
+    let ip = ctx.session.peer_ip();
+    let access = ctx.usage_tracker.access().clone();
+    if let Some(ip) = ip {
+        // Keep the failures as long as either counts them
+        let keep = throttle_cfg
+            .failure_window
+            .zip(ban_cfg.auth_failure_window)
+            .map(|(throttle, ban)| throttle.max(ban));
+        if let Err(e) = access.record_auth_failure(ip, keep, now).await {
+            tracing::warn!(error = %e, "Failed to record failed login");
+        }
+    }
+    let delay = match throttle::record_failure(&*ctx.auth, &throttle_cfg, username, ip, now).await {
+        Ok(delay) => delay,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to count failed login");
+            Duration::ZERO
+        }
+    };
+    let banned = match ip {
+        Some(ip) => access
+            .ban_after_failures(ip, &ban_cfg, now)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Failed to check address ban");
+                false
+            }),
+        None => false,
+    };
+    (delay, banned)
+}
+
+/// Authenticate the session as `username`, whose credentials have been
+/// checked, and load their usage.
+///
+/// Returns false if the user has reached their connection limit; admins
+/// bypass the limit.
+async fn log_in(ctx: &mut HandlerContext, username: &str) -> bool {
+    let is_admin = ctx.auth.is_admin(username).await.unwrap_or(false);
+    if !is_admin {
+        let Some(slot) = ctx.usage_tracker.try_connect(username).await else {
+            tracing::debug!(username = %username, "Connection limit exceeded");
+            return false;
+        };
+        ctx.session.hold_user_slot(slot);
+
+        // Load usage data from database for this user
+        if let Err(e) = ctx.usage_tracker.load_user(username).await {
+            tracing::warn!(username = %username, error = %e, "Failed to load user usage");
+        }
+    }
+
+    ctx.session
+        .authenticate_with_admin(username.to_string(), is_admin);
+    // Log username only at debug level for GDPR compliance
+    tracing::debug!(username = %username, is_admin = is_admin, "User authenticated");
+    true
+}
+
+/// Authenticate the session by a verified TLS client certificate.
+///
+/// `names` are the certificate's names in order of preference; the first
+/// that is a known user is logged in. A client whose certificate names no
+/// user, or who is over their connection limit, stays unauthenticated and
+/// may still use AUTHINFO.
+pub(crate) async fn authenticate_certificate(ctx: &mut HandlerContext, names: &[String]) {
+    if ctx.session.is_authenticated() {
+        return;
+    }
+    for name in names {
+        match ctx.auth.user_exists(name).await {
+            Ok(true) => {
+                log_in(ctx, name).await;
+                return;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to look up client certificate user");
+                return;
+            }
+        }
+    }
+    if !names.is_empty() {
+        tracing::debug!(names = ?names, "Client certificate names no known user");
+    }
+}
+
 /// Handler for the MODE command.
+///
+/// `MODE READER` is refused on transit listeners and `MODE STREAM` on
+/// reader listeners, as the commands they lead to are.
 pub struct ModeHandler;
 
 impl CommandHandler for ModeHandler {
@@ -99,7 +225,17 @@ impl CommandHandler for ModeHandler {
             return Ok(());
         }
 
+        let role = ctx.session.role();
         match args[0].to_ascii_uppercase().as_str() {
+            "READER" if !role.permits("ARTICLE") => {
+                write_simple(&mut ctx.writer, RESP_502_WRONG_LISTENER).await?;
+            }
+            "STREAM" if !role.permits("CHECK") => {
+                write_simple(&mut ctx.writer, RESP_502_WRONG_LISTENER).await?;
+            }
+            "STREAM" if !ctx.session.may_feed() => {
+                write_simple(&mut ctx.writer, RESP_480_AUTH_REQUIRED).await?;
+            }
             "READER" => {
                 if ctx.session.can_post() {
                     write_simple(&mut ctx.writer, RESP_200_POSTING_ALLOWED).await?;
@@ -110,6 +246,14 @@ impl CommandHandler for ModeHandler {
             "STREAM" => {
                 write_simple(&mut ctx.writer, RESP_203_STREAMING).await?;
             }
+            "CANCEL" => {
+                if !ctx.session.is_admin() {
+                    write_simple(&mut ctx.writer, RESP_502_ADMIN_REQUIRED).await?;
+                    return Ok(());
+                }
+                ctx.session.enter_cancel_mode();
+                write_simple(&mut ctx.writer, RESP_284_CANCEL_MODE).await?;
+            }
             _ => {
                 write_simple(&mut ctx.writer, RESP_501_UNKNOWN_MODE).await?;
             }
@@ -117,3 +261,47 @@ impl CommandHandler for ModeHandler {
         Ok(())
     }
 }
+
+/// Cancel the article named by one line received in cancel mode.
+///
+/// `MODE CANCEL` lets an administrator, such as an external spam filter,
+/// remove articles by Message-ID without composing control messages. Locks
+/// on the articles do not apply, as with signed administrative cancels.
+pub(crate) async fn cancel_article(ctx: &mut HandlerContext, message_id: &str) -> HandlerResult {
+    let message_id = message_id.trim();
+    if !super::args::is_message_id(message_id) {
+        return write_simple(&mut ctx.writer, RESP_484_INVALID_ID).await;
+    }
+    if ctx.storage.get_message_size(message_id).await?.is_none() {
+        return write_simple(&mut ctx.writer, RESP_484_NO_ARTICLE).await;
+    }
+    ctx.storage.delete_article_by_id(message_id).await?;
+    tracing::info!(message_id = message_id, "Article cancelled");
+    crate::audit::record(
+        &*ctx.storage,
+        AuditEntry::new(AuditAction::Cancel, message_id).by_session(&ctx.session),
+    )
+    .await;
+    write_simple(&mut ctx.writer, RESP_289_CANCELLED).await
+}
+
+/// Handler for the XFEATURE command.
+///
+/// Only `XFEATURE COMPRESS GZIP [TERMINATOR]` is supported; it enables zlib
+/// compression of subsequent OVER/XOVER responses for this session.
+pub struct XFeatureHandler;
+
+impl CommandHandler for XFeatureHandler {
+    async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
+        let words: Vec<String> = args.iter().map(|a| a.to_ascii_uppercase()).collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let mode = match words.as_slice() {
+            ["COMPRESS", "GZIP"] => OverviewCompression::Gzip,
+            ["COMPRESS", "GZIP", "TERMINATOR"] => OverviewCompression::GzipTerminator,
+            [] => return write_simple(&mut ctx.writer, RESP_501_NOT_ENOUGH).await,
+            _ => return write_simple(&mut ctx.writer, RESP_503_NOT_SUPPORTED).await,
+        };
+        ctx.session.set_overview_compression(mode);
+        write_simple(&mut ctx.writer, RESP_290_FEATURE_ENABLED).await
+    }
+}
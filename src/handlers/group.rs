@@ -1,10 +1,12 @@
 //! Group and listing command handlers.
 
-use super::utils::{write_lines, write_simple};
+use super::utils::{session_read_access, write_lines, write_simple};
 use super::{CommandHandler, HandlerContext, HandlerResult};
 use crate::error::StorageError;
+use crate::peers::PeerDb;
 use crate::responses::*;
-use crate::{parse_datetime, wildmat};
+use crate::storage::GroupWatermarks;
+use crate::{parse_datetime_at, wildmat};
 use futures_util::{StreamExt, TryStreamExt};
 use tokio::io::AsyncWriteExt;
 use tracing::Span;
@@ -17,30 +19,35 @@ impl CommandHandler for GroupHandler {
         if let Some(group_name) = args.first() {
             Span::current().record("group", group_name.as_str());
 
-            // Check if the group exists using the storage interface
-            if !ctx.storage.group_exists(group_name).await? {
+            // Check if the group exists using the storage interface; groups
+            // the client may not read are treated as missing
+            let readable =
+                session_read_access(&*ctx.config.read().await, &ctx.session).allows(group_name);
+            let marks = if readable {
+                ctx.storage.get_group_watermarks(group_name).await?
+            } else {
+                None
+            };
+            let Some(marks) = marks else {
                 let err = StorageError::GroupNotFound(group_name.clone());
                 tracing::debug!(error = %err, "Group lookup failed");
                 Span::current().record("outcome", "not_found");
                 write_simple(&mut ctx.writer, RESP_411_NO_SUCH_GROUP).await?;
                 return Ok(());
-            }
-
-            let stream = ctx.storage.list_article_numbers(group_name);
-            let nums = stream.try_collect::<Vec<u64>>().await?;
-            let count = nums.len();
-            let high = nums.last().copied().unwrap_or(0);
-            let low = nums.first().copied().unwrap_or(0);
+            };
 
             ctx.session
-                .select_group(group_name.clone(), nums.first().copied());
+                .select_group(group_name.clone(), first_article(&marks));
 
-            Span::current().record("article_count", count as u64);
+            Span::current().record("article_count", marks.count);
             Span::current().record("outcome", "success");
 
             write_simple(
                 &mut ctx.writer,
-                &format!("211 {count} {low} {high} {group_name}\r\n"),
+                &format!(
+                    "211 {} {} {} {group_name}\r\n",
+                    marks.count, marks.low, marks.high
+                ),
             )
             .await?;
         } else {
@@ -60,8 +67,14 @@ impl CommandHandler for ListHandler {
                 "ACTIVE" => {
                     handle_list_active(ctx, args.get(1)).await?;
                 }
+                "COUNTS" => {
+                    handle_list_counts(ctx, args.get(1)).await?;
+                }
                 "NEWSGROUPS" => {
-                    handle_list_newsgroups(ctx).await?;
+                    handle_list_newsgroups(ctx, args.get(1)).await?;
+                }
+                "SUBSCRIPTIONS" => {
+                    handle_list_subscriptions(ctx).await?;
                 }
                 "ACTIVE.TIMES" => {
                     handle_list_active_times(ctx).await?;
@@ -72,6 +85,18 @@ impl CommandHandler for ListHandler {
                 "HEADERS" => {
                     handle_list_headers(ctx).await?;
                 }
+                "PINNED" => {
+                    handle_list_pinned(ctx, args.get(1)).await?;
+                }
+                "EXPIRE" => {
+                    handle_list_expire(ctx, args.get(1)).await?;
+                }
+                "ACTIVITY" => {
+                    handle_list_activity(ctx, args.get(1)).await?;
+                }
+                "PEERS" => {
+                    handle_list_peers(ctx).await?;
+                }
                 "DISTRIB.PATS" => {
                     write_simple(&mut ctx.writer, RESP_503_NOT_SUPPORTED).await?;
                 }
@@ -100,8 +125,25 @@ impl CommandHandler for ListGroupHandler {
             write_simple(&mut ctx.writer, RESP_412_NO_GROUP).await?;
             return Ok(());
         };
+        let readable =
+            session_read_access(&*ctx.config.read().await, &ctx.session).allows(&group_name);
+        let marks = if readable {
+            ctx.storage.get_group_watermarks(&group_name).await?
+        } else {
+            None
+        };
+        let Some(marks) = marks else {
+            write_simple(&mut ctx.writer, RESP_411_NO_SUCH_GROUP).await?;
+            return Ok(());
+        };
 
-        write_simple(&mut ctx.writer, RESP_211_LISTGROUP).await?;
+        ctx.session
+            .select_group(group_name.clone(), first_article(&marks));
+        write_simple(
+            &mut ctx.writer,
+            &listgroup_response(marks.count, marks.low, marks.high, &group_name),
+        )
+        .await?;
         let mut stream = ctx.storage.list_article_numbers(&group_name);
         while let Some(result) = stream.next().await {
             let num = result?;
@@ -131,6 +173,39 @@ impl CommandHandler for LastHandler {
     }
 }
 
+/// Read the `date time [GMT]` arguments of NEWGROUPS and NEWNEWS. Invalid
+/// arguments, and dates further ahead of the server's clock than
+/// `clock_skew_secs`, are answered with 501 and give `None`, so clients with
+/// a wrong clock are told instead of receiving empty lists.
+async fn since_arg(
+    ctx: &mut HandlerContext,
+    args: &[String],
+) -> anyhow::Result<Option<chrono::DateTime<chrono::Utc>>> {
+    let gmt = match args.get(2) {
+        Some(arg) if arg.eq_ignore_ascii_case("GMT") => true,
+        Some(_) => {
+            write_simple(&mut ctx.writer, RESP_501_INVALID_ARG).await?;
+            return Ok(None);
+        }
+        None => false,
+    };
+    let now = chrono::Utc::now();
+    let Ok(since) = parse_datetime_at(&args[0], &args[1], gmt, now) else {
+        write_simple(&mut ctx.writer, RESP_501_INVALID_DATE).await?;
+        return Ok(None);
+    };
+    let skew = ctx.config.read().await.clock_skew_secs;
+    let skew = chrono::Duration::seconds(i64::try_from(skew).unwrap_or(i64::MAX));
+    if now
+        .checked_add_signed(skew)
+        .is_some_and(|limit| since > limit)
+    {
+        write_simple(&mut ctx.writer, RESP_501_FUTURE_DATE).await?;
+        return Ok(None);
+    }
+    Ok(Some(since))
+}
+
 /// Handler for the NEWGROUPS command.
 pub struct NewGroupsHandler;
 
@@ -141,27 +216,18 @@ impl CommandHandler for NewGroupsHandler {
             return Ok(());
         }
 
-        let date = &args[0];
-        let time = &args[1];
-        let gmt = match args.get(2) {
-            Some(arg) => {
-                if !arg.eq_ignore_ascii_case("GMT") {
-                    write_simple(&mut ctx.writer, RESP_501_INVALID_ARG).await?;
-                    return Ok(());
-                }
-                true
-            }
-            None => false,
-        };
-        let Ok(since) = parse_datetime(date, time, gmt) else {
-            write_simple(&mut ctx.writer, RESP_501_INVALID_DATE).await?;
+        let Some(since) = since_arg(ctx, args).await? else {
             return Ok(());
         };
 
+        let access = session_read_access(&*ctx.config.read().await, &ctx.session);
         write_simple(&mut ctx.writer, RESP_231_NEWGROUPS).await?;
         let mut stream = ctx.storage.list_groups_since(since);
         while let Some(result) = stream.next().await {
             let group = result?;
+            if !access.allows(&group) {
+                continue;
+            }
             ctx.writer.write_all(group.as_bytes()).await?;
             ctx.writer.write_all(b"\r\n").await?;
         }
@@ -181,28 +247,16 @@ impl CommandHandler for NewNewsHandler {
         }
 
         let wildmat_pattern = &args[0];
-        let date = &args[1];
-        let time = &args[2];
-        let gmt = match args.get(3) {
-            Some(arg) => {
-                if !arg.eq_ignore_ascii_case("GMT") {
-                    write_simple(&mut ctx.writer, RESP_501_INVALID_ARG).await?;
-                    return Ok(());
-                }
-                true
-            }
-            None => false,
-        };
-        let Ok(since) = parse_datetime(date, time, gmt) else {
-            write_simple(&mut ctx.writer, RESP_501_INVALID_DATE).await?;
+        let Some(since) = since_arg(ctx, &args[1..]).await? else {
             return Ok(());
         };
 
+        let access = session_read_access(&*ctx.config.read().await, &ctx.session);
         write_simple(&mut ctx.writer, RESP_230_NEWNEWS).await?;
         let mut groups_stream = ctx.storage.list_groups();
         while let Some(result) = groups_stream.next().await {
             let group = result?;
-            if wildmat::wildmat(&group, wildmat_pattern) {
+            if wildmat::wildmat(wildmat_pattern, &group) && access.allows(&group) {
                 let mut articles_stream = ctx.storage.list_article_ids_since(&group, since);
                 while let Some(article_result) = articles_stream.next().await {
                     let article_id = article_result?;
@@ -217,51 +271,171 @@ impl CommandHandler for NewNewsHandler {
     }
 }
 
+/// The article a newly selected group starts at: its first, if it has any.
+fn first_article(marks: &GroupWatermarks) -> Option<u64> {
+    (marks.count > 0).then_some(marks.low)
+}
+
 // Helper functions for LIST subcommands
 
 async fn handle_list_active(ctx: &mut HandlerContext, pattern: Option<&String>) -> HandlerResult {
+    write_group_counts(ctx, pattern, false).await
+}
+
+/// `LIST COUNTS [wildmat]`: like `LIST ACTIVE`, with the number of articles
+/// in each group before its status (RFC 6048).
+async fn handle_list_counts(ctx: &mut HandlerContext, pattern: Option<&String>) -> HandlerResult {
+    write_group_counts(ctx, pattern, true).await
+}
+
+/// One `group high low [count] status` line per readable group, from a
+/// single storage query for the counts. Archived groups have the status
+/// `n`, as posting to them is not allowed.
+async fn write_group_counts(
+    ctx: &mut HandlerContext,
+    pattern: Option<&String>,
+    with_count: bool,
+) -> HandlerResult {
+    let access = session_read_access(&*ctx.config.read().await, &ctx.session);
+    let archived: Vec<String> = ctx.storage.list_archived_groups().try_collect().await?;
     write_simple(&mut ctx.writer, RESP_215_LIST_FOLLOWS).await?;
-    let mut groups_stream = ctx.storage.list_groups();
+    let mut groups_stream = ctx.storage.list_groups_with_counts();
     while let Some(result) = groups_stream.next().await {
-        let group = result?;
-        if let Some(pat) = pattern
-            && !wildmat::wildmat(&group, pat)
-        {
+        let (group, marks, moderated) = result?;
+        if !access.allows(&group) || pattern.is_some_and(|pat| !wildmat::wildmat(pat, &group)) {
             continue;
         }
 
-        let mut nums_stream = ctx.storage.list_article_numbers(&group);
-        let mut low = None;
-        let mut high = None;
+        let mut line = format!("{group} {} {}", marks.high, marks.low);
+        if with_count {
+            line.push_str(&format!(" {}", marks.count));
+        }
+        line.push_str(if archived.contains(&group) {
+            " n\r\n"
+        } else if moderated {
+            " m\r\n"
+        } else {
+            " y\r\n"
+        });
+        ctx.writer.write_all(line.as_bytes()).await?;
+    }
 
-        while let Some(result) = nums_stream.next().await {
-            let num = result?;
-            if low.is_none() {
-                low = Some(num);
-            }
-            high = Some(num);
+    ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+    Ok(())
+}
+
+/// `LIST PINNED [wildmat]`: one `group number message-id` line per pinned
+/// article.
+async fn handle_list_pinned(ctx: &mut HandlerContext, pattern: Option<&String>) -> HandlerResult {
+    let access = session_read_access(&*ctx.config.read().await, &ctx.session);
+    write_simple(&mut ctx.writer, RESP_215_PINNED).await?;
+    let mut stream = ctx.storage.list_pinned_articles();
+    while let Some(result) = stream.next().await {
+        let (group, number, message_id) = result?;
+        if !access.allows(&group) || pattern.is_some_and(|pat| !wildmat::wildmat(pat, &group)) {
+            continue;
         }
+        ctx.writer
+            .write_all(format!("{group} {number} {message_id}\r\n").as_bytes())
+            .await?;
+    }
+    ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+    Ok(())
+}
 
-        let low = low.unwrap_or(0);
-        let high = high.unwrap_or(0);
+/// `LIST EXPIRE [wildmat]`: one `group days` line per group, giving how
+/// many days retention keeps its articles, or `never` when it keeps them
+/// indefinitely.
+async fn handle_list_expire(ctx: &mut HandlerContext, pattern: Option<&String>) -> HandlerResult {
+    let access = session_read_access(&*ctx.config.read().await, &ctx.session);
+    let mut groups = Vec::new();
+    let mut groups_stream = ctx.storage.list_groups();
+    while let Some(result) = groups_stream.next().await {
+        let group = result?;
+        if access.allows(&group) && pattern.is_none_or(|pat| wildmat::wildmat(pat, &group)) {
+            groups.push(group);
+        }
+    }
+    drop(groups_stream);
+
+    let lines: Vec<String> = {
+        let cfg = ctx.config.read().await;
+        groups
+            .into_iter()
+            .map(|group| match cfg.retention_for_group(&group) {
+                Some(retention) => format!("{group} {}\r\n", retention.num_days()),
+                None => format!("{group} never\r\n"),
+            })
+            .collect()
+    };
+    write_simple(&mut ctx.writer, RESP_215_EXPIRE).await?;
+    for line in lines {
+        ctx.writer.write_all(line.as_bytes()).await?;
+    }
+    ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+    Ok(())
+}
 
-        ctx.writer.write_all(group.as_bytes()).await?;
-        ctx.writer.write_all(b" ").await?;
-        ctx.writer.write_all(high.to_string().as_bytes()).await?;
-        ctx.writer.write_all(b" ").await?;
-        ctx.writer.write_all(low.to_string().as_bytes()).await?;
-        ctx.writer.write_all(b" y\r\n").await?;
+/// `LIST ACTIVITY [wildmat]`: one `group last-post posts` line per group,
+/// giving the Unix time of its last article, or 0 if it never had one, and
+/// how many articles it has received. The most recently posted to groups
+/// come first.
+async fn handle_list_activity(ctx: &mut HandlerContext, pattern: Option<&String>) -> HandlerResult {
+    let access = session_read_access(&*ctx.config.read().await, &ctx.session);
+    write_simple(&mut ctx.writer, RESP_215_ACTIVITY).await?;
+    let mut stream = ctx.storage.list_group_activity();
+    while let Some(result) = stream.next().await {
+        let (group, activity) = result?;
+        if !access.allows(&group) || pattern.is_some_and(|pat| !wildmat::wildmat(pat, &group)) {
+            continue;
+        }
+        ctx.writer
+            .write_all(
+                format!(
+                    "{group} {} {}\r\n",
+                    activity.last_post.unwrap_or(0),
+                    activity.posts
+                )
+                .as_bytes(),
+            )
+            .await?;
     }
+    ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+    Ok(())
+}
 
+/// `LIST PEERS`: what each peer was offered and how it answered, for
+/// administrators.
+async fn handle_list_peers(ctx: &mut HandlerContext) -> HandlerResult {
+    if !ctx.session.is_admin() {
+        write_simple(&mut ctx.writer, RESP_502_ADMIN_REQUIRED).await?;
+        return Ok(());
+    }
+    let path = ctx.config.read().await.peer_db_path.clone();
+    let stats = PeerDb::new(&path).await?.peer_stats().await?;
+    write_simple(&mut ctx.writer, RESP_215_PEERS).await?;
+    for peer in stats {
+        ctx.writer
+            .write_all(format!("{peer}\r\n").as_bytes())
+            .await?;
+    }
     ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
     Ok(())
 }
 
-async fn handle_list_newsgroups(ctx: &mut HandlerContext) -> HandlerResult {
+/// `LIST NEWSGROUPS [wildmat]`: each group with its description.
+async fn handle_list_newsgroups(
+    ctx: &mut HandlerContext,
+    pattern: Option<&String>,
+) -> HandlerResult {
+    let access = session_read_access(&*ctx.config.read().await, &ctx.session);
     write_simple(&mut ctx.writer, RESP_215_DESCRIPTIONS).await?;
     let mut groups_stream = ctx.storage.list_groups_with_descriptions();
     while let Some(result) = groups_stream.next().await {
         let (group, description) = result?;
+        if !access.allows(&group) || pattern.is_some_and(|pat| !wildmat::wildmat(pat, &group)) {
+            continue;
+        }
         ctx.writer
             .write_all(format!("{group} {description}\r\n").as_bytes())
             .await?;
@@ -270,11 +444,40 @@ async fn handle_list_newsgroups(ctx: &mut HandlerContext) -> HandlerResult {
     Ok(())
 }
 
+/// `LIST SUBSCRIPTIONS` (RFC 6048): the carried groups matching
+/// `default_subscriptions`, recommended to new readers.
+async fn handle_list_subscriptions(ctx: &mut HandlerContext) -> HandlerResult {
+    let (access, patterns) = {
+        let cfg = ctx.config.read().await;
+        (
+            session_read_access(&cfg, &ctx.session),
+            cfg.default_subscriptions.clone(),
+        )
+    };
+    write_simple(&mut ctx.writer, RESP_215_SUBSCRIPTIONS).await?;
+    let mut groups_stream = ctx.storage.list_groups();
+    while let Some(result) = groups_stream.next().await {
+        let group = result?;
+        if !access.allows(&group) || !patterns.iter().any(|pat| wildmat::wildmat(pat, &group)) {
+            continue;
+        }
+        ctx.writer
+            .write_all(format!("{group}\r\n").as_bytes())
+            .await?;
+    }
+    ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+    Ok(())
+}
+
 async fn handle_list_active_times(ctx: &mut HandlerContext) -> HandlerResult {
+    let access = session_read_access(&*ctx.config.read().await, &ctx.session);
     write_simple(&mut ctx.writer, RESP_215_INFO_FOLLOWS).await?;
     let mut stream = ctx.storage.list_groups_with_times();
     while let Some(result) = stream.next().await {
         let (group, time) = result?;
+        if !access.allows(&group) {
+            continue;
+        }
         ctx.writer
             .write_all(format!("{group} {time} -\r\n").as_bytes())
             .await?;
@@ -288,10 +491,11 @@ async fn handle_list_overview_fmt(ctx: &mut HandlerContext) -> HandlerResult {
     use crate::overview::get_overview_format_lines;
 
     ctx.writer
-        .write_all(RESP_215_OVERVIEW_FMT.as_bytes())
+        .write_all(localize(RESP_215_OVERVIEW_FMT).as_bytes())
         .await?;
 
-    let format_lines = get_overview_format_lines();
+    let extra = ctx.config.read().await.extra_overview_headers();
+    let format_lines = get_overview_format_lines(&extra);
     for line in format_lines {
         ctx.writer.write_all(line.as_bytes()).await?;
     }
@@ -301,17 +505,12 @@ async fn handle_list_overview_fmt(ctx: &mut HandlerContext) -> HandlerResult {
 }
 
 async fn handle_list_headers(ctx: &mut HandlerContext) -> HandlerResult {
-    write_lines(
-        &mut ctx.writer,
-        &[
-            RESP_215_METADATA,
-            RESP_COLON,
-            RESP_LINES,
-            RESP_BYTES,
-            RESP_DOT_CRLF,
-        ],
-    )
-    .await
+    let mut lines = vec![RESP_215_METADATA, RESP_COLON, RESP_LINES, RESP_BYTES];
+    if ctx.config.read().await.detect_binaries {
+        lines.push(RESP_BINARY);
+    }
+    lines.push(RESP_DOT_CRLF);
+    write_lines(&mut ctx.writer, &lines).await
 }
 
 /// Navigate to the next or previous article in the current group.
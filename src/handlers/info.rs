@@ -2,6 +2,9 @@
 
 use super::utils::write_simple;
 use super::{CommandHandler, HandlerContext, HandlerResult};
+use crate::error::HandlerError;
+use crate::limits::LimitCheckResult;
+use crate::posting_account::is_banned;
 use crate::responses::*;
 use tokio::io::AsyncWriteExt;
 
@@ -23,49 +26,117 @@ pub struct HelpHandler;
 impl CommandHandler for HelpHandler {
     async fn handle(ctx: &mut HandlerContext, _args: &[String]) -> HandlerResult {
         ctx.writer
-            .write_all(RESP_100_HELP_FOLLOWS.as_bytes())
+            .write_all(localize(RESP_100_HELP_FOLLOWS).as_bytes())
             .await?;
-        ctx.writer.write_all(RESP_HELP_TEXT.as_bytes()).await?;
+        ctx.writer.write_all(help_text().as_bytes()).await?;
         ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
         Ok(())
     }
 }
 
 /// Handler for the CAPABILITIES command.
+///
+/// The list describes what this session may do right now (RFC 3977 section
+/// 5.2), so it changes as the client authenticates or negotiates TLS.
 pub struct CapabilitiesHandler;
 
 impl CommandHandler for CapabilitiesHandler {
     async fn handle(ctx: &mut HandlerContext, _args: &[String]) -> HandlerResult {
+        let capabilities = capabilities(ctx).await;
         ctx.writer
-            .write_all(RESP_101_CAPABILITIES.as_bytes())
+            .write_all(localize(RESP_101_CAPABILITIES).as_bytes())
             .await?;
-        ctx.writer.write_all(RESP_CAP_VERSION.as_bytes()).await?;
-        ctx.writer
-            .write_all(RESP_CAP_IMPLEMENTATION.as_bytes())
-            .await?;
-        ctx.writer.write_all(RESP_CAP_READER.as_bytes()).await?;
-
-        // Show POST capability only if user can currently post
-        if ctx.session.can_post() {
-            ctx.writer.write_all(RESP_CAP_POST.as_bytes()).await?;
+        let max_over_range = ctx.config.read().await.max_over_range;
+        let over = capabilities.contains(&RESP_CAP_OVER);
+        for capability in capabilities {
+            ctx.writer.write_all(capability.as_bytes()).await?;
         }
-
-        // Show AUTHINFO capability only if auth is available and user not yet authenticated
-        if ctx.session.can_authenticate() && !ctx.session.is_authenticated() {
-            ctx.writer.write_all(RESP_CAP_AUTHINFO.as_bytes()).await?;
+        if let Some(max) = max_over_range.filter(|_| over) {
+            let line = over_page_capability(max);
+            ctx.writer.write_all(line.as_bytes()).await?;
+        }
+        if ctx.session.tls_required() {
+            let line = tls_required_capability(ctx.session.tls_port());
+            ctx.writer.write_all(line.as_bytes()).await?;
         }
-
-        ctx.writer.write_all(RESP_CAP_NEWNEWS.as_bytes()).await?;
-        ctx.writer.write_all(RESP_CAP_IHAVE.as_bytes()).await?;
-        ctx.writer.write_all(RESP_CAP_STREAMING.as_bytes()).await?;
-        ctx.writer.write_all(RESP_CAP_OVER.as_bytes()).await?;
-        ctx.writer.write_all(RESP_CAP_HDR.as_bytes()).await?;
-        ctx.writer.write_all(RESP_CAP_LIST.as_bytes()).await?;
         ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
         Ok(())
     }
 }
 
+/// Capability lines advertised to the session of `ctx`.
+async fn capabilities(ctx: &mut HandlerContext) -> Vec<&'static str> {
+    // Only advertise what the accepting listener allows
+    let role = ctx.session.role();
+    let reader = role.permits("ARTICLE");
+    // Feeds come from peers on transit listeners; elsewhere only from
+    // clients who have identified themselves
+    let transit = ctx.session.may_feed();
+
+    let mut lines = vec![RESP_CAP_VERSION, RESP_CAP_IMPLEMENTATION];
+    if reader {
+        lines.push(RESP_CAP_READER);
+    }
+    if may_post(ctx).await {
+        lines.push(RESP_CAP_POST);
+        if ctx.session.is_authenticated() && role.permits("XPOSTCHECK") {
+            lines.push(RESP_CAP_XPOSTCHECK);
+        }
+    }
+    if ctx.session.can_authenticate() && !ctx.session.is_authenticated() {
+        lines.push(RESP_CAP_AUTHINFO);
+    }
+    if ctx.session.can_starttls() {
+        lines.push(RESP_CAP_STARTTLS);
+    }
+    if ctx.session.can_xhost() {
+        lines.push(RESP_CAP_XHOST);
+    }
+    if reader {
+        lines.push(RESP_CAP_NEWNEWS);
+    }
+    if transit {
+        lines.extend([RESP_CAP_IHAVE, RESP_CAP_STREAMING]);
+    }
+    if reader {
+        // LIST PEERS is offered to administrators only
+        let list = if ctx.session.is_admin() {
+            RESP_CAP_LIST_ADMIN
+        } else {
+            RESP_CAP_LIST
+        };
+        lines.extend([
+            RESP_CAP_OVER,
+            RESP_CAP_HDR,
+            list,
+            RESP_CAP_XZVER,
+            RESP_CAP_XTHREAD,
+            RESP_CAP_BODY_RANGE,
+            RESP_CAP_XFEATURE_COMPRESS,
+        ]);
+    }
+    lines
+}
+
+/// Whether POST would be accepted from the session of `ctx`: the
+/// connection allows posting, and neither the user's limits nor a ban on
+/// the anonymous poster's posting account forbid it.
+async fn may_post(ctx: &mut HandlerContext) -> bool {
+    if !ctx.session.can_post() {
+        return false;
+    }
+    match ctx.session.username() {
+        Some(username) if ctx.session.is_authenticated() => {
+            ctx.session.is_admin()
+                || ctx.usage_tracker.can_post(username).await != LimitCheckResult::PostingDisabled
+        }
+        _ => match ctx.session.posting_account() {
+            Some(token) => !is_banned(&ctx.config.read().await.posting_accounts, token),
+            None => true,
+        },
+    }
+}
+
 /// Handler for the QUIT command.
 pub struct QuitHandler;
 
@@ -73,6 +144,6 @@ impl CommandHandler for QuitHandler {
     async fn handle(ctx: &mut HandlerContext, _args: &[String]) -> HandlerResult {
         write_simple(&mut ctx.writer, RESP_205_CLOSING).await?;
         // Return an error to signal the connection should close
-        Err(anyhow::anyhow!("Connection closed by QUIT command"))
+        Err(HandlerError::Quit.into())
     }
 }
@@ -2,17 +2,20 @@
 //!
 //! This module contains handlers for all NNTP commands, organized by category.
 
+pub mod args;
 pub mod article;
 pub mod auth;
 pub mod group;
 pub mod info;
+pub mod moderation;
 pub mod post;
 pub mod streaming;
 pub mod utils;
 
 use crate::Command;
 use crate::auth::DynAuth;
-use crate::config::Config;
+use crate::config::{Config, ListenerRole};
+use crate::error::HandlerError;
 use crate::limits::UsageTracker;
 use crate::queue::ArticleQueue;
 use crate::session::Session;
@@ -20,6 +23,8 @@ use crate::storage::DynStorage;
 use anyhow::Result;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::task::{Context, Poll, ready};
 use tokio::io::{AsyncBufRead, AsyncWrite};
 use tokio::sync::RwLock;
 
@@ -29,6 +34,71 @@ pub type DynReader = Pin<Box<dyn AsyncBufRead + Send>>;
 /// Type-erased async writer
 pub type DynWriter = Pin<Box<dyn AsyncWrite + Send>>;
 
+/// Records how the response to the current command has begun.
+///
+/// Once a final status line (`1xx` or `2xx`) is sent the command has been
+/// answered, and a multi-line block may be open; a handler failing after
+/// that cannot be answered with another status line without the client
+/// taking it for data.
+#[derive(Debug, Default)]
+pub struct ResponseProgress {
+    /// First byte written since the command was read, or 0 for none
+    first: AtomicU8,
+}
+
+impl ResponseProgress {
+    /// Start tracking the response to a new command.
+    fn reset(&self) {
+        self.first.store(0, Ordering::Relaxed);
+    }
+
+    fn record(&self, data: &[u8]) {
+        if let Some(&byte) = data.first() {
+            let _ = self
+                .first
+                .compare_exchange(0, byte, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the command has been given a final status line.
+    pub fn answered(&self) -> bool {
+        matches!(self.first.load(Ordering::Relaxed), b'1' | b'2')
+    }
+}
+
+/// Writer noting in a [`ResponseProgress`] what it writes.
+pub struct ProgressWriter<W> {
+    inner: W,
+    progress: Arc<ResponseProgress>,
+}
+
+impl<W> ProgressWriter<W> {
+    pub fn new(inner: W, progress: Arc<ResponseProgress>) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ProgressWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, data))?;
+        this.progress.record(&data[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
 /// Result type for command handlers.
 pub type HandlerResult = Result<()>;
 
@@ -42,6 +112,9 @@ pub struct HandlerContext {
     pub session: Session,
     pub queue: ArticleQueue,
     pub usage_tracker: Arc<UsageTracker>,
+    /// How the response to the current command has begun, as recorded by
+    /// a [`ProgressWriter`] around `writer`
+    pub progress: Arc<ResponseProgress>,
 }
 
 /// Trait for command handlers.
@@ -51,8 +124,63 @@ pub trait CommandHandler {
 }
 
 /// Dispatch a command to the appropriate handler.
+///
+/// A handler failing is answered with the status line of its
+/// [`HandlerError`], so the client is not left waiting for a response. Only
+/// errors ending the session, such as a lost connection, are returned; so
+/// is a failure after the command was answered, when a multi-line response
+/// may be cut short and the session can only be ended.
 pub async fn dispatch_command(ctx: &mut HandlerContext, cmd: &Command) -> HandlerResult {
-    match cmd.name.to_ascii_uppercase().as_str() {
+    ctx.progress.reset();
+    let Err(err) = run_command(ctx, cmd).await else {
+        return Ok(());
+    };
+    let err = HandlerError::from(err);
+    if ctx.progress.answered() && !err.is_fatal() {
+        tracing::error!(command = %cmd.name, error = %err, "Command failed during its response");
+        return Err(err.into());
+    }
+    match err.to_response() {
+        Some(response) => {
+            if matches!(err, HandlerError::Internal(_)) {
+                tracing::error!(command = %cmd.name, error = %err, "Command failed");
+            } else {
+                tracing::debug!(command = %cmd.name, error = %err, "Command refused");
+            }
+            utils::write_simple(&mut ctx.writer, &response).await
+        }
+        None => Err(err.into()),
+    }
+}
+
+/// Answer `response` to a command refused before its handler ran.
+///
+/// The article always following TAKETHIS is read and discarded first, so the
+/// session stays in step with the peer.
+async fn refuse(ctx: &mut HandlerContext, name: &str, response: &str) -> HandlerResult {
+    if name == "TAKETHIS" {
+        utils::drain_article(&mut ctx.reader).await?;
+    }
+    utils::write_simple(&mut ctx.writer, response).await
+}
+
+/// Run the handler of `cmd`.
+async fn run_command(ctx: &mut HandlerContext, cmd: &Command) -> HandlerResult {
+    let name = cmd.name.to_ascii_uppercase();
+    if !ctx.session.role().permits(&name) {
+        use crate::responses::RESP_502_WRONG_LISTENER;
+        return refuse(ctx, &name, RESP_502_WRONG_LISTENER).await;
+    }
+    // Only peers and authenticated feeders may send articles
+    if ListenerRole::is_transit_command(&name) && !ctx.session.may_feed() {
+        use crate::responses::RESP_480_AUTH_REQUIRED;
+        return refuse(ctx, &name, RESP_480_AUTH_REQUIRED).await;
+    }
+    if let Err(err) = args::validate(&name, &cmd.args) {
+        return utils::write_simple(&mut ctx.writer, &err.to_response()).await;
+    }
+
+    match name.as_str() {
         // Article retrieval commands
         "ARTICLE" => article::ArticleHandler::handle(ctx, &cmd.args).await,
         "HEAD" => article::HeadHandler::handle(ctx, &cmd.args).await,
@@ -73,16 +201,24 @@ pub async fn dispatch_command(ctx: &mut HandlerContext, cmd: &Command) -> Handle
         "XPAT" => article::XPatHandler::handle(ctx, &cmd.args).await,
         "OVER" => article::OverHandler::handle(ctx, &cmd.args).await,
         "XOVER" => article::OverHandler::handle(ctx, &cmd.args).await,
+        "XZVER" => article::XzverHandler::handle(ctx, &cmd.args).await,
+        "XZHDR" => article::XzhdrHandler::handle(ctx, &cmd.args).await,
+        "XTHREAD" => article::XThreadHandler::handle(ctx, &cmd.args).await,
 
         // Posting and streaming commands
         "POST" => post::PostHandler::handle(ctx, &cmd.args).await,
+        "XPOSTCHECK" => post::PostCheckHandler::handle(ctx, &cmd.args).await,
         "IHAVE" => streaming::IHaveHandler::handle(ctx, &cmd.args).await,
         "CHECK" => streaming::CheckHandler::handle(ctx, &cmd.args).await,
         "TAKETHIS" => streaming::TakeThisHandler::handle(ctx, &cmd.args).await,
 
+        // Moderation commands
+        "XMODERATE" => moderation::XModerateHandler::handle(ctx, &cmd.args).await,
+
         // Authentication and mode commands
         "AUTHINFO" => auth::AuthInfoHandler::handle(ctx, &cmd.args).await,
         "MODE" => auth::ModeHandler::handle(ctx, &cmd.args).await,
+        "XFEATURE" => auth::XFeatureHandler::handle(ctx, &cmd.args).await,
 
         // Information commands
         "CAPABILITIES" => info::CapabilitiesHandler::handle(ctx, &cmd.args).await,
@@ -92,10 +228,10 @@ pub async fn dispatch_command(ctx: &mut HandlerContext, cmd: &Command) -> Handle
 
         // Unknown command
         _ => {
-            use crate::responses::RESP_500_UNKNOWN_CMD;
+            use crate::responses::{RESP_500_UNKNOWN_CMD, localize};
             use tokio::io::AsyncWriteExt;
             ctx.writer
-                .write_all(RESP_500_UNKNOWN_CMD.as_bytes())
+                .write_all(localize(RESP_500_UNKNOWN_CMD).as_bytes())
                 .await?;
             Ok(())
         }
@@ -0,0 +1,134 @@
+//! Moderation queue command handlers.
+
+use super::utils::{get_header_value, send_body, send_headers, write_simple};
+use super::{CommandHandler, HandlerContext, HandlerResult};
+use crate::Message;
+use crate::audit::{self, AuditAction, AuditEntry};
+use crate::moderation;
+use crate::responses::*;
+use tokio::io::AsyncWriteExt;
+
+/// Handler for the XMODERATE command.
+///
+/// `XMODERATE LIST [wildmat]` lists queued articles the user may moderate,
+/// optionally only those posted to matching groups, one per line as
+/// `id<TAB>message-id<TAB>newsgroups<TAB>subject`.
+/// `XMODERATE GET <id>` returns a queued article, and `XMODERATE APPROVE <id>`
+/// and `XMODERATE REJECT <id>` act on one entry. `XMODERATE PIN <group>
+/// <message-id>` and `XMODERATE UNPIN <group> <message-id>` set whether an
+/// article is pinned in a group the user moderates.
+pub struct XModerateHandler;
+
+impl CommandHandler for XModerateHandler {
+    async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
+        let Some(username) = ctx
+            .session
+            .is_authenticated()
+            .then(|| ctx.session.username().map(str::to_string))
+            .flatten()
+        else {
+            return write_simple(&mut ctx.writer, RESP_480_AUTH_REQUIRED).await;
+        };
+
+        let Some(action) = args.first() else {
+            return write_simple(&mut ctx.writer, RESP_501_NOT_ENOUGH).await;
+        };
+
+        match action.to_ascii_uppercase().as_str() {
+            "LIST" => {
+                let pending = moderation::list_pending(
+                    &ctx.storage,
+                    &ctx.auth,
+                    Some(&username),
+                    args.get(1).map(String::as_str),
+                )
+                .await?;
+                ctx.writer
+                    .write_all(localize(RESP_215_PENDING).as_bytes())
+                    .await?;
+                for entry in pending {
+                    let field = |name| {
+                        get_header_value(&entry.message, name)
+                            .unwrap_or_default()
+                            .replace(['\t', '\r', '\n'], " ")
+                    };
+                    let line = format!(
+                        "{}\t{}\t{}\t{}\r\n",
+                        entry.id,
+                        field("Message-ID"),
+                        field("Newsgroups"),
+                        field("Subject")
+                    );
+                    ctx.writer.write_all(line.as_bytes()).await?;
+                }
+                ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+                Ok(())
+            }
+            "GET" | "APPROVE" | "REJECT" => {
+                let Some(id) = args.get(1).and_then(|a| a.parse::<u64>().ok()) else {
+                    return write_simple(&mut ctx.writer, RESP_501_NOT_ENOUGH).await;
+                };
+                let Some(entry) = ctx.storage.get_pending_article(id).await? else {
+                    return write_simple(&mut ctx.writer, RESP_430_NO_PENDING).await;
+                };
+                if !moderation::can_moderate(&ctx.storage, &ctx.auth, &username, &entry.message)
+                    .await?
+                {
+                    return write_simple(&mut ctx.writer, RESP_502_NOT_MODERATOR).await;
+                }
+
+                if action.eq_ignore_ascii_case("GET") {
+                    let message_id =
+                        get_header_value(&entry.message, "Message-ID").unwrap_or_default();
+                    let status = format!("220 {id} {message_id} pending article follows\r\n");
+                    ctx.writer.write_all(status.as_bytes()).await?;
+                    send_headers(&mut ctx.writer, &entry.message).await?;
+                    ctx.writer.write_all(b"\r\n").await?;
+                    send_body(&mut ctx.writer, &entry.message.body).await?;
+                    ctx.writer.write_all(RESP_DOT_CRLF.as_bytes()).await?;
+                    Ok(())
+                } else if action.eq_ignore_ascii_case("APPROVE") {
+                    moderation::approve(&ctx.storage, id, &username).await?;
+                    audit_decision(ctx, AuditAction::Approve, &entry.message).await;
+                    write_simple(&mut ctx.writer, RESP_240_ARTICLE_APPROVED).await
+                } else {
+                    moderation::reject(&ctx.storage, id).await?;
+                    audit_decision(ctx, AuditAction::Reject, &entry.message).await;
+                    write_simple(&mut ctx.writer, RESP_241_ARTICLE_REJECTED).await
+                }
+            }
+            "PIN" | "UNPIN" => {
+                let (Some(group), Some(message_id)) = (args.get(1), args.get(2)) else {
+                    return write_simple(&mut ctx.writer, RESP_501_NOT_ENOUGH).await;
+                };
+                if !ctx.auth.is_admin(&username).await?
+                    && !ctx.auth.is_moderator(&username, group).await?
+                {
+                    return write_simple(&mut ctx.writer, RESP_502_NOT_GROUP_MODERATOR).await;
+                }
+                let pin = action.eq_ignore_ascii_case("PIN");
+                if !ctx
+                    .storage
+                    .set_article_pinned(group, message_id, pin)
+                    .await?
+                {
+                    return write_simple(&mut ctx.writer, RESP_430_NO_ARTICLE).await;
+                }
+                let response = if pin {
+                    RESP_242_ARTICLE_PINNED
+                } else {
+                    RESP_243_ARTICLE_UNPINNED
+                };
+                write_simple(&mut ctx.writer, response).await
+            }
+            _ => write_simple(&mut ctx.writer, RESP_501_UNKNOWN_KEYWORD).await,
+        }
+    }
+}
+
+/// Record the moderator of `ctx` approving or rejecting `article` in the
+/// audit log.
+async fn audit_decision(ctx: &mut HandlerContext, action: AuditAction, article: &Message) {
+    let entry = AuditEntry::for_article(action, article).by_session(&ctx.session);
+    audit::record(&*ctx.storage, entry).await;
+}
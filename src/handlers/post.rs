@@ -1,16 +1,21 @@
 //! Posting command handlers.
 
 use super::utils::{
-    check_bandwidth_rejected, comprehensive_validate_article, read_message, record_bandwidth_usage,
-    write_simple,
+    ArticleBlock, check_bandwidth_rejected, comprehensive_validate_article, read_article_block,
+    session_poster, write_simple,
 };
 use super::{CommandHandler, HandlerContext, HandlerResult};
+use crate::audit::{self, AuditAction, AuditEntry};
+use crate::cancel_lock::add_cancel_headers;
 use crate::error::{AuthError, NntpError};
+use crate::filters::{ArchivedGroup, PolicyRejection};
 use crate::limits::LimitCheckResult;
+use crate::local_post::{self, Screened};
+use crate::posting_account::is_banned;
 use crate::prelude::*;
 use crate::queue::QueuedArticle;
 use crate::responses::*;
-use crate::{control, ensure_message_id, parse, parse_message};
+use crate::{Message, control, parse_message};
 use tracing::Span;
 
 /// Handler for the POST command.
@@ -48,9 +53,42 @@ impl CommandHandler for PostHandler {
             }
         }
 
+        // Don't take an article the queue has no room for
+        if ctx.queue.is_full() {
+            Span::current().record("outcome", "rejected_queue_full");
+            let line = with_retry(RESP_440_POST_PROHIBITED, ctx.queue.retry_after());
+            write_simple(&mut ctx.writer, &line).await?;
+            return Ok(());
+        }
+
+        // Refuse anonymous posters whose posting-account token is banned
+        if !ctx.session.is_authenticated()
+            && let Some(token) = ctx.session.posting_account()
+            && is_banned(&ctx.config.read().await.posting_accounts, token)
+        {
+            Span::current().record("outcome", "rejected_banned_account");
+            write_simple(&mut ctx.writer, RESP_440_POST_PROHIBITED).await?;
+            return Ok(());
+        }
+
         write_simple(&mut ctx.writer, RESP_340_SEND_ARTICLE).await?;
 
-        let msg = read_message(&mut ctx.reader).await?;
+        let max_bytes = ctx.config.read().await.max_message_bytes;
+        let (msg, metadata) = match read_article_block(&mut ctx.reader, max_bytes).await? {
+            ArticleBlock::Complete { text, metadata } => (text, metadata),
+            ArticleBlock::TooLarge { size } => {
+                Span::current().record("size_bytes", size);
+                Span::current().record("outcome", "rejected_too_large");
+                write_simple(&mut ctx.writer, RESP_441_POSTING_FAILED).await?;
+                return Ok(());
+            }
+            ArticleBlock::Malformed { reason } => {
+                tracing::debug!(%reason, "Article refused while reading");
+                Span::current().record("outcome", "rejected_malformed");
+                write_simple(&mut ctx.writer, RESP_441_POSTING_FAILED).await?;
+                return Ok(());
+            }
+        };
         let Ok((_, mut message)) = parse_message(&msg) else {
             Span::current().record("outcome", "rejected_parse");
             write_simple(&mut ctx.writer, RESP_441_POSTING_FAILED).await?;
@@ -60,11 +98,23 @@ impl CommandHandler for PostHandler {
         // Check if this is a control message first
         let is_control = control::is_control_message(&message);
 
-        // Ensure required headers
-        let cfg_guard = ctx.config.read().await;
-        ensure_message_id(&mut message, &cfg_guard.site_name);
-        parse::ensure_date(&mut message);
-        parse::escape_message_id_header(&mut message);
+        // Normalise headers as the injecting site. Screening may wait on
+        // filters over the network, so it works on a copy of the
+        // configuration rather than keeping reloads waiting
+        let cfg = ctx.config.read().await.clone();
+        local_post::prepare(
+            &mut message,
+            &cfg,
+            session_poster(&ctx.session).user,
+            ctx.session.posting_account(),
+        );
+        if let Some(secret) = &cfg.cancel_lock_secret
+            && let Some(poster) = session_poster(&ctx.session)
+                .user
+                .or(ctx.session.posting_account())
+        {
+            add_cancel_headers(&mut message, secret, poster);
+        }
 
         // Record article metadata in current span
         if let Some(msg_id) = message
@@ -75,31 +125,55 @@ impl CommandHandler for PostHandler {
         {
             Span::current().record("message_id", msg_id);
         }
-        let size = msg.len() as u64;
+        let size = metadata.size;
         Span::current().record("size_bytes", size);
         Span::current().record("is_control", is_control);
 
-        // Check per-user bandwidth limit (only for authenticated non-admin users)
+        // Check per-user bandwidth limit (only for authenticated non-admin users);
+        // a windowed limit comes with the time it starts over
+        if ctx.session.is_authenticated()
+            && !ctx.session.is_admin()
+            && let Some(username) = ctx.session.username()
+            && let Some(after) = ctx.usage_tracker.bandwidth_retry_after(username).await
+            && ctx.usage_tracker.check_bandwidth(username, size).await
+                == LimitCheckResult::BandwidthExceeded
+        {
+            Span::current().record("outcome", "rejected_bandwidth");
+            let line = with_retry(RESP_441_POSTING_FAILED, after);
+            write_simple(&mut ctx.writer, &line).await?;
+            return Ok(());
+        }
         if check_bandwidth_rejected(&mut ctx.writer, &ctx.session, &ctx.usage_tracker, size).await?
         {
             return Ok(());
         }
 
-        // Comprehensive validation before queuing for POST (to maintain expected behavior)
-        match comprehensive_validate_article(&ctx.storage, &ctx.auth, &cfg_guard, &message, size)
-            .await
-        {
-            Ok(()) => { /* validation passed, continue */ }
-            Err(e) => {
-                tracing::info!(error = %e, "Article validation failed");
-                Span::current().record("outcome", "rejected_validation");
-                write_simple(&mut ctx.writer, RESP_441_POSTING_FAILED).await?;
-                return Ok(());
-            }
+        let screened = local_post::screen(
+            &ctx.storage,
+            &ctx.auth,
+            &cfg,
+            &mut message,
+            &metadata,
+            Some(session_poster(&ctx.session)),
+        )
+        .await?;
+        // Held and quarantined posts wait for a moderator
+        let held = match screened {
+            Screened::Accepted => None,
+            Screened::Held => Some("held_for_moderation"),
+            Screened::Quarantined => Some("quarantined"),
+            Screened::Refused(e) => return refuse_invalid(&mut ctx.writer, &e).await,
+        };
+        if let Some(outcome) = held {
+            audit_post(ctx, &message).await;
+            Span::current().record("outcome", outcome);
+            write_simple(&mut ctx.writer, RESP_240_ARTICLE_RECEIVED).await?;
+            return Ok(());
         }
-        drop(cfg_guard);
 
         // Submit to queue for background processing
+        let audit_entry =
+            AuditEntry::for_article(AuditAction::Post, &message).by_session(&ctx.session);
         let queued_article = QueuedArticle {
             message,
             size,
@@ -107,14 +181,14 @@ impl CommandHandler for PostHandler {
             already_validated: true, // POST uses comprehensive validation and queues for storage only
         };
 
-        if ctx.queue.submit(queued_article).await.is_err() {
+        if ctx.queue.try_submit(queued_article).await.is_err() {
             Span::current().record("outcome", "rejected_queue_full");
-            write_simple(&mut ctx.writer, RESP_441_POSTING_FAILED).await?;
+            let line = with_retry(RESP_441_POSTING_FAILED, ctx.queue.retry_after());
+            write_simple(&mut ctx.writer, &line).await?;
             return Ok(());
         }
 
-        // Record bandwidth usage for authenticated non-admin users
-        record_bandwidth_usage(&ctx.session, &ctx.usage_tracker, size, true).await;
+        audit::record(&*ctx.storage, audit_entry).await;
 
         Span::current().record("outcome", "accepted");
         write_simple(&mut ctx.writer, RESP_240_ARTICLE_RECEIVED).await?;
@@ -122,6 +196,141 @@ impl CommandHandler for PostHandler {
     }
 }
 
+/// Refuse a post the filters rejected with `err`, telling the poster when
+/// it was for an archived group.
+async fn refuse_invalid<W: tokio::io::AsyncWrite + Unpin>(
+    writer: &mut W,
+    err: &anyhow::Error,
+) -> HandlerResult {
+    tracing::info!(error = %err, "Article validation failed");
+    if ArchivedGroup::is(err) {
+        Span::current().record("outcome", "rejected_archived");
+        write_simple(writer, RESP_441_ARCHIVED).await?;
+    } else {
+        Span::current().record("outcome", "rejected_validation");
+        write_simple(writer, RESP_441_POSTING_FAILED).await?;
+    }
+    Ok(())
+}
+
+/// Refuse an XPOSTCHECK whose headers the filters rejected with `err`.
+///
+/// Only the reasons of policy refusals are shown to the client; other
+/// errors, such as a failing storage or authentication backend, get the
+/// generic 441 so their details stay in the log.
+async fn refuse_check<W: tokio::io::AsyncWrite + Unpin>(
+    writer: &mut W,
+    err: &anyhow::Error,
+) -> HandlerResult {
+    if let Some(rejection) = err.downcast_ref::<PolicyRejection>() {
+        Span::current().record("outcome", "rejected_validation");
+        let reason = rejection.0.replace(['\r', '\n'], " ");
+        return write_simple(writer, &format!("441 {reason}\r\n")).await;
+    }
+    refuse_invalid(writer, err).await
+}
+
+/// Record a post of `article` by the client of `ctx` in the audit log.
+async fn audit_post(ctx: &mut HandlerContext, article: &Message) {
+    let entry = AuditEntry::for_article(AuditAction::Post, article).by_session(&ctx.session);
+    audit::record(&*ctx.storage, entry).await;
+}
+
+/// Handler for the XPOSTCHECK command.
+///
+/// `XPOSTCHECK [size]` takes the headers of an article the client intends to
+/// post, terminated like an article, and reports whether a POST of an
+/// article of `size` bytes with those headers would be accepted (244), held
+/// for moderation (245) or refused, so a client can give up before
+/// uploading a large body. Checks that need the body are not run.
+pub struct PostCheckHandler;
+
+impl CommandHandler for PostCheckHandler {
+    async fn handle(ctx: &mut HandlerContext, args: &[String]) -> HandlerResult {
+        let Some(username) = ctx
+            .session
+            .is_authenticated()
+            .then(|| ctx.session.username().map(str::to_string))
+            .flatten()
+        else {
+            return write_simple(&mut ctx.writer, RESP_480_AUTH_REQUIRED).await;
+        };
+        if !ctx.session.can_post()
+            || (!ctx.session.is_admin()
+                && ctx.usage_tracker.can_post(&username).await == LimitCheckResult::PostingDisabled)
+        {
+            Span::current().record("outcome", "rejected_posting_disabled");
+            return write_simple(&mut ctx.writer, RESP_440_POST_PROHIBITED).await;
+        }
+        let declared_size = args.first().and_then(|a| a.parse::<u64>().ok());
+
+        write_simple(&mut ctx.writer, RESP_345_SEND_HEADERS).await?;
+        let max_bytes = ctx.config.read().await.max_message_bytes;
+        let mut text = match read_article_block(&mut ctx.reader, max_bytes).await? {
+            ArticleBlock::Complete { text, .. } => text,
+            ArticleBlock::TooLarge { .. } => {
+                Span::current().record("outcome", "rejected_too_large");
+                return write_simple(&mut ctx.writer, RESP_441_POSTING_FAILED).await;
+            }
+            ArticleBlock::Malformed { reason } => {
+                tracing::debug!(%reason, "Article refused while reading");
+                Span::current().record("outcome", "rejected_malformed");
+                return write_simple(&mut ctx.writer, RESP_441_POSTING_FAILED).await;
+            }
+        };
+        // The headers may be sent without the blank line that ends them
+        if !text.contains("\r\n\r\n") {
+            text.push_str("\r\n");
+        }
+        let Ok((_, message)) = parse_message(&text) else {
+            Span::current().record("outcome", "rejected_parse");
+            return write_simple(&mut ctx.writer, RESP_441_POSTING_FAILED).await;
+        };
+        let size = declared_size.unwrap_or(text.len() as u64);
+        Span::current().record("size_bytes", size);
+
+        // Checked on a copy of the configuration, as POST screens with one
+        let cfg = ctx.config.read().await.clone();
+        if max_bytes.is_some_and(|max| size > max) {
+            Span::current().record("outcome", "rejected_too_large");
+            return write_simple(&mut ctx.writer, RESP_441_TOO_LARGE).await;
+        }
+        if check_bandwidth_rejected(&mut ctx.writer, &ctx.session, &ctx.usage_tracker, size).await?
+        {
+            return Ok(());
+        }
+
+        // Run the checks POST would run on these headers
+        let held = crate::moderation::needs_moderation(&ctx.storage, &message).await?;
+        let chain = if held {
+            crate::moderation::pending_filter_chain()
+        } else {
+            crate::filters::FilterChain::default()
+        };
+        if let Err(e) = chain
+            .validate(
+                &ctx.storage,
+                &ctx.auth,
+                &cfg,
+                &message,
+                size,
+                Some(session_poster(&ctx.session)),
+            )
+            .await
+        {
+            return refuse_check(&mut ctx.writer, &e).await;
+        }
+
+        if held {
+            Span::current().record("outcome", "would_hold");
+            write_simple(&mut ctx.writer, RESP_245_POST_WOULD_BE_HELD).await
+        } else {
+            Span::current().record("outcome", "would_accept");
+            write_simple(&mut ctx.writer, RESP_244_POST_WOULD_BE_ACCEPTED).await
+        }
+    }
+}
+
 /// Validate an article for posting (legacy function, now uses comprehensive validation).
 pub async fn validate_article(
     storage: &crate::storage::DynStorage,
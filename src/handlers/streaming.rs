@@ -1,13 +1,161 @@
 //! Streaming command handlers (IHAVE, CHECK, TAKETHIS).
+//!
+//! By default an offered article passes every filter and is stored before
+//! the peer is told it was accepted. With `transit_validation = "deferred"`
+//! only the fast filters run first; the article is then queued and
+//! acknowledged, and the queue workers run the remaining filters before
+//! storing it.
+//!
+//! When the storage fails while an article is looked up or stored, the peer
+//! is answered `436` (or `431` to `CHECK` and `TAKETHIS`) rather than told
+//! the article was refused, and nothing is recorded in the history, so the
+//! peer offers it again later.
 
 use super::utils::{
-    check_bandwidth_rejected, comprehensive_validate_article, read_message, record_bandwidth_usage,
-    write_simple,
+    ArticleBlock, ArticleMetadata, check_bandwidth_rejected, configured_filter_chain,
+    read_article_block, validate_article_with_metadata, write_simple,
 };
 use super::{CommandHandler, HandlerContext, HandlerResult};
+use crate::auth::DynAuth;
+use crate::config::{Config, TransitValidation};
+use crate::filters::{ArchivedGroup, TemporaryFailure};
+use crate::queue::{ArticleQueue, QueuedArticle};
 use crate::responses::*;
-use crate::{control, ensure_message_id, parse, parse_message};
-use tracing::Span;
+use crate::storage::DynStorage;
+use crate::{
+    Message, control, ensure_message_id, history, parse, parse_message, rejected, rewrite,
+};
+use anyhow::Result;
+use tracing::{Span, warn};
+
+/// Whether the server has the article `id`, or has queued it for
+/// validation, counting the offer as a duplicate if so.
+async fn already_have(ctx: &mut HandlerContext, id: &str) -> Result<bool> {
+    if ctx.queue.is_pending(id) {
+        ctx.queue.record_duplicate(true);
+        return Ok(true);
+    }
+    let seen = history::seen(&*ctx.storage, id).await?;
+    if seen {
+        ctx.queue.record_duplicate(false);
+    }
+    Ok(seen)
+}
+
+/// Note that the storage failed while an article was transferred; the
+/// caller asks the peer to try again later.
+fn storage_failed(queue: &ArticleQueue, error: &anyhow::Error) {
+    warn!(error = %error, "Storage failed during transfer; asking peer to retry");
+    queue.record_storage_deferral();
+    Span::current().record("outcome", "deferred_storage");
+}
+
+/// What became of an article left for the queue workers to validate
+enum Deferred {
+    /// A fast filter refused it
+    Rejected,
+    /// It was refused for an archived group
+    Archived,
+    /// The queue had no room for it
+    QueueFull,
+    /// It was queued and may be acknowledged
+    Queued,
+}
+
+/// Run the fast filters on `article` and queue it for the queue workers to
+/// validate and store.
+async fn defer_validation(
+    storage: &DynStorage,
+    auth: &DynAuth,
+    queue: &ArticleQueue,
+    cfg: &Config,
+    id: &str,
+    article: Message,
+    metadata: &ArticleMetadata,
+) -> Result<Deferred> {
+    if let Err(e) = configured_filter_chain(cfg)
+        .validate_fast(storage, auth, cfg, &article, metadata)
+        .await
+    {
+        history::remember_rejection(&**storage, id).await;
+        rejected::keep(&**storage, cfg, &article, &e).await;
+        if ArchivedGroup::is(&e) {
+            Span::current().record("outcome", "rejected_archived");
+            return Ok(Deferred::Archived);
+        }
+        Span::current().record("outcome", "rejected_validation");
+        return Ok(Deferred::Rejected);
+    }
+    let queued_article = QueuedArticle {
+        message: article,
+        size: metadata.size,
+        is_control: false,
+        already_validated: false,
+    };
+    if queue.try_submit(queued_article).await.is_err() {
+        Span::current().record("outcome", "deferred_queue_full");
+        return Ok(Deferred::QueueFull);
+    }
+    Span::current().record("outcome", "queued_for_validation");
+    Ok(Deferred::Queued)
+}
+
+/// What the filters made of an article offered for immediate storage
+enum Screened {
+    /// It passed, tagged as the filters asked, and may be stored
+    Accepted,
+    /// A filter quarantined it for a moderator
+    Quarantined,
+    /// A filter or the storage could not decide on it for now
+    Retry,
+    /// A filter refused it
+    Rejected,
+    /// It was refused for an archived group
+    Archived,
+}
+
+/// Run every filter on `article` and carry out their verdict.
+async fn screen(
+    storage: &DynStorage,
+    auth: &DynAuth,
+    queue: &ArticleQueue,
+    cfg: &Config,
+    id: &str,
+    article: &mut Message,
+    metadata: &ArticleMetadata,
+) -> Screened {
+    let verdict =
+        match validate_article_with_metadata(storage, auth, cfg, article, metadata, None).await {
+            Ok(verdict) => verdict,
+            Err(e) if TemporaryFailure::is(&e) => {
+                Span::current().record("outcome", "deferred_filter");
+                return Screened::Retry;
+            }
+            Err(e) => {
+                history::remember_rejection(&**storage, id).await;
+                rejected::keep(&**storage, cfg, article, &e).await;
+                if ArchivedGroup::is(&e) {
+                    Span::current().record("outcome", "rejected_archived");
+                    return Screened::Archived;
+                }
+                Span::current().record("outcome", "rejected_validation");
+                return Screened::Rejected;
+            }
+        };
+
+    // Filters may tag the article or quarantine it for a moderator
+    match verdict.apply(storage, article).await {
+        Ok(false) => Screened::Accepted,
+        Ok(true) => {
+            Span::current().record("outcome", "quarantined");
+            Screened::Quarantined
+        }
+        Err(e) => {
+            storage_failed(queue, &e);
+            Screened::Retry
+        }
+    }
+}
 
 /// Handler for the IHAVE command.
 pub struct IHaveHandler;
@@ -17,14 +165,44 @@ impl CommandHandler for IHaveHandler {
         if let Some(id) = args.first() {
             Span::current().record("message_id", id.as_str());
 
-            if ctx.storage.get_article_by_id(id).await?.is_some() {
-                Span::current().record("outcome", "already_have");
-                write_simple(&mut ctx.writer, RESP_435_NOT_WANTED).await?;
+            match already_have(ctx, id).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    Span::current().record("outcome", "already_have");
+                    write_simple(&mut ctx.writer, RESP_435_NOT_WANTED).await?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    storage_failed(&ctx.queue, &e);
+                    write_simple(&mut ctx.writer, RESP_436_TRY_LATER).await?;
+                    return Ok(());
+                }
+            }
+
+            // Ask the peer to offer it again once the queue has drained
+            if ctx.queue.is_full() {
+                Span::current().record("outcome", "deferred_queue_full");
+                write_simple(&mut ctx.writer, RESP_436_TRY_LATER).await?;
                 return Ok(());
             }
 
             write_simple(&mut ctx.writer, RESP_335_SEND_IT).await?;
-            let msg = read_message(&mut ctx.reader).await?;
+            let max_bytes = ctx.config.read().await.max_message_bytes;
+            let (msg, metadata) = match read_article_block(&mut ctx.reader, max_bytes).await? {
+                ArticleBlock::Complete { text, metadata } => (text, metadata),
+                ArticleBlock::TooLarge { size } => {
+                    Span::current().record("size_bytes", size);
+                    Span::current().record("outcome", "rejected_too_large");
+                    write_simple(&mut ctx.writer, RESP_437_REJECTED).await?;
+                    return Ok(());
+                }
+                ArticleBlock::Malformed { reason } => {
+                    tracing::debug!(%reason, "Article refused while reading");
+                    Span::current().record("outcome", "rejected_malformed");
+                    write_simple(&mut ctx.writer, RESP_437_REJECTED).await?;
+                    return Ok(());
+                }
+            };
             let Ok((_, mut article)) = parse_message(&msg) else {
                 Span::current().record("outcome", "rejected_parse");
                 write_simple(&mut ctx.writer, RESP_437_REJECTED).await?;
@@ -36,9 +214,22 @@ impl CommandHandler for IHaveHandler {
             Span::current().record("is_control", is_control);
 
             let cfg_guard = ctx.config.read().await;
+            // Refuse articles that have passed through us before, so that
+            // peering loops cannot circulate them for ever
+            if rewrite::path_names_any(&article, &cfg_guard.path_identities()) {
+                Span::current().record("outcome", "rejected_path_loop");
+                history::remember_rejection(&*ctx.storage, id).await;
+                write_simple(&mut ctx.writer, RESP_437_REJECTED).await?;
+                return Ok(());
+            }
             ensure_message_id(&mut article, &cfg_guard.site_name);
             parse::ensure_date(&mut article);
             parse::escape_message_id_header(&mut article);
+            rewrite::add_path(
+                &mut article,
+                &cfg_guard.site_name,
+                rewrite::POSTED_PATH_TAIL,
+            );
 
             // Handle control messages immediately without comprehensive validation
             if is_control {
@@ -54,7 +245,7 @@ impl CommandHandler for IHaveHandler {
             }
 
             // Comprehensive validation before queuing for IHAVE (non-control messages)
-            let size = msg.len() as u64;
+            let size = metadata.size;
             Span::current().record("size_bytes", size);
 
             // Check per-user bandwidth limit (only for authenticated non-admin users)
@@ -64,36 +255,74 @@ impl CommandHandler for IHaveHandler {
                 return Ok(());
             }
 
-            if comprehensive_validate_article(&ctx.storage, &ctx.auth, &cfg_guard, &article, size)
-                .await
-                .is_err()
-            {
-                Span::current().record("outcome", "rejected_validation");
-                write_simple(&mut ctx.writer, RESP_437_REJECTED).await?;
+            if cfg_guard.transit_validation == TransitValidation::Deferred {
+                let response = match defer_validation(
+                    &ctx.storage,
+                    &ctx.auth,
+                    &ctx.queue,
+                    &cfg_guard,
+                    id,
+                    article,
+                    &metadata,
+                )
+                .await?
+                {
+                    Deferred::Rejected => RESP_437_REJECTED,
+                    Deferred::Archived => RESP_437_ARCHIVED,
+                    Deferred::QueueFull => RESP_436_TRY_LATER,
+                    Deferred::Queued => RESP_235_TRANSFER_OK,
+                };
+                write_simple(&mut ctx.writer, response).await?;
                 return Ok(());
             }
+
+            let screened = screen(
+                &ctx.storage,
+                &ctx.auth,
+                &ctx.queue,
+                &cfg_guard,
+                id,
+                &mut article,
+                &metadata,
+            )
+            .await;
             drop(cfg_guard);
+            let response = match screened {
+                Screened::Accepted => None,
+                Screened::Quarantined => Some(RESP_235_TRANSFER_OK),
+                Screened::Retry => Some(RESP_436_TRY_LATER),
+                Screened::Rejected => Some(RESP_437_REJECTED),
+                Screened::Archived => Some(RESP_437_ARCHIVED),
+            };
+            if let Some(response) = response {
+                write_simple(&mut ctx.writer, response).await?;
+                return Ok(());
+            }
 
             // Submit to queue for background storage and immediate storage for protocol compliance
-            let queued_article = crate::queue::QueuedArticle {
+            let queued_article = QueuedArticle {
                 message: article.clone(),
                 size,
                 is_control: false, // Control messages are handled above, so this is always false
                 already_validated: true, // IHAVE does comprehensive validation before queuing
             };
 
+            // The queue filled up while the article was sent
+            if ctx.queue.is_full() {
+                Span::current().record("outcome", "deferred_queue_full");
+                write_simple(&mut ctx.writer, RESP_436_TRY_LATER).await?;
+                return Ok(());
+            }
+
             // Store immediately for protocol compliance (second IHAVE should know article exists)
-            if ctx.storage.store_article(&article).await.is_err() {
-                Span::current().record("outcome", "rejected_storage");
-                write_simple(&mut ctx.writer, RESP_437_REJECTED).await?;
+            if let Err(e) = ctx.storage.store_article(&article).await {
+                storage_failed(&ctx.queue, &e);
+                write_simple(&mut ctx.writer, RESP_436_TRY_LATER).await?;
                 return Ok(());
             }
 
             // Also queue for background processing consistency
-            let _ = ctx.queue.submit(queued_article).await; // Don't fail if queue is full since we already stored
-
-            // Record bandwidth usage for authenticated non-admin users
-            record_bandwidth_usage(&ctx.session, &ctx.usage_tracker, size, true).await;
+            let _ = ctx.queue.try_submit(queued_article).await; // Don't wait for room since we already stored
 
             Span::current().record("outcome", "accepted");
             write_simple(&mut ctx.writer, RESP_235_TRANSFER_OK).await?;
@@ -112,13 +341,25 @@ impl CommandHandler for CheckHandler {
         if let Some(id) = args.first() {
             Span::current().record("message_id", id.as_str());
 
-            if ctx.storage.get_article_by_id(id).await?.is_some() {
-                Span::current().record("outcome", "already_have");
-                write_simple(&mut ctx.writer, &streaming_response(438, id)).await?;
-            } else {
-                Span::current().record("outcome", "send_it");
-                write_simple(&mut ctx.writer, &streaming_response(238, id)).await?;
-            }
+            let code = match already_have(ctx, id).await {
+                Ok(true) => {
+                    Span::current().record("outcome", "already_have");
+                    438
+                }
+                Ok(false) if ctx.queue.is_full() => {
+                    Span::current().record("outcome", "deferred_queue_full");
+                    431
+                }
+                Ok(false) => {
+                    Span::current().record("outcome", "send_it");
+                    238
+                }
+                Err(e) => {
+                    storage_failed(&ctx.queue, &e);
+                    431
+                }
+            };
+            write_simple(&mut ctx.writer, &streaming_response(code, id)).await?;
         } else {
             write_simple(&mut ctx.writer, RESP_501_MSGID_REQUIRED).await?;
         }
@@ -134,17 +375,40 @@ impl CommandHandler for TakeThisHandler {
         if let Some(id) = args.first() {
             Span::current().record("message_id", id.as_str());
 
-            let msg = read_message(&mut ctx.reader).await?;
+            let max_bytes = ctx.config.read().await.max_message_bytes;
+            let (msg, metadata) = match read_article_block(&mut ctx.reader, max_bytes).await? {
+                ArticleBlock::Complete { text, metadata } => (text, metadata),
+                ArticleBlock::TooLarge { size } => {
+                    Span::current().record("size_bytes", size);
+                    Span::current().record("outcome", "rejected_too_large");
+                    write_simple(&mut ctx.writer, &streaming_response(439, id)).await?;
+                    return Ok(());
+                }
+                ArticleBlock::Malformed { reason } => {
+                    tracing::debug!(%reason, "Article refused while reading");
+                    Span::current().record("outcome", "rejected_malformed");
+                    write_simple(&mut ctx.writer, &streaming_response(439, id)).await?;
+                    return Ok(());
+                }
+            };
             let Ok((_, mut article)) = parse_message(&msg) else {
                 Span::current().record("outcome", "rejected_parse");
                 write_simple(&mut ctx.writer, &streaming_response(439, id)).await?;
                 return Ok(());
             };
 
-            if ctx.storage.get_article_by_id(id).await?.is_some() {
-                Span::current().record("outcome", "already_have");
-                write_simple(&mut ctx.writer, &streaming_response(439, id)).await?;
-                return Ok(());
+            match already_have(ctx, id).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    Span::current().record("outcome", "already_have");
+                    write_simple(&mut ctx.writer, &streaming_response(439, id)).await?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    storage_failed(&ctx.queue, &e);
+                    write_simple(&mut ctx.writer, &streaming_response(431, id)).await?;
+                    return Ok(());
+                }
             }
 
             // Check if this is a control message first
@@ -152,9 +416,22 @@ impl CommandHandler for TakeThisHandler {
             Span::current().record("is_control", is_control);
 
             let cfg_guard = ctx.config.read().await;
+            // Refuse articles that have passed through us before, so that
+            // peering loops cannot circulate them for ever
+            if rewrite::path_names_any(&article, &cfg_guard.path_identities()) {
+                Span::current().record("outcome", "rejected_path_loop");
+                history::remember_rejection(&*ctx.storage, id).await;
+                write_simple(&mut ctx.writer, &streaming_response(439, id)).await?;
+                return Ok(());
+            }
             ensure_message_id(&mut article, &cfg_guard.site_name);
             parse::ensure_date(&mut article);
             parse::escape_message_id_header(&mut article);
+            rewrite::add_path(
+                &mut article,
+                &cfg_guard.site_name,
+                rewrite::POSTED_PATH_TAIL,
+            );
 
             // Handle control messages immediately without comprehensive validation
             if is_control {
@@ -170,7 +447,7 @@ impl CommandHandler for TakeThisHandler {
             }
 
             // Comprehensive validation before queuing for TAKETHIS (non-control messages)
-            let size = msg.len() as u64;
+            let size = metadata.size;
             Span::current().record("size_bytes", size);
 
             // Check per-user bandwidth limit (only for authenticated non-admin users)
@@ -180,36 +457,72 @@ impl CommandHandler for TakeThisHandler {
                 return Ok(());
             }
 
-            if comprehensive_validate_article(&ctx.storage, &ctx.auth, &cfg_guard, &article, size)
-                .await
-                .is_err()
-            {
-                Span::current().record("outcome", "rejected_validation");
-                write_simple(&mut ctx.writer, &streaming_response(439, id)).await?;
+            if cfg_guard.transit_validation == TransitValidation::Deferred {
+                let code = match defer_validation(
+                    &ctx.storage,
+                    &ctx.auth,
+                    &ctx.queue,
+                    &cfg_guard,
+                    id,
+                    article,
+                    &metadata,
+                )
+                .await?
+                {
+                    Deferred::Rejected | Deferred::Archived => 439,
+                    Deferred::QueueFull => 431,
+                    Deferred::Queued => 239,
+                };
+                write_simple(&mut ctx.writer, &streaming_response(code, id)).await?;
                 return Ok(());
             }
+
+            let screened = screen(
+                &ctx.storage,
+                &ctx.auth,
+                &ctx.queue,
+                &cfg_guard,
+                id,
+                &mut article,
+                &metadata,
+            )
+            .await;
             drop(cfg_guard);
+            let code = match screened {
+                Screened::Accepted => None,
+                Screened::Quarantined => Some(239),
+                Screened::Retry => Some(431),
+                Screened::Rejected | Screened::Archived => Some(439),
+            };
+            if let Some(code) = code {
+                write_simple(&mut ctx.writer, &streaming_response(code, id)).await?;
+                return Ok(());
+            }
 
             // Submit to queue for background storage and immediate storage for protocol compliance
-            let queued_article = crate::queue::QueuedArticle {
+            let queued_article = QueuedArticle {
                 message: article.clone(),
                 size,
                 is_control: false, // Control messages are handled above, so this is always false
                 already_validated: true, // TAKETHIS does comprehensive validation before queuing
             };
 
+            // Ask the peer to send it again once the queue has drained
+            if ctx.queue.is_full() {
+                Span::current().record("outcome", "deferred_queue_full");
+                write_simple(&mut ctx.writer, &streaming_response(431, id)).await?;
+                return Ok(());
+            }
+
             // Store immediately for protocol compliance (duplicate TAKETHIS should be detected)
-            if ctx.storage.store_article(&article).await.is_err() {
-                Span::current().record("outcome", "rejected_storage");
-                write_simple(&mut ctx.writer, &streaming_response(439, id)).await?;
+            if let Err(e) = ctx.storage.store_article(&article).await {
+                storage_failed(&ctx.queue, &e);
+                write_simple(&mut ctx.writer, &streaming_response(431, id)).await?;
                 return Ok(());
             }
 
             // Also queue for background processing consistency
-            let _ = ctx.queue.submit(queued_article).await; // Don't fail if queue is full since we already stored
-
-            // Record bandwidth usage for authenticated non-admin users
-            record_bandwidth_usage(&ctx.session, &ctx.usage_tracker, size, true).await;
+            let _ = ctx.queue.try_submit(queued_article).await; // Don't wait for room since we already stored
 
             Span::current().record("outcome", "accepted");
             write_simple(&mut ctx.writer, &streaming_response(239, id)).await?;
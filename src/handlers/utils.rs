@@ -1,6 +1,8 @@
 //! Utility functions for command handlers.
 
 use crate::Message;
+use crate::config::{Config, ReadAccess};
+use crate::filters::Poster;
 use crate::limits::{LimitCheckResult, UsageTracker};
 use crate::session::Session;
 use crate::storage::DynStorage;
@@ -9,7 +11,8 @@ use smallvec::SmallVec;
 use std::error::Error;
 use std::fmt;
 use std::sync::Arc;
-use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::RwLock;
 use tracing::Span;
 
 /// Extract newsgroups from message headers.
@@ -83,19 +86,92 @@ impl fmt::Display for ArticleQueryError {
 impl Error for ArticleQueryError {}
 
 /// Write a simple response line to the writer.
+///
+/// The writer is not flushed, so that responses to pipelined commands can
+/// be sent together.
 pub async fn write_simple<W: AsyncWrite + Unpin>(writer: &mut W, response: &str) -> Result<()> {
     use tokio::io::AsyncWriteExt;
-    writer.write_all(response.as_bytes()).await?;
-    writer.flush().await?;
+    writer
+        .write_all(crate::responses::localize(response).as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// Replace any `Xref` header of `article` with one listing its number in
+/// each group it is stored in. Articles stored in a single group get none.
+pub async fn add_xref_header(
+    storage: &DynStorage,
+    site_name: &str,
+    article: &mut Message,
+) -> Result<()> {
+    article
+        .headers
+        .retain(|(k, _)| !k.eq_ignore_ascii_case("Xref"));
+    let Some(id) = extract_message_id(article) else {
+        return Ok(());
+    };
+    let numbers = storage.get_article_numbers(&id).await?;
+    if let Some(xref) = xref_value(site_name, &numbers) {
+        article.headers.push(("Xref".into(), xref));
+    }
     Ok(())
 }
 
-/// Send article headers to the writer.
+/// The Xref header listing the `numbers` of an article in each group it is
+/// stored in, or `None` if it is stored in a single group.
+#[must_use]
+pub fn xref_value(site_name: &str, numbers: &[(String, u64)]) -> Option<String> {
+    if numbers.len() < 2 {
+        return None;
+    }
+    let mut xref = site_name.to_string();
+    for (group, number) in numbers {
+        xref.push_str(&format!(" {group}:{number}"));
+    }
+    Some(xref)
+}
+
+/// Replace any `X-Archive-Until` header of `article` with the time the
+/// retention of its groups, or an earlier `Expires` header, will remove it.
+/// Articles kept indefinitely in any of their groups get none.
+pub async fn add_archive_until_header(
+    storage: &DynStorage,
+    config: &RwLock<Config>,
+    article: &mut Message,
+) -> Result<()> {
+    article
+        .headers
+        .retain(|(k, _)| !k.eq_ignore_ascii_case("X-Archive-Until"));
+    let Some(id) = extract_message_id(article) else {
+        return Ok(());
+    };
+    let arrivals = storage.get_article_arrivals(&id).await?;
+    let mut until = None;
+    {
+        let cfg = config.read().await;
+        for (group, arrived, pinned) in arrivals {
+            let Some(retention) = cfg.retention_for_group(&group).filter(|_| !pinned) else {
+                return Ok(());
+            };
+            until = until.max(arrived.checked_add_signed(retention));
+        }
+    }
+    if let Some(expires) = crate::retention::parse_expires_header(article) {
+        until = until.map(|until| until.min(expires));
+    }
+    if let Some(until) = until {
+        article
+            .headers
+            .push(("X-Archive-Until".into(), until.to_rfc2822()));
+    }
+    Ok(())
+}
+
+/// Send article headers to the writer, folding long header lines.
 pub async fn send_headers<W: AsyncWrite + Unpin>(writer: &mut W, article: &Message) -> Result<()> {
     for (name, val) in &article.headers {
-        writer.write_all(name.as_bytes()).await?;
-        writer.write_all(b": ").await?;
-        writer.write_all(val.as_bytes()).await?;
+        let line = crate::rewrite::fold_header(name, val);
+        writer.write_all(line.as_bytes()).await?;
         writer.write_all(b"\r\n").await?;
     }
     Ok(())
@@ -114,29 +190,64 @@ pub async fn send_body<W: AsyncWrite + Unpin>(writer: &mut W, body: &str) -> Res
 }
 
 /// Get metadata value for an article.
-pub async fn metadata_value(storage: &DynStorage, msg: &Message, name: &str) -> Option<String> {
+///
+/// `:binary` is only known when `detect_binaries` is set.
+#[must_use]
+pub fn metadata_value(msg: &Message, name: &str, detect_binaries: bool) -> Option<String> {
     match name {
-        ":bytes" => {
-            if let Some(id) = extract_message_id(msg) {
-                storage
-                    .get_message_size(&id)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|s| s.to_string())
-                    .or_else(|| Some((msg.body.len() as u64).to_string()))
-            } else {
-                Some((msg.body.len() as u64).to_string())
-            }
-        }
-        ":lines" => Some(msg.body.lines().count().to_string()),
+        ":bytes" => Some(crate::overview::article_size(msg).to_string()),
+        ":lines" => Some(crate::overview::article_lines(msg).to_string()),
+        ":binary" if detect_binaries => crate::overview::binary_encoding(msg),
         _ => None,
     }
 }
 
+/// Whether the client of `session` may read the article with Message-ID
+/// `id`, that is whether it is filed in a group the client may read.
+///
+/// # Errors
+///
+/// Returns an error if the storage cannot be read.
+pub async fn may_read_message_id(
+    storage: &DynStorage,
+    config: &RwLock<Config>,
+    session: &Session,
+    id: &str,
+) -> Result<bool> {
+    let access = session_read_access(&*config.read().await, session);
+    if access.allows_all() {
+        return Ok(true);
+    }
+    let numbers = storage.get_article_numbers(id).await?;
+    Ok(numbers.is_empty() || numbers.iter().any(|(group, _)| access.allows(group)))
+}
+
+/// Look up the article with Message-ID `id` for the client of `session`.
+///
+/// Every command taking a Message-ID resolves it here, so that articles
+/// filed only in groups the client may not read are not found, as GROUP
+/// does not find the groups themselves.
+pub async fn article_by_id(
+    storage: &DynStorage,
+    config: &RwLock<Config>,
+    session: &Session,
+    id: &str,
+) -> Result<Message, ArticleQueryError> {
+    let article = storage
+        .get_article_by_id(id)
+        .await
+        .map_err(|_| ArticleQueryError::MessageIdNotFound)?
+        .ok_or(ArticleQueryError::MessageIdNotFound)?;
+    match may_read_message_id(storage, config, session, id).await {
+        Ok(true) => Ok(article),
+        _ => Err(ArticleQueryError::MessageIdNotFound),
+    }
+}
+
 /// Resolve articles based on argument (number, range, or message-id).
 pub async fn resolve_articles(
     storage: &DynStorage,
+    config: &RwLock<Config>,
     session: &mut Session,
     arg: Option<&str>,
 ) -> Result<Vec<(u64, Message)>, ArticleQueryError> {
@@ -145,15 +256,7 @@ pub async fn resolve_articles(
     if let Some(arg) = arg {
         if arg.starts_with('<') && arg.ends_with('>') {
             // Message-ID
-            if let Some(article) = storage
-                .get_article_by_id(arg)
-                .await
-                .map_err(|_| ArticleQueryError::MessageIdNotFound)?
-            {
-                articles.push((0, article));
-            } else {
-                return Err(ArticleQueryError::MessageIdNotFound);
-            }
+            articles.push((0, article_by_id(storage, config, session, arg).await?));
         } else if let Some(group) = session.current_group().map(|s| s.to_string()) {
             // Article number or range
             let nums = crate::parse_range(storage, &group, arg)
@@ -253,6 +356,7 @@ pub async fn handle_article_operation<W: AsyncWrite + Unpin>(
     writer: &mut W,
     storage: &DynStorage,
     session: &mut Session,
+    config: &RwLock<Config>,
     args: &[String],
     operation: ArticleOperation,
     bandwidth_ctx: Option<BandwidthContext>,
@@ -271,17 +375,25 @@ pub async fn handle_article_operation<W: AsyncWrite + Unpin>(
         }
     }
 
-    match resolve_articles(storage, session, args.first().map(String::as_str)).await {
+    match resolve_articles(storage, config, session, args.first().map(String::as_str)).await {
         Ok(articles) => {
-            for (num, article) in articles {
+            for (num, mut article) in articles {
                 let id = extract_message_id(&article).unwrap_or_default();
+                if matches!(
+                    operation,
+                    ArticleOperation::Full | ArticleOperation::Headers
+                ) {
+                    let site_name = config.read().await.site_name.clone();
+                    add_xref_header(storage, &site_name, &mut article).await?;
+                    add_archive_until_header(storage, config, &mut article).await?;
+                }
 
                 // Record resolved message_id if we didn't have it from args
                 if args.first().is_none_or(|a| !a.starts_with('<')) {
                     Span::current().record("message_id", id.as_str());
                 }
 
-                // Calculate article size for bandwidth tracking
+                // Calculate article size for the bandwidth check
                 // Only track bandwidth for Full, Headers, and Body operations (not Stat)
                 let article_size = match operation {
                     ArticleOperation::Full => {
@@ -349,16 +461,6 @@ pub async fn handle_article_operation<W: AsyncWrite + Unpin>(
                         // STAT just sends the status line, no content
                     }
                 }
-
-                // Record bandwidth usage after successful send
-                if article_size > 0 {
-                    if let Some(ref bw_ctx) = bandwidth_ctx {
-                        bw_ctx
-                            .tracker
-                            .record_bandwidth(&bw_ctx.username, article_size, false)
-                            .await;
-                    }
-                }
             }
             Span::current().record("outcome", "success");
         }
@@ -393,22 +495,6 @@ pub async fn check_bandwidth_rejected<W: AsyncWrite + Unpin>(
     Ok(false)
 }
 
-/// Record bandwidth usage for authenticated non-admin users.
-pub async fn record_bandwidth_usage(
-    session: &Session,
-    usage_tracker: &std::sync::Arc<UsageTracker>,
-    size: u64,
-    is_upload: bool,
-) {
-    if session.is_authenticated() && !session.is_admin() {
-        if let Some(username) = session.username() {
-            usage_tracker
-                .record_bandwidth(username, size, is_upload)
-                .await;
-        }
-    }
-}
-
 /// Handle errors from article queries consistently.
 pub async fn handle_article_error<W: AsyncWrite + Unpin>(
     writer: &mut W,
@@ -479,24 +565,28 @@ pub async fn write_lines<W: AsyncWrite + Unpin>(writer: &mut W, lines: &[&str])
     Ok(())
 }
 
+pub use crate::article_reader::{ArticleBlock, ArticleMetadata, read_article_block};
+
 /// Read a message from the reader until dot termination.
+///
+/// This reads without a size limit; connection handlers should prefer
+/// [`read_article_block`], which enforces the configured cap.
 pub async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<String> {
-    let mut msg = String::new();
-    let mut line = String::new();
-
-    loop {
-        line.clear();
-        reader.read_line(&mut line).await?;
-        if line == ".\r\n" || line == ".\n" {
-            break;
-        }
-        if line.starts_with("..") {
-            msg.push_str(&line[1..]);
-        } else {
-            msg.push_str(&line);
-        }
+    match read_article_block(reader, None).await? {
+        ArticleBlock::Complete { text, .. } => Ok(text),
+        ArticleBlock::TooLarge { .. } => unreachable!("no size limit was given"),
+        ArticleBlock::Malformed { reason } => Err(reason.into()),
     }
-    Ok(msg)
+}
+
+/// Read a dot-terminated article from the reader and discard it.
+///
+/// Used when a command followed by an article, such as TAKETHIS, is refused
+/// before the article is read, so that its lines are not taken for commands.
+pub async fn drain_article<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<()> {
+    // Nothing fits within a limit of zero bytes, so nothing is buffered
+    read_article_block(reader, Some(0)).await?;
+    Ok(())
 }
 
 /// Perform basic validation on an article before queuing
@@ -555,10 +645,64 @@ pub async fn validate_article_with_filters(
     filter_chain: &crate::filters::FilterChain,
 ) -> Result<()> {
     filter_chain
-        .validate(storage, auth, cfg, article, size)
+        .validate(storage, auth, cfg, article, size, None)
         .await
 }
 
+/// The filter chain configured in `cfg.filters`.
+///
+/// Falls back to the default chain if the configuration is invalid, so a bad
+/// filter block never lets articles through unchecked.
+pub fn configured_filter_chain(
+    cfg: &crate::config::Config,
+) -> std::sync::Arc<crate::filters::FilterChain> {
+    match crate::filters::factory::cached_filter_chain(&cfg.filters) {
+        Ok(chain) => chain,
+        Err(e) => {
+            tracing::error!("Failed to create filter chain: {}", e);
+            std::sync::Arc::new(crate::filters::FilterChain::default())
+        }
+    }
+}
+
+/// Validate an article with the configured filter chain, passing along the
+/// metadata gathered by [`read_article_block`] so filters can use it directly.
+///
+/// Returns the verdict of the filters, which the caller applies with
+/// [`crate::filters::FilterVerdict::apply`] before storing the article.
+/// `poster` is the local client posting the article, or `None` for an
+/// article received from a peer.
+pub async fn validate_article_with_metadata(
+    storage: &crate::storage::DynStorage,
+    auth: &crate::auth::DynAuth,
+    cfg: &crate::config::Config,
+    article: &crate::Message,
+    metadata: &ArticleMetadata,
+    poster: Option<Poster<'_>>,
+) -> Result<crate::filters::FilterVerdict> {
+    configured_filter_chain(cfg)
+        .evaluate_with_metadata(storage, auth, cfg, article, metadata, poster)
+        .await
+}
+
+/// The poster of articles posted on `session`.
+#[must_use]
+pub fn session_poster(session: &Session) -> Poster<'_> {
+    Poster {
+        user: session
+            .is_authenticated()
+            .then(|| session.username())
+            .flatten(),
+        admin: session.is_admin(),
+    }
+}
+
+/// The groups the client of `session` may read.
+#[must_use]
+pub fn session_read_access(cfg: &crate::config::Config, session: &Session) -> ReadAccess {
+    cfg.read_access(session_poster(session).user, session.is_admin())
+}
+
 /// Write a formatted response line efficiently, avoiding format! allocations where possible
 pub async fn write_response_with_args<W: AsyncWrite + Unpin>(
     writer: &mut W,
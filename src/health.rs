@@ -0,0 +1,435 @@
+//! Health and readiness endpoints for container orchestration.
+//!
+//! When `health_addr` is set, the server answers plain HTTP requests on that
+//! address so that Kubernetes, compose and similar supervisors can watch it:
+//!
+//! - `GET /healthz` answers `200` while the storage and authentication
+//!   databases can be reached and `503` once either has gone away, so that
+//!   a liveness probe restarts the server;
+//! - `GET /readyz` additionally answers `503` while the article queue is
+//!   full, while no NNTP listener is accepting connections or while the
+//!   server is draining, so that a readiness probe sends clients elsewhere.
+//!
+//! Both report the individual checks as gauges in the OpenMetrics text
+//! format, along with the hit and miss counters of the article cache when
+//! it is enabled, the processing-time histogram of each article worker and
+//! counts of the offers declined as duplicates or deferred because the
+//! storage failed, so the endpoints can be scraped as well as probed. The endpoints
+//! carry no credentials and reveal little, but they should still listen on
+//! loopback or a cluster-internal address.
+//!
+//! `renews healthcheck [--ready]` probes the endpoints of the local server
+//! and exits non-zero when the check fails, for images without an HTTP
+//! client.
+
+use crate::auth::DynAuth;
+use crate::queue::{ArticleQueue, IngestStats, WORKER_BUCKETS, WorkerHistogram};
+use crate::server::ConnectionTracker;
+use crate::storage::DynStorage;
+use crate::storage::cache::{ArticleCache, CacheStats};
+use anyhow::{Result, anyhow};
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+/// How long a database may take to answer before it counts as gone.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long `renews healthcheck` waits for the server.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request line plus headers accepted, in bytes.
+const MAX_HEAD_BYTES: u64 = 8 * 1024;
+
+/// Content type of the OpenMetrics text format.
+const OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Result of checking the server's components.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthStatus {
+    /// The storage database answered
+    pub storage_up: bool,
+    /// The authentication database answered
+    pub auth_up: bool,
+    /// Articles waiting in the article queue
+    pub queue_length: usize,
+    /// Largest number of articles the queue holds
+    pub queue_capacity: usize,
+    /// NNTP listeners accepting connections
+    pub listeners: usize,
+    /// New connections are being turned away
+    pub draining: bool,
+    /// Counters of the article cache, if it is enabled
+    pub article_cache: Option<CacheStats>,
+    /// Article workers running
+    pub workers: usize,
+    /// Time each article worker has taken over its batches, by worker number
+    pub worker_histograms: Vec<(usize, WorkerHistogram)>,
+    /// Offers declined as duplicates or deferred by the streaming handlers
+    pub ingest: IngestStats,
+}
+
+impl HealthStatus {
+    /// Whether the server is alive: its databases can be reached.
+    #[must_use]
+    pub fn is_live(&self) -> bool {
+        self.storage_up && self.auth_up
+    }
+
+    /// Whether the server should be sent clients.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.is_live()
+            && self.queue_length < self.queue_capacity
+            && self.listeners > 0
+            && !self.draining
+    }
+
+    /// Render the checks as OpenMetrics gauges.
+    #[must_use]
+    pub fn to_openmetrics(&self) -> String {
+        let gauges = [
+            (
+                "renews_storage_up",
+                "Whether the storage database can be reached.",
+                u64::from(self.storage_up),
+            ),
+            (
+                "renews_auth_up",
+                "Whether the authentication database can be reached.",
+                u64::from(self.auth_up),
+            ),
+            (
+                "renews_queue_length",
+                "Articles waiting in the article queue.",
+                self.queue_length as u64,
+            ),
+            (
+                "renews_queue_capacity",
+                "Largest number of articles the article queue holds.",
+                self.queue_capacity as u64,
+            ),
+            (
+                "renews_listeners",
+                "NNTP listeners accepting connections.",
+                self.listeners as u64,
+            ),
+            (
+                "renews_draining",
+                "Whether new connections are turned away.",
+                u64::from(self.draining),
+            ),
+            (
+                "renews_queue_workers",
+                "Article workers running.",
+                self.workers as u64,
+            ),
+            (
+                "renews_ready",
+                "Whether the server is ready for clients.",
+                u64::from(self.is_ready()),
+            ),
+        ];
+        let mut out = String::new();
+        for (name, help, value) in gauges {
+            let _ = write!(
+                out,
+                "# TYPE {name} gauge\n# HELP {name} {help}\n{name} {value}\n"
+            );
+        }
+        let ingest = [
+            (
+                "renews_ingest_pending_duplicates",
+                "Offers declined because the article was queued awaiting validation.",
+                self.ingest.pending_duplicates,
+            ),
+            (
+                "renews_ingest_duplicates",
+                "Offers declined because the article was stored or in the history.",
+                self.ingest.duplicates,
+            ),
+            (
+                "renews_ingest_storage_deferrals",
+                "Transfers peers were asked to retry because the storage failed.",
+                self.ingest.storage_deferrals,
+            ),
+        ];
+        for (name, help, value) in ingest {
+            let _ = write!(
+                out,
+                "# TYPE {name} counter\n# HELP {name} {help}\n{name}_total {value}\n"
+            );
+        }
+        if let Some(stats) = &self.article_cache {
+            let counters = [
+                (
+                    "renews_article_cache_hits",
+                    "Article lookups answered from the article cache.",
+                    stats.hits,
+                ),
+                (
+                    "renews_article_cache_misses",
+                    "Article lookups the article cache could not answer.",
+                    stats.misses,
+                ),
+                (
+                    "renews_article_cache_evictions",
+                    "Articles evicted from the article cache to make room.",
+                    stats.evictions,
+                ),
+                (
+                    "renews_article_cache_expirations",
+                    "Articles dropped from the article cache after their time to live.",
+                    stats.expirations,
+                ),
+            ];
+            for (name, help, value) in counters {
+                let _ = write!(
+                    out,
+                    "# TYPE {name} counter\n# HELP {name} {help}\n{name}_total {value}\n"
+                );
+            }
+            let _ = write!(
+                out,
+                "# TYPE renews_article_cache_bytes gauge\n# HELP renews_article_cache_bytes Size of the articles in the article cache.\nrenews_article_cache_bytes {}\n",
+                stats.bytes
+            );
+        }
+        if !self.worker_histograms.is_empty() {
+            out.push_str(
+                "# TYPE renews_worker_batch_seconds histogram\n# HELP renews_worker_batch_seconds Time article workers take over a batch of articles.\n",
+            );
+        }
+        for (worker, histogram) in &self.worker_histograms {
+            let mut cumulative = 0;
+            for (bound, count) in WORKER_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "renews_worker_batch_seconds_bucket{{worker=\"{worker}\",le=\"{bound}\"}} {cumulative}"
+                );
+            }
+            let _ = write!(
+                out,
+                "renews_worker_batch_seconds_bucket{{worker=\"{worker}\",le=\"+Inf\"}} {count}\n\
+                 renews_worker_batch_seconds_count{{worker=\"{worker}\"}} {count}\n\
+                 renews_worker_batch_seconds_sum{{worker=\"{worker}\"}} {sum}\n",
+                count = histogram.count,
+                sum = histogram.sum_micros as f64 / 1e6,
+            );
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Checks the server's components for the health endpoints.
+pub struct HealthCheck {
+    storage: DynStorage,
+    auth: DynAuth,
+    queue: ArticleQueue,
+    tracker: Arc<ConnectionTracker>,
+    article_cache: Option<Arc<ArticleCache>>,
+}
+
+impl HealthCheck {
+    #[must_use]
+    pub fn new(
+        storage: DynStorage,
+        auth: DynAuth,
+        queue: ArticleQueue,
+        tracker: Arc<ConnectionTracker>,
+    ) -> Self {
+        Self {
+            storage,
+            auth,
+            queue,
+            tracker,
+            article_cache: None,
+        }
+    }
+
+    /// Also report the counters of `cache`.
+    #[must_use]
+    pub fn with_article_cache(mut self, cache: Arc<ArticleCache>) -> Self {
+        self.article_cache = Some(cache);
+        self
+    }
+
+    /// Check every component.
+    pub async fn check(&self) -> HealthStatus {
+        let (storage, auth) = tokio::join!(
+            tokio::time::timeout(PING_TIMEOUT, self.storage.ping()),
+            tokio::time::timeout(PING_TIMEOUT, self.auth.ping()),
+        );
+        let storage_up = reachable("storage", storage);
+        let auth_up = reachable("authentication", auth);
+        HealthStatus {
+            storage_up,
+            auth_up,
+            queue_length: self.queue.len(),
+            queue_capacity: self.queue.capacity(),
+            listeners: self.tracker.active_listeners(),
+            draining: self.tracker.is_draining(),
+            article_cache: self.article_cache.as_ref().map(|cache| cache.stats()),
+            workers: self.queue.worker_count(),
+            worker_histograms: self.queue.worker_histograms(),
+            ingest: self.queue.ingest_stats(),
+        }
+    }
+
+    /// Accept probes on `listener` until it fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting a connection fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let health = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = health.handle(stream).await {
+                    debug!(peer = %peer, error = %e, "Health probe failed");
+                }
+            });
+        }
+    }
+
+    /// Answer the one request carried by `stream`.
+    async fn handle(&self, stream: TcpStream) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut reader = BufReader::new(read.take(MAX_HEAD_BYTES));
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        // Skip the headers; the endpoints take no input
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let path = parts.next().unwrap_or_default();
+        let path = path.split('?').next().unwrap_or_default();
+        let (status, content_type, body) = match (method, path) {
+            ("GET" | "HEAD", "/healthz" | "/readyz") => {
+                let status = self.check().await;
+                let ok = if path == "/healthz" {
+                    status.is_live()
+                } else {
+                    status.is_ready()
+                };
+                (
+                    if ok { 200 } else { 503 },
+                    OPENMETRICS,
+                    status.to_openmetrics(),
+                )
+            }
+            (_, "/healthz" | "/readyz") => (405, "text/plain", "method not allowed\n".to_string()),
+            _ => (404, "text/plain", "not found\n".to_string()),
+        };
+        let reason = match status {
+            200 => "OK",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Service Unavailable",
+        };
+        let mut response = format!(
+            "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        if method != "HEAD" {
+            response.push_str(&body);
+        }
+        write.write_all(response.as_bytes()).await?;
+        write.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// Whether a database ping finished in time and succeeded, logging why not.
+fn reachable(
+    database: &str,
+    result: std::result::Result<Result<()>, tokio::time::error::Elapsed>,
+) -> bool {
+    match result {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            warn!(database, error = %e, "Health check failed");
+            false
+        }
+        Err(_) => {
+            warn!(database, "Health check timed out");
+            false
+        }
+    }
+}
+
+/// Listen for probes on the `health_addr` address `raw`.
+///
+/// # Errors
+///
+/// Returns an error if the address cannot be bound.
+pub async fn bind(raw: &str) -> Result<TcpListener> {
+    let addr = crate::config::listen_addr(raw);
+    TcpListener::bind(&addr).await.map_err(|e| {
+        anyhow!(
+            "Failed to bind to health check address '{addr}': {e}
+
+You can change the health check listen address in your configuration file using the 'health_addr' setting
+or disable the health endpoints by removing the 'health_addr' configuration."
+        )
+    })
+}
+
+/// Request `path` from the health endpoints at the `health_addr` address
+/// `raw` and return the status code and body.
+///
+/// Wildcard addresses are probed on loopback.
+///
+/// # Errors
+///
+/// Returns an error if the server cannot be reached or does not answer in
+/// time.
+pub async fn probe(raw: &str, path: &str) -> Result<(u16, String)> {
+    let addr = crate::config::listen_addr(raw);
+    let addr = if let Some(port) = addr.strip_prefix("0.0.0.0:") {
+        format!("127.0.0.1:{port}")
+    } else if let Some(port) = addr.strip_prefix("[::]:") {
+        format!("[::1]:{port}")
+    } else {
+        addr
+    };
+    let exchange = async {
+        let mut stream = TcpStream::connect(&addr).await?;
+        stream
+            .write_all(
+                format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n")
+                    .as_bytes(),
+            )
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok::<_, anyhow::Error>(response)
+    };
+    let response = tokio::time::timeout(PROBE_TIMEOUT, exchange)
+        .await
+        .map_err(|_| anyhow!("Health check of '{addr}' timed out"))?
+        .map_err(|e| anyhow!("Failed to reach health check address '{addr}': {e}"))?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow!("Malformed health check response from '{addr}'"))?;
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_string())
+        .unwrap_or_default();
+    Ok((status, body))
+}
@@ -0,0 +1,32 @@
+//! Message-ID history.
+//!
+//! Like INN's history file, the storage remembers the Message-IDs of
+//! articles it no longer holds: those deleted by retention, cancels or
+//! administrators, and those refused when offered by a peer. IHAVE, CHECK,
+//! TAKETHIS and peer pulls treat a remembered article as one the server
+//! already has, so peers cannot feed back spam that was removed. Entries are
+//! forgotten `history_retention_days` after they are recorded, by the
+//! retention cleanup.
+
+use crate::storage::Storage;
+use anyhow::Result;
+use tracing::warn;
+
+/// Whether the server holds `message_id` or remembers it in the history.
+///
+/// # Errors
+///
+/// Returns an error if the storage cannot be read.
+pub async fn seen(storage: &dyn Storage, message_id: &str) -> Result<bool> {
+    Ok(storage.get_article_by_id(message_id).await?.is_some()
+        || storage.in_history(message_id).await?)
+}
+
+/// Remember that an offer of `message_id` was refused, so the same article
+/// is not accepted later. Failures are logged rather than returned, as the
+/// article has already been refused.
+pub async fn remember_rejection(storage: &dyn Storage, message_id: &str) {
+    if let Err(e) = storage.remember_message_id(message_id).await {
+        warn!(message_id = message_id, error = %e, "Failed to record rejected article in history");
+    }
+}
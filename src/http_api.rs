@@ -0,0 +1,571 @@
+//! HTTP API for posting and reading articles.
+//!
+//! A small JSON interface over HTTP/1.1 for web applications and bots that do
+//! not speak NNTP:
+//!
+//! - `GET /groups` lists the newsgroups with their descriptions;
+//! - `GET /articles/<message-id>` returns an article, with the Message-ID
+//!   percent-encoded and the angle brackets optional;
+//! - `POST /articles` posts the raw article text sent as the request body.
+//!
+//! Posted articles take the same path as NNTP `POST` (see [`crate::local_post`]):
+//! headers are rewritten, unapproved posts to moderated groups are held for a
+//! moderator, and the article is checked by the filter chain before it is
+//! handed to the article queue. When the queue is full the post is refused
+//! with `503` and a `Retry-After` header. Posting requires HTTP Basic
+//! credentials unless anonymous posting is enabled; anonymous posts carry the
+//! posting account of the client address, which may be banned. Each connection carries one request. The API speaks plain HTTP,
+//! so it should listen on loopback or behind a TLS-terminating proxy.
+
+use crate::audit::{self, AuditAction, AuditEntry};
+use crate::auth::DynAuth;
+use crate::config::{Config, listen_addr};
+use crate::filters::Poster;
+use crate::handlers::utils::{ArticleMetadata, get_header_value};
+use crate::limits::{LimitCheckResult, UsageTracker};
+use crate::local_post::{self, Screened};
+use crate::queue::{ArticleQueue, QueuedArticle};
+use crate::storage::DynStorage;
+use crate::{control, parse_message, posting_account};
+use anyhow::Result;
+use base64::Engine;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+use tracing::{debug, error, info};
+
+/// Largest request line plus headers accepted, in bytes.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Shared state of the HTTP API.
+pub struct HttpApi {
+    storage: DynStorage,
+    auth: DynAuth,
+    config: Arc<RwLock<Config>>,
+    queue: ArticleQueue,
+    usage_tracker: Arc<UsageTracker>,
+}
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// An HTTP response with a JSON body.
+struct Response {
+    status: u16,
+    body: Value,
+    challenge: bool,
+    retry_after: Option<Duration>,
+}
+
+impl Response {
+    fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            body,
+            challenge: false,
+            retry_after: None,
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self::json(status, json!({ "error": message.to_string() }))
+    }
+
+    /// A 401 response asking for Basic credentials.
+    fn unauthorized() -> Self {
+        Self {
+            challenge: true,
+            ..Self::error(401, "authentication required")
+        }
+    }
+
+    /// A 503 response asking the client to try again after `after`.
+    fn unavailable(message: &str, after: Duration) -> Self {
+        Self {
+            retry_after: Some(after),
+            ..Self::error(503, message)
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        422 => "Unprocessable Content",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Outcome of reading a request from a connection.
+enum ReadOutcome {
+    Request(Request),
+    Reject(Response),
+}
+
+impl HttpApi {
+    /// Create the API over the server's shared components.
+    pub fn new(
+        storage: DynStorage,
+        auth: DynAuth,
+        config: Arc<RwLock<Config>>,
+        queue: ArticleQueue,
+        usage_tracker: Arc<UsageTracker>,
+    ) -> Self {
+        Self {
+            storage,
+            auth,
+            config,
+            queue,
+            usage_tracker,
+        }
+    }
+
+    /// Accept connections on `listener` until it fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting a connection fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let api = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = api.handle_connection(stream).await {
+                    debug!(peer = %peer, error = %e, "HTTP API client error");
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream) -> Result<()> {
+        let peer = stream.peer_addr().ok().map(|addr| addr.ip());
+        let (read, mut write) = stream.into_split();
+        let mut reader = BufReader::new(read);
+        let max_body = self.config.read().await.max_message_bytes;
+        let response = match read_request(&mut reader, max_body).await? {
+            ReadOutcome::Request(request) => match self.route(&request, peer).await {
+                Ok(response) => response,
+                Err(e) => {
+                    error!(error = %e, "HTTP API request failed");
+                    Response::error(500, "internal error")
+                }
+            },
+            ReadOutcome::Reject(response) => response,
+        };
+        write.write_all(&encode_response(&response)).await?;
+        write.shutdown().await?;
+        Ok(())
+    }
+
+    async fn route(&self, request: &Request, peer: Option<IpAddr>) -> Result<Response> {
+        let path = request.path.split('?').next().unwrap_or_default();
+        match (request.method.as_str(), path) {
+            ("GET", "/groups") => self.list_groups().await,
+            ("POST", "/articles") => self.post_article(request, peer).await,
+            ("GET", path) if path.starts_with("/articles/") => {
+                self.get_article(&path["/articles/".len()..]).await
+            }
+            (_, "/groups" | "/articles") => Ok(Response::error(405, "method not allowed")),
+            (_, path) if path.starts_with("/articles/") => {
+                Ok(Response::error(405, "method not allowed"))
+            }
+            _ => Ok(Response::error(404, "not found")),
+        }
+    }
+
+    async fn list_groups(&self) -> Result<Response> {
+        // Readers are anonymous, so groups with read restrictions are hidden
+        let access = self.config.read().await.read_access(None, false);
+        let mut groups = Vec::new();
+        let mut stream = self.storage.list_groups_with_descriptions();
+        while let Some(result) = stream.next().await {
+            let (name, description) = result?;
+            if !access.allows(&name) {
+                continue;
+            }
+            groups.push(json!({ "name": name, "description": description }));
+        }
+        Ok(Response::json(200, Value::Array(groups)))
+    }
+
+    async fn get_article(&self, raw_id: &str) -> Result<Response> {
+        let Some(decoded) = percent_decode(raw_id) else {
+            return Ok(Response::error(400, "invalid message-id"));
+        };
+        let message_id = if decoded.starts_with('<') {
+            decoded
+        } else {
+            format!("<{decoded}>")
+        };
+        let Some(article) = self.storage.get_article_by_id(&message_id).await? else {
+            return Ok(Response::error(404, "no such article"));
+        };
+        let numbers = self.storage.get_article_numbers(&message_id).await?;
+        let access = self.config.read().await.read_access(None, false);
+        if !numbers.is_empty() && !numbers.iter().any(|(group, _)| access.allows(group)) {
+            return Ok(Response::error(404, "no such article"));
+        }
+        let groups: Vec<Value> = numbers
+            .into_iter()
+            .filter(|(group, _)| access.allows(group))
+            .map(|(group, number)| json!({ "group": group, "number": number }))
+            .collect();
+        let headers: Vec<Value> = article
+            .headers
+            .iter()
+            .map(|(name, value)| json!([name, value]))
+            .collect();
+        Ok(Response::json(
+            200,
+            json!({
+                "message_id": message_id,
+                "groups": groups,
+                "headers": headers,
+                "body": article.body,
+            }),
+        ))
+    }
+
+    async fn post_article(&self, request: &Request, peer: Option<IpAddr>) -> Result<Response> {
+        // Authenticate the poster, falling back to anonymous posting
+        let username = match request.header("Authorization") {
+            Some(value) => match basic_credentials(value) {
+                Some((user, pass)) if self.auth.verify_user(&user, &pass).await? => Some(user),
+                _ => return Ok(Response::unauthorized()),
+            },
+            None if self.config.read().await.allow_anonymous_posting => None,
+            None => return Ok(Response::unauthorized()),
+        };
+        let limited_user = match &username {
+            Some(user) if !self.auth.is_admin(user).await? => Some(user.as_str()),
+            _ => None,
+        };
+        if let Some(user) = limited_user
+            && self.usage_tracker.can_post(user).await == LimitCheckResult::PostingDisabled
+        {
+            return Ok(Response::error(403, "posting not allowed"));
+        }
+        let poster = Poster {
+            user: username.as_deref(),
+            admin: username.is_some() && limited_user.is_none(),
+        };
+        // Anonymous posters are known by the posting account of their address
+        let posting_account = {
+            let cfg = self.config.read().await;
+            let token = peer
+                .filter(|_| username.is_none() && cfg.allow_anonymous_posting)
+                .map(|ip| posting_account::current_token(&cfg.posting_accounts, ip));
+            if token
+                .as_deref()
+                .is_some_and(|token| posting_account::is_banned(&cfg.posting_accounts, token))
+            {
+                return Ok(Response::error(403, "posting not allowed"));
+            }
+            token
+        };
+
+        let Ok(text) = std::str::from_utf8(&request.body) else {
+            return Ok(Response::error(400, "article is not valid UTF-8"));
+        };
+        let text = if text.contains("\r\n") {
+            text.to_string()
+        } else {
+            text.replace('\n', "\r\n")
+        };
+        let metadata = ArticleMetadata::from_text(&text);
+        let Ok((_, mut message)) = parse_message(&text) else {
+            return Ok(Response::error(400, "malformed article"));
+        };
+
+        if let Some(user) = limited_user
+            && self
+                .usage_tracker
+                .check_bandwidth(user, metadata.size)
+                .await
+                == LimitCheckResult::BandwidthExceeded
+        {
+            return Ok(Response::error(403, "bandwidth limit exceeded"));
+        }
+
+        let is_control = control::is_control_message(&message);
+        let cfg_guard = self.config.read().await;
+        local_post::prepare(
+            &mut message,
+            &cfg_guard,
+            poster.user,
+            posting_account.as_deref(),
+        );
+        let message_id = get_header_value(&message, "Message-ID").unwrap_or_default();
+        let screened = local_post::screen(
+            &self.storage,
+            &self.auth,
+            &cfg_guard,
+            &mut message,
+            &metadata,
+            Some(poster),
+        )
+        .await?;
+        drop(cfg_guard);
+
+        let entry = AuditEntry::for_article(AuditAction::Post, &message)
+            .by(username.as_deref())
+            .from_addr(peer);
+        match screened {
+            Screened::Accepted => {}
+            Screened::Held | Screened::Quarantined => {
+                self.record_upload(limited_user, metadata.size).await;
+                audit::record(&*self.storage, entry).await;
+                info!(message_id = %message_id, "HTTP API article held for a moderator");
+                return Ok(Response::json(
+                    202,
+                    json!({ "message_id": message_id, "status": "held" }),
+                ));
+            }
+            Screened::Refused(e) => return Ok(Response::error(422, e)),
+        }
+
+        let queued = QueuedArticle {
+            message,
+            size: metadata.size,
+            is_control,
+            already_validated: true,
+        };
+        if self.queue.try_submit(queued).await.is_err() {
+            return Ok(Response::unavailable(
+                "article queue full",
+                self.queue.retry_after(),
+            ));
+        }
+        self.record_upload(limited_user, metadata.size).await;
+        audit::record(&*self.storage, entry).await;
+        info!(message_id = %message_id, "HTTP API article queued");
+        Ok(Response::json(
+            202,
+            json!({ "message_id": message_id, "status": "queued" }),
+        ))
+    }
+
+    async fn record_upload(&self, user: Option<&str>, size: u64) {
+        if let Some(user) = user {
+            self.usage_tracker.record_bandwidth(user, size, true).await;
+        }
+    }
+}
+
+/// Read one request, rejecting oversized heads and bodies.
+async fn read_request<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    max_body: Option<u64>,
+) -> Result<ReadOutcome> {
+    let mut head_bytes = 0usize;
+    let mut line = String::new();
+    let mut lines = Vec::new();
+    loop {
+        line.clear();
+        let read = (&mut *reader)
+            .take((MAX_HEAD_BYTES - head_bytes + 1) as u64)
+            .read_line(&mut line)
+            .await?;
+        if read == 0 {
+            return Err(anyhow::anyhow!("connection closed before end of request"));
+        }
+        head_bytes += read;
+        if head_bytes > MAX_HEAD_BYTES {
+            return Ok(ReadOutcome::Reject(Response::error(
+                431,
+                "request head too large",
+            )));
+        }
+        let trimmed = line.trim_end_matches(['\r', '\n']);
+        if trimmed.is_empty() {
+            break;
+        }
+        lines.push(trimmed.to_string());
+    }
+
+    let mut lines = lines.into_iter();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(path), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Ok(ReadOutcome::Reject(Response::error(
+            400,
+            "bad request line",
+        )));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Ok(ReadOutcome::Reject(Response::error(
+            400,
+            "unsupported version",
+        )));
+    }
+    let mut headers = Vec::new();
+    for header in lines {
+        let Some((name, value)) = header.split_once(':') else {
+            return Ok(ReadOutcome::Reject(Response::error(400, "bad header")));
+        };
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        headers,
+        body: Vec::new(),
+    };
+
+    if request.header("Transfer-Encoding").is_some() {
+        return Ok(ReadOutcome::Reject(Response::error(
+            411,
+            "chunked bodies are not supported",
+        )));
+    }
+    let length = match request.header("Content-Length") {
+        Some(value) => match value.parse::<u64>() {
+            Ok(length) => length,
+            Err(_) => return Ok(ReadOutcome::Reject(Response::error(400, "bad length"))),
+        },
+        None if request.method == "POST" => {
+            return Ok(ReadOutcome::Reject(Response::error(411, "length required")));
+        }
+        None => 0,
+    };
+    if max_body.is_some_and(|max| length > max) {
+        return Ok(ReadOutcome::Reject(Response::error(
+            413,
+            "article too large",
+        )));
+    }
+    let mut body = Vec::new();
+    (&mut *reader).take(length).read_to_end(&mut body).await?;
+    if (body.len() as u64) < length {
+        return Err(anyhow::anyhow!("connection closed before end of body"));
+    }
+    request.body = body;
+    Ok(ReadOutcome::Request(request))
+}
+
+fn encode_response(response: &Response) -> Vec<u8> {
+    let body = response.body.to_string();
+    let mut out = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        body.len()
+    );
+    if response.challenge {
+        out.push_str("WWW-Authenticate: Basic realm=\"renews\"\r\n");
+    }
+    if let Some(after) = response.retry_after {
+        out.push_str(&format!("Retry-After: {}\r\n", after.as_secs().max(1)));
+    }
+    out.push_str("\r\n");
+    out.push_str(&body);
+    out.into_bytes()
+}
+
+/// Decode the username and password of a `Basic` Authorization header.
+fn basic_credentials(value: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Basic") {
+        return None;
+    }
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, pass) = decoded.split_once(':')?;
+    Some((user.to_string(), pass.to_string()))
+}
+
+/// Decode `%XX` escapes in a path segment.
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut out = Vec::with_capacity(segment.len());
+    let mut bytes = segment.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Listen on the configured `http_addr` and serve the API.
+///
+/// # Errors
+///
+/// Returns an error if the address cannot be bound or accepting fails.
+pub async fn run_http_api(api: Arc<HttpApi>) -> Result<()> {
+    let Some(raw) = api.config.read().await.http_addr.clone() else {
+        return Ok(());
+    };
+    let addr = listen_addr(&raw);
+    let listener = TcpListener::bind(&addr).await.map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to bind to HTTP API address '{addr}': {e}
+
+You can change the HTTP API listen address in your configuration file using the 'http_addr' setting
+or disable the HTTP API by removing the 'http_addr' configuration."
+        )
+    })?;
+    info!("listening HTTP API on {addr}");
+    api.serve(listener).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_basic_credentials() {
+        assert_eq!(
+            basic_credentials("Basic dXNlcjpwYTpzcw=="),
+            Some(("user".into(), "pa:ss".into()))
+        );
+        assert_eq!(basic_credentials("Bearer dXNlcjpwYXNz"), None);
+        assert_eq!(basic_credentials("Basic !!!"), None);
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(
+            percent_decode("%3Cid%40example.com%3E").as_deref(),
+            Some("<id@example.com>")
+        );
+        assert_eq!(percent_decode("plain@id").as_deref(), Some("plain@id"));
+        assert_eq!(percent_decode("%4"), None);
+        assert_eq!(percent_decode("%zz"), None);
+    }
+}
@@ -0,0 +1,202 @@
+//! Import of articles from mbox files and maildirs.
+//!
+//! `renews admin import` reads articles back from the mboxes and maildirs
+//! written by `renews admin export`, or by other servers and mail archives,
+//! to seed a new server. Articles are stored [`IMPORT_BATCH`] at a time with
+//! [`Storage::store_articles`], which the PostgreSQL backend loads with
+//! `COPY`. Articles already stored or in the history are skipped, so an
+//! interrupted import can be run again, as are articles without a
+//! Message-ID and those posted to no group the server carries.
+
+use crate::Message;
+use crate::handlers::utils::get_header_value;
+use crate::storage::Storage;
+use crate::storage::common::parse_newsgroups_from_message;
+use anyhow::{Result, anyhow};
+use futures_util::TryStreamExt;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Articles stored in one transaction.
+pub const IMPORT_BATCH: usize = 500;
+
+/// Outcome of an import.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Articles stored
+    pub imported: u64,
+    /// Articles already stored or in the history
+    pub duplicates: u64,
+    /// Articles that could not be read, had no Message-ID, were posted to
+    /// no carried group or were refused by the storage
+    pub skipped: u64,
+}
+
+/// The messages of an mbox, as text with CRLF line endings.
+///
+/// A line starting with `From ` at the start of the file or after an empty
+/// line begins a message. Body lines quoted as `>From `, with any number of
+/// `>`, lose one `>` as in the mboxrd variant the export writes.
+pub fn mbox_messages<R: BufRead>(reader: R) -> impl Iterator<Item = Result<String>> {
+    let mut lines = reader.split(b'\n');
+    let mut current: Option<String> = None;
+    let mut previous_empty = true;
+    let mut done = false;
+    std::iter::from_fn(move || {
+        while !done {
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Some(Err(e.into())),
+                None => {
+                    done = true;
+                    return current.take().map(finish_mbox_message).map(Ok);
+                }
+            };
+            let line = String::from_utf8_lossy(&line);
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+            if previous_empty && line.starts_with("From ") {
+                previous_empty = false;
+                if let Some(message) = current.replace(String::new()) {
+                    return Some(Ok(finish_mbox_message(message)));
+                }
+                continue;
+            }
+            previous_empty = line.is_empty();
+            if let Some(message) = current.as_mut() {
+                let unquoted = match line.trim_start_matches('>').starts_with("From ") {
+                    true if line.starts_with('>') => &line[1..],
+                    _ => line,
+                };
+                message.push_str(unquoted);
+                message.push_str("\r\n");
+            }
+        }
+        None
+    })
+}
+
+/// Drop the empty line separating a message from the next.
+fn finish_mbox_message(mut message: String) -> String {
+    if message.ends_with("\r\n\r\n") {
+        message.truncate(message.len() - 2);
+    }
+    message
+}
+
+/// The messages of the `new` and `cur` directories of the maildir at
+/// `root`, in file name order, as text with CRLF line endings.
+///
+/// # Errors
+///
+/// Returns an error if the directories cannot be listed.
+pub fn maildir_messages(root: &Path) -> Result<impl Iterator<Item = Result<String>>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for dir in ["new", "cur"] {
+        let path = root.join(dir);
+        let entries = std::fs::read_dir(&path)
+            .map_err(|e| anyhow!("Failed to read '{}': {e}", path.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files.into_iter().map(|path| {
+        let bytes = std::fs::read(&path)
+            .map_err(|e| anyhow!("Failed to read '{}': {e}", path.display()))?;
+        let text = String::from_utf8_lossy(&bytes);
+        Ok(text
+            .split_inclusive('\n')
+            .map(|line| {
+                let line = line.trim_end_matches('\n');
+                format!("{}\r\n", line.strip_suffix('\r').unwrap_or(line))
+            })
+            .collect())
+    }))
+}
+
+/// Store the articles of `messages` that are new and posted to a carried
+/// group, [`IMPORT_BATCH`] at a time.
+///
+/// # Errors
+///
+/// Returns an error if a message cannot be read or the storage cannot be
+/// read.
+pub async fn import_articles(
+    storage: &dyn Storage,
+    messages: impl Iterator<Item = Result<String>>,
+) -> Result<ImportSummary> {
+    let carried: HashSet<String> = storage.list_groups().try_collect().await?;
+    let mut summary = ImportSummary::default();
+    let mut seen = HashSet::new();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH);
+    for text in messages {
+        let text = text?;
+        let article = match crate::parse_message(&text) {
+            Ok((_, article)) => article,
+            Err(e) => {
+                warn!(error = ?e, "Skipping unreadable message");
+                summary.skipped += 1;
+                continue;
+            }
+        };
+        let Some(id) = get_header_value(&article, "Message-ID") else {
+            summary.skipped += 1;
+            continue;
+        };
+        if !parse_newsgroups_from_message(&article)
+            .iter()
+            .any(|group| carried.contains(group))
+        {
+            summary.skipped += 1;
+            continue;
+        }
+        if !seen.insert(id.clone())
+            || storage.get_message_size(&id).await?.is_some()
+            || storage.in_history(&id).await?
+        {
+            summary.duplicates += 1;
+            continue;
+        }
+        batch.push(article);
+        if batch.len() == IMPORT_BATCH {
+            store(storage, &mut batch, &mut summary).await?;
+        }
+    }
+    store(storage, &mut batch, &mut summary).await?;
+    Ok(summary)
+}
+
+/// Store `batch` in one transaction or, if that fails, one article at a
+/// time, so that one article the storage refuses is skipped rather than
+/// ending the import, as the article queue does.
+async fn store(
+    storage: &dyn Storage,
+    batch: &mut Vec<Message>,
+    summary: &mut ImportSummary,
+) -> Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    match storage.store_articles(batch).await {
+        Ok(()) => summary.imported += batch.len() as u64,
+        Err(e) => {
+            warn!(error = %e, count = batch.len(), "Failed to store article batch, storing articles one at a time");
+            for article in batch.iter() {
+                match storage.store_article(article).await {
+                    Ok(()) => summary.imported += 1,
+                    Err(e) => {
+                        warn!(error = %e, "Skipping article the storage refused");
+                        summary.skipped += 1;
+                    }
+                }
+            }
+        }
+    }
+    batch.clear();
+    Ok(())
+}
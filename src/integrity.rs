@@ -0,0 +1,222 @@
+//! Storage integrity checks.
+//!
+//! A crash or a disk filling up at the wrong moment can leave the storage
+//! tables disagreeing with each other without any error being reported:
+//! article numbers pointing at messages that are gone, overview rows that
+//! are missing, out of date or left behind by deleted articles, recorded
+//! message sizes that no longer match the stored article, and watermarks
+//! that do not frame the articles of a group. `renews admin check-store`
+//! walks every group through the [`Storage`] trait, so that it works on
+//! every backend, and reports what it finds.
+//!
+//! With `--repair`, groups with dangling article numbers, overview
+//! problems or a lagging low watermark are passed to
+//! [`Storage::rebuild_overview`] and checked again. Size mismatches and a
+//! high watermark below the highest article number cannot be repaired this
+//! way and are only reported.
+
+use crate::handlers::utils::get_header_value;
+use crate::overview::{article_size, format_overview_line};
+use crate::storage::Storage;
+use anyhow::Result;
+use futures_util::TryStreamExt;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Article numbers whose overview rows are read at once.
+const OVERVIEW_CHUNK: u64 = 1000;
+
+/// What checking one group found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupCheck {
+    /// Name of the group
+    pub group: String,
+    /// Article numbers checked
+    pub checked: u64,
+    /// Article numbers whose message no longer exists
+    pub dangling: Vec<u64>,
+    /// Articles without an overview row
+    pub missing_overview: Vec<u64>,
+    /// Articles whose overview row does not match the stored article
+    pub stale_overview: Vec<u64>,
+    /// Overview rows left behind by articles no longer in the group
+    pub orphan_overview: Vec<u64>,
+    /// Articles whose recorded size is not that of the stored article, as
+    /// `(number, recorded, actual)`
+    pub size_mismatches: Vec<(u64, u64, u64)>,
+    /// The low watermark is below the lowest article number, or not above
+    /// the high one in a group whose articles have all gone
+    pub low_water_lags: bool,
+    /// Articles numbered below the low watermark or above the high one
+    pub outside_watermarks: Vec<u64>,
+}
+
+impl GroupCheck {
+    /// Whether nothing is wrong with the group.
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.problems() == 0
+    }
+
+    /// Number of problems found.
+    #[must_use]
+    pub fn problems(&self) -> usize {
+        self.dangling.len()
+            + self.missing_overview.len()
+            + self.stale_overview.len()
+            + self.orphan_overview.len()
+            + self.size_mismatches.len()
+            + usize::from(self.low_water_lags)
+            + self.outside_watermarks.len()
+    }
+
+    /// Whether [`Storage::rebuild_overview`] would repair some of the
+    /// problems.
+    #[must_use]
+    pub fn is_repairable(&self) -> bool {
+        !self.dangling.is_empty()
+            || !self.missing_overview.is_empty()
+            || !self.stale_overview.is_empty()
+            || !self.orphan_overview.is_empty()
+            || self.low_water_lags
+    }
+}
+
+impl fmt::Display for GroupCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} checked, {} dangling, {} missing overview, {} stale overview, \
+             {} orphan overview, {} size mismatches, {} outside watermarks",
+            self.group,
+            self.checked,
+            self.dangling.len(),
+            self.missing_overview.len(),
+            self.stale_overview.len(),
+            self.orphan_overview.len(),
+            self.size_mismatches.len(),
+            self.outside_watermarks.len(),
+        )?;
+        if self.low_water_lags {
+            write!(f, ", low watermark lags")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the articles, overview rows and watermarks of `group`, formatting
+/// expected overview rows with the `extra` overview headers.
+///
+/// # Errors
+///
+/// Returns an error if the storage cannot be read or the group does not
+/// exist.
+pub async fn check_group(
+    storage: &dyn Storage,
+    group: &str,
+    extra: &[String],
+) -> Result<GroupCheck> {
+    let watermarks = storage
+        .get_group_watermarks(group)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No such group: {group}"))?;
+    let numbers: Vec<u64> = storage.list_article_numbers(group).try_collect().await?;
+    let mut check = GroupCheck {
+        group: group.to_string(),
+        ..GroupCheck::default()
+    };
+
+    for &number in &numbers {
+        check.checked += 1;
+        let Some(article) = storage.get_article_by_number(group, number).await? else {
+            check.dangling.push(number);
+            continue;
+        };
+        let actual = article_size(&article);
+        let recorded = match get_header_value(&article, "Message-ID") {
+            Some(id) => storage.get_message_size(&id).await?,
+            None => None,
+        };
+        if let Some(recorded) = recorded.filter(|recorded| *recorded != actual) {
+            check.size_mismatches.push((number, recorded, actual));
+        }
+        let expected = format_overview_line(number, &article, extra);
+        match storage
+            .get_overview_range(group, number, number)
+            .await?
+            .first()
+        {
+            None => check.missing_overview.push(number),
+            Some(line) if *line != expected => check.stale_overview.push(number),
+            Some(_) => {}
+        }
+    }
+
+    // Overview rows of numbers no article has, including those past the
+    // watermarks
+    let present: BTreeSet<u64> = numbers.iter().copied().collect();
+    let last = numbers.last().copied().unwrap_or(0).max(watermarks.high);
+    let mut start = 0;
+    loop {
+        let end = if start > last {
+            u64::MAX
+        } else {
+            start.saturating_add(OVERVIEW_CHUNK - 1)
+        };
+        for line in storage.get_overview_range(group, start, end).await? {
+            let number = line.split('\t').next().and_then(|n| n.parse::<u64>().ok());
+            if let Some(number) = number.filter(|number| !present.contains(number)) {
+                check.orphan_overview.push(number);
+            }
+        }
+        if end == u64::MAX {
+            break;
+        }
+        start = end + 1;
+    }
+
+    // Once every article has gone the low watermark sits above the high one
+    check.low_water_lags = match present.first() {
+        Some(&lowest) => watermarks.low < lowest,
+        None => watermarks.high > 0 && watermarks.low <= watermarks.high,
+    };
+    check.outside_watermarks = present
+        .iter()
+        .filter(|&&number| number < watermarks.low || number > watermarks.high)
+        .copied()
+        .collect();
+    Ok(check)
+}
+
+/// Check every group matching `wildmat`, rebuilding the overview of those
+/// with repairable problems if `repair` is set and reporting the state they
+/// were left in.
+///
+/// # Errors
+///
+/// Returns an error if the storage cannot be read or repaired.
+pub async fn check_store(
+    storage: &dyn Storage,
+    wildmat: &str,
+    extra: &[String],
+    repair: bool,
+) -> Result<Vec<(GroupCheck, Option<GroupCheck>)>> {
+    let groups: Vec<String> = storage
+        .list_groups()
+        .try_filter(|group| std::future::ready(crate::wildmat::wildmat(wildmat, group)))
+        .try_collect()
+        .await?;
+
+    let mut results = Vec::with_capacity(groups.len());
+    for group in groups {
+        let check = check_group(storage, &group, extra).await?;
+        let after = if repair && check.is_repairable() {
+            storage.rebuild_overview(&group).await?;
+            Some(check_group(storage, &group, extra).await?)
+        } else {
+            None
+        };
+        results.push((check, after));
+    }
+    Ok(results)
+}
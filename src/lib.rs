@@ -1,41 +1,84 @@
 pub mod parse;
 pub use parse::{
     Command, Message, Response, ensure_date, ensure_message_id, parse_command, parse_datetime,
-    parse_message, parse_range, parse_response,
+    parse_datetime_at, parse_message, parse_range, parse_response,
 };
 
+pub mod access;
+pub mod article_reader;
+pub mod audit;
 pub mod auth;
+pub mod bridge;
+pub mod cancel_lock;
+pub mod checkgroups;
+pub mod client_cert;
+pub mod compress;
 pub mod config;
 pub mod control;
+pub mod control_socket;
+pub mod digest;
+pub mod embedded;
 pub mod error;
+pub mod export;
+pub mod feed;
 pub mod filters;
+pub mod gateway;
+pub mod groups;
 pub mod handlers;
+pub mod health;
+pub mod history;
+#[cfg(feature = "http-api")]
+pub mod http_api;
+pub mod import;
+pub mod integrity;
 pub mod limits;
+pub mod listener;
+pub mod local_post;
+pub mod maintenance;
+pub mod migrations;
+pub mod moderation;
+pub mod nocem;
 pub mod overview;
 pub mod peers;
+pub mod posting_account;
 pub mod prelude;
+pub mod protocol_trace;
 pub mod queue;
+pub mod rejected;
+pub mod replication;
 pub mod responses;
+pub mod resume;
 pub mod retention;
+pub mod rewrite;
 pub mod server;
 pub mod session;
 pub mod storage;
+pub mod thread;
+pub mod tls;
+pub mod transport;
+pub mod vhost;
 pub mod wildmat;
 #[cfg(feature = "websocket")]
 pub mod ws;
 
 use crate::auth::DynAuth;
-use crate::config::Config;
-use crate::handlers::{HandlerContext, dispatch_command};
-use crate::limits::UsageTracker;
+use crate::config::{Config, ListenerPolicy, ListenerRole};
+use crate::handlers::{HandlerContext, ProgressWriter, ResponseProgress, dispatch_command};
+use crate::limits::{ByteMeter, LimitCheckResult, Metered, UsageTracker};
+use crate::peers::PeerAddresses;
+use crate::protocol_trace::{ProtocolTracer, Traced};
 use crate::queue::ArticleQueue;
 use crate::session::Session;
 use crate::storage::DynStorage;
+use crate::transport::UpgradableStream;
+use crate::vhost::{Site, VirtualHosts};
 use anyhow::Result;
+use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::io::{self, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::sync::RwLock;
+use tokio_rustls::TlsAcceptor;
 use tracing::{Instrument, debug, info_span};
 
 /// Per-connection cached configuration values.
@@ -46,6 +89,31 @@ struct ConnectionConfig {
     idle_timeout: Duration,
 }
 
+/// Transport details of an accepted client connection.
+#[derive(Clone, Default)]
+pub struct ConnectionInfo {
+    /// The connection is already protected by TLS
+    pub is_tls: bool,
+    /// Acceptor used to upgrade a plaintext connection on STARTTLS
+    pub starttls: Option<TlsAcceptor>,
+    /// Client address, used to derive a posting-account token and recorded
+    /// in the audit log
+    pub peer_ip: Option<IpAddr>,
+    /// Overrides from the `[[listener]]` block that accepted the connection
+    pub policy: ListenerPolicy,
+    /// Names of the verified TLS client certificate, if one was presented
+    pub client_names: Vec<String>,
+    /// Host name the client asked for with TLS SNI
+    pub server_name: Option<String>,
+    /// Sites served besides the main one
+    pub vhosts: Arc<VirtualHosts>,
+    /// Writes the protocol trace of the connection if it is targeted
+    pub trace: Option<Arc<ProtocolTracer>>,
+    /// Resolved addresses of the configured peers, shared by the
+    /// connections of a server
+    pub peer_addresses: Arc<PeerAddresses>,
+}
+
 /// Handle a client connection.
 ///
 /// # Errors
@@ -61,30 +129,180 @@ pub async fn handle_client<S>(
     queue: ArticleQueue,
     usage_tracker: Arc<UsageTracker>,
 ) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let info = ConnectionInfo {
+        is_tls,
+        ..ConnectionInfo::default()
+    };
+    handle_client_with_info(socket, storage, auth, cfg, info, queue, usage_tracker).await
+}
+
+/// Handle a plaintext client connection that may be upgraded with STARTTLS.
+///
+/// `acceptor` performs the TLS handshake when the client issues STARTTLS
+/// (RFC 4642).
+///
+/// # Errors
+///
+/// Returns an error if there's a problem handling the client connection,
+/// including a failed TLS handshake after STARTTLS.
+pub async fn handle_client_with_starttls<S>(
+    socket: S,
+    storage: DynStorage,
+    auth: DynAuth,
+    cfg: Arc<RwLock<Config>>,
+    acceptor: TlsAcceptor,
+    queue: ArticleQueue,
+    usage_tracker: Arc<UsageTracker>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let info = ConnectionInfo {
+        starttls: Some(acceptor),
+        ..ConnectionInfo::default()
+    };
+    handle_client_with_info(socket, storage, auth, cfg, info, queue, usage_tracker).await
+}
+
+/// Handle a client connection described by `info`.
+///
+/// When anonymous posting is allowed and the peer address is known, the
+/// session is given a posting-account token derived from the address. The
+/// address itself is kept only to record posts and administrative actions
+/// in the audit log; on listeners of role `all` it is also matched against
+/// the configured peers, which may feed articles without authenticating. A
+/// client certificate naming a known user authenticates the session before
+/// the greeting. `storage`, `cfg` and `queue` are those of the main site,
+/// which is served unless the listener or the SNI host name selects a
+/// virtual host.
+///
+/// # Errors
+///
+/// Returns an error if there's a problem handling the client connection,
+/// including a failed TLS handshake after STARTTLS.
+pub async fn handle_client_with_info<S>(
+    socket: S,
+    storage: DynStorage,
+    auth: DynAuth,
+    cfg: Arc<RwLock<Config>>,
+    info: ConnectionInfo,
+    queue: ArticleQueue,
+    usage_tracker: Arc<UsageTracker>,
+) -> Result<()>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
     use crate::responses::*;
 
-    let (read_half, write_half) = io::split(socket);
-    let reader = BufReader::new(read_half);
+    let ConnectionInfo {
+        is_tls,
+        starttls,
+        peer_ip,
+        policy,
+        client_names,
+        server_name,
+        vhosts,
+        trace,
+        peer_addresses,
+    } = info;
+
+    let home = Site {
+        storage,
+        config: cfg,
+        queue,
+    };
+    let site = policy
+        .vhost
+        .as_deref()
+        .and_then(|name| vhosts.named(name))
+        .or_else(|| server_name.as_deref().and_then(|host| vhosts.find(host)))
+        .map_or_else(|| home.clone(), |vhost| vhost.site.clone());
 
-    // Cache configuration values at connection start so they don't change mid-connection
-    let (connection_config, allow_auth_insecure, allow_anonymous_posting) = {
-        let cfg_guard = cfg.read().await;
+    // Cache configuration values at connection start so they don't change mid-connection.
+    // Listener overrides take precedence over the global settings.
+    // With TLS required for authentication, the port of the TLS listener
+    // is named to clients refused on plaintext
+    let (
+        connection_config,
+        allow_auth_insecure,
+        tls_required,
+        allow_anonymous_posting,
+        posting_account,
+        catalog,
+        peer_rules,
+    ) = {
+        let cfg_guard = site.config.read().await;
+        let allow_anonymous_posting = policy
+            .allow_anonymous_posting
+            .unwrap_or(cfg_guard.allow_anonymous_posting);
+        let posting_account = peer_ip
+            .filter(|_| allow_anonymous_posting)
+            .map(|ip| posting_account::current_token(&cfg_guard.posting_accounts, ip));
         (
             ConnectionConfig {
                 site_name: cfg_guard.site_name.clone(),
-                idle_timeout: Duration::from_secs(cfg_guard.idle_timeout_secs),
+                idle_timeout: Duration::from_secs(
+                    policy
+                        .idle_timeout_secs
+                        .unwrap_or(cfg_guard.idle_timeout_secs),
+                ),
+            },
+            policy
+                .allow_auth_insecure_connections
+                .unwrap_or(cfg_guard.allow_auth_insecure_connections),
+            cfg_guard.require_tls_for_auth.then(|| cfg_guard.tls_port()),
+            allow_anonymous_posting,
+            posting_account,
+            {
+                let catalog = match policy.locale.as_deref() {
+                    Some(locale) => {
+                        responses::ResponseCatalog::for_locale(&cfg_guard.responses, locale)
+                    }
+                    None => responses::ResponseCatalog::from_config(&cfg_guard.responses),
+                };
+                (!catalog.is_empty()).then(|| Arc::new(catalog))
+            },
+            // Only clients of `all` listeners are told apart by address
+            if policy.role == ListenerRole::All && peer_ip.is_some() {
+                cfg_guard.peers.clone()
+            } else {
+                Vec::new()
             },
-            cfg_guard.allow_auth_insecure_connections,
-            cfg_guard.allow_anonymous_posting,
         )
     };
+    let known_peer = match peer_ip {
+        Some(ip) if !peer_rules.is_empty() => peer_addresses.contains(&peer_rules, ip).await,
+        _ => false,
+    };
 
-    let session = Session::new(is_tls, allow_auth_insecure, allow_anonymous_posting);
+    let mut session = Session::new(is_tls, allow_auth_insecure, allow_anonymous_posting);
+    session.set_starttls_available(starttls.is_some());
+    if let Some(port) = tls_required {
+        session.set_tls_required(port);
+    }
+    session.set_xhost_available(!vhosts.is_empty());
+    session.set_posting_account(posting_account);
+    session.set_peer_ip(peer_ip);
+    session.set_role(policy.role);
+    session.set_known_peer(known_peer);
     let session_id = session.session_id();
 
+    // Both halves share the socket so STARTTLS can swap it for a TLS stream.
+    // Responses are buffered until the reader needs more input, so commands
+    // pipelined in one segment are answered with a single write. The bytes
+    // passing through either half are counted against the user's bandwidth,
+    // and copied to the protocol trace when the connection is traced.
+    let meter = Arc::new(ByteMeter::default());
+    let trace = trace.map(|tracer| tracer.connection(session_id, peer_ip));
+    let stream = Traced::new(
+        Metered::new(UpgradableStream::new(socket), meter.clone()),
+        trace.clone(),
+    );
+    let reader = BufReader::new(stream.clone());
+
     // Create session span - NO client_addr for GDPR compliance
     let session_span = info_span!(
         "session",
@@ -94,28 +312,37 @@ where
         duration_ms = tracing::field::Empty,
     );
 
-    // Run the connection handling within the session span
-    async move {
+    // Run the connection handling within the session span, answering in the
+    // locale of the listener when it has one, else in that of the site
+    let session = async move {
         let start = Instant::now();
         let mut commands_processed: u64 = 0;
 
+        let progress = Arc::new(ResponseProgress::default());
         let mut ctx = HandlerContext {
             reader: Box::pin(reader),
-            writer: Box::pin(write_half),
-            storage,
+            writer: Box::pin(ProgressWriter::new(stream.clone(), progress.clone())),
+            storage: site.storage,
             auth,
-            config: cfg,
+            config: site.config,
             session,
-            queue,
+            queue: site.queue,
             usage_tracker,
+            progress,
         };
+        crate::handlers::auth::authenticate_certificate(&mut ctx, &client_names).await;
+        if let Some(trace) = &trace {
+            trace.set_user(ctx.session.username());
+        }
 
         // Send greeting - reflects current posting ability
         if ctx.session.can_post() {
-            ctx.writer.write_all(RESP_200_READY.as_bytes()).await?;
+            ctx.writer
+                .write_all(localize(RESP_200_READY).as_bytes())
+                .await?;
         } else {
             ctx.writer
-                .write_all(RESP_201_READY_NO_POST.as_bytes())
+                .write_all(localize(RESP_201_READY_NO_POST).as_bytes())
                 .await?;
         }
 
@@ -148,8 +375,23 @@ where
             }
 
             let trimmed = line.trim_end_matches(['\r', '\n']);
+
+            // In cancel mode every line but QUIT names an article to cancel
+            if ctx.session.is_cancel_mode() && !trimmed.trim().eq_ignore_ascii_case("QUIT") {
+                commands_processed += 1;
+                if let Err(e) = crate::handlers::auth::cancel_article(&mut ctx, trimmed).await {
+                    debug!(error = %e, "Cancel failed");
+                    ctx.writer
+                        .write_all(localize(RESP_484_CANCEL_FAILED).as_bytes())
+                        .await?;
+                }
+                continue;
+            }
+
             let Ok((_, cmd)) = parse_command(trimmed) else {
-                ctx.writer.write_all(RESP_500_SYNTAX.as_bytes()).await?;
+                ctx.writer
+                    .write_all(localize(RESP_500_SYNTAX).as_bytes())
+                    .await?;
                 continue;
             };
 
@@ -166,7 +408,9 @@ where
             // Handle QUIT specially since it needs to break the loop
             if cmd.name.as_str() == "QUIT" {
                 async {
-                    ctx.writer.write_all(RESP_205_CLOSING.as_bytes()).await?;
+                    ctx.writer
+                        .write_all(localize(RESP_205_CLOSING).as_bytes())
+                        .await?;
                     ctx.writer.flush().await
                 }
                 .instrument(cmd_span.clone())
@@ -175,34 +419,171 @@ where
                 break;
             }
 
+            // Handle STARTTLS specially since it replaces the reader and writer
+            if cmd.name.as_str() == "STARTTLS" {
+                async { start_tls(&mut ctx, &stream, starttls.as_ref()).await }
+                    .instrument(cmd_span.clone())
+                    .await?;
+                cmd_span.record("duration_ms", cmd_start.elapsed().as_millis() as u64);
+                continue;
+            }
+
+            // Handle XHOST specially since it replaces the site being served
+            if cmd.name.as_str() == "XHOST" {
+                async { select_host(&mut ctx, &vhosts, &home, &cmd.args).await }
+                    .instrument(cmd_span.clone())
+                    .await?;
+                cmd_span.record("duration_ms", cmd_start.elapsed().as_millis() as u64);
+                continue;
+            }
+
             // Dispatch command within span
             let result = async { dispatch_command(&mut ctx, &cmd).await }
                 .instrument(cmd_span.clone())
                 .await;
 
             cmd_span.record("duration_ms", cmd_start.elapsed().as_millis() as u64);
+            if let Some(trace) = &trace {
+                trace.set_user(ctx.session.username());
+            }
 
+            // Failed commands have been answered; what remains ends the session
             if let Err(e) = result {
-                // Log the error but continue processing other commands
-                debug!(command = %cmd.name, error = %e, "Command failed");
+                debug!(command = %cmd.name, error = %e, "Ending session");
+                break;
+            }
+
+            if charge_traffic(&ctx.session, &ctx.usage_tracker, &meter).await
+                == LimitCheckResult::BandwidthExceeded
+            {
+                debug!("Bandwidth limit exceeded; closing connection");
+                ctx.writer
+                    .write_all(localize(RESP_502_BANDWIDTH_EXCEEDED).as_bytes())
+                    .await?;
+                break;
             }
         }
 
+        // Deliver responses still buffered when the client went away or timed out
+        let _ = ctx.writer.flush().await;
+        charge_traffic(&ctx.session, &ctx.usage_tracker, &meter).await;
+
         // Record final session metrics
         tracing::Span::current().record("commands_processed", commands_processed);
         tracing::Span::current().record("duration_ms", start.elapsed().as_millis() as u64);
 
-        // Clean up connection tracking for authenticated users (admins are not tracked)
-        if ctx.session.is_authenticated() && !ctx.session.is_admin() {
-            if let Some(username) = ctx.session.username() {
-                ctx.usage_tracker.disconnect(username);
-            }
-        }
-
         tracing::info!("Session ended");
 
         Ok(())
     }
-    .instrument(session_span)
-    .await
+    .instrument(session_span);
+    responses::with_catalog(catalog, session).await
+}
+
+/// Charge the traffic of the session since the last call to its user, if
+/// it is authenticated as one whose bandwidth is limited. Traffic before
+/// authentication and of administrators is not charged.
+async fn charge_traffic(
+    session: &Session,
+    usage_tracker: &UsageTracker,
+    meter: &ByteMeter,
+) -> LimitCheckResult {
+    let (uploaded, downloaded) = meter.take();
+    if !session.is_authenticated() || session.is_admin() {
+        return LimitCheckResult::Allowed;
+    }
+    match session.username() {
+        Some(username) => usage_tracker.charge(username, uploaded, downloaded).await,
+        None => LimitCheckResult::Allowed,
+    }
+}
+
+/// Serve the site named by the argument of XHOST for the rest of the
+/// session: a virtual host by its name or a host name, or the main site
+/// `home` by its `site_name`.
+async fn select_host(
+    ctx: &mut HandlerContext,
+    vhosts: &VirtualHosts,
+    home: &Site,
+    args: &[String],
+) -> Result<()> {
+    use crate::responses::*;
+
+    let [host] = args else {
+        ctx.writer
+            .write_all(localize(RESP_501_SYNTAX).as_bytes())
+            .await?;
+        return Ok(());
+    };
+    if ctx.session.is_authenticated() {
+        ctx.writer
+            .write_all(localize(RESP_502_HOST_AUTHENTICATED).as_bytes())
+            .await?;
+        return Ok(());
+    }
+    let site = match vhosts.find(host) {
+        Some(vhost) => vhost.site.clone(),
+        None if home
+            .config
+            .read()
+            .await
+            .site_name
+            .eq_ignore_ascii_case(host) =>
+        {
+            home.clone()
+        }
+        None => {
+            ctx.writer
+                .write_all(localize(RESP_501_UNKNOWN_HOST).as_bytes())
+                .await?;
+            return Ok(());
+        }
+    };
+
+    ctx.storage = site.storage;
+    ctx.config = site.config;
+    ctx.queue = site.queue;
+    // Groups of one site mean nothing on another
+    ctx.session.leave_group();
+    ctx.writer
+        .write_all(localize(RESP_290_HOST_SELECTED).as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// Upgrade the connection to TLS in response to STARTTLS (RFC 4642).
+///
+/// Any plaintext the client pipelined after the command is discarded along
+/// with the old reader, so it can never be mistaken for protected input.
+async fn start_tls(
+    ctx: &mut HandlerContext,
+    stream: &Traced<Metered<UpgradableStream>>,
+    acceptor: Option<&TlsAcceptor>,
+) -> Result<()> {
+    use crate::responses::*;
+
+    if ctx.session.is_tls() || ctx.session.is_authenticated() {
+        ctx.writer
+            .write_all(localize(RESP_502_TLS_ACTIVE).as_bytes())
+            .await?;
+        return Ok(());
+    }
+    let Some(acceptor) = acceptor else {
+        ctx.writer
+            .write_all(localize(RESP_580_TLS_UNAVAILABLE).as_bytes())
+            .await?;
+        return Ok(());
+    };
+
+    ctx.writer
+        .write_all(localize(RESP_382_CONTINUE_TLS).as_bytes())
+        .await?;
+    ctx.writer.flush().await?;
+    let client_names = stream.get_ref().get_ref().start_tls(acceptor).await?;
+
+    ctx.reader = Box::pin(BufReader::new(stream.clone()));
+    ctx.session.start_tls();
+    debug!("TLS negotiated via STARTTLS");
+    crate::handlers::auth::authenticate_certificate(ctx, &client_names).await;
+    Ok(())
 }
@@ -0,0 +1,125 @@
+//! Byte counting on client connections.
+//!
+//! A [`Metered`] stream counts the bytes read from and written through it,
+//! so that every byte a session exchanges, not only article transfers, is
+//! charged against the user's bandwidth allowance. The session takes the
+//! traffic since its last look with [`ByteMeter::take`] after each command.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Bytes read from and written to a connection, shared by its streams.
+#[derive(Debug, Default)]
+pub struct ByteMeter {
+    read: AtomicU64,
+    written: AtomicU64,
+    /// Totals at the last call to `take`
+    taken_read: AtomicU64,
+    taken_written: AtomicU64,
+}
+
+impl ByteMeter {
+    /// Bytes read from the client so far.
+    #[must_use]
+    pub fn read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    /// Bytes written to the client so far.
+    #[must_use]
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    /// Bytes read and written since the last call, as `(read, written)`.
+    pub fn take(&self) -> (u64, u64) {
+        let read = self.read();
+        let written = self.written();
+        let previous_read = self.taken_read.swap(read, Ordering::Relaxed);
+        let previous_written = self.taken_written.swap(written, Ordering::Relaxed);
+        (
+            read.saturating_sub(previous_read),
+            written.saturating_sub(previous_written),
+        )
+    }
+}
+
+/// A stream counting the bytes passing through it in a [`ByteMeter`].
+#[derive(Debug, Clone)]
+pub struct Metered<S> {
+    inner: S,
+    meter: Arc<ByteMeter>,
+}
+
+impl<S> Metered<S> {
+    pub fn new(inner: S, meter: Arc<ByteMeter>) -> Self {
+        Self { inner, meter }
+    }
+
+    /// The wrapped stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Metered<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let n = (buf.filled().len() - before) as u64;
+        self.meter.read.fetch_add(n, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Metered<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.meter.written.fetch_add(n as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn counts_bytes_in_both_directions() {
+        let (client, server) = tokio::io::duplex(64);
+        let meter = Arc::new(ByteMeter::default());
+        let mut metered = Metered::new(server, meter.clone());
+        let mut client = client;
+
+        client.write_all(b"GROUP misc\r\n").await.unwrap();
+        let mut buf = [0u8; 12];
+        metered.read_exact(&mut buf).await.unwrap();
+        metered.write_all(b"211 0 0 0 misc\r\n").await.unwrap();
+
+        assert_eq!((meter.read(), meter.written()), (12, 16));
+        assert_eq!(meter.take(), (12, 16));
+        metered.write_all(b".\r\n").await.unwrap();
+        assert_eq!(meter.take(), (0, 3));
+    }
+}
@@ -6,8 +6,12 @@
 //! - Connection limits (max simultaneous connections)
 //! - Usage tracking with time-windowed resets
 
+mod meter;
+mod registry;
 mod tracker;
 
+pub use meter::{ByteMeter, Metered};
+pub use registry::{ConnectionKey, ConnectionRegistry, ConnectionSlot};
 pub use tracker::UsageTracker;
 
 use chrono::{DateTime, Utc};
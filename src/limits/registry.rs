@@ -0,0 +1,129 @@
+//! Shared registry of open connections.
+//!
+//! Every listener counts client connections here by source address when
+//! they are accepted and by user once they authenticate, so limits hold
+//! across plain, TLS and additional listeners alike. A [`ConnectionSlot`]
+//! is handed out for each counted connection and gives its place back when
+//! dropped, which also covers sessions whose task is aborted.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+
+/// What connections are counted by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConnectionKey {
+    /// An authenticated user
+    User(String),
+    /// A client address
+    Ip(IpAddr),
+}
+
+/// Counts of open connections by user and by client address.
+#[derive(Debug, Default)]
+pub struct ConnectionRegistry {
+    counts: DashMap<ConnectionKey, usize>,
+}
+
+impl ConnectionRegistry {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a connection under `key` unless `max` connections are already
+    /// open, returning the slot that releases it. `None` for `max` means
+    /// unlimited.
+    pub fn try_acquire(
+        self: &Arc<Self>,
+        key: ConnectionKey,
+        max: Option<u32>,
+    ) -> Option<ConnectionSlot> {
+        let max = max.map_or(usize::MAX, |m| m as usize);
+        match self.counts.entry(key.clone()) {
+            Entry::Occupied(mut entry) => {
+                if *entry.get() >= max {
+                    return None;
+                }
+                *entry.get_mut() += 1;
+            }
+            Entry::Vacant(entry) => {
+                if max == 0 {
+                    return None;
+                }
+                entry.insert(1);
+            }
+        }
+        Some(ConnectionSlot {
+            registry: self.clone(),
+            key,
+        })
+    }
+
+    /// Number of open connections counted under `key`.
+    #[must_use]
+    pub fn count(&self, key: &ConnectionKey) -> usize {
+        self.counts.get(key).map_or(0, |count| *count)
+    }
+
+    fn release(&self, key: &ConnectionKey) {
+        if let Entry::Occupied(mut entry) = self.counts.entry(key.clone()) {
+            if *entry.get() <= 1 {
+                entry.remove();
+            } else {
+                *entry.get_mut() -= 1;
+            }
+        }
+    }
+}
+
+/// A connection counted in a [`ConnectionRegistry`], released on drop.
+#[derive(Debug)]
+pub struct ConnectionSlot {
+    registry: Arc<ConnectionRegistry>,
+    key: ConnectionKey,
+}
+
+impl ConnectionSlot {
+    #[must_use]
+    pub fn key(&self) -> &ConnectionKey {
+        &self.key
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.registry.release(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_limited_and_released() {
+        let registry = Arc::new(ConnectionRegistry::new());
+        let user = ConnectionKey::User("alice".into());
+        let first = registry.try_acquire(user.clone(), Some(2)).unwrap();
+        let second = registry.try_acquire(user.clone(), Some(2)).unwrap();
+        assert!(registry.try_acquire(user.clone(), Some(2)).is_none());
+        assert_eq!(registry.count(&user), 2);
+
+        drop(first);
+        let third = registry.try_acquire(user.clone(), Some(2)).unwrap();
+        drop(second);
+        drop(third);
+        assert_eq!(registry.count(&user), 0);
+        assert!(registry.counts.is_empty());
+
+        let ip = ConnectionKey::Ip("192.0.2.1".parse().unwrap());
+        assert!(registry.try_acquire(ip.clone(), Some(0)).is_none());
+        let _unlimited: Vec<_> = (0..10)
+            .map(|_| registry.try_acquire(ip.clone(), None).unwrap())
+            .collect();
+        assert_eq!(registry.count(&ip), 10);
+    }
+}
@@ -1,21 +1,22 @@
 //! Usage tracker for real-time connection and bandwidth tracking.
 //!
 //! The `UsageTracker` maintains in-memory state for:
-//! - Per-user connection counts
+//! - Per-user connection counts, kept in its [`ConnectionRegistry`]
 //! - Per-user bandwidth usage with rolling window support
 //!
 //! Usage is periodically persisted to the database and loaded at startup.
 
 use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
 
 use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
 use tokio::sync::RwLock;
 
+use crate::access::AccessList;
 use crate::auth::DynAuth;
 use crate::config::UserLimitsConfig;
 
+use super::registry::{ConnectionKey, ConnectionRegistry, ConnectionSlot};
 use super::{LimitCheckResult, UserLimits, UserUsage};
 
 /// In-memory bandwidth state for a user.
@@ -42,8 +43,11 @@ impl Default for BandwidthState {
 /// to the database. It is designed to be shared across all connections
 /// via `Arc<UsageTracker>`.
 pub struct UsageTracker {
-    /// Per-user connection counts: username -> atomic count
-    connections: DashMap<String, AtomicUsize>,
+    /// Open connections by user and client address
+    registry: Arc<ConnectionRegistry>,
+
+    /// Address ban and allow lists
+    access: Arc<AccessList>,
 
     /// Per-user bandwidth usage: username -> bandwidth state
     /// Uses Arc to allow cloning the lock out before awaiting, avoiding deadlocks
@@ -63,7 +67,8 @@ impl UsageTracker {
     /// Create a new usage tracker.
     pub fn new(auth: DynAuth, defaults: UserLimitsConfig) -> Self {
         Self {
-            connections: DashMap::new(),
+            registry: Arc::new(ConnectionRegistry::new()),
+            access: Arc::new(AccessList::new(auth.clone())),
             bandwidth: DashMap::new(),
             limits_cache: DashMap::new(),
             defaults: RwLock::new(defaults),
@@ -110,79 +115,36 @@ impl UsageTracker {
         self.limits_cache.remove(username);
     }
 
-    /// Try to establish a new connection for a user.
-    ///
-    /// Returns `Allowed` if the connection is permitted, or `ConnectionLimitExceeded`
-    /// if the user has reached their connection limit.
+    /// Count a new connection for an authenticated user.
     ///
-    /// This method increments the connection count if allowed.
-    pub async fn try_connect(&self, username: &str) -> LimitCheckResult {
+    /// Returns the slot holding the connection's place, which is given back
+    /// when the slot is dropped, or `None` if the user has reached their
+    /// connection limit.
+    pub async fn try_connect(&self, username: &str) -> Option<ConnectionSlot> {
         let limits = self.get_effective_limits(username).await;
+        self.registry.try_acquire(
+            ConnectionKey::User(username.to_string()),
+            limits.max_connections,
+        )
+    }
 
-        // Check if connections are unlimited
-        let Some(max_connections) = limits.max_connections else {
-            // Unlimited - just increment and allow
-            self.connections
-                .entry(username.to_string())
-                .or_insert_with(|| AtomicUsize::new(0))
-                .fetch_add(1, Ordering::SeqCst);
-            return LimitCheckResult::Allowed;
-        };
-
-        // Get or create connection counter
-        let counter = self
-            .connections
-            .entry(username.to_string())
-            .or_insert_with(|| AtomicUsize::new(0));
-
-        // Try to increment if under limit
-        loop {
-            let current = counter.load(Ordering::SeqCst);
-            if current >= max_connections as usize {
-                return LimitCheckResult::ConnectionLimitExceeded;
-            }
-
-            // Try to atomically increment
-            if counter
-                .compare_exchange(current, current + 1, Ordering::SeqCst, Ordering::SeqCst)
-                .is_ok()
-            {
-                return LimitCheckResult::Allowed;
-            }
-            // If CAS failed, another thread modified the counter - retry
-        }
+    /// Get current connection count for a user.
+    #[must_use]
+    pub fn connection_count(&self, username: &str) -> usize {
+        self.registry
+            .count(&ConnectionKey::User(username.to_string()))
     }
 
-    /// Record a disconnection for a user.
-    pub fn disconnect(&self, username: &str) {
-        if let Some(counter) = self.connections.get(username) {
-            let prev = counter.fetch_sub(1, Ordering::SeqCst);
-            // Clean up if this was the last connection
-            if prev == 1 {
-                // Must drop the Ref before calling remove() to avoid deadlock
-                // (get() holds a read lock, remove() needs a write lock on the same shard)
-                drop(counter);
-                // Remove the entry to avoid memory growth
-                // (the entry might have been incremented again by another thread,
-                // so we need to check the value and only remove if still 0)
-                if let Some((_, counter)) = self.connections.remove(username) {
-                    if counter.load(Ordering::SeqCst) > 0 {
-                        // Race: someone connected between our check and remove
-                        // Put it back
-                        self.connections.insert(username.to_string(), counter);
-                    }
-                }
-            }
-        }
+    /// The registry counting open connections by user and client address.
+    #[must_use]
+    pub fn registry(&self) -> &Arc<ConnectionRegistry> {
+        &self.registry
     }
 
-    /// Get current connection count for a user.
+    /// The address ban and allow lists.
     #[must_use]
-    pub fn connection_count(&self, username: &str) -> usize {
-        self.connections
-            .get(username)
-            .map(|c| c.load(Ordering::SeqCst))
-            .unwrap_or(0)
+    pub fn access(&self) -> &Arc<AccessList> {
+        &self.access
     }
 
     /// Check if a user can post (based on can_post permission).
@@ -197,8 +159,8 @@ impl UsageTracker {
 
     /// Check if a bandwidth transfer of `bytes` is allowed.
     ///
-    /// This does NOT record the usage - call `record_bandwidth` after
-    /// the transfer completes successfully.
+    /// This does NOT record the usage; client connections charge their
+    /// traffic with `charge` after each command.
     pub async fn check_bandwidth(&self, username: &str, bytes: u64) -> LimitCheckResult {
         let limits = self.get_effective_limits(username).await;
 
@@ -241,6 +203,19 @@ impl UsageTracker {
         LimitCheckResult::Allowed
     }
 
+    /// How long until a user's bandwidth window starts over and their usage
+    /// is reset, or `None` if their limit is not time-windowed.
+    pub async fn bandwidth_retry_after(&self, username: &str) -> Option<std::time::Duration> {
+        let period_secs = self
+            .get_effective_limits(username)
+            .await
+            .bandwidth_period_secs?;
+        let window_start = self.get_usage(username).await.window_start?;
+        let reset = window_start + Duration::seconds(period_secs as i64);
+        let left = reset.signed_duration_since(Utc::now()).num_seconds().max(1);
+        Some(std::time::Duration::from_secs(left as u64))
+    }
+
     /// Record bandwidth usage after a successful transfer.
     ///
     /// # Arguments
@@ -265,6 +240,21 @@ impl UsageTracker {
         }
     }
 
+    /// Charge `uploaded` and `downloaded` bytes of connection traffic to a
+    /// user and report whether they are now over their bandwidth limit.
+    pub async fn charge(&self, username: &str, uploaded: u64, downloaded: u64) -> LimitCheckResult {
+        // Start a new window first if the current one has expired, so the
+        // traffic is counted in the window it belongs to
+        let _ = self.check_bandwidth(username, 0).await;
+        if uploaded > 0 {
+            self.record_bandwidth(username, uploaded, true).await;
+        }
+        if downloaded > 0 {
+            self.record_bandwidth(username, downloaded, false).await;
+        }
+        self.check_bandwidth(username, 0).await
+    }
+
     /// Get current usage for a user.
     pub async fn get_usage(&self, username: &str) -> UserUsage {
         // Clone the Arc to release the DashMap reference before awaiting
@@ -357,7 +347,7 @@ impl UsageTracker {
 impl std::fmt::Debug for UsageTracker {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("UsageTracker")
-            .field("connections_count", &self.connections.len())
+            .field("registry", &self.registry)
             .field("bandwidth_tracked_users", &self.bandwidth.len())
             .finish()
     }
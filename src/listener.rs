@@ -0,0 +1,247 @@
+//! NNTP listeners on TCP or Unix domain sockets.
+//!
+//! An address of the form `unix:///run/renews/nntp.sock` listens on a Unix
+//! domain socket, so that local gateways such as web frontends and the
+//! WebSocket bridge reach the server without TCP or an open port. The
+//! socket file is created with the `[unix_socket]` mode and ownership; a
+//! file left behind by an earlier run is replaced.
+//!
+//! `systemd://name` addresses take the named socket from systemd socket
+//! activation. `systemd_socket` only hands out TCP sockets, so the Unix
+//! sockets among those systemd passes are picked out by
+//! [`capture_activated`] before it takes the rest.
+
+use crate::config::{UnixSocketConfig, listen_addr};
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::io;
+use std::mem::ManuallyDrop;
+use std::net::IpAddr;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+/// First descriptor passed by systemd socket activation
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Unix sockets passed by systemd, by name
+static ACTIVATED: OnceLock<Mutex<HashMap<String, RawFd>>> = OnceLock::new();
+
+/// Path of the Unix domain socket named by the address `raw`, if it is a
+/// `unix://` address.
+#[must_use]
+pub fn unix_path(raw: &str) -> Option<&str> {
+    raw.trim().strip_prefix("unix://")
+}
+
+/// A listener accepting NNTP connections.
+pub enum NntpListener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl NntpListener {
+    /// Accept a connection, returning it with the client's address. Clients
+    /// of Unix sockets have none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting fails.
+    pub async fn accept(&self) -> io::Result<(NntpStream, Option<IpAddr>)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                Ok((NntpStream::Tcp(stream), Some(peer.ip())))
+            }
+            Self::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((NntpStream::Unix(stream), None))
+            }
+        }
+    }
+}
+
+/// A connection accepted by an [`NntpListener`], or opened by [`connect`].
+pub enum NntpStream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl AsyncRead for NntpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for NntpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Connect to the NNTP listener at `raw`, a `unix://` address or one in
+/// the forms of `addr`.
+///
+/// # Errors
+///
+/// Returns an error if the connection fails.
+pub async fn connect(raw: &str) -> io::Result<NntpStream> {
+    match unix_path(raw) {
+        Some(path) => UnixStream::connect(path).await.map(NntpStream::Unix),
+        None => TcpStream::connect(listen_addr(raw))
+            .await
+            .map(NntpStream::Tcp),
+    }
+}
+
+/// Listen on the Unix domain socket at `path`, with the mode and ownership
+/// of `settings`.
+///
+/// # Errors
+///
+/// Returns an error if another server is listening on `path`, the socket
+/// cannot be created, or its mode or owner cannot be set.
+pub fn bind_unix(path: &str, settings: &UnixSocketConfig) -> Result<UnixListener> {
+    let socket = Path::new(path);
+    if let Ok(meta) = std::fs::symlink_metadata(socket) {
+        if !meta.file_type().is_socket() {
+            return Err(anyhow!(
+                "Cannot listen on '{path}': the file exists and is not a socket"
+            ));
+        }
+        if std::os::unix::net::UnixStream::connect(socket).is_ok() {
+            return Err(anyhow!(
+                "Cannot listen on '{path}': another server is listening on it"
+            ));
+        }
+        std::fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket).map_err(|e| {
+        anyhow!(
+            "Failed to bind to Unix socket '{path}': {e}
+
+Check that the directory exists and that renews may create files in it."
+        )
+    })?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(settings.mode))?;
+    let uid = settings
+        .owner
+        .as_deref()
+        .map(|owner| lookup_id("/etc/passwd", owner))
+        .transpose()?;
+    let gid = settings
+        .group
+        .as_deref()
+        .map(|group| lookup_id("/etc/group", group))
+        .transpose()?;
+    if uid.is_some() || gid.is_some() {
+        std::os::unix::fs::chown(socket, uid, gid)
+            .map_err(|e| anyhow!("Failed to change the owner of '{path}': {e}"))?;
+    }
+    Ok(listener)
+}
+
+/// Id of the user or group `name` in the passwd or group file `file`.
+/// Numeric names are taken as ids.
+fn lookup_id(file: &str, name: &str) -> Result<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+    let entries = std::fs::read_to_string(file)?;
+    entries
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&name))
+        .and_then(|fields| fields.get(2)?.parse().ok())
+        .ok_or_else(|| anyhow!("'{name}' not found in {file}"))
+}
+
+/// Keep the Unix sockets passed by systemd socket activation, by their
+/// `FileDescriptorName`, for [`take_activated`].
+///
+/// Must run before `systemd_socket::init`, which clears the environment
+/// describing the sockets and leaves the Unix ones unused. It neither
+/// closes them nor hands them out, so they stay ours.
+pub fn capture_activated() {
+    let env = |name| std::env::var(name).ok();
+    if env("LISTEN_PID").and_then(|pid| pid.parse().ok()) != Some(std::process::id()) {
+        return;
+    }
+    let Some(count) = env("LISTEN_FDS").and_then(|n| n.parse::<RawFd>().ok()) else {
+        return;
+    };
+    let names = env("LISTEN_FDNAMES").unwrap_or_default();
+    let mut names = names.split(':');
+
+    let mut sockets = HashMap::new();
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+        let name = names.next().unwrap_or("unknown");
+        // SAFETY: systemd passed descriptors LISTEN_FDS_START and on to this
+        // process. The probe is never dropped, so the descriptor is not
+        // closed whatever kind of socket it is.
+        let probe = ManuallyDrop::new(unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) });
+        // Only Unix sockets have a Unix socket address
+        if probe.local_addr().is_ok() {
+            sockets.insert(name.to_string(), fd);
+        }
+    }
+    let _ = ACTIVATED.set(Mutex::new(sockets));
+}
+
+/// Take the Unix socket systemd passed with the name `name`. A socket is
+/// only handed out once.
+///
+/// # Errors
+///
+/// Returns an error if the socket cannot be used with tokio.
+pub fn take_activated(name: &str) -> Result<Option<UnixListener>> {
+    let Some(sockets) = ACTIVATED.get() else {
+        return Ok(None);
+    };
+    let fd = sockets
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(name);
+    let Some(fd) = fd else {
+        return Ok(None);
+    };
+    // SAFETY: the descriptor is a listening Unix socket passed by systemd,
+    // which nothing else owns, and it was removed from the map above so it
+    // is adopted only once.
+    let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(UnixListener::from_std(listener)?))
+}
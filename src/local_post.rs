@@ -0,0 +1,104 @@
+//! Articles injected on behalf of local posters.
+//!
+//! NNTP `POST`, the HTTP API and the mail gateway all inject articles into
+//! the server and take them through the same steps, so an article is
+//! treated alike whichever way it arrives:
+//!
+//! 1. [`prepare`] normalises the headers as the injecting site and marks an
+//!    anonymous post with the poster's posting account;
+//! 2. [`screen`] holds unapproved posts to moderated groups for a moderator,
+//!    runs the configured filters and carries out their verdict.
+//!
+//! An article [`Screened::Accepted`] is then queued or stored by the caller.
+
+use crate::article_reader::ArticleMetadata;
+use crate::auth::DynAuth;
+use crate::config::Config;
+use crate::filters::Poster;
+use crate::handlers::utils::validate_article_with_metadata;
+use crate::posting_account::add_injection_info;
+use crate::storage::DynStorage;
+use crate::{Message, control, moderation, rejected, rewrite};
+use anyhow::Result;
+
+/// What became of a screened article.
+#[derive(Debug)]
+pub enum Screened {
+    /// It passed the filters, tagged as they asked, and may be stored
+    Accepted,
+    /// It was held for a moderator of a moderated group
+    Held,
+    /// A filter quarantined it for a moderator
+    Quarantined,
+    /// A filter refused it, or it could not be held for moderation
+    Refused(anyhow::Error),
+}
+
+/// Normalise the headers of `message` as the injecting site.
+///
+/// `posting_account` is the token of the client's address; it is added to
+/// the `Injection-Info` of a post without an authenticated `user`.
+pub fn prepare(
+    message: &mut Message,
+    cfg: &Config,
+    user: Option<&str>,
+    posting_account: Option<&str>,
+) {
+    rewrite::rewrite_posted(message, &cfg.site_name);
+    if user.is_none()
+        && let Some(token) = posting_account
+    {
+        add_injection_info(message, &cfg.site_name, token);
+    }
+}
+
+/// Hold `message` for a moderator or run the configured filters on it.
+///
+/// `poster` is the local client posting it, or `None` for a post the site
+/// itself injects, such as mail taken in by a gateway. Articles the filters
+/// refuse are kept for inspection.
+///
+/// # Errors
+///
+/// Returns an error if the storage fails while the article is checked or
+/// quarantined.
+pub async fn screen(
+    storage: &DynStorage,
+    auth: &DynAuth,
+    cfg: &Config,
+    message: &mut Message,
+    metadata: &ArticleMetadata,
+    poster: Option<Poster<'_>>,
+) -> Result<Screened> {
+    // Unapproved posts to moderated groups are held for a moderator
+    if !control::is_control_message(message)
+        && moderation::needs_moderation(storage, message).await?
+    {
+        let held = match moderation::pending_filter_chain()
+            .validate_with_metadata(storage, auth, cfg, message, metadata, poster)
+            .await
+        {
+            Ok(()) => storage.add_pending_article(message).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        return Ok(match held {
+            Ok(()) => Screened::Held,
+            Err(e) => Screened::Refused(e),
+        });
+    }
+
+    let verdict =
+        match validate_article_with_metadata(storage, auth, cfg, message, metadata, poster).await {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                rejected::keep(&**storage, cfg, message, &e).await;
+                return Ok(Screened::Refused(e));
+            }
+        };
+
+    // Filters may tag the article or quarantine it for a moderator
+    if verdict.apply(storage, message).await? {
+        return Ok(Screened::Quarantined);
+    }
+    Ok(Screened::Accepted)
+}
@@ -4,8 +4,12 @@ use clap::{Parser, Subcommand};
 use tokio::runtime::Runtime;
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
+use renews::access::{AddressAction, AddressRule, IpNetwork};
+use renews::audit::{self, AuditAction, AuditEntry};
 use renews::auth;
+use renews::auth::throttle::{self, LoginSubject};
 use renews::config::{Config, DEFAULT_LOG_FILTER, parse_duration_secs, parse_size};
+use renews::gateway::Received;
 use renews::limits::UserLimits;
 use renews::server;
 use renews::storage;
@@ -18,6 +22,10 @@ struct Args {
     /// Initialize databases and exit
     #[arg(long)]
     init: bool,
+    /// Run admin commands on the `[[vhost]]` of this name instead of the
+    /// main site
+    #[arg(long)]
+    vhost: Option<String>,
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -27,6 +35,20 @@ enum Command {
     /// Administrative actions
     #[command(subcommand)]
     Admin(AdminCommand),
+    /// Send a command such as `reload` or `status` to the control socket of
+    /// the running server
+    Control {
+        /// Command and arguments
+        #[arg(required = true)]
+        command: Vec<String>,
+    },
+    /// Probe the health endpoint of the running server, exiting non-zero
+    /// when it is unhealthy
+    Healthcheck {
+        /// Probe readiness instead of liveness
+        #[arg(long)]
+        ready: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -60,6 +82,8 @@ enum AdminCommand {
     UpdateKey { user: String, pgp_key: String },
     /// Set moderation status for a group
     SetModerated { group: String, moderated: String },
+    /// Set the description of a group shown by LIST NEWSGROUPS
+    SetDescription { group: String, description: String },
     /// Grant admin privileges to a user
     AddAdmin { user: String },
     /// Revoke admin privileges from a user
@@ -100,6 +124,38 @@ enum AdminCommand {
         /// Username to reset usage for
         user: String,
     },
+    /// Lift the lockout of a user after failed logins and forget them
+    UnlockUser { user: String },
+    /// Lift the lockout of a client address after failed logins and forget
+    /// them
+    UnlockAddress { address: std::net::IpAddr },
+    /// Refuse connections from an address or CIDR network
+    BanAddress {
+        /// Address or network, e.g. 192.0.2.7 or 2001:db8::/32
+        network: String,
+        /// How long the ban lasts (e.g., "1h", "7d"; default: forever)
+        #[arg(long)]
+        duration: Option<String>,
+        /// Why the address is banned
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Exempt an address or CIDR network from bans, automatic bans and
+    /// max_connections_per_ip
+    AllowAddress {
+        /// Address or network, e.g. 192.0.2.7 or 2001:db8::/32
+        network: String,
+        /// Why the address is allowed
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Remove the ban or allow rule of an address or network
+    RemoveAddress {
+        /// Address or network, as given to ban-address or allow-address
+        network: String,
+    },
+    /// List the address ban and allow rules in force
+    ListAddresses,
     /// Import newsgroups from a file (ISC format: group<whitespace>description). Use '-' for stdin.
     ImportGroups {
         /// Path to the newsgroups file, or '-' for stdin
@@ -107,6 +163,175 @@ enum AdminCommand {
     },
     /// Export newsgroups to stdout (ISC format: group<tab>description)
     ExportGroups,
+    /// Create the groups named in [[group_settings]] and apply their
+    /// configured moderation flags to the groups already carried
+    SyncGroups,
+    /// Export the articles of newsgroups to an mbox or a maildir
+    Export {
+        /// Wildmat pattern for groups to export
+        #[arg(long, default_value = "*")]
+        group: String,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Mbox)]
+        format: ExportFormat,
+        /// Only export articles that arrived after this date (YYYY-MM-DD or
+        /// RFC 3339)
+        #[arg(long, value_parser = renews::export::parse_since)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+        /// mbox file or maildir directory to write to; an mbox is written
+        /// to stdout by default
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Import articles from an mbox or a maildir into the groups carried
+    Import {
+        /// mbox file or maildir directory to read, or '-' for an mbox on
+        /// stdin
+        path: std::path::PathBuf,
+        /// Input format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Mbox)]
+        format: ExportFormat,
+    },
+    /// List articles held in the moderation queue
+    ListPending,
+    /// Approve a held article and post it with an Approved header
+    ApprovePending {
+        /// Moderation queue id (see list-pending)
+        id: u64,
+        /// Moderator recorded in the Approved header
+        moderator: String,
+    },
+    /// Reject and discard a held article
+    RejectPending {
+        /// Moderation queue id (see list-pending)
+        id: u64,
+    },
+    /// Inspect, inject or delete the articles refused by filters (kept when
+    /// rejected_retention_days is set)
+    #[command(subcommand)]
+    Rejected(RejectedCommand),
+    /// Post a mail read from standard input to the group of the [[gateway]]
+    /// taking mail for the address, for use in mail aliases
+    ReceiveMail {
+        /// Address the mail was delivered to
+        address: String,
+    },
+    /// Show the changes held from checkgroups messages, or apply or
+    /// discard the changes with the given id
+    ApplyCheckgroups {
+        /// Id of the held changes (see apply-checkgroups without an id)
+        id: Option<u64>,
+        /// Discard the changes instead of applying them
+        #[arg(long, requires = "id")]
+        discard: bool,
+    },
+    /// Pin an article in a group so that retention keeps it
+    PinArticle {
+        /// Group name
+        group: String,
+        /// Message-ID of the article
+        message_id: String,
+    },
+    /// Unpin an article in a group
+    UnpinArticle {
+        /// Group name
+        group: String,
+        /// Message-ID of the article
+        message_id: String,
+    },
+    /// List pinned articles
+    ListPinned,
+    /// Verify and rebuild overview data from stored article headers
+    RebuildOverview {
+        /// Wildmat pattern for groups to rebuild (default: all groups)
+        #[arg(default_value = "*")]
+        wildmat: String,
+    },
+    /// Check that article numbers, messages, overview rows, message sizes
+    /// and watermarks agree; exits non-zero if problems remain
+    CheckStore {
+        /// Wildmat pattern for groups to check (default: all groups)
+        #[arg(default_value = "*")]
+        wildmat: String,
+        /// Rebuild the overview of groups with problems a rebuild can fix
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Number the articles of a group from 1 again, closing the gaps left by
+    /// expired and cancelled articles. Run it while the server is stopped;
+    /// read marks clients keep for the group no longer match afterwards
+    RenumberGroup {
+        /// Group name
+        group: String,
+    },
+    /// Archive a group: keep it readable but refuse new articles for it and
+    /// stop exchanging it with peers
+    ArchiveGroup {
+        /// Group name
+        group: String,
+    },
+    /// Open an archived group for new articles again
+    UnarchiveGroup {
+        /// Group name
+        group: String,
+    },
+    /// Write a consistent copy of the storage database to a new file while
+    /// the server keeps running (SQLite only)
+    Snapshot {
+        /// Path of the snapshot file, which must not exist yet
+        path: std::path::PathBuf,
+    },
+    /// Print the audit log of posts and administrative actions
+    Audit {
+        /// Only print entries recorded since this date (YYYY-MM-DD or
+        /// RFC 3339)
+        #[arg(long, value_parser = renews::export::parse_since)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    /// Print what each peer was offered and how it answered: name,
+    /// offered, accepted, refused, rejected, deferred, bytes accepted and
+    /// time of last contact
+    PeerStats,
+    /// Refresh stored PGP keys from the key servers now and list the users
+    /// whose key is expired, revoked or unreadable
+    RefreshKeys,
+    /// Inspect schema migrations
+    #[command(subcommand)]
+    Migrations(MigrationsCommand),
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ExportFormat {
+    Mbox,
+    Maildir,
+}
+
+#[derive(Subcommand)]
+enum RejectedCommand {
+    /// List the kept articles with their id, Message-ID, groups and reason
+    List,
+    /// Print the reason and the full text of a kept article
+    Show {
+        /// Id of the kept article (see rejected list)
+        id: u64,
+    },
+    /// Store a kept article without filtering it again
+    Reinject {
+        /// Id of the kept article (see rejected list)
+        id: u64,
+    },
+    /// Forget a kept article
+    Delete {
+        /// Id of the kept article (see rejected list)
+        id: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrationsCommand {
+    /// List the migrations of the storage and auth databases with when
+    /// they were applied and their checksums
+    List,
 }
 
 /// Import newsgroups from a file in ISC format (group<whitespace>description).
@@ -208,20 +433,286 @@ async fn export_groups(storage: &storage::DynStorage) -> Result<()> {
     Ok(())
 }
 
+/// Export the articles of the groups matching `wildmat`, reporting the
+/// number exported on stderr so that an mbox can be written to stdout.
+async fn export_articles(
+    storage: &storage::DynStorage,
+    cfg: &Config,
+    wildmat: &str,
+    format: ExportFormat,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    output: Option<&std::path::Path>,
+) -> Result<()> {
+    use renews::export::{ExportSink, Maildir, Mbox};
+
+    let mut sink: Box<dyn ExportSink> = match (format, output) {
+        (ExportFormat::Mbox, None) => {
+            Box::new(Mbox::new(std::io::BufWriter::new(std::io::stdout().lock())))
+        }
+        (ExportFormat::Mbox, Some(path)) => {
+            let file = std::fs::File::create_new(path)
+                .map_err(|e| anyhow::anyhow!("Failed to create mbox '{}': {e}", path.display()))?;
+            Box::new(Mbox::new(std::io::BufWriter::new(file)))
+        }
+        (ExportFormat::Maildir, Some(path)) => Box::new(Maildir::create(path, &cfg.site_name)?),
+        (ExportFormat::Maildir, None) => {
+            return Err(anyhow::anyhow!("--output is required for a maildir export"));
+        }
+    };
+    let summary =
+        renews::export::export_articles(storage.as_ref(), wildmat, since, sink.as_mut()).await?;
+    eprintln!(
+        "Exported {} articles from {} groups",
+        summary.articles, summary.groups
+    );
+    Ok(())
+}
+
+/// Import the articles of an mbox or a maildir at `path`.
+async fn import_articles(
+    storage: &storage::DynStorage,
+    path: &std::path::Path,
+    format: ExportFormat,
+) -> Result<()> {
+    use renews::import::{maildir_messages, mbox_messages};
+
+    let summary = match format {
+        ExportFormat::Mbox if path.as_os_str() == "-" => {
+            let messages = mbox_messages(std::io::stdin().lock());
+            renews::import::import_articles(storage.as_ref(), messages).await?
+        }
+        ExportFormat::Mbox => {
+            let file = std::fs::File::open(path)
+                .map_err(|e| anyhow::anyhow!("Failed to open mbox '{}': {e}", path.display()))?;
+            let messages = mbox_messages(std::io::BufReader::new(file));
+            renews::import::import_articles(storage.as_ref(), messages).await?
+        }
+        ExportFormat::Maildir => {
+            let messages = maildir_messages(path)?;
+            renews::import::import_articles(storage.as_ref(), messages).await?
+        }
+    };
+    println!(
+        "Imported {} articles, {} already present, skipped {}",
+        summary.imported, summary.duplicates, summary.skipped
+    );
+    Ok(())
+}
+
+/// Verify and rebuild the overview of every group matching `wildmat`,
+/// printing a summary line for each group.
+async fn rebuild_overview(storage: &storage::DynStorage, wildmat: &str) -> Result<()> {
+    use futures_util::StreamExt;
+
+    let mut groups = Vec::new();
+    let mut stream = storage.list_groups();
+    while let Some(group) = stream.next().await {
+        let group = group?;
+        if renews::wildmat::wildmat(wildmat, &group) {
+            groups.push(group);
+        }
+    }
+    drop(stream);
+
+    for group in groups {
+        let repair = storage.rebuild_overview(&group).await?;
+        println!(
+            "{group}: {} checked, {} rebuilt, {} stale removed, {} dangling removed",
+            repair.checked, repair.rebuilt, repair.stale_removed, repair.dangling_removed
+        );
+    }
+    Ok(())
+}
+
+/// Check the storage of every group matching `wildmat`, repairing what a
+/// rebuild of the overview can if `repair` is set, and print a summary line
+/// for each group with problems.
+async fn check_store(
+    storage: &storage::DynStorage,
+    cfg: &Config,
+    wildmat: &str,
+    repair: bool,
+) -> Result<()> {
+    let extra = cfg.extra_overview_headers();
+    let results = renews::integrity::check_store(&**storage, wildmat, &extra, repair).await?;
+    let checked = results.len();
+    let mut remaining = 0;
+    for (check, after) in results {
+        if check.is_clean() {
+            continue;
+        }
+        println!("{check}");
+        let left = match after {
+            Some(after) if after.is_clean() => {
+                println!("{}: repaired", after.group);
+                after
+            }
+            Some(after) => {
+                println!("after repair, {after}");
+                after
+            }
+            None => check,
+        };
+        remaining += left.problems();
+    }
+    if remaining > 0 {
+        return Err(anyhow::anyhow!(
+            "{remaining} problems found in {checked} groups checked"
+        ));
+    }
+    println!("{checked} groups checked, no problems remain");
+    Ok(())
+}
+
+/// Renumber the articles of `group` and report the watermarks before and
+/// after.
+async fn renumber_group(storage: &storage::DynStorage, group: &str) -> Result<()> {
+    let Some(before) = storage.get_group_watermarks(group).await? else {
+        return Err(anyhow::anyhow!("No such group: {group}"));
+    };
+    eprintln!(
+        "Warning: renumbering {group} invalidates the read marks clients keep for it; \
+         readers will see its articles as new, or miss them, until they reset the group"
+    );
+    let after = storage
+        .renumber_group(group)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No such group: {group}"))?;
+    let detail = format!(
+        "{}-{} to {}-{}",
+        before.low, before.high, after.low, after.high
+    );
+    let entry = AuditEntry::new(AuditAction::RenumberGroup, group)
+        .by_cli()
+        .with_detail(&detail);
+    audit::record(&**storage, entry).await;
+    println!("{group}: {} articles, numbered {detail}", after.count);
+    Ok(())
+}
+
+/// Archive or unarchive `group` and record it in the audit log.
+async fn set_archived(storage: &storage::DynStorage, group: &str, archived: bool) -> Result<()> {
+    if !storage.group_exists(group).await? {
+        return Err(anyhow::anyhow!("No such group: {group}"));
+    }
+    storage.set_group_archived(group, archived).await?;
+    let action = if archived {
+        AuditAction::ArchiveGroup
+    } else {
+        AuditAction::UnarchiveGroup
+    };
+    audit::record(&**storage, AuditEntry::new(action, group).by_cli()).await;
+    Ok(())
+}
+
+/// Add or replace the rule of a network and record it in the audit log.
+async fn set_address_rule(
+    storage: &storage::DynStorage,
+    auth: &auth::DynAuth,
+    rule: &AddressRule,
+    action: AuditAction,
+) -> Result<()> {
+    auth.add_address_rule(rule).await?;
+    let mut entry = AuditEntry::new(action, rule.network.to_string()).by_cli();
+    if let Some(reason) = &rule.reason {
+        entry = entry.with_detail(reason.clone());
+    }
+    audit::record(&**storage, entry).await;
+    Ok(())
+}
+
+/// Print the audit log entries recorded since `since`, or all of them, one
+/// per line as `time<TAB>action<TAB>actor<TAB>source<TAB>target<TAB>detail`.
+async fn print_audit_log(
+    storage: &storage::DynStorage,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<()> {
+    use futures_util::StreamExt;
+
+    let since = since.unwrap_or(chrono::DateTime::UNIX_EPOCH);
+    let mut entries = storage.list_audit_since(since);
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let time = chrono::DateTime::from_timestamp(entry.recorded_at, 0)
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .unwrap_or_default();
+        println!(
+            "{time}\t{}\t{}\t{}\t{}\t{}",
+            entry.action,
+            entry.actor.as_deref().unwrap_or("-"),
+            entry.source.as_deref().unwrap_or("-"),
+            entry.target,
+            entry.detail.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+/// Print the migration history of the storage and auth databases.
+async fn list_migrations(cfg: &Config) -> Result<()> {
+    use renews::migrations::{Database, MigrationState, history};
+
+    let mut modified = false;
+    for (database, uri) in [
+        (Database::Storage, &cfg.db_path),
+        (Database::Auth, &cfg.auth_db_path),
+    ] {
+        println!("{database} ({uri}):");
+        for migration in history(database, uri).await? {
+            modified |= migration.state == MigrationState::Modified;
+            println!(
+                "  {:04}\t{}\t{}\t{}\t{}",
+                migration.version,
+                migration.state,
+                migration.installed_on.as_deref().unwrap_or("-"),
+                migration.checksum_hex(),
+                migration.description
+            );
+        }
+    }
+    if modified {
+        return Err(anyhow::anyhow!(
+            "applied migrations have been edited; renews will refuse to start"
+        ));
+    }
+    Ok(())
+}
+
 async fn run_admin(cmd: AdminCommand, cfg: &Config) -> Result<()> {
-    let storage = storage::open(&cfg.db_path).await?;
+    // Migration history is read without opening, and so migrating, the
+    // databases
+    if let AdminCommand::Migrations(MigrationsCommand::List) = cmd {
+        return list_migrations(cfg).await;
+    }
+    let storage = storage::replicated::for_config(
+        storage::sharded::open(&cfg.db_path, &cfg.shards, &cfg.sqlite).await?,
+        &cfg.replication,
+    );
+    // Overview rows rebuilt or written here carry the configured fields
+    storage.set_overview_headers(cfg.extra_overview_headers());
     let auth = auth::open(&cfg.auth_db_path).await?;
     match cmd {
         AdminCommand::AddGroup { group, groups } => {
-            // Add the first group
-            storage.add_group(&group, false).await?;
-            // Add any additional groups
-            for g in groups {
-                storage.add_group(&g, false).await?;
+            for g in std::iter::once(group).chain(groups) {
+                let moderated = cfg.group_moderation(&g).unwrap_or(false);
+                storage.add_group(&g, moderated).await?;
+                audit::record(
+                    &*storage,
+                    AuditEntry::new(AuditAction::AddGroup, g).by_cli(),
+                )
+                .await;
             }
         }
         AdminCommand::RemoveGroup { wildmat } => {
             storage.remove_groups_by_pattern(&wildmat).await?;
+            let entry = AuditEntry::new(AuditAction::RemoveGroup, wildmat).by_cli();
+            audit::record(&*storage, entry).await;
+        }
+        AdminCommand::ArchiveGroup { group } => {
+            set_archived(&storage, &group, true).await?;
+        }
+        AdminCommand::UnarchiveGroup { group } => {
+            set_archived(&storage, &group, false).await?;
         }
         AdminCommand::AddUser {
             user,
@@ -230,12 +721,22 @@ async fn run_admin(cmd: AdminCommand, cfg: &Config) -> Result<()> {
         } => {
             auth.add_user_with_key(&user, &pass, pgp_key.as_deref())
                 .await?;
+            audit::record(
+                &*storage,
+                AuditEntry::new(AuditAction::AddUser, user).by_cli(),
+            )
+            .await;
         }
         AdminCommand::UpdatePassword { user, new_pass } => {
             auth.update_password(&user, &new_pass).await?;
         }
         AdminCommand::RemoveUser { user } => {
             auth.remove_user(&user).await?;
+            audit::record(
+                &*storage,
+                AuditEntry::new(AuditAction::RemoveUser, user).by_cli(),
+            )
+            .await;
         }
         AdminCommand::UpdateKey { user, pgp_key } => {
             auth.update_pgp_key(&user, &pgp_key).await?;
@@ -252,17 +753,40 @@ async fn run_admin(cmd: AdminCommand, cfg: &Config) -> Result<()> {
             };
             storage.set_group_moderated(&group, is_moderated).await?;
         }
+        AdminCommand::SetDescription { group, description } => {
+            if !storage.set_group_description(&group, &description).await? {
+                return Err(anyhow::anyhow!("No such group: {group}"));
+            }
+        }
         AdminCommand::AddAdmin { user } => {
             auth.add_admin_without_key(&user).await?;
+            audit::record(
+                &*storage,
+                AuditEntry::new(AuditAction::AddAdmin, user).by_cli(),
+            )
+            .await;
         }
         AdminCommand::RemoveAdmin { user } => {
             auth.remove_admin(&user).await?;
+            audit::record(
+                &*storage,
+                AuditEntry::new(AuditAction::RemoveAdmin, user).by_cli(),
+            )
+            .await;
         }
         AdminCommand::AddModerator { user, group } => {
             auth.add_moderator(&user, &group).await?;
+            let entry = AuditEntry::new(AuditAction::AddModerator, user)
+                .by_cli()
+                .with_detail(group);
+            audit::record(&*storage, entry).await;
         }
         AdminCommand::RemoveModerator { user, group } => {
             auth.remove_moderator(&user, &group).await?;
+            let entry = AuditEntry::new(AuditAction::RemoveModerator, user)
+                .by_cli()
+                .with_detail(group);
+            audit::record(&*storage, entry).await;
         }
         AdminCommand::SetLimits {
             user,
@@ -355,12 +879,283 @@ async fn run_admin(cmd: AdminCommand, cfg: &Config) -> Result<()> {
             auth.reset_user_usage(&user).await?;
             println!("Usage counters reset for user '{user}'");
         }
+        AdminCommand::UnlockUser { user } => {
+            throttle::unlock(&*auth, &LoginSubject::User(&user)).await?;
+            println!("Failed logins cleared for user '{user}'");
+        }
+        AdminCommand::UnlockAddress { address } => {
+            throttle::unlock(&*auth, &LoginSubject::Address(address)).await?;
+            println!("Failed logins cleared for {address}");
+        }
+        AdminCommand::BanAddress {
+            network,
+            duration,
+            reason,
+        } => {
+            let expires_at = match duration {
+                Some(duration) => {
+                    let secs = parse_duration_secs(&duration)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid duration: '{duration}'"))?;
+                    let secs = i64::try_from(secs)?;
+                    Some(chrono::Utc::now().timestamp().saturating_add(secs))
+                }
+                None => None,
+            };
+            let rule = AddressRule {
+                network: network.parse()?,
+                action: AddressAction::Ban,
+                reason,
+                expires_at,
+            };
+            set_address_rule(&storage, &auth, &rule, AuditAction::BanAddress).await?;
+        }
+        AdminCommand::AllowAddress { network, reason } => {
+            let rule = AddressRule {
+                network: network.parse()?,
+                action: AddressAction::Allow,
+                reason,
+                expires_at: None,
+            };
+            set_address_rule(&storage, &auth, &rule, AuditAction::AllowAddress).await?;
+        }
+        AdminCommand::RemoveAddress { network } => {
+            let network = network.parse::<IpNetwork>()?.to_string();
+            if !auth.remove_address_rule(&network).await? {
+                return Err(anyhow::anyhow!("No rule for {network}"));
+            }
+            let entry = AuditEntry::new(AuditAction::RemoveAddress, network).by_cli();
+            audit::record(&*storage, entry).await;
+        }
+        AdminCommand::ListAddresses => {
+            for rule in auth
+                .list_address_rules(chrono::Utc::now().timestamp())
+                .await?
+            {
+                println!("{rule}");
+            }
+        }
         AdminCommand::ImportGroups { file } => {
             import_groups(&storage, &file).await?;
         }
         AdminCommand::ExportGroups => {
             export_groups(&storage).await?;
         }
+        AdminCommand::SyncGroups => {
+            let sync = renews::groups::sync_groups(&*storage, cfg).await?;
+            for group in &sync.created {
+                println!("Created {group}");
+            }
+            for group in &sync.moderation_changed {
+                println!("Changed moderation of {group}");
+            }
+            println!(
+                "Created {} groups, changed moderation of {} groups",
+                sync.created.len(),
+                sync.moderation_changed.len()
+            );
+        }
+        AdminCommand::Export {
+            group,
+            format,
+            since,
+            output,
+        } => {
+            export_articles(&storage, cfg, &group, format, since, output.as_deref()).await?;
+        }
+        AdminCommand::Import { path, format } => {
+            import_articles(&storage, &path, format).await?;
+        }
+        AdminCommand::ListPending => {
+            for entry in renews::moderation::list_pending(&storage, &auth, None, None).await? {
+                let field = |name| {
+                    renews::handlers::utils::get_header_value(&entry.message, name)
+                        .unwrap_or_default()
+                };
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    entry.id,
+                    field("Message-ID"),
+                    field("Newsgroups"),
+                    field("Subject")
+                );
+            }
+        }
+        AdminCommand::ApprovePending { id, moderator } => {
+            let Some(article) = renews::moderation::approve(&storage, id, &moderator).await? else {
+                return Err(anyhow::anyhow!("No pending article with id {id}"));
+            };
+            let entry = AuditEntry::for_article(AuditAction::Approve, &article).by_cli();
+            audit::record(&*storage, entry).await;
+            println!("Approved pending article {id}");
+        }
+        AdminCommand::RejectPending { id } => {
+            let Some(pending) = storage.get_pending_article(id).await? else {
+                return Err(anyhow::anyhow!("No pending article with id {id}"));
+            };
+            renews::moderation::reject(&storage, id).await?;
+            let entry = AuditEntry::for_article(AuditAction::Reject, &pending.message).by_cli();
+            audit::record(&*storage, entry).await;
+            println!("Rejected pending article {id}");
+        }
+        AdminCommand::Rejected(RejectedCommand::List) => {
+            use futures_util::StreamExt;
+            let mut rejected = storage.list_rejected_articles();
+            while let Some(entry) = rejected.next().await {
+                let entry = entry?;
+                let field = |name| {
+                    renews::handlers::utils::get_header_value(&entry.message, name)
+                        .unwrap_or_default()
+                };
+                let rejected_at = chrono::DateTime::from_timestamp(entry.rejected_at, 0)
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_default();
+                println!(
+                    "{}\t{rejected_at}\t{}\t{}\t{}",
+                    entry.id,
+                    field("Message-ID"),
+                    field("Newsgroups"),
+                    entry.reason
+                );
+            }
+        }
+        AdminCommand::Rejected(RejectedCommand::Show { id }) => {
+            let Some(entry) = storage.get_rejected_article(id).await? else {
+                return Err(anyhow::anyhow!("No rejected article with id {id}"));
+            };
+            println!("Reason: {}", entry.reason);
+            println!();
+            for (name, value) in &entry.message.headers {
+                println!("{name}: {value}");
+            }
+            println!();
+            print!("{}", entry.message.body);
+        }
+        AdminCommand::Rejected(RejectedCommand::Reinject { id }) => {
+            let Some(article) = renews::rejected::reinject(&*storage, id).await? else {
+                return Err(anyhow::anyhow!("No rejected article with id {id}"));
+            };
+            let entry = AuditEntry::for_article(AuditAction::Reinject, &article).by_cli();
+            audit::record(&*storage, entry).await;
+            println!("Reinjected rejected article {id}");
+        }
+        AdminCommand::Rejected(RejectedCommand::Delete { id }) => {
+            if !storage.remove_rejected_article(id).await? {
+                return Err(anyhow::anyhow!("No rejected article with id {id}"));
+            }
+            println!("Deleted rejected article {id}");
+        }
+        AdminCommand::ReceiveMail { address } => {
+            use std::io::Read;
+            let mut mail = String::new();
+            std::io::stdin().read_to_string(&mut mail)?;
+            let received = renews::gateway::receive(&storage, &auth, cfg, &address, &mail).await?;
+            match received {
+                Received::Posted(id) => println!("Posted {id}"),
+                Received::Held(id) => println!("Held {id} for moderation"),
+                Received::Duplicate(id) => println!("Already posted {id}"),
+                Received::Looped => println!("Dropped mail sent by this gateway"),
+            }
+        }
+        AdminCommand::ApplyCheckgroups { id: None, .. } => {
+            for pending in storage.list_pending_checkgroups().await? {
+                let received = chrono::DateTime::from_timestamp(pending.received_at, 0)
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_default();
+                println!("{}\t{}\t{received}", pending.id, pending.signer);
+                for change in pending.diff.lines() {
+                    println!("  {change}");
+                }
+            }
+        }
+        AdminCommand::ApplyCheckgroups {
+            id: Some(id),
+            discard: true,
+        } => {
+            if !storage.remove_pending_checkgroups(id).await? {
+                return Err(anyhow::anyhow!("No pending checkgroups with id {id}"));
+            }
+            println!("Discarded pending checkgroups {id}");
+        }
+        AdminCommand::ApplyCheckgroups {
+            id: Some(id),
+            discard: false,
+        } => {
+            let Some(diff) = renews::checkgroups::apply_pending(&*storage, id).await? else {
+                return Err(anyhow::anyhow!("No pending checkgroups with id {id}"));
+            };
+            println!(
+                "Applied pending checkgroups {id}: created {} groups, removed {}, changed moderation of {}",
+                diff.add.len(),
+                diff.remove.len(),
+                diff.moderate.len()
+            );
+        }
+        AdminCommand::PinArticle { group, message_id } => {
+            if !storage
+                .set_article_pinned(&group, &message_id, true)
+                .await?
+            {
+                return Err(anyhow::anyhow!("No article {message_id} in group {group}"));
+            }
+            println!("Pinned {message_id} in {group}");
+        }
+        AdminCommand::UnpinArticle { group, message_id } => {
+            if !storage
+                .set_article_pinned(&group, &message_id, false)
+                .await?
+            {
+                return Err(anyhow::anyhow!("No article {message_id} in group {group}"));
+            }
+            println!("Unpinned {message_id} in {group}");
+        }
+        AdminCommand::ListPinned => {
+            use futures_util::StreamExt;
+            let mut pinned = storage.list_pinned_articles();
+            while let Some(result) = pinned.next().await {
+                let (group, number, message_id) = result?;
+                println!("{group}\t{number}\t{message_id}");
+            }
+        }
+        AdminCommand::RebuildOverview { wildmat } => {
+            rebuild_overview(&storage, &wildmat).await?;
+        }
+        AdminCommand::CheckStore { wildmat, repair } => {
+            check_store(&storage, cfg, &wildmat, repair).await?;
+        }
+        AdminCommand::RenumberGroup { group } => {
+            renumber_group(&storage, &group).await?;
+        }
+        AdminCommand::Snapshot { path } => {
+            storage.snapshot_to(&path).await?;
+            println!("Snapshot written to {}", path.display());
+        }
+        AdminCommand::Audit { since } => {
+            print_audit_log(&storage, since).await?;
+        }
+        AdminCommand::PeerStats => {
+            let peer_db = renews::peers::PeerDb::new(&cfg.peer_db_path).await?;
+            for stats in peer_db.peer_stats().await? {
+                println!("{stats}");
+            }
+        }
+        AdminCommand::RefreshKeys => {
+            let discovery = renews::auth::pgp_discovery::DefaultPgpKeyDiscovery::with_key_servers(
+                cfg.pgp_key_servers.clone(),
+            );
+            let report =
+                renews::auth::pgp_refresh::refresh_keys(&auth, &discovery, chrono::Utc::now())
+                    .await?;
+            println!(
+                "Checked {} keys, updated {}, {} invalid",
+                report.checked,
+                report.updated,
+                report.invalid.len()
+            );
+            for (user, reason) in report.invalid {
+                println!("{user}\t{reason}");
+            }
+        }
+        AdminCommand::Migrations(_) => unreachable!("handled before opening the databases"),
     }
     Ok(())
 }
@@ -402,8 +1197,25 @@ fn format_duration(secs: u64) -> String {
     }
 }
 
+/// The configuration admin commands work with: that of the virtual host
+/// called `vhost` when one is named, otherwise that of the main site.
+fn admin_config(cfg: &Config, vhost: Option<&str>) -> Result<Config> {
+    let Some(name) = vhost else {
+        return Ok(cfg.clone());
+    };
+    let vhost = cfg
+        .vhosts
+        .iter()
+        .find(|vhost| vhost.name == name)
+        .ok_or_else(|| anyhow::anyhow!("No [[vhost]] named '{name}' in the configuration"))?;
+    Ok(renews::vhost::site_config(cfg, vhost))
+}
+
 async fn run_init(cfg: &Config) -> Result<()> {
-    storage::open(&cfg.db_path).await?;
+    storage::sharded::open(&cfg.db_path, &cfg.shards, &cfg.sqlite).await?;
+    for vhost in &cfg.vhosts {
+        storage::open_with_config(&vhost.db_path, &cfg.sqlite).await?;
+    }
     auth::open(&cfg.auth_db_path).await?;
     let peer_db = renews::peers::PeerDb::new(&cfg.peer_db_path).await?;
     let names: Vec<String> = cfg.peers.iter().map(|p| p.sitename.clone()).collect();
@@ -446,6 +1258,29 @@ fn init_tracing(config: &Config) {
     );
 }
 
+async fn run_control(command: &str, cfg: &Config) -> Result<()> {
+    let Some(path) = cfg.control_socket.as_deref() else {
+        anyhow::bail!("control_socket is not set in the configuration");
+    };
+    for line in renews::control_socket::send_command(path, command).await? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+async fn run_healthcheck(ready: bool, cfg: &Config) -> Result<()> {
+    let Some(addr) = cfg.health_addr.as_deref() else {
+        anyhow::bail!("health_addr is not set in the configuration");
+    };
+    let path = if ready { "/readyz" } else { "/healthz" };
+    let (status, body) = renews::health::probe(addr, path).await?;
+    print!("{body}");
+    if status != 200 {
+        anyhow::bail!("{path} answered {status}");
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_lines)]
 fn main() -> Result<()> {
     // Parse args first to get config path
@@ -464,7 +1299,9 @@ fn main() -> Result<()> {
     // Initialize tracing based on configuration
     init_tracing(&cfg_initial);
 
-    // Initialize systemd socket support
+    // Initialize systemd socket support, keeping the Unix sockets that
+    // systemd_socket leaves out
+    renews::listener::capture_activated();
     if let Err(e) = systemd_socket::init() {
         tracing::warn!(error = %e, "Failed to initialize systemd socket support");
     };
@@ -499,7 +1336,25 @@ fn main() -> Result<()> {
         if let Some(cmd) = args.command {
             match cmd {
                 Command::Admin(c) => {
-                    if let Err(e) = run_admin(c, &cfg_initial).await {
+                    let result = match admin_config(&cfg_initial, args.vhost.as_deref()) {
+                        Ok(cfg) => run_admin(c, &cfg).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        eprintln!("Error: {e}");
+                        std::process::exit(1);
+                    }
+                    return Ok(());
+                }
+                Command::Control { command } => {
+                    if let Err(e) = run_control(&command.join(" "), &cfg_initial).await {
+                        eprintln!("Error: {e}");
+                        std::process::exit(1);
+                    }
+                    return Ok(());
+                }
+                Command::Healthcheck { ready } => {
+                    if let Err(e) = run_healthcheck(ready, &cfg_initial).await {
                         eprintln!("Error: {e}");
                         std::process::exit(1);
                     }
@@ -0,0 +1,64 @@
+//! Scheduled storage maintenance.
+//!
+//! Deleting articles frees rows but not disk space: SQLite keeps the pages
+//! of deleted rows in its file and PostgreSQL leaves dead rows behind until
+//! they are vacuumed, so without maintenance the database only ever grows.
+//! With `maintenance_schedule` set, the server purges messages no longer in
+//! any group, compacts the database and refreshes its planner statistics on
+//! that cron schedule, and logs the space given back.
+//!
+//! SQLite is compacted with `VACUUM`, which rewrites the file and holds
+//! back writers while it runs, so the schedule should fall in a quiet hour.
+//! PostgreSQL is given a plain `VACUUM (ANALYZE)`, which does not lock out
+//! readers or writers and leaves the space to be reused by new rows rather
+//! than returned to the operating system.
+
+use crate::config::Config;
+use crate::storage::Storage;
+use anyhow::Result;
+use tracing::{Instrument, info, info_span};
+
+/// Purge orphaned messages and compact `storage`, returning the bytes
+/// given back.
+///
+/// A replication standby leaves the purge to its primary, whose purge is
+/// replicated, and only compacts its own database.
+///
+/// # Errors
+///
+/// Returns an error if the storage fails.
+pub async fn run_maintenance(storage: &dyn Storage, cfg: &Config) -> Result<u64> {
+    let span = info_span!(
+        "storage.maintenance",
+        size_before = tracing::field::Empty,
+        size_after = tracing::field::Empty,
+        reclaimed_bytes = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+    );
+
+    async {
+        let start = std::time::Instant::now();
+        info!("Starting storage maintenance");
+        let size_before = storage.database_size().await?;
+        if !cfg.replication.is_standby() {
+            storage.purge_orphan_messages().await?;
+        }
+        storage.compact().await?;
+        let size_after = storage.database_size().await?;
+        let reclaimed = size_before.saturating_sub(size_after);
+
+        let span = tracing::Span::current();
+        span.record("size_before", size_before);
+        span.record("size_after", size_after);
+        span.record("reclaimed_bytes", reclaimed);
+        span.record("duration_ms", start.elapsed().as_millis() as u64);
+        info!(
+            size_bytes = size_after,
+            reclaimed_bytes = reclaimed,
+            "Storage maintenance complete"
+        );
+        Ok(reclaimed)
+    }
+    .instrument(span)
+    .await
+}
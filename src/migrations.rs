@@ -0,0 +1,287 @@
+//! Schema migration history
+//!
+//! The storage and authentication databases are migrated with sqlx, which
+//! records every applied migration in the `_sqlx_migrations` table together
+//! with the time it was applied and a SHA-384 checksum of its SQL. When a
+//! database is opened the recorded checksums are compared with those of the
+//! migrations built into the binary, and the server refuses to start if an
+//! applied migration has since been edited.
+//!
+//! [`history`] reads the recorded history without migrating the database,
+//! so it can be inspected even when the server refuses to start.
+
+use anyhow::{Result, anyhow};
+use sqlx::Connection;
+use sqlx::migrate::{MigrateError, Migration};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which of the server's databases a history belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Database {
+    Storage,
+    Auth,
+}
+
+impl fmt::Display for Database {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Storage => "storage",
+            Self::Auth => "auth",
+        })
+    }
+}
+
+/// State of one migration in a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    /// Applied with the checksum of the built-in migration
+    Applied,
+    /// Built in but not applied yet
+    Pending,
+    /// Applied, but the built-in migration has been edited since
+    Modified,
+    /// Recorded as failed part way through
+    Failed,
+    /// Applied but not built in, usually by a newer release
+    Unknown,
+}
+
+impl fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Applied => "applied",
+            Self::Pending => "pending",
+            Self::Modified => "modified",
+            Self::Failed => "failed",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+/// One entry of a migration history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+    /// When the migration was applied, as recorded by the database
+    pub installed_on: Option<String>,
+    /// Checksum recorded when the migration was applied, or that of the
+    /// built-in migration if it is pending
+    pub checksum: Vec<u8>,
+}
+
+impl MigrationStatus {
+    /// The checksum as lowercase hex.
+    #[must_use]
+    pub fn checksum_hex(&self) -> String {
+        self.checksum.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+/// A row of the `_sqlx_migrations` table.
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct AppliedRow {
+    version: i64,
+    description: String,
+    installed_on: String,
+    success: bool,
+    checksum: Vec<u8>,
+}
+
+/// Describe a failure to migrate a database, explaining the checksum
+/// check when it is what failed.
+#[must_use]
+pub(crate) fn describe_failure(e: &MigrateError) -> String {
+    match e {
+        MigrateError::VersionMismatch(_) => format!(
+            "{e}; applied migrations must not be edited. Restore the original \
+             migration, or compare the checksums with `renews admin migrations list`"
+        ),
+        MigrateError::VersionMissing(_) => {
+            format!("{e}; the database was migrated by a newer release of renews")
+        }
+        _ => e.to_string(),
+    }
+}
+
+/// Merge the built-in migrations with the rows recorded in a database,
+/// ordered by version.
+fn compare<'a>(
+    shipped: impl IntoIterator<Item = &'a Migration>,
+    applied: Vec<AppliedRow>,
+) -> Vec<MigrationStatus> {
+    let mut history = BTreeMap::new();
+    for migration in shipped {
+        if migration.migration_type.is_down_migration() {
+            continue;
+        }
+        history.insert(
+            migration.version,
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                state: MigrationState::Pending,
+                installed_on: None,
+                checksum: migration.checksum.to_vec(),
+            },
+        );
+    }
+    for row in applied {
+        let state = match history.get(&row.version) {
+            _ if !row.success => MigrationState::Failed,
+            Some(shipped) if shipped.checksum == row.checksum => MigrationState::Applied,
+            Some(_) => MigrationState::Modified,
+            None => MigrationState::Unknown,
+        };
+        history.insert(
+            row.version,
+            MigrationStatus {
+                version: row.version,
+                description: row.description,
+                state,
+                installed_on: Some(row.installed_on),
+                checksum: row.checksum,
+            },
+        );
+    }
+    history.into_values().collect()
+}
+
+/// Read the migration history of the database at `uri`.
+///
+/// The database is opened read-only and is not migrated. A database that
+/// has never been migrated lists every built-in migration as pending.
+///
+/// # Errors
+///
+/// Returns an error if the URI is not supported or the database cannot be
+/// read.
+pub async fn history(database: Database, uri: &str) -> Result<Vec<MigrationStatus>> {
+    if uri.starts_with("sqlite:") {
+        let migrator = match database {
+            Database::Storage => &crate::storage::sqlite::MIGRATOR,
+            Database::Auth => &crate::auth::sqlite::MIGRATOR,
+        };
+        let options = SqliteConnectOptions::from_str(uri)?.read_only(true);
+        let mut conn = SqliteConnection::connect_with(&options)
+            .await
+            .map_err(|e| anyhow!("cannot open {database} database '{uri}': {e}"))?;
+        let (tables,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+        )
+        .fetch_one(&mut conn)
+        .await?;
+        let applied = if tables == 0 {
+            Vec::new()
+        } else {
+            sqlx::query_as(
+                "SELECT version, description, CAST(installed_on AS TEXT) AS installed_on, \
+                 success, checksum FROM _sqlx_migrations ORDER BY version",
+            )
+            .fetch_all(&mut conn)
+            .await?
+        };
+        conn.close().await?;
+        Ok(compare(migrator.iter(), applied))
+    } else if uri.starts_with("postgres:") {
+        #[cfg(feature = "postgres")]
+        {
+            use sqlx::postgres::PgConnection;
+            let migrator = match database {
+                Database::Storage => &crate::storage::postgres::MIGRATOR,
+                Database::Auth => &crate::auth::postgres::MIGRATOR,
+            };
+            let mut conn = PgConnection::connect(uri)
+                .await
+                .map_err(|e| anyhow!("cannot open {database} database '{uri}': {e}"))?;
+            let (exists,): (bool,) =
+                sqlx::query_as("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+                    .fetch_one(&mut conn)
+                    .await?;
+            let applied = if exists {
+                sqlx::query_as(
+                    "SELECT version, description, installed_on::text AS installed_on, \
+                     success, checksum FROM _sqlx_migrations ORDER BY version",
+                )
+                .fetch_all(&mut conn)
+                .await?
+            } else {
+                Vec::new()
+            };
+            conn.close().await?;
+            Ok(compare(migrator.iter(), applied))
+        }
+        #[cfg(not(feature = "postgres"))]
+        {
+            Err(anyhow!(
+                "PostgreSQL support is not enabled. Rebuild with --features postgres"
+            ))
+        }
+    } else {
+        Err(anyhow!("unsupported {database} database URI '{uri}'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::migrate::MigrationType;
+    use std::borrow::Cow;
+
+    fn shipped(version: i64, sql: &'static str) -> Migration {
+        Migration::new(
+            version,
+            Cow::Borrowed("test"),
+            MigrationType::Simple,
+            Cow::Borrowed(sql),
+        )
+    }
+
+    fn row(migration: &Migration, success: bool) -> AppliedRow {
+        AppliedRow {
+            version: migration.version,
+            description: migration.description.to_string(),
+            installed_on: "2024-01-01 00:00:00".to_string(),
+            success,
+            checksum: migration.checksum.to_vec(),
+        }
+    }
+
+    #[test]
+    fn compares_checksums_with_the_built_in_migrations() {
+        let one = shipped(1, "CREATE TABLE a (x)");
+        let two = shipped(2, "CREATE TABLE b (x)");
+        let three = shipped(3, "CREATE TABLE c (x)");
+        let edited = shipped(2, "CREATE TABLE b (x, y)");
+        let failed = shipped(5, "CREATE TABLE d (x)");
+        let newer = shipped(6, "CREATE TABLE e (x)");
+
+        let history = compare(
+            [&one, &edited, &three],
+            vec![
+                row(&one, true),
+                row(&two, true),
+                row(&failed, false),
+                row(&newer, true),
+            ],
+        );
+        let states: Vec<_> = history.iter().map(|m| (m.version, m.state)).collect();
+        assert_eq!(
+            states,
+            vec![
+                (1, MigrationState::Applied),
+                (2, MigrationState::Modified),
+                (3, MigrationState::Pending),
+                (5, MigrationState::Failed),
+                (6, MigrationState::Unknown),
+            ]
+        );
+        // The checksum shown is the one recorded when it was applied
+        assert_eq!(history[1].checksum, two.checksum.to_vec());
+        assert!(history[2].installed_on.is_none());
+    }
+}
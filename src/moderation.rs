@@ -0,0 +1,129 @@
+//! Moderation queue for moderated newsgroups.
+//!
+//! Posts to moderated groups that carry no `Approved` header are held in the
+//! storage backend's pending table instead of being refused. Moderators list
+//! the queue, read entries and approve or reject them, either through the
+//! admin CLI or the `XMODERATE` NNTP command. Approved articles get an `Approved` header
+//! naming the moderator and are stored like any other article.
+
+use crate::Message;
+use crate::auth::DynAuth;
+use crate::filters::{FilterChain, groups, header, size};
+use crate::handlers::utils::{extract_newsgroups, has_header};
+use crate::storage::{DynStorage, PendingArticle};
+use crate::wildmat::wildmat;
+use anyhow::Result;
+use futures_util::StreamExt;
+
+/// Filters applied to an article before it is placed in the moderation
+/// queue. Moderation checks are left to the moderator.
+pub fn pending_filter_chain() -> FilterChain {
+    FilterChain::new()
+        .add_filter(Box::new(header::HeaderFilter))
+        .add_filter(Box::new(size::SizeFilter))
+        .add_filter(Box::new(groups::GroupExistenceFilter))
+}
+
+/// Newsgroups of `article` that are moderated.
+pub async fn moderated_groups(storage: &DynStorage, article: &Message) -> Result<Vec<String>> {
+    let mut moderated = Vec::new();
+    for group in extract_newsgroups(article) {
+        if storage.is_group_moderated(&group).await? {
+            moderated.push(group);
+        }
+    }
+    Ok(moderated)
+}
+
+/// Check whether `article` should be held for a moderator: it is posted to
+/// at least one moderated group and carries no `Approved` header.
+pub async fn needs_moderation(storage: &DynStorage, article: &Message) -> Result<bool> {
+    if has_header(article, "Approved") {
+        return Ok(false);
+    }
+    Ok(!moderated_groups(storage, article).await?.is_empty())
+}
+
+/// Check whether `user` may approve or reject `article`.
+///
+/// Admins may moderate anything; otherwise the user must be a moderator of
+/// every moderated group the article is posted to.
+pub async fn can_moderate(
+    storage: &DynStorage,
+    auth: &DynAuth,
+    user: &str,
+    article: &Message,
+) -> Result<bool> {
+    if auth.is_admin(user).await? {
+        return Ok(true);
+    }
+    let groups = moderated_groups(storage, article).await?;
+    if groups.is_empty() {
+        return Ok(false);
+    }
+    for group in &groups {
+        if !auth.is_moderator(user, group).await? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// List queued articles, restricted to those `user` may moderate when given
+/// and to those posted to a group matching the wildmat `groups` when given.
+pub async fn list_pending(
+    storage: &DynStorage,
+    auth: &DynAuth,
+    user: Option<&str>,
+    groups: Option<&str>,
+) -> Result<Vec<PendingArticle>> {
+    let mut pending = Vec::new();
+    let mut stream = storage.list_pending_articles();
+    while let Some(entry) = stream.next().await {
+        let entry = entry?;
+        if let Some(pattern) = groups
+            && !extract_newsgroups(&entry.message)
+                .iter()
+                .any(|group| wildmat(pattern, group))
+        {
+            continue;
+        }
+        if let Some(user) = user
+            && !can_moderate(storage, auth, user, &entry.message).await?
+        {
+            continue;
+        }
+        pending.push(entry);
+    }
+    Ok(pending)
+}
+
+/// Approve queued article `id` on behalf of `moderator` and store it.
+///
+/// Returns the stored article, or `None` if no such entry exists.
+pub async fn approve(storage: &DynStorage, id: u64, moderator: &str) -> Result<Option<Message>> {
+    let Some(pending) = storage.get_pending_article(id).await? else {
+        return Ok(None);
+    };
+    let mut message = pending.message;
+    message
+        .headers
+        .retain(|(k, _)| !k.eq_ignore_ascii_case("Approved"));
+    message
+        .headers
+        .push(("Approved".to_string(), moderator.to_string()));
+    storage.store_article(&message).await?;
+    storage.remove_pending_article(id).await?;
+    Ok(Some(message))
+}
+
+/// Reject queued article `id`, discarding it.
+///
+/// Returns false if no such entry exists.
+pub async fn reject(storage: &DynStorage, id: u64) -> Result<bool> {
+    if storage.get_pending_article(id).await?.is_none() {
+        return Ok(false);
+    }
+    storage.remove_pending_article(id).await?;
+    Ok(true)
+}
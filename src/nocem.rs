@@ -0,0 +1,326 @@
+//! NoCeM notice processing
+//!
+//! NoCeM ("no see 'em") issuers post PGP-signed notices listing articles they
+//! have classified, usually spam, to groups such as `alt.nocem.misc`. When an
+//! article arrives in one of the configured notice groups it is parsed as a
+//! notice; if it was issued by a subscribed issuer, carries a valid signature
+//! from that issuer's key and has a type the issuer is trusted for, the
+//! listed articles are removed from storage, as INN's `perl-nocem` does.
+//!
+//! A notice is a clearsigned message whose text contains:
+//!
+//! ```text
+//! @@BEGIN NCM HEADERS
+//! Version: 0.93
+//! Issuer: spam-hunter@example.org
+//! Type: spam
+//! Action: hide
+//! Notice-ID: sh-2024-001
+//! Count: 2
+//! @@BEGIN NCM BODY
+//! <spam1@example.com> misc.test alt.test
+//! <spam2@example.com> misc.test
+//! @@END NCM BODY
+//! ```
+
+use crate::Message;
+use crate::config::{NocemConfig, NocemIssuer};
+use crate::handlers::utils::extract_newsgroups;
+use crate::storage::DynStorage;
+use anyhow::{Result, anyhow};
+
+const BEGIN_SIGNED: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+const BEGIN_SIGNATURE: &str = "-----BEGIN PGP SIGNATURE-----";
+const END_SIGNATURE: &str = "-----END PGP SIGNATURE-----";
+
+/// A parsed NoCeM notice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notice {
+    pub issuer: String,
+    pub notice_id: String,
+    /// Classification of the listed articles, such as `spam`
+    pub kind: String,
+    pub action: String,
+    /// Listed Message-IDs with the groups each was seen in
+    pub entries: Vec<(String, Vec<String>)>,
+}
+
+/// The parts of a clearsigned message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClearSigned {
+    /// Text with dash-escaping undone, one element per line
+    lines: Vec<String>,
+    /// The armored signature
+    armor: String,
+}
+
+impl ClearSigned {
+    /// The data covered by the signature: every line with trailing
+    /// whitespace removed, joined by CRLF, without a final line break.
+    fn signed_data(&self) -> String {
+        self.lines
+            .iter()
+            .map(|l| l.trim_end_matches([' ', '\t']))
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    }
+}
+
+/// Split a clearsigned message into its text and signature.
+fn split_clearsigned(body: &str) -> Option<ClearSigned> {
+    let mut lines = body.lines();
+    lines.by_ref().find(|l| l.trim_end() == BEGIN_SIGNED)?;
+    // Armor headers such as "Hash: SHA1" end at the first blank line
+    lines.by_ref().find(|l| l.trim().is_empty())?;
+
+    let mut text = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim_end() == BEGIN_SIGNATURE {
+            let mut armor = format!("{BEGIN_SIGNATURE}\n");
+            for line in lines.by_ref() {
+                let line = line.trim_end();
+                armor.push_str(line);
+                armor.push('\n');
+                if line == END_SIGNATURE {
+                    return Some(ClearSigned { lines: text, armor });
+                }
+            }
+            return None;
+        }
+        text.push(line.strip_prefix("- ").unwrap_or(line).to_string());
+    }
+    None
+}
+
+/// Parse the NCM headers and body out of the signed text of a notice.
+///
+/// # Errors
+///
+/// Returns an error if a section or required header is missing, the
+/// version or action is not supported, or `Count` does not match the
+/// number of listed articles.
+pub fn parse_notice(text: &str) -> Result<Notice> {
+    let mut lines = text.lines().map(str::trim_end);
+    lines
+        .by_ref()
+        .find(|l| *l == "@@BEGIN NCM HEADERS")
+        .ok_or_else(|| anyhow!("no NCM headers"))?;
+
+    let mut headers = Vec::new();
+    let mut in_body = false;
+    for line in lines.by_ref() {
+        if line == "@@BEGIN NCM BODY" {
+            in_body = true;
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    if !in_body {
+        return Err(anyhow!("no NCM body"));
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| anyhow!("missing NCM header {name}"))
+    };
+
+    let mut entries: Vec<(String, Vec<String>)> = Vec::new();
+    let mut complete = false;
+    for line in lines {
+        if line == "@@END NCM BODY" {
+            complete = true;
+            break;
+        }
+        if line.starts_with(char::is_whitespace) {
+            // Continuation: more groups for the previous article
+            if let Some((_, groups)) = entries.last_mut() {
+                groups.extend(line.split_whitespace().map(str::to_string));
+            }
+        } else if line.starts_with('<') {
+            let mut words = line.split_whitespace();
+            let id = words.next().unwrap_or_default().to_string();
+            entries.push((id, words.map(str::to_string).collect()));
+        }
+    }
+    if !complete {
+        return Err(anyhow!("NCM body is truncated"));
+    }
+
+    let version = header("version")?;
+    if !version.starts_with("0.9") {
+        return Err(anyhow!("unsupported NoCeM version {version}"));
+    }
+    let action = header("action")?;
+    if !action.eq_ignore_ascii_case("hide") {
+        return Err(anyhow!("unsupported NoCeM action {action}"));
+    }
+    if let Ok(count) = header("count")
+        && count.parse::<usize>().ok() != Some(entries.len())
+    {
+        return Err(anyhow!(
+            "Count {count} does not match {} listed articles",
+            entries.len()
+        ));
+    }
+
+    Ok(Notice {
+        issuer: header("issuer")?,
+        notice_id: header("notice-id")?,
+        kind: header("type")?,
+        action,
+        entries,
+    })
+}
+
+/// Whether `article` was posted to a NoCeM notice group while notices are
+/// being processed.
+#[must_use]
+pub fn is_notice(article: &Message, cfg: &NocemConfig) -> bool {
+    !cfg.issuers.is_empty()
+        && extract_newsgroups(article)
+            .iter()
+            .any(|g| cfg.groups.iter().any(|n| n.eq_ignore_ascii_case(g)))
+}
+
+/// Check the signature of a notice against the key of its issuer.
+async fn verify(issuer: &NocemIssuer, signed: &ClearSigned) -> Result<()> {
+    let key = tokio::fs::read_to_string(&issuer.key_file)
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "cannot read key of NoCeM issuer {} from '{}': {e}",
+                issuer.name,
+                issuer.key_file.display()
+            )
+        })?;
+    crate::control::verify_detached(&key, &signed.armor, signed.signed_data().as_bytes())?
+        .map_err(|e| anyhow!("bad signature from NoCeM issuer {}: {e}", issuer.name))
+}
+
+/// Apply the NoCeM notice in `article`, returning how many of the listed
+/// articles were removed.
+///
+/// Notices of types the issuer is not trusted for are ignored.
+///
+/// # Errors
+///
+/// Returns an error if the article is not a well-formed notice, its issuer
+/// is not subscribed, its signature does not verify, or storage fails.
+pub async fn process_notice(
+    article: &Message,
+    storage: &DynStorage,
+    cfg: &NocemConfig,
+) -> Result<usize> {
+    let signed =
+        split_clearsigned(&article.body).ok_or_else(|| anyhow!("notice is not PGP-signed"))?;
+    let notice = parse_notice(&signed.lines.join("\n"))?;
+    let issuer = cfg
+        .issuers
+        .iter()
+        .find(|i| i.name.eq_ignore_ascii_case(&notice.issuer))
+        .ok_or_else(|| anyhow!("not subscribed to NoCeM issuer {}", notice.issuer))?;
+    if !issuer
+        .types
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(&notice.kind))
+    {
+        tracing::debug!(
+            issuer = %issuer.name,
+            kind = %notice.kind,
+            "Ignoring NoCeM notice of untrusted type"
+        );
+        return Ok(0);
+    }
+    verify(issuer, &signed).await?;
+
+    let mut removed = 0;
+    for (id, _) in &notice.entries {
+        if storage.get_article_by_id(id).await?.is_some() {
+            storage.delete_article_by_id(id).await?;
+            removed += 1;
+        }
+    }
+    tracing::info!(
+        issuer = %issuer.name,
+        notice_id = %notice.notice_id,
+        listed = notice.entries.len(),
+        removed,
+        "Applied NoCeM notice"
+    );
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTICE: &str = "@@BEGIN NCM HEADERS\n\
+        Version: 0.93\n\
+        Issuer: hunter@example.org\n\
+        Type: spam\n\
+        Action: hide\n\
+        Notice-ID: n1\n\
+        Count: 2\n\
+        @@BEGIN NCM BODY\n\
+        # comment\n\
+        <a@example.com> misc.test\n\
+        \x20alt.test\n\
+        <b@example.com> misc.test\n\
+        @@END NCM BODY\n";
+
+    #[test]
+    fn parses_entries_and_continuations() {
+        let notice = parse_notice(NOTICE).unwrap();
+        assert_eq!(notice.issuer, "hunter@example.org");
+        assert_eq!(notice.notice_id, "n1");
+        assert_eq!(notice.kind, "spam");
+        assert_eq!(
+            notice.entries,
+            vec![
+                (
+                    "<a@example.com>".to_string(),
+                    vec!["misc.test".to_string(), "alt.test".to_string()]
+                ),
+                ("<b@example.com>".to_string(), vec!["misc.test".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_notices() {
+        let truncated = NOTICE.replace("@@END NCM BODY\n", "");
+        assert!(parse_notice(&truncated).is_err());
+        let miscounted = NOTICE.replace("Count: 2", "Count: 3");
+        assert!(parse_notice(&miscounted).is_err());
+        let action = NOTICE.replace("Action: hide", "Action: delete");
+        assert!(parse_notice(&action).is_err());
+        let anonymous = NOTICE.replace("Issuer: hunter@example.org\n", "");
+        assert!(parse_notice(&anonymous).is_err());
+    }
+
+    #[test]
+    fn splits_clearsigned_text() {
+        let body = "preamble\r\n\
+            -----BEGIN PGP SIGNED MESSAGE-----\r\n\
+            Hash: SHA256\r\n\
+            \r\n\
+            - -- dashed\r\n\
+            trailing  \r\n\
+            -----BEGIN PGP SIGNATURE-----\r\n\
+            \r\n\
+            abcd\r\n\
+            -----END PGP SIGNATURE-----\r\n";
+        let signed = split_clearsigned(body).unwrap();
+        assert_eq!(signed.lines, vec!["-- dashed", "trailing  "]);
+        assert_eq!(signed.signed_data(), "-- dashed\r\ntrailing");
+        assert_eq!(
+            signed.armor,
+            "-----BEGIN PGP SIGNATURE-----\n\nabcd\n-----END PGP SIGNATURE-----\n"
+        );
+        assert!(split_clearsigned("no signature here").is_none());
+    }
+}
@@ -1,11 +1,11 @@
 //! Overview format handling for NNTP OVER and XOVER commands.
 //!
 //! This module provides centralized configuration for overview information
-//! as specified in RFC2980 and RFC3977.
+//! as specified in RFC2980 and RFC3977, and the metadata items derived
+//! from the article itself.
 
 use crate::Message;
-use crate::handlers::utils::{extract_message_id, get_header_value};
-use anyhow::Result;
+use crate::handlers::utils::get_header_value;
 
 /// Standard overview format fields as defined in RFC2980.
 /// This determines the order and content of fields returned by OVER/XOVER commands
@@ -18,41 +18,184 @@ pub const OVERVIEW_FORMAT: &[&str] = &[
     "References:",
     ":bytes",
     ":lines",
+    "Xref:full",
 ];
 
-/// Generate overview line for an article according to the standard format.
-/// Returns a tab-separated line with article number and overview fields.
-pub async fn generate_overview_line(
-    storage: &dyn crate::storage::Storage,
-    article_number: u64,
-    article: &Message,
-) -> Result<String> {
+/// Size of an article as sent by ARTICLE: headers, the empty line and the
+/// body, counting each CRLF as two octets and leaving out dot-stuffing and
+/// the terminating line, as RFC 3977 specifies for `:bytes`.
+#[must_use]
+pub fn article_size(article: &Message) -> u64 {
+    let headers: usize = article
+        .headers
+        .iter()
+        .map(|(name, val)| crate::rewrite::fold_header(name, val).len() + 2)
+        .sum();
+    let body: usize = article.body.lines().map(|line| line.len() + 2).sum();
+    (headers + 2 + body) as u64
+}
+
+/// Number of lines in the body of an article, for `:lines`.
+#[must_use]
+pub fn article_lines(article: &Message) -> u64 {
+    article.body.lines().count() as u64
+}
+
+/// Detect a binary posted with yEnc or uuencode in the body of an article.
+///
+/// yEnc parts are reported as `yenc part/total` when the `=ybegin` line
+/// numbers them, otherwise as `yenc`; uuencoded bodies as `uuencode`.
+#[must_use]
+pub fn binary_encoding(article: &Message) -> Option<String> {
+    article.body.lines().find_map(|line| {
+        if let Some(params) = line.strip_prefix("=ybegin ") {
+            let param = |key: &str| {
+                params
+                    .split_whitespace()
+                    .find_map(|p| p.strip_prefix(key)?.strip_prefix('='))
+                    .and_then(|v| v.parse::<u32>().ok())
+            };
+            return Some(match (param("part"), param("total")) {
+                (Some(part), Some(total)) => format!("yenc {part}/{total}"),
+                _ => "yenc".to_string(),
+            });
+        }
+        let (mode, name) = line.strip_prefix("begin ")?.split_once(' ')?;
+        (matches!(mode.len(), 3 | 4)
+            && mode.bytes().all(|b| matches!(b, b'0'..=b'7'))
+            && !name.trim().is_empty())
+        .then(|| "uuencode".to_string())
+    })
+}
+
+/// Format the overview line of an article.
+/// Returns a tab-separated line with article number and overview fields,
+/// followed by one `Name: value` field for each of the `extra` headers.
+#[must_use]
+pub fn format_overview_line(article_number: u64, article: &Message, extra: &[String]) -> String {
     let subject = get_header_value(article, "Subject").unwrap_or_default();
     let from = get_header_value(article, "From").unwrap_or_default();
     let date = get_header_value(article, "Date").unwrap_or_default();
     let msgid = get_header_value(article, "Message-ID").unwrap_or_default();
     let refs = get_header_value(article, "References").unwrap_or_default();
 
-    let bytes = if let Some(id) = extract_message_id(article) {
-        storage
-            .get_message_size(&id)
-            .await?
-            .unwrap_or(article.body.len() as u64)
-    } else {
-        article.body.len() as u64
-    };
+    let bytes = article_size(article);
+    let lines = article_lines(article);
+    let xref = get_header_value(article, "Xref")
+        .map(|xref| format!("Xref: {xref}"))
+        .unwrap_or_default();
 
-    let lines = article.body.lines().count();
-
-    Ok(format!(
-        "{article_number}\t{subject}\t{from}\t{date}\t{msgid}\t{refs}\t{bytes}\t{lines}"
-    ))
+    let mut line = format!(
+        "{article_number}\t{subject}\t{from}\t{date}\t{msgid}\t{refs}\t{bytes}\t{lines}\t{xref}"
+    );
+    for name in extra {
+        line.push('\t');
+        if let Some(value) = get_header_value(article, name) {
+            // Tabs and line breaks would split the field
+            let value: String = value
+                .chars()
+                .filter(|c| !matches!(c, '\r' | '\n'))
+                .map(|c| if c == '\t' { ' ' } else { c })
+                .collect();
+            line.push_str(&format!("{name}: {value}"));
+        }
+    }
+    line
 }
 
-/// Get the overview format fields for LIST OVERVIEW.FMT command.
-pub fn get_overview_format_lines() -> Vec<String> {
+/// Get the overview format fields for LIST OVERVIEW.FMT command, the
+/// `extra` headers following the standard fields as full fields.
+pub fn get_overview_format_lines(extra: &[String]) -> Vec<String> {
     OVERVIEW_FORMAT
         .iter()
         .map(|&s| format!("{s}\r\n"))
+        .chain(extra.iter().map(|name| format!("{name}:full\r\n")))
         .collect()
 }
+
+/// Header names for extra overview fields, as configured: a trailing `:`
+/// or `:full` is dropped, and headers already in the overview or listed
+/// twice are left out.
+#[must_use]
+pub fn extra_headers(configured: &[String]) -> Vec<String> {
+    let mut headers: Vec<String> = Vec::new();
+    for name in configured {
+        let name = name.trim();
+        let name = name
+            .strip_suffix(":full")
+            .or_else(|| name.strip_suffix(':'))
+            .unwrap_or(name)
+            .trim();
+        let standard = OVERVIEW_FORMAT.iter().any(|field| {
+            field
+                .split(':')
+                .next()
+                .is_some_and(|header| header.eq_ignore_ascii_case(name))
+        });
+        if name.is_empty() || standard || headers.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        headers.push(name.to_string());
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smallvec::smallvec;
+
+    fn article(body: &str) -> Message {
+        Message {
+            headers: smallvec![("Subject".to_string(), "Test".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn size_counts_headers_and_crlf_line_endings() {
+        // "Subject: Test\r\n" + "\r\n" + ".one\r\n" + "two\r\n"
+        assert_eq!(article_size(&article(".one\ntwo")), 15 + 2 + 6 + 5);
+        assert_eq!(article_size(&article(".one\r\ntwo\r\n")), 28);
+        assert_eq!(article_lines(&article(".one\r\ntwo\r\n")), 2);
+    }
+
+    #[test]
+    fn extra_headers_follow_the_standard_fields() {
+        let mut article = article("Body");
+        article.headers.push((
+            "Newsgroups".to_string(),
+            "misc.test,\tmisc.misc".to_string(),
+        ));
+        let extra = extra_headers(&[
+            "Newsgroups:full".to_string(),
+            "Xref:full".to_string(),
+            "keywords".to_string(),
+            "newsgroups".to_string(),
+        ]);
+        assert_eq!(extra, vec!["Newsgroups", "keywords"]);
+
+        let line = format_overview_line(3, &article, &extra);
+        assert!(
+            line.ends_with("\tNewsgroups: misc.test, misc.misc\t"),
+            "{line:?}"
+        );
+        assert_eq!(line.split('\t').count(), 11);
+        assert_eq!(
+            get_overview_format_lines(&extra)[7..],
+            ["Xref:full\r\n", "Newsgroups:full\r\n", "keywords:full\r\n"]
+        );
+    }
+
+    #[test]
+    fn binaries_are_detected() {
+        let yenc = "text\r\n=ybegin part=1 total=3 line=128 size=9 name=x\r\n";
+        assert_eq!(binary_encoding(&article(yenc)).as_deref(), Some("yenc 1/3"));
+        let single = "=ybegin line=128 size=9 name=x\r\n";
+        assert_eq!(binary_encoding(&article(single)).as_deref(), Some("yenc"));
+        let uu = "begin 0644 file name.txt\r\n";
+        assert_eq!(binary_encoding(&article(uu)).as_deref(), Some("uuencode"));
+        assert_eq!(binary_encoding(&article("begin 9 things\r\n")), None);
+        assert_eq!(binary_encoding(&article("Plain text\r\n")), None);
+    }
+}
@@ -310,26 +310,69 @@ pub fn parse_datetime(
     time: &str,
     gmt: bool,
 ) -> Result<chrono::DateTime<chrono::Utc>, &'static str> {
+    parse_datetime_at(date, time, gmt, chrono::Utc::now())
+}
+
+/// Parse NEWGROUPS and NEWNEWS date and time arguments as of `now`.
+///
+/// A two-digit year is taken from the century of `now` if it is not later
+/// than the current year and from the previous century otherwise (RFC 3977
+/// Section 7.3.2). Without `GMT` the time is the server's local time; a
+/// time repeated when clocks go back is read as the earlier one, and a time
+/// skipped when they go forward as the hour after.
+///
+/// # Errors
+///
+/// Returns an error if the date or time format is invalid.
+pub fn parse_datetime_at(
+    date: &str,
+    time: &str,
+    gmt: bool,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<chrono::DateTime<chrono::Utc>, &'static str> {
+    use chrono::Datelike;
+
     if !(date.len() == 6 || date.len() == 8) || !date.chars().all(|c| c.is_ascii_digit()) {
         return Err("invalid date");
     }
     if time.len() != 6 || !time.chars().all(|c| c.is_ascii_digit()) {
         return Err("invalid time");
     }
-    let fmt = if date.len() == 6 { "%y%m%d" } else { "%Y%m%d" };
-    let naive_date = chrono::NaiveDate::parse_from_str(date, fmt).map_err(|_| "invalid date")?;
+    let number = |digits: &str| digits.parse::<u32>().map_err(|_| "invalid date");
+    let (year, rest) = date.split_at(date.len() - 4);
+    let year = if year.len() == 2 {
+        let yy = i32::try_from(number(year)?).map_err(|_| "invalid date")?;
+        let century = now.year() - now.year().rem_euclid(100);
+        if yy <= now.year().rem_euclid(100) {
+            century + yy
+        } else {
+            century - 100 + yy
+        }
+    } else {
+        i32::try_from(number(year)?).map_err(|_| "invalid date")?
+    };
+    let naive_date =
+        chrono::NaiveDate::from_ymd_opt(year, number(&rest[..2])?, number(&rest[2..])?)
+            .ok_or("invalid date")?;
     let naive_time =
         chrono::NaiveTime::parse_from_str(time, "%H%M%S").map_err(|_| "invalid time")?;
     let naive = naive_date.and_time(naive_time);
-    Ok(if gmt {
-        chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(naive, chrono::Utc)
-    } else {
-        chrono::Local
-            .from_local_datetime(&naive)
-            .single()
-            .ok_or("invalid local time")?
-            .with_timezone(&chrono::Utc)
-    })
+    if gmt {
+        return Ok(chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(
+            naive,
+            chrono::Utc,
+        ));
+    }
+    chrono::Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| {
+            chrono::Local
+                .from_local_datetime(&(naive + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .map(|local| local.with_timezone(&chrono::Utc))
+        .ok_or("invalid local time")
 }
 
 /// Parse the article number range format used by several commands
@@ -3,19 +3,37 @@
 //! This module handles the synchronization of articles between NNTP servers
 //! using peer relationships. It supports both IHAVE and TAKETHIS transfer modes
 //! for efficient article distribution.
+//!
+//! Each peer is pushed to, pulled from, or both, according to its
+//! [`PeerMode`]. Pushes and pulls keep separate watermarks in the peer
+//! database, and a per-peer history of articles known to be at the peer
+//! keeps articles from being offered back to the server they came from.
+//!
+//! Every article offered to a peer, by the scheduled push or a streaming
+//! feed, is counted in the peer database by how the peer answered, along
+//! with the bytes it accepted and when it was last reached. Each round is
+//! also logged in the form of an `innfeed` `final` line, so `innreport`
+//! can summarize feed health from the logs.
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use futures_util::{StreamExt, TryStreamExt};
 use rustls_native_certs::load_native_certs;
+use rustls_pemfile::{certs, pkcs8_private_keys};
 use sqlx::{
     Row, SqlitePool,
     sqlite::{SqliteConnectOptions, SqlitePoolOptions},
 };
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader as StdBufReader;
+use std::net::IpAddr;
 use std::str::FromStr;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
 use tokio::net::TcpStream;
+use tokio::sync::RwLock;
 use tokio_cron_scheduler::{Job, JobScheduler};
 use tokio_rustls::{
     TlsConnector,
@@ -24,29 +42,148 @@ use tokio_rustls::{
 use tracing::{Instrument, info_span};
 use uuid;
 
+use crate::config::{Config, PeerFilter, PeerMode, PeerRule, PeerTransport};
+use crate::queue::{ArticleQueue, QueuedArticle};
 use crate::storage::DynStorage;
-use crate::wildmat::wildmat;
+use crate::transport::ClientStream;
 use crate::{
     Message,
-    handlers::utils::{extract_message_id, send_body, send_headers, write_simple},
+    handlers::utils::{
+        extract_message_id, extract_newsgroups, send_body, send_headers, write_simple,
+    },
+    parse_message,
 };
 
 /// Result type for peer operations.
 type PeerResult<T> = Result<T>;
 
 /// Connection credentials for peer authentication.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct PeerCredentials {
     username: String,
-    password: String,
+    password: Option<String>,
 }
 
 /// Parsed peer connection information.
-#[derive(Debug, Clone)]
-struct PeerConnectionInfo {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PeerConnectionInfo {
     host: String,
     port: u16,
     credentials: Option<PeerCredentials>,
+    tls: bool,
+    /// Certificate and key files presented to the peer
+    client_identity: Option<(String, String)>,
+}
+
+/// Connection information for a peer from its address and transport
+/// settings. `username` and `password` take precedence over credentials
+/// embedded in the address.
+pub(crate) fn peer_connection_info(
+    sitename: &str,
+    transport: &PeerTransport,
+) -> PeerConnectionInfo {
+    let default_port = if transport.tls { 563 } else { 119 };
+    let mut info = parse_peer_address(sitename, default_port);
+    if let Some(username) = &transport.username {
+        info.credentials = Some(PeerCredentials {
+            username: username.clone(),
+            password: transport.password.clone(),
+        });
+    }
+    info.tls = transport.tls;
+    info.client_identity = transport
+        .tls_client_cert
+        .clone()
+        .zip(transport.tls_client_key.clone());
+    info
+}
+
+/// How long a peer's host name may take to resolve before it matches
+/// nothing.
+const PEER_LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long resolved peer addresses are trusted before being looked up
+/// again.
+const PEER_ADDRESSES_TTL: Duration = Duration::from_secs(300);
+
+/// The addresses of the configured peers, so that a connection from one of
+/// them may feed articles without authenticating.
+///
+/// Shared by the connections of a server, so that host names are looked up
+/// once per [`PEER_ADDRESSES_TTL`] or change of the configured peers rather
+/// than on every connection.
+#[derive(Default)]
+pub struct PeerAddresses {
+    resolved: tokio::sync::Mutex<Option<ResolvedPeers>>,
+}
+
+/// Peer addresses resolved at one time.
+struct ResolvedPeers {
+    /// The `(host, port)` of each peer they were resolved for
+    hosts: Vec<(String, u16)>,
+    at: Instant,
+    addrs: HashSet<IpAddr>,
+}
+
+impl PeerAddresses {
+    /// Whether `ip` is the address of one of `peers`. Peers named by host
+    /// name are resolved; names failing to resolve match nothing.
+    pub async fn contains(&self, peers: &[PeerRule], ip: IpAddr) -> bool {
+        let hosts: Vec<(String, u16)> = peers
+            .iter()
+            .map(|peer| {
+                let info = peer_connection_info(&peer.sitename, &peer.transport);
+                (info.host, info.port)
+            })
+            .collect();
+        // Connections arriving while the names are looked up wait for that
+        // lookup rather than starting their own
+        let mut resolved = self.resolved.lock().await;
+        let fresh = resolved
+            .as_ref()
+            .is_some_and(|r| r.hosts == hosts && r.at.elapsed() < PEER_ADDRESSES_TTL);
+        if !fresh {
+            let addrs = resolve_hosts(&hosts).await;
+            *resolved = Some(ResolvedPeers {
+                hosts,
+                at: Instant::now(),
+                addrs,
+            });
+        }
+        resolved
+            .as_ref()
+            .is_some_and(|r| r.addrs.contains(&ip.to_canonical()))
+    }
+}
+
+/// Resolve `hosts` at once, each given at most [`PEER_LOOKUP_TIMEOUT`].
+async fn resolve_hosts(hosts: &[(String, u16)]) -> HashSet<IpAddr> {
+    let lookups = hosts.iter().map(|(host, port)| async move {
+        if let Ok(addr) = host.parse::<IpAddr>() {
+            return vec![addr.to_canonical()];
+        }
+        match tokio::time::timeout(
+            PEER_LOOKUP_TIMEOUT,
+            tokio::net::lookup_host((host.as_str(), *port)),
+        )
+        .await
+        {
+            Ok(Ok(addrs)) => addrs.map(|addr| addr.ip().to_canonical()).collect(),
+            Ok(Err(e)) => {
+                tracing::warn!(host = %host, error = %e, "Failed to resolve peer");
+                Vec::new()
+            }
+            Err(_) => {
+                tracing::warn!(host = %host, "Timed out resolving peer");
+                Vec::new()
+            }
+        }
+    });
+    futures_util::future::join_all(lookups)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
 }
 
 /// Parse peer address string into connection components.
@@ -64,6 +201,8 @@ fn parse_peer_address(addr: &str, default_port: u16) -> PeerConnectionInfo {
         host,
         port,
         credentials,
+        tls: true,
+        client_identity: None,
     }
 }
 
@@ -79,7 +218,7 @@ fn extract_credentials(addr: &str) -> (Option<PeerCredentials>, &str) {
 
     let credentials = PeerCredentials {
         username: username.to_string(),
-        password: password.to_string(),
+        password: Some(password.to_string()),
     };
     (Some(credentials), rest)
 }
@@ -130,48 +269,91 @@ fn parse_regular_address(host_port: &str, default_port: u16) -> (String, u16) {
     (host_port[..colon_pos].to_string(), port)
 }
 
-/// Creates a TLS connector for secure peer connections.
-fn create_tls_connector() -> PeerResult<TlsConnector> {
+/// Creates a TLS connector for secure peer connections, presenting the
+/// certificate in `client_identity` if one is given.
+pub(crate) fn create_tls_connector(
+    client_identity: Option<&(String, String)>,
+) -> PeerResult<TlsConnector> {
     let mut roots = RootCertStore::empty();
     for cert in load_native_certs()? {
         roots.add(&rustls::Certificate(cert.0))?;
     }
-    let config = rustls::ClientConfig::builder()
+    let builder = rustls::ClientConfig::builder()
         .with_safe_defaults()
-        .with_root_certificates(roots)
-        .with_no_client_auth();
+        .with_root_certificates(roots);
+    let config = match client_identity {
+        Some((cert_path, key_path)) => {
+            let (certs, key) = load_client_identity(cert_path, key_path)?;
+            builder.with_client_auth_cert(certs, key)?
+        }
+        None => builder.with_no_client_auth(),
+    };
     Ok(TlsConnector::from(Arc::new(config)))
 }
 
+/// Load a client certificate chain and its PKCS#8 private key.
+fn load_client_identity(
+    cert_path: &str,
+    key_path: &str,
+) -> PeerResult<(Vec<rustls::Certificate>, rustls::PrivateKey)> {
+    let open = |path: &str| {
+        File::open(path)
+            .map(StdBufReader::new)
+            .map_err(|e| anyhow::anyhow!("Failed to open '{path}': {e}"))
+    };
+    let certs: Vec<_> = certs(&mut open(cert_path)?)
+        .map_err(|e| anyhow::anyhow!("Failed to parse certificate file '{cert_path}': {e}"))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No certificate found in certificate file '{cert_path}'"
+        ));
+    }
+    let key = pkcs8_private_keys(&mut open(key_path)?)
+        .map_err(|e| anyhow::anyhow!("Failed to parse private key file '{key_path}': {e}"))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No PKCS#8 private key found in '{key_path}'"))?;
+    Ok((certs, rustls::PrivateKey(key)))
+}
+
 /// Manages a connection to a peer NNTP server.
-struct PeerConnection {
-    reader: BufReader<tokio::io::ReadHalf<tokio_rustls::client::TlsStream<TcpStream>>>,
-    writer: tokio::io::WriteHalf<tokio_rustls::client::TlsStream<TcpStream>>,
+pub(crate) struct PeerConnection {
+    reader: BufReader<ReadHalf<Box<dyn ClientStream>>>,
+    writer: WriteHalf<Box<dyn ClientStream>>,
     line_buffer: String,
 }
 
 impl PeerConnection {
     /// Establish a connection to a peer server.
-    async fn connect(connection_info: &PeerConnectionInfo) -> PeerResult<Self> {
+    pub(crate) async fn connect(connection_info: &PeerConnectionInfo) -> PeerResult<Self> {
         let addr = format!("{}:{}", connection_info.host, connection_info.port);
         let tcp = TcpStream::connect(&addr)
             .await
             .map_err(|e| anyhow::anyhow!("Failed to connect to {addr}: {e}"))?;
 
-        let connector = create_tls_connector()
-            .map_err(|e| anyhow::anyhow!("Failed to create TLS connector: {e}"))?;
-
-        let server_name =
-            rustls::ServerName::try_from(connection_info.host.as_str()).map_err(|e| {
-                anyhow::anyhow!("Invalid server name '{}': {}", connection_info.host, e)
-            })?;
-
-        let tls_stream = connector
-            .connect(server_name, tcp)
-            .await
-            .map_err(|e| anyhow::anyhow!("TLS handshake failed for {addr}: {e}"))?;
+        let stream: Box<dyn ClientStream> = if connection_info.tls {
+            let connector = create_tls_connector(connection_info.client_identity.as_ref())
+                .map_err(|e| anyhow::anyhow!("Failed to create TLS connector: {e}"))?;
+
+            let server_name =
+                rustls::ServerName::try_from(connection_info.host.as_str()).map_err(|e| {
+                    anyhow::anyhow!("Invalid server name '{}': {}", connection_info.host, e)
+                })?;
+
+            Box::new(
+                connector
+                    .connect(server_name, tcp)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("TLS handshake failed for {addr}: {e}"))?,
+            )
+        } else {
+            Box::new(tcp)
+        };
 
-        let (read_half, write_half) = tokio::io::split(tls_stream);
+        let (read_half, write_half) = tokio::io::split(stream);
 
         let mut connection = Self {
             reader: BufReader::new(read_half),
@@ -201,15 +383,75 @@ impl PeerConnection {
     }
 
     /// Read a response line from the server.
-    async fn read_response(&mut self) -> PeerResult<&str> {
+    pub(crate) async fn read_response(&mut self) -> PeerResult<&str> {
         self.line_buffer.clear();
         self.reader.read_line(&mut self.line_buffer).await?;
         Ok(&self.line_buffer)
     }
 
+    /// Read the lines of a multi-line response up to the terminating dot,
+    /// removing dot-stuffing.
+    async fn read_multiline(&mut self) -> PeerResult<Vec<String>> {
+        let mut lines = Vec::new();
+        loop {
+            self.line_buffer.clear();
+            if self.reader.read_line(&mut self.line_buffer).await? == 0 {
+                return Err(anyhow::anyhow!(
+                    "Connection closed during multi-line response"
+                ));
+            }
+            let line = self.line_buffer.trim_end_matches(['\r', '\n']);
+            if line == "." {
+                return Ok(lines);
+            }
+            lines.push(line.strip_prefix('.').unwrap_or(line).to_string());
+        }
+    }
+
+    /// List the Message-IDs the peer received since `since` in groups
+    /// matching `pattern`.
+    async fn new_news(&mut self, pattern: &str, since: DateTime<Utc>) -> PeerResult<Vec<String>> {
+        self.send_command(&format!(
+            "NEWNEWS {pattern} {} GMT\r\n",
+            since.format("%Y%m%d %H%M%S")
+        ))
+        .await?;
+        let response = self.read_response().await?;
+        if !response.starts_with("230") {
+            return Err(anyhow::anyhow!("NEWNEWS failed: {}", response.trim()));
+        }
+        self.read_multiline().await
+    }
+
+    /// Fetch an article by Message-ID, or `None` if the peer no longer has it.
+    async fn fetch_article(&mut self, msg_id: &str) -> PeerResult<Option<Message>> {
+        self.send_command(&format!("ARTICLE {msg_id}\r\n")).await?;
+        let response = self.read_response().await?;
+        if !response.starts_with("220") {
+            return Ok(None);
+        }
+        let mut text = self.read_multiline().await?.join("\r\n");
+        text.push_str("\r\n");
+        let (_, article) = parse_message(&text)
+            .map_err(|e| anyhow::anyhow!("Failed to parse article {msg_id}: {e}"))?;
+        Ok(Some(article))
+    }
+
+    /// Switch the connection to streaming mode (RFC 4644).
+    pub(crate) async fn mode_stream(&mut self) -> PeerResult<()> {
+        self.send_command("MODE STREAM\r\n").await?;
+        let response = self.read_response().await?;
+        if !response.starts_with("203") {
+            return Err(anyhow::anyhow!("MODE STREAM refused: {}", response.trim()));
+        }
+        Ok(())
+    }
+
     /// Send a command to the server.
-    async fn send_command(&mut self, command: &str) -> PeerResult<()> {
-        write_simple(&mut self.writer, command).await
+    pub(crate) async fn send_command(&mut self, command: &str) -> PeerResult<()> {
+        write_simple(&mut self.writer, command).await?;
+        self.writer.flush().await?;
+        Ok(())
     }
 
     /// Authenticate with the server using provided credentials.
@@ -219,7 +461,10 @@ impl PeerConnection {
         let response = self.read_response().await?;
 
         if response.starts_with("381") {
-            self.send_command(&format!("AUTHINFO PASS {}\r\n", creds.password))
+            let Some(password) = &creds.password else {
+                return Err(anyhow::anyhow!("Peer asked for a password but none is set"));
+            };
+            self.send_command(&format!("AUTHINFO PASS {password}\r\n"))
                 .await?;
             let response = self.read_response().await?;
             if !response.starts_with("281") {
@@ -233,12 +478,12 @@ impl PeerConnection {
     }
 
     /// Transfer an article using IHAVE protocol.
-    async fn transfer_article(&mut self, article: &Message, msg_id: &str) -> PeerResult<()> {
+    async fn transfer_article(&mut self, article: &Message, msg_id: &str) -> PeerResult<Transfer> {
         // Send IHAVE command
         self.send_command(&format!("IHAVE {msg_id}\r\n")).await?;
         let response = self.read_response().await?;
         if !response.starts_with("335") {
-            return Ok(()); // Article not wanted by peer
+            return Ok(ihave_refusal(response));
         }
 
         // Send article content
@@ -246,15 +491,18 @@ impl PeerConnection {
 
         // Read and validate final response
         let response = self.read_response().await?;
+        if response.starts_with("436") || response.starts_with("437") {
+            return Ok(ihave_refusal(response));
+        }
         if !response.starts_with("2") {
             return Err(anyhow::anyhow!("Transfer failed: {}", response.trim()));
         }
 
-        Ok(())
+        Ok(Transfer::Accepted)
     }
 
     /// Send the complete article content including headers and body.
-    async fn send_article_content(&mut self, article: &Message) -> PeerResult<()> {
+    pub(crate) async fn send_article_content(&mut self, article: &Message) -> PeerResult<()> {
         send_headers(&mut self.writer, article).await?;
         self.send_command("\r\n").await?;
         send_body(&mut self.writer, &article.body).await?;
@@ -263,12 +511,24 @@ impl PeerConnection {
     }
 
     /// Close the connection gracefully.
-    async fn close(mut self) -> PeerResult<()> {
+    pub(crate) async fn close(mut self) -> PeerResult<()> {
         let _ = self.writer.shutdown().await;
         Ok(())
     }
 }
 
+/// How a peer declining an article with `IHAVE` answered.
+fn ihave_refusal(response: &str) -> Transfer {
+    if response.starts_with("436") {
+        Transfer::Deferred
+    } else if response.starts_with("437") {
+        Transfer::Rejected
+    } else {
+        // Not wanted by the peer
+        Transfer::Refused
+    }
+}
+
 #[derive(Clone)]
 pub struct PeerDb {
     pool: SqlitePool,
@@ -292,7 +552,61 @@ impl PeerDb {
         sqlx::query(
             r"CREATE TABLE IF NOT EXISTS peers (
                 sitename TEXT PRIMARY KEY,
-                last_sync INTEGER
+                last_sync INTEGER,
+                last_pull INTEGER NOT NULL DEFAULT 0
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Databases created before pulling was supported lack the pull
+        // watermark
+        let has_last_pull: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM pragma_table_info('peers') WHERE name = 'last_pull'",
+        )
+        .fetch_one(&pool)
+        .await?;
+        if has_last_pull == 0 {
+            sqlx::query("ALTER TABLE peers ADD COLUMN last_pull INTEGER NOT NULL DEFAULT 0")
+                .execute(&pool)
+                .await?;
+        }
+
+        // Articles known to be present at each peer
+        sqlx::query(
+            r"CREATE TABLE IF NOT EXISTS peer_history (
+                sitename TEXT NOT NULL,
+                message_id TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL,
+                PRIMARY KEY(sitename, message_id)
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Articles offered to each peer, by outcome
+        sqlx::query(
+            r"CREATE TABLE IF NOT EXISTS peer_stats (
+                sitename TEXT PRIMARY KEY,
+                offered INTEGER NOT NULL DEFAULT 0,
+                accepted INTEGER NOT NULL DEFAULT 0,
+                refused INTEGER NOT NULL DEFAULT 0,
+                rejected INTEGER NOT NULL DEFAULT 0,
+                deferred INTEGER NOT NULL DEFAULT 0,
+                bytes INTEGER NOT NULL DEFAULT 0,
+                last_contact INTEGER NOT NULL DEFAULT 0
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Articles waiting to be streamed to each peer
+        sqlx::query(
+            r"CREATE TABLE IF NOT EXISTS peer_backlog (
+                sitename TEXT NOT NULL,
+                message_id TEXT NOT NULL,
+                not_before INTEGER NOT NULL,
+                PRIMARY KEY(sitename, message_id)
             )",
         )
         .execute(&pool)
@@ -345,13 +659,25 @@ impl PeerDb {
                     .bind(&existing_peer)
                     .execute(&self.pool)
                     .await?;
+                sqlx::query("DELETE FROM peer_history WHERE sitename = ?")
+                    .bind(&existing_peer)
+                    .execute(&self.pool)
+                    .await?;
+                sqlx::query("DELETE FROM peer_backlog WHERE sitename = ?")
+                    .bind(&existing_peer)
+                    .execute(&self.pool)
+                    .await?;
+                sqlx::query("DELETE FROM peer_stats WHERE sitename = ?")
+                    .bind(&existing_peer)
+                    .execute(&self.pool)
+                    .await?;
             }
         }
 
         Ok(())
     }
 
-    /// Update the last synchronization time for a peer.
+    /// Update the time up to which articles have been pushed to a peer.
     ///
     /// # Errors
     ///
@@ -365,7 +691,7 @@ impl PeerDb {
         Ok(())
     }
 
-    /// Get the last synchronization time for a peer.
+    /// Get the time up to which articles have been pushed to a peer.
     ///
     /// # Errors
     ///
@@ -388,21 +714,357 @@ impl PeerDb {
             None => Ok(None),
         }
     }
+
+    /// Update the time up to which articles have been pulled from a peer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database update fails.
+    pub async fn update_last_pull(&self, name: &str, when: DateTime<Utc>) -> PeerResult<()> {
+        sqlx::query("UPDATE peers SET last_pull = ? WHERE sitename = ?")
+            .bind(when.timestamp())
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the time up to which articles have been pulled from a peer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_last_pull(&self, name: &str) -> PeerResult<Option<DateTime<Utc>>> {
+        let timestamp: Option<i64> =
+            sqlx::query_scalar("SELECT last_pull FROM peers WHERE sitename = ?")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(timestamp
+            .filter(|&t| t != 0)
+            .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)))
+    }
+
+    /// Record that a peer has an article, so it is not offered to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database update fails.
+    pub async fn record_peer_has(&self, name: &str, message_id: &str) -> PeerResult<()> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO peer_history (sitename, message_id, recorded_at) VALUES (?, ?, ?)",
+        )
+        .bind(name)
+        .bind(message_id)
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Check whether a peer is known to have an article.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn peer_has(&self, name: &str, message_id: &str) -> PeerResult<bool> {
+        let row = sqlx::query("SELECT 1 FROM peer_history WHERE sitename = ? AND message_id = ?")
+            .bind(name)
+            .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    /// Forget history entries recorded before `before`.
+    ///
+    /// Articles are recorded no earlier than they were stored locally, so
+    /// entries older than the push watermark can no longer affect a push.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database update fails.
+    pub async fn prune_history(&self, name: &str, before: DateTime<Utc>) -> PeerResult<()> {
+        sqlx::query("DELETE FROM peer_history WHERE sitename = ? AND recorded_at < ?")
+            .bind(name)
+            .bind(before.timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Add an article to the backlog of a streaming peer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database update fails.
+    pub async fn push_backlog(&self, name: &str, message_id: &str) -> PeerResult<()> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO peer_backlog (sitename, message_id, not_before) VALUES (?, ?, ?)",
+        )
+        .bind(name)
+        .bind(message_id)
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// List up to `limit` backlog entries of a peer that are due, oldest
+    /// first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn due_backlog(&self, name: &str, limit: usize) -> PeerResult<Vec<String>> {
+        Ok(sqlx::query_scalar(
+            "SELECT message_id FROM peer_backlog WHERE sitename = ? AND not_before <= ? \
+             ORDER BY not_before, rowid LIMIT ?",
+        )
+        .bind(name)
+        .bind(Utc::now().timestamp())
+        .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Postpone a backlog entry until `until`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database update fails.
+    pub async fn defer_backlog(
+        &self,
+        name: &str,
+        message_id: &str,
+        until: DateTime<Utc>,
+    ) -> PeerResult<()> {
+        sqlx::query("UPDATE peer_backlog SET not_before = ? WHERE sitename = ? AND message_id = ?")
+            .bind(until.timestamp())
+            .bind(name)
+            .bind(message_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove an article from the backlog of a peer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database update fails.
+    pub async fn remove_backlog(&self, name: &str, message_id: &str) -> PeerResult<()> {
+        sqlx::query("DELETE FROM peer_backlog WHERE sitename = ? AND message_id = ?")
+            .bind(name)
+            .bind(message_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Number of articles in the backlog of a peer, due or not.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn backlog_len(&self, name: &str) -> PeerResult<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM peer_backlog WHERE sitename = ?")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count.unsigned_abs())
+    }
+
+    /// Add the traffic of a round with a peer to its counters and record
+    /// that it was reached now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database update fails.
+    pub async fn record_traffic(&self, name: &str, traffic: &PeerTraffic) -> PeerResult<()> {
+        let count = |n: u64| i64::try_from(n).unwrap_or(i64::MAX);
+        sqlx::query(
+            "INSERT INTO peer_stats \
+             (sitename, offered, accepted, refused, rejected, deferred, bytes, last_contact) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT(sitename) DO UPDATE SET \
+             offered = offered + excluded.offered, \
+             accepted = accepted + excluded.accepted, \
+             refused = refused + excluded.refused, \
+             rejected = rejected + excluded.rejected, \
+             deferred = deferred + excluded.deferred, \
+             bytes = bytes + excluded.bytes, \
+             last_contact = excluded.last_contact",
+        )
+        .bind(name)
+        .bind(count(traffic.offered))
+        .bind(count(traffic.accepted))
+        .bind(count(traffic.refused))
+        .bind(count(traffic.rejected))
+        .bind(count(traffic.deferred))
+        .bind(count(traffic.bytes))
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The counters of every configured peer, by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn peer_stats(&self) -> PeerResult<Vec<PeerStats>> {
+        let rows = sqlx::query(
+            "SELECT p.sitename, s.offered, s.accepted, s.refused, s.rejected, s.deferred, \
+             s.bytes, s.last_contact \
+             FROM peers p LEFT JOIN peer_stats s ON s.sitename = p.sitename \
+             ORDER BY p.sitename",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let count = |row: &sqlx::sqlite::SqliteRow, column: &str| -> PeerResult<u64> {
+            let value: Option<i64> = row.try_get(column)?;
+            Ok(value.unwrap_or(0).unsigned_abs())
+        };
+        rows.iter()
+            .map(|row| {
+                let last_contact: Option<i64> = row.try_get("last_contact")?;
+                Ok(PeerStats {
+                    sitename: row.try_get("sitename")?,
+                    traffic: PeerTraffic {
+                        offered: count(row, "offered")?,
+                        accepted: count(row, "accepted")?,
+                        refused: count(row, "refused")?,
+                        rejected: count(row, "rejected")?,
+                        deferred: count(row, "deferred")?,
+                        bytes: count(row, "bytes")?,
+                    },
+                    last_contact: last_contact
+                        .filter(|&t| t != 0)
+                        .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Articles offered to a peer, by how it answered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PeerTraffic {
+    pub offered: u64,
+    pub accepted: u64,
+    /// Not wanted because the peer already has them
+    pub refused: u64,
+    pub rejected: u64,
+    /// Put off by the peer for now
+    pub deferred: u64,
+    /// Size of the accepted articles
+    pub bytes: u64,
+}
+
+impl PeerTraffic {
+    /// Count one answer to an article of `size` bytes.
+    pub fn count(&mut self, answer: Transfer, size: u64) {
+        match answer {
+            Transfer::Accepted => {
+                self.accepted += 1;
+                self.bytes += size;
+            }
+            Transfer::Refused => self.refused += 1,
+            Transfer::Rejected => self.rejected += 1,
+            Transfer::Deferred => self.deferred += 1,
+        }
+    }
+
+    /// Add the counters of `other`.
+    pub fn merge(&mut self, other: &PeerTraffic) {
+        self.offered += other.offered;
+        self.accepted += other.accepted;
+        self.refused += other.refused;
+        self.rejected += other.rejected;
+        self.deferred += other.deferred;
+        self.bytes += other.bytes;
+    }
+}
+
+/// How a peer answered an offered article.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    Accepted,
+    Refused,
+    Rejected,
+    Deferred,
+}
+
+/// The counters of a peer and when it was last reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerStats {
+    pub sitename: String,
+    pub traffic: PeerTraffic,
+    pub last_contact: Option<DateTime<Utc>>,
+}
+
+impl std::fmt::Display for PeerStats {
+    /// The name, the counters in declaration order and the time of last
+    /// contact, or `-` if never reached, separated by spaces.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let t = &self.traffic;
+        let last_contact = self.last_contact.map_or_else(
+            || "-".to_string(),
+            |at| at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        );
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {last_contact}",
+            self.sitename, t.offered, t.accepted, t.refused, t.rejected, t.deferred, t.bytes
+        )
+    }
+}
+
+/// Log a round of `seconds` with `peer` as an `innfeed` `final` line, the
+/// form `innreport` reads feed statistics from.
+pub(crate) fn log_traffic(peer: &str, seconds: u64, traffic: &PeerTraffic) {
+    tracing::info!(
+        target: "innfeed",
+        "{peer} final seconds {seconds} offered {} accepted {} refused {} rejected {} \
+         missing 0 accsize {} rejsize 0 deferred {}",
+        traffic.offered,
+        traffic.accepted,
+        traffic.refused,
+        traffic.rejected,
+        traffic.bytes,
+        traffic.deferred,
+    );
+}
+
+/// Size of an article as sent to a peer.
+pub(crate) fn article_size(article: &Message) -> u64 {
+    let headers: usize = article
+        .headers
+        .iter()
+        .map(|(k, v)| k.len() + v.len() + 4)
+        .sum();
+    (headers + 2 + article.body.len()) as u64
 }
 
 #[derive(Clone, Debug)]
 pub struct PeerConfig {
     pub sitename: String,
-    pub patterns: Vec<String>,
+    pub filter: PeerFilter,
     pub sync_schedule: Option<String>,
+    pub mode: PeerMode,
+    pub transport: PeerTransport,
 }
 
 impl From<&crate::config::PeerRule> for PeerConfig {
     fn from(r: &crate::config::PeerRule) -> Self {
         Self {
             sitename: r.sitename.clone(),
-            patterns: r.patterns.clone(),
+            filter: r.filter.clone(),
             sync_schedule: r.sync_schedule.clone(),
+            mode: r.mode,
+            transport: r.transport.clone(),
         }
     }
 }
@@ -416,7 +1078,8 @@ pub async fn add_peer_job(
     default_schedule: String,
     db: PeerDb,
     storage: DynStorage,
-    site_name: String,
+    queue: ArticleQueue,
+    config: Arc<RwLock<Config>>,
 ) -> PeerResult<uuid::Uuid> {
     let schedule = peer.sync_schedule.as_deref().unwrap_or(&default_schedule);
 
@@ -429,50 +1092,16 @@ pub async fn add_peer_job(
     let peer_clone = peer.clone();
     let db_clone = db.clone();
     let storage_clone = storage.clone();
-    let site_name_clone = site_name.clone();
 
     let job = Job::new_async(schedule, move |_uuid, _l| {
         let peer = peer_clone.clone();
         let db = db_clone.clone();
         let storage = storage_clone.clone();
-        let site_name = site_name_clone.clone();
+        let queue = queue.clone();
+        let config = config.clone();
 
         Box::pin(async move {
-            let span = info_span!(
-                "peer.sync",
-                peer_name = peer.sitename.as_str(),
-                groups_processed = tracing::field::Empty,
-                articles_synced = tracing::field::Empty,
-                articles_skipped = tracing::field::Empty,
-                errors = tracing::field::Empty,
-                duration_ms = tracing::field::Empty,
-            );
-
-            async {
-                let sync_start = std::time::Instant::now();
-
-                match sync_peer_once(&peer, &db, &storage, &site_name).await {
-                    Ok(stats) => {
-                        let duration_ms = sync_start.elapsed().as_millis() as u64;
-                        tracing::Span::current().record("groups_processed", stats.groups_processed);
-                        tracing::Span::current().record("articles_synced", stats.articles_sent);
-                        tracing::Span::current().record("articles_skipped", stats.articles_skipped);
-                        tracing::Span::current().record("errors", stats.errors);
-                        tracing::Span::current().record("duration_ms", duration_ms);
-                        tracing::debug!(duration_ms = duration_ms, "Peer sync completed");
-                    }
-                    Err(e) => {
-                        tracing::error!(error = %e, "Peer sync failed");
-                    }
-                }
-
-                // Update last sync time regardless of success/failure
-                if let Err(e) = db.update_last_sync(&peer.sitename, Utc::now()).await {
-                    tracing::error!(error = %e, "Failed to update last sync time");
-                }
-            }
-            .instrument(span)
-            .await;
+            sync_peer(&peer, &db, &storage, &queue, &config).await;
         })
     })?;
 
@@ -487,11 +1116,125 @@ pub async fn add_peer_job(
     Ok(job_uuid)
 }
 
-async fn send_article_to_peer(host: &str, article: &Message) -> PeerResult<()> {
+/// Outcome of one synchronization round with a peer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PeerSyncReport {
+    /// Articles offered to the peer
+    pub sent: u64,
+    /// Articles not offered because the peer already has them
+    pub skipped: u64,
+    /// Articles fetched from the peer
+    pub pulled: u64,
+    /// Articles that could not be offered
+    pub errors: u64,
+}
+
+/// Run one synchronization round with a peer, pulling new articles from it
+/// and pushing local articles to it according to its mode.
+///
+/// Pulling runs first so that articles the peer lists are recorded in its
+/// history and are not pushed back to it. Pulled articles are submitted to
+/// `queue` and validated by its workers like those offered with IHAVE.
+pub async fn sync_peer(
+    peer: &PeerConfig,
+    db: &PeerDb,
+    storage: &DynStorage,
+    queue: &ArticleQueue,
+    config: &RwLock<Config>,
+) -> PeerSyncReport {
+    let span = info_span!(
+        "peer.sync",
+        peer_name = peer.sitename.as_str(),
+        groups_processed = tracing::field::Empty,
+        articles_synced = tracing::field::Empty,
+        articles_skipped = tracing::field::Empty,
+        articles_pulled = tracing::field::Empty,
+        errors = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+    );
+
+    async {
+        let sync_start = std::time::Instant::now();
+        let mut report = PeerSyncReport::default();
+        let mut traffic = PeerTraffic::default();
+        let mut contacted = false;
+        // Watermarks trail the start of the run by a second so that
+        // articles stored while it runs are considered again next time; the
+        // peer history suppresses the duplicates
+        let watermark = Utc::now() - chrono::Duration::seconds(1);
+
+        if peer.mode.pulls() {
+            match pull_peer_once(peer, db, storage, queue, config).await {
+                Ok(stats) => {
+                    report.pulled = stats.fetched;
+                    contacted = stats.contacted;
+                    tracing::Span::current().record("articles_pulled", stats.fetched);
+                    tracing::debug!(
+                        articles_pulled = stats.fetched,
+                        articles_skipped = stats.skipped,
+                        "Peer pull completed"
+                    );
+                    // Only advance the pull watermark when every new article
+                    // was seen, so failures are retried
+                    if let Err(e) = db.update_last_pull(&peer.sitename, watermark).await {
+                        tracing::error!(error = %e, "Failed to update last pull time");
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "Peer pull failed");
+                }
+            }
+        }
+
+        if peer.mode.pushes() {
+            let site_name = config.read().await.site_name.clone();
+            match sync_peer_once(peer, db, storage, &site_name).await {
+                Ok(stats) => {
+                    report.sent = stats.articles_sent;
+                    report.skipped = stats.articles_skipped;
+                    report.errors = stats.errors;
+                    traffic = stats.traffic;
+                    let duration_ms = sync_start.elapsed().as_millis() as u64;
+                    tracing::Span::current().record("groups_processed", stats.groups_processed);
+                    tracing::Span::current().record("articles_synced", stats.articles_sent);
+                    tracing::Span::current().record("articles_skipped", stats.articles_skipped);
+                    tracing::Span::current().record("errors", stats.errors);
+                    tracing::Span::current().record("duration_ms", duration_ms);
+                    tracing::debug!(duration_ms = duration_ms, "Peer sync completed");
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "Peer sync failed");
+                }
+            }
+
+            // Update last sync time regardless of success/failure
+            if let Err(e) = db.update_last_sync(&peer.sitename, watermark).await {
+                tracing::error!(error = %e, "Failed to update last sync time");
+            }
+            if let Err(e) = db.prune_history(&peer.sitename, watermark).await {
+                tracing::error!(error = %e, "Failed to prune peer history");
+            }
+        }
+
+        if contacted || traffic.offered > 0 {
+            log_traffic(&peer.sitename, sync_start.elapsed().as_secs(), &traffic);
+            if let Err(e) = db.record_traffic(&peer.sitename, &traffic).await {
+                tracing::error!(error = %e, "Failed to record peer statistics");
+            }
+        }
+
+        report
+    }
+    .instrument(span)
+    .await
+}
+
+async fn send_article_to_peer(peer: &PeerConfig, article: &Message) -> PeerResult<Transfer> {
+    let host = peer.sitename.as_str();
     let msg_id = extract_message_id(article)
         .ok_or_else(|| anyhow::anyhow!("Article missing Message-ID header"))?;
 
-    let connection_info = parse_peer_address(host, 563);
+    let connection_info = peer_connection_info(host, &peer.transport);
     let mut connection = PeerConnection::connect(&connection_info)
         .await
         .map_err(|e| anyhow::anyhow!("Failed to connect to peer {host}: {e}"))?;
@@ -512,6 +1255,7 @@ struct SyncStats {
     articles_sent: u64,
     articles_skipped: u64,
     errors: u64,
+    traffic: PeerTraffic,
 }
 
 impl SyncStats {
@@ -519,6 +1263,7 @@ impl SyncStats {
         self.articles_sent += other.sent;
         self.articles_skipped += other.skipped;
         self.errors += other.errors;
+        self.traffic.merge(&other.traffic);
     }
 }
 
@@ -528,6 +1273,7 @@ struct GroupSyncStats {
     sent: u64,
     skipped: u64,
     errors: u64,
+    traffic: PeerTraffic,
 }
 
 async fn sync_peer_once(
@@ -538,12 +1284,17 @@ async fn sync_peer_once(
 ) -> PeerResult<SyncStats> {
     let last_sync = db.get_last_sync(&peer.sitename).await?;
     let mut stats = SyncStats::default();
+    // Archived groups are kept for reading only and not propagated
+    let archived = storage
+        .list_archived_groups()
+        .try_collect::<Vec<String>>()
+        .await?;
 
     let mut groups = storage.list_groups();
     while let Some(result) = groups.next().await {
         let group = result?;
 
-        if !peer.patterns.iter().any(|pattern| wildmat(pattern, &group)) {
+        if !peer.filter.wants_group(&group) || archived.contains(&group) {
             continue;
         }
 
@@ -554,7 +1305,7 @@ async fn sync_peer_once(
         let article_ids = article_ids_stream.try_collect::<Vec<String>>().await?;
 
         let group_stats =
-            process_group_articles(peer, storage, site_name, &group, article_ids).await?;
+            process_group_articles(peer, db, storage, site_name, &group, article_ids).await?;
         stats.merge(group_stats);
         stats.groups_processed += 1;
     }
@@ -565,6 +1316,7 @@ async fn sync_peer_once(
 /// Process and send articles from a specific group to a peer.
 async fn process_group_articles(
     peer: &PeerConfig,
+    db: &PeerDb,
     storage: &DynStorage,
     site_name: &str,
     group: &str,
@@ -592,9 +1344,14 @@ async fn process_group_articles(
         match result {
             Ok((article_id, original_article)) => {
                 found_ids.insert(article_id.clone());
-                match process_fetched_article(peer, site_name, &article_id, &original_article).await
+                match process_fetched_article(peer, db, site_name, &article_id, &original_article)
+                    .await
                 {
-                    Ok(ArticleProcessResult::Sent) => stats.sent += 1,
+                    Ok(ArticleProcessResult::Sent { answer, size }) => {
+                        stats.sent += 1;
+                        stats.traffic.offered += 1;
+                        stats.traffic.count(answer, size);
+                    }
                     Ok(ArticleProcessResult::Skipped) => stats.skipped += 1,
                     Err(e) => {
                         stats.errors += 1;
@@ -643,13 +1400,19 @@ async fn process_group_articles(
 /// Result of processing a single article.
 #[derive(Debug)]
 enum ArticleProcessResult {
-    Sent,
+    /// Offered to the peer, which gave `answer` for an article of `size`
+    /// bytes
+    Sent {
+        answer: Transfer,
+        size: u64,
+    },
     Skipped,
 }
 
 /// Process an already-fetched article for peer distribution (used by batch processing).
 async fn process_fetched_article(
     peer: &PeerConfig,
+    db: &PeerDb,
     site_name: &str,
     article_id: &str,
     original_article: &Message,
@@ -662,20 +1425,205 @@ async fn process_fetched_article(
         );
         return Ok(ArticleProcessResult::Skipped);
     }
+    if db.peer_has(&peer.sitename, article_id).await? {
+        tracing::debug!(
+            article_id = article_id,
+            peer_name = peer.sitename.as_str(),
+            "Skipping article (peer already has it)"
+        );
+        return Ok(ArticleProcessResult::Skipped);
+    }
+    if !peer.filter.accepts(original_article) {
+        tracing::debug!(
+            article_id = article_id,
+            peer_name = peer.sitename.as_str(),
+            "Skipping article (excluded by peer filter)"
+        );
+        return Ok(ArticleProcessResult::Skipped);
+    }
 
     let peer_article = create_peer_article(original_article, site_name)?;
-    send_article_to_peer(&peer.sitename, &peer_article).await?;
+    let answer = send_article_to_peer(peer, &peer_article).await?;
+    db.record_peer_has(&peer.sitename, article_id).await?;
     tracing::debug!(
         article_id = article_id,
         peer_name = peer.sitename.as_str(),
+        answer = ?answer,
         "Article sent"
     );
 
-    Ok(ArticleProcessResult::Sent)
+    Ok(ArticleProcessResult::Sent {
+        answer,
+        size: article_size(&peer_article),
+    })
+}
+
+/// Statistics from pulling articles from a peer.
+#[derive(Debug, Default)]
+struct PullStats {
+    fetched: u64,
+    skipped: u64,
+    /// Whether the peer was connected to
+    contacted: bool,
+}
+
+/// Fetch the articles a peer received since the last pull.
+async fn pull_peer_once(
+    peer: &PeerConfig,
+    db: &PeerDb,
+    storage: &DynStorage,
+    queue: &ArticleQueue,
+    config: &RwLock<Config>,
+) -> PeerResult<PullStats> {
+    if peer.filter.included_patterns().next().is_none() {
+        return Ok(PullStats::default());
+    }
+    let since = db
+        .get_last_pull(&peer.sitename)
+        .await?
+        .unwrap_or(DateTime::UNIX_EPOCH);
+
+    let connection_info = peer_connection_info(&peer.sitename, &peer.transport);
+    let mut connection = PeerConnection::connect(&connection_info)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to connect to peer {}: {e}", peer.sitename))?;
+
+    let result = pull_new_articles(&mut connection, peer, db, storage, queue, config, since)
+        .await
+        .map(|stats| PullStats {
+            contacted: true,
+            ..stats
+        });
+
+    if let Err(close_err) = connection.close().await {
+        tracing::warn!(peer = peer.sitename.as_str(), error = %close_err, "Failed to close connection");
+    }
+
+    result
+}
+
+async fn pull_new_articles(
+    connection: &mut PeerConnection,
+    peer: &PeerConfig,
+    db: &PeerDb,
+    storage: &DynStorage,
+    queue: &ArticleQueue,
+    config: &RwLock<Config>,
+    since: DateTime<Utc>,
+) -> PeerResult<PullStats> {
+    let mut seen = std::collections::HashSet::new();
+    let mut article_ids = Vec::new();
+    for pattern in peer.filter.included_patterns() {
+        for id in connection.new_news(pattern, since).await? {
+            if seen.insert(id.clone()) {
+                article_ids.push(id);
+            }
+        }
+    }
+
+    let mut stats = PullStats::default();
+    for article_id in article_ids {
+        // Whatever happens below, the peer has the article and it must not
+        // be offered back
+        if peer.mode.pushes() {
+            db.record_peer_has(&peer.sitename, &article_id).await?;
+        }
+
+        // Articles we removed or refused are not fetched again
+        if queue.is_pending(&article_id)
+            || storage.get_message_size(&article_id).await?.is_some()
+            || storage.in_history(&article_id).await?
+        {
+            stats.skipped += 1;
+            continue;
+        }
+        let Some(mut article) = connection.fetch_article(&article_id).await? else {
+            stats.skipped += 1;
+            continue;
+        };
+        if !carries_any_group(storage, &article).await? {
+            tracing::debug!(
+                article_id = article_id.as_str(),
+                peer_name = peer.sitename.as_str(),
+                "Skipping pulled article (no local groups)"
+            );
+            stats.skipped += 1;
+            continue;
+        }
+        if names_archived_group(storage, &article).await? {
+            tracing::debug!(
+                article_id = article_id.as_str(),
+                peer_name = peer.sitename.as_str(),
+                "Skipping pulled article (archived group)"
+            );
+            stats.skipped += 1;
+            continue;
+        }
+
+        {
+            let cfg = config.read().await;
+            // Refuse articles that have passed through us before, as IHAVE does
+            if crate::rewrite::path_names_any(&article, &cfg.path_identities()) {
+                tracing::debug!(
+                    article_id = article_id.as_str(),
+                    peer_name = peer.sitename.as_str(),
+                    "Skipping pulled article (path loop)"
+                );
+                crate::history::remember_rejection(&**storage, &article_id).await;
+                stats.skipped += 1;
+                continue;
+            }
+            crate::parse::ensure_date(&mut article);
+            crate::parse::escape_message_id_header(&mut article);
+            crate::rewrite::add_path(
+                &mut article,
+                &cfg.site_name,
+                crate::rewrite::POSTED_PATH_TAIL,
+            );
+        }
+
+        // The queue workers run the filters, the moderation check and
+        // control and NoCeM processing before storing the article
+        let queued = QueuedArticle {
+            size: article_size(&article),
+            is_control: crate::control::is_control_message(&article),
+            message: article,
+            already_validated: false,
+        };
+        queue.submit(queued).await?;
+        stats.fetched += 1;
+        tracing::debug!(
+            article_id = article_id.as_str(),
+            peer_name = peer.sitename.as_str(),
+            "Article pulled"
+        );
+    }
+
+    Ok(stats)
+}
+
+/// Whether any of the article's newsgroups exists locally.
+async fn carries_any_group(storage: &DynStorage, article: &Message) -> PeerResult<bool> {
+    for group in extract_newsgroups(article) {
+        if storage.group_exists(&group).await? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Whether any of the article's newsgroups is archived locally.
+async fn names_archived_group(storage: &DynStorage, article: &Message) -> PeerResult<bool> {
+    for group in extract_newsgroups(article) {
+        if storage.is_group_archived(&group).await? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
 
 /// Creates a copy of an article with appropriate Path header for peer distribution.
-fn create_peer_article(orig: &Message, site_name: &str) -> PeerResult<Message> {
+pub(crate) fn create_peer_article(orig: &Message, site_name: &str) -> PeerResult<Message> {
     let mut article = orig.clone();
 
     // Update or add Path header
@@ -684,7 +1632,9 @@ fn create_peer_article(orig: &Message, site_name: &str) -> PeerResult<Message> {
         .iter_mut()
         .find(|(k, _)| k.eq_ignore_ascii_case("Path"))
     {
-        *path_value = format!("{site_name}!{path_value}");
+        if path_value.split('!').next() != Some(site_name) {
+            *path_value = format!("{site_name}!{path_value}");
+        }
     } else {
         article.headers.push(("Path".into(), site_name.to_string()));
     }
@@ -693,7 +1643,7 @@ fn create_peer_article(orig: &Message, site_name: &str) -> PeerResult<Message> {
 }
 
 /// Checks if an article should be skipped for a specific peer.
-fn should_skip_article(article: &Message, peer_sitename: &str) -> bool {
+pub(crate) fn should_skip_article(article: &Message, peer_sitename: &str) -> bool {
     article
         .headers
         .iter()
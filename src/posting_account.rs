@@ -0,0 +1,131 @@
+//! Posting-account tokens for anonymous posting.
+//!
+//! When anonymous posting is allowed, each connection is given a token
+//! derived from the client address. The token is added to the
+//! `Injection-Info` header of anonymous posts (RFC 5536 section 3.2.8) so
+//! abuse from one source can be correlated and banned, while the address
+//! itself is never stored or logged.
+
+use crate::Message;
+use crate::config::PostingAccountConfig;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Derives an opaque posting-account token from a client address.
+///
+/// Implementations must be one-way: the address must not be recoverable
+/// from the token.
+pub trait IdObfuscator: Send + Sync {
+    /// Token for `ip` at unix time `now`.
+    fn posting_account(&self, ip: IpAddr, now: u64) -> String;
+}
+
+/// Salted SHA-256 of the client address, rotated every `rotation` seconds.
+///
+/// Tokens are stable within a rotation period and unrelated across periods,
+/// so bans keyed by token expire when the period ends.
+pub struct RotatingHash {
+    salt: Vec<u8>,
+    rotation: Option<u64>,
+}
+
+impl RotatingHash {
+    pub fn new(salt: impl Into<Vec<u8>>, rotation: Option<u64>) -> Self {
+        Self {
+            salt: salt.into(),
+            rotation: rotation.filter(|&secs| secs > 0),
+        }
+    }
+
+    /// Build from configuration, falling back to a per-process random salt.
+    pub fn from_config(cfg: &PostingAccountConfig) -> Self {
+        let salt = match &cfg.salt {
+            Some(salt) => salt.as_bytes().to_vec(),
+            None => process_salt().to_vec(),
+        };
+        Self::new(salt, cfg.rotation)
+    }
+}
+
+impl IdObfuscator for RotatingHash {
+    fn posting_account(&self, ip: IpAddr, now: u64) -> String {
+        let period = self.rotation.map_or(0, |secs| now / secs);
+        let mut hasher = Sha256::new();
+        hasher.update(&self.salt);
+        hasher.update(period.to_be_bytes());
+        match ip.to_canonical() {
+            IpAddr::V4(v4) => hasher.update(v4.octets()),
+            IpAddr::V6(v6) => hasher.update(v6.octets()),
+        }
+        hasher.finalize()[..16]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+/// Token of a client at `ip` under `cfg`, for the current rotation period.
+pub fn current_token(cfg: &PostingAccountConfig, ip: IpAddr) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    RotatingHash::from_config(cfg).posting_account(ip, now)
+}
+
+/// Random salt used when none is configured.
+fn process_salt() -> &'static [u8; 32] {
+    static SALT: OnceLock<[u8; 32]> = OnceLock::new();
+    SALT.get_or_init(rand::random)
+}
+
+/// Check whether `token` is on the configured ban list.
+pub fn is_banned(cfg: &PostingAccountConfig, token: &str) -> bool {
+    cfg.banned.iter().any(|banned| banned == token)
+}
+
+/// Replace any `Injection-Info` header with one naming `site_name` and the
+/// posting-account `token`.
+pub fn add_injection_info(message: &mut Message, site_name: &str, token: &str) {
+    message
+        .headers
+        .retain(|(k, _)| !k.eq_ignore_ascii_case("Injection-Info"));
+    message.headers.push((
+        "Injection-Info".to_string(),
+        format!("{site_name}; posting-account=\"{token}\""),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_stable_within_a_period() {
+        let hash = RotatingHash::new("salt", Some(3600));
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let token = hash.posting_account(ip, 7200);
+        assert_eq!(token.len(), 32);
+        assert_eq!(token, hash.posting_account(ip, 7200 + 3599));
+        assert_ne!(token, hash.posting_account(ip, 7200 + 3600));
+        assert_ne!(
+            token,
+            hash.posting_account("192.0.2.2".parse().unwrap(), 7200)
+        );
+        assert_ne!(
+            token,
+            RotatingHash::new("other", Some(3600)).posting_account(ip, 7200)
+        );
+    }
+
+    #[test]
+    fn mapped_ipv4_matches_plain_ipv4() {
+        let hash = RotatingHash::new("salt", None);
+        assert_eq!(
+            hash.posting_account("192.0.2.1".parse().unwrap(), 0),
+            hash.posting_account("::ffff:192.0.2.1".parse().unwrap(), u64::MAX)
+        );
+    }
+}
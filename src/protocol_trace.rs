@@ -0,0 +1,590 @@
+//! Protocol traces of selected client connections.
+//!
+//! Interoperability problems with unusual clients are hard to follow in the
+//! server log, which records commands but not what was said. When
+//! `[trace] file` is set, the sessions of the client addresses and users
+//! named under `[trace]`, or added through the control socket, are copied
+//! line by line to that file, each line marked `C` for the client or `S`
+//! for the server:
+//!
+//! ```text
+//! 2026-10-17T09:14:03.512Z 6c1d... S 200 Service available, posting allowed
+//! 2026-10-17T09:14:03.520Z 6c1d... C AUTHINFO PASS [redacted]
+//! ```
+//!
+//! Passwords and SASL responses are never written. A user's session is
+//! traced from the command after it authenticates, an address's from the
+//! greeting. The file is rotated once it grows past `max_file_bytes`,
+//! keeping `keep_files` older files as `<file>.1`, `<file>.2` and so on.
+//! Lines are written by a background task; should it fall behind, lines are
+//! dropped rather than slowing the traced sessions down.
+
+use crate::config::TraceConfig;
+use anyhow::{Context as _, Result};
+use chrono::{SecondsFormat, Utc};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::task::{Context, Poll, ready};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::mpsc;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Trace lines waiting to be written before further lines are dropped
+const BACKLOG: usize = 4096;
+
+/// Longest line traced; the rest of a longer line is left out
+const MAX_LINE: usize = 8192;
+
+/// Shown in place of credentials
+const REDACTED: &str = "[redacted]";
+
+/// A client address or user whose sessions are traced
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TraceTarget {
+    Address(IpAddr),
+    User(String),
+}
+
+impl FromStr for TraceTarget {
+    type Err = std::convert::Infallible;
+
+    /// Anything that is not an IP address names a user
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse()
+            .map_or_else(|_| Self::User(s.to_string()), Self::Address))
+    }
+}
+
+impl fmt::Display for TraceTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Address(ip) => write!(f, "address {ip}"),
+            Self::User(user) => write!(f, "user {user}"),
+        }
+    }
+}
+
+/// Writes the protocol traces of the targeted connections.
+pub struct ProtocolTracer {
+    /// Targets from the configuration, replaced on reload
+    configured: RwLock<BTreeSet<TraceTarget>>,
+    /// Targets added through the control socket, kept across reloads
+    added: RwLock<BTreeSet<TraceTarget>>,
+    lines: mpsc::Sender<String>,
+    dropped: AtomicU64,
+}
+
+impl ProtocolTracer {
+    /// Open the trace file of `cfg` and start writing to it, or return
+    /// `None` if no file is configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trace file cannot be opened.
+    pub async fn start(cfg: &TraceConfig) -> Result<Option<Arc<Self>>> {
+        let Some(path) = &cfg.file else {
+            return Ok(None);
+        };
+        let file = RotatingFile::open(path.into(), cfg.max_file_bytes, cfg.keep_files)
+            .await
+            .with_context(|| format!("failed to open trace file {path}"))?;
+        let (lines, receiver) = mpsc::channel(BACKLOG);
+        tokio::spawn(write_lines(file, receiver));
+        let tracer = Self {
+            configured: RwLock::default(),
+            added: RwLock::default(),
+            lines,
+            dropped: AtomicU64::new(0),
+        };
+        tracer.configure(cfg);
+        Ok(Some(Arc::new(tracer)))
+    }
+
+    /// Trace the addresses and users of `cfg` instead of those previously
+    /// configured. Targets added through the control socket stay.
+    pub fn configure(&self, cfg: &TraceConfig) {
+        let targets = cfg
+            .addresses
+            .iter()
+            .copied()
+            .map(TraceTarget::Address)
+            .chain(cfg.users.iter().cloned().map(TraceTarget::User))
+            .collect();
+        *self
+            .configured
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = targets;
+    }
+
+    /// Start tracing `target`
+    pub fn add(&self, target: TraceTarget) {
+        self.added
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(target);
+    }
+
+    /// Stop tracing `target`, returning false if it was not traced. A
+    /// configured target is traced again after the next reload.
+    pub fn remove(&self, target: &TraceTarget) -> bool {
+        let added = self
+            .added
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(target);
+        let configured = self
+            .configured
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(target);
+        added || configured
+    }
+
+    /// The targets traced, in order
+    #[must_use]
+    pub fn targets(&self) -> Vec<TraceTarget> {
+        let mut targets = self
+            .configured
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        targets.extend(
+            self.added
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .cloned(),
+        );
+        targets.into_iter().collect()
+    }
+
+    /// Lines left out of the trace file because the writer fell behind
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Whether a connection from `ip`, authenticated as `user`, is traced
+    fn traces(&self, ip: Option<IpAddr>, user: Option<&str>) -> bool {
+        let matches = |targets: &RwLock<BTreeSet<TraceTarget>>| {
+            targets
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .any(|target| match target {
+                    TraceTarget::Address(addr) => ip == Some(*addr),
+                    TraceTarget::User(name) => user == Some(name.as_str()),
+                })
+        };
+        matches(&self.configured) || matches(&self.added)
+    }
+
+    /// Follow the session `session_id` from `peer_ip`
+    #[must_use]
+    pub fn connection(
+        self: &Arc<Self>,
+        session_id: Uuid,
+        peer_ip: Option<IpAddr>,
+    ) -> Arc<ConnectionTrace> {
+        Arc::new(ConnectionTrace {
+            tracer: self.clone(),
+            session_id,
+            peer_ip,
+            state: Mutex::default(),
+        })
+    }
+
+    fn write(&self, line: String) {
+        if self.lines.try_send(line).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Tracing state of one client connection
+pub struct ConnectionTrace {
+    tracer: Arc<ProtocolTracer>,
+    session_id: Uuid,
+    peer_ip: Option<IpAddr>,
+    state: Mutex<TraceState>,
+}
+
+#[derive(Default)]
+struct TraceState {
+    user: Option<String>,
+    active: bool,
+    /// Partial lines from the client and from the server
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+/// Which side of the connection sent a line
+#[derive(Clone, Copy)]
+enum Direction {
+    Client,
+    Server,
+}
+
+impl ConnectionTrace {
+    /// Record the user the session is authenticated as
+    pub fn set_user(&self, user: Option<&str>) {
+        let mut state = self.lock();
+        if state.user.as_deref() != user {
+            state.user = user.map(str::to_string);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TraceState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Trace `bytes` passing in `direction`, if the connection is traced
+    fn record(&self, direction: Direction, bytes: &[u8]) {
+        let mut state = self.lock();
+        let active = self.tracer.traces(self.peer_ip, state.user.as_deref());
+        if active != state.active {
+            state.active = active;
+            state.input.clear();
+            state.output.clear();
+            let what = if active { "started" } else { "stopped" };
+            let peer = self
+                .peer_ip
+                .map_or_else(|| "unknown address".to_string(), |ip| ip.to_string());
+            let user = state.user.as_deref().unwrap_or("-");
+            self.write_line(&format!("* trace {what} for {peer}, user {user}"));
+        }
+        if !active {
+            return;
+        }
+
+        let (marker, pending) = match direction {
+            Direction::Client => ("C", &mut state.input),
+            Direction::Server => ("S", &mut state.output),
+        };
+        pending.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            lines.push(line);
+        }
+        if pending.len() > MAX_LINE {
+            lines.push(std::mem::take(pending));
+        }
+        drop(state);
+
+        for line in lines {
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\r', '\n']);
+            let text = match direction {
+                Direction::Client => redact(text),
+                Direction::Server => text.into(),
+            };
+            let text = truncate(&text);
+            self.write_line(&format!("{marker} {text}"));
+        }
+    }
+
+    fn write_line(&self, line: &str) {
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        self.tracer
+            .write(format!("{now} {} {line}\n", self.session_id));
+    }
+}
+
+/// Hide the credentials a client line carries
+fn redact(line: &str) -> std::borrow::Cow<'_, str> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [authinfo, pass, _, ..]
+            if authinfo.eq_ignore_ascii_case("AUTHINFO") && pass.eq_ignore_ascii_case("PASS") =>
+        {
+            format!("{authinfo} {pass} {REDACTED}").into()
+        }
+        [authinfo, sasl, mechanism, _, ..]
+            if authinfo.eq_ignore_ascii_case("AUTHINFO") && sasl.eq_ignore_ascii_case("SASL") =>
+        {
+            format!("{authinfo} {sasl} {mechanism} {REDACTED}").into()
+        }
+        _ => line.into(),
+    }
+}
+
+/// Cut `line` to at most [`MAX_LINE`] bytes on a character boundary
+fn truncate(line: &str) -> &str {
+    if line.len() <= MAX_LINE {
+        return line;
+    }
+    let mut end = MAX_LINE;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// A stream copying the lines passing through it to a [`ConnectionTrace`].
+#[derive(Clone)]
+pub struct Traced<S> {
+    inner: S,
+    trace: Option<Arc<ConnectionTrace>>,
+}
+
+impl<S> Traced<S> {
+    /// Trace `inner` to `trace`, or pass it through untouched without one
+    pub fn new(inner: S, trace: Option<Arc<ConnectionTrace>>) -> Self {
+        Self { inner, trace }
+    }
+
+    /// The wrapped stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Traced<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        if let Some(trace) = &self.trace {
+            trace.record(Direction::Client, &buf.filled()[before..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Traced<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        if let Some(trace) = &self.trace {
+            trace.record(Direction::Server, &buf[..n]);
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Write trace lines to `file` as they arrive
+async fn write_lines(mut file: RotatingFile, mut lines: mpsc::Receiver<String>) {
+    let mut chunk = String::new();
+    while let Some(line) = lines.recv().await {
+        chunk.push_str(&line);
+        while let Ok(line) = lines.try_recv() {
+            chunk.push_str(&line);
+        }
+        if let Err(e) = file.write(chunk.as_bytes()).await {
+            warn!(error = %e, "Failed to write protocol trace");
+        }
+        chunk.clear();
+    }
+}
+
+/// A file moved aside once it reaches its size limit
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    async fn open(path: PathBuf, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let size = file.metadata().await?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_bytes,
+            keep,
+        })
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + bytes.len() as u64 > self.max_bytes {
+            self.rotate().await?;
+        }
+        self.file.write_all(bytes).await?;
+        self.file.flush().await?;
+        self.size += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Shift the older files up by one, dropping the oldest, and start an
+    /// empty file
+    async fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..self.keep).rev() {
+            rename_if_exists(&numbered(&self.path, n), &numbered(&self.path, n + 1)).await?;
+        }
+        if self.keep > 0 {
+            rename_if_exists(&self.path, &numbered(&self.path, 1)).await?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+            .await?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    name.into()
+}
+
+async fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match tokio::fs::rename(from, to).await {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn credentials_are_redacted() {
+        assert_eq!(redact("AUTHINFO PASS hunter2"), "AUTHINFO PASS [redacted]");
+        assert_eq!(
+            redact("authinfo pass two words"),
+            "authinfo pass [redacted]"
+        );
+        assert_eq!(
+            redact("AUTHINFO SASL PLAIN AGJvYgBodW50ZXIy"),
+            "AUTHINFO SASL PLAIN [redacted]"
+        );
+        assert_eq!(redact("AUTHINFO USER bob"), "AUTHINFO USER bob");
+        assert_eq!(redact("GROUP misc.test"), "GROUP misc.test");
+    }
+
+    #[test]
+    fn targets_are_addresses_or_users() {
+        assert_eq!(
+            "192.0.2.7".parse::<TraceTarget>().unwrap(),
+            TraceTarget::Address("192.0.2.7".parse().unwrap())
+        );
+        assert_eq!(
+            "bob".parse::<TraceTarget>().unwrap(),
+            TraceTarget::User("bob".into())
+        );
+    }
+
+    async fn tracer(dir: &Path, cfg: &str) -> Arc<ProtocolTracer> {
+        let mut cfg: TraceConfig = toml::from_str(cfg).unwrap();
+        cfg.file = Some(dir.join("trace.log").display().to_string());
+        ProtocolTracer::start(&cfg).await.unwrap().unwrap()
+    }
+
+    async fn trace_lines(dir: &Path) -> Vec<String> {
+        // Give the writer a moment to catch up
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        std::fs::read_to_string(dir.join("trace.log"))
+            .unwrap()
+            .lines()
+            .map(|line| line.splitn(3, ' ').nth(2).unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn sessions_of_traced_users_are_written_from_authentication() {
+        let dir = tempfile::tempdir().unwrap();
+        let tracer = tracer(dir.path(), "users = [\"bob\"]").await;
+        let trace = tracer.connection(Uuid::new_v4(), Some("192.0.2.7".parse().unwrap()));
+        let (mut client, server) = tokio::io::duplex(256);
+        let mut traced = Traced::new(server, Some(trace.clone()));
+
+        traced.write_all(b"200 ready\r\n").await.unwrap();
+        client.write_all(b"AUTHINFO PASS secret\r\n").await.unwrap();
+        let mut buf = [0u8; 22];
+        traced.read_exact(&mut buf).await.unwrap();
+        trace.set_user(Some("bob"));
+        client.write_all(b"GROUP misc").await.unwrap();
+        client.write_all(b".test\r\n").await.unwrap();
+        let mut buf = [0u8; 17];
+        traced.read_exact(&mut buf).await.unwrap();
+        traced.write_all(b"211 0 0 0 misc.test\r\n").await.unwrap();
+
+        assert_eq!(
+            trace_lines(dir.path()).await,
+            vec![
+                "* trace started for 192.0.2.7, user bob",
+                "C GROUP misc.test",
+                "S 211 0 0 0 misc.test",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn traces_can_be_added_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let tracer = tracer(dir.path(), "addresses = [\"192.0.2.7\"]").await;
+        let trace = tracer.connection(Uuid::new_v4(), Some("192.0.2.8".parse().unwrap()));
+        let mut traced = Traced::new(tokio::io::sink(), Some(trace));
+
+        traced.write_all(b"200 not traced\r\n").await.unwrap();
+        tracer.add("192.0.2.8".parse().unwrap());
+        assert_eq!(tracer.targets().len(), 2);
+        traced.write_all(b"200 traced\r\n").await.unwrap();
+        assert!(tracer.remove(&"192.0.2.8".parse().unwrap()));
+        assert!(!tracer.remove(&"192.0.2.8".parse().unwrap()));
+        traced.write_all(b"200 not traced\r\n").await.unwrap();
+
+        assert_eq!(
+            trace_lines(dir.path()).await,
+            vec![
+                "* trace started for 192.0.2.8, user -",
+                "S 200 traced",
+                "* trace stopped for 192.0.2.8, user -",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn full_files_are_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.log");
+        let mut file = RotatingFile::open(path.clone(), 10, 2).await.unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write(line.as_bytes()).await.unwrap();
+        }
+
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(path.clone()), "fourth\n");
+        assert_eq!(read(numbered(&path, 1)), "third\n");
+        assert_eq!(read(numbered(&path, 2)), "second\n");
+        assert!(!numbered(&path, 3).exists());
+    }
+}
@@ -3,16 +3,45 @@
 //! This module implements a queue-based article submission system using flume.
 //! Articles are validated minimally on submission, queued, and then processed
 //! by background workers that perform comprehensive validation and storage.
+//!
+//! When a journal path is configured, every submitted article is appended to
+//! a write-ahead journal before it is acknowledged to the client, and marked
+//! done once a worker has finished with it. Articles still pending in the
+//! journal are replayed when the worker pool starts.
+//!
+//! With `transit_validation = "deferred"`, IHAVE and TAKETHIS queue articles
+//! that have only passed the fast filters. The queue remembers their
+//! Message-IDs until a worker has finished with them, so that a second
+//! offer is refused meanwhile, and a worker whose filters reject one records
+//! it in the history.
+//!
+//! With `article_worker_max_count` set, the pool starts
+//! `article_worker_count` workers and adds one whenever articles have been
+//! left waiting for a whole scaling interval, up to the maximum, retiring
+//! them again once the queue has stayed empty for a while. The time each
+//! worker takes over a batch is kept as a histogram for the health
+//! endpoints, along with counts of the offers the streaming handlers
+//! declined as duplicates and the transfers they deferred because the
+//! storage failed.
 
 use crate::Message;
+use crate::audit::{AuditAction, AuditEntry};
 use crate::auth::DynAuth;
 use crate::config::Config;
+use crate::feed::Feeder;
+use crate::handlers::utils::get_header_value;
 use crate::storage::DynStorage;
 use anyhow::Result;
 use flume::{Receiver, Sender};
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{Instrument, debug, error, info, info_span};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{Instrument, debug, error, info, info_span, warn};
 
 /// An article queued for processing
 #[derive(Debug, Clone)]
@@ -32,24 +61,214 @@ pub struct QueuedArticle {
 pub struct ArticleQueue {
     sender: Sender<QueuedArticle>,
     receiver: Receiver<QueuedArticle>,
+    journal: Option<Arc<QueueJournal>>,
+    /// Message-IDs of queued articles not yet validated
+    pending: Arc<std::sync::Mutex<HashSet<String>>>,
+    /// Articles submitted that no worker has finished with yet
+    unfinished: Arc<AtomicUsize>,
+    /// How fast the workers have been taking articles off the queue
+    drain: Arc<std::sync::Mutex<DrainRate>>,
+    /// Running workers and how long they take over batches
+    workers: Arc<WorkerStats>,
+    /// Offers declined or deferred before reaching the queue
+    ingest: Arc<IngestCounters>,
+}
+
+/// Offers of articles that were declined as duplicates or deferred
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Offers declined because the article was queued awaiting validation
+    pub pending_duplicates: u64,
+    /// Offers declined because the article was stored or in the history
+    pub duplicates: u64,
+    /// Transfers the peer was asked to retry because the storage failed
+    pub storage_deferrals: u64,
+}
+
+#[derive(Debug, Default)]
+struct IngestCounters {
+    pending_duplicates: AtomicU64,
+    duplicates: AtomicU64,
+    storage_deferrals: AtomicU64,
+}
+
+/// Upper bounds, in seconds, of the buckets of [`WorkerHistogram`]
+pub const WORKER_BUCKETS: [f64; 8] = [0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 30.0];
+
+/// Time one worker took over each batch of articles
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerHistogram {
+    /// Batches that took at most the matching bound of [`WORKER_BUCKETS`],
+    /// and not the bound before it
+    pub buckets: [u64; WORKER_BUCKETS.len()],
+    /// Batches processed
+    pub count: u64,
+    /// Total processing time in microseconds
+    pub sum_micros: u64,
+}
+
+impl WorkerHistogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = WORKER_BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum_micros = self
+            .sum_micros
+            .saturating_add(u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX));
+    }
+}
+
+/// Workers taking articles off one queue
+#[derive(Debug, Default)]
+struct WorkerStats {
+    running: std::sync::atomic::AtomicUsize,
+    histograms: std::sync::Mutex<std::collections::BTreeMap<usize, WorkerHistogram>>,
+}
+
+/// Seconds over which the drain rate of the queue is measured
+const DRAIN_WINDOW_SECS: f64 = 10.0;
+
+/// Retry hint given while the workers have not finished any article yet
+const DEFAULT_RETRY_SECS: u64 = 30;
+
+/// Longest retry hint given to clients of a full queue
+const MAX_RETRY_SECS: u64 = 300;
+
+/// Articles finished by the workers, measured over a sliding window
+#[derive(Debug, Default)]
+struct DrainRate {
+    window_start: Option<Instant>,
+    drained: u64,
+    /// Articles per second over the last complete window
+    per_sec: Option<f64>,
+}
+
+impl DrainRate {
+    fn record(&mut self, count: usize) {
+        let now = Instant::now();
+        let start = *self.window_start.get_or_insert(now);
+        self.drained += count as u64;
+        let elapsed = now.duration_since(start).as_secs_f64();
+        if elapsed >= DRAIN_WINDOW_SECS {
+            self.per_sec = Some(self.drained as f64 / elapsed);
+            self.window_start = Some(now);
+            self.drained = 0;
+        }
+    }
+
+    /// Articles per second, from the current window until one is complete
+    fn rate(&self) -> Option<f64> {
+        let partial = || {
+            let elapsed = self.window_start?.elapsed().as_secs_f64();
+            (self.drained > 0 && elapsed > 0.0).then(|| self.drained as f64 / elapsed)
+        };
+        self.per_sec.or_else(partial)
+    }
 }
 
 impl ArticleQueue {
     /// Create a new article queue with the specified capacity
     pub fn new(capacity: usize) -> Self {
         let (sender, receiver) = flume::bounded(capacity);
-        Self { sender, receiver }
+        Self {
+            sender,
+            receiver,
+            journal: None,
+            pending: Arc::default(),
+            unfinished: Arc::default(),
+            drain: Arc::default(),
+            workers: Arc::default(),
+            ingest: Arc::default(),
+        }
+    }
+
+    /// Create a new article queue backed by a persistent journal at `path`
+    ///
+    /// Articles left pending in an existing journal are loaded and will be
+    /// replayed by [`WorkerPool::start`].
+    pub async fn with_journal(capacity: usize, path: impl AsRef<Path>) -> Result<Self> {
+        let mut queue = Self::new(capacity);
+        queue.journal = Some(Arc::new(QueueJournal::open(path.as_ref()).await?));
+        Ok(queue)
     }
 
     /// Submit an article to the queue for processing
     ///
     /// Returns Ok(()) if the article was queued successfully,
-    /// Err if the queue is full or closed.
+    /// Err if the queue is full or closed, or the journal could not be written.
     pub async fn submit(&self, article: QueuedArticle) -> Result<()> {
-        self.sender
-            .send_async(article)
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to queue article: {e}"))
+        if let Some(journal) = &self.journal {
+            journal.append(&article).await?;
+        }
+        self.unfinished.fetch_add(1, Ordering::SeqCst);
+        self.sender.send_async(article).await.map_err(|e| {
+            self.unfinished.fetch_sub(1, Ordering::SeqCst);
+            anyhow::anyhow!("Failed to queue article: {e}")
+        })
+    }
+
+    /// Submit an article to the queue without waiting for room
+    ///
+    /// Returns Err if the queue is full or closed, or the journal could not
+    /// be written, so a peer can be told to try again later instead of being
+    /// held up.
+    pub async fn try_submit(&self, article: QueuedArticle) -> Result<()> {
+        if self.is_full() {
+            return Err(anyhow::anyhow!("Failed to queue article: queue is full"));
+        }
+        if let Some(journal) = &self.journal {
+            journal.append(&article).await?;
+        }
+        let message = self.journal.is_some().then(|| article.message.clone());
+        let pending_id = self.mark_pending(&article);
+        self.unfinished.fetch_add(1, Ordering::SeqCst);
+        if let Err(e) = self.sender.try_send(article) {
+            self.unfinished.fetch_sub(1, Ordering::SeqCst);
+            if let Some(id) = pending_id {
+                self.pending_ids().remove(&id);
+            }
+            // Filled up since it was checked; don't replay it on restart
+            if let (Some(journal), Some(message)) = (&self.journal, message) {
+                journal.complete(&message).await?;
+            }
+            return Err(anyhow::anyhow!("Failed to queue article: {e}"));
+        }
+        Ok(())
+    }
+
+    /// Returns true if an article with `message_id` is queued awaiting
+    /// validation
+    pub fn is_pending(&self, message_id: &str) -> bool {
+        self.pending_ids().contains(message_id)
+    }
+
+    fn pending_ids(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Remember the Message-ID of `article` if it still has to be validated
+    fn mark_pending(&self, article: &QueuedArticle) -> Option<String> {
+        if article.already_validated {
+            return None;
+        }
+        let id = journal_key(&article.message).to_string();
+        self.pending_ids().insert(id.clone());
+        Some(id)
+    }
+
+    /// Forget the Message-IDs of a batch of articles a worker has finished
+    fn release(&self, batch: &[QueuedArticle]) {
+        self.drain
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(batch.len());
+        let mut pending = self.pending_ids();
+        for article in batch.iter().filter(|a| !a.already_validated) {
+            pending.remove(journal_key(&article.message));
+        }
+        self.unfinished.fetch_sub(batch.len(), Ordering::SeqCst);
     }
 
     /// Get the receiver for worker tasks
@@ -62,12 +281,290 @@ impl ArticleQueue {
         self.sender.is_empty()
     }
 
+    /// Returns true once every article submitted has been taken off the
+    /// queue and a worker has finished with it
+    pub fn is_idle(&self) -> bool {
+        self.unfinished.load(Ordering::SeqCst) == 0
+    }
+
     /// Returns the number of items in the queue
     pub fn len(&self) -> usize {
         self.sender.len()
     }
+
+    /// Returns the largest number of articles the queue holds
+    pub fn capacity(&self) -> usize {
+        self.sender.capacity().unwrap_or(usize::MAX)
+    }
+
+    /// Returns true if no more articles can be queued without waiting
+    pub fn is_full(&self) -> bool {
+        self.sender.is_full()
+    }
+
+    /// Estimate how long a client refused because the queue is full should
+    /// wait, from how fast the workers have recently been emptying it
+    pub fn retry_after(&self) -> Duration {
+        let rate = self
+            .drain
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .rate();
+        let secs = match rate {
+            Some(per_sec) => ((self.len() + 1) as f64 / per_sec).ceil() as u64,
+            None => DEFAULT_RETRY_SECS,
+        };
+        Duration::from_secs(secs.clamp(1, MAX_RETRY_SECS))
+    }
+
+    /// Returns the number of workers taking articles off the queue
+    pub fn worker_count(&self) -> usize {
+        self.workers
+            .running
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Processing-time histograms of every worker that has taken articles
+    /// off the queue, by worker number
+    pub fn worker_histograms(&self) -> Vec<(usize, WorkerHistogram)> {
+        self.workers
+            .histograms
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(id, histogram)| (*id, histogram.clone()))
+            .collect()
+    }
+
+    /// Count an offer declined as a duplicate, `pending` if the article was
+    /// still queued awaiting validation
+    pub fn record_duplicate(&self, pending: bool) {
+        let counter = if pending {
+            &self.ingest.pending_duplicates
+        } else {
+            &self.ingest.duplicates
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a transfer the peer was asked to retry because the storage
+    /// failed
+    pub fn record_storage_deferral(&self) {
+        self.ingest
+            .storage_deferrals
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Offers declined as duplicates or deferred so far
+    pub fn ingest_stats(&self) -> IngestStats {
+        IngestStats {
+            pending_duplicates: self.ingest.pending_duplicates.load(Ordering::Relaxed),
+            duplicates: self.ingest.duplicates.load(Ordering::Relaxed),
+            storage_deferrals: self.ingest.storage_deferrals.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_batch(&self, worker_id: usize, elapsed: Duration) {
+        self.workers
+            .histograms
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(worker_id)
+            .or_default()
+            .observe(elapsed);
+    }
+
+    /// Re-queue articles left pending in the journal by a previous run
+    async fn replay(&self) -> Result<usize> {
+        let Some(journal) = &self.journal else {
+            return Ok(0);
+        };
+        let pending = journal.take_pending().await;
+        let count = pending.len();
+        for article in pending {
+            self.mark_pending(&article);
+            self.unfinished.fetch_add(1, Ordering::SeqCst);
+            self.sender.send_async(article).await.map_err(|e| {
+                self.unfinished.fetch_sub(1, Ordering::SeqCst);
+                anyhow::anyhow!("Failed to replay article: {e}")
+            })?;
+        }
+        Ok(count)
+    }
+}
+
+/// A single line in the queue journal
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum JournalRecord {
+    Add {
+        headers: Vec<(String, String)>,
+        body: String,
+        size: u64,
+        is_control: bool,
+        already_validated: bool,
+    },
+    Done {
+        message_id: String,
+    },
+}
+
+impl JournalRecord {
+    fn add(article: &QueuedArticle) -> Self {
+        Self::Add {
+            headers: article.message.headers.to_vec(),
+            body: article.message.body.clone(),
+            size: article.size,
+            is_control: article.is_control,
+            already_validated: article.already_validated,
+        }
+    }
+
+    fn into_article(self) -> Option<QueuedArticle> {
+        match self {
+            Self::Add {
+                headers,
+                body,
+                size,
+                is_control,
+                already_validated,
+            } => Some(QueuedArticle {
+                message: Message {
+                    headers: headers.into_iter().collect(),
+                    body,
+                },
+                size,
+                is_control,
+                already_validated,
+            }),
+            Self::Done { .. } => None,
+        }
+    }
+}
+
+/// Append-only write-ahead journal for queued articles
+///
+/// Each line is a JSON [`JournalRecord`]. Pending articles are those with an
+/// `add` record not yet matched by a `done` record for the same Message-ID.
+pub struct QueueJournal {
+    file: Mutex<tokio::fs::File>,
+    pending: Mutex<Vec<QueuedArticle>>,
+}
+
+impl QueueJournal {
+    /// Open the journal at `path`, loading and compacting any pending entries
+    pub async fn open(path: &Path) -> Result<Self> {
+        let pending = match tokio::fs::read_to_string(path).await {
+            Ok(text) => Self::pending_from(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to read article queue journal '{}': {e}",
+                    path.display()
+                ));
+            }
+        };
+
+        // Rewrite the journal with only the pending entries so it does not
+        // grow without bound across restarts
+        let tmp = path.with_extension("tmp");
+        let mut compacted = String::new();
+        for article in &pending {
+            compacted.push_str(&serde_json::to_string(&JournalRecord::add(article))?);
+            compacted.push('\n');
+        }
+        tokio::fs::write(&tmp, compacted).await.map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to write article queue journal '{}': {e}",
+                tmp.display()
+            )
+        })?;
+        tokio::fs::rename(&tmp, path).await?;
+
+        let file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .await?;
+
+        if !pending.is_empty() {
+            info!(
+                pending = pending.len(),
+                "Loaded pending articles from queue journal"
+            );
+        }
+
+        Ok(Self {
+            file: Mutex::new(file),
+            pending: Mutex::new(pending),
+        })
+    }
+
+    fn pending_from(text: &str) -> Vec<QueuedArticle> {
+        let mut pending: Vec<QueuedArticle> = Vec::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str::<JournalRecord>(line) {
+                Ok(JournalRecord::Done { message_id }) => {
+                    if let Some(pos) = pending
+                        .iter()
+                        .position(|a| journal_key(&a.message) == message_id)
+                    {
+                        pending.remove(pos);
+                    }
+                }
+                Ok(record) => pending.extend(record.into_article()),
+                // A torn final line from a crash mid-write is expected; skip it
+                Err(e) => error!(error = %e, "Skipping unreadable queue journal entry"),
+            }
+        }
+        pending
+    }
+
+    async fn write_record(&self, record: &JournalRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    /// Record an article as queued
+    pub async fn append(&self, article: &QueuedArticle) -> Result<()> {
+        self.write_record(&JournalRecord::add(article)).await
+    }
+
+    /// Record an article as processed
+    pub async fn complete(&self, message: &Message) -> Result<()> {
+        self.write_record(&JournalRecord::Done {
+            message_id: journal_key(message).to_string(),
+        })
+        .await
+    }
+
+    /// Take the articles that were pending when the journal was opened
+    pub async fn take_pending(&self) -> Vec<QueuedArticle> {
+        std::mem::take(&mut *self.pending.lock().await)
+    }
+}
+
+fn journal_key(message: &Message) -> &str {
+    message
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Message-ID"))
+        .map(|(_, v)| v.as_str())
+        .unwrap_or("")
 }
 
+/// How often an autoscaling pool compares its workers with the queue
+const SCALE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Scaling intervals the queue must stay empty before a worker is retired
+const IDLE_INTERVALS: u32 = 30;
+
+/// Least time between two warnings that the pool is saturated
+const SATURATION_WARN_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Article worker pool configuration
 pub struct WorkerPool {
     queue: ArticleQueue,
@@ -75,6 +572,8 @@ pub struct WorkerPool {
     auth: DynAuth,
     config: Arc<RwLock<Config>>,
     worker_count: usize,
+    max_workers: usize,
+    feeder: Option<Arc<Feeder>>,
 }
 
 impl WorkerPool {
@@ -92,89 +591,475 @@ impl WorkerPool {
             auth,
             config,
             worker_count,
+            max_workers: worker_count,
+            feeder: None,
         }
     }
 
-    /// Start all worker tasks
-    pub async fn start(&self) -> Vec<tokio::task::JoinHandle<()>> {
-        let mut handles = Vec::with_capacity(self.worker_count);
-
-        for worker_id in 0..self.worker_count {
-            let receiver = self.queue.receiver();
-            let storage = self.storage.clone();
-            let auth = self.auth.clone();
-            let config = self.config.clone();
+    /// Offer every accepted article to the streaming feeds of `feeder`
+    #[must_use]
+    pub fn with_feeder(mut self, feeder: Arc<Feeder>) -> Self {
+        self.feeder = Some(feeder);
+        self
+    }
 
-            let handle = tokio::spawn(async move {
-                worker_task(worker_id, receiver, storage, auth, config).await;
-            });
+    /// Add workers while articles are left waiting, up to `max_workers`,
+    /// and retire them down to the initial count once the queue is idle
+    #[must_use]
+    pub fn with_max_workers(mut self, max_workers: usize) -> Self {
+        self.max_workers = max_workers.max(self.worker_count);
+        self
+    }
 
-            handles.push(handle);
-        }
+    /// Start all worker tasks
+    ///
+    /// An autoscaling pool returns the task that starts and stops its
+    /// workers rather than the workers themselves.
+    pub async fn start(&self) -> Vec<tokio::task::JoinHandle<()>> {
+        let handles = if self.max_workers > self.worker_count {
+            let scaler = Scaler {
+                pool: self.spawner(),
+                min: self.worker_count,
+                max: self.max_workers,
+                workers: Vec::new(),
+            };
+            vec![tokio::spawn(scaler.run())]
+        } else {
+            let pool = self.spawner();
+            (0..self.worker_count)
+                .map(|worker_id| pool.spawn(worker_id, None))
+                .collect()
+        };
 
         info!(
             worker_count = self.worker_count,
+            max_workers = self.max_workers,
             "Article processing workers started"
         );
+
+        match self.queue.replay().await {
+            Ok(0) => {}
+            Ok(count) => info!(count = count, "Replayed articles from queue journal"),
+            Err(e) => error!(error = %e, "Failed to replay queue journal"),
+        }
         handles
     }
+
+    fn spawner(&self) -> Spawner {
+        Spawner {
+            queue: self.queue.clone(),
+            storage: self.storage.clone(),
+            auth: self.auth.clone(),
+            config: self.config.clone(),
+            feeder: self.feeder.clone(),
+        }
+    }
+}
+
+/// Everything a worker task needs
+#[derive(Clone)]
+struct Spawner {
+    queue: ArticleQueue,
+    storage: DynStorage,
+    auth: DynAuth,
+    config: Arc<RwLock<Config>>,
+    feeder: Option<Arc<Feeder>>,
+}
+
+impl Spawner {
+    fn spawn(
+        &self,
+        worker_id: usize,
+        stop: Option<tokio::sync::oneshot::Receiver<()>>,
+    ) -> tokio::task::JoinHandle<()> {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            worker_task(
+                worker_id,
+                pool.queue,
+                pool.storage,
+                pool.auth,
+                pool.config,
+                pool.feeder,
+                stop,
+            )
+            .await;
+        })
+    }
+}
+
+/// Starts and retires the workers of an autoscaling pool
+struct Scaler {
+    pool: Spawner,
+    min: usize,
+    max: usize,
+    /// Running workers, numbered by position, with the means to stop them
+    workers: Vec<(
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<()>,
+    )>,
+}
+
+impl Scaler {
+    async fn run(mut self) {
+        while self.workers.len() < self.min {
+            self.grow();
+        }
+        let queue = self.pool.queue.clone();
+        let mut backlog = false;
+        let mut idle = 0u32;
+        let mut warned: Option<Instant> = None;
+        let mut ticker = tokio::time::interval(SCALE_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let depth = queue.len();
+            if depth == 0 {
+                backlog = false;
+                idle += 1;
+                if idle >= IDLE_INTERVALS && self.workers.len() > self.min {
+                    idle = 0;
+                    self.shrink().await;
+                }
+                continue;
+            }
+            idle = 0;
+            // Articles still waiting after a whole interval
+            if backlog && self.workers.len() < self.max {
+                self.grow();
+            }
+            backlog = true;
+            if self.workers.len() == self.max
+                && depth * 2 >= queue.capacity()
+                && warned.is_none_or(|at| at.elapsed() >= SATURATION_WARN_INTERVAL)
+            {
+                warned = Some(Instant::now());
+                warn!(
+                    workers = self.max,
+                    queue_length = depth,
+                    queue_capacity = queue.capacity(),
+                    "Article workers saturated; consider raising article_worker_max_count"
+                );
+            }
+        }
+    }
+
+    fn grow(&mut self) {
+        let (stop, stopped) = tokio::sync::oneshot::channel();
+        let worker_id = self.workers.len();
+        let handle = self.pool.spawn(worker_id, Some(stopped));
+        self.workers.push((stop, handle));
+        debug!(workers = self.workers.len(), "Added article worker");
+    }
+
+    /// Retire the most recently started worker once it has finished its
+    /// batch
+    async fn shrink(&mut self) {
+        if let Some((stop, handle)) = self.workers.pop() {
+            let _ = stop.send(());
+            let _ = handle.await;
+            debug!(workers = self.workers.len(), "Retired article worker");
+        }
+    }
+}
+
+/// Most articles a worker takes from the queue and stores in one transaction
+const MAX_BATCH: usize = 64;
+
+/// Wait for the next article, or `None` once the queue has closed or the
+/// worker is asked to stop
+async fn next_article(
+    receiver: &Receiver<QueuedArticle>,
+    stop: &mut Option<tokio::sync::oneshot::Receiver<()>>,
+) -> Option<QueuedArticle> {
+    match stop {
+        Some(stop) => tokio::select! {
+            biased;
+            _ = stop => None,
+            article = receiver.recv_async() => article.ok(),
+        },
+        None => receiver.recv_async().await.ok(),
+    }
 }
 
 /// Worker task that processes articles from the queue
+///
+/// Articles already waiting in the queue are taken together and stored in
+/// one transaction. Control messages are processed on their own, after the
+/// articles queued before them have been stored. A worker given `stop`
+/// finishes its batch and returns once it is signalled.
 async fn worker_task(
     worker_id: usize,
-    receiver: Receiver<QueuedArticle>,
+    queue: ArticleQueue,
     storage: DynStorage,
     auth: DynAuth,
     config: Arc<RwLock<Config>>,
+    feeder: Option<Arc<Feeder>>,
+    mut stop: Option<tokio::sync::oneshot::Receiver<()>>,
 ) {
     debug!(worker_id = worker_id, "Article worker started");
+    let running = &queue.workers.running;
+    running.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
-    while let Ok(queued_article) = receiver.recv_async().await {
-        let message_id = queued_article
-            .message
-            .headers
-            .iter()
-            .find(|(k, _)| k.eq_ignore_ascii_case("Message-ID"))
-            .map(|(_, v)| v.as_str())
-            .unwrap_or("<unknown>");
-
-        let span = info_span!(
-            "queue.process",
-            worker_id = worker_id,
-            message_id = message_id,
-            size_bytes = queued_article.size,
-            is_control = queued_article.is_control,
-            outcome = tracing::field::Empty,
-        );
-
-        async {
-            let start = std::time::Instant::now();
-            match process_article(&queued_article, &storage, &auth, &config).await {
-                Ok(()) => {
-                    tracing::Span::current().record("outcome", "success");
-                    debug!(duration_ms = start.elapsed().as_millis() as u64, "Article processed");
-                }
-                Err(e) => {
-                    tracing::Span::current().record("outcome", "failed");
-                    error!(error = %e, duration_ms = start.elapsed().as_millis() as u64, "Article processing failed");
+    let receiver = queue.receiver();
+    let journal = queue.journal.clone();
+    let mut next = next_article(&receiver, &mut stop).await;
+    while let Some(first) = next.take() {
+        let start = Instant::now();
+        let mut batch = vec![first];
+        if !batch[0].is_control {
+            while batch.len() < MAX_BATCH
+                && let Ok(queued) = receiver.try_recv()
+            {
+                if queued.is_control {
+                    next = Some(queued);
+                    break;
                 }
+                batch.push(queued);
             }
         }
-        .instrument(span)
+
+        process_batch(
+            worker_id,
+            &batch,
+            &storage,
+            &auth,
+            &config,
+            journal.as_deref(),
+            feeder.as_deref(),
+        )
         .await;
+        queue.release(&batch);
+        queue.record_batch(worker_id, start.elapsed());
+
+        if next.is_none() {
+            next = next_article(&receiver, &mut stop).await;
+        }
     }
 
+    running.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
     debug!(worker_id = worker_id, "Article worker stopped");
 }
 
-/// Process a single article: comprehensive validation and storage
-async fn process_article(
+/// An article that passed validation, with the span it is processed in
+struct Accepted<'a> {
+    queued: &'a QueuedArticle,
+    span: tracing::Span,
+    start: std::time::Instant,
+}
+
+/// Validate every article of `batch`, store the new ones together and offer
+/// them to the streaming feeds
+async fn process_batch(
+    worker_id: usize,
+    batch: &[QueuedArticle],
+    storage: &DynStorage,
+    auth: &DynAuth,
+    config: &Arc<RwLock<Config>>,
+    journal: Option<&QueueJournal>,
+    feeder: Option<&Feeder>,
+) {
+    // Articles to store, in queue order, and those already stored
+    let mut fresh = Vec::new();
+    let mut fresh_ids = HashSet::new();
+    let mut fresh_articles = Vec::new();
+    let mut existing = Vec::new();
+    // Articles replacing an earlier one, stored on their own
+    let mut superseding = Vec::new();
+
+    for queued in batch {
+        let span = process_span(worker_id, queued);
+        let start = std::time::Instant::now();
+        let accepted = Accepted {
+            queued,
+            span,
+            start,
+        };
+        match prepare_article(queued, storage, auth, config)
+            .instrument(accepted.span.clone())
+            .await
+        {
+            Ok(Some(article)) => {
+                match superseded_article(&article, storage, auth, config).await {
+                    Ok(Some(superseded)) => {
+                        superseding.push((accepted, article, superseded));
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        finish(accepted, Err(e), journal).await;
+                        continue;
+                    }
+                }
+                // Check whether the article already exists to avoid duplicates
+                let message_id = journal_key(&article).to_string();
+                let stored = if message_id.is_empty() {
+                    Ok(false)
+                } else if fresh_ids.contains(&message_id) {
+                    Ok(true)
+                } else {
+                    storage
+                        .get_article_by_id(&message_id)
+                        .await
+                        .map(|found| found.is_some())
+                };
+                match stored {
+                    Ok(true) => existing.push((accepted, article)),
+                    Ok(false) => {
+                        fresh_ids.insert(message_id);
+                        fresh.push(accepted);
+                        fresh_articles.push(article);
+                    }
+                    Err(e) => finish(accepted, Err(e), journal).await,
+                }
+            }
+            Ok(None) => finish(accepted, Ok(()), journal).await,
+            Err(e) => finish(accepted, Err(e), journal).await,
+        }
+    }
+
+    let results = store_batch(storage, &fresh_articles).await;
+    for ((accepted, article), result) in fresh.into_iter().zip(&fresh_articles).zip(results) {
+        let result = match result {
+            Ok(()) => {
+                debug!(parent: &accepted.span, "Article stored successfully");
+                offer(feeder, config, article, &accepted.span).await;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        };
+        finish(accepted, result, journal).await;
+    }
+    for (accepted, article) in existing {
+        debug!(parent: &accepted.span, "Article already exists, skipping storage");
+        offer(feeder, config, &article, &accepted.span).await;
+        finish(accepted, Ok(()), journal).await;
+    }
+    // After the batch, so that an article superseding one stored with it
+    // finds it
+    for (accepted, article, (old_id, detail)) in superseding {
+        let result = storage.supersede_article(&old_id, &article).await;
+        if result.is_ok() {
+            debug!(parent: &accepted.span, superseded = %old_id, "Article superseded");
+            let poster = get_header_value(&article, "From");
+            let entry = AuditEntry::new(AuditAction::Supersede, old_id.as_str())
+                .by(poster.as_deref())
+                .with_detail(detail);
+            crate::audit::record(&**storage, entry).await;
+            offer(feeder, config, &article, &accepted.span).await;
+        }
+        finish(accepted, result, journal).await;
+    }
+}
+
+/// The article `article` replaces, if its Supersedes header is authorized
+async fn superseded_article(
+    article: &Message,
+    storage: &DynStorage,
+    auth: &DynAuth,
+    config: &Arc<RwLock<Config>>,
+) -> Result<Option<(String, &'static str)>> {
+    if get_header_value(article, "Supersedes").is_none() {
+        return Ok(None);
+    }
+    let cfg_guard = config.read().await;
+    crate::control::superseded_article(article, storage, auth, &cfg_guard).await
+}
+
+fn process_span(worker_id: usize, queued_article: &QueuedArticle) -> tracing::Span {
+    let message_id = queued_article
+        .message
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Message-ID"))
+        .map(|(_, v)| v.as_str())
+        .unwrap_or("<unknown>");
+
+    info_span!(
+        "queue.process",
+        worker_id = worker_id,
+        message_id = message_id,
+        size_bytes = queued_article.size,
+        is_control = queued_article.is_control,
+        outcome = tracing::field::Empty,
+    )
+}
+
+/// Record the outcome of an article and mark it done in the journal
+async fn finish(accepted: Accepted<'_>, result: Result<()>, journal: Option<&QueueJournal>) {
+    let Accepted {
+        queued,
+        span,
+        start,
+    } = accepted;
+    async {
+        match result {
+            Ok(()) => {
+                tracing::Span::current().record("outcome", "success");
+                debug!(duration_ms = start.elapsed().as_millis() as u64, "Article processed");
+            }
+            Err(e) => {
+                tracing::Span::current().record("outcome", "failed");
+                error!(error = %e, duration_ms = start.elapsed().as_millis() as u64, "Article processing failed");
+            }
+        }
+        if let Some(journal) = journal
+            && let Err(e) = journal.complete(&queued.message).await
+        {
+            error!(error = %e, "Failed to record article in queue journal");
+        }
+    }
+    .instrument(span)
+    .await;
+}
+
+/// Store `articles` in one transaction. If the transaction fails they are
+/// stored one at a time, so one bad article cannot hold back the others.
+async fn store_batch(storage: &DynStorage, articles: &[Message]) -> Vec<Result<()>> {
+    match articles {
+        [] => Vec::new(),
+        [article] => vec![storage.store_article(article).await],
+        _ => match storage.store_articles(articles).await {
+            Ok(()) => articles.iter().map(|_| Ok(())).collect(),
+            Err(e) => {
+                warn!(error = %e, count = articles.len(), "Failed to store article batch, storing articles one at a time");
+                let mut results = Vec::with_capacity(articles.len());
+                for article in articles {
+                    results.push(storage.store_article(article).await);
+                }
+                results
+            }
+        },
+    }
+}
+
+/// Offer a stored article to the streaming feeds and the mail gateways
+///
+/// IHAVE and TAKETHIS articles were stored before they were queued, so they
+/// are offered to streaming feeds here as well.
+async fn offer(
+    feeder: Option<&Feeder>,
+    config: &Arc<RwLock<Config>>,
+    article: &Message,
+    span: &tracing::Span,
+) {
+    if let Some(feeder) = feeder {
+        feeder.offer(article).instrument(span.clone()).await;
+    }
+    crate::gateway::forward(config, article)
+        .instrument(span.clone())
+        .await;
+}
+
+/// Validate a single article, returning it if it should be stored
+///
+/// Control messages and articles a filter has dealt with are consumed here.
+async fn prepare_article(
     queued_article: &QueuedArticle,
     storage: &DynStorage,
     auth: &DynAuth,
     config: &Arc<RwLock<Config>>,
-) -> Result<()> {
+) -> Result<Option<Message>> {
     let article = &queued_article.message;
 
     // Handle control messages first
@@ -182,52 +1067,51 @@ async fn process_article(
         let cfg_guard = config.read().await;
         if crate::control::handle_control(article, storage, auth, &cfg_guard).await? {
             debug!("Processed control message");
-            return Ok(());
+            return Ok(None);
         }
     }
 
     // Perform comprehensive validation only if not already done
+    let mut article = std::borrow::Cow::Borrowed(article);
     if !queued_article.already_validated {
         let cfg_guard = config.read().await;
 
-        // Create filter chain from configuration
-        let filter_chain = match crate::filters::factory::create_filter_chain(&cfg_guard.filters) {
-            Ok(chain) => chain,
+        // Use the configured filter chain for validation
+        let verdict = match crate::handlers::utils::configured_filter_chain(&cfg_guard)
+            .evaluate(storage, auth, &cfg_guard, &article, queued_article.size)
+            .await
+        {
+            Ok(verdict) => verdict,
             Err(e) => {
-                error!("Failed to create filter chain: {}", e);
-                // Fall back to default chain if configuration is invalid
-                crate::filters::FilterChain::default()
+                // The peer was told the article was taken; refuse later offers
+                // unless a filter only failed for now
+                if !crate::filters::TemporaryFailure::is(&e) {
+                    crate::history::remember_rejection(&**storage, journal_key(&article)).await;
+                    crate::rejected::keep(&**storage, &cfg_guard, &article, &e).await;
+                }
+                return Err(e);
             }
         };
-
-        // Use the configured filter chain for validation
-        crate::handlers::utils::validate_article_with_filters(
-            storage,
-            auth,
-            &cfg_guard,
-            article,
-            queued_article.size,
-            &filter_chain,
-        )
-        .await?;
         drop(cfg_guard);
-    }
 
-    // Store the article (check if it already exists to avoid duplicates)
-    let message_id = article
-        .headers
-        .iter()
-        .find(|(k, _)| k.eq_ignore_ascii_case("Message-ID"))
-        .map(|(_, v)| v.as_str())
-        .unwrap_or("");
-
-    if !message_id.is_empty() && storage.get_article_by_id(message_id).await?.is_some() {
-        debug!("Article already exists, skipping storage");
-        return Ok(());
+        if verdict != crate::filters::FilterVerdict::default()
+            && verdict.apply(storage, article.to_mut()).await?
+        {
+            return Ok(None);
+        }
     }
 
-    storage.store_article(article).await?;
-    debug!("Article stored successfully");
+    // Apply NoCeM notices; IHAVE and TAKETHIS have already stored the
+    // article by the time it gets here
+    let nocem = {
+        let cfg_guard = config.read().await;
+        crate::nocem::is_notice(&article, &cfg_guard.nocem).then(|| cfg_guard.nocem.clone())
+    };
+    if let Some(nocem) = nocem
+        && let Err(e) = crate::nocem::process_notice(&article, storage, &nocem).await
+    {
+        warn!(error = %e, "Ignoring NoCeM notice");
+    }
 
-    Ok(())
+    Ok(Some(article.into_owned()))
 }
@@ -0,0 +1,43 @@
+//! Articles refused by filters.
+//!
+//! When `rejected_retention_days` is set, articles that the filter chain
+//! refuses for good (spam, size, milter and the other configured filters)
+//! are kept together with the reason for that many days. Administrators
+//! list and read them with `renews admin rejected`, and inject the ones that
+//! were refused wrongly. Refusals that only hold for now, and articles for
+//! archived groups, are not kept.
+
+use crate::Message;
+use crate::config::Config;
+use crate::filters::{ArchivedGroup, TemporaryFailure};
+use crate::storage::Storage;
+use anyhow::Result;
+use tracing::warn;
+
+/// Keep `article`, refused by the filters with `error`, if the
+/// configuration asks for it. Failures are logged rather than returned, as
+/// the article has already been refused.
+pub async fn keep(storage: &dyn Storage, cfg: &Config, article: &Message, error: &anyhow::Error) {
+    if cfg.rejected_retention_days == 0 || TemporaryFailure::is(error) || ArchivedGroup::is(error) {
+        return;
+    }
+    if let Err(e) = storage
+        .add_rejected_article(article, &format!("{error:#}"))
+        .await
+    {
+        warn!(error = %e, "Failed to keep rejected article");
+    }
+}
+
+/// Store kept article `id` without passing it through the filters again,
+/// and forget it.
+///
+/// Returns the stored article, or `None` if no such entry exists.
+pub async fn reinject(storage: &dyn Storage, id: u64) -> Result<Option<Message>> {
+    let Some(rejected) = storage.get_rejected_article(id).await? else {
+        return Ok(None);
+    };
+    storage.store_article(&rejected.message).await?;
+    storage.remove_rejected_article(id).await?;
+    Ok(Some(rejected.message))
+}
@@ -0,0 +1,403 @@
+//! Hot standby replication of the article store.
+//!
+//! NNTP peering copies articles between servers but not group metadata,
+//! moderation flags or deletions, so a peer cannot take over from a failed
+//! server. With `[replication]` configured, a primary records every change
+//! to its article store in a replication log, numbered in the order the
+//! changes were made, and serves the log to standbys on `listen`. A standby
+//! follows the primary at `primary`, applies each change to its own store in
+//! the same order and records it in its own log under the primary's number,
+//! so that it resumes where it stopped after a restart and can itself be
+//! promoted to primary.
+//!
+//! The protocol is line based. The standby sends `FOLLOW <seq> <secret>`,
+//! where `<seq>` is the last change it applied, and the primary answers `ok`
+//! followed by one `<seq> <change>` line per change, with the change as JSON,
+//! and `heartbeat` lines while there is nothing to send. Errors are reported
+//! as `error <reason>` before the connection is closed.
+//!
+//! Articles are numbered as on the primary because changes are applied in
+//! order. The moderation queue, audit log, resume tokens and usage data stay
+//! local to each server.
+
+use crate::Message;
+use crate::storage::{DynStorage, Storage};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info, warn};
+
+/// How often a primary looks for new changes while a standby is caught up.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a primary stays silent before telling a standby it is alive.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a standby waits for a line before giving up on the primary.
+const READ_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// How long a standby waits before reconnecting to the primary.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Longest `FOLLOW` line accepted, in bytes.
+const MAX_FOLLOW_BYTES: u64 = 4096;
+
+/// A change to the article store, as recorded in the replication log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    Store {
+        headers: Vec<(String, String)>,
+        body: String,
+        /// Unix timestamp the primary inserted the article at, which
+        /// retention compares against
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        inserted_at: Option<i64>,
+    },
+    Delete {
+        message_id: String,
+    },
+    AddGroup {
+        group: String,
+        moderated: bool,
+        description: Option<String>,
+    },
+    SetModerated {
+        group: String,
+        moderated: bool,
+    },
+    SetArchived {
+        group: String,
+        archived: bool,
+    },
+    SetDescription {
+        group: String,
+        description: String,
+    },
+    RemoveGroup {
+        group: String,
+    },
+    RemoveGroups {
+        pattern: String,
+    },
+    PurgeGroup {
+        group: String,
+        before: i64,
+    },
+    PurgeOrphans,
+    RenumberGroup {
+        group: String,
+    },
+    SetPinned {
+        group: String,
+        message_id: String,
+        pinned: bool,
+    },
+    Remember {
+        message_id: String,
+    },
+    PurgeHistory {
+        before: i64,
+    },
+}
+
+impl Change {
+    /// A change storing `article`, stamped with its insertion time when it
+    /// is recorded.
+    #[must_use]
+    pub fn store(article: &Message) -> Self {
+        Self::Store {
+            headers: article.headers.to_vec(),
+            body: article.body.clone(),
+            inserted_at: None,
+        }
+    }
+
+    /// Make the change to `storage`.
+    ///
+    /// Articles already stored are skipped, so a change applied again after
+    /// a standby stopped before recording it has no further effect.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the storage fails.
+    pub async fn apply(self, storage: &dyn Storage) -> Result<()> {
+        match self {
+            Self::Store {
+                headers,
+                body,
+                inserted_at,
+            } => {
+                let article = Message {
+                    headers: headers.into_iter().collect(),
+                    body,
+                };
+                let message_id = crate::handlers::utils::get_header_value(&article, "Message-ID")
+                    .ok_or_else(|| anyhow!("replicated article has no Message-ID"))?;
+                if storage.get_article_by_id(&message_id).await?.is_none() {
+                    match inserted_at {
+                        Some(at) => {
+                            let at = timestamp(at)?;
+                            crate::storage::replicated::inserted_at(
+                                at,
+                                storage.store_article(&article),
+                            )
+                            .await?;
+                        }
+                        None => storage.store_article(&article).await?,
+                    }
+                }
+            }
+            Self::Delete { message_id } => storage.delete_article_by_id(&message_id).await?,
+            Self::AddGroup {
+                group,
+                moderated,
+                description: Some(description),
+            } => {
+                storage
+                    .add_group_with_description(&group, moderated, &description)
+                    .await?;
+            }
+            Self::AddGroup {
+                group,
+                moderated,
+                description: None,
+            } => storage.add_group(&group, moderated).await?,
+            Self::SetModerated { group, moderated } => {
+                storage.set_group_moderated(&group, moderated).await?;
+            }
+            Self::SetArchived { group, archived } => {
+                storage.set_group_archived(&group, archived).await?;
+            }
+            Self::SetDescription { group, description } => {
+                storage.set_group_description(&group, &description).await?;
+            }
+            Self::RemoveGroup { group } => storage.remove_group(&group).await?,
+            Self::RemoveGroups { pattern } => storage.remove_groups_by_pattern(&pattern).await?,
+            Self::PurgeGroup { group, before } => {
+                storage
+                    .purge_group_before(&group, timestamp(before)?)
+                    .await?;
+            }
+            Self::PurgeOrphans => storage.purge_orphan_messages().await?,
+            Self::RenumberGroup { group } => {
+                storage.renumber_group(&group).await?;
+            }
+            Self::SetPinned {
+                group,
+                message_id,
+                pinned,
+            } => {
+                storage
+                    .set_article_pinned(&group, &message_id, pinned)
+                    .await?;
+            }
+            Self::Remember { message_id } => storage.remember_message_id(&message_id).await?,
+            Self::PurgeHistory { before } => {
+                storage.purge_history_before(timestamp(before)?).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn timestamp(secs: i64) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp(secs, 0).ok_or_else(|| anyhow!("invalid timestamp {secs}"))
+}
+
+/// Compare secrets without revealing through timing how much matched.
+fn secrets_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Serves the replication log to standbys.
+pub struct ReplicationServer {
+    storage: DynStorage,
+    secret: String,
+}
+
+impl ReplicationServer {
+    #[must_use]
+    pub fn new(storage: DynStorage, secret: String) -> Self {
+        Self { storage, secret }
+    }
+
+    /// Accept standbys on `listener` until it fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting a connection fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                info!(peer = %peer, "Standby connected");
+                if let Err(e) = server.handle(stream).await {
+                    warn!(peer = %peer, error = %e, "Standby disconnected");
+                }
+            });
+        }
+    }
+
+    /// Stream changes to the standby on `stream` until it goes away.
+    async fn handle(&self, stream: TcpStream) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut reader = BufReader::new(read.take(MAX_FOLLOW_BYTES));
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        let mut words = line.trim_end().splitn(3, ' ');
+        let (Some("FOLLOW"), Some(seq), Some(secret)) = (words.next(), words.next(), words.next())
+        else {
+            write
+                .write_all(b"error expected FOLLOW <seq> <secret>\n")
+                .await?;
+            return Err(anyhow!("malformed FOLLOW request"));
+        };
+        if !secrets_match(secret, &self.secret) {
+            write.write_all(b"error authentication failed\n").await?;
+            return Err(anyhow!("wrong replication secret"));
+        }
+        let Ok(mut position) = seq.parse::<u64>() else {
+            write.write_all(b"error invalid position\n").await?;
+            return Err(anyhow!("invalid position '{seq}'"));
+        };
+        write.write_all(b"ok\n").await?;
+        // Detect the standby closing the connection while it is caught up
+        let mut closed = Box::pin(async move {
+            let mut rest = reader.into_inner().into_inner();
+            let _ = tokio::io::copy(&mut rest, &mut tokio::io::sink()).await;
+        });
+
+        let mut idle = Duration::ZERO;
+        loop {
+            let mut changes = self.storage.list_changes_since(position);
+            let mut sent = false;
+            while let Some(change) = changes.next().await {
+                let (seq, change) = change?;
+                if seq != position + 1 {
+                    let reason = format!(
+                        "change {} is no longer in the replication log; copy the primary's database again",
+                        position + 1
+                    );
+                    write
+                        .write_all(format!("error {reason}\n").as_bytes())
+                        .await?;
+                    return Err(anyhow!(reason));
+                }
+                write
+                    .write_all(format!("{seq} {change}\n").as_bytes())
+                    .await?;
+                position = seq;
+                sent = true;
+            }
+            drop(changes);
+            if sent {
+                idle = Duration::ZERO;
+            } else if idle >= HEARTBEAT_INTERVAL {
+                write.write_all(b"heartbeat\n").await?;
+                idle = Duration::ZERO;
+            }
+            tokio::select! {
+                () = &mut closed => return Ok(()),
+                () = tokio::time::sleep(POLL_INTERVAL) => idle += POLL_INTERVAL,
+            }
+        }
+    }
+}
+
+/// Listen for standbys on the replication address `raw`.
+///
+/// # Errors
+///
+/// Returns an error if the address cannot be bound.
+pub async fn bind(raw: &str) -> Result<TcpListener> {
+    let addr = crate::config::listen_addr(raw);
+    TcpListener::bind(&addr).await.map_err(|e| {
+        anyhow!(
+            "Failed to bind to replication address '{addr}': {e}
+
+You can change the replication listen address in your configuration file using the 'listen' setting
+of the [replication] section."
+        )
+    })
+}
+
+/// Follow the primary at `primary` for ever, applying its changes to
+/// `storage` and reconnecting whenever the connection is lost.
+pub async fn follow(storage: DynStorage, primary: String, secret: String) {
+    loop {
+        match follow_once(&*storage, &primary, &secret).await {
+            Ok(()) => warn!(primary = %primary, "Primary closed the replication connection"),
+            Err(e) => error!(primary = %primary, error = %e, "Replication from primary failed"),
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+/// Follow the primary over one connection until it fails.
+async fn follow_once(storage: &dyn Storage, primary: &str, secret: &str) -> Result<()> {
+    let mut position = storage.last_change_seq().await?;
+    let stream = TcpStream::connect(primary).await?;
+    let (read, mut write) = stream.into_split();
+    write
+        .write_all(format!("FOLLOW {position} {secret}\n").as_bytes())
+        .await?;
+    let mut lines = BufReader::new(read).lines();
+    match next_line(&mut lines).await?.as_deref() {
+        Some("ok") => info!(primary = %primary, position, "Following primary"),
+        Some(line) => return Err(refusal(line)),
+        None => return Ok(()),
+    }
+    while let Some(line) = next_line(&mut lines).await? {
+        if line == "heartbeat" {
+            continue;
+        }
+        let Some((seq, json)) = line.split_once(' ') else {
+            return Err(anyhow!("malformed replication line '{line}'"));
+        };
+        let Ok(seq) = seq.parse::<u64>() else {
+            return Err(refusal(&line));
+        };
+        if seq != position + 1 {
+            return Err(anyhow!("expected change {} but got {seq}", position + 1));
+        }
+        let change: Change = serde_json::from_str(json)?;
+        debug!(seq, "Applying replicated change");
+        change.apply(storage).await?;
+        storage.record_change(seq, json).await?;
+        position = seq;
+    }
+    Ok(())
+}
+
+async fn next_line<R: AsyncRead + Unpin>(
+    lines: &mut tokio::io::Lines<BufReader<R>>,
+) -> Result<Option<String>> {
+    tokio::time::timeout(READ_TIMEOUT, lines.next_line())
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "primary sent nothing for {} seconds",
+                READ_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(Into::into)
+}
+
+fn refusal(line: &str) -> anyhow::Error {
+    match line.strip_prefix("error ") {
+        Some(reason) => anyhow!("primary refused: {reason}"),
+        None => anyhow!("unexpected reply '{line}'"),
+    }
+}
@@ -1,12 +1,24 @@
 //! Response constants module.
 //!
 //! Contains all NNTP response codes and messages used throughout the server.
+//! The human-readable text of the fixed status lines in [`STATUS_LINES`] can
+//! be replaced per locale through the `[responses]` configuration table;
+//! status lines are written through [`localize`] so that the
+//! [`ResponseCatalog`] of the session applies to them. Each session runs
+//! under the catalog of its site's configuration, or of its listener's own
+//! `locale`, see [`with_catalog`].
+
+use crate::config::ResponsesConfig;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 // Basic response codes
 pub const RESP_CRLF: &str = "\r\n";
 pub const RESP_DOT_CRLF: &str = ".\r\n";
 
 // Connection and status responses
+pub const RESP_400_DRAINING: &str = "400 service temporarily unavailable, try again later\r\n";
 pub const RESP_200_READY: &str = "200 NNTP Service Ready\r\n";
 pub const RESP_201_READY_NO_POST: &str = "201 NNTP Service Ready - no posting allowed\r\n";
 pub const RESP_200_POSTING_ALLOWED: &str = "200 Posting allowed\r\n";
@@ -21,14 +33,22 @@ pub const RESP_222_BODY: &str = "222";
 pub const RESP_223_STAT: &str = "223";
 pub const RESP_224_OVERVIEW: &str = "224 Overview information follows\r\n";
 pub const RESP_225_HEADERS: &str = "225 Headers follow\r\n";
+pub const RESP_224_COMPRESSED_OVERVIEW: &str = "224 compressed overview information follows\r\n";
+pub const RESP_225_COMPRESSED_HEADERS: &str = "225 compressed headers follow\r\n";
 
 // Group and list responses
 pub const RESP_211_GROUP: &str = "211";
-pub const RESP_211_LISTGROUP: &str = "211 article numbers follow\r\n";
+pub const RESP_211_LIST_FOLLOWS: &str = "211 list follows\r\n";
 pub const RESP_215_LIST_FOLLOWS: &str = "215 list of newsgroups follows\r\n";
 pub const RESP_215_DESCRIPTIONS: &str = "215 descriptions follow\r\n";
+pub const RESP_215_SUBSCRIPTIONS: &str = "215 list of recommended newsgroups follows\r\n";
 pub const RESP_215_INFO_FOLLOWS: &str = "215 information follows\r\n";
 pub const RESP_215_OVERVIEW_FMT: &str = "215 Order of fields in overview database.\r\n";
+pub const RESP_215_PENDING: &str = "215 pending articles follow\r\n";
+pub const RESP_215_PINNED: &str = "215 pinned articles follow\r\n";
+pub const RESP_215_EXPIRE: &str = "215 group retention follows\r\n";
+pub const RESP_215_ACTIVITY: &str = "215 group activity follows\r\n";
+pub const RESP_215_PEERS: &str = "215 peer statistics follow\r\n";
 pub const RESP_215_METADATA: &str = "215 metadata items supported:\r\n";
 pub const RESP_221_HEADER_FOLLOWS: &str = "221 Header follows\r\n";
 pub const RESP_230_NEWNEWS: &str = "230 list of new articles follows\r\n";
@@ -39,19 +59,33 @@ pub const RESP_235_TRANSFER_OK: &str = "235 Article transferred OK\r\n";
 pub const RESP_238_CHECK_OK: &str = "238";
 pub const RESP_239_TAKETHIS_OK: &str = "239";
 pub const RESP_240_ARTICLE_RECEIVED: &str = "240 article received\r\n";
+pub const RESP_240_ARTICLE_APPROVED: &str = "240 article approved\r\n";
+pub const RESP_241_ARTICLE_REJECTED: &str = "241 article rejected\r\n";
+pub const RESP_242_ARTICLE_PINNED: &str = "242 article pinned\r\n";
+pub const RESP_243_ARTICLE_UNPINNED: &str = "243 article unpinned\r\n";
+pub const RESP_244_POST_WOULD_BE_ACCEPTED: &str = "244 article would be accepted\r\n";
+pub const RESP_245_POST_WOULD_BE_HELD: &str = "245 article would be held for moderation\r\n";
 
 // Authentication responses
 pub const RESP_281_AUTH_OK: &str = "281 authentication accepted\r\n";
+pub const RESP_284_CANCEL_MODE: &str = "284 cancel mode, send Message-IDs to cancel\r\n";
+pub const RESP_289_CANCELLED: &str = "289 article cancelled\r\n";
+pub const RESP_290_FEATURE_ENABLED: &str = "290 feature enabled\r\n";
+pub const RESP_290_HOST_SELECTED: &str = "290 virtual host selected\r\n";
 pub const RESP_290_PASSWORD_OK: &str = "290 Password for {user} accepted\r\n";
 
 // Error responses
 pub const RESP_340_SEND_ARTICLE: &str =
     "340 send article to be posted. End with <CR-LF>.<CR-LF>\r\n";
 pub const RESP_335_SEND_IT: &str = "335 Send it; end with <CR-LF>.<CR-LF>\r\n";
+pub const RESP_345_SEND_HEADERS: &str =
+    "345 send headers to be checked. End with <CR-LF>.<CR-LF>\r\n";
 pub const RESP_381_PASSWORD_REQ: &str = "381 password required\r\n";
+pub const RESP_382_CONTINUE_TLS: &str = "382 Continue with TLS negotiation\r\n";
 
 // 4xx error responses
 pub const RESP_403_BANDWIDTH_EXCEEDED: &str = "403 bandwidth limit exceeded\r\n";
+pub const RESP_403_INTERNAL_FAULT: &str = "403 internal fault\r\n";
 pub const RESP_411_NO_SUCH_GROUP: &str = "411 no such newsgroup\r\n";
 pub const RESP_412_NO_GROUP: &str = "412 no newsgroup selected\r\n";
 pub const RESP_420_NO_CURRENT: &str = "420 no current article selected\r\n";
@@ -60,16 +94,24 @@ pub const RESP_422_NO_PREV: &str = "422 no previous article\r\n";
 pub const RESP_423_RANGE_EMPTY: &str = "423 no articles in that range\r\n";
 pub const RESP_423_NO_ARTICLE_NUM: &str = "423 no such article number in this group\r\n";
 pub const RESP_430_NO_ARTICLE: &str = "430 no such article\r\n";
+pub const RESP_430_NO_PENDING: &str = "430 no such pending article\r\n";
 pub const RESP_435_NOT_WANTED: &str = "435 article not wanted\r\n";
+pub const RESP_436_TRY_LATER: &str = "436 transfer not possible; try again later\r\n";
 pub const RESP_437_REJECTED: &str = "437 article rejected\r\n";
+pub const RESP_437_ARCHIVED: &str = "437 group is archived\r\n";
 pub const RESP_438_CHECK_REJECT: &str = "438";
 pub const RESP_439_TAKETHIS_REJECT: &str = "439";
 pub const RESP_440_POST_PROHIBITED: &str = "440 posting not allowed\r\n";
 pub const RESP_441_POSTING_FAILED: &str = "441 posting failed\r\n";
+pub const RESP_441_ARCHIVED: &str = "441 group is archived\r\n";
+pub const RESP_441_TOO_LARGE: &str = "441 article too large\r\n";
 pub const RESP_480_AUTH_REQUIRED: &str = "480 authentication required\r\n";
 pub const RESP_481_AUTH_REJECTED: &str = "481 Authentication rejected\r\n";
-pub const RESP_481_CONN_LIMIT: &str = "481 connection limit exceeded\r\n";
+pub const RESP_481_LOCKED_OUT: &str = "481 too many failed logins\r\n";
 pub const RESP_483_SECURE_REQ: &str = "483 Secure connection required\r\n";
+pub const RESP_484_NO_ARTICLE: &str = "484 no such article\r\n";
+pub const RESP_484_INVALID_ID: &str = "484 invalid message-id\r\n";
+pub const RESP_484_CANCEL_FAILED: &str = "484 cancel failed\r\n";
 
 // 5xx error responses
 pub const RESP_500_SYNTAX: &str = "500 Syntax error\r\n";
@@ -77,13 +119,34 @@ pub const RESP_500_UNKNOWN_CMD: &str = "500 command not recognized\r\n";
 pub const RESP_501_SYNTAX: &str = "501 Syntax error\r\n";
 pub const RESP_501_INVALID_ID: &str = "501 invalid id\r\n";
 pub const RESP_501_INVALID_ARG: &str = "501 invalid argument\r\n";
+pub const RESP_501_INVALID_NUMBER: &str = "501 invalid number\r\n";
+pub const RESP_501_INVALID_MSGID: &str = "501 invalid message-id\r\n";
+pub const RESP_501_INVALID_ARTICLE_RANGE: &str = "501 invalid range\r\n";
+pub const RESP_501_INVALID_ARTICLE: &str = "501 invalid article\r\n";
+pub const RESP_501_INVALID_TIME: &str = "501 invalid time\r\n";
+pub const RESP_501_TOO_MANY_ARGS: &str = "501 too many arguments\r\n";
 pub const RESP_501_INVALID_DATE: &str = "501 invalid date\r\n";
+pub const RESP_501_FUTURE_DATE: &str = "501 date is in the future\r\n";
 pub const RESP_501_MSGID_REQUIRED: &str = "501 message-id required\r\n";
 pub const RESP_501_NOT_ENOUGH: &str = "501 not enough arguments\r\n";
 pub const RESP_501_UNKNOWN_KEYWORD: &str = "501 unknown keyword\r\n";
 pub const RESP_501_UNKNOWN_MODE: &str = "501 unknown mode\r\n";
+pub const RESP_501_INVALID_RANGE: &str = "501 invalid byte range\r\n";
 pub const RESP_501_MISSING_MODE: &str = "501 missing mode\r\n";
+pub const RESP_501_UNKNOWN_HOST: &str = "501 unknown virtual host\r\n";
+pub const RESP_502_ADMIN_REQUIRED: &str = "502 administrator access required\r\n";
+pub const RESP_502_NOT_MODERATOR: &str = "502 not a moderator for this article\r\n";
+pub const RESP_502_NOT_GROUP_MODERATOR: &str = "502 not a moderator for this group\r\n";
+pub const RESP_502_TLS_ACTIVE: &str = "502 TLS already active or session authenticated\r\n";
+pub const RESP_502_WRONG_LISTENER: &str = "502 command not available on this port\r\n";
+pub const RESP_502_CONN_LIMIT: &str = "502 connection limit exceeded\r\n";
+pub const RESP_502_TOO_MANY_CONNECTIONS: &str = "502 too many connections from your address\r\n";
+pub const RESP_502_ADDRESS_BANNED: &str = "502 access from your address is denied\r\n";
+pub const RESP_502_BANDWIDTH_EXCEEDED: &str = "502 bandwidth limit exceeded\r\n";
+pub const RESP_502_HOST_AUTHENTICATED: &str =
+    "502 virtual host cannot be changed after authentication\r\n";
 pub const RESP_503_NOT_SUPPORTED: &str = "503 feature not supported\r\n";
+pub const RESP_580_TLS_UNAVAILABLE: &str = "580 Can not initiate TLS negotiation\r\n";
 
 // Capability responses
 pub const RESP_101_CAPABILITIES: &str = "101 Capability list follows\r\n";
@@ -96,18 +159,29 @@ pub const RESP_CAP_IMPLEMENTATION: &str =
 pub const RESP_CAP_READER: &str = "READER\r\n";
 pub const RESP_CAP_IHAVE: &str = "IHAVE\r\n";
 pub const RESP_CAP_POST: &str = "POST\r\n";
+pub const RESP_CAP_XPOSTCHECK: &str = "XPOSTCHECK\r\n";
 pub const RESP_CAP_NEWNEWS: &str = "NEWNEWS\r\n";
 pub const RESP_CAP_HDR: &str = "HDR\r\n";
 pub const RESP_CAP_OVER: &str = "OVER MSGID\r\n";
-pub const RESP_CAP_LIST: &str = "LIST ACTIVE NEWSGROUPS ACTIVE.TIMES OVERVIEW.FMT HEADERS\r\n";
+pub const RESP_CAP_LIST: &str = "LIST ACTIVE COUNTS NEWSGROUPS ACTIVE.TIMES OVERVIEW.FMT HEADERS PINNED EXPIRE ACTIVITY SUBSCRIPTIONS\r\n";
+pub const RESP_CAP_LIST_ADMIN: &str = "LIST ACTIVE COUNTS NEWSGROUPS ACTIVE.TIMES OVERVIEW.FMT HEADERS PINNED EXPIRE ACTIVITY SUBSCRIPTIONS PEERS\r\n";
 pub const RESP_CAP_AUTHINFO: &str = "AUTHINFO USER\r\n";
+pub const RESP_CAP_STARTTLS: &str = "STARTTLS\r\n";
 pub const RESP_CAP_STREAMING: &str = "STREAMING\r\n";
+pub const RESP_CAP_XZVER: &str = "XZVER\r\n";
+pub const RESP_CAP_XTHREAD: &str = "XTHREAD\r\n";
+pub const RESP_CAP_XHOST: &str = "XHOST\r\n";
+pub const RESP_CAP_BODY_RANGE: &str = "XBODYRANGE\r\n";
+pub const RESP_CAP_XFEATURE_COMPRESS: &str = "XFEATURE-COMPRESS GZIP TERMINATOR\r\n";
 
 // Help text
 pub const RESP_HELP_TEXT: &str = concat!(
     "CAPABILITIES\r\n",
     "MODE READER\r\n",
     "MODE STREAM\r\n",
+    "MODE CANCEL\r\n",
+    "STARTTLS\r\n",
+    "XHOST\r\n",
     "GROUP\r\n",
     "LIST\r\n",
     "LISTGROUP\r\n",
@@ -117,6 +191,10 @@ pub const RESP_HELP_TEXT: &str = concat!(
     "STAT\r\n",
     "HDR\r\n",
     "OVER\r\n",
+    "XZVER\r\n",
+    "XZHDR\r\n",
+    "XTHREAD\r\n",
+    "XFEATURE COMPRESS GZIP\r\n",
     "NEXT\r\n",
     "LAST\r\n",
     "NEWGROUPS\r\n",
@@ -125,6 +203,8 @@ pub const RESP_HELP_TEXT: &str = concat!(
     "CHECK\r\n",
     "TAKETHIS\r\n",
     "POST\r\n",
+    "XPOSTCHECK\r\n",
+    "XMODERATE\r\n",
     "DATE\r\n",
     "HELP\r\n",
     "QUIT\r\n"
@@ -138,8 +218,234 @@ pub const RESP_MESSAGE_ID: &str = "Message-ID:\r\n";
 pub const RESP_REFERENCES: &str = "References:\r\n";
 pub const RESP_BYTES: &str = ":bytes\r\n";
 pub const RESP_LINES: &str = ":lines\r\n";
+pub const RESP_BINARY: &str = ":binary\r\n";
 pub const RESP_COLON: &str = ":\r\n";
 
+/// Status lines with fixed text, whose text may be localized.
+pub const STATUS_LINES: &[&str] = &[
+    RESP_400_DRAINING,
+    RESP_200_READY,
+    RESP_201_READY_NO_POST,
+    RESP_200_POSTING_ALLOWED,
+    RESP_201_POSTING_PROHIBITED,
+    RESP_203_STREAMING,
+    RESP_205_CLOSING,
+    RESP_224_OVERVIEW,
+    RESP_225_HEADERS,
+    RESP_224_COMPRESSED_OVERVIEW,
+    RESP_225_COMPRESSED_HEADERS,
+    RESP_211_LIST_FOLLOWS,
+    RESP_215_LIST_FOLLOWS,
+    RESP_215_DESCRIPTIONS,
+    RESP_215_SUBSCRIPTIONS,
+    RESP_215_INFO_FOLLOWS,
+    RESP_215_OVERVIEW_FMT,
+    RESP_215_PENDING,
+    RESP_215_PINNED,
+    RESP_215_EXPIRE,
+    RESP_215_ACTIVITY,
+    RESP_215_PEERS,
+    RESP_215_METADATA,
+    RESP_221_HEADER_FOLLOWS,
+    RESP_230_NEWNEWS,
+    RESP_231_NEWGROUPS,
+    RESP_235_TRANSFER_OK,
+    RESP_240_ARTICLE_RECEIVED,
+    RESP_240_ARTICLE_APPROVED,
+    RESP_241_ARTICLE_REJECTED,
+    RESP_242_ARTICLE_PINNED,
+    RESP_243_ARTICLE_UNPINNED,
+    RESP_244_POST_WOULD_BE_ACCEPTED,
+    RESP_245_POST_WOULD_BE_HELD,
+    RESP_281_AUTH_OK,
+    RESP_284_CANCEL_MODE,
+    RESP_289_CANCELLED,
+    RESP_290_FEATURE_ENABLED,
+    RESP_290_HOST_SELECTED,
+    RESP_340_SEND_ARTICLE,
+    RESP_335_SEND_IT,
+    RESP_345_SEND_HEADERS,
+    RESP_381_PASSWORD_REQ,
+    RESP_382_CONTINUE_TLS,
+    RESP_403_BANDWIDTH_EXCEEDED,
+    RESP_403_INTERNAL_FAULT,
+    RESP_411_NO_SUCH_GROUP,
+    RESP_412_NO_GROUP,
+    RESP_420_NO_CURRENT,
+    RESP_421_NO_NEXT,
+    RESP_422_NO_PREV,
+    RESP_423_RANGE_EMPTY,
+    RESP_423_NO_ARTICLE_NUM,
+    RESP_430_NO_ARTICLE,
+    RESP_430_NO_PENDING,
+    RESP_435_NOT_WANTED,
+    RESP_436_TRY_LATER,
+    RESP_437_REJECTED,
+    RESP_437_ARCHIVED,
+    RESP_440_POST_PROHIBITED,
+    RESP_441_POSTING_FAILED,
+    RESP_441_ARCHIVED,
+    RESP_441_TOO_LARGE,
+    RESP_480_AUTH_REQUIRED,
+    RESP_481_AUTH_REJECTED,
+    RESP_481_LOCKED_OUT,
+    RESP_483_SECURE_REQ,
+    RESP_484_NO_ARTICLE,
+    RESP_484_INVALID_ID,
+    RESP_484_CANCEL_FAILED,
+    RESP_500_SYNTAX,
+    RESP_500_UNKNOWN_CMD,
+    RESP_501_SYNTAX,
+    RESP_501_INVALID_ID,
+    RESP_501_INVALID_ARG,
+    RESP_501_INVALID_NUMBER,
+    RESP_501_INVALID_MSGID,
+    RESP_501_INVALID_ARTICLE_RANGE,
+    RESP_501_INVALID_ARTICLE,
+    RESP_501_INVALID_TIME,
+    RESP_501_TOO_MANY_ARGS,
+    RESP_501_INVALID_DATE,
+    RESP_501_FUTURE_DATE,
+    RESP_501_MSGID_REQUIRED,
+    RESP_501_NOT_ENOUGH,
+    RESP_501_UNKNOWN_KEYWORD,
+    RESP_501_UNKNOWN_MODE,
+    RESP_501_INVALID_RANGE,
+    RESP_501_MISSING_MODE,
+    RESP_501_UNKNOWN_HOST,
+    RESP_502_ADMIN_REQUIRED,
+    RESP_502_NOT_MODERATOR,
+    RESP_502_NOT_GROUP_MODERATOR,
+    RESP_502_TLS_ACTIVE,
+    RESP_502_WRONG_LISTENER,
+    RESP_502_CONN_LIMIT,
+    RESP_502_TOO_MANY_CONNECTIONS,
+    RESP_502_ADDRESS_BANNED,
+    RESP_502_BANDWIDTH_EXCEEDED,
+    RESP_502_HOST_AUTHENTICATED,
+    RESP_503_NOT_SUPPORTED,
+    RESP_580_TLS_UNAVAILABLE,
+    RESP_101_CAPABILITIES,
+    RESP_100_HELP_FOLLOWS,
+];
+
+/// Status texts overriding the built-in ones, keyed by response code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseCatalog {
+    texts: HashMap<u16, String>,
+    /// Body of the HELP response, with CRLF line ends and dot-stuffed
+    help: Option<String>,
+}
+
+impl ResponseCatalog {
+    /// Resolve the texts of the locale selected in `cfg`.
+    #[must_use]
+    pub fn from_config(cfg: &ResponsesConfig) -> Self {
+        match &cfg.locale {
+            Some(locale) => Self::for_locale(cfg, locale),
+            None => Self::default(),
+        }
+    }
+
+    /// Resolve the texts of `locale` from the tables of `cfg`.
+    ///
+    /// A regional locale inherits the texts of the locales it belongs to:
+    /// `de-AT` uses the `de-AT` table first and falls back to `de` for codes
+    /// that table does not override. The `help` entry replaces the body of
+    /// the HELP response.
+    #[must_use]
+    pub fn for_locale(cfg: &ResponsesConfig, locale: &str) -> Self {
+        let mut catalog = Self::default();
+        // Apply the most general locale first so specific ones win
+        let parts: Vec<&str> = locale.split('-').collect();
+        for len in 1..=parts.len() {
+            let Some(table) = cfg.locales.get(&parts[..len].join("-")) else {
+                continue;
+            };
+            for (key, text) in table {
+                if key == HELP_KEY {
+                    catalog.help = Some(help_body(text));
+                } else if let Ok(code) = key.parse::<u16>() {
+                    catalog.texts.insert(code, text.clone());
+                }
+            }
+        }
+        catalog
+    }
+
+    /// Whether the catalog overrides no text at all.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty() && self.help.is_none()
+    }
+
+    /// Replace the text of `line` if it is one of the [`STATUS_LINES`] and
+    /// its code has an override.
+    #[must_use]
+    pub fn localize<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let Some(code) = line.get(..3).and_then(|c| c.parse::<u16>().ok()) else {
+            return Cow::Borrowed(line);
+        };
+        match self.texts.get(&code) {
+            Some(text) if STATUS_LINES.contains(&line) => Cow::Owned(format!("{code} {text}\r\n")),
+            _ => Cow::Borrowed(line),
+        }
+    }
+}
+
+/// Key of a locale table holding the HELP text rather than a status text.
+pub const HELP_KEY: &str = "help";
+
+/// Format a configured HELP text as a multi-line response body.
+fn help_body(text: &str) -> String {
+    let mut body = String::new();
+    for line in text.trim_end().lines() {
+        if line.starts_with('.') {
+            body.push('.');
+        }
+        body.push_str(line);
+        body.push_str(RESP_CRLF);
+    }
+    body
+}
+
+tokio::task_local! {
+    static SESSION_CATALOG: Option<Arc<ResponseCatalog>>;
+}
+
+/// Run `session` with `catalog` applied to its status lines. With `None`
+/// the built-in texts apply.
+pub async fn with_catalog<F: std::future::Future>(
+    catalog: Option<Arc<ResponseCatalog>>,
+    session: F,
+) -> F::Output {
+    SESSION_CATALOG.scope(catalog, session).await
+}
+
+/// The catalog of the current session, if it has one.
+fn current_catalog() -> Option<Arc<ResponseCatalog>> {
+    SESSION_CATALOG.try_with(Clone::clone).ok().flatten()
+}
+
+/// Apply the catalog of the session to a status line.
+#[must_use]
+pub fn localize(line: &str) -> Cow<'_, str> {
+    match current_catalog() {
+        Some(catalog) => catalog.localize(line),
+        None => Cow::Borrowed(line),
+    }
+}
+
+/// Body of the HELP response in the catalog of the session, or the built-in
+/// [`RESP_HELP_TEXT`].
+#[must_use]
+pub fn help_text() -> Cow<'static, str> {
+    match current_catalog().and_then(|catalog| catalog.help.clone()) {
+        Some(help) => Cow::Owned(help),
+        None => Cow::Borrowed(RESP_HELP_TEXT),
+    }
+}
+
 /// Format a streaming protocol response (CHECK/TAKETHIS).
 ///
 /// Used for responses that include a message-id, such as:
@@ -149,3 +455,72 @@ pub const RESP_COLON: &str = ":\r\n";
 pub fn streaming_response(code: u16, message_id: &str) -> String {
     format!("{code} {message_id}\r\n")
 }
+
+/// Add a retry hint to a 440, 441 or 481 response, as in
+/// `441 posting failed, retry in 120s`.
+///
+/// Clients may wait this long before trying again; the WebSocket bridge
+/// hands the hint to JSON clients as `retry_after`.
+pub fn with_retry(line: &str, after: std::time::Duration) -> String {
+    format!(
+        "{}, retry in {}s\r\n",
+        localize(line).trim_end(),
+        after.as_secs().max(1)
+    )
+}
+
+/// Add the ways of reaching TLS to a 483 response, as in
+/// `483 Secure connection required; use STARTTLS or TLS on port 563`.
+///
+/// Without either the response is returned as it is.
+pub fn with_tls_hint(line: &str, port: Option<u16>, starttls: bool) -> String {
+    let line = localize(line);
+    let hint = match (starttls, port) {
+        (true, Some(port)) => format!("use STARTTLS or TLS on port {port}"),
+        (false, Some(port)) => format!("use TLS on port {port}"),
+        (true, None) => "use STARTTLS".to_string(),
+        (false, None) => return line.into_owned(),
+    };
+    format!("{}; {hint}\r\n", line.trim_end())
+}
+
+/// Add the argument a 501 response refuses, as in `501 invalid number: x`.
+pub fn with_argument(line: &str, arg: &str) -> String {
+    format!("{}: {arg}\r\n", localize(line).trim_end())
+}
+
+/// 211 line starting the article numbers of LISTGROUP, as in
+/// `211 3 1 3 misc list follows`.
+pub fn listgroup_response(count: u64, low: u64, high: u64, group: &str) -> String {
+    let line = localize(RESP_211_LIST_FOLLOWS);
+    let text = line.trim_end().get(4..).unwrap_or_default();
+    format!("211 {count} {low} {high} {group} {text}\r\n")
+}
+
+/// 224 line of an OVER range cut at `max_over_range`, naming the range the
+/// client asks for next.
+pub fn overview_continues(next: &str) -> String {
+    format!("224 Overview information follows; next range {next}\r\n")
+}
+
+/// `XOVERPAGE` capability line giving the most article numbers one OVER
+/// command covers.
+pub fn over_page_capability(max: u64) -> String {
+    format!("XOVERPAGE {max}\r\n")
+}
+
+/// `XREQUIRETLS` capability line telling plaintext clients that AUTHINFO
+/// needs TLS, followed by the port of the TLS listener if there is one.
+pub fn tls_required_capability(port: Option<u16>) -> String {
+    match port {
+        Some(port) => format!("XREQUIRETLS {port}\r\n"),
+        None => "XREQUIRETLS\r\n".to_string(),
+    }
+}
+
+/// Seconds of the retry hint at the end of a status text, if any.
+#[must_use]
+pub fn retry_after(text: &str) -> Option<u64> {
+    let (_, hint) = text.trim_end().rsplit_once(", retry in ")?;
+    hint.strip_suffix('s')?.parse().ok()
+}
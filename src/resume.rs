@@ -0,0 +1,130 @@
+//! Resumable byte-range body downloads.
+//!
+//! `BODY <article> <first>-[<last>]` returns the bytes `first..=last` of an
+//! article body, or everything from `first` when `last` is omitted. Offsets
+//! count bytes of the stored body, before dot-stuffing. The response line
+//! names the range actually sent, the total body length and a resume token:
+//!
+//! ```text
+//! 222 0 <id@example> 0-65535/1048576 r0123... body range follows
+//! ```
+//!
+//! A client whose connection drops can reconnect and send
+//! `BODY <token> <first>-` to continue from the last byte it received,
+//! without selecting a group again. Tokens stay valid for
+//! [`TOKEN_LIFETIME`] after they were last used.
+//!
+//! The range is sent as a normal multi-line data block. When it ends part
+//! way through a line, a CRLF is added to terminate the block; clients use
+//! the range in the response line to discard it.
+
+use chrono::Duration;
+
+/// How long a resume token stays valid after it was last used.
+pub const TOKEN_LIFETIME: Duration = Duration::days(1);
+
+/// An inclusive byte range of an article body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub first: u64,
+    /// Last byte to send, or `None` for the rest of the body
+    pub last: Option<u64>,
+}
+
+impl ByteRange {
+    /// Number of bytes requested; storage stops at the end of the body.
+    #[must_use]
+    pub fn byte_count(&self) -> u64 {
+        self.last.map_or(u64::MAX, |last| last - self.first + 1)
+    }
+}
+
+/// Parse a `<first>-[<last>]` range argument.
+#[must_use]
+pub fn parse_byte_range(arg: &str) -> Option<ByteRange> {
+    let (first, last) = arg.split_once('-')?;
+    let first = first.parse::<u64>().ok()?;
+    let last = if last.is_empty() {
+        None
+    } else {
+        Some(last.parse::<u64>().ok().filter(|&last| last >= first)?)
+    };
+    Some(ByteRange { first, last })
+}
+
+/// Generate a new resume token.
+#[must_use]
+pub fn new_token() -> String {
+    format!("r{:032x}", rand::random::<u128>())
+}
+
+/// Check whether an article argument is a resume token rather than an
+/// article number or Message-ID.
+#[must_use]
+pub fn is_token(arg: &str) -> bool {
+    arg.len() == 33 && arg.starts_with('r') && arg[1..].bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Encode part of a body as the contents of a multi-line data block.
+///
+/// Lines starting with a dot are dot-stuffed, and a final partial line is
+/// terminated with CRLF.
+#[must_use]
+pub fn encode_chunk(chunk: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(chunk.len() + 8);
+    for line in chunk.split_inclusive(|&b| b == b'\n') {
+        if line.starts_with(b".") {
+            out.push(b'.');
+        }
+        out.extend_from_slice(line);
+    }
+    if !out.ends_with(b"\r\n") && !chunk.is_empty() {
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(
+            parse_byte_range("10-19"),
+            Some(ByteRange {
+                first: 10,
+                last: Some(19)
+            })
+        );
+        assert_eq!(parse_byte_range("10-19").unwrap().byte_count(), 10);
+        assert_eq!(
+            parse_byte_range("5-"),
+            Some(ByteRange {
+                first: 5,
+                last: None
+            })
+        );
+        assert_eq!(parse_byte_range("19-10"), None);
+        assert_eq!(parse_byte_range("-10"), None);
+        assert_eq!(parse_byte_range("10"), None);
+    }
+
+    #[test]
+    fn recognises_tokens() {
+        let token = new_token();
+        assert!(is_token(&token));
+        assert!(!is_token("42"));
+        assert!(!is_token("<id@example>"));
+    }
+
+    #[test]
+    fn encodes_partial_lines() {
+        assert_eq!(encode_chunk(b"a\r\n.b\r\n"), b"a\r\n..b\r\n");
+        assert_eq!(
+            encode_chunk(b".end of line\r\nstart"),
+            b"..end of line\r\nstart\r\n"
+        );
+        assert_eq!(encode_chunk(b""), b"");
+    }
+}
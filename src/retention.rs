@@ -3,7 +3,8 @@ use crate::config::Config;
 use crate::storage::Storage;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use futures_util::StreamExt;
+use futures_util::{StreamExt, TryStreamExt};
+use std::collections::HashSet;
 use tracing::{Instrument, debug, info, info_span, warn};
 
 /// Clean up expired articles based on retention policies.
@@ -12,6 +13,9 @@ use tracing::{Instrument, debug, info, info_span, warn};
 /// 1. Time-based retention: Removes articles older than the configured retention period for each group
 /// 2. Expires header cleanup: Removes articles with an `Expires` header that has passed
 ///
+/// Pinned articles are skipped by both. A replication standby leaves its
+/// articles to the cleanups replicated from its primary.
+///
 /// # Errors
 ///
 /// Returns an error if there are issues accessing the storage or configuration.
@@ -30,6 +34,19 @@ pub async fn cleanup_expired_articles(storage: &dyn Storage, cfg: &Config) -> Re
         let mut groups_processed = 0u64;
         let mut total_deleted = 0u64;
 
+        // A standby's store follows the primary, including its cleanups
+        if cfg.replication.is_standby() {
+            return cleanup_local_records(storage, cfg, now).await;
+        }
+
+        // Expired articles are deleted from all groups, so an article
+        // pinned in any group is kept
+        let pinned: HashSet<String> = storage
+            .list_pinned_articles()
+            .map_ok(|(_, _, id)| id)
+            .try_collect()
+            .await?;
+
         let mut groups = storage.list_groups();
         while let Some(result) = groups.next().await {
             let group = result?;
@@ -38,7 +55,7 @@ pub async fn cleanup_expired_articles(storage: &dyn Storage, cfg: &Config) -> Re
                 warn!(group = group.as_str(), error = %e, "Failed to apply retention policy");
             }
             // Remove articles with expired Expires headers
-            match cleanup_group_by_expires_header(storage, group.as_str(), now).await {
+            match cleanup_group_by_expires_header(storage, group.as_str(), &pinned, now).await {
                 Ok(deleted) => total_deleted += deleted,
                 Err(e) => {
                     warn!(group = group.as_str(), error = %e, "Failed to clean up expired articles")
@@ -52,6 +69,14 @@ pub async fn cleanup_expired_articles(storage: &dyn Storage, cfg: &Config) -> Re
         debug!("Cleaning up orphaned messages");
         storage.purge_orphan_messages().await?;
 
+        // Forget removed and refused articles once they are unlikely to be
+        // offered again
+        if let Some(cutoff) = days_before(now, cfg.history_retention_days) {
+            storage.purge_history_before(cutoff).await?;
+        }
+
+        cleanup_local_records(storage, cfg, now).await?;
+
         tracing::Span::current().record("groups_processed", groups_processed);
         tracing::Span::current().record("articles_deleted", total_deleted);
         tracing::Span::current().record("duration_ms", start.elapsed().as_millis() as u64);
@@ -66,6 +91,44 @@ pub async fn cleanup_expired_articles(storage: &dyn Storage, cfg: &Config) -> Re
     .await
 }
 
+/// Forget records kept by this server alone: lapsed resume tokens, audit
+/// log entries past their retention unless they are kept forever, refused
+/// articles past their retention, and replication log entries standbys no
+/// longer need.
+async fn cleanup_local_records(
+    storage: &dyn Storage,
+    cfg: &Config,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    storage
+        .purge_resume_tokens_before(now - crate::resume::TOKEN_LIFETIME)
+        .await?;
+    if cfg.audit_retention_days > 0
+        && let Some(cutoff) = days_before(now, cfg.audit_retention_days)
+    {
+        storage.purge_audit_before(cutoff).await?;
+    }
+    if cfg.rejected_retention_days > 0
+        && let Some(cutoff) = days_before(now, cfg.rejected_retention_days)
+    {
+        storage.purge_rejected_before(cutoff).await?;
+    }
+    if cfg.replication.log_retention_days > 0
+        && let Some(cutoff) = days_before(now, cfg.replication.log_retention_days)
+    {
+        storage.purge_changes_before(cutoff).await?;
+    }
+    Ok(())
+}
+
+/// The time `days` days before `now`, if it can be represented.
+fn days_before(now: DateTime<Utc>, days: u64) -> Option<DateTime<Utc>> {
+    i64::try_from(days)
+        .ok()
+        .and_then(chrono::Duration::try_days)
+        .and_then(|days| now.checked_sub_signed(days))
+}
+
 /// Apply time-based retention policy for a single group.
 async fn cleanup_group_by_retention(
     storage: &dyn Storage,
@@ -96,9 +159,12 @@ async fn cleanup_group_by_retention(
 }
 
 /// Remove articles with expired Expires headers from a single group.
+///
+/// Articles in `pinned` are skipped.
 async fn cleanup_group_by_expires_header(
     storage: &dyn Storage,
     group: &str,
+    pinned: &HashSet<String>,
     now: DateTime<Utc>,
 ) -> Result<u64> {
     let mut stream = storage.list_article_ids(group);
@@ -106,6 +172,9 @@ async fn cleanup_group_by_expires_header(
     let mut expired_count = 0u64;
     while let Some(result) = stream.next().await {
         let id = result?;
+        if pinned.contains(&id) {
+            continue;
+        }
         match storage.get_article_by_id(&id).await {
             Ok(Some(article)) => {
                 if let Some(expires_time) = parse_expires_header(&article)
@@ -150,7 +219,7 @@ async fn cleanup_group_by_expires_header(
 /// # Returns
 /// * `Some(DateTime<Utc>)` if a valid Expires header is found and parsed successfully
 /// * `None` if no Expires header is found or it cannot be parsed
-fn parse_expires_header(msg: &Message) -> Option<DateTime<Utc>> {
+pub(crate) fn parse_expires_header(msg: &Message) -> Option<DateTime<Utc>> {
     msg.headers
         .iter()
         .find(|(k, _)| k.eq_ignore_ascii_case("Expires"))
@@ -0,0 +1,203 @@
+//! Header rewriting for locally posted articles.
+//!
+//! Articles received through POST are normalised before validation so that
+//! downstream peers see the headers an injecting agent is expected to add
+//! (RFC 5537 Section 3.5):
+//!
+//! - headers only a server may set, such as `Xref` and `NNTP-Posting-Host`,
+//!   are removed;
+//! - missing `Message-ID`, `Date` and `Lines` headers are added;
+//! - the site name is added to the `Path` header, or `Path` is created.
+//!
+//! Articles received from peers only get the site name added to `Path`,
+//! after [`path_names_any`] has checked that they have not passed through
+//! this server before.
+//!
+//! Header values are stored unfolded. [`fold_header`] folds long values
+//! when they are written to the wire.
+
+use crate::Message;
+use crate::parse::{ensure_date, ensure_message_id, escape_message_id_header};
+
+/// Headers that clients may not supply; they are removed from posted
+/// articles.
+pub const FORBIDDEN_HEADERS: &[&str] = &[
+    "Xref",
+    "NNTP-Posting-Host",
+    "NNTP-Posting-Date",
+    "Injection-Info",
+    "X-Trace",
+    "X-Complaints-To",
+];
+
+/// Line length, excluding CRLF, above which headers are folded.
+pub const MAX_HEADER_LINE: usize = 78;
+
+/// Path tail used for articles posted by a local client, or received from
+/// a peer without a `Path`.
+pub const POSTED_PATH_TAIL: &str = "not-for-mail";
+
+/// Normalise the headers of an article posted by a local client.
+pub fn rewrite_posted(msg: &mut Message, site_name: &str) {
+    strip_forbidden(msg);
+    ensure_message_id(msg, site_name);
+    escape_message_id_header(msg);
+    ensure_date(msg);
+    ensure_lines(msg);
+    add_path(msg, site_name, POSTED_PATH_TAIL);
+}
+
+/// Remove every header listed in [`FORBIDDEN_HEADERS`].
+pub fn strip_forbidden(msg: &mut Message) {
+    msg.headers.retain(|(k, _)| {
+        !FORBIDDEN_HEADERS
+            .iter()
+            .any(|forbidden| k.eq_ignore_ascii_case(forbidden))
+    });
+}
+
+/// Add a `Lines` header counting the body lines when none is present.
+pub fn ensure_lines(msg: &mut Message) {
+    if msg
+        .headers
+        .iter()
+        .any(|(k, _)| k.eq_ignore_ascii_case("Lines"))
+    {
+        return;
+    }
+    let lines = msg.body.lines().count();
+    msg.headers.push(("Lines".into(), lines.to_string()));
+}
+
+/// Add `site_name` as the leftmost element of the `Path` header.
+///
+/// A missing `Path` is created as `site_name!tail`. Nothing is added when
+/// `site_name` is already the leftmost element.
+pub fn add_path(msg: &mut Message, site_name: &str, tail: &str) {
+    if let Some((_, path)) = msg
+        .headers
+        .iter_mut()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Path"))
+    {
+        let path_value = path.trim();
+        if path_value.is_empty() {
+            *path = format!("{site_name}!{tail}");
+        } else if path_value.split('!').next() != Some(site_name) {
+            *path = format!("{site_name}!{path_value}");
+        }
+    } else {
+        msg.headers
+            .push(("Path".into(), format!("{site_name}!{tail}")));
+    }
+}
+
+/// Whether the `Path` header of `msg` names any of `sites`.
+///
+/// Path elements are compared without regard to case, as site names are
+/// usually domain names.
+#[must_use]
+pub fn path_names_any(msg: &Message, sites: &[&str]) -> bool {
+    msg.headers
+        .iter()
+        .filter(|(k, _)| k.eq_ignore_ascii_case("Path"))
+        .flat_map(|(_, path)| path.split('!'))
+        .map(str::trim)
+        .any(|element| sites.iter().any(|site| element.eq_ignore_ascii_case(site)))
+}
+
+/// Format a header line, folding it at spaces so that no line is longer
+/// than [`MAX_HEADER_LINE`] where possible.
+///
+/// Continuation lines start with the space they were folded at, so
+/// unfolding restores the original value. The result has no trailing CRLF.
+#[must_use]
+pub fn fold_header(name: &str, value: &str) -> String {
+    let mut out = format!("{name}: {value}");
+    if out.len() <= MAX_HEADER_LINE {
+        return out;
+    }
+
+    out.truncate(name.len() + 1);
+    let mut line_len = out.len();
+    let mut line_has_word = false;
+    for word in value.split(' ') {
+        if line_has_word && !word.is_empty() && line_len + 1 + word.len() > MAX_HEADER_LINE {
+            out.push_str("\r\n");
+            line_len = 0;
+            line_has_word = false;
+        }
+        out.push(' ');
+        out.push_str(word);
+        line_len += 1 + word.len();
+        line_has_word |= !word.is_empty();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smallvec::smallvec;
+
+    fn message(headers: &[(&str, &str)], body: &str) -> Message {
+        Message {
+            headers: headers
+                .iter()
+                .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+                .collect(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn rewrites_posted_headers() {
+        let mut msg = message(
+            &[
+                ("From", "a@test"),
+                ("Xref", "other misc:1"),
+                ("nntp-posting-host", "client.example"),
+            ],
+            "one\r\ntwo\r\n",
+        );
+        rewrite_posted(&mut msg, "site");
+        let names: Vec<&str> = msg.headers.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(names, ["From", "Message-ID", "Date", "Lines", "Path"]);
+        assert_eq!(msg.headers[3].1, "2");
+        assert_eq!(msg.headers[4].1, "site!not-for-mail");
+    }
+
+    #[test]
+    fn prepends_site_to_path_once() {
+        let mut msg = Message {
+            headers: smallvec![("Path".into(), "peer!origin".into())],
+            body: String::new(),
+        };
+        add_path(&mut msg, "site", POSTED_PATH_TAIL);
+        add_path(&mut msg, "site", POSTED_PATH_TAIL);
+        assert_eq!(msg.headers[0].1, "site!peer!origin");
+    }
+
+    #[test]
+    fn finds_sites_anywhere_in_path() {
+        let msg = Message {
+            headers: smallvec![("Path".into(), "peer!Site.Example!origin".into())],
+            body: String::new(),
+        };
+        assert!(path_names_any(&msg, &["other", "site.example"]));
+        assert!(!path_names_any(&msg, &["example", "origin.example"]));
+    }
+
+    #[test]
+    fn folds_long_headers_at_spaces() {
+        let value = "word ".repeat(30);
+        let value = value.trim_end();
+        let folded = fold_header("Subject", value);
+        assert!(folded.split("\r\n").all(|l| l.len() <= MAX_HEADER_LINE));
+        assert!(folded.split("\r\n").skip(1).all(|l| l.starts_with(' ')));
+        assert_eq!(folded.replace("\r\n", ""), format!("Subject: {value}"));
+
+        assert_eq!(fold_header("Subject", "short"), "Subject: short");
+        let long = "x".repeat(100);
+        assert_eq!(fold_header("Subject", &long), format!("Subject: {long}"));
+    }
+}
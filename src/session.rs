@@ -1,7 +1,22 @@
 //! Connection session state management
 
+use crate::config::ListenerRole;
+use crate::limits::ConnectionSlot;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use uuid::Uuid;
 
+/// Compression applied to OVER/XOVER responses after XFEATURE COMPRESS GZIP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverviewCompression {
+    /// Overview data is sent as plain text
+    None,
+    /// Overview lines are zlib-compressed; the terminating dot is sent uncompressed
+    Gzip,
+    /// Overview lines and the terminating dot are zlib-compressed together
+    GzipTerminator,
+}
+
 /// Encapsulated session state for a client connection
 pub struct Session {
     session_id: Uuid,
@@ -10,10 +25,28 @@ pub struct Session {
     authenticated: bool,
     username: Option<String>,
     is_tls: bool,
+    starttls_available: bool,
+    xhost_available: bool,
     in_stream_mode: bool,
+    in_cancel_mode: bool,
     allow_auth_insecure: bool,
+    /// AUTHINFO is refused without TLS and clients are pointed at TLS
+    tls_required: bool,
+    /// Port of the implicit TLS listener named to clients when TLS is required
+    tls_port: Option<u16>,
     allow_anonymous_posting: bool,
     is_admin: bool,
+    overview_compression: OverviewCompression,
+    posting_account: Option<String>,
+    /// Client address, kept only for the audit log
+    peer_ip: Option<IpAddr>,
+    role: ListenerRole,
+    /// The client connects from the address of a configured peer
+    known_peer: bool,
+    resume_tokens: HashMap<String, String>,
+    /// The authenticated user's place in the connection registry, given
+    /// back when the session ends
+    user_slot: Option<ConnectionSlot>,
 }
 
 impl Session {
@@ -25,10 +58,22 @@ impl Session {
             authenticated: false,
             username: None,
             is_tls,
+            starttls_available: false,
+            xhost_available: false,
             in_stream_mode: false,
+            in_cancel_mode: false,
             allow_auth_insecure,
+            tls_required: false,
+            tls_port: None,
             allow_anonymous_posting,
             is_admin: false,
+            overview_compression: OverviewCompression::None,
+            posting_account: None,
+            peer_ip: None,
+            role: ListenerRole::All,
+            known_peer: false,
+            resume_tokens: HashMap::new(),
+            user_slot: None,
         }
     }
 
@@ -85,6 +130,12 @@ impl Session {
         self.is_admin = is_admin;
     }
 
+    /// Keep the authenticated user's connection counted until the session
+    /// ends.
+    pub fn hold_user_slot(&mut self, slot: ConnectionSlot) {
+        self.user_slot = Some(slot);
+    }
+
     /// Mark the session as authenticated (username should already be set).
     pub fn confirm_authentication(&mut self) {
         self.authenticated = true;
@@ -109,13 +160,71 @@ impl Session {
     /// Check if the session can currently post articles.
     /// Requires either authentication or anonymous posting to be enabled.
     pub fn can_post(&self) -> bool {
-        self.authenticated || self.allow_anonymous_posting
+        (self.authenticated || self.allow_anonymous_posting) && self.role.permits("POST")
     }
 
     pub fn is_tls(&self) -> bool {
         self.is_tls
     }
 
+    /// Refuse authentication without TLS regardless of the insecure
+    /// authentication setting, pointing clients at the TLS listener on
+    /// `port` if there is one.
+    pub fn set_tls_required(&mut self, port: Option<u16>) {
+        self.tls_required = true;
+        self.tls_port = port;
+        self.allow_auth_insecure = false;
+    }
+
+    /// Check if clients are to be pointed at TLS: it is required for
+    /// authentication and not yet active.
+    pub fn tls_required(&self) -> bool {
+        self.tls_required && !self.is_tls
+    }
+
+    /// Port of the TLS listener named to clients when TLS is required
+    pub fn tls_port(&self) -> Option<u16> {
+        self.tls_port
+    }
+
+    // STARTTLS
+    /// Mark that this plaintext connection can be upgraded with STARTTLS
+    pub fn set_starttls_available(&mut self, available: bool) {
+        self.starttls_available = available;
+    }
+
+    /// Check if STARTTLS may be issued: TLS is configured, not yet active,
+    /// and the client has not authenticated (RFC 4642 section 2.2.2).
+    pub fn can_starttls(&self) -> bool {
+        self.starttls_available && !self.is_tls && !self.authenticated
+    }
+
+    // Virtual hosts
+    /// Mark that other sites can be selected with XHOST
+    pub fn set_xhost_available(&mut self, available: bool) {
+        self.xhost_available = available;
+    }
+
+    /// Check if XHOST should be advertised: virtual hosts are configured and
+    /// the client has not authenticated.
+    pub fn can_xhost(&self) -> bool {
+        self.xhost_available && !self.authenticated
+    }
+
+    /// Record a completed STARTTLS negotiation.
+    ///
+    /// Everything learned over the plaintext connection is discarded, as
+    /// required by RFC 4642 section 2.2.2; the session id is kept.
+    pub fn start_tls(&mut self) {
+        self.is_tls = true;
+        self.current_group = None;
+        self.current_article = None;
+        self.username = None;
+        self.in_stream_mode = false;
+        self.in_cancel_mode = false;
+        self.overview_compression = OverviewCompression::None;
+    }
+
     // Stream mode
     pub fn enter_stream_mode(&mut self) {
         self.in_stream_mode = true;
@@ -125,6 +234,16 @@ impl Session {
         self.in_stream_mode
     }
 
+    // Cancel mode
+    /// Treat every following line as the Message-ID of an article to cancel
+    pub fn enter_cancel_mode(&mut self) {
+        self.in_cancel_mode = true;
+    }
+
+    pub fn is_cancel_mode(&self) -> bool {
+        self.in_cancel_mode
+    }
+
     // Admin status
     /// Check if the authenticated user is an admin
     pub fn is_admin(&self) -> bool {
@@ -135,4 +254,75 @@ impl Session {
     pub fn set_admin(&mut self, is_admin: bool) {
         self.is_admin = is_admin;
     }
+
+    // Posting account
+    /// Set the posting-account token derived from the client address
+    pub fn set_posting_account(&mut self, token: Option<String>) {
+        self.posting_account = token;
+    }
+
+    /// Get the posting-account token identifying an anonymous poster
+    pub fn posting_account(&self) -> Option<&str> {
+        self.posting_account.as_deref()
+    }
+
+    // Client address
+    /// Set the client address recorded in the audit log
+    pub fn set_peer_ip(&mut self, ip: Option<IpAddr>) {
+        self.peer_ip = ip;
+    }
+
+    /// Get the client address recorded in the audit log
+    pub fn peer_ip(&self) -> Option<IpAddr> {
+        self.peer_ip
+    }
+
+    // Listener role
+    /// Restrict the session to the commands of the accepting listener
+    pub fn set_role(&mut self, role: ListenerRole) {
+        self.role = role;
+    }
+
+    /// Get which commands the accepting listener allows
+    pub fn role(&self) -> ListenerRole {
+        self.role
+    }
+
+    /// Note that the client connects from the address of a configured peer
+    pub fn set_known_peer(&mut self, known: bool) {
+        self.known_peer = known;
+    }
+
+    /// Check if the session may feed articles with IHAVE, CHECK and
+    /// TAKETHIS: peers on transit listeners may, and on listeners of role
+    /// `all` only configured peers and clients who have authenticated.
+    pub fn may_feed(&self) -> bool {
+        match self.role {
+            ListenerRole::Transit => true,
+            ListenerRole::All => self.known_peer || self.authenticated,
+            ListenerRole::Reader => false,
+        }
+    }
+
+    // Resumable downloads
+    /// Remember the resume token issued for a byte-range download of `message_id`
+    pub fn set_resume_token(&mut self, message_id: String, token: String) {
+        self.resume_tokens.insert(message_id, token);
+    }
+
+    /// Get the resume token already issued for `message_id` in this session
+    pub fn resume_token(&self, message_id: &str) -> Option<&str> {
+        self.resume_tokens.get(message_id).map(String::as_str)
+    }
+
+    // Compression
+    /// Set how overview responses are compressed (XFEATURE COMPRESS GZIP)
+    pub fn set_overview_compression(&mut self, mode: OverviewCompression) {
+        self.overview_compression = mode;
+    }
+
+    /// Get how overview responses should be compressed
+    pub fn overview_compression(&self) -> OverviewCompression {
+        self.overview_compression
+    }
 }
@@ -0,0 +1,844 @@
+//! In-memory article and overview caches.
+//!
+//! [`CachedStorage`] wraps another backend and keeps recently fetched
+//! articles in an [`ArticleCache`] keyed by Message-ID, so repeated
+//! `ARTICLE <id>` requests for hot articles avoid the database. It can also
+//! carry an [`OverviewCache`] of rendered `OVER` ranges, so readers opening
+//! a popular group do not each rebuild the same overview. Both caches are
+//! bounded by the total size of their entries and evict the least recently
+//! used entries first; cached articles can also be given a time to live so
+//! that a flash crowd is served from memory while it lasts. Deleting an article removes it from the article
+//! cache and drops the cached overviews of its groups, storing an article
+//! drops those of the groups it was posted to, and operations that may
+//! delete many articles clear both caches.
+
+use super::{
+    ArticleStream, AuditStream, ChangeStream, GroupActivityStream, GroupCountStream,
+    GroupDescriptionStream, GroupWatermarks, OverviewRepair, PendingArticle, PendingArticleStream,
+    PendingCheckgroups, PinnedArticleStream, RejectedArticle, RejectedArticleStream, Storage,
+    StringStream, StringTimestampStream, U64Stream,
+};
+use crate::Message;
+use crate::audit::AuditEntry;
+use crate::handlers::utils::get_header_value;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Hit and size counters of an [`ArticleCache`] or [`OverviewCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Entries dropped because they outlived the time to live
+    pub expirations: u64,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+impl CacheStats {
+    /// Fraction of lookups answered from the cache.
+    #[must_use]
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    size: u64,
+    last_used: u64,
+    stored_at: Instant,
+}
+
+struct Lru<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// Keys ordered by last use, oldest first
+    order: BTreeMap<u64, K>,
+    clock: u64,
+    bytes: u64,
+}
+
+impl<K, V> Default for Lru<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+            bytes: 0,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Lru<K, V> {
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.last_used);
+            self.bytes -= entry.size;
+        }
+    }
+}
+
+/// A least-recently-used map bounded by the total size of its values.
+struct SizedLru<K, V> {
+    capacity_bytes: u64,
+    /// How long an entry is served after it was stored, if not forever
+    ttl: Option<Duration>,
+    lru: Mutex<Lru<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SizedLru<K, V> {
+    fn new(capacity_bytes: u64) -> Self {
+        Self {
+            capacity_bytes,
+            ttl: None,
+            lru: Mutex::new(Lru::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru<K, V>> {
+        self.lru.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Look up a value, marking it as recently used.
+    fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut lru = self.lock();
+        lru.clock += 1;
+        let now = lru.clock;
+        let Some(entry) = lru.entries.get_mut(key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        if self.ttl.is_some_and(|ttl| entry.stored_at.elapsed() >= ttl) {
+            lru.remove(key);
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let previous = std::mem::replace(&mut entry.last_used, now);
+        let value = entry.value.clone();
+        if let Some(key) = lru.order.remove(&previous) {
+            lru.order.insert(now, key);
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(value)
+    }
+
+    /// Add a value taking `size` bytes, evicting the least recently used
+    /// entries to make room. Values larger than the whole cache are not
+    /// stored.
+    fn insert(&self, key: K, value: V, size: u64) {
+        if size > self.capacity_bytes {
+            return;
+        }
+        let mut lru = self.lock();
+        lru.remove(&key);
+        lru.clock += 1;
+        let now = lru.clock;
+        lru.order.insert(now, key.clone());
+        lru.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                last_used: now,
+                stored_at: Instant::now(),
+            },
+        );
+        lru.bytes += size;
+
+        while lru.bytes > self.capacity_bytes {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = lru.entries.remove(&oldest) {
+                lru.bytes -= entry.size;
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn remove<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().remove(key);
+    }
+
+    /// Drop the entries whose key does not satisfy `keep`.
+    fn retain(&self, mut keep: impl FnMut(&K) -> bool) {
+        let mut lru = self.lock();
+        let stale: Vec<K> = lru.entries.keys().filter(|k| !keep(k)).cloned().collect();
+        for key in stale {
+            lru.remove(&key);
+        }
+    }
+
+    fn clear(&self) {
+        let mut lru = self.lock();
+        lru.entries.clear();
+        lru.order.clear();
+        lru.bytes = 0;
+    }
+
+    fn stats(&self) -> CacheStats {
+        let lru = self.lock();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            entries: lru.entries.len(),
+            bytes: lru.bytes,
+        }
+    }
+}
+
+/// A least-recently-used cache of articles bounded by total size.
+pub struct ArticleCache {
+    lru: SizedLru<String, Message>,
+}
+
+impl ArticleCache {
+    /// Create a cache holding at most `capacity_bytes` of articles.
+    #[must_use]
+    pub fn new(capacity_bytes: u64) -> Self {
+        Self {
+            lru: SizedLru::new(capacity_bytes),
+        }
+    }
+
+    /// Serve each article for at most `ttl` after it was cached.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.lru.ttl = Some(ttl);
+        self
+    }
+
+    /// Look up an article, marking it as recently used. An article cached
+    /// longer than the time to live ago is dropped instead.
+    pub fn get(&self, message_id: &str) -> Option<Message> {
+        self.lru.get(message_id)
+    }
+
+    /// Add an article, evicting the least recently used entries to make
+    /// room. Articles larger than the whole cache are not stored.
+    pub fn insert(&self, message_id: &str, message: &Message) {
+        self.lru.insert(
+            message_id.to_string(),
+            message.clone(),
+            message_size(message),
+        );
+    }
+
+    /// Drop a cached article.
+    pub fn invalidate(&self, message_id: &str) {
+        self.lru.remove(message_id);
+    }
+
+    /// Drop every cached article.
+    pub fn clear(&self) {
+        self.lru.clear();
+    }
+
+    /// Current counters.
+    pub fn stats(&self) -> CacheStats {
+        self.lru.stats()
+    }
+}
+
+/// Approximate memory used by an article.
+fn message_size(message: &Message) -> u64 {
+    let headers: usize = message
+        .headers
+        .iter()
+        .map(|(k, v)| k.len() + v.len() + 4)
+        .sum();
+    (headers + message.body.len()) as u64
+}
+
+/// An `OVER` range of a group as of the group's high-water mark.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OverviewKey {
+    pub group: String,
+    pub start: u64,
+    pub end: u64,
+    /// Highest article number in the group when the range was rendered
+    pub high: u64,
+}
+
+/// Rendered overview of a range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedOverview {
+    /// The CRLF-terminated overview lines, without the terminating `.`
+    pub text: String,
+    /// Number of the last article listed, which becomes the current article
+    pub last_article: u64,
+}
+
+/// A least-recently-used cache of rendered `OVER` ranges bounded by total
+/// size.
+pub struct OverviewCache {
+    lru: SizedLru<OverviewKey, Arc<RenderedOverview>>,
+}
+
+impl OverviewCache {
+    /// Create a cache holding at most `capacity_bytes` of overview text.
+    #[must_use]
+    pub fn new(capacity_bytes: u64) -> Self {
+        Self {
+            lru: SizedLru::new(capacity_bytes),
+        }
+    }
+
+    /// Look up a range, marking it as recently used.
+    pub fn get(&self, key: &OverviewKey) -> Option<Arc<RenderedOverview>> {
+        self.lru.get(key)
+    }
+
+    /// Add a rendered range, evicting the least recently used entries to
+    /// make room.
+    pub fn insert(&self, key: OverviewKey, overview: RenderedOverview) {
+        let size = (key.group.len() + overview.text.len()) as u64;
+        self.lru.insert(key, Arc::new(overview), size);
+    }
+
+    /// Drop the cached ranges of `group`.
+    pub fn invalidate_group(&self, group: &str) {
+        self.lru.retain(|key| key.group != group);
+    }
+
+    /// Drop every cached range.
+    pub fn clear(&self) {
+        self.lru.clear();
+    }
+
+    /// Current counters.
+    pub fn stats(&self) -> CacheStats {
+        self.lru.stats()
+    }
+}
+
+/// A storage backend that serves `get_article_by_id` from an
+/// [`ArticleCache`], offers an [`OverviewCache`] to the `OVER` handler and
+/// delegates everything else.
+pub struct CachedStorage {
+    inner: Arc<dyn Storage>,
+    articles: Option<Arc<ArticleCache>>,
+    overviews: Option<Arc<OverviewCache>>,
+}
+
+impl CachedStorage {
+    /// Wrap `inner`, caching articles in `cache`.
+    pub fn new(inner: Arc<dyn Storage>, cache: Arc<ArticleCache>) -> Self {
+        Self {
+            inner,
+            articles: Some(cache),
+            overviews: None,
+        }
+    }
+
+    /// Wrap `inner` without an article cache.
+    pub fn uncached(inner: Arc<dyn Storage>) -> Self {
+        Self {
+            inner,
+            articles: None,
+            overviews: None,
+        }
+    }
+
+    /// Also cache rendered overview ranges in `cache`.
+    #[must_use]
+    pub fn with_overview_cache(mut self, cache: Arc<OverviewCache>) -> Self {
+        self.overviews = Some(cache);
+        self
+    }
+
+    /// Remove `message_id` with `op`, dropping what is cached of it.
+    async fn removing(
+        &self,
+        message_id: &str,
+        op: impl Future<Output = Result<()>> + Send,
+    ) -> Result<()> {
+        let groups = match &self.overviews {
+            Some(_) => self.inner.get_article_numbers(message_id).await?,
+            None => Vec::new(),
+        };
+        op.await?;
+        if let Some(articles) = &self.articles {
+            articles.invalidate(message_id);
+        }
+        if let Some(overviews) = &self.overviews {
+            for (group, _) in groups {
+                overviews.invalidate_group(&group);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the cached overviews of the groups `article` was posted to.
+    fn invalidate_overviews_of(&self, article: &Message) {
+        let Some(overviews) = &self.overviews else {
+            return;
+        };
+        if let Some(groups) = get_header_value(article, "Newsgroups") {
+            for group in groups.split(',') {
+                overviews.invalidate_group(group.trim());
+            }
+        }
+    }
+
+    fn clear(&self) {
+        if let Some(articles) = &self.articles {
+            articles.clear();
+        }
+        if let Some(overviews) = &self.overviews {
+            overviews.clear();
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for CachedStorage {
+    async fn store_article(&self, article: &Message) -> Result<()> {
+        self.inner.store_article(article).await?;
+        self.invalidate_overviews_of(article);
+        Ok(())
+    }
+
+    async fn store_article_in(&self, article: &Message, groups: &[String]) -> Result<()> {
+        self.inner.store_article_in(article, groups).await?;
+        self.invalidate_overviews_of(article);
+        Ok(())
+    }
+
+    async fn store_articles(&self, articles: &[Message]) -> Result<()> {
+        self.inner.store_articles(articles).await?;
+        for article in articles {
+            self.invalidate_overviews_of(article);
+        }
+        Ok(())
+    }
+
+    async fn get_article_by_number(&self, group: &str, number: u64) -> Result<Option<Message>> {
+        self.inner.get_article_by_number(group, number).await
+    }
+
+    async fn get_article_by_id(&self, message_id: &str) -> Result<Option<Message>> {
+        let Some(cache) = &self.articles else {
+            return self.inner.get_article_by_id(message_id).await;
+        };
+        if let Some(message) = cache.get(message_id) {
+            return Ok(Some(message));
+        }
+        let article = self.inner.get_article_by_id(message_id).await?;
+        if let Some(message) = &article {
+            cache.insert(message_id, message);
+        }
+        Ok(article)
+    }
+
+    fn get_articles_by_ids<'a>(&'a self, message_ids: &'a [String]) -> ArticleStream<'a> {
+        self.inner.get_articles_by_ids(message_ids)
+    }
+
+    async fn get_overview_range(&self, group: &str, start: u64, end: u64) -> Result<Vec<String>> {
+        self.inner.get_overview_range(group, start, end).await
+    }
+
+    async fn add_group(&self, group: &str, moderated: bool) -> Result<()> {
+        self.inner.add_group(group, moderated).await
+    }
+
+    async fn set_group_moderated(&self, group: &str, moderated: bool) -> Result<()> {
+        self.inner.set_group_moderated(group, moderated).await
+    }
+
+    async fn set_group_archived(&self, group: &str, archived: bool) -> Result<()> {
+        self.inner.set_group_archived(group, archived).await
+    }
+
+    async fn remove_group(&self, group: &str) -> Result<()> {
+        self.inner.remove_group(group).await?;
+        self.clear();
+        Ok(())
+    }
+
+    async fn remove_groups_by_pattern(&self, pattern: &str) -> Result<()> {
+        self.inner.remove_groups_by_pattern(pattern).await?;
+        self.clear();
+        Ok(())
+    }
+
+    fn list_groups(&self) -> StringStream<'_> {
+        self.inner.list_groups()
+    }
+
+    fn list_groups_since(&self, since: chrono::DateTime<chrono::Utc>) -> StringStream<'_> {
+        self.inner.list_groups_since(since)
+    }
+
+    fn list_groups_with_times(&self) -> StringTimestampStream<'_> {
+        self.inner.list_groups_with_times()
+    }
+
+    fn list_article_numbers(&self, group: &str) -> U64Stream<'_> {
+        self.inner.list_article_numbers(group)
+    }
+
+    fn list_article_ids(&self, group: &str) -> StringStream<'_> {
+        self.inner.list_article_ids(group)
+    }
+
+    fn list_article_ids_since(
+        &self,
+        group: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> StringStream<'_> {
+        self.inner.list_article_ids_since(group, since)
+    }
+
+    async fn purge_group_before(
+        &self,
+        group: &str,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        self.inner.purge_group_before(group, before).await?;
+        if let Some(overviews) = &self.overviews {
+            overviews.invalidate_group(group);
+        }
+        Ok(())
+    }
+
+    async fn purge_orphan_messages(&self) -> Result<()> {
+        self.inner.purge_orphan_messages().await?;
+        self.clear();
+        Ok(())
+    }
+
+    async fn database_size(&self) -> Result<u64> {
+        self.inner.database_size().await
+    }
+
+    async fn compact(&self) -> Result<()> {
+        self.inner.compact().await
+    }
+
+    async fn rebuild_overview(&self, group: &str) -> Result<OverviewRepair> {
+        let repair = self.inner.rebuild_overview(group).await?;
+        if let Some(overviews) = &self.overviews {
+            overviews.invalidate_group(group);
+        }
+        Ok(repair)
+    }
+
+    async fn renumber_group(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        let watermarks = self.inner.renumber_group(group).await?;
+        if let Some(overviews) = &self.overviews {
+            overviews.invalidate_group(group);
+        }
+        Ok(watermarks)
+    }
+
+    async fn list_replies(&self, group: &str, message_id: &str) -> Result<Vec<(u64, String)>> {
+        self.inner.list_replies(group, message_id).await
+    }
+
+    async fn get_message_size(&self, message_id: &str) -> Result<Option<u64>> {
+        self.inner.get_message_size(message_id).await
+    }
+
+    async fn get_article_numbers(&self, message_id: &str) -> Result<Vec<(String, u64)>> {
+        self.inner.get_article_numbers(message_id).await
+    }
+
+    async fn get_article_numbers_batch(
+        &self,
+        message_ids: &[String],
+    ) -> Result<std::collections::HashMap<String, Vec<(String, u64)>>> {
+        self.inner.get_article_numbers_batch(message_ids).await
+    }
+
+    async fn get_article_arrivals(
+        &self,
+        message_id: &str,
+    ) -> Result<Vec<(String, chrono::DateTime<chrono::Utc>, bool)>> {
+        self.inner.get_article_arrivals(message_id).await
+    }
+
+    async fn get_body_range(
+        &self,
+        message_id: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Option<(u64, Vec<u8>)>> {
+        self.inner.get_body_range(message_id, offset, len).await
+    }
+
+    async fn set_article_pinned(
+        &self,
+        group: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> Result<bool> {
+        self.inner
+            .set_article_pinned(group, message_id, pinned)
+            .await
+    }
+
+    fn list_pinned_articles(&self) -> PinnedArticleStream<'_> {
+        self.inner.list_pinned_articles()
+    }
+
+    async fn delete_article_by_id(&self, message_id: &str) -> Result<()> {
+        self.removing(message_id, self.inner.delete_article_by_id(message_id))
+            .await
+    }
+
+    async fn withdraw_article(&self, message_id: &str) -> Result<()> {
+        self.removing(message_id, self.inner.withdraw_article(message_id))
+            .await
+    }
+
+    async fn supersede_article(&self, old_id: &str, article: &Message) -> Result<()> {
+        let groups = match &self.overviews {
+            Some(_) => self.inner.get_article_numbers(old_id).await?,
+            None => Vec::new(),
+        };
+        self.inner.supersede_article(old_id, article).await?;
+        if let Some(articles) = &self.articles {
+            articles.invalidate(old_id);
+        }
+        if let Some(overviews) = &self.overviews {
+            for (group, _) in groups {
+                overviews.invalidate_group(&group);
+            }
+        }
+        self.invalidate_overviews_of(article);
+        Ok(())
+    }
+
+    async fn is_group_moderated(&self, group: &str) -> Result<bool> {
+        self.inner.is_group_moderated(group).await
+    }
+
+    async fn is_group_archived(&self, group: &str) -> Result<bool> {
+        self.inner.is_group_archived(group).await
+    }
+
+    fn list_archived_groups(&self) -> StringStream<'_> {
+        self.inner.list_archived_groups()
+    }
+
+    async fn group_exists(&self, group: &str) -> Result<bool> {
+        self.inner.group_exists(group).await
+    }
+
+    async fn get_group_watermarks(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        self.inner.get_group_watermarks(group).await
+    }
+
+    fn list_groups_with_counts(&self) -> GroupCountStream<'_> {
+        self.inner.list_groups_with_counts()
+    }
+
+    fn list_group_activity(&self) -> GroupActivityStream<'_> {
+        self.inner.list_group_activity()
+    }
+
+    async fn add_group_with_description(
+        &self,
+        group: &str,
+        moderated: bool,
+        description: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_group_with_description(group, moderated, description)
+            .await
+    }
+
+    async fn set_group_description(&self, group: &str, description: &str) -> Result<bool> {
+        self.inner.set_group_description(group, description).await
+    }
+
+    fn list_groups_with_descriptions(&self) -> GroupDescriptionStream<'_> {
+        self.inner.list_groups_with_descriptions()
+    }
+
+    async fn add_pending_article(&self, article: &Message) -> Result<u64> {
+        self.inner.add_pending_article(article).await
+    }
+
+    async fn get_pending_article(&self, id: u64) -> Result<Option<PendingArticle>> {
+        self.inner.get_pending_article(id).await
+    }
+
+    fn list_pending_articles(&self) -> PendingArticleStream<'_> {
+        self.inner.list_pending_articles()
+    }
+
+    async fn remove_pending_article(&self, id: u64) -> Result<()> {
+        self.inner.remove_pending_article(id).await
+    }
+
+    async fn add_pending_checkgroups(&self, signer: &str, diff: &str) -> Result<u64> {
+        self.inner.add_pending_checkgroups(signer, diff).await
+    }
+
+    async fn list_pending_checkgroups(&self) -> Result<Vec<PendingCheckgroups>> {
+        self.inner.list_pending_checkgroups().await
+    }
+
+    async fn remove_pending_checkgroups(&self, id: u64) -> Result<bool> {
+        self.inner.remove_pending_checkgroups(id).await
+    }
+
+    async fn add_rejected_article(&self, article: &Message, reason: &str) -> Result<u64> {
+        self.inner.add_rejected_article(article, reason).await
+    }
+
+    async fn get_rejected_article(&self, id: u64) -> Result<Option<RejectedArticle>> {
+        self.inner.get_rejected_article(id).await
+    }
+
+    fn list_rejected_articles(&self) -> RejectedArticleStream<'_> {
+        self.inner.list_rejected_articles()
+    }
+
+    async fn remove_rejected_article(&self, id: u64) -> Result<bool> {
+        self.inner.remove_rejected_article(id).await
+    }
+
+    async fn purge_rejected_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_rejected_before(before).await
+    }
+
+    async fn add_resume_token(&self, token: &str, message_id: &str) -> Result<()> {
+        self.inner.add_resume_token(token, message_id).await
+    }
+
+    async fn get_resume_token(
+        &self,
+        token: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<String>> {
+        self.inner.get_resume_token(token, since).await
+    }
+
+    async fn purge_resume_tokens_before(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        self.inner.purge_resume_tokens_before(before).await
+    }
+
+    async fn remember_message_id(&self, message_id: &str) -> Result<()> {
+        self.inner.remember_message_id(message_id).await
+    }
+
+    async fn in_history(&self, message_id: &str) -> Result<bool> {
+        self.inner.in_history(message_id).await
+    }
+
+    async fn purge_history_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_history_before(before).await
+    }
+
+    async fn record_audit(&self, entry: &AuditEntry) -> Result<()> {
+        self.inner.record_audit(entry).await
+    }
+
+    fn list_audit_since(&self, since: chrono::DateTime<chrono::Utc>) -> AuditStream<'_> {
+        self.inner.list_audit_since(since)
+    }
+
+    async fn purge_audit_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_audit_before(before).await
+    }
+
+    async fn record_change(&self, seq: u64, change: &str) -> Result<()> {
+        self.inner.record_change(seq, change).await
+    }
+
+    async fn append_changes(&self, changes: &[String]) -> Result<()> {
+        self.inner.append_changes(changes).await
+    }
+
+    async fn last_change_seq(&self) -> Result<u64> {
+        self.inner.last_change_seq().await
+    }
+
+    fn list_changes_since(&self, after: u64) -> ChangeStream<'_> {
+        self.inner.list_changes_since(after)
+    }
+
+    async fn purge_changes_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_changes_before(before).await
+    }
+
+    async fn snapshot_to(&self, path: &std::path::Path) -> Result<()> {
+        self.inner.snapshot_to(path).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+
+    fn set_records_changes(&self, records: bool) {
+        self.inner.set_records_changes(records);
+    }
+
+    fn database_count(&self) -> usize {
+        self.inner.database_count()
+    }
+
+    fn database_of(&self, group: &str) -> usize {
+        self.inner.database_of(group)
+    }
+
+    fn set_compression(&self, compression: crate::config::ArticleCompression) {
+        self.inner.set_compression(compression);
+    }
+
+    fn set_overview_headers(&self, headers: Vec<String>) {
+        // Ranges rendered with the old fields must not be served again
+        if let Some(cache) = &self.overviews {
+            cache.clear();
+        }
+        self.inner.set_overview_headers(headers);
+    }
+
+    fn overview_cache(&self) -> Option<&OverviewCache> {
+        self.overviews
+            .as_deref()
+            .or_else(|| self.inner.overview_cache())
+    }
+}
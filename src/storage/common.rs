@@ -1,7 +1,15 @@
 use crate::Message;
+use crate::config::ArticleCompression;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 
+/// zstd level of compressed article bodies
+const BODY_COMPRESSION_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+/// Message-IDs bound in one statement by batched lookups, well within the
+/// parameter limits of SQLite and PostgreSQL
+pub const BATCH_LOOKUP_IDS: usize = 500;
+
 /// Serializable wrapper for message headers.
 #[derive(Serialize, Deserialize)]
 pub struct Headers(pub SmallVec<[(String, String); 8]>);
@@ -35,11 +43,69 @@ pub fn parse_newsgroups_from_message(article: &Message) -> SmallVec<[String; 4]>
         .unwrap_or_default()
 }
 
-/// Common logic for reconstructing a Message from database row data
-pub fn reconstruct_message_from_row(headers_str: &str, body: &str) -> anyhow::Result<Message> {
+/// Common logic for reconstructing a Message from database row data.
+/// `compressed` is the zstd-compressed body of a message stored with an
+/// empty `body`.
+pub fn reconstruct_message_from_row(
+    headers_str: &str,
+    body: String,
+    compressed: Option<Vec<u8>>,
+) -> anyhow::Result<Message> {
     let Headers(headers) = serde_json::from_str(headers_str)?;
     Ok(Message {
         headers,
-        body: body.to_string(),
+        body: decode_body(body, compressed)?,
     })
 }
+
+/// The body of `article` compressed with zstd, if it is posted to a group
+/// whose bodies are compressed and compressing makes it smaller. The
+/// message is then stored with an empty `body` and the compressed copy.
+pub fn compress_body(article: &Message, compression: &ArticleCompression) -> Option<Vec<u8>> {
+    if !parse_newsgroups_from_message(article)
+        .iter()
+        .any(|group| compression.compresses(group))
+    {
+        return None;
+    }
+    let compressed = zstd::bulk::compress(article.body.as_bytes(), BODY_COMPRESSION_LEVEL).ok()?;
+    (compressed.len() < article.body.len()).then_some(compressed)
+}
+
+/// The text of a stored body, decompressing `compressed` when the body was
+/// stored compressed.
+pub fn decode_body(body: String, compressed: Option<Vec<u8>>) -> anyhow::Result<String> {
+    match compressed {
+        Some(bytes) => Ok(String::from_utf8(zstd::stream::decode_all(
+            bytes.as_slice(),
+        )?)?),
+        None => Ok(body),
+    }
+}
+
+/// Total length of `body` and the `len` bytes from `offset`, as returned by
+/// `get_body_range`, for bodies that cannot be sliced by the database.
+pub fn body_range(body: &str, offset: u64, len: u64) -> (u64, Vec<u8>) {
+    let bytes = body.as_bytes();
+    let start = usize::try_from(offset)
+        .unwrap_or(usize::MAX)
+        .min(bytes.len());
+    let end = start
+        .saturating_add(usize::try_from(len).unwrap_or(usize::MAX))
+        .min(bytes.len());
+    (bytes.len() as u64, bytes[start..end].to_vec())
+}
+
+/// Convert a byte offset and length into the 1-based start and length taken
+/// by SQL substring functions.
+///
+/// Both are clamped to 1 GiB so that `start + len` fits in a 32-bit
+/// integer; SQLite misbehaves when the sum overflows. Bodies are never that
+/// large.
+pub fn sql_substring_bounds(offset: u64, len: u64) -> (i64, i64) {
+    let limit = 1 << 30;
+    (
+        i64::try_from(offset.min(limit)).unwrap_or(0) + 1,
+        i64::try_from(len.min(limit)).unwrap_or(0),
+    )
+}
@@ -0,0 +1,9 @@
+-- Message-IDs of articles that were removed or refused, so offers of them
+-- are declined until they are forgotten
+
+CREATE TABLE IF NOT EXISTS history (
+    message_id TEXT PRIMARY KEY,
+    recorded_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_history_recorded_at ON history(recorded_at);
@@ -0,0 +1,4 @@
+-- Article bodies stored compressed with zstd. A message whose body_zstd is
+-- set keeps an empty body
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS body_zstd BYTEA;
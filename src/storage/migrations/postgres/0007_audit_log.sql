@@ -0,0 +1,13 @@
+-- Administrative and posting actions, with who took them and from where
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    recorded_at BIGINT NOT NULL,
+    actor TEXT,
+    source TEXT,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    detail TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_recorded_at ON audit_log(recorded_at);
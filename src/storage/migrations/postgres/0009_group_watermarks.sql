@@ -0,0 +1,9 @@
+-- Per-group article number watermarks, kept so that numbers are never
+-- reused and the low watermark never falls after articles expire
+
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS high_water BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS low_water BIGINT NOT NULL DEFAULT 0;
+
+UPDATE groups SET
+    high_water = COALESCE((SELECT MAX(number) FROM group_articles WHERE group_name = groups.name), 0),
+    low_water = COALESCE((SELECT MIN(number) FROM group_articles WHERE group_name = groups.name), 0);
@@ -0,0 +1,13 @@
+-- Message-IDs named in the References and In-Reply-To headers of each
+-- article, indexed so that the replies to an article can be found without
+-- reading the overview of the whole group. Articles stored before this
+-- migration are indexed by `renews admin rebuild-overview`.
+
+CREATE TABLE IF NOT EXISTS article_references (
+    group_name TEXT NOT NULL,
+    article_number BIGINT NOT NULL,
+    referenced_id TEXT NOT NULL,
+    PRIMARY KEY(group_name, article_number, referenced_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_article_references_referenced ON article_references(group_name, referenced_id);
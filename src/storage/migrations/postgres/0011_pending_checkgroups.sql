@@ -0,0 +1,9 @@
+-- Changes asked for by checkgroups messages, held until an administrator
+-- applies them with `renews admin apply-checkgroups`
+
+CREATE TABLE IF NOT EXISTS pending_checkgroups (
+    id BIGSERIAL PRIMARY KEY,
+    signer TEXT NOT NULL,
+    diff TEXT NOT NULL,
+    received_at BIGINT NOT NULL
+);
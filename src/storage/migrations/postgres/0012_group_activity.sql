@@ -0,0 +1,9 @@
+-- Per-group activity: when the last article arrived and how many articles
+-- the group has received, including those since expired
+
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS last_post_at BIGINT;
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS post_count BIGINT NOT NULL DEFAULT 0;
+
+UPDATE groups SET
+    last_post_at = (SELECT MAX(inserted_at) FROM group_articles WHERE group_name = groups.name),
+    post_count = (SELECT COUNT(*) FROM group_articles WHERE group_name = groups.name);
@@ -0,0 +1,3 @@
+-- Archived groups stay readable but take no new articles
+
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,10 @@
+-- Moderation queue for unapproved posts to moderated groups
+
+CREATE TABLE IF NOT EXISTS pending_articles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id TEXT NOT NULL UNIQUE,
+    headers TEXT NOT NULL,
+    body TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    submitted_at INTEGER NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Tokens that let a client resume a byte-range body download on a new connection
+
+CREATE TABLE IF NOT EXISTS resume_tokens (
+    token TEXT PRIMARY KEY,
+    message_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Per-group article flags such as pinned articles kept by retention
+
+ALTER TABLE group_articles ADD COLUMN flags INTEGER NOT NULL DEFAULT 0;
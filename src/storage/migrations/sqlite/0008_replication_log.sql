@@ -0,0 +1,10 @@
+-- Changes to the article store, in the order they were made, followed by
+-- standby servers
+
+CREATE TABLE IF NOT EXISTS replication_log (
+    seq INTEGER PRIMARY KEY,
+    recorded_at INTEGER NOT NULL,
+    change TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_replication_log_recorded_at ON replication_log(recorded_at);
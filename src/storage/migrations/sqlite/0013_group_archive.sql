@@ -0,0 +1,3 @@
+-- Archived groups stay readable but take no new articles
+
+ALTER TABLE groups ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
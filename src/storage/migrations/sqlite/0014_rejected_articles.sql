@@ -0,0 +1,13 @@
+-- Articles refused by filters, kept for `rejected_retention_days` so that
+-- an administrator can inspect them and inject the wrongly refused ones
+
+CREATE TABLE IF NOT EXISTS rejected_articles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id TEXT NOT NULL,
+    headers TEXT NOT NULL,
+    body TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    rejected_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_rejected_articles_rejected_at ON rejected_articles(rejected_at);
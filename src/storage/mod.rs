@@ -1,4 +1,5 @@
 use crate::Message;
+use crate::audit::AuditEntry;
 use anyhow::Result;
 use async_trait::async_trait;
 use futures_core::Stream;
@@ -11,12 +12,113 @@ type U64Stream<'a> = Pin<Box<dyn Stream<Item = Result<u64>> + Send + 'a>>;
 type StringTimestampStream<'a> = Pin<Box<dyn Stream<Item = Result<(String, i64)>> + Send + 'a>>;
 type ArticleStream<'a> = Pin<Box<dyn Stream<Item = Result<(String, Message)>> + Send + 'a>>;
 type GroupDescriptionStream<'a> = Pin<Box<dyn Stream<Item = Result<(String, String)>> + Send + 'a>>;
+type PendingArticleStream<'a> = Pin<Box<dyn Stream<Item = Result<PendingArticle>> + Send + 'a>>;
+type RejectedArticleStream<'a> = Pin<Box<dyn Stream<Item = Result<RejectedArticle>> + Send + 'a>>;
+type PinnedArticleStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<(String, u64, String)>> + Send + 'a>>;
+type AuditStream<'a> = Pin<Box<dyn Stream<Item = Result<AuditEntry>> + Send + 'a>>;
+type ChangeStream<'a> = Pin<Box<dyn Stream<Item = Result<(u64, String)>> + Send + 'a>>;
+type GroupCountStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<(String, GroupWatermarks, bool)>> + Send + 'a>>;
+type GroupActivityStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<(String, GroupActivity)>> + Send + 'a>>;
+
+/// Flag bit of an article pinned in a group. Retention never removes pinned
+/// articles.
+pub const ARTICLE_FLAG_PINNED: i64 = 1;
+
+/// An unapproved article held in the moderation queue.
+#[derive(Debug, Clone)]
+pub struct PendingArticle {
+    /// Queue identifier used to approve or reject the article
+    pub id: u64,
+    /// The article as submitted
+    pub message: Message,
+    /// Size of the article body in bytes
+    pub size: u64,
+    /// Unix timestamp of when the article was queued
+    pub submitted_at: i64,
+}
+
+/// Changes asked for by a checkgroups message, held for an administrator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingCheckgroups {
+    /// Identifier used to apply or discard the changes
+    pub id: u64,
+    /// Sender of the checkgroups message
+    pub signer: String,
+    /// The changes, in the form of [`crate::control::CheckgroupsDiff`]
+    pub diff: String,
+    /// Unix timestamp of when the message arrived
+    pub received_at: i64,
+}
+
+/// An article refused by a filter, kept for inspection.
+#[derive(Debug, Clone)]
+pub struct RejectedArticle {
+    /// Identifier used to inspect, inject or delete the article
+    pub id: u64,
+    /// The article as received
+    pub message: Message,
+    /// Why the article was refused
+    pub reason: String,
+    /// Unix timestamp of when the article was refused
+    pub rejected_at: i64,
+}
+
+/// Outcome of verifying and rebuilding the overview of a group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OverviewRepair {
+    /// Articles whose overview entry was checked
+    pub checked: u64,
+    /// Overview entries that were missing or out of date and were rewritten
+    pub rebuilt: u64,
+    /// Overview entries removed because no article has their number
+    pub stale_removed: u64,
+    /// Article numbers removed because their message no longer exists
+    pub dangling_removed: u64,
+}
+
+/// Article count and watermarks of a group.
+///
+/// The high watermark is the last number given to an article in the group
+/// and never falls, so numbers are never reused. The low watermark is the
+/// lowest number still stored, or one above the high watermark once every
+/// article has expired; it never falls either. Both are 0 for a group that
+/// never had an article. Only [`Storage::renumber_group`] lowers them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupWatermarks {
+    /// Articles stored in the group
+    pub count: u64,
+    /// Low watermark
+    pub low: u64,
+    /// High watermark
+    pub high: u64,
+}
+
+/// How recently and how much a group has been posted to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupActivity {
+    /// Unix timestamp of the last article stored in the group, or `None` if
+    /// it never had one
+    pub last_post: Option<i64>,
+    /// Articles the group has received, including those since expired
+    pub posts: u64,
+}
 
 #[async_trait]
 pub trait Storage: Send + Sync {
     /// Store `article` and associate it with all groups specified in the Newsgroups header
     async fn store_article(&self, article: &Message) -> Result<()>;
 
+    /// Store `article` and number it in `groups` only, whatever its
+    /// Newsgroups header says
+    async fn store_article_in(&self, article: &Message, groups: &[String]) -> Result<()>;
+
+    /// Store several articles in one transaction, as `store_article` would
+    /// one at a time. Either every article is stored or none is.
+    async fn store_articles(&self, articles: &[Message]) -> Result<()>;
+
     /// Retrieve an article by group name and article number
     async fn get_article_by_number(&self, group: &str, number: u64) -> Result<Option<Message>>;
 
@@ -37,6 +139,10 @@ pub trait Storage: Send + Sync {
     /// Set moderation status for an existing newsgroup.
     async fn set_group_moderated(&self, group: &str, moderated: bool) -> Result<()>;
 
+    /// Archive an existing newsgroup, keeping it readable but closed to new
+    /// articles, or open it again.
+    async fn set_group_archived(&self, group: &str, archived: bool) -> Result<()>;
+
     /// Remove a newsgroup from the server's list
     async fn remove_group(&self, group: &str) -> Result<()>;
 
@@ -52,6 +158,19 @@ pub trait Storage: Send + Sync {
     /// Retrieve all newsgroups with their creation timestamps
     fn list_groups_with_times(&self) -> StringTimestampStream<'_>;
 
+    /// Article count and watermarks of `group`, or `None` if the group does
+    /// not exist
+    async fn get_group_watermarks(&self, group: &str) -> Result<Option<GroupWatermarks>>;
+
+    /// Every group with its article count and watermarks and whether it is
+    /// moderated, sorted by name. Reads all groups in one query rather than
+    /// one per group.
+    fn list_groups_with_counts(&self) -> GroupCountStream<'_>;
+
+    /// Every group with its activity, most recently posted to first and
+    /// groups never posted to last, ties broken by name.
+    fn list_group_activity(&self) -> GroupActivityStream<'_>;
+
     /// List all article numbers for a group
     fn list_article_numbers(&self, group: &str) -> U64Stream<'_>;
 
@@ -65,7 +184,8 @@ pub trait Storage: Send + Sync {
         since: chrono::DateTime<chrono::Utc>,
     ) -> StringStream<'_>;
 
-    /// Remove articles in `group` that were inserted before `before`
+    /// Remove articles in `group` that were inserted before `before`, except
+    /// pinned articles
     async fn purge_group_before(
         &self,
         group: &str,
@@ -75,15 +195,92 @@ pub trait Storage: Send + Sync {
     /// Delete any messages no longer referenced by any group
     async fn purge_orphan_messages(&self) -> Result<()>;
 
+    /// Space taken by the database, in bytes
+    async fn database_size(&self) -> Result<u64>;
+
+    /// Give back the space left by deleted rows and refresh the statistics
+    /// the query planner works from
+    async fn compact(&self) -> Result<()>;
+
+    /// Regenerate the overview of `group` from the stored message headers.
+    ///
+    /// Article numbers pointing at missing messages and overview rows without
+    /// an article are removed; missing or out-of-date overview rows are
+    /// rewritten.
+    async fn rebuild_overview(&self, group: &str) -> Result<OverviewRepair>;
+
+    /// Number the articles of `group` from 1 in their current order, moving
+    /// their overview and reference entries along, and set the watermarks
+    /// to the new numbers. Returns the new watermarks, or `None` if the
+    /// group does not exist.
+    async fn renumber_group(&self, group: &str) -> Result<Option<GroupWatermarks>>;
+
+    /// List the articles of `group` whose `References` or `In-Reply-To`
+    /// header names `message_id` as `(number, message-id)`, ordered by
+    /// number
+    async fn list_replies(&self, group: &str, message_id: &str) -> Result<Vec<(u64, String)>>;
+
     /// Retrieve the stored size in bytes of a message by its Message-ID
     async fn get_message_size(&self, message_id: &str) -> Result<Option<u64>>;
 
+    /// List the groups an article is stored in with its number in each,
+    /// ordered by group name
+    async fn get_article_numbers(&self, message_id: &str) -> Result<Vec<(String, u64)>>;
+
+    /// [`Storage::get_article_numbers`] of each of `message_ids` that is
+    /// stored in a group, looked up together
+    async fn get_article_numbers_batch(
+        &self,
+        message_ids: &[String],
+    ) -> Result<std::collections::HashMap<String, Vec<(String, u64)>>>;
+
+    /// List the groups an article is stored in with the time it arrived in
+    /// each and whether it is pinned there, ordered by group name
+    async fn get_article_arrivals(
+        &self,
+        message_id: &str,
+    ) -> Result<Vec<(String, chrono::DateTime<chrono::Utc>, bool)>>;
+
+    /// Read up to `len` bytes of an article body starting at byte `offset`
+    /// without loading the rest of the article. Returns the total body length
+    /// in bytes together with the requested bytes.
+    async fn get_body_range(
+        &self,
+        message_id: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Option<(u64, Vec<u8>)>>;
+
+    /// Pin or unpin the article `message_id` in `group`. Returns `false` if
+    /// the article is not in `group`.
+    async fn set_article_pinned(&self, group: &str, message_id: &str, pinned: bool)
+    -> Result<bool>;
+
+    /// List pinned articles as `(group, number, message-id)`, ordered by
+    /// group and number
+    fn list_pinned_articles(&self) -> PinnedArticleStream<'_>;
+
     /// Delete an article by Message-ID from all groups
     async fn delete_article_by_id(&self, message_id: &str) -> Result<()>;
 
+    /// Remove an article stored by a change that then failed, without
+    /// recording it in the history, so that it may be offered again
+    async fn withdraw_article(&self, message_id: &str) -> Result<()>;
+
+    /// Replace the article `old_id` by `article` in one transaction: the old
+    /// article is deleted and recorded in the history, and `article` is
+    /// stored unless it already is.
+    async fn supersede_article(&self, old_id: &str, article: &Message) -> Result<()>;
+
     /// Check if a group is moderated.
     async fn is_group_moderated(&self, group: &str) -> Result<bool>;
 
+    /// Check if a group is archived.
+    async fn is_group_archived(&self, group: &str) -> Result<bool>;
+
+    /// Names of the archived groups, sorted.
+    fn list_archived_groups(&self) -> StringStream<'_>;
+
     /// Check if a group exists.
     async fn group_exists(&self, group: &str) -> Result<bool>;
 
@@ -96,21 +293,167 @@ pub trait Storage: Send + Sync {
         description: &str,
     ) -> Result<()>;
 
+    /// Set the description of an existing newsgroup. Returns `false` if the
+    /// group does not exist.
+    async fn set_group_description(&self, group: &str, description: &str) -> Result<bool>;
+
     /// Retrieve all newsgroups with their descriptions
     fn list_groups_with_descriptions(&self) -> GroupDescriptionStream<'_>;
+
+    /// Hold an unapproved article in the moderation queue, returning its
+    /// queue id. Re-submitting the same Message-ID returns the existing id.
+    async fn add_pending_article(&self, article: &Message) -> Result<u64>;
+
+    /// Retrieve an article from the moderation queue by id
+    async fn get_pending_article(&self, id: u64) -> Result<Option<PendingArticle>>;
+
+    /// List all articles in the moderation queue, oldest first
+    fn list_pending_articles(&self) -> PendingArticleStream<'_>;
+
+    /// Remove an article from the moderation queue
+    async fn remove_pending_article(&self, id: u64) -> Result<()>;
+
+    /// Hold the changes a checkgroups message asks for until an
+    /// administrator applies them, returning their id
+    async fn add_pending_checkgroups(&self, signer: &str, diff: &str) -> Result<u64>;
+
+    /// List the held checkgroups changes, oldest first
+    async fn list_pending_checkgroups(&self) -> Result<Vec<PendingCheckgroups>>;
+
+    /// Discard held checkgroups changes, returning whether they existed
+    async fn remove_pending_checkgroups(&self, id: u64) -> Result<bool>;
+
+    /// Keep an article refused by a filter together with the reason,
+    /// returning its id
+    async fn add_rejected_article(&self, article: &Message, reason: &str) -> Result<u64>;
+
+    /// Retrieve a kept refused article by id
+    async fn get_rejected_article(&self, id: u64) -> Result<Option<RejectedArticle>>;
+
+    /// List the kept refused articles, oldest first
+    fn list_rejected_articles(&self) -> RejectedArticleStream<'_>;
+
+    /// Forget a kept refused article, returning whether it existed
+    async fn remove_rejected_article(&self, id: u64) -> Result<bool>;
+
+    /// Forget refused articles kept since before `before`
+    async fn purge_rejected_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()>;
+
+    /// Record a token that lets a client resume downloading `message_id`
+    async fn add_resume_token(&self, token: &str, message_id: &str) -> Result<()>;
+
+    /// Look up the Message-ID of a resume token issued after `since`
+    async fn get_resume_token(
+        &self,
+        token: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<String>>;
+
+    /// Delete resume tokens issued before `before`
+    async fn purge_resume_tokens_before(&self, before: chrono::DateTime<chrono::Utc>)
+    -> Result<()>;
+
+    /// Record `message_id` in the history, so that offers of it are
+    /// declined. Deleting an article records it as well.
+    async fn remember_message_id(&self, message_id: &str) -> Result<()>;
+
+    /// Whether `message_id` is in the history
+    async fn in_history(&self, message_id: &str) -> Result<bool>;
+
+    /// Forget history entries recorded before `before`
+    async fn purge_history_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()>;
+
+    /// Add `entry` to the audit log
+    async fn record_audit(&self, entry: &AuditEntry) -> Result<()>;
+
+    /// List audit log entries recorded at or after `since`, oldest first
+    fn list_audit_since(&self, since: chrono::DateTime<chrono::Utc>) -> AuditStream<'_>;
+
+    /// Forget audit log entries recorded before `before`
+    async fn purge_audit_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()>;
+
+    /// Add change number `seq`, serialized as `change`, to the replication
+    /// log followed by standby servers
+    async fn record_change(&self, seq: u64, change: &str) -> Result<()>;
+
+    /// Add `changes` to the replication log, numbered after its newest entry
+    async fn append_changes(&self, changes: &[String]) -> Result<()>;
+
+    /// Sequence number of the newest change in the replication log, or 0 if
+    /// it is empty
+    async fn last_change_seq(&self) -> Result<u64>;
+
+    /// List the changes of the replication log after `after` as
+    /// `(seq, change)`, oldest first
+    fn list_changes_since(&self, after: u64) -> ChangeStream<'_>;
+
+    /// Forget replication log entries recorded before `before`. The newest
+    /// entry is always kept so that numbering carries on.
+    async fn purge_changes_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()>;
+
+    /// Write a consistent copy of the database to the new file `path`
+    /// while the database stays in use. Fails if `path` already exists or
+    /// the backend cannot take snapshots itself.
+    async fn snapshot_to(&self, path: &std::path::Path) -> Result<()>;
+
+    /// Check that the database can be reached
+    async fn ping(&self) -> Result<()>;
+
+    /// Store the bodies of articles posted to the groups chosen by
+    /// `compression` compressed from now on. Articles already stored keep
+    /// their form; both forms are read transparently.
+    fn set_compression(&self, _compression: crate::config::ArticleCompression) {}
+
+    /// Write the replication log entries of changes made through
+    /// [`replicated::ReplicatedStorage`] in the transactions making them.
+    /// Shards other than the main database, whose log is not served, do not.
+    fn set_records_changes(&self, _records: bool) {}
+
+    /// Number of databases the storage spreads its groups over.
+    fn database_count(&self) -> usize {
+        1
+    }
+
+    /// Position of the database holding `group` among the
+    /// [`Storage::database_count`] databases; the first holds state that
+    /// belongs to no group.
+    fn database_of(&self, _group: &str) -> usize {
+        0
+    }
+
+    /// Add the `headers` as extra fields to the overview rows written from
+    /// now on. Rows already stored are changed by `rebuild_overview`.
+    fn set_overview_headers(&self, _headers: Vec<String>) {}
+
+    /// Cache of rendered `OVER` ranges, if the storage keeps one
+    fn overview_cache(&self) -> Option<&cache::OverviewCache> {
+        None
+    }
 }
 
 pub type DynStorage = Arc<dyn Storage>;
 
+pub mod cache;
 pub mod common;
 #[cfg(feature = "postgres")]
 pub mod postgres;
+pub mod replicated;
+pub mod sharded;
 pub mod sqlite;
 
 /// Create a storage backend from a connection URI.
 pub async fn open(uri: &str) -> Result<DynStorage> {
+    open_with_config(uri, &crate::config::SqliteConfig::default()).await
+}
+
+/// Create a storage backend from a connection URI, tuning SQLite databases
+/// with `sqlite_cfg`.
+pub async fn open_with_config(
+    uri: &str,
+    sqlite_cfg: &crate::config::SqliteConfig,
+) -> Result<DynStorage> {
     if uri.starts_with("sqlite:") {
-        sqlite::SqliteStorage::new(uri)
+        sqlite::SqliteStorage::with_config(uri, sqlite_cfg)
             .await
             .map(|s| Arc::new(s) as DynStorage)
             .map_err(|e| {
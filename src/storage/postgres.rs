@@ -1,23 +1,53 @@
+use super::replicated;
 use super::{
-    ArticleStream, GroupDescriptionStream, Message, Storage, StringStream, StringTimestampStream,
+    ARTICLE_FLAG_PINNED, ArticleStream, AuditStream, ChangeStream, GroupActivity,
+    GroupActivityStream, GroupCountStream, GroupDescriptionStream, GroupWatermarks, Message,
+    OverviewRepair, PendingArticle, PendingArticleStream, PendingCheckgroups, PinnedArticleStream,
+    RejectedArticle, RejectedArticleStream, Storage, StringStream, StringTimestampStream,
     U64Stream,
-    common::{Headers, extract_message_id, parse_newsgroups_from_message},
+    common::{
+        BATCH_LOOKUP_IDS, Headers, body_range, compress_body, decode_body, extract_message_id,
+        parse_newsgroups_from_message, sql_substring_bounds,
+    },
 };
+use crate::audit::AuditEntry;
+use crate::config::ArticleCompression;
 use anyhow::Result;
 use async_stream::stream;
 use async_trait::async_trait;
 use futures_util::StreamExt;
+use sqlx::migrate::Migrator;
 use sqlx::{
     PgPool, Row,
     postgres::{PgConnectOptions, PgPoolOptions},
 };
+use std::fmt::Write as _;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 
 #[derive(Clone)]
 pub struct PostgresStorage {
     pool: PgPool,
+    /// Groups whose article bodies are stored compressed
+    compression: Arc<RwLock<Arc<ArticleCompression>>>,
+    /// Headers added to overview rows after the standard fields
+    overview_headers: Arc<RwLock<Arc<Vec<String>>>>,
+    /// Whether replicated changes are logged in the transactions making them
+    records_changes: Arc<AtomicBool>,
 }
 
+/// Schema migrations of the PostgreSQL storage database.
+pub(crate) static MIGRATOR: Migrator = sqlx::migrate!("src/storage/migrations/postgres");
+
+/// Record the Message-IDs of messages no longer in any group in the
+/// history, before they are deleted.
+const REMEMBER_ORPHANS: &str = "INSERT INTO history (message_id, recorded_at) SELECT message_id, $1 FROM messages WHERE message_id NOT IN (SELECT DISTINCT message_id FROM group_articles) ON CONFLICT (message_id) DO UPDATE SET recorded_at = EXCLUDED.recorded_at";
+
+/// Raise the low watermark of a group to its lowest remaining article, or
+/// above its high watermark once it has none.
+const RAISE_LOW_WATER: &str = "UPDATE groups SET low_water = GREATEST(low_water, COALESCE((SELECT MIN(number) FROM group_articles WHERE group_name = groups.name), high_water + 1)) WHERE name = $1";
+
 impl PostgresStorage {
     #[tracing::instrument(skip_all)]
     /// Create a new Postgres storage backend.
@@ -70,88 +100,634 @@ Please verify:
             })?;
 
         // Run migrations using sqlx's built-in migration system
-        sqlx::migrate!("src/storage/migrations/postgres")
-            .run(&pool)
-            .await
-            .map_err(|e| {
-                anyhow::anyhow!(
-                    "Failed to run storage migrations for PostgreSQL database '{}': {}",
-                    uri,
-                    e
-                )
-            })?;
+        MIGRATOR.run(&pool).await.map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to run storage migrations for PostgreSQL database '{}': {}",
+                uri,
+                crate::migrations::describe_failure(&e)
+            )
+        })?;
 
         tracing::info!("PostgreSQL storage database ready at '{}'", uri);
 
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            compression: Arc::default(),
+            overview_headers: Arc::default(),
+            records_changes: Arc::new(AtomicBool::new(true)),
+        })
+    }
+
+    fn compression(&self) -> Arc<ArticleCompression> {
+        self.compression
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn overview_headers(&self) -> Arc<Vec<String>> {
+        self.overview_headers
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Write the replication log entries of the change being made within
+    /// `tx`, which makes it.
+    async fn record_changes(&self, tx: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+        if self.records_changes.load(Ordering::Relaxed) {
+            append_changes(tx, &replicated::take_changes()).await?;
+        }
+        Ok(())
     }
 }
 
-#[async_trait]
-impl Storage for PostgresStorage {
-    #[tracing::instrument(skip_all)]
-    async fn store_article(&self, article: &Message) -> Result<()> {
-        let msg_id =
-            extract_message_id(article).ok_or_else(|| anyhow::anyhow!("missing Message-ID"))?;
-        let headers = serde_json::to_string(&Headers(article.headers.clone()))?;
+/// Add `changes` to the replication log within `tx`, each numbered after
+/// the newest entry.
+///
+/// The log is locked against other writers until `tx` ends, so that
+/// entries are committed in the order of their numbers and a number is
+/// never skipped, as standbys require; a sequence would give neither.
+async fn append_changes(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    changes: &[String],
+) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    sqlx::query("LOCK TABLE replication_log IN EXCLUSIVE MODE")
+        .execute(&mut **tx)
+        .await?;
+    let now = chrono::Utc::now().timestamp();
+    for change in changes {
+        sqlx::query(
+            "INSERT INTO replication_log (seq, recorded_at, change) SELECT COALESCE(MAX(seq), 0) + 1, $1, $2 FROM replication_log",
+        )
+        .bind(now)
+        .bind(change)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Write `article`, its group numbers and overview rows within `tx`
+async fn insert_article(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    article: &Message,
+    groups: &[String],
+    now: i64,
+    compression: &ArticleCompression,
+    overview_headers: &[String],
+) -> Result<()> {
+    let msg_id =
+        extract_message_id(article).ok_or_else(|| anyhow::anyhow!("missing Message-ID"))?;
+    let headers = serde_json::to_string(&Headers(article.headers.clone()))?;
+
+    // Store the message once, with its body compressed if it is posted to
+    // a compressed group
+    let compressed = compress_body(article, compression);
+    sqlx::query(
+        "INSERT INTO messages (message_id, headers, body, body_zstd, size) VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+    )
+    .bind(&msg_id)
+    .bind(&headers)
+    .bind(if compressed.is_some() { "" } else { article.body.as_str() })
+    .bind(&compressed)
+    .bind(i64::try_from(crate::overview::article_size(article)).unwrap_or(i64::MAX))
+    .execute(&mut **tx)
+    .await?;
+
+    // Associate with each group and create overview data
+    lock_groups(tx, groups).await?;
+    let references = crate::thread::references(article);
+    for group in groups {
+        let next = next_number(tx, group, now).await?;
 
-        // Store the message once
         sqlx::query(
-            "INSERT INTO messages (message_id, headers, body, size) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+            "INSERT INTO group_articles (group_name, number, message_id, inserted_at) VALUES ($1, $2, $3, $4)",
         )
+        .bind(group)
+        .bind(next)
         .bind(&msg_id)
-        .bind(&headers)
-        .bind(&article.body)
-        .bind(i64::try_from(article.body.len()).unwrap_or(i64::MAX))
-        .execute(&self.pool)
+        .bind(now)
+        .execute(&mut **tx)
         .await?;
 
-        // Extract newsgroups from headers
-        let newsgroups = parse_newsgroups_from_message(article);
+        let overview_data = crate::overview::format_overview_line(
+            u64::try_from(next).unwrap_or(0),
+            article,
+            overview_headers,
+        );
 
-        // Associate with each group and create overview data
-        let now = chrono::Utc::now().timestamp();
-        for group in newsgroups {
-            let next: i64 = sqlx::query_scalar(
-                "SELECT COALESCE(MAX(number),0)+1 FROM group_articles WHERE group_name = $1",
-            )
-            .bind(&group)
-            .fetch_one(&self.pool)
+        sqlx::query(
+            "INSERT INTO overview (group_name, article_number, overview_data) VALUES ($1, $2, $3) ON CONFLICT (group_name, article_number) DO UPDATE SET overview_data = EXCLUDED.overview_data",
+        )
+        .bind(group)
+        .bind(next)
+        .bind(&overview_data)
+        .execute(&mut **tx)
+        .await?;
+
+        index_references(tx, group, next, &references).await?;
+    }
+
+    Ok(())
+}
+
+/// Lock the rows of `groups` until `tx` ends, in name order so that
+/// transactions storing articles in the same groups cannot deadlock
+async fn lock_groups(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    groups: &[String],
+) -> Result<()> {
+    sqlx::query("SELECT name FROM groups WHERE name = ANY($1) ORDER BY name FOR UPDATE")
+        .bind(groups)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Take the next article number of `group` within `tx`, recording a post
+/// at `now` in the group's activity.
+///
+/// The high watermark is the group's counter: raising it locks the group's
+/// row until `tx` ends, so concurrent writers are handed distinct numbers,
+/// and numbers of expired articles are not handed out again. Articles
+/// stored in a group without a row are numbered after the highest stored,
+/// under a lock on the group name.
+async fn next_number(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    group: &str,
+    now: i64,
+) -> Result<i64> {
+    let next: Option<i64> = sqlx::query_scalar(
+        "UPDATE groups SET high_water = high_water + 1, low_water = CASE WHEN low_water = 0 OR low_water > high_water THEN high_water + 1 ELSE low_water END, last_post_at = $2, post_count = post_count + 1 WHERE name = $1 RETURNING high_water",
+    )
+    .bind(group)
+    .bind(now)
+    .fetch_optional(&mut **tx)
+    .await?;
+    if let Some(next) = next {
+        return Ok(next);
+    }
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(group)
+        .execute(&mut **tx)
+        .await?;
+    Ok(sqlx::query_scalar(
+        "SELECT COALESCE(MAX(number), 0) + 1 FROM group_articles WHERE group_name = $1",
+    )
+    .bind(group)
+    .fetch_one(&mut **tx)
+    .await?)
+}
+
+/// Record the Message-IDs `references` named by article `number` of `group`
+/// in place of any recorded before
+async fn index_references(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    group: &str,
+    number: i64,
+    references: &[String],
+) -> Result<()> {
+    sqlx::query("DELETE FROM article_references WHERE group_name = $1 AND article_number = $2")
+        .bind(group)
+        .bind(number)
+        .execute(&mut **tx)
+        .await?;
+    for referenced in references {
+        sqlx::query(
+            "INSERT INTO article_references (group_name, article_number, referenced_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        )
+        .bind(group)
+        .bind(number)
+        .bind(referenced)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Delete `groups` and their articles within `tx`, recording messages left
+/// in no group in the history
+async fn remove_groups(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    groups: &[String],
+) -> Result<()> {
+    for group in groups {
+        sqlx::query("DELETE FROM group_articles WHERE group_name = $1")
+            .bind(group)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query("DELETE FROM groups WHERE name = $1")
+            .bind(group)
+            .execute(&mut **tx)
             .await?;
+    }
+    remove_orphans(tx).await
+}
 
-            sqlx::query(
-                "INSERT INTO group_articles (group_name, number, message_id, inserted_at) VALUES ($1, $2, $3, $4)",
-            )
-            .bind(&group)
-            .bind(next)
-            .bind(&msg_id)
-            .bind(now)
-            .execute(&self.pool)
+/// Delete messages no longer in any group within `tx`, recording them in
+/// the history
+async fn remove_orphans(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    sqlx::query(REMEMBER_ORPHANS)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(
+        "DELETE FROM messages WHERE message_id NOT IN (SELECT DISTINCT message_id FROM group_articles)",
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Delete `message_id` from every group within `tx`, recording it in the
+/// history so that it is not accepted again
+async fn remove_article(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    message_id: &str,
+    now: i64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO history (message_id, recorded_at) VALUES ($1, $2) ON CONFLICT (message_id) DO UPDATE SET recorded_at = EXCLUDED.recorded_at",
+    )
+    .bind(message_id)
+    .bind(now)
+    .execute(&mut **tx)
+    .await?;
+    unlink_article(tx, message_id).await
+}
+
+/// Delete `message_id` from every group within `tx` without recording it
+/// in the history
+async fn unlink_article(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    message_id: &str,
+) -> Result<()> {
+    let groups: Vec<String> =
+        sqlx::query_scalar("DELETE FROM group_articles WHERE message_id = $1 RETURNING group_name")
+            .bind(message_id)
+            .fetch_all(&mut **tx)
+            .await?;
+    for group in &groups {
+        sqlx::query(RAISE_LOW_WATER)
+            .bind(group)
+            .execute(&mut **tx)
             .await?;
+    }
+    sqlx::query(
+        "DELETE FROM messages WHERE message_id = $1 AND NOT EXISTS (SELECT 1 FROM group_articles WHERE message_id = $1)",
+    )
+    .bind(message_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Batches of at least this many articles are loaded with `COPY` into
+/// staging tables rather than with one `INSERT` per row
+const COPY_MIN_ARTICLES: usize = 16;
+
+/// Write `articles`, their group numbers, overview rows and references
+/// within `tx` as [`insert_article`] would one at a time, streaming the rows
+/// to the server with `COPY`.
+///
+/// Numbers are reserved a block per group, and the rows that may clash with
+/// rows already stored go through temporary staging tables, dropped at the
+/// end of `tx`, so that they are merged as the single-row inserts do:
+/// messages already stored are kept, and overview rows and references left
+/// behind at the new numbers are replaced.
+async fn copy_articles(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    articles: &[Message],
+    now: i64,
+    compression: &ArticleCompression,
+    overview_headers: &[String],
+) -> Result<()> {
+    let mut placed = Vec::with_capacity(articles.len());
+    let mut counts: std::collections::BTreeMap<String, i64> = std::collections::BTreeMap::new();
+    for article in articles {
+        let msg_id =
+            extract_message_id(article).ok_or_else(|| anyhow::anyhow!("missing Message-ID"))?;
+        let groups = parse_newsgroups_from_message(article);
+        for group in &groups {
+            *counts.entry(group.clone()).or_default() += 1;
+        }
+        placed.push((article, msg_id, groups));
+    }
+    let mut next = NumberBlocks::default();
+    for (group, count) in counts {
+        let first = reserve_numbers(tx, &group, count, now).await?;
+        next.reserve(group, first);
+    }
 
-            // Generate and store overview data
-            let overview_data = {
-                use crate::overview::generate_overview_line;
-                generate_overview_line(self, next as u64, article).await?
+    let mut messages = String::new();
+    let mut numbers = String::new();
+    let mut overview = String::new();
+    let mut references = String::new();
+    let mut seen = std::collections::HashSet::with_capacity(placed.len());
+    for (article, msg_id, groups) in &placed {
+        // The first copy of a message wins, as with ON CONFLICT DO NOTHING
+        if seen.insert(msg_id.as_str()) {
+            let headers = serde_json::to_string(&Headers(article.headers.clone()))?;
+            let compressed = compress_body(article, compression);
+            let body = if compressed.is_some() {
+                ""
+            } else {
+                article.body.as_str()
             };
+            let size = crate::overview::article_size(article).to_string();
+            let zstd = compressed.map(|bytes| {
+                let mut hex = String::with_capacity(2 + bytes.len() * 2);
+                hex.push_str("\\x");
+                for byte in bytes {
+                    let _ = write!(hex, "{byte:02x}");
+                }
+                hex
+            });
+            copy_row(
+                &mut messages,
+                &[
+                    Some(msg_id),
+                    Some(&headers),
+                    Some(body),
+                    zstd.as_deref(),
+                    Some(&size),
+                ],
+            );
+        }
 
-            sqlx::query(
-                "INSERT INTO overview (group_name, article_number, overview_data) VALUES ($1, $2, $3) ON CONFLICT (group_name, article_number) DO UPDATE SET overview_data = EXCLUDED.overview_data",
+        let referenced = crate::thread::references(article);
+        for group in groups {
+            let current = next.take(group)?;
+            let number_text = current.to_string();
+            copy_row(
+                &mut numbers,
+                &[
+                    Some(group),
+                    Some(&number_text),
+                    Some(msg_id),
+                    Some(&now.to_string()),
+                ],
+            );
+            let line = crate::overview::format_overview_line(
+                u64::try_from(current).unwrap_or(0),
+                article,
+                overview_headers,
+            );
+            copy_row(
+                &mut overview,
+                &[Some(group), Some(&number_text), Some(&line)],
+            );
+            for id in &referenced {
+                copy_row(
+                    &mut references,
+                    &[Some(group), Some(&number_text), Some(id)],
+                );
+            }
+        }
+    }
+
+    for (staging, table) in [
+        ("import_messages", "messages"),
+        ("import_overview", "overview"),
+        ("import_references", "article_references"),
+    ] {
+        sqlx::query(&format!(
+            "CREATE TEMPORARY TABLE {staging} (LIKE {table}) ON COMMIT DROP"
+        ))
+        .execute(&mut **tx)
+        .await?;
+    }
+    copy_in(
+        tx,
+        "COPY import_messages (message_id, headers, body, body_zstd, size) FROM STDIN",
+        &messages,
+    )
+    .await?;
+    sqlx::query(
+        "INSERT INTO messages (message_id, headers, body, body_zstd, size) \
+         SELECT message_id, headers, body, body_zstd, size FROM import_messages \
+         ON CONFLICT DO NOTHING",
+    )
+    .execute(&mut **tx)
+    .await?;
+    copy_in(
+        tx,
+        "COPY group_articles (group_name, number, message_id, inserted_at) FROM STDIN",
+        &numbers,
+    )
+    .await?;
+    copy_in(
+        tx,
+        "COPY import_overview (group_name, article_number, overview_data) FROM STDIN",
+        &overview,
+    )
+    .await?;
+    sqlx::query(
+        "INSERT INTO overview (group_name, article_number, overview_data) \
+         SELECT group_name, article_number, overview_data FROM import_overview \
+         ON CONFLICT (group_name, article_number) DO UPDATE SET overview_data = EXCLUDED.overview_data",
+    )
+    .execute(&mut **tx)
+    .await?;
+    copy_in(
+        tx,
+        "COPY import_references (group_name, article_number, referenced_id) FROM STDIN",
+        &references,
+    )
+    .await?;
+    sqlx::query(
+        "DELETE FROM article_references r USING import_overview o \
+         WHERE r.group_name = o.group_name AND r.article_number = o.article_number",
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO article_references (group_name, article_number, referenced_id) \
+         SELECT group_name, article_number, referenced_id FROM import_references \
+         ON CONFLICT DO NOTHING",
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Take `count` consecutive article numbers of `group` within `tx`, as
+/// [`next_number`] takes one, and return the first.
+async fn reserve_numbers(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    group: &str,
+    count: i64,
+    now: i64,
+) -> Result<i64> {
+    let high: Option<i64> = sqlx::query_scalar(
+        "UPDATE groups SET high_water = high_water + $3, low_water = CASE WHEN low_water = 0 OR low_water > high_water THEN high_water + 1 ELSE low_water END, last_post_at = $2, post_count = post_count + $3 WHERE name = $1 RETURNING high_water",
+    )
+    .bind(group)
+    .bind(now)
+    .bind(count)
+    .fetch_optional(&mut **tx)
+    .await?;
+    if let Some(high) = high {
+        return Ok(block_start(high, count));
+    }
+    next_number(tx, group, now).await
+}
+
+/// First number of a block of `count` numbers ending at `high`, the high
+/// watermark once the block is reserved
+fn block_start(high: i64, count: i64) -> i64 {
+    high - count + 1
+}
+
+/// Article numbers handed out in order from the blocks reserved per group
+#[derive(Default)]
+struct NumberBlocks {
+    next: std::collections::HashMap<String, i64>,
+}
+
+impl NumberBlocks {
+    /// Hand out the numbers of `group` from `first` on
+    fn reserve(&mut self, group: String, first: i64) {
+        self.next.insert(group, first);
+    }
+
+    /// The next number of `group`
+    fn take(&mut self, group: &str) -> Result<i64> {
+        let number = self
+            .next
+            .get_mut(group)
+            .ok_or_else(|| anyhow::anyhow!("no numbers reserved for {group}"))?;
+        let current = *number;
+        *number += 1;
+        Ok(current)
+    }
+}
+
+/// Stream `rows`, in the text format of `COPY`, to the `COPY ... FROM STDIN`
+/// `statement` within `tx`
+async fn copy_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    statement: &str,
+    rows: &str,
+) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let mut copy = (**tx).copy_in_raw(statement).await?;
+    if let Err(e) = copy.send(rows.as_bytes()).await {
+        copy.abort(e.to_string()).await?;
+        return Err(e.into());
+    }
+    copy.finish().await?;
+    Ok(())
+}
+
+/// Append a row of `fields` to `out` in the text format of `COPY`, `None`
+/// standing for NULL. NUL characters, which PostgreSQL text cannot hold, are
+/// passed on for the server to refuse, as it refuses them in an `INSERT`.
+fn copy_row(out: &mut String, fields: &[Option<&str>]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push('\t');
+        }
+        let Some(field) = field else {
+            out.push_str("\\N");
+            continue;
+        };
+        for c in field.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                _ => out.push(c),
+            }
+        }
+    }
+    out.push('\n');
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    #[tracing::instrument(skip_all)]
+    async fn store_article(&self, article: &Message) -> Result<()> {
+        self.store_article_in(article, &parse_newsgroups_from_message(article))
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn store_article_in(&self, article: &Message, groups: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let compression = self.compression();
+        let overview_headers = self.overview_headers();
+        insert_article(
+            &mut tx,
+            article,
+            groups,
+            replicated::now().timestamp(),
+            &compression,
+            &overview_headers,
+        )
+        .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn store_articles(&self, articles: &[Message]) -> Result<()> {
+        let compression = self.compression();
+        let overview_headers = self.overview_headers();
+        let mut tx = self.pool.begin().await?;
+        let now = replicated::now().timestamp();
+        let groups: std::collections::BTreeSet<String> = articles
+            .iter()
+            .flat_map(parse_newsgroups_from_message)
+            .collect();
+        let groups: Vec<String> = groups.into_iter().collect();
+        lock_groups(&mut tx, &groups).await?;
+        if articles.len() >= COPY_MIN_ARTICLES {
+            match copy_articles(&mut tx, articles, now, &compression, &overview_headers).await {
+                Ok(()) => {
+                    self.record_changes(&mut tx).await?;
+                    tx.commit().await?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    // The server may refuse COPY or temporary tables to the
+                    // role; the batch is written row by row then, which
+                    // fails as well if an article itself is at fault
+                    tracing::warn!(error = %e, count = articles.len(), "COPY of article batch failed, inserting rows");
+                    tx.rollback().await?;
+                    tx = self.pool.begin().await?;
+                    lock_groups(&mut tx, &groups).await?;
+                }
+            }
+        }
+        for article in articles {
+            insert_article(
+                &mut tx,
+                article,
+                &parse_newsgroups_from_message(article),
+                now,
+                &compression,
+                &overview_headers,
             )
-            .bind(&group)
-            .bind(next)
-            .bind(&overview_data)
-            .execute(&self.pool)
             .await?;
         }
-
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
     #[tracing::instrument(skip_all)]
     async fn get_article_by_number(&self, group: &str, number: u64) -> Result<Option<Message>> {
         if let Some(row) = sqlx::query(
-            "SELECT m.headers, m.body FROM messages m JOIN group_articles g ON m.message_id = g.message_id WHERE g.group_name = $1 AND g.number = $2",
+            "SELECT m.headers, m.body, m.body_zstd FROM messages m JOIN group_articles g ON m.message_id = g.message_id WHERE g.group_name = $1 AND g.number = $2",
         )
         .bind(group)
         .bind(i64::try_from(number).unwrap_or(-1))
@@ -160,7 +736,12 @@ impl Storage for PostgresStorage {
         {
             let headers_str: String = row.try_get("headers")?;
             let body: String = row.try_get("body")?;
-            Ok(Some(crate::storage::common::reconstruct_message_from_row(&headers_str, &body)?))
+            let compressed: Option<Vec<u8>> = row.try_get("body_zstd")?;
+            Ok(Some(crate::storage::common::reconstruct_message_from_row(
+                &headers_str,
+                body,
+                compressed,
+            )?))
         } else {
             Ok(None)
         }
@@ -168,16 +749,19 @@ impl Storage for PostgresStorage {
 
     #[tracing::instrument(skip_all)]
     async fn get_article_by_id(&self, message_id: &str) -> Result<Option<Message>> {
-        if let Some(row) = sqlx::query("SELECT headers, body FROM messages WHERE message_id = $1")
-            .bind(message_id)
-            .fetch_optional(&self.pool)
-            .await?
+        if let Some(row) =
+            sqlx::query("SELECT headers, body, body_zstd FROM messages WHERE message_id = $1")
+                .bind(message_id)
+                .fetch_optional(&self.pool)
+                .await?
         {
             let headers_str: String = row.try_get("headers")?;
             let body: String = row.try_get("body")?;
+            let compressed: Option<Vec<u8>> = row.try_get("body_zstd")?;
             Ok(Some(crate::storage::common::reconstruct_message_from_row(
                 &headers_str,
-                &body,
+                body,
+                compressed,
             )?))
         } else {
             Ok(None)
@@ -195,7 +779,7 @@ impl Storage for PostgresStorage {
 
             // Build a parameterized query with the right number of placeholders
             let placeholders = (1..=message_ids.len()).map(|i| format!("${i}")).collect::<Vec<_>>().join(", ");
-            let query = format!("SELECT message_id, headers, body FROM messages WHERE message_id IN ({placeholders})");
+            let query = format!("SELECT message_id, headers, body, body_zstd FROM messages WHERE message_id IN ({placeholders})");
 
             let mut query_builder = sqlx::query(&query);
             for message_id in message_ids {
@@ -210,15 +794,16 @@ impl Storage for PostgresStorage {
                         match (
                             r.try_get::<String, _>("message_id"),
                             r.try_get::<String, _>("headers"),
-                            r.try_get::<String, _>("body")
+                            r.try_get::<String, _>("body"),
+                            r.try_get::<Option<Vec<u8>>, _>("body_zstd")
                         ) {
-                            (Ok(message_id), Ok(headers_str), Ok(body)) => {
-                                match crate::storage::common::reconstruct_message_from_row(&headers_str, &body) {
+                            (Ok(message_id), Ok(headers_str), Ok(body), Ok(compressed)) => {
+                                match crate::storage::common::reconstruct_message_from_row(&headers_str, body, compressed) {
                                     Ok(message) => yield Ok((message_id, message)),
                                     Err(e) => yield Err(e),
                                 }
                             },
-                            (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+                            (Err(e), _, _, _) | (_, Err(e), _, _) | (_, _, Err(e), _) | (_, _, _, Err(e)) => {
                                 yield Err(anyhow::Error::from(e))
                             }
                         }
@@ -232,42 +817,52 @@ impl Storage for PostgresStorage {
     #[tracing::instrument(skip_all)]
     async fn add_group(&self, group: &str, moderated: bool) -> Result<()> {
         let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
         sqlx::query(
             "INSERT INTO groups (name, created_at, moderated) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
         )
         .bind(group)
         .bind(now)
         .bind(moderated)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
     #[tracing::instrument(skip_all)]
     async fn set_group_moderated(&self, group: &str, moderated: bool) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
         sqlx::query("UPDATE groups SET moderated = $1 WHERE name = $2")
             .bind(moderated)
             .bind(group)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
     #[tracing::instrument(skip_all)]
-    async fn remove_group(&self, group: &str) -> Result<()> {
-        sqlx::query("DELETE FROM group_articles WHERE group_name = $1")
-            .bind(group)
-            .execute(&self.pool)
-            .await?;
-        sqlx::query("DELETE FROM groups WHERE name = $1")
+    async fn set_group_archived(&self, group: &str, archived: bool) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE groups SET archived = $1 WHERE name = $2")
+            .bind(archived)
             .bind(group)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
-        sqlx::query(
-            "DELETE FROM messages WHERE message_id NOT IN (SELECT DISTINCT message_id FROM group_articles)",
-        )
-        .execute(&self.pool)
-        .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn remove_group(&self, group: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        remove_groups(&mut tx, &[group.to_string()]).await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -286,11 +881,10 @@ impl Storage for PostgresStorage {
             }
         }
 
-        // Remove each matching group
-        for group in matching_groups {
-            self.remove_group(&group).await?;
-        }
-
+        let mut tx = self.pool.begin().await?;
+        remove_groups(&mut tx, &matching_groups).await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -308,6 +902,35 @@ impl Storage for PostgresStorage {
         }
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn is_group_archived(&self, group: &str) -> Result<bool> {
+        let row = sqlx::query("SELECT archived FROM groups WHERE name = $1")
+            .bind(group)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some(r) = row {
+            let a: bool = r.try_get("archived")?;
+            Ok(a)
+        } else {
+            Ok(false)
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn list_archived_groups(&self) -> StringStream<'_> {
+        let pool = self.pool.clone();
+        Box::pin(stream! {
+            let mut rows = sqlx::query("SELECT name FROM groups WHERE archived ORDER BY name")
+                .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                yield row
+                    .and_then(|r| r.try_get::<String, _>("name"))
+                    .map_err(anyhow::Error::from);
+            }
+        })
+    }
+
     #[tracing::instrument(skip_all)]
     async fn group_exists(&self, group: &str) -> Result<bool> {
         let row = sqlx::query("SELECT 1 FROM groups WHERE name = $1 LIMIT 1")
@@ -317,6 +940,27 @@ impl Storage for PostgresStorage {
         Ok(row.is_some())
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn get_group_watermarks(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        let Some(row) = sqlx::query(
+            "SELECT low_water, high_water, (SELECT COUNT(*) FROM group_articles WHERE group_name = groups.name) AS count FROM groups WHERE name = $1",
+        )
+        .bind(group)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let count: i64 = row.try_get("count")?;
+        let low: i64 = row.try_get("low_water")?;
+        let high: i64 = row.try_get("high_water")?;
+        Ok(Some(GroupWatermarks {
+            count: u64::try_from(count).unwrap_or(0),
+            low: u64::try_from(low).unwrap_or(0),
+            high: u64::try_from(high).unwrap_or(0),
+        }))
+    }
+
     #[tracing::instrument(skip_all)]
     async fn add_group_with_description(
         &self,
@@ -325,6 +969,7 @@ impl Storage for PostgresStorage {
         description: &str,
     ) -> Result<()> {
         let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
         // Use INSERT ... ON CONFLICT to upsert: if group exists, update moderated and description
         sqlx::query(
             "INSERT INTO groups (name, created_at, moderated, description) VALUES ($1, $2, $3, $4)
@@ -334,11 +979,26 @@ impl Storage for PostgresStorage {
         .bind(now)
         .bind(moderated)
         .bind(description)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn set_group_description(&self, group: &str, description: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query("UPDATE groups SET description = $1 WHERE name = $2")
+            .bind(description)
+            .bind(group)
+            .execute(&mut *tx)
+            .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
     #[tracing::instrument(skip_all)]
     fn list_groups_with_descriptions(&self) -> GroupDescriptionStream<'_> {
         let pool = self.pool.clone();
@@ -423,6 +1083,55 @@ impl Storage for PostgresStorage {
         })
     }
 
+    #[tracing::instrument(skip_all)]
+    fn list_groups_with_counts(&self) -> GroupCountStream<'_> {
+        let pool = self.pool.clone();
+        Box::pin(stream! {
+            let mut rows = sqlx::query(
+                "SELECT g.name, g.low_water, g.high_water, g.moderated, COUNT(ga.number) AS count
+                 FROM groups g LEFT JOIN group_articles ga ON ga.group_name = g.name
+                 GROUP BY g.name, g.low_water, g.high_water, g.moderated ORDER BY g.name",
+            )
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                yield row.map_err(anyhow::Error::from).and_then(|r| {
+                    let count: i64 = r.try_get("count")?;
+                    let low: i64 = r.try_get("low_water")?;
+                    let high: i64 = r.try_get("high_water")?;
+                    let marks = GroupWatermarks {
+                        count: u64::try_from(count).unwrap_or(0),
+                        low: u64::try_from(low).unwrap_or(0),
+                        high: u64::try_from(high).unwrap_or(0),
+                    };
+                    Ok((r.try_get("name")?, marks, r.try_get::<bool, _>("moderated")?))
+                });
+            }
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn list_group_activity(&self) -> GroupActivityStream<'_> {
+        let pool = self.pool.clone();
+        Box::pin(stream! {
+            let mut rows = sqlx::query(
+                "SELECT name, last_post_at, post_count FROM groups ORDER BY last_post_at DESC NULLS LAST, name",
+            )
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                yield row.map_err(anyhow::Error::from).and_then(|r| {
+                    let posts: i64 = r.try_get("post_count")?;
+                    let activity = GroupActivity {
+                        last_post: r.try_get("last_post_at")?,
+                        posts: u64::try_from(posts).unwrap_or(0),
+                    };
+                    Ok((r.try_get("name")?, activity))
+                });
+            }
+        })
+    }
+
     #[tracing::instrument(skip_all)]
     fn list_article_numbers(&self, group: &str) -> U64Stream<'_> {
         let pool = self.pool.clone();
@@ -498,49 +1207,441 @@ impl Storage for PostgresStorage {
         group: &str,
         before: chrono::DateTime<chrono::Utc>,
     ) -> Result<()> {
-        sqlx::query("DELETE FROM group_articles WHERE group_name = $1 AND inserted_at < $2")
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM group_articles WHERE group_name = $1 AND inserted_at < $2 AND (flags & $3) = 0")
             .bind(group)
             .bind(before.timestamp())
-            .execute(&self.pool)
+            .bind(ARTICLE_FLAG_PINNED)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(RAISE_LOW_WATER)
+            .bind(group)
+            .execute(&mut *tx)
             .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
     #[tracing::instrument(skip_all)]
     async fn purge_orphan_messages(&self) -> Result<()> {
-        sqlx::query(
-            "DELETE FROM messages WHERE message_id NOT IN (SELECT DISTINCT message_id FROM group_articles)",
-        )
-        .execute(&self.pool)
-        .await?;
+        let mut tx = self.pool.begin().await?;
+        remove_orphans(&mut tx).await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
+    async fn database_size(&self) -> Result<u64> {
+        let size: i64 = sqlx::query_scalar("SELECT pg_database_size(current_database())")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(u64::try_from(size).unwrap_or(0))
+    }
+
     #[tracing::instrument(skip_all)]
-    async fn get_message_size(&self, message_id: &str) -> Result<Option<u64>> {
-        if let Some(row) = sqlx::query("SELECT size FROM messages WHERE message_id = $1")
-            .bind(message_id)
-            .fetch_optional(&self.pool)
-            .await?
-        {
-            let size: i64 = row.try_get("size")?;
+    async fn compact(&self) -> Result<()> {
+        // A plain VACUUM leaves the tables usable while it runs and makes
+        // the space of dead rows available again; autovacuum keeps its own
+        // schedule
+        sqlx::query("VACUUM (ANALYZE)").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn rebuild_overview(&self, group: &str) -> Result<OverviewRepair> {
+        let overview_headers = self.overview_headers();
+        // Article numbers whose message is gone can never be served
+        let dangling_removed = sqlx::query(
+            "DELETE FROM group_articles WHERE group_name = $1 AND message_id NOT IN (SELECT message_id FROM messages)",
+        )
+        .bind(group)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        sqlx::query(RAISE_LOW_WATER)
+            .bind(group)
+            .execute(&self.pool)
+            .await?;
+
+        let stale_removed = sqlx::query(
+            "DELETE FROM overview WHERE group_name = $1 AND article_number NOT IN (SELECT number FROM group_articles WHERE group_name = $2)",
+        )
+        .bind(group)
+        .bind(group)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        sqlx::query(
+            "DELETE FROM article_references WHERE group_name = $1 AND article_number NOT IN (SELECT number FROM group_articles WHERE group_name = $2)",
+        )
+        .bind(group)
+        .bind(group)
+        .execute(&self.pool)
+        .await?;
+
+        let mut repair = OverviewRepair {
+            stale_removed,
+            dangling_removed,
+            ..OverviewRepair::default()
+        };
+
+        // Only numbers and current overview rows are loaded up front; the
+        // articles themselves are read one at a time
+        let rows = sqlx::query(
+            "SELECT g.number, o.overview_data FROM group_articles g \
+             LEFT JOIN overview o ON o.group_name = g.group_name AND o.article_number = g.number \
+             WHERE g.group_name = $1 ORDER BY g.number",
+        )
+        .bind(group)
+        .fetch_all(&self.pool)
+        .await?;
+
+        for row in rows {
+            let number: i64 = row.try_get("number")?;
+            let current: Option<String> = row.try_get("overview_data")?;
+            let Some(article) = self
+                .get_article_by_number(group, u64::try_from(number).unwrap_or(0))
+                .await?
+            else {
+                continue;
+            };
+            repair.checked += 1;
+
+            // Articles stored before references were indexed gain their
+            // entries here
+            let mut tx = self.pool.begin().await?;
+            index_references(&mut tx, group, number, &crate::thread::references(&article)).await?;
+            tx.commit().await?;
+
+            let overview_data = crate::overview::format_overview_line(
+                u64::try_from(number).unwrap_or(0),
+                &article,
+                &overview_headers,
+            );
+            if current.as_deref() == Some(overview_data.as_str()) {
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO overview (group_name, article_number, overview_data) VALUES ($1, $2, $3) ON CONFLICT (group_name, article_number) DO UPDATE SET overview_data = EXCLUDED.overview_data",
+            )
+            .bind(group)
+            .bind(number)
+            .bind(&overview_data)
+            .execute(&self.pool)
+            .await?;
+            repair.rebuilt += 1;
+        }
+
+        Ok(repair)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn renumber_group(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        let mut tx = self.pool.begin().await?;
+        // Hold back articles stored to the group meanwhile
+        let exists = sqlx::query("SELECT 1 FROM groups WHERE name = $1 FOR UPDATE")
+            .bind(group)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            return Ok(None);
+        }
+        let numbers: Vec<i64> = sqlx::query_scalar(
+            "SELECT number FROM group_articles WHERE group_name = $1 ORDER BY number",
+        )
+        .bind(group)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // Negate the old numbers first, so that no new number collides with
+        // an old one still in place
+        for statement in [
+            "UPDATE group_articles SET number = -number WHERE group_name = $1",
+            "UPDATE overview SET article_number = -article_number WHERE group_name = $1",
+            "UPDATE article_references SET article_number = -article_number WHERE group_name = $1",
+        ] {
+            sqlx::query(statement).bind(group).execute(&mut *tx).await?;
+        }
+        for (new, old) in (1i64..).zip(&numbers) {
+            sqlx::query(
+                "UPDATE group_articles SET number = $1 WHERE group_name = $2 AND number = $3",
+            )
+            .bind(new)
+            .bind(group)
+            .bind(-old)
+            .execute(&mut *tx)
+            .await?;
+            // The overview line starts with the article number
+            sqlx::query(
+                "UPDATE overview SET article_number = $1, overview_data = $2 || substr(overview_data, strpos(overview_data, chr(9))) WHERE group_name = $3 AND article_number = $4",
+            )
+            .bind(new)
+            .bind(new.to_string())
+            .bind(group)
+            .bind(-old)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                "UPDATE article_references SET article_number = $1 WHERE group_name = $2 AND article_number = $3",
+            )
+            .bind(new)
+            .bind(group)
+            .bind(-old)
+            .execute(&mut *tx)
+            .await?;
+        }
+        // Entries of numbers without an article
+        sqlx::query("DELETE FROM overview WHERE group_name = $1 AND article_number < 0")
+            .bind(group)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM article_references WHERE group_name = $1 AND article_number < 0")
+            .bind(group)
+            .execute(&mut *tx)
+            .await?;
+
+        let count = i64::try_from(numbers.len()).unwrap_or(i64::MAX);
+        sqlx::query("UPDATE groups SET low_water = $1, high_water = $2 WHERE name = $3")
+            .bind(count.min(1))
+            .bind(count)
+            .bind(group)
+            .execute(&mut *tx)
+            .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        self.get_group_watermarks(group).await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_message_size(&self, message_id: &str) -> Result<Option<u64>> {
+        if let Some(row) = sqlx::query("SELECT size FROM messages WHERE message_id = $1")
+            .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await?
+        {
+            let size: i64 = row.try_get("size")?;
             Ok(Some(u64::try_from(size).unwrap_or(0)))
         } else {
             Ok(None)
         }
     }
 
-    async fn delete_article_by_id(&self, message_id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM group_articles WHERE message_id = $1")
-            .bind(message_id)
-            .execute(&self.pool)
-            .await?;
-        sqlx::query(
-            "DELETE FROM messages WHERE message_id = $1 AND NOT EXISTS (SELECT 1 FROM group_articles WHERE message_id = $1)",
+    #[tracing::instrument(skip_all)]
+    async fn get_article_numbers(&self, message_id: &str) -> Result<Vec<(String, u64)>> {
+        let rows = sqlx::query(
+            "SELECT group_name, number FROM group_articles WHERE message_id = $1 ORDER BY group_name",
         )
         .bind(message_id)
-        .execute(&self.pool)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                let number: i64 = row.try_get("number")?;
+                Ok((
+                    row.try_get("group_name")?,
+                    u64::try_from(number).unwrap_or(0),
+                ))
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_article_numbers_batch(
+        &self,
+        message_ids: &[String],
+    ) -> Result<std::collections::HashMap<String, Vec<(String, u64)>>> {
+        let mut numbers: std::collections::HashMap<String, Vec<(String, u64)>> =
+            std::collections::HashMap::new();
+        for chunk in message_ids.chunks(BATCH_LOOKUP_IDS) {
+            let placeholders = (1..=chunk.len())
+                .map(|i| format!("${i}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let query = format!(
+                "SELECT message_id, group_name, number FROM group_articles \
+                 WHERE message_id IN ({placeholders}) ORDER BY message_id, group_name"
+            );
+            let mut query = sqlx::query(&query);
+            for message_id in chunk {
+                query = query.bind(message_id);
+            }
+            for row in query.fetch_all(&self.pool).await? {
+                let number: i64 = row.try_get("number")?;
+                numbers
+                    .entry(row.try_get("message_id")?)
+                    .or_default()
+                    .push((
+                        row.try_get("group_name")?,
+                        u64::try_from(number).unwrap_or(0),
+                    ));
+            }
+        }
+        Ok(numbers)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_article_arrivals(
+        &self,
+        message_id: &str,
+    ) -> Result<Vec<(String, chrono::DateTime<chrono::Utc>, bool)>> {
+        let rows = sqlx::query(
+            "SELECT group_name, inserted_at, flags FROM group_articles WHERE message_id = $1 ORDER BY group_name",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
         .await?;
+        rows.into_iter()
+            .map(|row| {
+                let inserted_at: i64 = row.try_get("inserted_at")?;
+                let flags: i64 = row.try_get("flags")?;
+                Ok((
+                    row.try_get("group_name")?,
+                    chrono::DateTime::from_timestamp(inserted_at, 0).unwrap_or_default(),
+                    flags & ARTICLE_FLAG_PINNED != 0,
+                ))
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn list_replies(&self, group: &str, message_id: &str) -> Result<Vec<(u64, String)>> {
+        let rows = sqlx::query(
+            "SELECT g.number, g.message_id FROM article_references r \
+             JOIN group_articles g ON g.group_name = r.group_name AND g.number = r.article_number \
+             WHERE r.group_name = $1 AND r.referenced_id = $2 ORDER BY g.number",
+        )
+        .bind(group)
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                let number: i64 = row.try_get("number")?;
+                Ok((
+                    u64::try_from(number).unwrap_or(0),
+                    row.try_get("message_id")?,
+                ))
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_body_range(
+        &self,
+        message_id: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Option<(u64, Vec<u8>)>> {
+        let (start, count) = sql_substring_bounds(offset, len);
+        let row = sqlx::query(
+            "SELECT octet_length(body) AS total, substring(convert_to(body, 'UTF8') FROM $1::integer FOR $2::integer) AS chunk, body_zstd FROM messages WHERE message_id = $3",
+        )
+        .bind(start)
+        .bind(count)
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|r| {
+            // Compressed bodies are sliced once decompressed
+            if let Some(compressed) = r.try_get::<Option<Vec<u8>>, _>("body_zstd")? {
+                let body = decode_body(String::new(), Some(compressed))?;
+                return Ok(body_range(&body, offset, len));
+            }
+            let total: i64 = r.try_get("total")?;
+            let chunk: Option<Vec<u8>> = r.try_get("chunk")?;
+            Ok((u64::try_from(total).unwrap_or(0), chunk.unwrap_or_default()))
+        })
+        .transpose()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn set_article_pinned(
+        &self,
+        group: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> Result<bool> {
+        let sql = if pinned {
+            "UPDATE group_articles SET flags = flags | $1 WHERE group_name = $2 AND message_id = $3"
+        } else {
+            "UPDATE group_articles SET flags = flags & ~$1 WHERE group_name = $2 AND message_id = $3"
+        };
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(sql)
+            .bind(ARTICLE_FLAG_PINNED)
+            .bind(group)
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn list_pinned_articles(&self) -> PinnedArticleStream<'_> {
+        let pool = self.pool.clone();
+        Box::pin(stream! {
+            let mut rows = sqlx::query(
+                "SELECT group_name, number, message_id FROM group_articles WHERE (flags & $1) != 0 ORDER BY group_name, number",
+            )
+            .bind(ARTICLE_FLAG_PINNED)
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                match row {
+                    Ok(r) => yield pinned_from_row(&r),
+                    Err(e) => yield Err(anyhow::Error::from(e)),
+                }
+            }
+        })
+    }
+
+    async fn delete_article_by_id(&self, message_id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        remove_article(&mut tx, message_id, chrono::Utc::now().timestamp()).await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn withdraw_article(&self, message_id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        unlink_article(&mut tx, message_id).await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn supersede_article(&self, old_id: &str, article: &Message) -> Result<()> {
+        let compression = self.compression();
+        let overview_headers = self.overview_headers();
+        let mut tx = self.pool.begin().await?;
+        let now = replicated::now().timestamp();
+        remove_article(&mut tx, old_id, now).await?;
+        let msg_id =
+            extract_message_id(article).ok_or_else(|| anyhow::anyhow!("missing Message-ID"))?;
+        let stored = sqlx::query("SELECT 1 FROM messages WHERE message_id = $1")
+            .bind(&msg_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !stored {
+            insert_article(
+                &mut tx,
+                article,
+                &parse_newsgroups_from_message(article),
+                now,
+                &compression,
+                &overview_headers,
+            )
+            .await?;
+        }
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -563,4 +1664,454 @@ impl Storage for PostgresStorage {
 
         Ok(overview_lines)
     }
+
+    #[tracing::instrument(skip_all)]
+    async fn add_pending_article(&self, article: &Message) -> Result<u64> {
+        let msg_id =
+            extract_message_id(article).ok_or_else(|| anyhow::anyhow!("missing Message-ID"))?;
+        let headers = serde_json::to_string(&Headers(article.headers.clone()))?;
+        sqlx::query(
+            "INSERT INTO pending_articles (message_id, headers, body, size, submitted_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (message_id) DO NOTHING",
+        )
+        .bind(&msg_id)
+        .bind(&headers)
+        .bind(&article.body)
+        .bind(i64::try_from(article.body.len()).unwrap_or(i64::MAX))
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        let id: i64 = sqlx::query_scalar("SELECT id FROM pending_articles WHERE message_id = $1")
+            .bind(&msg_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(u64::try_from(id).unwrap_or(0))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_pending_article(&self, id: u64) -> Result<Option<PendingArticle>> {
+        let row = sqlx::query(
+            "SELECT id, headers, body, size, submitted_at FROM pending_articles WHERE id = $1",
+        )
+        .bind(i64::try_from(id).unwrap_or(i64::MAX))
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|r| pending_from_row(&r)).transpose()
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn list_pending_articles(&self) -> PendingArticleStream<'_> {
+        let pool = self.pool.clone();
+        Box::pin(stream! {
+            let mut rows = sqlx::query(
+                "SELECT id, headers, body, size, submitted_at FROM pending_articles ORDER BY id",
+            )
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                match row {
+                    Ok(r) => yield pending_from_row(&r),
+                    Err(e) => yield Err(anyhow::Error::from(e)),
+                }
+            }
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn remove_pending_article(&self, id: u64) -> Result<()> {
+        sqlx::query("DELETE FROM pending_articles WHERE id = $1")
+            .bind(i64::try_from(id).unwrap_or(i64::MAX))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn add_pending_checkgroups(&self, signer: &str, diff: &str) -> Result<u64> {
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO pending_checkgroups (signer, diff, received_at) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(signer)
+        .bind(diff)
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(u64::try_from(id).unwrap_or(0))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn list_pending_checkgroups(&self) -> Result<Vec<PendingCheckgroups>> {
+        let rows = sqlx::query(
+            "SELECT id, signer, diff, received_at FROM pending_checkgroups ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                let id: i64 = row.try_get("id")?;
+                Ok(PendingCheckgroups {
+                    id: u64::try_from(id).unwrap_or(0),
+                    signer: row.try_get("signer")?,
+                    diff: row.try_get("diff")?,
+                    received_at: row.try_get("received_at")?,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn remove_pending_checkgroups(&self, id: u64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM pending_checkgroups WHERE id = $1")
+            .bind(i64::try_from(id).unwrap_or(i64::MAX))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn add_rejected_article(&self, article: &Message, reason: &str) -> Result<u64> {
+        let headers = serde_json::to_string(&Headers(article.headers.clone()))?;
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO rejected_articles (message_id, headers, body, reason, rejected_at) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        )
+        .bind(extract_message_id(article).unwrap_or_default())
+        .bind(&headers)
+        .bind(&article.body)
+        .bind(reason)
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(u64::try_from(id).unwrap_or(0))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_rejected_article(&self, id: u64) -> Result<Option<RejectedArticle>> {
+        let row = sqlx::query(
+            "SELECT id, headers, body, reason, rejected_at FROM rejected_articles WHERE id = $1",
+        )
+        .bind(i64::try_from(id).unwrap_or(i64::MAX))
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|r| rejected_from_row(&r)).transpose()
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn list_rejected_articles(&self) -> RejectedArticleStream<'_> {
+        let pool = self.pool.clone();
+        Box::pin(stream! {
+            let mut rows = sqlx::query(
+                "SELECT id, headers, body, reason, rejected_at FROM rejected_articles ORDER BY id",
+            )
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                match row {
+                    Ok(r) => yield rejected_from_row(&r),
+                    Err(e) => yield Err(anyhow::Error::from(e)),
+                }
+            }
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn remove_rejected_article(&self, id: u64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM rejected_articles WHERE id = $1")
+            .bind(i64::try_from(id).unwrap_or(i64::MAX))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn purge_rejected_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        sqlx::query("DELETE FROM rejected_articles WHERE rejected_at < $1")
+            .bind(before.timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_resume_token(&self, token: &str, message_id: &str) -> Result<()> {
+        sqlx::query("INSERT INTO resume_tokens (token, message_id, created_at) VALUES ($1, $2, $3) ON CONFLICT (token) DO UPDATE SET message_id = EXCLUDED.message_id, created_at = EXCLUDED.created_at")
+            .bind(token)
+            .bind(message_id)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_resume_token(
+        &self,
+        token: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<String>> {
+        let id = sqlx::query_scalar(
+            "SELECT message_id FROM resume_tokens WHERE token = $1 AND created_at >= $2",
+        )
+        .bind(token)
+        .bind(since.timestamp())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    async fn purge_resume_tokens_before(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        sqlx::query("DELETE FROM resume_tokens WHERE created_at < $1")
+            .bind(before.timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn remember_message_id(&self, message_id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO history (message_id, recorded_at) VALUES ($1, $2) ON CONFLICT (message_id) DO UPDATE SET recorded_at = EXCLUDED.recorded_at",
+        )
+        .bind(message_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn in_history(&self, message_id: &str) -> Result<bool> {
+        let row = sqlx::query("SELECT 1 FROM history WHERE message_id = $1")
+            .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    async fn purge_history_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM history WHERE recorded_at < $1")
+            .bind(before.timestamp())
+            .execute(&mut *tx)
+            .await?;
+        self.record_changes(&mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn record_audit(&self, entry: &AuditEntry) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO audit_log (recorded_at, actor, source, action, target, detail) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(entry.recorded_at)
+        .bind(&entry.actor)
+        .bind(&entry.source)
+        .bind(entry.action.as_str())
+        .bind(&entry.target)
+        .bind(&entry.detail)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    fn list_audit_since(&self, since: chrono::DateTime<chrono::Utc>) -> AuditStream<'_> {
+        let pool = self.pool.clone();
+        Box::pin(stream! {
+            let mut rows = sqlx::query(
+                "SELECT recorded_at, actor, source, action, target, detail FROM audit_log WHERE recorded_at >= $1 ORDER BY id",
+            )
+            .bind(since.timestamp())
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                match row {
+                    Ok(r) => yield audit_from_row(&r),
+                    Err(e) => yield Err(anyhow::Error::from(e)),
+                }
+            }
+        })
+    }
+
+    async fn purge_audit_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        sqlx::query("DELETE FROM audit_log WHERE recorded_at < $1")
+            .bind(before.timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_change(&self, seq: u64, change: &str) -> Result<()> {
+        sqlx::query("INSERT INTO replication_log (seq, recorded_at, change) VALUES ($1, $2, $3)")
+            .bind(i64::try_from(seq)?)
+            .bind(chrono::Utc::now().timestamp())
+            .bind(change)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn append_changes(&self, changes: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        append_changes(&mut tx, changes).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn last_change_seq(&self) -> Result<u64> {
+        let seq: Option<i64> = sqlx::query_scalar("SELECT MAX(seq) FROM replication_log")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(seq.and_then(|s| u64::try_from(s).ok()).unwrap_or(0))
+    }
+
+    fn list_changes_since(&self, after: u64) -> ChangeStream<'_> {
+        let pool = self.pool.clone();
+        Box::pin(stream! {
+            let mut rows = sqlx::query(
+                "SELECT seq, change FROM replication_log WHERE seq > $1 ORDER BY seq",
+            )
+            .bind(i64::try_from(after).unwrap_or(i64::MAX))
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                match row {
+                    Ok(r) => match (r.try_get::<i64, _>("seq"), r.try_get::<String, _>("change")) {
+                        (Ok(seq), Ok(change)) => yield Ok((u64::try_from(seq).unwrap_or(0), change)),
+                        (Err(e), _) | (_, Err(e)) => yield Err(anyhow::Error::from(e)),
+                    },
+                    Err(e) => yield Err(anyhow::Error::from(e)),
+                }
+            }
+        })
+    }
+
+    async fn purge_changes_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        sqlx::query(
+            "DELETE FROM replication_log WHERE recorded_at < $1 AND seq < (SELECT MAX(seq) FROM replication_log)",
+        )
+        .bind(before.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn snapshot_to(&self, _path: &std::path::Path) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "PostgreSQL databases cannot be snapshotted by renews
+
+Take online backups with the PostgreSQL tools instead:
+- pg_dump -Fc dbname > renews.dump for a consistent logical copy
+- pg_basebackup -D /backup/dir for a physical copy of the whole cluster"
+        ))
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    fn set_records_changes(&self, records: bool) {
+        self.records_changes.store(records, Ordering::Relaxed);
+    }
+
+    fn set_compression(&self, compression: ArticleCompression) {
+        *self.compression.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(compression);
+    }
+
+    fn set_overview_headers(&self, headers: Vec<String>) {
+        *self
+            .overview_headers
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Arc::new(headers);
+    }
+}
+
+/// Build a [`PendingArticle`] from a `pending_articles` row.
+fn pending_from_row(row: &sqlx::postgres::PgRow) -> Result<PendingArticle> {
+    let id: i64 = row.try_get("id")?;
+    let headers: String = row.try_get("headers")?;
+    let body: String = row.try_get("body")?;
+    let size: i64 = row.try_get("size")?;
+    Ok(PendingArticle {
+        id: u64::try_from(id).unwrap_or(0),
+        message: crate::storage::common::reconstruct_message_from_row(&headers, body, None)?,
+        size: u64::try_from(size).unwrap_or(0),
+        submitted_at: row.try_get("submitted_at")?,
+    })
+}
+
+fn rejected_from_row(row: &sqlx::postgres::PgRow) -> Result<RejectedArticle> {
+    let id: i64 = row.try_get("id")?;
+    let headers: String = row.try_get("headers")?;
+    let body: String = row.try_get("body")?;
+    Ok(RejectedArticle {
+        id: u64::try_from(id).unwrap_or(0),
+        message: crate::storage::common::reconstruct_message_from_row(&headers, body, None)?,
+        reason: row.try_get("reason")?,
+        rejected_at: row.try_get("rejected_at")?,
+    })
+}
+
+fn pinned_from_row(row: &sqlx::postgres::PgRow) -> Result<(String, u64, String)> {
+    let number: i64 = row.try_get("number")?;
+    Ok((
+        row.try_get("group_name")?,
+        u64::try_from(number).unwrap_or(0),
+        row.try_get("message_id")?,
+    ))
+}
+
+/// Build an [`AuditEntry`] from an `audit_log` row.
+fn audit_from_row(row: &sqlx::postgres::PgRow) -> Result<AuditEntry> {
+    let action: String = row.try_get("action")?;
+    Ok(AuditEntry {
+        recorded_at: row.try_get("recorded_at")?,
+        actor: row.try_get("actor")?,
+        source: row.try_get("source")?,
+        action: action.parse()?,
+        target: row.try_get("target")?,
+        detail: row.try_get("detail")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_rows_escape_the_text_format() {
+        let mut out = String::new();
+        copy_row(
+            &mut out,
+            &[Some("a\tb"), Some("back\\slash"), None, Some("")],
+        );
+        copy_row(&mut out, &[Some("line\r\nnext\n"), Some("\\N")]);
+        assert_eq!(
+            out,
+            "a\\tb\tback\\\\slash\t\\N\t\n\
+             line\\r\\nnext\\n\t\\\\N\n"
+        );
+
+        // NUL is left for the server to refuse, as it is in an INSERT
+        let mut out = String::new();
+        copy_row(&mut out, &[Some("nul\0byte")]);
+        assert_eq!(out, "nul\0byte\n");
+    }
+
+    #[test]
+    fn reserved_blocks_number_articles_in_order() {
+        // Three numbers reserved in a group whose high watermark was 7
+        assert_eq!(block_start(10, 3), 8);
+        assert_eq!(block_start(1, 1), 1);
+
+        let mut blocks = NumberBlocks::default();
+        blocks.reserve("misc".into(), block_start(10, 3));
+        blocks.reserve("other".into(), block_start(1, 1));
+        let taken: Vec<i64> = ["misc", "other", "misc", "misc"]
+            .iter()
+            .map(|group| blocks.take(group).unwrap())
+            .collect();
+        assert_eq!(taken, [8, 1, 9, 10]);
+        assert!(blocks.take("unreserved").is_err());
+    }
 }
@@ -0,0 +1,642 @@
+//! Recording of changes for hot standby replication.
+//!
+//! [`ReplicatedStorage`] wraps the storage of a primary and records every
+//! change to the article store in the replication log served to standbys
+//! by [`crate::replication`]. The backend writes the log entries in the
+//! transaction making the change, numbered by the database after the newest
+//! entry, so that a change is never made without being recorded and other
+//! processes writing to the same database, such as the admin CLI, cannot
+//! take the same number. Changes to the same database are made one at a
+//! time, so that standbys applying them in log order number articles as the
+//! primary did.
+
+use super::{
+    ArticleStream, AuditStream, ChangeStream, DynStorage, GroupActivityStream, GroupCountStream,
+    GroupDescriptionStream, GroupWatermarks, OverviewRepair, PendingArticle, PendingArticleStream,
+    PendingCheckgroups, PinnedArticleStream, RejectedArticle, RejectedArticleStream, Storage,
+    StringStream, StringTimestampStream, U64Stream,
+};
+use crate::Message;
+use crate::audit::AuditEntry;
+use crate::config::ReplicationConfig;
+use crate::replication::Change;
+use crate::storage::cache::OverviewCache;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::{Arc, PoisonError};
+use tokio::sync::Mutex;
+
+/// A change being made, with the log entries still to be written for it.
+struct Recording {
+    /// Time articles stored by the change are inserted at
+    at: DateTime<Utc>,
+    changes: std::sync::Mutex<Vec<String>>,
+}
+
+tokio::task_local! {
+    static RECORDING: Arc<Recording>;
+}
+
+/// Time at which articles stored now are inserted: that of the replicated
+/// change being made or applied, else the current time.
+pub(crate) fn now() -> DateTime<Utc> {
+    RECORDING.try_with(|r| r.at).unwrap_or_else(|_| Utc::now())
+}
+
+/// Take the log entries of the change being made, for the backend to write
+/// in the transaction making it. Empty outside replicated storage and once
+/// taken.
+pub(crate) fn take_changes() -> Vec<String> {
+    RECORDING
+        .try_with(|r| {
+            std::mem::take(&mut *r.changes.lock().unwrap_or_else(PoisonError::into_inner))
+        })
+        .unwrap_or_default()
+}
+
+/// Run `op`, inserting the articles it stores at `at` as the primary did.
+pub(crate) async fn inserted_at<F: Future>(at: DateTime<Utc>, op: F) -> F::Output {
+    let recording = Recording {
+        at,
+        changes: std::sync::Mutex::default(),
+    };
+    RECORDING.scope(Arc::new(recording), op).await
+}
+
+/// Run `op`, part of a change spanning databases, without any of them
+/// writing its log entries; the part completing the change writes them, or
+/// they are recorded once the whole change succeeds.
+pub(crate) async fn deferred<F: Future>(op: F) -> F::Output {
+    inserted_at(now(), op).await
+}
+
+/// Storage that records its changes in the replication log.
+pub struct ReplicatedStorage {
+    inner: DynStorage,
+    /// Serializes the changes to each database of `inner`
+    writers: Vec<Mutex<()>>,
+}
+
+impl ReplicatedStorage {
+    #[must_use]
+    pub fn new(inner: DynStorage) -> Self {
+        let writers = (0..inner.database_count())
+            .map(|_| Mutex::new(()))
+            .collect();
+        Self { inner, writers }
+    }
+
+    /// Databases of `inner` that `changes` write to: those holding the
+    /// groups they touch, the first for state belonging to no group, and
+    /// every database for changes that may reach any.
+    fn databases_of(&self, changes: &[Change]) -> BTreeSet<usize> {
+        let mut databases = BTreeSet::new();
+        for change in changes {
+            match change {
+                Change::Store { headers, .. } => {
+                    let groups: Vec<&str> = headers
+                        .iter()
+                        .find(|(k, _)| k.eq_ignore_ascii_case("Newsgroups"))
+                        .map(|(_, v)| v.split(',').map(str::trim).filter(|g| !g.is_empty()))
+                        .into_iter()
+                        .flatten()
+                        .collect();
+                    if groups.is_empty() {
+                        databases.insert(0);
+                    }
+                    databases.extend(groups.iter().map(|g| self.inner.database_of(g)));
+                }
+                Change::AddGroup { group, .. }
+                | Change::SetModerated { group, .. }
+                | Change::SetArchived { group, .. }
+                | Change::SetDescription { group, .. }
+                | Change::RemoveGroup { group }
+                | Change::PurgeGroup { group, .. }
+                | Change::RenumberGroup { group }
+                | Change::SetPinned { group, .. } => {
+                    databases.insert(self.inner.database_of(group));
+                }
+                Change::Remember { .. } => {
+                    databases.insert(0);
+                }
+                Change::Delete { .. }
+                | Change::RemoveGroups { .. }
+                | Change::PurgeOrphans
+                | Change::PurgeHistory { .. } => return (0..self.writers.len()).collect(),
+            }
+        }
+        databases
+    }
+
+    /// Make a change with `op`, having the backend record `changes` for it
+    /// in the same transaction.
+    ///
+    /// Entries the backend did not take, because the change spans databases
+    /// or turned out to touch nothing, are recorded once it succeeds.
+    async fn replicate<T>(
+        &self,
+        mut changes: Vec<Change>,
+        op: impl Future<Output = Result<T>> + Send,
+    ) -> Result<T> {
+        // Locked in the order of the databases, so that changes spanning
+        // several cannot wait on each other
+        let mut writers = Vec::new();
+        for i in self.databases_of(&changes) {
+            writers.push(self.writers[i].lock().await);
+        }
+        let at = Utc::now();
+        for change in &mut changes {
+            if let Change::Store { inserted_at, .. } = change {
+                *inserted_at = Some(at.timestamp());
+            }
+        }
+        let recording = Arc::new(Recording {
+            at,
+            changes: std::sync::Mutex::new(
+                changes
+                    .iter()
+                    .map(serde_json::to_string)
+                    .collect::<Result<_, _>>()?,
+            ),
+        });
+        let value = RECORDING.scope(recording.clone(), op).await?;
+        let rest = std::mem::take(
+            &mut *recording
+                .changes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        if !rest.is_empty() {
+            self.inner.append_changes(&rest).await?;
+        }
+        Ok(value)
+    }
+}
+
+/// Wrap `storage` so that its changes are recorded if `cfg` makes this
+/// server a replication primary.
+#[must_use]
+pub fn for_config(storage: DynStorage, cfg: &ReplicationConfig) -> DynStorage {
+    if cfg.listen.is_some() && !cfg.is_standby() {
+        Arc::new(ReplicatedStorage::new(storage))
+    } else {
+        storage
+    }
+}
+
+#[async_trait]
+impl Storage for ReplicatedStorage {
+    async fn store_article(&self, article: &Message) -> Result<()> {
+        self.replicate(
+            vec![Change::store(article)],
+            self.inner.store_article(article),
+        )
+        .await
+    }
+
+    async fn store_article_in(&self, article: &Message, groups: &[String]) -> Result<()> {
+        // Only sharded storage, which replication wraps rather than the
+        // other way round, stores articles in chosen groups; the change is
+        // recorded by the `store_article` that led there
+        self.inner.store_article_in(article, groups).await
+    }
+
+    async fn store_articles(&self, articles: &[Message]) -> Result<()> {
+        self.replicate(
+            articles.iter().map(Change::store).collect(),
+            self.inner.store_articles(articles),
+        )
+        .await
+    }
+
+    async fn get_article_by_number(&self, group: &str, number: u64) -> Result<Option<Message>> {
+        self.inner.get_article_by_number(group, number).await
+    }
+
+    async fn get_article_by_id(&self, message_id: &str) -> Result<Option<Message>> {
+        self.inner.get_article_by_id(message_id).await
+    }
+
+    fn get_articles_by_ids<'a>(&'a self, message_ids: &'a [String]) -> ArticleStream<'a> {
+        self.inner.get_articles_by_ids(message_ids)
+    }
+
+    async fn get_overview_range(&self, group: &str, start: u64, end: u64) -> Result<Vec<String>> {
+        self.inner.get_overview_range(group, start, end).await
+    }
+
+    async fn add_group(&self, group: &str, moderated: bool) -> Result<()> {
+        let change = Change::AddGroup {
+            group: group.to_string(),
+            moderated,
+            description: None,
+        };
+        self.replicate(vec![change], self.inner.add_group(group, moderated))
+            .await
+    }
+
+    async fn set_group_moderated(&self, group: &str, moderated: bool) -> Result<()> {
+        let change = Change::SetModerated {
+            group: group.to_string(),
+            moderated,
+        };
+        self.replicate(
+            vec![change],
+            self.inner.set_group_moderated(group, moderated),
+        )
+        .await
+    }
+
+    async fn set_group_archived(&self, group: &str, archived: bool) -> Result<()> {
+        let change = Change::SetArchived {
+            group: group.to_string(),
+            archived,
+        };
+        self.replicate(vec![change], self.inner.set_group_archived(group, archived))
+            .await
+    }
+
+    async fn remove_group(&self, group: &str) -> Result<()> {
+        let change = Change::RemoveGroup {
+            group: group.to_string(),
+        };
+        self.replicate(vec![change], self.inner.remove_group(group))
+            .await
+    }
+
+    async fn remove_groups_by_pattern(&self, pattern: &str) -> Result<()> {
+        let change = Change::RemoveGroups {
+            pattern: pattern.to_string(),
+        };
+        self.replicate(vec![change], self.inner.remove_groups_by_pattern(pattern))
+            .await
+    }
+
+    fn list_groups(&self) -> StringStream<'_> {
+        self.inner.list_groups()
+    }
+
+    fn list_groups_since(&self, since: chrono::DateTime<chrono::Utc>) -> StringStream<'_> {
+        self.inner.list_groups_since(since)
+    }
+
+    fn list_groups_with_times(&self) -> StringTimestampStream<'_> {
+        self.inner.list_groups_with_times()
+    }
+
+    fn list_article_numbers(&self, group: &str) -> U64Stream<'_> {
+        self.inner.list_article_numbers(group)
+    }
+
+    fn list_article_ids(&self, group: &str) -> StringStream<'_> {
+        self.inner.list_article_ids(group)
+    }
+
+    fn list_article_ids_since(
+        &self,
+        group: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> StringStream<'_> {
+        self.inner.list_article_ids_since(group, since)
+    }
+
+    async fn purge_group_before(
+        &self,
+        group: &str,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        let change = Change::PurgeGroup {
+            group: group.to_string(),
+            before: before.timestamp(),
+        };
+        self.replicate(vec![change], self.inner.purge_group_before(group, before))
+            .await
+    }
+
+    async fn purge_orphan_messages(&self) -> Result<()> {
+        self.replicate(
+            vec![Change::PurgeOrphans],
+            self.inner.purge_orphan_messages(),
+        )
+        .await
+    }
+
+    async fn database_size(&self) -> Result<u64> {
+        self.inner.database_size().await
+    }
+
+    async fn compact(&self) -> Result<()> {
+        self.inner.compact().await
+    }
+
+    async fn rebuild_overview(&self, group: &str) -> Result<OverviewRepair> {
+        self.inner.rebuild_overview(group).await
+    }
+
+    async fn renumber_group(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        let change = Change::RenumberGroup {
+            group: group.to_string(),
+        };
+        self.replicate(vec![change], self.inner.renumber_group(group))
+            .await
+    }
+
+    async fn list_replies(&self, group: &str, message_id: &str) -> Result<Vec<(u64, String)>> {
+        self.inner.list_replies(group, message_id).await
+    }
+
+    async fn get_message_size(&self, message_id: &str) -> Result<Option<u64>> {
+        self.inner.get_message_size(message_id).await
+    }
+
+    async fn get_article_numbers(&self, message_id: &str) -> Result<Vec<(String, u64)>> {
+        self.inner.get_article_numbers(message_id).await
+    }
+
+    async fn get_article_numbers_batch(
+        &self,
+        message_ids: &[String],
+    ) -> Result<std::collections::HashMap<String, Vec<(String, u64)>>> {
+        self.inner.get_article_numbers_batch(message_ids).await
+    }
+
+    async fn get_article_arrivals(
+        &self,
+        message_id: &str,
+    ) -> Result<Vec<(String, chrono::DateTime<chrono::Utc>, bool)>> {
+        self.inner.get_article_arrivals(message_id).await
+    }
+
+    async fn get_body_range(
+        &self,
+        message_id: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Option<(u64, Vec<u8>)>> {
+        self.inner.get_body_range(message_id, offset, len).await
+    }
+
+    async fn set_article_pinned(
+        &self,
+        group: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> Result<bool> {
+        let change = Change::SetPinned {
+            group: group.to_string(),
+            message_id: message_id.to_string(),
+            pinned,
+        };
+        self.replicate(
+            vec![change],
+            self.inner.set_article_pinned(group, message_id, pinned),
+        )
+        .await
+    }
+
+    fn list_pinned_articles(&self) -> PinnedArticleStream<'_> {
+        self.inner.list_pinned_articles()
+    }
+
+    async fn delete_article_by_id(&self, message_id: &str) -> Result<()> {
+        let change = Change::Delete {
+            message_id: message_id.to_string(),
+        };
+        self.replicate(vec![change], self.inner.delete_article_by_id(message_id))
+            .await
+    }
+
+    async fn withdraw_article(&self, message_id: &str) -> Result<()> {
+        // Only sharded storage withdraws articles, undoing a store whose
+        // change was never recorded
+        self.inner.withdraw_article(message_id).await
+    }
+
+    async fn supersede_article(&self, old_id: &str, article: &Message) -> Result<()> {
+        let changes = vec![
+            Change::Delete {
+                message_id: old_id.to_string(),
+            },
+            Change::store(article),
+        ];
+        self.replicate(changes, self.inner.supersede_article(old_id, article))
+            .await
+    }
+
+    async fn is_group_moderated(&self, group: &str) -> Result<bool> {
+        self.inner.is_group_moderated(group).await
+    }
+
+    async fn is_group_archived(&self, group: &str) -> Result<bool> {
+        self.inner.is_group_archived(group).await
+    }
+
+    fn list_archived_groups(&self) -> StringStream<'_> {
+        self.inner.list_archived_groups()
+    }
+
+    async fn group_exists(&self, group: &str) -> Result<bool> {
+        self.inner.group_exists(group).await
+    }
+
+    async fn get_group_watermarks(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        self.inner.get_group_watermarks(group).await
+    }
+
+    fn list_groups_with_counts(&self) -> GroupCountStream<'_> {
+        self.inner.list_groups_with_counts()
+    }
+
+    fn list_group_activity(&self) -> GroupActivityStream<'_> {
+        self.inner.list_group_activity()
+    }
+
+    async fn add_group_with_description(
+        &self,
+        group: &str,
+        moderated: bool,
+        description: &str,
+    ) -> Result<()> {
+        let change = Change::AddGroup {
+            group: group.to_string(),
+            moderated,
+            description: Some(description.to_string()),
+        };
+        self.replicate(
+            vec![change],
+            self.inner
+                .add_group_with_description(group, moderated, description),
+        )
+        .await
+    }
+
+    async fn set_group_description(&self, group: &str, description: &str) -> Result<bool> {
+        let change = Change::SetDescription {
+            group: group.to_string(),
+            description: description.to_string(),
+        };
+        self.replicate(
+            vec![change],
+            self.inner.set_group_description(group, description),
+        )
+        .await
+    }
+
+    fn list_groups_with_descriptions(&self) -> GroupDescriptionStream<'_> {
+        self.inner.list_groups_with_descriptions()
+    }
+
+    async fn add_pending_article(&self, article: &Message) -> Result<u64> {
+        self.inner.add_pending_article(article).await
+    }
+
+    async fn get_pending_article(&self, id: u64) -> Result<Option<PendingArticle>> {
+        self.inner.get_pending_article(id).await
+    }
+
+    fn list_pending_articles(&self) -> PendingArticleStream<'_> {
+        self.inner.list_pending_articles()
+    }
+
+    async fn remove_pending_article(&self, id: u64) -> Result<()> {
+        self.inner.remove_pending_article(id).await
+    }
+
+    async fn add_pending_checkgroups(&self, signer: &str, diff: &str) -> Result<u64> {
+        self.inner.add_pending_checkgroups(signer, diff).await
+    }
+
+    async fn list_pending_checkgroups(&self) -> Result<Vec<PendingCheckgroups>> {
+        self.inner.list_pending_checkgroups().await
+    }
+
+    async fn remove_pending_checkgroups(&self, id: u64) -> Result<bool> {
+        self.inner.remove_pending_checkgroups(id).await
+    }
+
+    async fn add_rejected_article(&self, article: &Message, reason: &str) -> Result<u64> {
+        self.inner.add_rejected_article(article, reason).await
+    }
+
+    async fn get_rejected_article(&self, id: u64) -> Result<Option<RejectedArticle>> {
+        self.inner.get_rejected_article(id).await
+    }
+
+    fn list_rejected_articles(&self) -> RejectedArticleStream<'_> {
+        self.inner.list_rejected_articles()
+    }
+
+    async fn remove_rejected_article(&self, id: u64) -> Result<bool> {
+        self.inner.remove_rejected_article(id).await
+    }
+
+    async fn purge_rejected_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_rejected_before(before).await
+    }
+
+    async fn add_resume_token(&self, token: &str, message_id: &str) -> Result<()> {
+        self.inner.add_resume_token(token, message_id).await
+    }
+
+    async fn get_resume_token(
+        &self,
+        token: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<String>> {
+        self.inner.get_resume_token(token, since).await
+    }
+
+    async fn purge_resume_tokens_before(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        self.inner.purge_resume_tokens_before(before).await
+    }
+
+    async fn remember_message_id(&self, message_id: &str) -> Result<()> {
+        let change = Change::Remember {
+            message_id: message_id.to_string(),
+        };
+        self.replicate(vec![change], self.inner.remember_message_id(message_id))
+            .await
+    }
+
+    async fn in_history(&self, message_id: &str) -> Result<bool> {
+        self.inner.in_history(message_id).await
+    }
+
+    async fn purge_history_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        let change = Change::PurgeHistory {
+            before: before.timestamp(),
+        };
+        self.replicate(vec![change], self.inner.purge_history_before(before))
+            .await
+    }
+
+    async fn record_audit(&self, entry: &AuditEntry) -> Result<()> {
+        self.inner.record_audit(entry).await
+    }
+
+    fn list_audit_since(&self, since: chrono::DateTime<chrono::Utc>) -> AuditStream<'_> {
+        self.inner.list_audit_since(since)
+    }
+
+    async fn purge_audit_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_audit_before(before).await
+    }
+
+    async fn record_change(&self, seq: u64, change: &str) -> Result<()> {
+        self.inner.record_change(seq, change).await
+    }
+
+    async fn append_changes(&self, changes: &[String]) -> Result<()> {
+        self.inner.append_changes(changes).await
+    }
+
+    async fn last_change_seq(&self) -> Result<u64> {
+        self.inner.last_change_seq().await
+    }
+
+    fn list_changes_since(&self, after: u64) -> ChangeStream<'_> {
+        self.inner.list_changes_since(after)
+    }
+
+    async fn purge_changes_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_changes_before(before).await
+    }
+
+    async fn snapshot_to(&self, path: &std::path::Path) -> Result<()> {
+        self.inner.snapshot_to(path).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+
+    fn set_records_changes(&self, records: bool) {
+        self.inner.set_records_changes(records);
+    }
+
+    fn database_count(&self) -> usize {
+        self.inner.database_count()
+    }
+
+    fn database_of(&self, group: &str) -> usize {
+        self.inner.database_of(group)
+    }
+
+    fn set_compression(&self, compression: crate::config::ArticleCompression) {
+        self.inner.set_compression(compression);
+    }
+
+    fn set_overview_headers(&self, headers: Vec<String>) {
+        self.inner.set_overview_headers(headers);
+    }
+
+    fn overview_cache(&self) -> Option<&OverviewCache> {
+        self.inner.overview_cache()
+    }
+}
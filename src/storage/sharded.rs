@@ -0,0 +1,806 @@
+//! Spreading groups over several article databases.
+//!
+//! [`ShardedStorage`] presents the main article database and the databases
+//! of the `[[shard]]` sections as one [`Storage`]. Every group lives in one
+//! of them: the first shard whose patterns match it, or else the main
+//! database. An article is stored in each database holding one of its
+//! groups and numbered there in those groups only, so writes to groups of
+//! different shards do not wait on each other; a store failing in one
+//! database is withdrawn from those it already succeeded in. State that
+//! belongs to no group, such as the moderation queue, resume tokens, the
+//! audit log and the replication log, is kept in the main database.
+
+use super::{
+    ArticleStream, AuditStream, ChangeStream, DynStorage, GroupActivityStream, GroupCountStream,
+    GroupDescriptionStream, GroupWatermarks, OverviewRepair, PendingArticle, PendingArticleStream,
+    PendingCheckgroups, PinnedArticleStream, RejectedArticle, RejectedArticleStream, Storage,
+    StringStream, StringTimestampStream, U64Stream,
+};
+use crate::Message;
+use crate::audit::AuditEntry;
+use crate::config::{ArticleCompression, ShardConfig, SqliteConfig};
+use crate::storage::common::{extract_message_id, parse_newsgroups_from_message};
+use crate::storage::replicated;
+use anyhow::Result;
+use async_stream::stream;
+use async_trait::async_trait;
+use futures_core::Stream;
+use futures_util::TryStreamExt;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Storage spreading groups over several databases.
+pub struct ShardedStorage {
+    /// The main database followed by the database of each shard
+    dbs: Vec<DynStorage>,
+    /// The shards, in the order of their databases in `dbs[1..]`
+    shards: Vec<ShardConfig>,
+}
+
+impl ShardedStorage {
+    /// Combine the `main` database with the database of each shard.
+    #[must_use]
+    pub fn new(main: DynStorage, shards: Vec<(ShardConfig, DynStorage)>) -> Self {
+        let (shards, dbs): (Vec<_>, Vec<_>) = shards.into_iter().unzip();
+        // Only the log of the main database is served to standbys
+        for db in &dbs {
+            db.set_records_changes(false);
+        }
+        Self {
+            dbs: std::iter::once(main).chain(dbs).collect(),
+            shards,
+        }
+    }
+
+    /// Position in `dbs` of the database holding `group`
+    fn index_of(&self, group: &str) -> usize {
+        self.shards
+            .iter()
+            .position(|shard| shard.holds(group))
+            .map_or(0, |i| i + 1)
+    }
+
+    /// The database holding `group`
+    fn holding(&self, group: &str) -> &DynStorage {
+        &self.dbs[self.index_of(group)]
+    }
+
+    fn main(&self) -> &DynStorage {
+        &self.dbs[0]
+    }
+
+    /// Split `groups` by the database holding them
+    fn route(&self, groups: &[String]) -> BTreeMap<usize, Vec<String>> {
+        let mut routes: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for group in groups {
+            routes
+                .entry(self.index_of(group))
+                .or_default()
+                .push(group.clone());
+        }
+        routes
+    }
+
+    /// Store each article in each database of its routes, in the groups it
+    /// holds there.
+    ///
+    /// The shards are written first and the main database last, so that the
+    /// replication log entries of the change are written in the transaction
+    /// completing it. Should a write fail, the articles are withdrawn from
+    /// the databases they were added to.
+    async fn store_routed(
+        &self,
+        stores: Vec<(&Message, BTreeMap<usize, Vec<String>>)>,
+    ) -> Result<()> {
+        let mut writes: Vec<(usize, &Message, Vec<String>)> = stores
+            .into_iter()
+            .flat_map(|(article, routes)| {
+                routes
+                    .into_iter()
+                    .map(move |(i, groups)| (i, article, groups))
+            })
+            .collect();
+        writes.sort_by_key(|(i, ..)| *i == 0);
+        let last = match writes.last() {
+            Some((0, ..)) => writes.pop(),
+            _ => None,
+        };
+        let mut added = Vec::new();
+        let mut stored = replicated::deferred(self.store_writes(writes, &mut added)).await;
+        if stored.is_ok()
+            && let Some(last) = last
+        {
+            stored = self.store_writes(vec![last], &mut added).await;
+        }
+        if stored.is_err() {
+            replicated::deferred(self.withdraw(added)).await;
+        }
+        stored
+    }
+
+    /// Store each article in the groups given in the database given,
+    /// noting in `added` each database it was not held by before.
+    async fn store_writes(
+        &self,
+        writes: Vec<(usize, &Message, Vec<String>)>,
+        added: &mut Vec<(usize, String)>,
+    ) -> Result<()> {
+        for (i, article, groups) in writes {
+            let message_id =
+                extract_message_id(article).ok_or_else(|| anyhow::anyhow!("missing Message-ID"))?;
+            let db = &self.dbs[i];
+            let held = db.get_message_size(&message_id).await?.is_some();
+            db.store_article_in(article, &groups).await?;
+            if !held {
+                added.push((i, message_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `op` on every database, the shards first and the main database
+    /// last, so that the replication log entries of the change are written
+    /// in the transaction completing it.
+    async fn on_every_db<'a, F>(&'a self, op: impl Fn(&'a DynStorage) -> F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let shards: Result<()> = replicated::deferred(async {
+            for db in &self.dbs[1..] {
+                op(db).await?;
+            }
+            Ok(())
+        })
+        .await;
+        shards?;
+        op(self.main()).await
+    }
+
+    /// Remove the articles of a failed store from the databases in `added`,
+    /// so that no database keeps its share of a store that did not happen.
+    async fn withdraw(&self, added: Vec<(usize, String)>) {
+        for (i, message_id) in added {
+            if let Err(e) = self.dbs[i].withdraw_article(&message_id).await {
+                tracing::error!(
+                    database = self.name_of(i),
+                    message_id = %message_id,
+                    error = %e,
+                    "Failed to withdraw an article of a failed store"
+                );
+            }
+        }
+    }
+
+    /// Name of database `index` in log messages and snapshot file names
+    fn name_of(&self, index: usize) -> &str {
+        match index {
+            0 => "main",
+            i => &self.shards[i - 1].name,
+        }
+    }
+}
+
+/// Open the main article database `db_path` and the database of each of
+/// `shards`, combining them when there are shards.
+///
+/// # Errors
+///
+/// Returns an error if a database cannot be opened.
+pub async fn open(
+    db_path: &str,
+    shards: &[ShardConfig],
+    sqlite_cfg: &SqliteConfig,
+) -> Result<DynStorage> {
+    let main = super::open_with_config(db_path, sqlite_cfg).await?;
+    if shards.is_empty() {
+        return Ok(main);
+    }
+    let mut opened = Vec::with_capacity(shards.len());
+    for shard in shards {
+        let storage = super::open_with_config(&shard.db_path, sqlite_cfg)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to open shard '{}': {e}", shard.name))?;
+        opened.push((shard.clone(), storage));
+    }
+    let sharded = ShardedStorage::new(main, opened);
+
+    // Groups are not moved when shards change, so a group carried before
+    // its shard was configured is no longer found
+    for (i, db) in sharded.dbs.iter().enumerate() {
+        let groups: Vec<String> = db.list_groups().try_collect().await?;
+        for group in groups.iter().filter(|g| sharded.index_of(g) != i) {
+            tracing::warn!(
+                group = %group,
+                database = sharded.name_of(i),
+                shard = sharded.name_of(sharded.index_of(group)),
+                "Group is stored outside the shard holding it"
+            );
+        }
+    }
+    Ok(Arc::new(sharded))
+}
+
+/// Stream `items`, or the error that kept them from being gathered.
+fn replay<'a, T: Send + 'a>(
+    items: impl Future<Output = Result<Vec<T>>> + Send + 'a,
+) -> Pin<Box<dyn Stream<Item = Result<T>> + Send + 'a>> {
+    Box::pin(stream! {
+        match items.await {
+            Ok(items) => {
+                for item in items {
+                    yield Ok(item);
+                }
+            }
+            Err(e) => yield Err(e),
+        }
+    })
+}
+
+/// File `path` is snapshotted to for the database named `name`.
+fn snapshot_path(path: &Path, name: &str) -> PathBuf {
+    let mut file = path.as_os_str().to_owned();
+    file.push(format!(".{name}"));
+    PathBuf::from(file)
+}
+
+#[async_trait]
+impl Storage for ShardedStorage {
+    async fn store_article(&self, article: &Message) -> Result<()> {
+        self.store_article_in(article, &parse_newsgroups_from_message(article))
+            .await
+    }
+
+    async fn store_article_in(&self, article: &Message, groups: &[String]) -> Result<()> {
+        if groups.is_empty() {
+            return self.main().store_article_in(article, groups).await;
+        }
+        let routes = self.route(groups);
+        if let Some((&i, groups)) = routes.first_key_value()
+            && routes.len() == 1
+        {
+            return self.dbs[i].store_article_in(article, groups).await;
+        }
+        self.store_routed(vec![(article, routes)]).await
+    }
+
+    async fn store_articles(&self, articles: &[Message]) -> Result<()> {
+        let routes: Vec<_> = articles
+            .iter()
+            .map(|article| self.route(&parse_newsgroups_from_message(article)))
+            .collect();
+        let mut held_by = routes.iter().flat_map(BTreeMap::keys);
+        let first = held_by.next().copied().unwrap_or(0);
+        // A batch held by one database is stored in one transaction there;
+        // otherwise each database stores its part of every article
+        if held_by.all(|&i| i == first) {
+            return self.dbs[first].store_articles(articles).await;
+        }
+        self.store_routed(articles.iter().zip(routes).collect())
+            .await
+    }
+
+    async fn get_article_by_number(&self, group: &str, number: u64) -> Result<Option<Message>> {
+        self.holding(group)
+            .get_article_by_number(group, number)
+            .await
+    }
+
+    async fn get_article_by_id(&self, message_id: &str) -> Result<Option<Message>> {
+        for db in &self.dbs {
+            if let Some(article) = db.get_article_by_id(message_id).await? {
+                return Ok(Some(article));
+            }
+        }
+        Ok(None)
+    }
+
+    fn get_articles_by_ids<'a>(&'a self, message_ids: &'a [String]) -> ArticleStream<'a> {
+        Box::pin(stream! {
+            let mut missing = message_ids.to_vec();
+            for db in &self.dbs {
+                if missing.is_empty() {
+                    break;
+                }
+                let found: Result<Vec<(String, Message)>> =
+                    db.get_articles_by_ids(&missing).try_collect().await;
+                match found {
+                    Ok(found) => {
+                        missing.retain(|id| !found.iter().any(|(found_id, _)| found_id == id));
+                        for article in found {
+                            yield Ok(article);
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        break;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn get_overview_range(&self, group: &str, start: u64, end: u64) -> Result<Vec<String>> {
+        self.holding(group)
+            .get_overview_range(group, start, end)
+            .await
+    }
+
+    async fn add_group(&self, group: &str, moderated: bool) -> Result<()> {
+        self.holding(group).add_group(group, moderated).await
+    }
+
+    async fn set_group_moderated(&self, group: &str, moderated: bool) -> Result<()> {
+        self.holding(group)
+            .set_group_moderated(group, moderated)
+            .await
+    }
+
+    async fn set_group_archived(&self, group: &str, archived: bool) -> Result<()> {
+        self.holding(group)
+            .set_group_archived(group, archived)
+            .await
+    }
+
+    async fn remove_group(&self, group: &str) -> Result<()> {
+        self.holding(group).remove_group(group).await
+    }
+
+    async fn remove_groups_by_pattern(&self, pattern: &str) -> Result<()> {
+        self.on_every_db(|db| db.remove_groups_by_pattern(pattern))
+            .await
+    }
+
+    fn list_groups(&self) -> StringStream<'_> {
+        replay(async move {
+            let mut groups = Vec::new();
+            for db in &self.dbs {
+                groups.extend(db.list_groups().try_collect::<Vec<_>>().await?);
+            }
+            groups.sort();
+            Ok(groups)
+        })
+    }
+
+    fn list_groups_since(&self, since: chrono::DateTime<chrono::Utc>) -> StringStream<'_> {
+        replay(async move {
+            let mut groups = Vec::new();
+            for db in &self.dbs {
+                groups.extend(db.list_groups_since(since).try_collect::<Vec<_>>().await?);
+            }
+            groups.sort();
+            Ok(groups)
+        })
+    }
+
+    fn list_groups_with_times(&self) -> StringTimestampStream<'_> {
+        replay(async move {
+            let mut groups = Vec::new();
+            for db in &self.dbs {
+                groups.extend(db.list_groups_with_times().try_collect::<Vec<_>>().await?);
+            }
+            groups.sort();
+            Ok(groups)
+        })
+    }
+
+    async fn get_group_watermarks(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        self.holding(group).get_group_watermarks(group).await
+    }
+
+    fn list_groups_with_counts(&self) -> GroupCountStream<'_> {
+        replay(async move {
+            let mut groups = Vec::new();
+            for db in &self.dbs {
+                groups.extend(db.list_groups_with_counts().try_collect::<Vec<_>>().await?);
+            }
+            groups.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(groups)
+        })
+    }
+
+    fn list_group_activity(&self) -> GroupActivityStream<'_> {
+        replay(async move {
+            let mut groups = Vec::new();
+            for db in &self.dbs {
+                groups.extend(db.list_group_activity().try_collect::<Vec<_>>().await?);
+            }
+            groups.sort_by(|a, b| {
+                b.1.last_post
+                    .cmp(&a.1.last_post)
+                    .then_with(|| a.0.cmp(&b.0))
+            });
+            Ok(groups)
+        })
+    }
+
+    fn list_article_numbers(&self, group: &str) -> U64Stream<'_> {
+        self.holding(group).list_article_numbers(group)
+    }
+
+    fn list_article_ids(&self, group: &str) -> StringStream<'_> {
+        self.holding(group).list_article_ids(group)
+    }
+
+    fn list_article_ids_since(
+        &self,
+        group: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> StringStream<'_> {
+        self.holding(group).list_article_ids_since(group, since)
+    }
+
+    async fn purge_group_before(
+        &self,
+        group: &str,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        self.holding(group).purge_group_before(group, before).await
+    }
+
+    async fn purge_orphan_messages(&self) -> Result<()> {
+        self.on_every_db(|db| db.purge_orphan_messages()).await
+    }
+
+    async fn database_size(&self) -> Result<u64> {
+        let mut size = 0;
+        for db in &self.dbs {
+            size += db.database_size().await?;
+        }
+        Ok(size)
+    }
+
+    async fn compact(&self) -> Result<()> {
+        for db in &self.dbs {
+            db.compact().await?;
+        }
+        Ok(())
+    }
+
+    async fn rebuild_overview(&self, group: &str) -> Result<OverviewRepair> {
+        self.holding(group).rebuild_overview(group).await
+    }
+
+    async fn renumber_group(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        self.holding(group).renumber_group(group).await
+    }
+
+    async fn list_replies(&self, group: &str, message_id: &str) -> Result<Vec<(u64, String)>> {
+        self.holding(group).list_replies(group, message_id).await
+    }
+
+    async fn get_message_size(&self, message_id: &str) -> Result<Option<u64>> {
+        for db in &self.dbs {
+            if let Some(size) = db.get_message_size(message_id).await? {
+                return Ok(Some(size));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn get_article_numbers(&self, message_id: &str) -> Result<Vec<(String, u64)>> {
+        let mut numbers = Vec::new();
+        for (i, db) in self.dbs.iter().enumerate() {
+            numbers.extend(
+                db.get_article_numbers(message_id)
+                    .await?
+                    .into_iter()
+                    .filter(|(group, _)| self.index_of(group) == i),
+            );
+        }
+        numbers.sort();
+        Ok(numbers)
+    }
+
+    async fn get_article_numbers_batch(
+        &self,
+        message_ids: &[String],
+    ) -> Result<std::collections::HashMap<String, Vec<(String, u64)>>> {
+        let mut numbers: std::collections::HashMap<String, Vec<(String, u64)>> =
+            std::collections::HashMap::new();
+        for (i, db) in self.dbs.iter().enumerate() {
+            for (message_id, found) in db.get_article_numbers_batch(message_ids).await? {
+                numbers.entry(message_id).or_default().extend(
+                    found
+                        .into_iter()
+                        .filter(|(group, _)| self.index_of(group) == i),
+                );
+            }
+        }
+        numbers.retain(|_, found| !found.is_empty());
+        for found in numbers.values_mut() {
+            found.sort();
+        }
+        Ok(numbers)
+    }
+
+    async fn get_article_arrivals(
+        &self,
+        message_id: &str,
+    ) -> Result<Vec<(String, chrono::DateTime<chrono::Utc>, bool)>> {
+        let mut arrivals = Vec::new();
+        for (i, db) in self.dbs.iter().enumerate() {
+            arrivals.extend(
+                db.get_article_arrivals(message_id)
+                    .await?
+                    .into_iter()
+                    .filter(|(group, _, _)| self.index_of(group) == i),
+            );
+        }
+        arrivals.sort();
+        Ok(arrivals)
+    }
+
+    async fn get_body_range(
+        &self,
+        message_id: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Option<(u64, Vec<u8>)>> {
+        for db in &self.dbs {
+            if let Some(range) = db.get_body_range(message_id, offset, len).await? {
+                return Ok(Some(range));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn set_article_pinned(
+        &self,
+        group: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> Result<bool> {
+        self.holding(group)
+            .set_article_pinned(group, message_id, pinned)
+            .await
+    }
+
+    fn list_pinned_articles(&self) -> PinnedArticleStream<'_> {
+        replay(async move {
+            let mut pinned = Vec::new();
+            for db in &self.dbs {
+                pinned.extend(db.list_pinned_articles().try_collect::<Vec<_>>().await?);
+            }
+            pinned.sort();
+            Ok(pinned)
+        })
+    }
+
+    async fn delete_article_by_id(&self, message_id: &str) -> Result<()> {
+        self.on_every_db(|db| db.delete_article_by_id(message_id))
+            .await
+    }
+
+    async fn withdraw_article(&self, message_id: &str) -> Result<()> {
+        self.on_every_db(|db| db.withdraw_article(message_id)).await
+    }
+
+    async fn supersede_article(&self, old_id: &str, article: &Message) -> Result<()> {
+        // Each database replaces the article on its own, so a failure can
+        // leave the old article removed without the new one stored. The
+        // store comes last and writes the log entries of both
+        replicated::deferred(self.delete_article_by_id(old_id)).await?;
+        let msg_id =
+            extract_message_id(article).ok_or_else(|| anyhow::anyhow!("missing Message-ID"))?;
+        if self.get_message_size(&msg_id).await?.is_none() {
+            self.store_article(article).await?;
+        }
+        Ok(())
+    }
+
+    async fn is_group_moderated(&self, group: &str) -> Result<bool> {
+        self.holding(group).is_group_moderated(group).await
+    }
+
+    async fn is_group_archived(&self, group: &str) -> Result<bool> {
+        self.holding(group).is_group_archived(group).await
+    }
+
+    fn list_archived_groups(&self) -> StringStream<'_> {
+        replay(async move {
+            let mut groups = Vec::new();
+            for db in &self.dbs {
+                groups.extend(db.list_archived_groups().try_collect::<Vec<_>>().await?);
+            }
+            groups.sort();
+            Ok(groups)
+        })
+    }
+
+    async fn group_exists(&self, group: &str) -> Result<bool> {
+        self.holding(group).group_exists(group).await
+    }
+
+    async fn add_group_with_description(
+        &self,
+        group: &str,
+        moderated: bool,
+        description: &str,
+    ) -> Result<()> {
+        self.holding(group)
+            .add_group_with_description(group, moderated, description)
+            .await
+    }
+
+    async fn set_group_description(&self, group: &str, description: &str) -> Result<bool> {
+        self.holding(group)
+            .set_group_description(group, description)
+            .await
+    }
+
+    fn list_groups_with_descriptions(&self) -> GroupDescriptionStream<'_> {
+        replay(async move {
+            let mut groups = Vec::new();
+            for db in &self.dbs {
+                groups.extend(
+                    db.list_groups_with_descriptions()
+                        .try_collect::<Vec<_>>()
+                        .await?,
+                );
+            }
+            groups.sort();
+            Ok(groups)
+        })
+    }
+
+    async fn add_pending_article(&self, article: &Message) -> Result<u64> {
+        self.main().add_pending_article(article).await
+    }
+
+    async fn get_pending_article(&self, id: u64) -> Result<Option<PendingArticle>> {
+        self.main().get_pending_article(id).await
+    }
+
+    fn list_pending_articles(&self) -> PendingArticleStream<'_> {
+        self.main().list_pending_articles()
+    }
+
+    async fn remove_pending_article(&self, id: u64) -> Result<()> {
+        self.main().remove_pending_article(id).await
+    }
+
+    async fn add_pending_checkgroups(&self, signer: &str, diff: &str) -> Result<u64> {
+        self.main().add_pending_checkgroups(signer, diff).await
+    }
+
+    async fn list_pending_checkgroups(&self) -> Result<Vec<PendingCheckgroups>> {
+        self.main().list_pending_checkgroups().await
+    }
+
+    async fn remove_pending_checkgroups(&self, id: u64) -> Result<bool> {
+        self.main().remove_pending_checkgroups(id).await
+    }
+
+    async fn add_rejected_article(&self, article: &Message, reason: &str) -> Result<u64> {
+        self.main().add_rejected_article(article, reason).await
+    }
+
+    async fn get_rejected_article(&self, id: u64) -> Result<Option<RejectedArticle>> {
+        self.main().get_rejected_article(id).await
+    }
+
+    fn list_rejected_articles(&self) -> RejectedArticleStream<'_> {
+        self.main().list_rejected_articles()
+    }
+
+    async fn remove_rejected_article(&self, id: u64) -> Result<bool> {
+        self.main().remove_rejected_article(id).await
+    }
+
+    async fn purge_rejected_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.main().purge_rejected_before(before).await
+    }
+
+    async fn add_resume_token(&self, token: &str, message_id: &str) -> Result<()> {
+        self.main().add_resume_token(token, message_id).await
+    }
+
+    async fn get_resume_token(
+        &self,
+        token: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<String>> {
+        self.main().get_resume_token(token, since).await
+    }
+
+    async fn purge_resume_tokens_before(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        self.main().purge_resume_tokens_before(before).await
+    }
+
+    async fn remember_message_id(&self, message_id: &str) -> Result<()> {
+        self.main().remember_message_id(message_id).await
+    }
+
+    async fn in_history(&self, message_id: &str) -> Result<bool> {
+        // Deleting an article records it in the history of every database
+        for db in &self.dbs {
+            if db.in_history(message_id).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    async fn purge_history_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.on_every_db(|db| db.purge_history_before(before)).await
+    }
+
+    async fn record_audit(&self, entry: &AuditEntry) -> Result<()> {
+        self.main().record_audit(entry).await
+    }
+
+    fn list_audit_since(&self, since: chrono::DateTime<chrono::Utc>) -> AuditStream<'_> {
+        self.main().list_audit_since(since)
+    }
+
+    async fn purge_audit_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.main().purge_audit_before(before).await
+    }
+
+    async fn record_change(&self, seq: u64, change: &str) -> Result<()> {
+        self.main().record_change(seq, change).await
+    }
+
+    async fn append_changes(&self, changes: &[String]) -> Result<()> {
+        self.main().append_changes(changes).await
+    }
+
+    async fn last_change_seq(&self) -> Result<u64> {
+        self.main().last_change_seq().await
+    }
+
+    fn list_changes_since(&self, after: u64) -> ChangeStream<'_> {
+        self.main().list_changes_since(after)
+    }
+
+    async fn purge_changes_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.main().purge_changes_before(before).await
+    }
+
+    /// The main database is written to `path` and each shard next to it,
+    /// with the name of the shard appended.
+    async fn snapshot_to(&self, path: &Path) -> Result<()> {
+        self.main().snapshot_to(path).await?;
+        for (i, db) in self.dbs.iter().enumerate().skip(1) {
+            db.snapshot_to(&snapshot_path(path, self.name_of(i)))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<()> {
+        for db in &self.dbs {
+            db.ping().await?;
+        }
+        Ok(())
+    }
+
+    fn set_records_changes(&self, records: bool) {
+        self.main().set_records_changes(records);
+    }
+
+    fn database_count(&self) -> usize {
+        self.dbs.len()
+    }
+
+    fn database_of(&self, group: &str) -> usize {
+        self.index_of(group)
+    }
+
+    fn set_compression(&self, compression: ArticleCompression) {
+        for db in &self.dbs {
+            db.set_compression(compression.clone());
+        }
+    }
+
+    fn set_overview_headers(&self, headers: Vec<String>) {
+        for db in &self.dbs {
+            db.set_overview_headers(headers.clone());
+        }
+    }
+}
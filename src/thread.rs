@@ -0,0 +1,129 @@
+//! Threads of articles for the XTHREAD command.
+//!
+//! A reader showing one discussion would otherwise have to fetch the
+//! overview of the whole group and rebuild the threads itself. The
+//! Message-IDs named in the `References` and `In-Reply-To` headers of each
+//! article are indexed when it is stored. The thread containing an article
+//! is found by following those headers up to its oldest ancestor, then
+//! repeatedly looking up the replies to each article found, as far as the
+//! articles still carried allow.
+
+use crate::Message;
+use crate::handlers::utils::get_header_value;
+use crate::storage::Storage;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+/// Largest number of articles returned for one thread.
+pub const MAX_THREAD_ARTICLES: usize = 5000;
+
+/// Message-IDs named in the `References` and `In-Reply-To` headers of
+/// `article`, oldest ancestor first and without repeats.
+#[must_use]
+pub fn references(article: &Message) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for header in ["References", "In-Reply-To"] {
+        let Some(value) = get_header_value(article, header) else {
+            continue;
+        };
+        // In-Reply-To may carry comments or phrases besides the
+        // Message-IDs, so only bracketed tokens are taken
+        let mut rest = value.as_str();
+        while let Some(start) = rest.find('<') {
+            let Some(len) = rest[start..].find('>') else {
+                break;
+            };
+            let id = &rest[start..=start + len];
+            if !id[1..].contains('<') && !ids.iter().any(|known| known == id) {
+                ids.push(id.to_string());
+            }
+            rest = &rest[start + len + 1..];
+        }
+    }
+    ids
+}
+
+/// Numbers in `group` of the articles in the thread containing `article`,
+/// in ascending order.
+///
+/// The thread is made of the ancestors of `article`, as named by its
+/// headers and theirs, and every article replying, directly or through
+/// others, to one of them or to `article`. At most [`MAX_THREAD_ARTICLES`]
+/// numbers are returned.
+///
+/// # Errors
+///
+/// Returns an error if the storage fails.
+pub async fn thread_numbers(
+    storage: &dyn Storage,
+    group: &str,
+    article: &Message,
+) -> Result<Vec<u64>> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<String> = VecDeque::new();
+    seen.extend(get_header_value(article, "Message-ID"));
+    queue.extend(seen.iter().cloned());
+
+    // Replies that only name their parent in In-Reply-To leave the rest of
+    // the ancestry to the parent's own headers
+    let mut ancestors = references(article);
+    while let Some(id) = ancestors.pop() {
+        if seen.len() >= MAX_THREAD_ARTICLES || !seen.insert(id.clone()) {
+            continue;
+        }
+        if let Some(ancestor) = storage.get_article_by_id(&id).await? {
+            ancestors.extend(references(&ancestor));
+        }
+        queue.push_back(id);
+    }
+
+    let mut numbers = BTreeSet::new();
+    for id in &queue {
+        if let Some((_, number)) = storage
+            .get_article_numbers(id)
+            .await?
+            .into_iter()
+            .find(|(name, _)| name == group)
+        {
+            numbers.insert(number);
+        }
+    }
+    while let Some(id) = queue.pop_front() {
+        if numbers.len() >= MAX_THREAD_ARTICLES {
+            break;
+        }
+        for (number, reply) in storage.list_replies(group, &id).await? {
+            numbers.insert(number);
+            if seen.insert(reply.clone()) {
+                queue.push_back(reply);
+            }
+        }
+    }
+    Ok(numbers.into_iter().take(MAX_THREAD_ARTICLES).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smallvec::smallvec;
+
+    #[test]
+    fn references_are_taken_from_both_headers_once() {
+        let article = Message {
+            headers: smallvec![
+                ("References".to_string(), "<a@x> <b@x>\t<c@x>".to_string()),
+                (
+                    "In-Reply-To".to_string(),
+                    "Bob's message <c@x> (sent <d@x>)".to_string()
+                ),
+            ],
+            body: String::new(),
+        };
+        assert_eq!(references(&article), ["<a@x>", "<b@x>", "<c@x>", "<d@x>"]);
+        let article = Message {
+            headers: smallvec![("Subject".to_string(), "hi".to_string())],
+            body: String::new(),
+        };
+        assert!(references(&article).is_empty());
+    }
+}
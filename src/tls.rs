@@ -0,0 +1,329 @@
+//! Protocol settings and handshake limits of TLS connections.
+//!
+//! The `[tls]` table chooses the oldest protocol version and the cipher
+//! suites accepted, and how clients may resume earlier sessions: readers on
+//! mobile networks reconnect often, and resuming skips the certificate
+//! exchange and key agreement of a full handshake. Sessions resume from a
+//! ticket sealed with a key known only to this process, or from a cache of
+//! recent sessions, so neither outlives a restart.
+//!
+//! Full handshakes are expensive for the server, so on the TLS listeners
+//! an address starting more than `handshakes_per_minute` of them is
+//! disconnected before the handshake until the minute is over.
+//!
+//! With `reload_on_change` the directories holding the certificate and key
+//! are watched, and a renewed certificate is loaded without SIGHUP once its
+//! key is confirmed to belong to it.
+
+use crate::config::{CipherPolicy, TlsConfig, TlsVersion};
+use anyhow::Result;
+use dashmap::DashMap;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::server::{
+    NoServerSessionStorage, ProducesTickets, ServerSessionMemoryCache,
+};
+use tokio_rustls::rustls::{
+    Certificate, ConfigBuilder, PrivateKey, ServerConfig, SignatureScheme, SupportedCipherSuite,
+    SupportedProtocolVersion, Ticketer, WantsVerifier, cipher_suite, sign, version,
+};
+
+/// Cipher suites with 256-bit keys, offered under [`CipherPolicy::Strict`]
+static STRICT_CIPHER_SUITES: &[SupportedCipherSuite] = &[
+    cipher_suite::TLS13_AES_256_GCM_SHA384,
+    cipher_suite::TLS13_CHACHA20_POLY1305_SHA256,
+    cipher_suite::TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+    cipher_suite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+    cipher_suite::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+    cipher_suite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+];
+
+/// Start a server configuration with the protocol versions and cipher
+/// suites of `settings`.
+///
+/// # Errors
+///
+/// Returns an error if no offered cipher suite suits the protocol versions.
+pub fn server_config_builder(
+    settings: &TlsConfig,
+) -> Result<ConfigBuilder<ServerConfig, WantsVerifier>> {
+    let suites = match settings.cipher_policy {
+        CipherPolicy::Default => tokio_rustls::rustls::DEFAULT_CIPHER_SUITES,
+        CipherPolicy::Strict => STRICT_CIPHER_SUITES,
+    };
+    let versions: &[&SupportedProtocolVersion] = match settings.min_version {
+        TlsVersion::Tls12 => &[&version::TLS13, &version::TLS12],
+        TlsVersion::Tls13 => &[&version::TLS13],
+    };
+    let builder = ServerConfig::builder()
+        .with_cipher_suites(suites)
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .map_err(|e| anyhow::anyhow!("Unusable [tls] settings: {e}"))?;
+    Ok(builder)
+}
+
+/// Set up session resumption on `config` as `settings` ask.
+///
+/// # Errors
+///
+/// Returns an error if no ticket key can be generated.
+pub fn configure_resumption(config: &mut ServerConfig, settings: &TlsConfig) -> Result<()> {
+    config.session_storage = if settings.session_cache_size > 0 {
+        ServerSessionMemoryCache::new(settings.session_cache_size)
+    } else {
+        Arc::new(NoServerSessionStorage {})
+    };
+    if settings.ticket_lifetime_secs > 0 {
+        config.ticketer = Arc::new(LimitedTicketer {
+            inner: Ticketer::new()?,
+            lifetime: settings.ticket_lifetime_secs,
+        });
+    }
+    Ok(())
+}
+
+/// Signature schemes tried when checking a key against its certificate,
+/// with the algorithm verifying each
+static KEY_CHECK_SCHEMES: &[(SignatureScheme, &webpki::SignatureAlgorithm)] = &[
+    (SignatureScheme::ED25519, &webpki::ED25519),
+    (
+        SignatureScheme::ECDSA_NISTP256_SHA256,
+        &webpki::ECDSA_P256_SHA256,
+    ),
+    (
+        SignatureScheme::ECDSA_NISTP384_SHA384,
+        &webpki::ECDSA_P384_SHA384,
+    ),
+    (
+        SignatureScheme::RSA_PSS_SHA256,
+        &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    ),
+];
+
+/// Check that `key` is the private key of the certificate `cert` by
+/// signing with it and verifying the signature against the certificate.
+/// rustls accepts a mismatched pair and only fails the handshakes.
+///
+/// # Errors
+///
+/// Returns an error if the key is unusable or belongs to another
+/// certificate.
+pub fn check_key_matches(cert: &Certificate, key: &PrivateKey) -> Result<()> {
+    let key = sign::any_supported_type(key)
+        .map_err(|e| anyhow::anyhow!("unsupported private key: {e}"))?;
+    let offered: Vec<SignatureScheme> = KEY_CHECK_SCHEMES.iter().map(|(s, _)| *s).collect();
+    let signer = key
+        .choose_scheme(&offered)
+        .ok_or_else(|| anyhow::anyhow!("no signature scheme suits the private key"))?;
+    let Some((_, algorithm)) = KEY_CHECK_SCHEMES
+        .iter()
+        .find(|(scheme, _)| *scheme == signer.scheme())
+    else {
+        anyhow::bail!("no signature scheme suits the private key");
+    };
+    let message = b"renews certificate key check";
+    let signature = signer.sign(message)?;
+    let cert = webpki::EndEntityCert::try_from(cert.0.as_slice())
+        .map_err(|e| anyhow::anyhow!("unreadable certificate: {e}"))?;
+    cert.verify_signature(algorithm, message, &signature)
+        .map_err(|_| anyhow::anyhow!("the private key does not belong to the certificate"))
+}
+
+/// Watch the directories holding `files`, sending on the returned channel
+/// whenever something in them is created, changed or removed. Directories
+/// are watched rather than the files, as certbot renews by pointing the
+/// symlinks of its `live` directory at new files. Watching stops when the
+/// watcher is dropped.
+///
+/// # Errors
+///
+/// Returns an error if a directory cannot be watched.
+pub fn watch_files(files: &[&str]) -> Result<(RecommendedWatcher, mpsc::Receiver<()>)> {
+    let (tx, rx) = mpsc::channel(1);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        // Reading the files to reload them must not trigger another reload
+        if event.is_ok_and(|event| {
+            matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            )
+        }) {
+            let _ = tx.try_send(());
+        }
+    })?;
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for file in files {
+        let path = Path::new(file);
+        // Also watch where a symlink points, for files rewritten in place
+        let target = std::fs::canonicalize(path).ok();
+        for path in std::iter::once(path).chain(target.as_deref()) {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            if !dirs.contains(&dir) {
+                watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+                dirs.push(dir);
+            }
+        }
+    }
+    Ok((watcher, rx))
+}
+
+/// Seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Session tickets refused once older than a configured lifetime.
+///
+/// The rustls ticketer rotates its keys on a fixed schedule, so the time a
+/// ticket was issued is sealed into it and checked when it comes back.
+struct LimitedTicketer {
+    inner: Arc<dyn ProducesTickets>,
+    lifetime: u32,
+}
+
+impl ProducesTickets for LimitedTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let mut sealed = unix_now().to_be_bytes().to_vec();
+        sealed.extend_from_slice(plain);
+        self.inner.encrypt(&sealed)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        let mut plain = self.inner.decrypt(cipher)?;
+        let issued = u64::from_be_bytes(plain.get(..8)?.try_into().ok()?);
+        if unix_now().saturating_sub(issued) > u64::from(self.lifetime) {
+            return None;
+        }
+        plain.drain(..8);
+        Some(plain)
+    }
+}
+
+/// How long handshakes are counted against an address
+const HANDSHAKE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Addresses tracked before those whose minute is over are forgotten
+const HANDSHAKE_PRUNE_THRESHOLD: usize = 4096;
+
+/// Counts the TLS handshakes each address starts per minute.
+#[derive(Debug, Default)]
+pub struct HandshakeLimiter {
+    windows: DashMap<IpAddr, (Instant, u32)>,
+}
+
+impl HandshakeLimiter {
+    /// Record a handshake from `ip`, unless it has already started
+    /// `per_minute` in the current minute. A limit of 0 allows every
+    /// handshake.
+    pub fn try_handshake(&self, ip: IpAddr, per_minute: u32) -> bool {
+        if per_minute == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        if self.windows.len() > HANDSHAKE_PRUNE_THRESHOLD {
+            self.windows
+                .retain(|_, (start, _)| now.duration_since(*start) < HANDSHAKE_WINDOW);
+        }
+        let mut window = self.windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(window.0) >= HANDSHAKE_WINDOW {
+            *window = (now, 0);
+        }
+        if window.1 >= per_minute {
+            return false;
+        }
+        window.1 += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshakes_are_limited_per_address() {
+        let limiter = HandshakeLimiter::default();
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+        assert!(limiter.try_handshake(a, 2));
+        assert!(limiter.try_handshake(a, 2));
+        assert!(!limiter.try_handshake(a, 2));
+        assert!(limiter.try_handshake(b, 2));
+        assert!(limiter.try_handshake(a, 0));
+    }
+
+    #[test]
+    fn expired_tickets_are_refused() {
+        let ticketer = LimitedTicketer {
+            inner: Ticketer::new().unwrap(),
+            lifetime: 60,
+        };
+        let ticket = ticketer.encrypt(b"session").unwrap();
+        assert_eq!(ticketer.decrypt(&ticket).unwrap(), b"session");
+
+        let mut stale = (unix_now() - 61).to_be_bytes().to_vec();
+        stale.extend_from_slice(b"session");
+        let stale = ticketer.inner.encrypt(&stale).unwrap();
+        assert!(ticketer.decrypt(&stale).is_none());
+    }
+
+    #[test]
+    fn keys_of_other_certificates_are_refused() {
+        use rcgen::{CertifiedKey, generate_simple_self_signed};
+
+        let pair = || {
+            let CertifiedKey { cert, signing_key } =
+                generate_simple_self_signed(["localhost".to_string()]).unwrap();
+            (
+                Certificate(cert.der().to_vec()),
+                PrivateKey(signing_key.serialize_der()),
+            )
+        };
+        let (cert, key) = pair();
+        let (_, other_key) = pair();
+        assert!(check_key_matches(&cert, &key).is_ok());
+        assert!(check_key_matches(&cert, &other_key).is_err());
+    }
+
+    #[tokio::test]
+    async fn changes_to_watched_files_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("fullchain.pem");
+        std::fs::write(&cert, "old").unwrap();
+        let (_watcher, mut changes) = watch_files(&[cert.to_str().unwrap()]).unwrap();
+
+        std::fs::write(&cert, "renewed").unwrap();
+        tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .expect("change reported")
+            .unwrap();
+    }
+
+    #[test]
+    fn strict_policy_with_tls13_only_builds() {
+        let settings = TlsConfig {
+            min_version: TlsVersion::Tls13,
+            cipher_policy: CipherPolicy::Strict,
+            ..TlsConfig::default()
+        };
+        assert!(server_config_builder(&settings).is_ok());
+    }
+}
@@ -0,0 +1,165 @@
+//! Client connection transport that can be upgraded to TLS mid-session.
+//!
+//! Handlers read and write through type-erased halves, so the underlying
+//! socket cannot be recovered from them once a session is running. The
+//! [`UpgradableStream`] keeps the socket in a shared slot instead: both
+//! halves are clones of the same handle, and STARTTLS swaps the plaintext
+//! socket for the negotiated TLS stream in place.
+//!
+//! Writes are buffered in the same slot and only reach the socket when the
+//! session is about to wait for input, or when the buffer fills. Commands a
+//! client pipelines in one segment are therefore all answered before the
+//! responses are sent, in as few writes as possible, while a response is
+//! always on its way before the server blocks on the client.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::TlsAcceptor;
+
+/// A bidirectional byte stream usable as a client connection.
+pub trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ClientStream for T {}
+
+type BoxedStream = Box<dyn ClientStream>;
+
+/// Responses are written to the socket once this much output is pending.
+const WRITE_BUFFER_SIZE: usize = 16 * 1024;
+
+struct Shared {
+    stream: Option<BoxedStream>,
+    /// Output not yet written to the stream.
+    output: Vec<u8>,
+    /// Whether output has been written since the stream was last flushed.
+    unflushed: bool,
+}
+
+impl Shared {
+    fn stream(&mut self) -> io::Result<Pin<&mut BoxedStream>> {
+        self.stream
+            .as_mut()
+            .map(Pin::new)
+            .ok_or_else(|| io::ErrorKind::NotConnected.into())
+    }
+
+    /// Write all buffered output to the stream without flushing it.
+    fn poll_write_output(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.output.is_empty() {
+            let Some(stream) = self.stream.as_mut() else {
+                return Poll::Ready(Err(io::ErrorKind::NotConnected.into()));
+            };
+            match ready!(Pin::new(stream).poll_write(cx, &self.output))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                n => {
+                    self.output.drain(..n);
+                    self.unflushed = true;
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Write all buffered output and flush the stream.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_output(cx))?;
+        if self.unflushed {
+            ready!(self.stream()?.poll_flush(cx))?;
+            self.unflushed = false;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Shared handle to a client connection that can be upgraded with STARTTLS.
+///
+/// The lock is only held for the duration of a single poll, never across an
+/// await point.
+#[derive(Clone)]
+pub struct UpgradableStream {
+    inner: Arc<Mutex<Shared>>,
+}
+
+impl UpgradableStream {
+    pub fn new<S: ClientStream + 'static>(stream: S) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Shared {
+                stream: Some(Box::new(stream)),
+                output: Vec::with_capacity(WRITE_BUFFER_SIZE),
+                unflushed: false,
+            })),
+        }
+    }
+
+    /// Negotiate TLS over the current stream and use it for all further I/O,
+    /// returning the names of the client certificate if one was presented.
+    ///
+    /// Pending output is sent over the plaintext stream first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream is already being upgraded or the TLS
+    /// handshake fails. The connection is unusable afterwards in either case.
+    pub async fn start_tls(&self, acceptor: &TlsAcceptor) -> io::Result<Vec<String>> {
+        std::future::poll_fn(|cx| self.lock().poll_drain(cx)).await?;
+        let plain = self
+            .lock()
+            .stream
+            .take()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        let tls = acceptor.accept(plain).await?;
+        let names = crate::client_cert::peer_names(&tls);
+        self.lock().stream = Some(Box::new(tls));
+        Ok(names)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl AsyncRead for UpgradableStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        // The client may be waiting for our responses before it sends more
+        let mut shared = self.lock();
+        ready!(shared.poll_drain(cx))?;
+        shared.stream()?.poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UpgradableStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut shared = self.lock();
+        shared.stream()?;
+        if shared.output.len() + buf.len() > WRITE_BUFFER_SIZE {
+            ready!(shared.poll_write_output(cx))?;
+        }
+        if buf.len() >= WRITE_BUFFER_SIZE {
+            let n = ready!(shared.stream()?.poll_write(cx, buf))?;
+            shared.unflushed = true;
+            return Poll::Ready(Ok(n));
+        }
+        shared.output.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.lock().poll_drain(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut shared = self.lock();
+        ready!(shared.poll_drain(cx))?;
+        shared.stream()?.poll_shutdown(cx)
+    }
+}
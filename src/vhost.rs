@@ -0,0 +1,176 @@
+//! Virtual hosting of several news sites by one server.
+//!
+//! Hosting providers serving many small communities would otherwise run a
+//! server process per site. Each `[[vhost]]` is a news site of its own: its
+//! groups and articles are kept in a separate article database, so two
+//! sites may carry groups of the same name, and the headers it adds name it
+//! by its own `site_name`. Users, group rules, filters and limits are shared
+//! with the main site.
+//!
+//! A connection is served the site named by the `vhost` of the listener
+//! that accepted it, otherwise the site whose host name the client asked
+//! for with TLS SNI, otherwise the main site. Until it authenticates, the
+//! client may switch sites with `XHOST <host>`, naming a virtual host by
+//! its name or one of its host names, or the main site by its `site_name`.
+//!
+//! Peering, replication, digests, the HTTP API and the WebSocket bridge
+//! serve the main site only.
+
+use crate::config::{Config, ReplicationConfig, VhostConfig};
+use crate::queue::ArticleQueue;
+use crate::storage::{self, DynStorage};
+use anyhow::{Result, anyhow};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// What a session works on: the articles, settings and article queue of
+/// one news site.
+#[derive(Clone)]
+pub struct Site {
+    pub storage: DynStorage,
+    pub config: Arc<RwLock<Config>>,
+    pub queue: ArticleQueue,
+}
+
+/// A virtual news site and where it is served from.
+pub struct VirtualHost {
+    pub config: VhostConfig,
+    pub site: Site,
+}
+
+/// The virtual hosts of a server.
+#[derive(Default)]
+pub struct VirtualHosts {
+    hosts: Vec<VirtualHost>,
+}
+
+impl VirtualHosts {
+    #[must_use]
+    pub fn new(hosts: Vec<VirtualHost>) -> Self {
+        Self { hosts }
+    }
+
+    /// Open the article database of every virtual host configured in `cfg`,
+    /// each with an empty article queue of the configured capacity.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database cannot be opened.
+    pub async fn open(cfg: &Config) -> Result<Self> {
+        let mut hosts = Vec::with_capacity(cfg.vhosts.len());
+        for vhost in &cfg.vhosts {
+            let storage = storage::open_with_config(&vhost.db_path, &cfg.sqlite)
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "Failed to open database of virtual host '{}': {e}",
+                        vhost.name
+                    )
+                })?;
+            storage.set_compression(cfg.article_compression());
+            storage.set_overview_headers(cfg.extra_overview_headers());
+            hosts.push(VirtualHost {
+                config: vhost.clone(),
+                site: Site {
+                    storage,
+                    config: Arc::new(RwLock::new(site_config(cfg, vhost))),
+                    queue: ArticleQueue::new(cfg.article_queue_capacity),
+                },
+            });
+        }
+        Ok(Self::new(hosts))
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &VirtualHost> {
+        self.hosts.iter()
+    }
+
+    /// The virtual host called `name` in the configuration.
+    #[must_use]
+    pub fn named(&self, name: &str) -> Option<&VirtualHost> {
+        self.hosts.iter().find(|host| host.config.name == name)
+    }
+
+    /// The virtual host known by `host`, its name or one of its host names.
+    #[must_use]
+    pub fn find(&self, host: &str) -> Option<&VirtualHost> {
+        self.hosts
+            .iter()
+            .find(|vhost| vhost.config.answers_to(host))
+    }
+
+    /// Apply the runtime-adjustable values of a reloaded configuration to
+    /// every virtual host.
+    pub async fn update_runtime(&self, cfg: &Config) {
+        for host in &self.hosts {
+            let updated = site_config(cfg, &host.config);
+            host.site.config.write().await.update_runtime(updated);
+        }
+    }
+}
+
+/// Host name the client of `stream` asked for with TLS SNI.
+pub fn server_name<S>(stream: &tokio_rustls::server::TlsStream<S>) -> Option<String> {
+    stream.get_ref().1.server_name().map(str::to_string)
+}
+
+/// The configuration of the site `vhost`: that of the main site, naming
+/// the virtual host and its database instead. Virtual hosts are not
+/// replicated.
+#[must_use]
+pub fn site_config(cfg: &Config, vhost: &VhostConfig) -> Config {
+    let mut site = cfg.clone();
+    site.site_name = vhost
+        .site_name
+        .clone()
+        .unwrap_or_else(|| vhost.name.clone());
+    site.path_aliases = Vec::new();
+    site.db_path = vhost.db_path.clone();
+    site.replication = ReplicationConfig::default();
+    site.vhosts = Vec::new();
+    site
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sites_are_named_after_the_virtual_host() {
+        let cfg: Config = toml::from_str(
+            r#"
+            addr = ":119"
+            site_name = "news.example"
+            path_aliases = ["old.example"]
+
+            [[vhost]]
+            name = "a"
+            hostnames = ["news.a.example"]
+            db_path = "sqlite:///tmp/a.db"
+
+            [[vhost]]
+            name = "b"
+            site_name = "news.b.example"
+            db_path = "sqlite:///tmp/b.db"
+            "#,
+        )
+        .unwrap();
+
+        let a = site_config(&cfg, &cfg.vhosts[0]);
+        assert_eq!(a.site_name, "a");
+        assert!(a.path_aliases.is_empty());
+        assert_eq!(a.db_path, "sqlite:///tmp/a.db");
+        assert_eq!(
+            site_config(&cfg, &cfg.vhosts[1]).site_name,
+            "news.b.example"
+        );
+        assert!(cfg.vhosts[0].answers_to("NEWS.A.example"));
+        assert!(cfg.vhosts[0].answers_to("a"));
+        assert!(!cfg.vhosts[1].answers_to("news.a.example"));
+    }
+}
@@ -9,6 +9,21 @@ pub fn wildmat(pattern: &str, text: &str) -> bool {
     }
 }
 
+/// Match `text` against a list of patterns, as in a wildmat of RFC 3977
+/// section 4.2: the last pattern that matches decides, and one prefixed
+/// with `!` excludes rather than includes.
+#[must_use]
+pub fn wildmat_list<S: AsRef<str>>(patterns: &[S], text: &str) -> bool {
+    patterns
+        .iter()
+        .rev()
+        .find_map(|pattern| match pattern.as_ref().strip_prefix('!') {
+            Some(pattern) => wildmat(pattern, text).then_some(false),
+            None => wildmat(pattern.as_ref(), text).then_some(true),
+        })
+        .unwrap_or(false)
+}
+
 fn pattern_to_regex(pattern: &str) -> Result<Regex, regex::Error> {
     let mut regex = String::from("^");
     let mut chars = pattern.chars().peekable();
@@ -81,7 +96,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::wildmat;
+    use super::{wildmat, wildmat_list};
 
     #[test]
     fn test_simple() {
@@ -103,4 +118,13 @@ mod tests {
         assert!(wildmat("a\\*b", "a*b"));
         assert!(!wildmat("a\\*b", "axxb"));
     }
+
+    #[test]
+    fn test_list_last_match_wins() {
+        let patterns = ["comp.*", "!comp.sys.*", "comp.sys.mac"];
+        assert!(wildmat_list(&patterns, "comp.lang.rust"));
+        assert!(!wildmat_list(&patterns, "comp.sys.amiga"));
+        assert!(wildmat_list(&patterns, "comp.sys.mac"));
+        assert!(!wildmat_list(&patterns, "alt.test"));
+    }
 }
@@ -0,0 +1,420 @@
+//! JSON sessions over the WebSocket bridge.
+//!
+//! A client that requests the [`JSON_PROTOCOL`] subprotocol exchanges one
+//! JSON object per text frame instead of raw NNTP. Requests name the command
+//! in `cmd` and give its arguments either positionally in `args` or by name,
+//! for example `{"cmd": "group", "name": "misc.test"}`. The bridge sends the
+//! command over its own NNTP connection and answers with the status code,
+//! the rest of the status line and, for multi-line responses, the data
+//! lines with dot-stuffing removed. Articles are also returned split into
+//! `headers` and `body`, so a browser reader needs no NNTP parser.
+//! Refusals that tell the client when to try again, such as
+//! `441 posting failed, retry in 120s`, carry the delay in seconds in
+//! `retry_after`.
+
+use anyhow::{Result, anyhow};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Map, Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{WebSocketStream, tungstenite::Message};
+use tracing::debug;
+
+use crate::listener::{self, NntpStream};
+use crate::responses::retry_after;
+
+/// WebSocket subprotocol that enables JSON sessions.
+pub const JSON_PROTOCOL: &str = "nntp-json";
+
+/// Named arguments of the commands that take them, in command line order.
+/// Arguments are optional from the end: the first one missing ends the line.
+const NAMED_ARGS: &[(&str, &[&str])] = &[
+    ("article", &["id"]),
+    ("head", &["id"]),
+    ("body", &["id"]),
+    ("stat", &["id"]),
+    ("group", &["name"]),
+    ("listgroup", &["name", "range"]),
+    ("over", &["range"]),
+    ("xover", &["range"]),
+    ("xthread", &["id"]),
+    ("hdr", &["field", "range"]),
+    ("xhdr", &["field", "range"]),
+    ("list", &["keyword", "wildmat"]),
+    ("newgroups", &["date", "time"]),
+    ("newnews", &["wildmat", "date", "time"]),
+    ("ihave", &["id"]),
+    ("mode", &["mode"]),
+    ("xhost", &["host"]),
+    ("authinfo user", &["user"]),
+    ("authinfo pass", &["password"]),
+];
+
+/// A request translated into NNTP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonCommand {
+    /// The command in lower case, as given in `cmd`
+    pub name: String,
+    /// The command line, with its CRLF
+    pub line: String,
+    /// The dot-stuffed article sent after a `335` or `340` reply to `POST`
+    /// or `IHAVE`, with its terminating line
+    pub article: Option<String>,
+}
+
+/// Translate a JSON request into an NNTP command.
+///
+/// # Errors
+///
+/// Returns an error if the request has no `cmd`, an argument is not a
+/// string or number or contains a line break, or `POST` or `IHAVE` has no
+/// `article`.
+pub fn parse_request(request: &Value) -> Result<JsonCommand> {
+    let object = request
+        .as_object()
+        .ok_or_else(|| anyhow!("request must be a JSON object"))?;
+    let name = object
+        .get("cmd")
+        .and_then(Value::as_str)
+        .map(|cmd| cmd.trim().to_ascii_lowercase())
+        .filter(|cmd| !cmd.is_empty())
+        .ok_or_else(|| anyhow!("request has no cmd"))?;
+
+    let mut words = vec![name.to_ascii_uppercase()];
+    match object.get("args") {
+        Some(Value::Array(args)) => {
+            for arg in args {
+                words.push(argument(arg)?);
+            }
+        }
+        Some(_) => return Err(anyhow!("args must be an array")),
+        None => {
+            let fields = NAMED_ARGS
+                .iter()
+                .find(|(cmd, _)| *cmd == name)
+                .map_or(&[][..], |(_, fields)| *fields);
+            for field in fields {
+                let Some(value) = object.get(*field) else {
+                    break;
+                };
+                words.push(argument(value)?);
+            }
+        }
+    }
+    let line = words.join(" ");
+    if line.contains(['\r', '\n']) {
+        return Err(anyhow!("command contains a line break"));
+    }
+
+    let article = match name.as_str() {
+        "post" | "ihave" => Some(article_text(
+            object
+                .get("article")
+                .ok_or_else(|| anyhow!("{name} requires an article"))?,
+        )?),
+        _ => None,
+    };
+    Ok(JsonCommand {
+        name,
+        line: format!("{line}\r\n"),
+        article,
+    })
+}
+
+fn argument(value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) if !s.is_empty() && !s.contains(char::is_whitespace) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        _ => Err(anyhow!(
+            "arguments must be numbers or strings without spaces"
+        )),
+    }
+}
+
+/// The article to send for `POST` or `IHAVE`: either its raw text or an
+/// object with `headers` as `[name, value]` pairs and a `body`.
+fn article_text(article: &Value) -> Result<String> {
+    let raw = match article {
+        Value::String(text) => text.clone(),
+        Value::Object(parts) => {
+            let mut text = String::new();
+            for header in parts
+                .get("headers")
+                .and_then(Value::as_array)
+                .ok_or_else(|| anyhow!("article headers must be an array"))?
+            {
+                let (Some(name), Some(value)) = (
+                    header.get(0).and_then(Value::as_str),
+                    header.get(1).and_then(Value::as_str),
+                ) else {
+                    return Err(anyhow!("article headers must be [name, value] pairs"));
+                };
+                text.push_str(&format!("{name}: {value}\n"));
+            }
+            text.push('\n');
+            text.push_str(parts.get("body").and_then(Value::as_str).unwrap_or(""));
+            text
+        }
+        _ => return Err(anyhow!("article must be a string or an object")),
+    };
+
+    let mut stuffed = String::with_capacity(raw.len() + 8);
+    for line in raw.lines() {
+        if line.starts_with('.') {
+            stuffed.push('.');
+        }
+        stuffed.push_str(line);
+        stuffed.push_str("\r\n");
+    }
+    stuffed.push_str(".\r\n");
+    Ok(stuffed)
+}
+
+/// Whether the response with `code` to `command` is followed by data lines.
+#[must_use]
+pub fn is_multiline(command: &str, code: u16) -> bool {
+    match code {
+        100 | 101 | 215 | 220 | 221 | 222 | 224 | 225 | 230 | 231 => true,
+        211 => command.starts_with("listgroup"),
+        _ => false,
+    }
+}
+
+/// Build the JSON response for a status line and its data lines.
+#[must_use]
+pub fn response(code: u16, text: &str, lines: Option<Vec<String>>) -> Value {
+    let mut object = Map::new();
+    object.insert("code".into(), json!(code));
+    object.insert("text".into(), json!(text));
+    if let Some(secs) = retry_after(text) {
+        object.insert("retry_after".into(), json!(secs));
+    }
+    if let Some(lines) = lines {
+        if (220..=222).contains(&code) {
+            let (headers, body) = split_article(code, &lines);
+            if code != 222 {
+                object.insert("headers".into(), headers.into());
+            }
+            if code != 221 {
+                object.insert("body".into(), body.into());
+            }
+        }
+        object.insert("lines".into(), json!(lines));
+    }
+    Value::Object(object)
+}
+
+/// Split article lines into unfolded `[name, value]` headers and the body.
+fn split_article(code: u16, lines: &[String]) -> (Vec<Value>, String) {
+    let (head, body) = if code == 222 {
+        (&[][..], lines)
+    } else {
+        match lines.iter().position(String::is_empty) {
+            Some(blank) => (&lines[..blank], &lines[blank + 1..]),
+            None => (lines, &[][..]),
+        }
+    };
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in head {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim_start());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.to_string(), value.trim_start().to_string()));
+        }
+    }
+    let headers = headers
+        .into_iter()
+        .map(|(name, value)| json!([name, value]))
+        .collect();
+
+    let mut text = String::new();
+    for line in body {
+        text.push_str(line);
+        text.push('\n');
+    }
+    (headers, text)
+}
+
+/// Split a status line into its code and the remaining text.
+fn status(line: &str) -> Option<(u16, String)> {
+    let (code, text) = line.split_at_checked(3)?;
+    Some((code.parse().ok()?, text.trim().to_string()))
+}
+
+/// Read one line from the NNTP server without its line ending, or `None`
+/// at end of stream.
+async fn read_line(reader: &mut BufReader<ReadHalf<NntpStream>>) -> Result<Option<String>> {
+    let mut buf = Vec::new();
+    if reader.read_until(b'\n', &mut buf).await? == 0 {
+        return Ok(None);
+    }
+    let line = String::from_utf8_lossy(&buf);
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Read a status line, returning its code and text.
+async fn read_status(
+    reader: &mut BufReader<ReadHalf<NntpStream>>,
+) -> Result<Option<(u16, String)>> {
+    match read_line(reader).await? {
+        Some(line) => status(&line)
+            .map(Some)
+            .ok_or_else(|| anyhow!("invalid status line from server: {line}")),
+        None => Ok(None),
+    }
+}
+
+/// Read data lines up to the terminating `.`, removing dot-stuffing.
+async fn read_block(reader: &mut BufReader<ReadHalf<NntpStream>>) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    loop {
+        let line = read_line(reader)
+            .await?
+            .ok_or_else(|| anyhow!("server closed the connection mid-response"))?;
+        if line == "." {
+            return Ok(lines);
+        }
+        lines.push(line.strip_prefix('.').map_or(line.clone(), str::to_string));
+    }
+}
+
+/// Proxy a JSON WebSocket session to a single NNTP connection.
+pub(super) async fn handle_json(
+    ws_stream: WebSocketStream<TcpStream>,
+    nntp_addr: &str,
+) -> Result<()> {
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+    let (nntp_read, mut nntp_write) = tokio::io::split(listener::connect(nntp_addr).await?);
+    let mut nntp_read = BufReader::new(nntp_read);
+
+    let Some((code, text)) = read_status(&mut nntp_read).await? else {
+        ws_write.close().await?;
+        return Ok(());
+    };
+    ws_write
+        .send(Message::Text(response(code, &text, None).to_string()))
+        .await?;
+
+    while let Some(msg) = ws_read.next().await {
+        let text = match msg? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            Message::Binary(_) => {
+                debug!("ignoring binary frame on JSON websocket");
+                continue;
+            }
+            _ => continue,
+        };
+        let command = match serde_json::from_str(&text)
+            .map_err(anyhow::Error::from)
+            .and_then(|request| parse_request(&request))
+        {
+            Ok(command) => command,
+            Err(e) => {
+                let error = json!({ "error": e.to_string() });
+                ws_write.send(Message::Text(error.to_string())).await?;
+                continue;
+            }
+        };
+
+        nntp_write.write_all(command.line.as_bytes()).await?;
+        let Some(mut reply) = read_status(&mut nntp_read).await? else {
+            break;
+        };
+        if let Some(article) = &command.article
+            && matches!(reply.0, 335 | 340)
+        {
+            nntp_write.write_all(article.as_bytes()).await?;
+            let Some(after) = read_status(&mut nntp_read).await? else {
+                break;
+            };
+            reply = after;
+        }
+        let (code, text) = reply;
+        let lines = if is_multiline(&command.name, code) {
+            Some(read_block(&mut nntp_read).await?)
+        } else {
+            None
+        };
+        ws_write
+            .send(Message::Text(response(code, &text, lines).to_string()))
+            .await?;
+        if code == 205 {
+            break;
+        }
+    }
+
+    let _ = nntp_write.shutdown().await;
+    ws_write.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_and_positional_arguments() {
+        let group = parse_request(&json!({"cmd": "group", "name": "misc.test"})).unwrap();
+        assert_eq!(group.line, "GROUP misc.test\r\n");
+        let over = parse_request(&json!({"cmd": "over", "range": "1-10"})).unwrap();
+        assert_eq!(over.line, "OVER 1-10\r\n");
+        let article = parse_request(&json!({"cmd": "article", "id": 3})).unwrap();
+        assert_eq!(article.line, "ARTICLE 3\r\n");
+        let thread = parse_request(&json!({"cmd": "xthread", "id": "<a@test>"})).unwrap();
+        assert_eq!(thread.line, "XTHREAD <a@test>\r\n");
+        let host = parse_request(&json!({"cmd": "xhost", "host": "news.example"})).unwrap();
+        assert_eq!(host.line, "XHOST news.example\r\n");
+        let list = parse_request(&json!({"cmd": "list", "args": ["active", "comp.*"]})).unwrap();
+        assert_eq!(list.line, "LIST active comp.*\r\n");
+        let user = parse_request(&json!({"cmd": "authinfo user", "user": "alice"})).unwrap();
+        assert_eq!(user.line, "AUTHINFO USER alice\r\n");
+        assert!(parse_request(&json!({"cmd": "group", "name": "a\r\nQUIT"})).is_err());
+        assert!(parse_request(&json!({"name": "misc"})).is_err());
+    }
+
+    #[test]
+    fn post_articles_are_dot_stuffed() {
+        let post = parse_request(&json!({
+            "cmd": "post",
+            "article": {
+                "headers": [["Newsgroups", "misc.test"], ["Subject", "hi"]],
+                "body": "line\n.dot\n"
+            }
+        }))
+        .unwrap();
+        assert_eq!(post.line, "POST\r\n");
+        assert_eq!(
+            post.article.as_deref(),
+            Some("Newsgroups: misc.test\r\nSubject: hi\r\n\r\nline\r\n..dot\r\n.\r\n")
+        );
+        assert!(parse_request(&json!({"cmd": "post"})).is_err());
+    }
+
+    #[test]
+    fn article_responses_are_split() {
+        let lines = ["Subject: a", "  folded", "From: b", "", "body", ".dot"]
+            .map(String::from)
+            .to_vec();
+        let value = response(220, "1 <a@test>", Some(lines));
+        assert_eq!(
+            value["headers"],
+            json!([["Subject", "a folded"], ["From", "b"]])
+        );
+        assert_eq!(value["body"], "body\n.dot\n");
+        assert!(is_multiline("listgroup", 211));
+        assert!(!is_multiline("group", 211));
+    }
+
+    #[test]
+    fn retry_hints_are_passed_on() {
+        let value = response(441, "posting failed, retry in 120s", None);
+        assert_eq!(value["retry_after"], 120);
+        let value = response(441, "posting failed", None);
+        assert!(value.get("retry_after").is_none());
+    }
+}
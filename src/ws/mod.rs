@@ -0,0 +1,330 @@
+//! WebSocket bridge.
+//!
+//! Browsers cannot open TCP connections, so when `ws_addr` is set the
+//! server accepts WebSocket connections and relays each to the NNTP
+//! listener. Plain WebSockets carry raw NNTP; the [`MULTIPLEX_PROTOCOL`]
+//! and [`JSON_PROTOCOL`] subprotocols carry several sessions over one
+//! socket or structured JSON requests and responses.
+
+mod json;
+
+pub use json::JSON_PROTOCOL;
+
+use anyhow::Result;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::{WebSocketStream, accept_hdr_async, tungstenite::Message};
+use tracing::{debug, error, info};
+
+use crate::config::{Config, listen_addr};
+use crate::listener::{self, NntpStream};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{RwLock, mpsc};
+use tokio::task::JoinSet;
+
+/// WebSocket subprotocol that enables multiplexed sessions.
+///
+/// In this mode every binary frame starts with a 4-byte big-endian channel
+/// id followed by the NNTP bytes for that channel. Each channel is carried
+/// over its own NNTP connection and therefore has its own session state.
+/// The first frame on a new channel id opens it; a frame with an empty
+/// payload closes it, in either direction.
+pub const MULTIPLEX_PROTOCOL: &str = "nntp-mux";
+
+/// Maximum number of concurrent channels on one multiplexed WebSocket.
+pub const MAX_CHANNELS: usize = 32;
+
+/// Build a multiplexed frame for `channel` carrying `payload`.
+pub fn encode_frame(channel: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&channel.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Split a multiplexed frame into its channel id and payload.
+pub fn decode_frame(frame: &[u8]) -> Option<(u32, &[u8])> {
+    let (id, payload) = frame.split_first_chunk::<4>()?;
+    Some((u32::from_be_bytes(*id), payload))
+}
+
+/// Address the bridge connects to for NNTP: the first `addr`, over its
+/// Unix socket or on loopback.
+fn nntp_addr(first: Option<&String>) -> String {
+    match first {
+        Some(addr) if listener::unix_path(addr).is_some() => addr.clone(),
+        addr => format!(
+            "127.0.0.1:{}",
+            addr.map_or(119, |addr| port_from_addr(addr, 119))
+        ),
+    }
+}
+
+fn port_from_addr(addr: &str, default_port: u16) -> u16 {
+    if let Some(stripped) = addr.strip_prefix('[') {
+        if let Some(end) = stripped.find(']') {
+            if let Some(p) = stripped[end + 1..].strip_prefix(':') {
+                if let Ok(port) = p.parse() {
+                    return port;
+                }
+            }
+            return default_port;
+        }
+    }
+    if let Some(idx) = addr.rfind(':') {
+        if addr[idx + 1..].chars().all(|c| c.is_ascii_digit()) {
+            if let Ok(port) = addr[idx + 1..].parse() {
+                return port;
+            }
+        }
+    }
+    default_port
+}
+pub async fn run_ws_bridge(cfg: Arc<RwLock<Config>>) -> Result<()> {
+    let (ws_addr_raw, nntp_addr) = {
+        let cfg_guard = cfg.read().await;
+        match cfg_guard.ws_addr.as_deref() {
+            Some(a) => (a.to_string(), nntp_addr(cfg_guard.addr.first())),
+            None => return Ok(()),
+        }
+    };
+    let addr = listen_addr(&ws_addr_raw);
+    info!("listening WebSocket on {addr}");
+    let listener = TcpListener::bind(&addr).await.map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to bind to WebSocket address '{}': {}
+
+This error typically occurs when:
+- Another process is already using this port (try: lsof -i :{} or netstat -tlnp | grep :{})
+- The port number is invalid (must be 1-65535)
+- Permission denied for privileged ports (<1024) - try running as root or use a port ≥1024
+- The address format is incorrect (should be 'host:port', ':port', or just 'port')
+
+You can change the WebSocket listen address in your configuration file using the 'ws_addr' setting
+or disable the WebSocket bridge by removing the 'ws_addr' configuration.",
+            addr,
+            e,
+            addr.split(':').next_back().unwrap_or("8080"),
+            addr.split(':').next_back().unwrap_or("8080")
+        )
+    })?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let nntp_addr_clone = nntp_addr.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, &nntp_addr_clone).await {
+                error!("websocket client error: {e}");
+            }
+        });
+    }
+}
+
+/// Session format negotiated through the WebSocket subprotocol.
+#[derive(Clone, Copy)]
+enum Mode {
+    Raw,
+    Multiplex,
+    Json,
+}
+
+async fn handle_client(stream: TcpStream, nntp_addr: &str) -> Result<()> {
+    let mut mode = Mode::Raw;
+    // The callback's error type is fixed by tungstenite
+    #[allow(clippy::result_large_err)]
+    let ws_stream = accept_hdr_async(stream, |req: &Request, mut resp: Response| {
+        // The first supported subprotocol in the client's list is chosen
+        let chosen = req
+            .headers()
+            .get_all("Sec-WebSocket-Protocol")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .find_map(|p| match p.trim() {
+                MULTIPLEX_PROTOCOL => Some((Mode::Multiplex, MULTIPLEX_PROTOCOL)),
+                JSON_PROTOCOL => Some((Mode::Json, JSON_PROTOCOL)),
+                _ => None,
+            });
+        if let Some((chosen, protocol)) = chosen {
+            mode = chosen;
+            resp.headers_mut()
+                .insert("Sec-WebSocket-Protocol", HeaderValue::from_static(protocol));
+        }
+        Ok(resp)
+    })
+    .await?;
+
+    match mode {
+        Mode::Raw => handle_single(ws_stream, nntp_addr).await,
+        Mode::Multiplex => handle_multiplexed(ws_stream, nntp_addr).await,
+        Mode::Json => json::handle_json(ws_stream, nntp_addr).await,
+    }
+}
+
+/// Proxy a plain WebSocket to a single NNTP connection.
+async fn handle_single(ws_stream: WebSocketStream<TcpStream>, nntp_addr: &str) -> Result<()> {
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+    let nntp = listener::connect(nntp_addr).await?;
+    let (mut nntp_read, mut nntp_write) = io::split(nntp);
+
+    let to_nntp = tokio::spawn(async move {
+        while let Some(msg) = ws_read.next().await {
+            match msg? {
+                Message::Text(t) => {
+                    nntp_write.write_all(t.as_bytes()).await?;
+                }
+                Message::Binary(b) => {
+                    nntp_write.write_all(&b).await?;
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        nntp_write.shutdown().await?;
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = nntp_read.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        ws_write.send(Message::Binary(buf[..n].to_vec())).await?;
+    }
+    ws_write.close().await?;
+    let _ = to_nntp.await?;
+    Ok(())
+}
+
+/// A channel of a multiplexed WebSocket and the NNTP connection behind it.
+struct Channel {
+    nntp_write: WriteHalf<NntpStream>,
+    /// Tells this channel apart from earlier ones that had the same id
+    generation: u64,
+}
+
+/// Proxy a multiplexed WebSocket, opening one NNTP connection per channel.
+async fn handle_multiplexed(ws_stream: WebSocketStream<TcpStream>, nntp_addr: &str) -> Result<()> {
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+    let (out_tx, mut out_rx) = mpsc::channel::<Vec<u8>>(64);
+
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = out_rx.recv().await {
+            ws_write.send(Message::Binary(frame)).await?;
+        }
+        ws_write.close().await?;
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let mut channels: HashMap<u32, Channel> = HashMap::new();
+    // Tasks reading from the NNTP connections, which end with the id and
+    // generation of their channel
+    let mut readers: JoinSet<(u32, u64)> = JoinSet::new();
+    let mut next_generation = 0u64;
+    loop {
+        tokio::select! {
+            msg = ws_read.next() => {
+                let Some(msg) = msg else { break };
+                let data = match msg? {
+                    Message::Binary(b) => b,
+                    Message::Close(_) => break,
+                    Message::Text(_) => {
+                        debug!("ignoring text frame on multiplexed websocket");
+                        continue;
+                    }
+                    _ => continue,
+                };
+                let Some((id, payload)) = decode_frame(&data) else {
+                    debug!("ignoring short frame on multiplexed websocket");
+                    continue;
+                };
+
+                if payload.is_empty() {
+                    if let Some(mut channel) = channels.remove(&id) {
+                        let _ = channel.nntp_write.shutdown().await;
+                    }
+                    continue;
+                }
+
+                if !channels.contains_key(&id) {
+                    if channels.len() >= MAX_CHANNELS {
+                        let _ = out_tx.send(encode_frame(id, &[])).await;
+                        continue;
+                    }
+                    // A channel that cannot be opened is closed at once; the
+                    // others carry on
+                    let nntp = match listener::connect(nntp_addr).await {
+                        Ok(nntp) => nntp,
+                        Err(e) => {
+                            error!("cannot open multiplexed channel {id}: {e}");
+                            let _ = out_tx.send(encode_frame(id, &[])).await;
+                            continue;
+                        }
+                    };
+                    let (read_half, write_half) = io::split(nntp);
+                    let generation = next_generation;
+                    next_generation += 1;
+                    readers.spawn(forward_channel(id, generation, read_half, out_tx.clone()));
+                    channels.insert(
+                        id,
+                        Channel {
+                            nntp_write: write_half,
+                            generation,
+                        },
+                    );
+                }
+
+                if let Some(channel) = channels.get_mut(&id)
+                    && channel.nntp_write.write_all(payload).await.is_err()
+                {
+                    channels.remove(&id);
+                    let _ = out_tx.send(encode_frame(id, &[])).await;
+                }
+            }
+            Some(done) = readers.join_next() => {
+                // A channel reopened under the same id is not the one closed
+                if let Ok((id, generation)) = done
+                    && channels.get(&id).is_some_and(|c| c.generation == generation)
+                {
+                    channels.remove(&id);
+                }
+            }
+        }
+    }
+
+    for (_, mut channel) in channels.drain() {
+        let _ = channel.nntp_write.shutdown().await;
+    }
+    // The readers hold senders of the frames; the writer finishes once
+    // they are gone
+    readers.shutdown().await;
+    drop(out_tx);
+    writer.await??;
+    Ok(())
+}
+
+/// Copy data from one channel's NNTP connection into multiplexed frames.
+async fn forward_channel(
+    id: u32,
+    generation: u64,
+    mut nntp_read: ReadHalf<NntpStream>,
+    out_tx: mpsc::Sender<Vec<u8>>,
+) -> (u32, u64) {
+    let mut buf = [0u8; 1024];
+    loop {
+        match nntp_read.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if out_tx.send(encode_frame(id, &buf[..n])).await.is_err() {
+                    return (id, generation);
+                }
+            }
+        }
+    }
+    let _ = out_tx.send(encode_frame(id, &[])).await;
+    (id, generation)
+}
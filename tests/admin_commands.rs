@@ -1,4 +1,5 @@
 use renews::{auth, storage};
+use std::str::FromStr;
 use tempfile::TempDir;
 
 async fn setup() -> (String, String, TempDir) {
@@ -122,6 +123,32 @@ async fn test_set_group_moderated() {
     assert!(!storage.is_group_moderated("test.group").await.unwrap());
 }
 
+#[tokio::test]
+async fn test_set_group_archived() {
+    use futures_util::TryStreamExt;
+
+    let (storage_path, _auth_path, _temp_dir) = setup().await;
+    let storage = storage::open(&storage_path).await.unwrap();
+    storage.add_group("test.group", false).await.unwrap();
+    storage.add_group("other.group", false).await.unwrap();
+    assert!(!storage.is_group_archived("test.group").await.unwrap());
+
+    storage
+        .set_group_archived("test.group", true)
+        .await
+        .unwrap();
+    assert!(storage.is_group_archived("test.group").await.unwrap());
+    assert!(!storage.is_group_archived("other.group").await.unwrap());
+    let archived: Vec<String> = storage.list_archived_groups().try_collect().await.unwrap();
+    assert_eq!(archived, vec!["test.group".to_string()]);
+
+    storage
+        .set_group_archived("test.group", false)
+        .await
+        .unwrap();
+    assert!(!storage.is_group_archived("test.group").await.unwrap());
+}
+
 #[tokio::test]
 async fn test_remove_groups_by_pattern() {
     let (storage_path, _auth_path, _temp_dir) = setup().await;
@@ -145,3 +172,324 @@ async fn test_remove_groups_by_pattern() {
     assert!(!storage.group_exists("test.group2").await.unwrap());
     assert!(storage.group_exists("other.group").await.unwrap());
 }
+
+#[tokio::test]
+async fn test_approve_and_reject_pending_articles() {
+    let (storage_path, auth_path, _temp_dir) = setup().await;
+    let storage = storage::open(&storage_path).await.unwrap();
+    let auth = auth::open(&auth_path).await.unwrap();
+    storage.add_group("mod.test", true).await.unwrap();
+
+    let mut ids = Vec::new();
+    for n in 1..=2 {
+        let text = format!(
+            "Message-ID: <pending{n}@test>\r\nNewsgroups: mod.test\r\nFrom: a@test\r\nSubject: s\r\n\r\nBody\r\n"
+        );
+        let (_, msg) = renews::parse_message(&text).unwrap();
+        assert!(
+            renews::moderation::needs_moderation(&storage, &msg)
+                .await
+                .unwrap()
+        );
+        ids.push(storage.add_pending_article(&msg).await.unwrap());
+    }
+
+    let pending = renews::moderation::list_pending(&storage, &auth, None, None)
+        .await
+        .unwrap();
+    assert_eq!(pending.iter().map(|p| p.id).collect::<Vec<_>>(), ids);
+
+    // Approving stores the article with an Approved header
+    let approved = renews::moderation::approve(&storage, ids[0], "moderator@test")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        approved
+            .headers
+            .iter()
+            .any(|(k, v)| k == "Approved" && v == "moderator@test")
+    );
+    assert!(
+        storage
+            .get_article_by_id("<pending1@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    // Rejecting discards the article
+    assert!(renews::moderation::reject(&storage, ids[1]).await.unwrap());
+    assert!(!renews::moderation::reject(&storage, ids[1]).await.unwrap());
+    assert!(
+        storage
+            .get_article_by_id("<pending2@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        renews::moderation::list_pending(&storage, &auth, None, None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_export_articles() {
+    use renews::export::{Maildir, Mbox, export_articles, parse_since};
+
+    let (storage_path, _auth_path, temp_dir) = setup().await;
+    let storage = storage::open(&storage_path).await.unwrap();
+    for group in ["misc.one", "misc.two", "other.group"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+    for (id, groups, body) in [
+        (
+            "<a@test>",
+            "misc.one,misc.two",
+            "From the start\r\n>From quoted\r\n",
+        ),
+        ("<b@test>", "misc.two", "Second\r\n"),
+        ("<c@test>", "other.group", "Elsewhere\r\n"),
+    ] {
+        let text = format!(
+            "Message-ID: {id}\r\nNewsgroups: {groups}\r\nFrom: Alice <alice@test>\r\n\
+             Date: Mon, 1 Jan 2024 12:00:00 +0000\r\nSubject: s\r\n\r\n{body}"
+        );
+        let (_, msg) = renews::parse_message(&text).unwrap();
+        storage.store_article(&msg).await.unwrap();
+    }
+
+    // Cross-posted articles are exported once and "From " lines quoted
+    let mut mbox = Mbox::new(Vec::new());
+    let summary = export_articles(storage.as_ref(), "misc.*", None, &mut mbox)
+        .await
+        .unwrap();
+    assert_eq!((summary.groups, summary.articles), (2, 2));
+    let text = String::from_utf8(mbox.into_inner()).unwrap();
+    assert!(text.starts_with("From alice@test Mon Jan  1 12:00:00 2024\n"));
+    assert_eq!(text.matches("\nFrom alice@test ").count(), 1);
+    assert!(text.contains("\n\n>From the start\n>>From quoted\n\n"));
+    assert!(!text.contains("Elsewhere"));
+
+    // Nothing arrived after the present
+    let mut mbox = Mbox::new(Vec::new());
+    let since = parse_since("2999-01-01").unwrap();
+    let summary = export_articles(storage.as_ref(), "*", Some(since), &mut mbox)
+        .await
+        .unwrap();
+    assert_eq!(summary.articles, 0);
+    assert!(mbox.into_inner().is_empty());
+    assert!(parse_since("2024-01-01T00:00:00Z").is_ok());
+    assert!(parse_since("yesterday").is_err());
+
+    let root = temp_dir.path().join("Maildir");
+    let mut maildir = Maildir::create(&root, "news.example").unwrap();
+    let summary = export_articles(storage.as_ref(), "*", None, &mut maildir)
+        .await
+        .unwrap();
+    assert_eq!(summary.articles, 3);
+    let files: Vec<_> = std::fs::read_dir(root.join("new"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 3);
+    assert_eq!(std::fs::read_dir(root.join("tmp")).unwrap().count(), 0);
+    let article = files
+        .iter()
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .find(|text| text.contains("<c@test>"))
+        .unwrap();
+    assert!(article.ends_with("Subject: s\n\nElsewhere\n"));
+}
+
+#[tokio::test]
+async fn test_import_articles_round_trips_an_export() {
+    use renews::export::{Maildir, Mbox, export_articles};
+    use renews::import::{ImportSummary, import_articles, maildir_messages, mbox_messages};
+
+    let (storage_path, _auth_path, temp_dir) = setup().await;
+    let source = storage::open(&storage_path).await.unwrap();
+    for group in ["misc.one", "misc.two", "other.group"] {
+        source.add_group(group, false).await.unwrap();
+    }
+    for (id, groups, body) in [
+        (
+            "<a@test>",
+            "misc.one,misc.two",
+            "From the start\r\n>From quoted\r\n\r\nFrom after a blank line\r\n",
+        ),
+        ("<b@test>", "misc.two", "Second\r\n"),
+        ("<c@test>", "other.group", "Elsewhere\r\n"),
+    ] {
+        let text = format!(
+            "Message-ID: {id}\r\nNewsgroups: {groups}\r\nFrom: Alice <alice@test>\r\n\
+             Date: Mon, 1 Jan 2024 12:00:00 +0000\r\nSubject: s\r\n\r\n{body}"
+        );
+        let (_, msg) = renews::parse_message(&text).unwrap();
+        source.store_article(&msg).await.unwrap();
+    }
+    let mut mbox = Mbox::new(Vec::new());
+    export_articles(source.as_ref(), "*", None, &mut mbox)
+        .await
+        .unwrap();
+    let mbox = mbox.into_inner();
+    let root = temp_dir.path().join("Maildir");
+    let mut maildir = Maildir::create(&root, "news.example").unwrap();
+    export_articles(source.as_ref(), "*", None, &mut maildir)
+        .await
+        .unwrap();
+
+    for (name, maildir) in [("mbox", false), ("maildir", true)] {
+        let target_path = format!("sqlite:///{}/{name}.db", temp_dir.path().to_str().unwrap());
+        let target = storage::open(&target_path).await.unwrap();
+        for group in ["misc.one", "misc.two"] {
+            target.add_group(group, false).await.unwrap();
+        }
+        let import = async || match maildir {
+            false => import_articles(target.as_ref(), mbox_messages(mbox.as_slice())).await,
+            true => import_articles(target.as_ref(), maildir_messages(&root).unwrap()).await,
+        };
+
+        // Articles for groups not carried are skipped
+        let summary = import().await.unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                imported: 2,
+                duplicates: 0,
+                skipped: 1,
+            },
+            "{name}"
+        );
+        assert_eq!(
+            target
+                .get_article_by_number("misc.two", 2)
+                .await
+                .unwrap()
+                .unwrap()
+                .body,
+            "Second\r\n"
+        );
+        let article = target.get_article_by_id("<a@test>").await.unwrap().unwrap();
+        assert_eq!(
+            article.body, "From the start\r\n>From quoted\r\n\r\nFrom after a blank line\r\n",
+            "{name}"
+        );
+        assert!(
+            target
+                .get_article_by_id("<c@test>")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Importing again stores nothing twice
+        let summary = import().await.unwrap();
+        assert_eq!((summary.imported, summary.duplicates), (0, 2), "{name}");
+    }
+}
+
+#[tokio::test]
+async fn test_migration_history_detects_edited_migrations() {
+    use renews::migrations::{Database, MigrationState, history};
+    use sqlx::Connection;
+
+    let (storage_path, auth_path, temp_dir) = setup().await;
+
+    let storage_history = history(Database::Storage, &storage_path).await.unwrap();
+    assert_eq!(
+        storage_history
+            .iter()
+            .map(|m| m.version)
+            .collect::<Vec<_>>(),
+        vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]
+    );
+    assert!(storage_history.iter().all(|m| {
+        m.state == MigrationState::Applied
+            && m.installed_on.is_some()
+            && m.checksum_hex().len() == 96
+    }));
+    let auth_history = history(Database::Auth, &auth_path).await.unwrap();
+    assert_eq!(auth_history.len(), 4);
+    assert!(
+        auth_history
+            .iter()
+            .all(|m| m.state == MigrationState::Applied)
+    );
+
+    // A database that was never migrated has every migration pending
+    let empty_path = format!("sqlite:///{}/empty.db", temp_dir.path().to_str().unwrap());
+    let options = sqlx::sqlite::SqliteConnectOptions::from_str(&empty_path)
+        .unwrap()
+        .create_if_missing(true);
+    sqlx::SqliteConnection::connect_with(&options)
+        .await
+        .unwrap()
+        .close()
+        .await
+        .unwrap();
+    let empty = history(Database::Storage, &empty_path).await.unwrap();
+    assert!(empty.iter().all(|m| m.state == MigrationState::Pending));
+
+    // Simulate an applied migration that has since been edited
+    let mut conn = sqlx::SqliteConnection::connect(&storage_path)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE _sqlx_migrations SET checksum = X'00' WHERE version = 2")
+        .execute(&mut conn)
+        .await
+        .unwrap();
+    conn.close().await.unwrap();
+
+    let storage_history = history(Database::Storage, &storage_path).await.unwrap();
+    assert_eq!(storage_history[1].state, MigrationState::Modified);
+    assert_eq!(storage_history[1].checksum_hex(), "00");
+    let err = storage::open(&storage_path).await.err().unwrap();
+    assert!(err.to_string().contains("has been modified"), "{err}");
+}
+
+#[tokio::test]
+async fn test_sync_groups_applies_declared_groups() {
+    let (storage_path, _auth_path, _temp_dir) = setup().await;
+    let storage = storage::open(&storage_path).await.unwrap();
+    storage.add_group("mod.talk", false).await.unwrap();
+    storage.add_group("mod.chat", true).await.unwrap();
+    storage.add_group("misc.test", true).await.unwrap();
+    let cfg: renews::config::Config = toml::from_str(
+        r#"
+addr = ":119"
+[[group_settings]]
+group = "local.announce"
+moderated = true
+
+[[group_settings]]
+pattern = "mod.*"
+moderated = true
+
+[[group_settings]]
+group = "mod.chat"
+moderated = false
+"#,
+    )
+    .unwrap();
+
+    let sync = renews::groups::sync_groups(&*storage, &cfg).await.unwrap();
+    assert_eq!(sync.created, vec!["local.announce".to_string()]);
+    let mut changed = sync.moderation_changed.clone();
+    changed.sort();
+    assert_eq!(
+        changed,
+        vec!["mod.chat".to_string(), "mod.talk".to_string()]
+    );
+    assert!(storage.is_group_moderated("local.announce").await.unwrap());
+    assert!(storage.is_group_moderated("mod.talk").await.unwrap());
+    assert!(!storage.is_group_moderated("mod.chat").await.unwrap());
+    // Groups without a declared flag keep theirs
+    assert!(storage.is_group_moderated("misc.test").await.unwrap());
+
+    let again = renews::groups::sync_groups(&*storage, &cfg).await.unwrap();
+    assert_eq!(again, renews::groups::GroupSync::default());
+}
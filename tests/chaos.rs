@@ -0,0 +1,300 @@
+//! Connection chaos tests.
+//!
+//! [`ChaosStream`] wraps the server side of a client connection and, driven
+//! by a seeded random generator, fragments the server's writes, delays and
+//! splits its reads so that commands and article lines arrive in pieces, and
+//! cuts the connection off at a random byte, often in the middle of an
+//! article. Each seed replays a scripted session of reader, posting and
+//! feeding commands from the address of a configured peer; the server must
+//! never panic or hang, and the stored articles and overview data must stay
+//! consistent whatever happened to the connection.
+
+use futures_util::TryStreamExt;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use renews::storage::{OverviewRepair, Storage};
+use renews::{ConnectionInfo, handle_client_with_info};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+use tokio::sync::RwLock;
+use tokio::time::Sleep;
+
+mod utils;
+
+/// Number of scripted sessions replayed, one per seed.
+const SEEDS: u64 = 64;
+
+/// Last body line of every test article; a stored body without it was
+/// truncated.
+const LAST_LINE: &str = "end of chaos article";
+
+/// A stream that misbehaves in reproducible ways.
+struct ChaosStream<S> {
+    inner: S,
+    rng: StdRng,
+    /// Pending delay before the next read
+    delay: Option<Pin<Box<Sleep>>>,
+    /// Bytes the server may read before it sees EOF
+    read_budget: u64,
+    /// Whether writes fail once the read budget is spent
+    break_writes: bool,
+}
+
+impl<S> ChaosStream<S> {
+    fn new(inner: S, seed: u64, read_budget: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let break_writes = rng.gen_bool(0.5);
+        Self {
+            inner,
+            rng,
+            delay: None,
+            read_budget,
+            break_writes,
+        }
+    }
+
+    /// Sleep for a short random time now and then, returning `Pending` while
+    /// the sleep lasts.
+    fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.delay.is_none() && self.rng.gen_ratio(1, 4) {
+            let micros = self.rng.gen_range(0..2000);
+            self.delay = Some(Box::pin(tokio::time::sleep(Duration::from_micros(micros))));
+        }
+        if let Some(delay) = &mut self.delay {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ChaosStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_delay(cx));
+        if this.read_budget == 0 {
+            // Abrupt EOF
+            return Poll::Ready(Ok(()));
+        }
+        // Hand out a few bytes at a time so lines arrive in pieces
+        let mut chunk = [0u8; 16];
+        let limit = this
+            .rng
+            .gen_range(1..=chunk.len())
+            .min(buf.remaining())
+            .min(usize::try_from(this.read_budget).unwrap_or(usize::MAX));
+        let mut partial = ReadBuf::new(&mut chunk[..limit]);
+        ready!(Pin::new(&mut this.inner).poll_read(cx, &mut partial))?;
+        buf.put_slice(partial.filled());
+        this.read_budget -= partial.filled().len() as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ChaosStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.read_budget == 0 && this.break_writes {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        // Accept only part of each write so responses are fragmented
+        let len = this.rng.gen_range(1..=32).min(data.len());
+        Pin::new(&mut this.inner).poll_write(cx, &data[..len])
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+fn article(id: &str, path: bool) -> String {
+    let path = if path {
+        "Path: peer.example!origin\r\n"
+    } else {
+        ""
+    };
+    format!(
+        "Message-ID: {id}\r\nNewsgroups: misc\r\nFrom: chaos@example.com\r\n\
+         Subject: chaos {id}\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n{path}\r\n\
+         first line\r\n..a dot-stuffed line\r\n\r\n{LAST_LINE}\r\n.\r\n"
+    )
+}
+
+/// Address the sessions connect from, that of the configured peer.
+const PEER_IP: &str = "192.0.2.7";
+
+/// Build a random client session for `seed`, along with the Message-IDs of
+/// the articles it feeds with IHAVE and TAKETHIS.
+fn script(seed: u64) -> (String, Vec<String>) {
+    let mut rng = StdRng::seed_from_u64(seed ^ 0x5eed);
+    let mut out = String::new();
+    let mut fed = Vec::new();
+    for n in 0..rng.gen_range(1..6) {
+        let id = format!("<{seed}-{n}@chaos>");
+        match rng.gen_range(0..6) {
+            0 => out.push_str(&format!("POST\r\n{}", article(&id, false))),
+            1 => {
+                out.push_str(&format!("IHAVE {id}\r\n{}", article(&id, true)));
+                fed.push(id);
+            }
+            2 => {
+                out.push_str(&format!("TAKETHIS {id}\r\n{}", article(&id, true)));
+                fed.push(id);
+            }
+            3 => out.push_str("GROUP misc\r\nOVER 1-\r\nLAST\r\nNEXT\r\n"),
+            4 => out.push_str(&format!("CHECK {id}\r\nARTICLE <0-0@chaos>\r\n")),
+            _ => out.push_str("LIST ACTIVE\r\nHDR Subject 1-\r\nBODY 1\r\n"),
+        }
+    }
+    out.push_str("QUIT\r\n");
+    (out, fed)
+}
+
+/// Replay the session of `seed` against a server reading through a
+/// [`ChaosStream`], returning the Message-IDs fed in it when the whole
+/// session reached the server.
+async fn run_session(
+    seed: u64,
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn renews::auth::AuthProvider>,
+    cfg: Arc<RwLock<renews::config::Config>>,
+    queue: renews::queue::ArticleQueue,
+) -> Vec<String> {
+    let (script, fed) = script(seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    // Most sessions are cut off somewhere, often inside an article
+    let budget = rng.gen_range(0..script.len() as u64 * 5 / 4);
+    let (mut client, server): (DuplexStream, DuplexStream) = tokio::io::duplex(1 << 20);
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &*cfg.read().await);
+    let server = ChaosStream::new(server, seed, budget);
+    let info = ConnectionInfo {
+        is_tls: true,
+        peer_ip: Some(PEER_IP.parse().unwrap()),
+        ..ConnectionInfo::default()
+    };
+    let handle = tokio::spawn(handle_client_with_info(
+        server,
+        storage,
+        auth,
+        cfg,
+        info,
+        queue,
+        usage_tracker,
+    ));
+
+    client.write_all(script.as_bytes()).await.unwrap();
+    let mut responses = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(10), client.read_to_end(&mut responses));
+    assert!(read.await.is_ok(), "seed {seed}: server hung");
+
+    match tokio::time::timeout(Duration::from_secs(10), handle).await {
+        Err(_) => panic!("seed {seed}: server did not finish"),
+        Ok(Err(e)) if e.is_panic() => panic!("seed {seed}: server panicked: {e}"),
+        // Errors from the broken connection are expected
+        Ok(_) => {}
+    }
+    if budget >= script.len() as u64 {
+        fed
+    } else {
+        Vec::new()
+    }
+}
+
+#[tokio::test]
+async fn chaotic_connections_leave_storage_consistent() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let mut cfg = utils::create_minimal_config();
+    cfg.allow_anonymous_posting = true;
+    cfg.peers = toml::from_str::<renews::config::Config>(&format!(
+        "addr = \":119\"\n[[peers]]\nsitename = \"{PEER_IP}\"\n"
+    ))
+    .unwrap()
+    .peers;
+    let cfg = Arc::new(RwLock::new(cfg));
+    let queue =
+        utils::create_test_queue_with_workers(storage.clone(), auth.clone(), cfg.clone()).await;
+
+    let mut fed = Vec::new();
+    for seed in 0..SEEDS {
+        fed.extend(
+            run_session(
+                seed,
+                storage.clone(),
+                auth.clone(),
+                cfg.clone(),
+                queue.clone(),
+            )
+            .await,
+        );
+    }
+
+    // Let the workers store everything that was accepted
+    for _ in 0..100 {
+        if queue.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Every stored article is complete
+    let ids: Vec<String> = storage
+        .list_article_ids("misc")
+        .try_collect()
+        .await
+        .unwrap();
+    assert!(!ids.is_empty(), "no session got an article through");
+    // Articles fed in sessions that reached the server whole were ingested
+    assert!(!fed.is_empty(), "no whole session fed an article");
+    for id in &fed {
+        assert!(ids.contains(id), "fed article {id} was not stored");
+    }
+    for id in &ids {
+        let article = storage
+            .get_article_by_id(id)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("{id} is numbered but missing"));
+        assert!(article.body.contains(LAST_LINE), "{id} was truncated");
+        assert!(article.body.contains("\r\n.a dot-stuffed line\r\n"));
+    }
+
+    // The overview matches the stored articles exactly
+    let repair = storage.rebuild_overview("misc").await.unwrap();
+    assert_eq!(
+        repair,
+        OverviewRepair {
+            checked: ids.len() as u64,
+            ..OverviewRepair::default()
+        }
+    );
+
+    // Article numbers are unique
+    let mut numbers: Vec<u64> = storage
+        .list_article_numbers("misc")
+        .try_collect()
+        .await
+        .unwrap();
+    let count = numbers.len();
+    numbers.dedup();
+    assert_eq!(numbers.len(), count);
+    assert_eq!(count, ids.len());
+}
@@ -12,6 +12,9 @@ fn help_lines() -> Vec<String> {
         "CAPABILITIES".into(),
         "MODE READER".into(),
         "MODE STREAM".into(),
+        "MODE CANCEL".into(),
+        "STARTTLS".into(),
+        "XHOST".into(),
         "GROUP".into(),
         "LIST".into(),
         "LISTGROUP".into(),
@@ -21,6 +24,10 @@ fn help_lines() -> Vec<String> {
         "STAT".into(),
         "HDR".into(),
         "OVER".into(),
+        "XZVER".into(),
+        "XZHDR".into(),
+        "XTHREAD".into(),
+        "XFEATURE COMPRESS GZIP".into(),
         "NEXT".into(),
         "LAST".into(),
         "NEWGROUPS".into(),
@@ -29,6 +36,8 @@ fn help_lines() -> Vec<String> {
         "CHECK".into(),
         "TAKETHIS".into(),
         "POST".into(),
+        "XPOSTCHECK".into(),
+        "XMODERATE".into(),
         "DATE".into(),
         "HELP".into(),
         "QUIT".into(),
@@ -92,7 +101,7 @@ async fn listgroup_and_navigation_commands() {
         .expect("GROUP misc", "211 2 1 2 misc")
         .expect_multi(
             "LISTGROUP",
-            vec!["211 article numbers follow", "1", "2", "."],
+            vec!["211 2 1 2 misc list follows", "1", "2", "."],
         )
         .expect_multi(
             "HEAD 1",
@@ -449,6 +458,90 @@ async fn capabilities_and_unknown_command() {
         .await;
 }
 
+#[tokio::test]
+async fn capabilities_follow_the_session() {
+    let (storage, auth) = utils::setup().await;
+    auth.add_user("alice", "pass").await.unwrap();
+    auth.add_user("bob", "pass").await.unwrap();
+    let limits = renews::limits::UserLimits {
+        can_post: false,
+        ..Default::default()
+    };
+    auth.set_user_limits("bob", &limits).await.unwrap();
+
+    let mut anonymous = utils::capabilities_lines();
+    anonymous.insert(4, "AUTHINFO USER".into());
+    // Authenticated users may post and feed articles
+    let mut alice = utils::capabilities_lines();
+    alice.insert(4, "POST".into());
+    alice.insert(5, "XPOSTCHECK".into());
+    alice.insert(7, "IHAVE".into());
+    alice.insert(8, "STREAMING".into());
+    // Feeds are refused until the client has identified itself
+    ClientMock::new()
+        .expect_multi("CAPABILITIES", anonymous)
+        .expect("IHAVE <feed@test>", "480 authentication required")
+        .expect("CHECK <feed@test>", "480 authentication required")
+        .expect("MODE STREAM", "480 authentication required")
+        .expect("AUTHINFO USER alice", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect_multi("CAPABILITIES", alice)
+        .expect("MODE STREAM", "203 Streaming permitted")
+        .run_tls(storage.clone(), auth.clone())
+        .await;
+
+    // POST is not offered to a user whose posting is disabled
+    let mut bob = utils::capabilities_lines();
+    bob.insert(5, "IHAVE".into());
+    bob.insert(6, "STREAMING".into());
+    ClientMock::with_auth("bob", "pass")
+        .expect_multi("CAPABILITIES", bob)
+        .expect("POST", "440 posting not allowed")
+        .run_tls(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn xthread_returns_the_thread_of_an_article() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let articles = [
+        "Message-ID: <root@test>\r\nSubject: Root\r\nNewsgroups: misc\r\n\r\nBody",
+        "Message-ID: <reply@test>\r\nSubject: Re: Root\r\nNewsgroups: misc\r\nReferences: <root@test>\r\n\r\nBody",
+        "Message-ID: <other@test>\r\nSubject: Other\r\nNewsgroups: misc\r\n\r\nBody",
+        "Message-ID: <deep@test>\r\nSubject: Re: Root\r\nNewsgroups: misc\r\nReferences: <root@test> <reply@test>\r\n\r\nBody",
+        "Message-ID: <irt@test>\r\nSubject: Re: Root\r\nNewsgroups: misc\r\nIn-Reply-To: <deep@test>\r\n\r\nBody",
+    ];
+    let mut lines = Vec::new();
+    for (number, text) in (1..).zip(articles) {
+        let article = store_test_article(&*storage, text).await;
+        lines.push(renews::overview::format_overview_line(
+            number,
+            &article,
+            &[],
+        ));
+    }
+    let overview = |numbers: &[usize]| {
+        std::iter::once("224 Overview information follows".to_string())
+            .chain(numbers.iter().map(|&n| lines[n - 1].clone()))
+            .chain(std::iter::once(".".to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    ClientMock::new()
+        .expect("XTHREAD 1", "412 no newsgroup selected")
+        .expect("GROUP misc", "211 5 1 5 misc")
+        .expect_multi("XTHREAD", overview(&[1, 2, 4, 5]))
+        .expect_multi("XTHREAD 2", overview(&[1, 2, 4, 5]))
+        .expect_multi("XTHREAD <irt@test>", overview(&[1, 2, 4, 5]))
+        .expect_multi("XTHREAD 3", overview(&[3]))
+        .expect("XTHREAD 9", "423 no such article number in this group")
+        .expect("XTHREAD <missing@test>", "430 no such article")
+        .expect("XTHREAD 1-2", "501 invalid argument")
+        .run(storage, auth)
+        .await;
+}
+
 #[tokio::test]
 async fn unsupported_mode_variant() {
     let (storage, auth) = utils::setup().await;
@@ -462,11 +555,111 @@ async fn unsupported_mode_variant() {
 async fn article_syntax_error() {
     let (storage, auth) = utils::setup().await;
     ClientMock::new()
-        .expect("ARTICLE a.message.id@no.angle.brackets", "501 invalid id")
+        .expect(
+            "ARTICLE a.message.id@no.angle.brackets",
+            "501 invalid article: a.message.id@no.angle.brackets",
+        )
         .run(storage, auth)
         .await;
 }
 
+#[tokio::test]
+async fn malformed_arguments_name_the_argument() {
+    let (storage, auth) = utils::setup().await;
+    let cases = [
+        ("ARTICLE 1 2", "501 too many arguments: 2"),
+        ("ARTICLE 5-2x", "501 invalid article: 5-2x"),
+        ("ARTICLE 1-2", "501 invalid article: 1-2"),
+        ("STAT 3-", "501 invalid article: 3-"),
+        ("HEAD abc", "501 invalid article: abc"),
+        ("BODY 1-x", "501 invalid article: 1-x"),
+        ("BODY <a@b> 9-3", "501 invalid byte range: 9-3"),
+        ("BODY <a@b> 0- extra", "501 too many arguments: extra"),
+        ("STAT <unterminated", "501 invalid article: <unterminated"),
+        ("GROUP misc extra", "501 too many arguments: extra"),
+        ("LIST ACTIVE * extra", "501 too many arguments: extra"),
+        ("LISTGROUP misc 1-x", "501 invalid range: 1-x"),
+        ("NEXT 1", "501 too many arguments: 1"),
+        ("LAST 1", "501 too many arguments: 1"),
+        ("NEWGROUPS 2024120 000000", "501 invalid date: 2024120"),
+        ("NEWGROUPS 20241201 0000", "501 invalid time: 0000"),
+        ("NEWGROUPS 20241201 000000 UTC", "501 invalid argument: UTC"),
+        ("NEWNEWS * 20241201 00000x", "501 invalid time: 00000x"),
+        ("HDR Subject 3-a", "501 invalid article: 3-a"),
+        ("XPAT Subject x *pat*", "501 invalid article: x"),
+        ("OVER -5", "501 invalid article: -5"),
+        ("XOVER 1 2", "501 too many arguments: 2"),
+        ("XZVER 2-b", "501 invalid article: 2-b"),
+        ("XZHDR Subject 1 2", "501 too many arguments: 2"),
+        ("POST now", "501 too many arguments: now"),
+        ("XMODERATE APPROVE one", "501 invalid number: one"),
+        ("MODE READER extra", "501 too many arguments: extra"),
+        (
+            "XFEATURE COMPRESS GZIP TERMINATOR extra",
+            "501 too many arguments: extra",
+        ),
+        (
+            "CAPABILITIES AUTHINFO extra",
+            "501 too many arguments: extra",
+        ),
+        ("DATE now", "501 too many arguments: now"),
+        ("HELP me", "501 too many arguments: me"),
+    ];
+    let mut client = ClientMock::new();
+    for (command, response) in cases {
+        client = client.expect(command, response);
+    }
+    client.run(storage.clone(), auth.clone()).await;
+
+    ClientMock::new()
+        .expect("IHAVE id@example", "501 invalid message-id: id@example")
+        .expect("CHECK <>", "501 invalid message-id: <>")
+        .expect("TAKETHIS", "501 message-id required")
+        .run_as_peer(storage, auth)
+        .await;
+}
+
+/// RFC 3977 Section 6.1.2: a range whose end is below its start is valid
+/// and selects no articles, so it is answered like any other empty range.
+#[tokio::test]
+async fn reversed_ranges_select_no_articles() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let (_, article) = renews::parse_message(
+        "Message-ID: <r@test>\r\nNewsgroups: misc\r\nFrom: a@test\r\nSubject: s\r\n\r\nb\r\n",
+    )
+    .unwrap();
+    storage.store_article(&article).await.unwrap();
+    ClientMock::new()
+        .expect("GROUP misc", "211 1 1 1 misc")
+        .expect("OVER 5-2", "423 no articles in that range")
+        .expect("HDR Subject 5-2", "423 no articles in that range")
+        .run(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn future_dates_beyond_clock_skew_are_refused() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let mut cfg = utils::create_minimal_config();
+    cfg.clock_skew_secs = 600;
+    let near = chrono::Utc::now() + chrono::Duration::seconds(120);
+    let (date, time) = (near.format("%Y%m%d"), near.format("%H%M%S"));
+    ClientMock::new()
+        .expect(
+            "NEWNEWS misc 20991231 000000 GMT",
+            "501 date is in the future",
+        )
+        .expect("NEWGROUPS 20991231 235959", "501 date is in the future")
+        .expect_multi(
+            &format!("NEWGROUPS {date} {time} GMT"),
+            vec!["231 list of new newsgroups follows", "."],
+        )
+        .run_with_cfg(cfg, storage, auth)
+        .await;
+}
+
 #[tokio::test]
 async fn head_without_group_returns_412() {
     let (storage, auth) = utils::setup().await;
@@ -491,6 +684,79 @@ async fn list_unknown_keyword() {
         .await;
 }
 
+#[tokio::test]
+async fn list_counts_adds_article_counts() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    storage.add_group("mod.test", true).await.unwrap();
+    storage.add_group("other", false).await.unwrap();
+    for n in 1..=3 {
+        let article = format!("Message-ID: <{n}@test>\r\nNewsgroups: misc.test\r\n\r\nBody");
+        let (_, msg) = parse_message(&article).unwrap();
+        storage.store_article(&msg).await.unwrap();
+    }
+    ClientMock::new()
+        .expect_multi(
+            "LIST COUNTS *.test",
+            vec![
+                "215 list of newsgroups follows",
+                "misc.test 3 1 3 y",
+                "mod.test 0 0 0 m",
+                ".",
+            ],
+        )
+        .expect_multi(
+            "LIST ACTIVE *.test",
+            vec![
+                "215 list of newsgroups follows",
+                "misc.test 3 1 y",
+                "mod.test 0 0 m",
+                ".",
+            ],
+        )
+        .run(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn list_activity_orders_groups_by_last_post() {
+    let (storage, auth) = utils::setup().await;
+    for group in ["a.test", "m.test", "z.test", "other"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+    // a.test is posted to last, so it comes first whether or not the clock
+    // ticks in between
+    for (n, group) in [(1, "z.test"), (2, "z.test"), (3, "a.test"), (4, "other")] {
+        let article = format!("Message-ID: <{n}@test>\r\nNewsgroups: {group}\r\n\r\nBody");
+        let (_, msg) = parse_message(&article).unwrap();
+        storage.store_article(&msg).await.unwrap();
+    }
+    // Expired articles still count as received
+    storage.delete_article_by_id("<1@test>").await.unwrap();
+
+    let activity: std::collections::HashMap<_, _> = storage
+        .list_group_activity()
+        .map(|r| r.unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect();
+    let last = |group: &str| activity[group].last_post.unwrap();
+    ClientMock::new()
+        .expect_multi(
+            "LIST ACTIVITY *.test",
+            vec![
+                "215 group activity follows".to_string(),
+                format!("a.test {} 1", last("a.test")),
+                format!("z.test {} 2", last("z.test")),
+                "m.test 0 0".to_string(),
+                ".".to_string(),
+            ],
+        )
+        .run(storage, auth)
+        .await;
+}
+
 #[tokio::test]
 async fn list_distrib_pats_not_supported() {
     let (storage, auth) = utils::setup().await;
@@ -842,7 +1108,7 @@ async fn listgroup_returns_numbers() {
     ClientMock::new()
         .expect_multi(
             "LISTGROUP misc.test",
-            vec!["211 article numbers follow", "1", "."],
+            vec!["211 1 1 1 misc.test list follows", "1", "."],
         )
         .run(storage, auth)
         .await;
@@ -896,6 +1162,57 @@ async fn list_newsgroups_returns_descriptions() {
         .await;
 }
 
+#[tokio::test]
+async fn list_newsgroups_filters_by_wildmat() {
+    let (storage, auth) = utils::setup().await;
+    storage
+        .add_group_with_description("misc.test", false, "A test newsgroup")
+        .await
+        .unwrap();
+    storage.add_group("alt.test", false).await.unwrap();
+    assert!(
+        storage
+            .set_group_description("alt.test", "Alternative tests")
+            .await
+            .unwrap()
+    );
+    assert!(
+        !storage
+            .set_group_description("no.such.group", "Missing")
+            .await
+            .unwrap()
+    );
+    ClientMock::new()
+        .expect_multi(
+            "LIST NEWSGROUPS alt.*",
+            vec!["215 descriptions follow", "alt.test Alternative tests", "."],
+        )
+        .run(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn list_subscriptions_returns_default_subscriptions() {
+    let (storage, auth) = utils::setup().await;
+    for group in ["misc.news", "misc.test", "alt.test"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+    let mut cfg = utils::create_minimal_config();
+    cfg.default_subscriptions = vec!["misc.*".into(), "no.such.group".into()];
+    ClientMock::new()
+        .expect_multi(
+            "LIST SUBSCRIPTIONS",
+            vec![
+                "215 list of recommended newsgroups follows",
+                "misc.news",
+                "misc.test",
+                ".",
+            ],
+        )
+        .run_with_cfg(cfg, storage, auth)
+        .await;
+}
+
 #[tokio::test]
 async fn list_all_keywords() {
     let (storage, auth) = utils::setup().await;
@@ -934,6 +1251,7 @@ async fn list_all_keywords() {
                 "References:",
                 ":bytes",
                 ":lines",
+                "Xref:full",
                 ".",
             ],
         )
@@ -1033,6 +1351,112 @@ async fn hdr_subject_range() {
         .await;
 }
 
+#[tokio::test]
+async fn hdr_metadata_with_binary_detection() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    store_test_article(
+        &*storage,
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\nSubject: A\r\n\r\n\
+         =ybegin part=2 total=5 line=128 size=2000 name=a.bin\r\n=ypart begin=1 end=10\r\n\
+         data\r\n=yend size=10 part=2\r\n",
+    )
+    .await;
+    store_test_article(
+        &*storage,
+        "Message-ID: <2@test>\r\nNewsgroups: misc.test\r\nSubject: B\r\n\r\n\
+         begin 644 b.txt\r\n#86)C\r\n`\r\nend\r\n",
+    )
+    .await;
+    store_test_article(
+        &*storage,
+        "Message-ID: <3@test>\r\nNewsgroups: misc.test\r\nSubject: C\r\n\r\nBody",
+    )
+    .await;
+    let mut cfg: renews::config::Config = toml::from_str("addr=\":119\"").unwrap();
+    cfg.detect_binaries = true;
+    ClientMock::new()
+        .expect_multi(
+            "LIST HEADERS",
+            vec![
+                "215 metadata items supported:",
+                ":",
+                ":lines",
+                ":bytes",
+                ":binary",
+                ".",
+            ],
+        )
+        .expect("GROUP misc.test", "211 3 1 3 misc.test")
+        .expect_multi(
+            "HDR :binary 1-3",
+            vec!["225 Headers follow", "1 yenc 2/5", "2 uuencode", "3", "."],
+        )
+        .expect_multi(
+            "HDR :lines 1-3",
+            vec!["225 Headers follow", "1 4", "2 4", "3 1", "."],
+        )
+        .expect_multi("HDR :bytes 3", vec!["225 Headers follow", "3 65", "."])
+        .run_with_cfg(cfg, storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn hdr_ranges_read_the_overview() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    storage.add_group("misc.other", false).await.unwrap();
+    let mut cfg = utils::create_minimal_config();
+    cfg.overview_headers = vec!["Keywords".into()];
+    storage.set_overview_headers(cfg.extra_overview_headers());
+    store_test_article(
+        &*storage,
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\nSubject: One\r\n\
+         Keywords: fish\r\n\r\nBody",
+    )
+    .await;
+    store_test_article(
+        &*storage,
+        "Message-ID: <2@test>\r\nNewsgroups: misc.test,misc.other\r\nSubject: Two\r\n\
+         References: <1@test>\r\n\r\nLine\r\nLine",
+    )
+    .await;
+    ClientMock::new()
+        .expect("GROUP misc.test", "211 2 1 2 misc.test")
+        // Cross-posts count the Xref header ARTICLE sends, as by Message-ID
+        .expect_multi(
+            "HDR :bytes <2@test>",
+            vec!["225 Headers follow", "0 143", "."],
+        )
+        .expect_multi(
+            "HDR :bytes 1-2",
+            vec!["225 Headers follow", "1 83", "2 143", "."],
+        )
+        .expect_multi(
+            "HDR :lines 1-",
+            vec!["225 Headers follow", "1 1", "2 2", "."],
+        )
+        .expect_multi(
+            "HDR references 1-2",
+            vec!["225 Headers follow", "1", "2 <1@test>", "."],
+        )
+        .expect_multi(
+            "HDR Keywords 1-2",
+            vec!["225 Headers follow", "1 fish", "2", "."],
+        )
+        .expect_multi(
+            "XPAT Subject 1-2 T*",
+            vec!["221 Header follows", "2 Two", "."],
+        )
+        // Headers outside the overview are read from the articles
+        .expect_multi(
+            "HDR Newsgroups 2",
+            vec!["225 Headers follow", "2 misc.test,misc.other", "."],
+        )
+        .run_with_cfg(cfg, storage, auth)
+        .await;
+}
+
 #[tokio::test]
 async fn hdr_all_headers_message_id() {
     let (storage, auth) = utils::setup().await;
@@ -1114,7 +1538,7 @@ async fn over_message_id() {
             "OVER <1@test>",
             vec![
                 "224 Overview information follows",
-                "0\tA\ta@test\t\t<1@test>\t\t4\t1",
+                "0\tA\ta@test\t\t<1@test>\t\t79\t1\t",
                 ".",
             ],
         )
@@ -1142,8 +1566,8 @@ async fn over_range() {
             "OVER 1-2",
             vec![
                 "224 Overview information follows",
-                "1\tA\ta@test\t\t<1@test>\t\t4\t1",
-                "2\tB\tb@test\t\t<2@test>\t\t4\t1",
+                "1\tA\ta@test\t\t<1@test>\t\t79\t1\t",
+                "2\tB\tb@test\t\t<2@test>\t\t79\t1\t",
                 ".",
             ],
         )
@@ -1152,62 +1576,203 @@ async fn over_range() {
 }
 
 #[tokio::test]
-async fn head_range() {
-    let (storage, auth) = utils::setup().await;
+async fn over_ranges_are_served_from_the_overview_cache() {
+    use renews::storage::cache::{CachedStorage, OverviewCache};
+    use std::sync::Arc;
+
+    let (inner, auth) = utils::setup().await;
+    let cache = Arc::new(OverviewCache::new(64 * 1024));
+    let storage: Arc<dyn renews::storage::Storage> =
+        Arc::new(CachedStorage::uncached(inner).with_overview_cache(cache.clone()));
     storage.add_group("misc.test", false).await.unwrap();
+    for (n, subject) in [(1, "A"), (2, "B")] {
+        store_test_article(
+            &*storage,
+            &format!(
+                "Message-ID: <{n}@test>\r\nNewsgroups: misc.test\r\nSubject: {subject}\r\n\
+                 From: a@test\r\n\r\nBody"
+            ),
+        )
+        .await;
+    }
+    fn overview<'a>(lines: &[&'a str]) -> Vec<&'a str> {
+        let mut expected = vec!["224 Overview information follows"];
+        expected.extend_from_slice(lines);
+        expected.push(".");
+        expected
+    }
+    let a = "1\tA\ta@test\t\t<1@test>\t\t79\t1\t";
+    let b = "2\tB\ta@test\t\t<2@test>\t\t79\t1\t";
+    let c = "3\tC\ta@test\t\t<3@test>\t\t79\t1\t";
+
+    ClientMock::new()
+        .expect("GROUP misc.test", "211 2 1 2 misc.test")
+        .expect_multi("OVER 1-2", overview(&[a, b]))
+        .expect_multi("XOVER 1-2", overview(&[a, b]))
+        .expect("STAT", "223 2 <2@test> article exists")
+        .run(storage.clone(), auth.clone())
+        .await;
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+    // A new article in the group makes the cached range stale
     store_test_article(
         &*storage,
-        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\n\r\nA",
+        "Message-ID: <3@test>\r\nNewsgroups: misc.test\r\nSubject: C\r\nFrom: a@test\r\n\r\nBody",
     )
     .await;
+    assert_eq!(cache.stats().entries, 0);
+    storage.delete_article_by_id("<2@test>").await.unwrap();
+    ClientMock::new()
+        .expect("GROUP misc.test", "211 2 1 3 misc.test")
+        .expect_multi("OVER 1-", overview(&[a, c]))
+        .expect_multi("OVER 1-3", overview(&[a, c]))
+        .run(storage.clone(), auth)
+        .await;
+    assert_eq!(cache.stats().hits, 2);
+
+    // Rebuilding the overview of the group drops its cached ranges
+    assert!(cache.stats().entries > 0);
+    storage.rebuild_overview("misc.test").await.unwrap();
+    assert_eq!(cache.stats().entries, 0);
+}
+
+#[tokio::test]
+async fn over_ranges_are_paged_at_max_over_range() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    for n in 1..=5 {
+        store_test_article(
+            &*storage,
+            &format!(
+                "Message-ID: <{n}@test>\r\nNewsgroups: misc.test\r\nSubject: S{n}\r\n\
+                 From: a@test\r\n\r\nBody"
+            ),
+        )
+        .await;
+    }
+    for id in ["<3@test>", "<4@test>"] {
+        storage.delete_article_by_id(id).await.unwrap();
+    }
+    let line = |n: u64| format!("{n}\tS{n}\ta@test\t\t<{n}@test>\t\t80\t1\t");
+    let page = |status: &str, numbers: &[u64]| {
+        let mut lines = vec![status.to_string()];
+        lines.extend(numbers.iter().map(|&n| line(n)));
+        lines.push(".".into());
+        lines
+    };
+    let mut cfg = utils::create_minimal_config();
+    cfg.max_over_range = Some(2);
+    let mut capabilities = utils::capabilities_lines();
+    capabilities.insert(capabilities.len() - 1, "XOVERPAGE 2".into());
+
+    ClientMock::new()
+        .expect_multi("CAPABILITIES", capabilities)
+        .expect("GROUP misc.test", "211 3 1 5 misc.test")
+        .expect_multi(
+            "OVER 1-",
+            page("224 Overview information follows; next range 3-", &[1, 2]),
+        )
+        // A page whose articles all expired still names the next one
+        .expect_multi(
+            "OVER 3-",
+            page("224 Overview information follows; next range 5-", &[]),
+        )
+        .expect_multi("OVER 5-", page("224 Overview information follows", &[5]))
+        .expect_multi(
+            "XOVER 1-5",
+            page("224 Overview information follows; next range 3-5", &[1, 2]),
+        )
+        .expect_multi(
+            "OVER 1-2",
+            page("224 Overview information follows", &[1, 2]),
+        )
+        .run_with_cfg(cfg, storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn extra_overview_headers_are_listed_and_served() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    let mut cfg = utils::create_minimal_config();
+    cfg.overview_headers = vec!["Newsgroups:full".into(), "Keywords".into()];
+    storage.set_overview_headers(cfg.extra_overview_headers());
     store_test_article(
         &*storage,
-        "Message-ID: <2@test>\r\nNewsgroups: misc.test\r\n\r\nB",
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\nSubject: S\r\n\
+         From: a@test\r\n\r\nBody",
     )
     .await;
+    let line = "1\tS\ta@test\t\t<1@test>\t\t79\t1\t\tNewsgroups: misc.test\t";
+
+    // Stored overview rows carry the same fields
+    assert_eq!(
+        storage.get_overview_range("misc.test", 1, 1).await.unwrap(),
+        vec![line]
+    );
     ClientMock::new()
-        .expect("GROUP misc.test", "211 2 1 2 misc.test")
         .expect_multi(
-            "HEAD 1-2",
+            "LIST OVERVIEW.FMT",
             vec![
-                "221 1 <1@test> article headers follow",
-                "Message-ID: <1@test>",
-                "Newsgroups: misc.test",
-                ".",
-                "221 2 <2@test> article headers follow",
-                "Message-ID: <2@test>",
-                "Newsgroups: misc.test",
+                "215 Order of fields in overview database.",
+                "Subject:",
+                "From:",
+                "Date:",
+                "Message-ID:",
+                "References:",
+                ":bytes",
+                ":lines",
+                "Xref:full",
+                "Newsgroups:full",
+                "Keywords:full",
                 ".",
             ],
         )
-        .run(storage, auth)
+        .expect("GROUP misc.test", "211 1 1 1 misc.test")
+        .expect_multi(
+            "OVER 1",
+            vec!["224 Overview information follows", line, "."],
+        )
+        .run_with_cfg(cfg, storage, auth)
         .await;
 }
 
 #[tokio::test]
-async fn body_range() {
+async fn cross_posted_articles_carry_xref() {
     let (storage, auth) = utils::setup().await;
+    storage.add_group("alt.test", false).await.unwrap();
     storage.add_group("misc.test", false).await.unwrap();
     store_test_article(
         &*storage,
-        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\n\r\nA",
+        "Message-ID: <0@test>\r\nNewsgroups: misc.test\r\nSubject: Z\r\nFrom: z@test\r\n\r\nBody",
     )
     .await;
     store_test_article(
         &*storage,
-        "Message-ID: <2@test>\r\nNewsgroups: misc.test\r\n\r\nB",
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test,alt.test\r\nSubject: A\r\nFrom: a@test\r\n\r\nBody",
     )
     .await;
     ClientMock::new()
         .expect("GROUP misc.test", "211 2 1 2 misc.test")
         .expect_multi(
-            "BODY 1-2",
+            "HEAD 2",
             vec![
-                "222 1 <1@test> article body follows",
-                "A",
+                "221 2 <1@test> article headers follow",
+                "Message-ID: <1@test>",
+                "Newsgroups: misc.test,alt.test",
+                "Subject: A",
+                "From: a@test",
+                "Xref: localhost alt.test:1 misc.test:2",
                 ".",
-                "222 2 <2@test> article body follows",
-                "B",
+            ],
+        )
+        .expect_multi(
+            "OVER 1-2",
+            vec![
+                "224 Overview information follows",
+                "1\tZ\tz@test\t\t<0@test>\t\t79\t1\t",
+                "2\tA\ta@test\t\t<1@test>\t\t128\t1\tXref: localhost alt.test:1 misc.test:2",
                 ".",
             ],
         )
@@ -1215,8 +1780,35 @@ async fn body_range() {
         .await;
 }
 
+/// RFC 3977 allows only an article number or message-id for HEAD; ranges
+/// are left to OVER and HDR.
 #[tokio::test]
-async fn article_range() {
+async fn head_range_is_refused() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    store_test_article(
+        &*storage,
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\n\r\nA",
+    )
+    .await;
+    ClientMock::new()
+        .expect("GROUP misc.test", "211 1 1 1 misc.test")
+        .expect("HEAD 1-2", "501 invalid article: 1-2")
+        .expect_multi(
+            "HEAD 1",
+            vec![
+                "221 1 <1@test> article headers follow",
+                "Message-ID: <1@test>",
+                "Newsgroups: misc.test",
+                ".",
+            ],
+        )
+        .run(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn body_range() {
     let (storage, auth) = utils::setup().await;
     storage.add_group("misc.test", false).await.unwrap();
     store_test_article(
@@ -1232,18 +1824,12 @@ async fn article_range() {
     ClientMock::new()
         .expect("GROUP misc.test", "211 2 1 2 misc.test")
         .expect_multi(
-            "ARTICLE 1-2",
+            "BODY 1-2",
             vec![
-                "220 1 <1@test> article follows",
-                "Message-ID: <1@test>",
-                "Newsgroups: misc.test",
-                "",
+                "222 1 <1@test> article body follows",
                 "A",
                 ".",
-                "220 2 <2@test> article follows",
-                "Message-ID: <2@test>",
-                "Newsgroups: misc.test",
-                "",
+                "222 2 <2@test> article body follows",
                 "B",
                 ".",
             ],
@@ -1252,6 +1838,24 @@ async fn article_range() {
         .await;
 }
 
+/// RFC 3977 allows only an article number or message-id for ARTICLE.
+#[tokio::test]
+async fn article_range_is_refused() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    store_test_article(
+        &*storage,
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\n\r\nA",
+    )
+    .await;
+    ClientMock::new()
+        .expect("GROUP misc.test", "211 1 1 1 misc.test")
+        .expect("ARTICLE 1-", "501 invalid article: 1-")
+        .expect("STAT 1", "223 1 <1@test> article exists")
+        .run(storage, auth)
+        .await;
+}
+
 #[tokio::test]
 async fn ihave_example() {
     let (storage, auth) = utils::setup().await;
@@ -1283,7 +1887,7 @@ async fn ihave_example() {
             "IHAVE <i.am.an.article.you.will.want@example.com>",
             "435 article not wanted",
         )
-        .run(storage, auth)
+        .run_as_peer(storage, auth)
         .await;
 }
 
@@ -1333,7 +1937,7 @@ async fn takethis_example() {
             utils::request_lines(take_reject.trim_end_matches("\r\n")),
             vec!["439 <i.am.an.article.you.have@example.com>"],
         )
-        .run(storage, auth)
+        .run_as_peer(storage, auth)
         .await;
 }
 
@@ -1379,7 +1983,45 @@ async fn mode_stream_check_and_takethis() {
         )
         .expect("CHECK <stream1@test>", "438 <stream1@test>")
         .expect("CHECK <stream2@test>", "438 <stream2@test>")
-        .run(storage, auth)
+        .run_as_peer(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn removed_and_refused_articles_are_not_wanted_again() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    utils::store_test_article(
+        &*storage,
+        "Message-ID: <removed@test>\r\nNewsgroups: misc.test\r\n\r\nBody\r\n",
+    )
+    .await;
+    storage
+        .delete_article_by_id("<removed@test>")
+        .await
+        .unwrap();
+    ClientMock::new()
+        .expect("IHAVE <removed@test>", "435 article not wanted")
+        .expect("MODE STREAM", "203 Streaming permitted")
+        .expect("CHECK <removed@test>", "438 <removed@test>")
+        .expect_request_multi(
+            utils::request_lines(
+                concat!(
+                    "TAKETHIS <uncarried@test>\r\n",
+                    "Newsgroups: not.carried\r\n",
+                    "From: a@test\r\n",
+                    "Subject: spam\r\n",
+                    "Message-ID: <uncarried@test>\r\n",
+                    "\r\n",
+                    "Body\r\n",
+                    ".\r\n"
+                )
+                .trim_end_matches("\r\n"),
+            ),
+            vec!["439 <uncarried@test>"],
+        )
+        .expect("CHECK <uncarried@test>", "438 <uncarried@test>")
+        .run_as_peer(storage, auth)
         .await;
 }
 
@@ -1,9 +1,10 @@
 //! Tests for posting and authentication security features
 
-use renews::{config::Config, session::Session};
+use renews::posting_account::{IdObfuscator, RotatingHash};
+use renews::{ConnectionInfo, config::Config, handle_client_with_info, session::Session};
 
 mod utils;
-use utils::create_minimal_config;
+use utils::{ClientMock, create_minimal_config};
 
 /// Test that the session logic works correctly for secure mode (default config)
 #[tokio::test]
@@ -204,3 +205,178 @@ fn test_config_update_runtime_preserves_flags() {
     assert!(config1.allow_auth_insecure_connections);
     assert!(config1.allow_anonymous_posting);
 }
+
+/// Start a plaintext server that passes the client address to the session.
+async fn start_server_with_peer_ip(
+    config: Config,
+) -> (
+    std::net::SocketAddr,
+    renews::storage::DynStorage,
+    tokio::task::JoinHandle<()>,
+) {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &config);
+    let cfg = std::sync::Arc::new(tokio::sync::RwLock::new(config));
+    let queue =
+        utils::create_test_queue_with_workers(storage.clone(), auth.clone(), cfg.clone()).await;
+
+    let server_storage = storage.clone();
+    let handle = tokio::spawn(async move {
+        let (sock, peer) = listener.accept().await.unwrap();
+        let info = ConnectionInfo {
+            peer_ip: Some(peer.ip()),
+            ..ConnectionInfo::default()
+        };
+        handle_client_with_info(sock, server_storage, auth, cfg, info, queue, usage_tracker)
+            .await
+            .unwrap();
+    });
+    (addr, storage, handle)
+}
+
+fn anonymous_config() -> Config {
+    let mut config = create_minimal_config();
+    config.allow_anonymous_posting = true;
+    config.posting_accounts.salt = Some("test-salt".into());
+    config
+}
+
+fn loopback_token(config: &Config) -> String {
+    RotatingHash::from_config(&config.posting_accounts).posting_account(
+        "127.0.0.1".parse().unwrap(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    )
+}
+
+/// Test that anonymous posts carry the posting-account token in Injection-Info
+#[tokio::test]
+async fn test_anonymous_post_has_posting_account() {
+    let config = anonymous_config();
+    let token = loopback_token(&config);
+    let (addr, storage, handle) = start_server_with_peer_ip(config).await;
+
+    ClientMock::new()
+        .expect(
+            "POST",
+            "340 send article to be posted. End with <CR-LF>.<CR-LF>",
+        )
+        .expect_request_multi(
+            vec![
+                "Message-ID: <anon@test>",
+                "Newsgroups: misc",
+                "From: anon@example.com",
+                "Subject: anonymous",
+                "Injection-Info: forged",
+                "",
+                "Body",
+                ".",
+            ],
+            vec!["240 article received"],
+        )
+        .expect("QUIT", "205 closing connection")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let article = storage
+        .get_article_by_id("<anon@test>")
+        .await
+        .unwrap()
+        .unwrap();
+    let injection_info: Vec<_> = article
+        .headers
+        .iter()
+        .filter(|(k, _)| k.eq_ignore_ascii_case("Injection-Info"))
+        .map(|(_, v)| v.as_str())
+        .collect();
+    assert_eq!(
+        injection_info,
+        vec![format!("test; posting-account=\"{token}\"")]
+    );
+}
+
+/// Test that a banned posting-account token cannot post
+#[tokio::test]
+async fn test_banned_posting_account_is_refused() {
+    let mut config = anonymous_config();
+    config.posting_accounts.banned = vec![loopback_token(&config)];
+    let (addr, _storage, handle) = start_server_with_peer_ip(config).await;
+
+    ClientMock::new()
+        .expect("POST", "440 posting not allowed")
+        .expect("QUIT", "205 closing connection")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+}
+
+/// Test that requiring TLS refuses AUTHINFO on plaintext despite the
+/// insecure authentication setting, and names the TLS port
+#[tokio::test]
+async fn test_require_tls_for_auth_points_at_tls_port() {
+    let (storage, auth) = utils::setup().await;
+    let mut config = create_minimal_config();
+    config.allow_auth_insecure_connections = true;
+    config.require_tls_for_auth = true;
+    config.tls_addr = Some(":5630".into());
+
+    let mut capabilities = utils::capabilities_lines();
+    let end = capabilities.len() - 1;
+    capabilities.insert(end, "XREQUIRETLS 5630".into());
+    ClientMock::new()
+        .expect_multi("CAPABILITIES", capabilities)
+        .expect(
+            "AUTHINFO USER alice",
+            "483 Secure connection required; use TLS on port 5630",
+        )
+        .run_with_cfg(config, storage, auth)
+        .await;
+}
+
+/// Test that TLS sessions are not told to use TLS
+#[tokio::test]
+async fn test_require_tls_for_auth_over_tls() {
+    let (storage, auth) = utils::setup().await;
+    let mut config = create_minimal_config();
+    config.require_tls_for_auth = true;
+    config.tls_addr = Some(":5630".into());
+
+    let mut capabilities = utils::capabilities_lines();
+    capabilities.insert(4, "AUTHINFO USER".into());
+    ClientMock::new()
+        .expect_multi("CAPABILITIES", capabilities)
+        .expect("AUTHINFO USER alice", "381 password required")
+        .run_with_cfg_tls(config, storage, auth)
+        .await;
+}
+
+/// Test that the TLS port comes from `tls_addr` or a TLS listener
+#[test]
+fn test_tls_port() {
+    let toml = r#"
+addr = ":119"
+require_tls_for_auth = true
+
+[[listener]]
+addr = "127.0.0.1:1119"
+
+[[listener]]
+addr = "[::1]:5631"
+tls = true
+"#;
+    let mut config: Config = toml::from_str(toml).unwrap();
+    assert!(config.require_tls_for_auth);
+    assert_eq!(config.tls_port(), Some(5631));
+    config.tls_addr = Some("563".into());
+    assert_eq!(config.tls_port(), Some(563));
+    config.tls_addr = None;
+    config.listeners.clear();
+    assert_eq!(config.tls_port(), None);
+}
@@ -1,29 +1,76 @@
+#[path = "integration/archived.rs"]
+mod archived;
 #[path = "integration/auth.rs"]
 mod auth;
+#[path = "integration/body_range.rs"]
+mod body_range;
+#[path = "integration/bridge.rs"]
+mod bridge;
 #[path = "integration/cancel_lock.rs"]
 mod cancel_lock;
+#[path = "integration/connection_limits.rs"]
+mod connection_limits;
+#[path = "integration/content_filters.rs"]
+mod content_filters;
 #[path = "integration/control.rs"]
 mod control;
+#[path = "integration/control_socket.rs"]
+mod control_socket;
+#[path = "integration/digest.rs"]
+mod digest;
+#[path = "integration/embedded.rs"]
+mod embedded;
+#[path = "integration/gateway.rs"]
+mod gateway;
+#[path = "integration/group_acl.rs"]
+mod group_acl;
 #[path = "integration/handler_failures.rs"]
 mod handler_failures;
+#[path = "integration/health.rs"]
+mod health;
+#[cfg(feature = "http-api")]
+#[path = "integration/http_api.rs"]
+mod http_api;
 #[path = "integration/idle_timeout.rs"]
 mod idle_timeout;
+#[path = "integration/listeners.rs"]
+mod listeners;
 #[path = "integration/max_size.rs"]
 mod max_size;
 #[path = "integration/moderated.rs"]
 mod moderated;
+#[path = "integration/nocem.rs"]
+mod nocem;
 #[path = "integration/peers.rs"]
 mod peers;
+#[path = "integration/pipelining.rs"]
+mod pipelining;
+#[path = "integration/post_check.rs"]
+mod post_check;
+#[path = "integration/post_rewrite.rs"]
+mod post_rewrite;
+#[cfg(feature = "postgres")]
+#[path = "integration/postgres.rs"]
+mod postgres;
+#[cfg(feature = "postgres")]
+#[path = "integration/postgres_copy.rs"]
+mod postgres_copy;
+#[path = "integration/replication.rs"]
+mod replication;
 #[path = "integration/resource_exhaustion.rs"]
 mod resource_exhaustion;
 #[path = "integration/retention.rs"]
 mod retention;
 #[path = "integration/storage.rs"]
 mod storage;
+#[path = "integration/storage_failures.rs"]
+mod storage_failures;
 #[path = "integration/tls.rs"]
 mod tls;
 #[path = "utils.rs"]
 mod utils;
+#[path = "integration/vhosts.rs"]
+mod vhosts;
 #[cfg(feature = "websocket")]
 #[path = "integration/ws.rs"]
 mod ws;
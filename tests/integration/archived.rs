@@ -0,0 +1,89 @@
+use crate::utils::{self, ClientMock, collect_article_numbers, store_test_article};
+
+fn article(message_id: &str) -> String {
+    format!(
+        "Message-ID: {message_id}\r\nNewsgroups: old.group\r\nFrom: user@example.com\r\n\
+         Subject: t\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\nPath: peer!not-for-mail\r\n\r\nBody\r\n."
+    )
+}
+
+async fn archived_setup() -> (
+    std::sync::Arc<dyn renews::storage::Storage>,
+    std::sync::Arc<dyn renews::auth::AuthProvider>,
+) {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("old.group", false).await.unwrap();
+    store_test_article(
+        &*storage,
+        "Message-ID: <kept@test>\r\nNewsgroups: old.group\r\nFrom: u@test\r\nSubject: t\r\n\r\nBody",
+    )
+    .await;
+    storage.set_group_archived("old.group", true).await.unwrap();
+    (storage, auth)
+}
+
+#[tokio::test]
+async fn archived_group_stays_readable() {
+    let (storage, auth) = archived_setup().await;
+    ClientMock::new()
+        .expect("GROUP old.group", "211 1 1 1 old.group")
+        .expect_multi(
+            "LIST ACTIVE old.*",
+            vec!["215 list of newsgroups follows", "old.group 1 1 n", "."],
+        )
+        .run(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn post_to_archived_group_is_refused() {
+    let (storage, auth) = archived_setup().await;
+    auth.add_user("user", "pass").await.unwrap();
+    ClientMock::new()
+        .expect("AUTHINFO USER user", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect(
+            "POST",
+            "340 send article to be posted. End with <CR-LF>.<CR-LF>",
+        )
+        .expect(&article("<new@test>"), "441 group is archived")
+        .run_tls(storage.clone(), auth)
+        .await;
+    assert_eq!(
+        collect_article_numbers(&*storage, "old.group").await,
+        vec![1]
+    );
+}
+
+#[tokio::test]
+async fn transit_article_for_archived_group_is_refused() {
+    let (storage, auth) = archived_setup().await;
+    ClientMock::new()
+        .expect("IHAVE <new@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect(&article("<new@test>"), "437 group is archived")
+        .expect("IHAVE <new@test>", "435 article not wanted")
+        .run_as_peer(storage.clone(), auth.clone())
+        .await;
+    assert_eq!(
+        collect_article_numbers(&*storage, "old.group").await,
+        vec![1]
+    );
+
+    // Unarchiving opens the group again
+    storage
+        .set_group_archived("old.group", false)
+        .await
+        .unwrap();
+    ClientMock::new()
+        .expect(
+            "IHAVE <other@test>",
+            "335 Send it; end with <CR-LF>.<CR-LF>",
+        )
+        .expect(&article("<other@test>"), "235 Article transferred OK")
+        .run_as_peer(storage.clone(), auth)
+        .await;
+    assert_eq!(
+        collect_article_numbers(&*storage, "old.group").await,
+        vec![1, 2]
+    );
+}
@@ -19,3 +19,55 @@ async fn add_and_remove_user() {
     auth.remove_user("user").await.unwrap();
     assert!(!auth.verify_user("user", "pass").await.unwrap());
 }
+
+/// Write an authentication program accepting `alice` with password
+/// `secret` and naming `mallory` for `bob`.
+fn auth_program(dir: &tempfile::TempDir) -> renews::config::AuthProgramConfig {
+    use std::os::unix::fs::PermissionsExt;
+    let path = dir.path().join("auth.sh");
+    std::fs::write(
+        &path,
+        "#!/bin/sh\n\
+         input=$(cat | tr -d '\\r')\n\
+         case \"$input\" in\n\
+           *'ClientAuthname: alice'*'ClientPassword: secret'*) echo 'User:alice' ;;\n\
+           *'ClientAuthname: bob'*) echo 'User:mallory' ;;\n\
+           *) echo 'no such user' >&2; exit 1 ;;\n\
+         esac\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    toml::from_str(&format!("path = {:?}", path.to_str().unwrap())).unwrap()
+}
+
+#[tokio::test]
+async fn auth_program_checks_credentials() {
+    let dir = tempfile::tempdir().unwrap();
+    let db: renews::auth::DynAuth =
+        std::sync::Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    db.add_user("alice", "database").await.unwrap();
+    db.add_admin_without_key("alice").await.unwrap();
+    let auth = renews::auth::program::for_config(db, Some(&auth_program(&dir)));
+
+    assert!(auth.verify_user("alice", "secret").await.unwrap());
+    assert!(!auth.verify_user("alice", "wrong").await.unwrap());
+    assert!(!auth.verify_user("alice", "database").await.unwrap());
+    assert!(!auth.verify_user("bob", "secret").await.unwrap());
+    // Everything but credentials still comes from the database
+    assert!(auth.is_admin("alice").await.unwrap());
+}
+
+#[tokio::test]
+async fn auth_program_failures_are_reported() {
+    let db: renews::auth::DynAuth =
+        std::sync::Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    let missing: renews::config::AuthProgramConfig =
+        toml::from_str("path = \"/nonexistent/renews-auth\"").unwrap();
+    let auth = renews::auth::program::for_config(db.clone(), Some(&missing));
+    assert!(!auth.verify_user("alice", "secret").await.unwrap());
+
+    let slow: renews::config::AuthProgramConfig =
+        toml::from_str("path = \"/bin/sleep\"\nargs = [\"5\"]\ntimeout_secs = 1").unwrap();
+    let auth = renews::auth::program::for_config(db, Some(&slow));
+    assert!(!auth.verify_user("alice", "secret").await.unwrap());
+}
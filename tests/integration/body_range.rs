@@ -0,0 +1,79 @@
+use crate::utils::{self, ClientMock};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+const ARTICLE: &str = "Message-ID: <range@test>\r\nNewsgroups: misc\r\nFrom: a@test\r\nSubject: range\r\n\r\n0123456789\r\n.dot\r\n";
+
+/// Send `command` and return the response line and data block lines.
+async fn body_range(addr: std::net::SocketAddr, command: &str) -> (String, Vec<String>) {
+    let (mut reader, mut writer) = utils::connect(addr).await;
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+
+    writer
+        .write_all(format!("{command}\r\n").as_bytes())
+        .await
+        .unwrap();
+    line.clear();
+    reader.read_line(&mut line).await.unwrap();
+    let status = line.trim_end().to_string();
+
+    let mut block = Vec::new();
+    if status.starts_with("222") {
+        loop {
+            line.clear();
+            reader.read_line(&mut line).await.unwrap();
+            let data = line.trim_end_matches(['\r', '\n']).to_string();
+            if data == "." {
+                break;
+            }
+            block.push(data);
+        }
+    }
+    writer.write_all(b"QUIT\r\n").await.unwrap();
+    (status, block)
+}
+
+#[tokio::test]
+async fn body_range_resumes_on_new_connection() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    utils::store_test_article(&*storage, ARTICLE).await;
+
+    let (addr, handle) = utils::setup_server(storage.clone(), auth.clone()).await;
+    let (status, block) = body_range(addr, "BODY <range@test> 0-4").await;
+    handle.await.unwrap();
+    let fields: Vec<&str> = status.split_whitespace().collect();
+    assert_eq!(&fields[..4], &["222", "0", "<range@test>", "0-4/18"]);
+    assert_eq!(block, vec!["01234"]);
+
+    // A new connection continues from the token without selecting a group
+    let token = fields[4];
+    let (addr, handle) = utils::setup_server(storage.clone(), auth.clone()).await;
+    let (status, block) = body_range(addr, &format!("BODY {token} 5-")).await;
+    handle.await.unwrap();
+    assert_eq!(
+        status,
+        format!("222 0 <range@test> 5-17/18 {token} body range follows")
+    );
+    assert_eq!(block, vec!["56789", "..dot"]);
+}
+
+#[tokio::test]
+async fn body_range_by_number_and_errors() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    utils::store_test_article(&*storage, ARTICLE).await;
+
+    ClientMock::new()
+        .expect("BODY 1 0-3", "412 no newsgroup selected")
+        .expect("GROUP misc", "211 1 1 1 misc")
+        .expect("BODY 1 5-2", "501 invalid byte range: 5-2")
+        .expect("BODY 1 18-", "501 invalid byte range")
+        .expect("BODY <missing@test> 0-", "430 no such article")
+        .expect(
+            "BODY r0123456789abcdef0123456789abcdef 0-",
+            "430 no such article",
+        )
+        .run(storage, auth)
+        .await;
+}
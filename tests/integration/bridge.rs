@@ -0,0 +1,204 @@
+use crate::utils::store_test_article;
+use renews::bridge::run_bridges;
+use renews::config::Config;
+use renews::storage::{Storage, sqlite::SqliteStorage};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+fn bridge_config(bridge: &str) -> Config {
+    toml::from_str(&format!(
+        "addr = \":119\"\nsite_name = \"test\"\n[[bridge]]\ngroups = \"misc.*\"\n{bridge}"
+    ))
+    .unwrap()
+}
+
+async fn storage_with_articles() -> Arc<dyn Storage> {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("misc.test", false).await.unwrap();
+    storage.add_group("other", false).await.unwrap();
+    for n in 1..=3 {
+        store_test_article(
+            &*storage,
+            &format!(
+                "Message-ID: <{n}@test>\r\nNewsgroups: misc.test\r\nFrom: poster{n}@example.com\r\n\
+                 Subject: Fish & chips {n}\r\nDate: Wed, 05 Oct 2022 0{n}:00:00 GMT\r\n\r\nLine <{n}>\r\nSecond"
+            ),
+        )
+        .await;
+    }
+    store_test_article(
+        &*storage,
+        "Message-ID: <other@test>\r\nNewsgroups: other\r\nSubject: Elsewhere\r\n\r\nBody",
+    )
+    .await;
+    storage
+}
+
+#[tokio::test]
+async fn rss_and_atom_feeds_hold_the_newest_articles() {
+    let storage = storage_with_articles().await;
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().display();
+    let cfg = bridge_config(&format!(
+        "format = \"rss\"\nmax_items = 2\noutput_dir = \"{output}\"\n\
+         link = \"https://news.example.org/{{group}}/{{message_id}}\"\n\
+         [[bridge]]\ngroups = \"misc.*\"\nformat = \"atom\"\noutput_dir = \"{output}\""
+    ));
+    run_bridges(&*storage, &cfg).await.unwrap();
+
+    let rss = std::fs::read_to_string(dir.path().join("misc.test.rss")).unwrap();
+    assert!(rss.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\">"));
+    assert!(rss.contains("<title>Fish &amp; chips 3</title>"));
+    assert!(rss.contains("<title>Fish &amp; chips 2</title>"));
+    assert!(!rss.contains("chips 1"));
+    assert!(rss.find("chips 3").unwrap() < rss.find("chips 2").unwrap());
+    assert!(rss.contains("<link>https://news.example.org/misc.test/3@test</link>"));
+    assert!(rss.contains("<guid isPermaLink=\"false\">&lt;3@test&gt;</guid>"));
+    assert!(rss.contains("<pubDate>Wed, 5 Oct 2022 03:00:00 +0000</pubDate>"));
+    assert!(rss.contains(
+        "<description>&lt;pre&gt;Line &amp;lt;3&amp;gt;\nSecond&lt;/pre&gt;</description>"
+    ));
+    assert!(!dir.path().join("other.rss").exists());
+
+    let atom = std::fs::read_to_string(dir.path().join("misc.test.atom")).unwrap();
+    assert!(atom.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+    assert!(atom.contains("<updated>2022-10-05T03:00:00+00:00</updated>"));
+    for n in 1..=3 {
+        assert!(atom.contains(&format!("<id>news:{n}@test</id>")));
+        assert!(atom.contains(&format!("<link href=\"news:{n}@test\"/>")));
+        assert!(atom.contains(&format!(
+            "<author><name>poster{n}@example.com</name></author>"
+        )));
+    }
+}
+
+/// IMAP server that keeps appended messages in memory.
+async fn fake_imap(mailbox: Arc<Mutex<Vec<String>>>) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mailbox = mailbox.clone();
+            tokio::spawn(async move {
+                let (read, mut write) = socket.into_split();
+                let mut reader = BufReader::new(read);
+                write.write_all(b"* OK fake IMAP ready\r\n").await.unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).await.unwrap() > 0 {
+                    let (tag, command) = line.trim_end().split_once(' ').unwrap();
+                    let (tag, command) = (tag.to_string(), command.to_string());
+                    line.clear();
+                    let reply = if command.starts_with("LOGIN ") {
+                        if command == "LOGIN \"bridge\" \"secret\"" {
+                            format!("{tag} OK logged in\r\n")
+                        } else {
+                            format!("{tag} NO bad credentials\r\n")
+                        }
+                    } else if command == "SELECT \"archive.misc.test\"" {
+                        format!("{tag} OK selected\r\n")
+                    } else if let Some(id) = command.strip_prefix("SEARCH HEADER Message-ID ") {
+                        let id = id.trim_matches('"');
+                        let found: Vec<String> = mailbox
+                            .lock()
+                            .unwrap()
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, m)| m.contains(&format!("Message-ID: {id}\r\n")))
+                            .map(|(i, _)| (i + 1).to_string())
+                            .collect();
+                        format!("* SEARCH {}\r\n{tag} OK search done\r\n", found.join(" "))
+                    } else if let Some(rest) = command.strip_prefix("APPEND ") {
+                        let len: usize = rest
+                            .rsplit_once('{')
+                            .unwrap()
+                            .1
+                            .trim_end_matches('}')
+                            .parse()
+                            .unwrap();
+                        write.write_all(b"+ go ahead\r\n").await.unwrap();
+                        let mut text = vec![0; len + 2];
+                        reader.read_exact(&mut text).await.unwrap();
+                        text.truncate(len);
+                        mailbox
+                            .lock()
+                            .unwrap()
+                            .push(String::from_utf8(text).unwrap());
+                        format!("{tag} OK appended\r\n")
+                    } else if command == "LOGOUT" {
+                        write
+                            .write_all(format!("* BYE\r\n{tag} OK bye\r\n").as_bytes())
+                            .await
+                            .unwrap();
+                        break;
+                    } else {
+                        format!("{tag} NO unexpected\r\n")
+                    };
+                    write.write_all(reply.as_bytes()).await.unwrap();
+                }
+            });
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn imap_bridge_appends_each_article_once() {
+    let storage = storage_with_articles().await;
+    let mailbox = Arc::new(Mutex::new(Vec::new()));
+    let addr = fake_imap(mailbox.clone()).await;
+    let cfg = bridge_config(&format!(
+        "format = \"imap\"\nimap_url = \"imap://{addr}/archive.{{group}}\"\n\
+         imap_user = \"bridge\"\nimap_password = \"secret\""
+    ));
+    run_bridges(&*storage, &cfg).await.unwrap();
+    {
+        let appended = mailbox.lock().unwrap();
+        assert_eq!(appended.len(), 3);
+        assert!(appended[0].starts_with("Message-ID: <1@test>\r\n"));
+        assert!(appended[2].contains("Subject: Fish & chips 3\r\n"));
+        assert!(appended[2].ends_with("\r\n\r\nLine <3>\r\nSecond\r\n"));
+    }
+
+    // A later run only appends what arrived since
+    store_test_article(
+        &*storage,
+        "Message-ID: <4@test>\r\nNewsgroups: misc.test\r\nSubject: New\r\n\r\nBody",
+    )
+    .await;
+    run_bridges(&*storage, &cfg).await.unwrap();
+    let appended = mailbox.lock().unwrap();
+    assert_eq!(appended.len(), 4);
+    assert!(appended[3].starts_with("Message-ID: <4@test>\r\n"));
+}
+
+#[test]
+fn bridges_need_their_destination() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    for (bridge, error) in [
+        ("format = \"rss\"", "output_dir must be set"),
+        ("format = \"imap\"", "imap_url must be set"),
+        (
+            "format = \"imap\"\nimap_url = \"https://mail.example.org/\"",
+            "is not an imap:// or imaps:// URL",
+        ),
+        (
+            "format = \"imap\"\nimap_url = \"imaps://mail.example.org/News\"",
+            "imap_user and imap_password must be set",
+        ),
+    ] {
+        std::fs::write(
+            &path,
+            format!("addr = \":119\"\n[[bridge]]\ngroups = \"*\"\n{bridge}\n"),
+        )
+        .unwrap();
+        let err = Config::from_file(path.to_str().unwrap())
+            .err()
+            .expect("invalid bridge accepted")
+            .to_string();
+        assert!(err.contains("Invalid [[bridge]] entry 1"), "{err}");
+        assert!(err.contains(error), "{err}");
+    }
+}
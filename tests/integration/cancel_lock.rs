@@ -1,4 +1,6 @@
 use base64::{Engine as _, engine::general_purpose::STANDARD};
+use futures_util::TryStreamExt;
+use renews::audit::AuditAction;
 use sha2::{Digest, Sha256};
 
 use crate::utils::{self, ClientMock, store_test_article};
@@ -26,7 +28,7 @@ async fn cancel_key_allows_cancel() {
             utils::request_lines(cancel.trim_end_matches("\r\n")),
             vec!["235 Article transferred OK"],
         )
-        .run(storage.clone(), auth)
+        .run_as_peer(storage.clone(), auth)
         .await;
     assert!(
         storage
@@ -36,3 +38,197 @@ async fn cancel_key_allows_cancel() {
             .is_none()
     );
 }
+
+/// POST an article as `user` over TLS with Cancel-Lock generation enabled.
+async fn post_as(
+    storage: &std::sync::Arc<dyn renews::storage::Storage>,
+    auth: &std::sync::Arc<dyn renews::auth::AuthProvider>,
+    user: &str,
+    article: &str,
+) {
+    let mut cfg = utils::create_minimal_config();
+    cfg.cancel_lock_secret = Some("server secret".to_string());
+    ClientMock::with_auth(user, "pass")
+        .expect(
+            "POST",
+            "340 send article to be posted. End with <CR-LF>.<CR-LF>",
+        )
+        .expect(&format!("{article}\r\n."), "240 article received")
+        .run_with_cfg_tls(cfg, storage.clone(), auth.clone())
+        .await;
+}
+
+/// Wait for the queue workers to settle after a POST.
+async fn processed() {
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+}
+
+#[tokio::test]
+async fn only_the_poster_can_cancel_a_locked_post() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    auth.add_user("alice", "pass").await.unwrap();
+    auth.add_user("bob", "pass").await.unwrap();
+
+    let headers = "From: alice@example.com\r\nSubject: locked\r\nNewsgroups: misc.test\r\n\
+                   Date: Wed, 05 Oct 2022 00:00:00 GMT";
+    post_as(
+        &storage,
+        &auth,
+        "alice",
+        &format!("Message-ID: <locked@test>\r\n{headers}\r\n\r\nBody"),
+    )
+    .await;
+    processed().await;
+    let article = storage
+        .get_article_by_id("<locked@test>")
+        .await
+        .unwrap()
+        .expect("article stored");
+    assert!(
+        renews::handlers::utils::get_header_value(&article, "Cancel-Lock")
+            .is_some_and(|lock| lock.starts_with("sha256:"))
+    );
+
+    let cancel = |id: &str| {
+        format!(
+            "Message-ID: <{id}@test>\r\nControl: cancel <locked@test>\r\n{headers}\r\n\r\ncancel"
+        )
+    };
+    post_as(&storage, &auth, "bob", &cancel("forged")).await;
+    processed().await;
+    assert!(
+        storage
+            .get_article_by_id("<locked@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    post_as(&storage, &auth, "alice", &cancel("own")).await;
+    processed().await;
+    assert!(
+        storage
+            .get_article_by_id("<locked@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn mode_cancel_removes_articles_for_admins() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    auth.add_user("admin", "pass").await.unwrap();
+    auth.add_admin("admin", "key").await.unwrap();
+    auth.add_user("user", "pass").await.unwrap();
+    store_test_article(
+        &*storage,
+        "Message-ID: <spam@test>\r\nNewsgroups: misc.test\r\nCancel-Lock: sha256:abc\r\n\r\nBody",
+    )
+    .await;
+
+    ClientMock::with_auth("user", "pass")
+        .expect("MODE CANCEL", "502 administrator access required")
+        .run_tls(storage.clone(), auth.clone())
+        .await;
+
+    ClientMock::with_auth("admin", "pass")
+        .expect("MODE CANCEL", "284 cancel mode, send Message-IDs to cancel")
+        .expect("<spam@test>", "289 article cancelled")
+        .expect("<spam@test>", "484 no such article")
+        .expect("not-an-id", "484 invalid message-id")
+        .expect("QUIT", "205 closing connection")
+        .run_tls(storage.clone(), auth)
+        .await;
+    assert!(
+        storage
+            .get_article_by_id("<spam@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // The cancel is recorded in the audit log under the administrator
+    let entries: Vec<_> = storage
+        .list_audit_since(chrono::DateTime::UNIX_EPOCH)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, AuditAction::Cancel);
+    assert_eq!(entries[0].actor.as_deref(), Some("admin"));
+    assert_eq!(entries[0].target, "<spam@test>");
+}
+
+#[tokio::test]
+async fn only_the_poster_can_supersede_a_locked_post() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    auth.add_user("alice", "pass").await.unwrap();
+    auth.add_user("bob", "pass").await.unwrap();
+
+    let headers = "From: alice@example.com\r\nSubject: FAQ\r\nNewsgroups: misc.test\r\n\
+                   Date: Wed, 05 Oct 2022 00:00:00 GMT";
+    post_as(
+        &storage,
+        &auth,
+        "alice",
+        &format!("Message-ID: <faq1@test>\r\n{headers}\r\n\r\nFirst edition"),
+    )
+    .await;
+    processed().await;
+
+    let superseding = |id: &str| {
+        format!(
+            "Message-ID: <{id}@test>\r\nSupersedes: <faq1@test>\r\n{headers}\r\n\r\nNew edition"
+        )
+    };
+    // Bob's article is stored alongside, the FAQ is kept
+    post_as(&storage, &auth, "bob", &superseding("forged")).await;
+    processed().await;
+    assert!(
+        storage
+            .get_article_by_id("<faq1@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        storage
+            .get_article_by_id("<forged@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    post_as(&storage, &auth, "alice", &superseding("faq2")).await;
+    processed().await;
+    assert!(
+        storage
+            .get_article_by_id("<faq1@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(storage.in_history("<faq1@test>").await.unwrap());
+    let current = storage
+        .get_article_by_id("<faq2@test>")
+        .await
+        .unwrap()
+        .expect("new edition stored");
+    assert!(current.body.contains("New edition"));
+
+    let entries: Vec<_> = storage
+        .list_audit_since(chrono::DateTime::UNIX_EPOCH)
+        .try_collect()
+        .await
+        .unwrap();
+    let supersede = entries
+        .iter()
+        .find(|entry| entry.action == AuditAction::Supersede)
+        .expect("supersede audited");
+    assert_eq!(supersede.target, "<faq1@test>");
+    assert_eq!(supersede.detail.as_deref(), Some("Cancel-Key"));
+}
@@ -0,0 +1,383 @@
+use crate::utils;
+use renews::ConnectionInfo;
+use renews::access::{AddressAction, AddressRule};
+use renews::auth::AuthProvider;
+use renews::auth::throttle::{self, LoginSubject};
+use renews::config::Config;
+use renews::limits::{ConnectionKey, UsageTracker, UserLimits};
+use renews::server::{ConnectionTracker, handle_connection};
+use renews::storage::Storage;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf};
+use tokio::sync::RwLock;
+use tokio::time::timeout;
+
+struct Listener {
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn AuthProvider>,
+    config: Arc<RwLock<Config>>,
+    usage_tracker: Arc<UsageTracker>,
+    tracker: Arc<ConnectionTracker>,
+}
+
+struct Client {
+    reader: BufReader<ReadHalf<DuplexStream>>,
+    writer: WriteHalf<DuplexStream>,
+}
+
+impl Client {
+    async fn line(&mut self) -> String {
+        let mut line = String::new();
+        timeout(Duration::from_secs(5), self.reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        line
+    }
+
+    async fn send(&mut self, command: &str) -> String {
+        self.writer
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await
+            .unwrap();
+        self.line().await
+    }
+}
+
+impl Listener {
+    async fn new(max_connections_per_ip: u32) -> Self {
+        let (storage, auth) = utils::setup().await;
+        let mut config = utils::create_minimal_config();
+        config.allow_auth_insecure_connections = true;
+        config.max_connections_per_ip = max_connections_per_ip;
+        let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &config);
+        let (tracker, _) = ConnectionTracker::new();
+        Self {
+            storage,
+            auth,
+            config: Arc::new(RwLock::new(config)),
+            usage_tracker,
+            tracker: Arc::new(tracker),
+        }
+    }
+
+    /// Accept a connection from `ip` and return the client end.
+    async fn connect(&self, ip: &str) -> Client {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let info = ConnectionInfo {
+            peer_ip: Some(ip.parse().unwrap()),
+            ..ConnectionInfo::default()
+        };
+        handle_connection(
+            server,
+            self.storage.clone(),
+            self.auth.clone(),
+            self.config.clone(),
+            info,
+            utils::create_test_queue(),
+            self.usage_tracker.clone(),
+            self.tracker.clone(),
+        )
+        .await;
+        let (reader, writer) = tokio::io::split(client);
+        Client {
+            reader: BufReader::new(reader),
+            writer,
+        }
+    }
+
+    /// Wait for the connections counted under `key` to drop to `count`.
+    async fn wait_for_count(&self, key: &ConnectionKey, count: usize) {
+        timeout(Duration::from_secs(5), async {
+            while self.usage_tracker.registry().count(key) != count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn connections_per_address_are_limited() {
+    let listener = Listener::new(1).await;
+    let mut first = listener.connect("192.0.2.1").await;
+    assert!(first.line().await.starts_with("20"));
+
+    let mut refused = listener.connect("192.0.2.1").await;
+    assert_eq!(
+        refused.line().await,
+        "502 too many connections from your address\r\n"
+    );
+    assert_eq!(refused.line().await, "");
+
+    let mut other = listener.connect("192.0.2.2").await;
+    assert!(other.line().await.starts_with("20"));
+
+    assert!(first.send("QUIT").await.starts_with("205"));
+    let ip: IpAddr = "192.0.2.1".parse().unwrap();
+    listener.wait_for_count(&ConnectionKey::Ip(ip), 0).await;
+    let mut again = listener.connect("192.0.2.1").await;
+    assert!(again.line().await.starts_with("20"));
+}
+
+#[tokio::test]
+async fn banned_networks_are_refused_and_allowed_addresses_exempted() {
+    let listener = Listener::new(1).await;
+    for (network, action) in [
+        ("192.0.2.0/24", AddressAction::Ban),
+        ("192.0.2.5", AddressAction::Allow),
+    ] {
+        let rule = AddressRule {
+            network: network.parse().unwrap(),
+            action,
+            reason: None,
+            expires_at: None,
+        };
+        listener.auth.add_address_rule(&rule).await.unwrap();
+    }
+    // A ban that has run out no longer applies
+    let expired = AddressRule {
+        network: "198.51.100.0/24".parse().unwrap(),
+        action: AddressAction::Ban,
+        reason: None,
+        expires_at: Some(chrono::Utc::now().timestamp() - 1),
+    };
+    listener.auth.add_address_rule(&expired).await.unwrap();
+
+    let mut banned = listener.connect("192.0.2.1").await;
+    assert_eq!(
+        banned.line().await,
+        "502 access from your address is denied\r\n"
+    );
+    assert_eq!(banned.line().await, "");
+
+    // The allowed address is neither banned nor held to one connection
+    let mut first = listener.connect("192.0.2.5").await;
+    assert!(first.line().await.starts_with("20"));
+    let mut second = listener.connect("192.0.2.5").await;
+    assert!(second.line().await.starts_with("20"));
+
+    let mut unbanned = listener.connect("198.51.100.1").await;
+    assert!(unbanned.line().await.starts_with("20"));
+}
+
+#[tokio::test]
+async fn repeated_login_failures_ban_the_address() {
+    let listener = Listener::new(0).await;
+    listener.auth.add_user("alice", "secret").await.unwrap();
+    listener.config.write().await.address_bans.auth_failures = 2;
+
+    // A successful login clears the failures counted so far
+    let mut client = listener.connect("192.0.2.1").await;
+    client.line().await;
+    assert!(client.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert!(client.send("AUTHINFO PASS wrong").await.starts_with("481"));
+    assert!(client.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert!(client.send("AUTHINFO PASS secret").await.starts_with("281"));
+
+    let mut client = listener.connect("192.0.2.1").await;
+    client.line().await;
+    assert!(client.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert!(client.send("AUTHINFO PASS wrong").await.starts_with("481"));
+    assert!(client.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert_eq!(
+        client.send("AUTHINFO PASS wrong").await,
+        "502 access from your address is denied\r\n"
+    );
+    assert_eq!(client.line().await, "");
+
+    let rules = listener
+        .auth
+        .list_address_rules(chrono::Utc::now().timestamp())
+        .await
+        .unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].network.to_string(), "192.0.2.1/32");
+    assert_eq!(rules[0].action, AddressAction::Ban);
+    assert!(rules[0].expires_at.is_some());
+
+    let mut refused = listener.connect("192.0.2.1").await;
+    assert_eq!(
+        refused.line().await,
+        "502 access from your address is denied\r\n"
+    );
+    let mut other = listener.connect("192.0.2.2").await;
+    assert!(other.line().await.starts_with("20"));
+}
+
+#[tokio::test]
+async fn failed_logins_are_delayed_then_locked_out() {
+    let listener = Listener::new(0).await;
+    listener.auth.add_user("alice", "secret").await.unwrap();
+    listener.auth.add_user("bob", "secret").await.unwrap();
+    {
+        let mut config = listener.config.write().await;
+        config.login_throttle.failure_delay = Some(1);
+        config.login_throttle.lockout_failures = 2;
+    }
+
+    let mut client = listener.connect("192.0.2.1").await;
+    client.line().await;
+    assert!(client.send("AUTHINFO USER alice").await.starts_with("381"));
+    let started = std::time::Instant::now();
+    assert!(client.send("AUTHINFO PASS wrong").await.starts_with("481"));
+    assert!(started.elapsed() >= Duration::from_secs(1));
+
+    listener.config.write().await.login_throttle.failure_delay = None;
+    assert!(client.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert!(client.send("AUTHINFO PASS wrong").await.starts_with("481"));
+
+    // Both failures are counted once against the address, in the count the
+    // address bans read as well
+    assert_eq!(
+        listener
+            .auth
+            .count_auth_failures("192.0.2.1/32", 0)
+            .await
+            .unwrap(),
+        2
+    );
+
+    // Locked out by user name, even with the right password and from
+    // elsewhere, and by address, whoever logs in from it
+    for (ip, user) in [
+        ("192.0.2.1", "alice"),
+        ("192.0.2.2", "alice"),
+        ("192.0.2.1", "bob"),
+    ] {
+        let mut client = listener.connect(ip).await;
+        client.line().await;
+        assert!(
+            client
+                .send(&format!("AUTHINFO USER {user}"))
+                .await
+                .starts_with("381")
+        );
+        let reply = client.send("AUTHINFO PASS secret").await;
+        assert!(
+            reply.starts_with("481 too many failed logins, retry in "),
+            "{reply}"
+        );
+    }
+
+    throttle::unlock(&*listener.auth, &LoginSubject::User("alice"))
+        .await
+        .unwrap();
+    let mut client = listener.connect("192.0.2.2").await;
+    client.line().await;
+    assert!(client.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert!(client.send("AUTHINFO PASS secret").await.starts_with("281"));
+
+    // Unlocking the address forgets its failures
+    throttle::unlock(
+        &*listener.auth,
+        &LoginSubject::Address("192.0.2.1".parse().unwrap()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        listener
+            .auth
+            .count_auth_failures("192.0.2.1/32", 0)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn connections_per_user_are_limited_across_addresses() {
+    let listener = Listener::new(0).await;
+    listener.auth.add_user("alice", "secret").await.unwrap();
+    let limits = UserLimits {
+        max_connections: Some(1),
+        ..UserLimits::default()
+    };
+    listener
+        .auth
+        .set_user_limits("alice", &limits)
+        .await
+        .unwrap();
+
+    let mut first = listener.connect("192.0.2.1").await;
+    first.line().await;
+    assert!(first.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert!(first.send("AUTHINFO PASS secret").await.starts_with("281"));
+
+    let mut second = listener.connect("192.0.2.2").await;
+    second.line().await;
+    assert!(second.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert_eq!(
+        second.send("AUTHINFO PASS secret").await,
+        "502 connection limit exceeded\r\n"
+    );
+
+    assert!(first.send("QUIT").await.starts_with("205"));
+    let user = ConnectionKey::User("alice".into());
+    listener.wait_for_count(&user, 0).await;
+    let mut third = listener.connect("192.0.2.3").await;
+    third.line().await;
+    assert!(third.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert!(third.send("AUTHINFO PASS secret").await.starts_with("281"));
+    assert_eq!(listener.usage_tracker.connection_count("alice"), 1);
+}
+
+#[tokio::test]
+async fn sessions_are_closed_once_the_bandwidth_limit_is_used() {
+    let listener = Listener::new(0).await;
+    listener.auth.add_user("alice", "secret").await.unwrap();
+    let limits = UserLimits {
+        bandwidth_limit: Some(200),
+        bandwidth_period_secs: None,
+        ..UserLimits::default()
+    };
+    listener
+        .auth
+        .set_user_limits("alice", &limits)
+        .await
+        .unwrap();
+
+    let mut client = listener.connect("192.0.2.1").await;
+    client.line().await;
+    // Traffic before authentication is not charged
+    for _ in 0..10 {
+        assert!(client.send("DATE").await.starts_with("111"));
+    }
+    assert!(client.send("AUTHINFO USER alice").await.starts_with("381"));
+    assert!(client.send("AUTHINFO PASS secret").await.starts_with("281"));
+    assert!(
+        listener
+            .usage_tracker
+            .get_usage("alice")
+            .await
+            .total_bandwidth()
+            < 100
+    );
+
+    // The command crossing the limit is answered, then the session ends
+    client
+        .writer
+        .write_all("DATE\r\n".repeat(20).as_bytes())
+        .await
+        .unwrap();
+    let mut answered = 0;
+    let mut reply = client.line().await;
+    while reply.starts_with("111") {
+        answered += 1;
+        reply = client.line().await;
+    }
+    assert_eq!(reply, "502 bandwidth limit exceeded\r\n");
+    assert_eq!(client.line().await, "");
+    assert!((1..20).contains(&answered));
+    assert!(
+        listener
+            .usage_tracker
+            .get_usage("alice")
+            .await
+            .total_bandwidth()
+            > 200
+    );
+}
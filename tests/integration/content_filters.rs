@@ -0,0 +1,446 @@
+use crate::utils::{self, ClientMock};
+use futures_util::TryStreamExt;
+use renews::config::{FilterConfig, TransitValidation};
+use renews::handlers::utils::get_header_value;
+use serde_json::json;
+use std::time::Duration;
+
+fn filter(name: &str, parameters: serde_json::Value) -> FilterConfig {
+    FilterConfig {
+        name: name.to_string(),
+        parameters: parameters.as_object().cloned().unwrap_or_default(),
+    }
+}
+
+fn post(id: &str, subject: &str, body: &str) -> String {
+    format!(
+        "Message-ID: {id}\r\nNewsgroups: misc\r\nFrom: poster@example.com\r\n\
+         Subject: {subject}\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\r\n{body}\r\n."
+    )
+}
+
+#[tokio::test]
+async fn regex_filters_reject_quarantine_and_tag_articles() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.allow_anonymous_posting = true;
+    cfg.filters = vec![
+        filter("HeaderFilter", json!({})),
+        filter("GroupExistenceFilter", json!({})),
+        filter(
+            "RegexFilter",
+            json!({ "target": "subject", "patterns": ["(?i)make money fast"] }),
+        ),
+        filter(
+            "RegexFilter",
+            json!({
+                "target": "body",
+                "patterns": ["casino"],
+                "action": "quarantine",
+            }),
+        ),
+        filter(
+            "RegexFilter",
+            json!({
+                "target": "header",
+                "header": "From",
+                "patterns": ["@example\\.com$"],
+                "action": "add-header",
+                "add_header": "X-Spam-Flag: YES",
+            }),
+        ),
+    ];
+
+    let send = "340 send article to be posted. End with <CR-LF>.<CR-LF>";
+    ClientMock::new()
+        .expect("POST", send)
+        .expect(
+            &post("<spam@test>", "MAKE MONEY FAST", "hello"),
+            "441 posting failed",
+        )
+        .expect("POST", send)
+        .expect(
+            &post("<casino@test>", "hello", "visit the casino"),
+            "240 article received",
+        )
+        .expect("POST", send)
+        .expect(
+            &post("<tagged@test>", "hello", "hello"),
+            "240 article received",
+        )
+        .run_with_cfg_tls(cfg, storage.clone(), auth)
+        .await;
+
+    let mut tagged = None;
+    for _ in 0..50 {
+        tagged = storage.get_article_by_id("<tagged@test>").await.unwrap();
+        if tagged.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let tagged = tagged.expect("tagged article not stored");
+    assert_eq!(
+        get_header_value(&tagged, "X-Spam-Flag").as_deref(),
+        Some("YES")
+    );
+
+    assert!(
+        storage
+            .get_article_by_id("<spam@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        storage
+            .get_article_by_id("<casino@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let pending: Vec<_> = storage.list_pending_articles().try_collect().await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(
+        get_header_value(&pending[0].message, "Message-ID").as_deref(),
+        Some("<casino@test>")
+    );
+}
+
+#[tokio::test]
+async fn cleanfeed_rejects_floods_and_excessive_crossposts() {
+    let (storage, auth) = utils::setup().await;
+    for group in ["misc", "alt.a", "alt.b", "alt.c"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.allow_anonymous_posting = true;
+    cfg.filters = vec![
+        filter("HeaderFilter", json!({})),
+        filter("GroupExistenceFilter", json!({})),
+        filter(
+            "CleanfeedFilter",
+            json!({ "max_duplicates": 2, "max_crosspost": 3 }),
+        ),
+    ];
+
+    let send = "340 send article to be posted. End with <CR-LF>.<CR-LF>";
+    let flood = "Cheap watches at cleanfeed.example.invalid";
+    ClientMock::new()
+        .expect("POST", send)
+        .expect(&post("<flood1@test>", "one", flood), "240 article received")
+        .expect("POST", send)
+        .expect(
+            &post("<flood2@test>", "two", &flood.to_uppercase()),
+            "240 article received",
+        )
+        .expect("POST", send)
+        .expect(&post("<flood3@test>", "three", flood), "441 posting failed")
+        .expect("POST", send)
+        .expect(
+            &post("<xpost@test>", "xpost", "hello")
+                .replace("Newsgroups: misc", "Newsgroups: misc,alt.a,alt.b,alt.c"),
+            "441 posting failed",
+        )
+        .run_with_cfg_tls(cfg, storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn deferred_validation_leaves_slow_filters_to_the_queue() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.transit_validation = TransitValidation::Deferred;
+    cfg.filters = vec![
+        filter("HeaderFilter", json!({})),
+        filter("GroupExistenceFilter", json!({})),
+        filter(
+            "RegexFilter",
+            json!({ "target": "subject", "patterns": ["(?i)make money fast"] }),
+        ),
+    ];
+
+    let nowhere = post("<nowhere@test>", "hello", "hello").replace("misc", "alt.nowhere");
+    ClientMock::new()
+        .expect("MODE STREAM", "203 Streaming permitted")
+        .expect(
+            &format!("TAKETHIS <nowhere@test>\r\n{nowhere}"),
+            "439 <nowhere@test>",
+        )
+        .expect(
+            &format!(
+                "TAKETHIS <spam@test>\r\n{}",
+                post("<spam@test>", "MAKE MONEY FAST", "hello")
+            ),
+            "239 <spam@test>",
+        )
+        .expect(
+            &format!(
+                "TAKETHIS <ham@test>\r\n{}",
+                post("<ham@test>", "hello", "hello")
+            ),
+            "239 <ham@test>",
+        )
+        .run_as_peer_with_cfg(cfg, storage.clone(), auth)
+        .await;
+
+    let mut ham = None;
+    for _ in 0..50 {
+        ham = storage.get_article_by_id("<ham@test>").await.unwrap();
+        if ham.is_some()
+            && renews::history::seen(&*storage, "<spam@test>")
+                .await
+                .unwrap()
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(ham.is_some(), "accepted article not stored");
+
+    // The queue workers refused the spam and remember it
+    assert!(
+        storage
+            .get_article_by_id("<spam@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        renews::history::seen(&*storage, "<spam@test>")
+            .await
+            .unwrap()
+    );
+    assert!(
+        renews::history::seen(&*storage, "<nowhere@test>")
+            .await
+            .unwrap()
+    );
+}
+
+/// Start a filter daemon speaking the external filter protocol that rejects
+/// articles whose subject contains "spam", defers those containing "later"
+/// and tags the rest. Returns its address and a count of connections made.
+async fn start_filter_daemon() -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = std::sync::Arc::new(AtomicUsize::new(0));
+    let count = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            count.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                loop {
+                    let mut len = [0u8; 4];
+                    if stream.read_exact(&mut len).await.is_err() {
+                        return;
+                    }
+                    let mut request = vec![0u8; u32::from_be_bytes(len) as usize];
+                    stream.read_exact(&mut request).await.unwrap();
+                    let request: serde_json::Value = serde_json::from_slice(&request).unwrap();
+                    let subject = request["headers"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .find(|h| h[0] == "Subject")
+                        .and_then(|h| h[1].as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    let answer = if subject.contains("spam") {
+                        json!({ "action": "reject", "reason": "spam" })
+                    } else if subject.contains("later") {
+                        json!({ "action": "tempfail" })
+                    } else {
+                        json!({
+                            "action": "accept",
+                            "add_headers": [["X-Filtered-By", "daemon"]],
+                        })
+                    };
+                    let answer = serde_json::to_vec(&answer).unwrap();
+                    stream
+                        .write_all(&(answer.len() as u32).to_be_bytes())
+                        .await
+                        .unwrap();
+                    stream.write_all(&answer).await.unwrap();
+                }
+            });
+        }
+    });
+    (format!("tcp://{addr}"), connections)
+}
+
+#[tokio::test]
+async fn external_filter_accepts_rejects_and_defers_articles() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let (address, connections) = start_filter_daemon().await;
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.allow_anonymous_posting = true;
+    cfg.filters = vec![
+        filter("HeaderFilter", json!({})),
+        filter("GroupExistenceFilter", json!({})),
+        filter("ExternalFilter", json!({ "address": address })),
+    ];
+
+    let send = "340 send article to be posted. End with <CR-LF>.<CR-LF>";
+    ClientMock::new()
+        .expect("POST", send)
+        .expect(&post("<spam@test>", "spam", "hello"), "441 posting failed")
+        .expect("POST", send)
+        .expect(
+            &post("<ham@test>", "hello", "hello"),
+            "240 article received",
+        )
+        .run_with_cfg_tls(cfg.clone(), storage.clone(), auth.clone())
+        .await;
+    ClientMock::new()
+        .expect("MODE STREAM", "203 Streaming permitted")
+        .expect(
+            &format!(
+                "TAKETHIS <later@test>\r\n{}",
+                post("<later@test>", "later", "hello")
+            ),
+            "431 <later@test>",
+        )
+        .expect(
+            &format!(
+                "TAKETHIS <feed@test>\r\n{}",
+                post("<feed@test>", "hello", "hello")
+            ),
+            "239 <feed@test>",
+        )
+        .run_as_peer_with_cfg(cfg, storage.clone(), auth)
+        .await;
+
+    let mut ham = None;
+    for _ in 0..50 {
+        ham = storage.get_article_by_id("<ham@test>").await.unwrap();
+        if ham.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let ham = ham.expect("accepted article not stored");
+    assert_eq!(
+        get_header_value(&ham, "X-Filtered-By").as_deref(),
+        Some("daemon")
+    );
+    assert!(
+        !renews::history::seen(&*storage, "<later@test>")
+            .await
+            .unwrap()
+    );
+    // Every article went over the one pooled connection
+    assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn external_filter_failure_policy() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("tcp://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.filters = vec![filter(
+        "ExternalFilter",
+        json!({ "address": address, "timeout_ms": 500 }),
+    )];
+    ClientMock::new()
+        .expect("IHAVE <down@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect(
+            &post("<down@test>", "hello", "hello"),
+            "436 transfer not possible; try again later",
+        )
+        .run_as_peer_with_cfg(cfg.clone(), storage.clone(), auth.clone())
+        .await;
+
+    cfg.filters = vec![filter(
+        "ExternalFilter",
+        json!({ "address": address, "timeout_ms": 500, "on_failure": "accept" }),
+    )];
+    ClientMock::new()
+        .expect("IHAVE <down@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect(
+            &post("<down@test>", "hello", "hello"),
+            "235 Article transferred OK",
+        )
+        .run_as_peer_with_cfg(cfg, storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn rejected_articles_are_kept_for_reinjection() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.rejected_retention_days = 7;
+    cfg.filters = vec![
+        filter("HeaderFilter", json!({})),
+        filter("GroupExistenceFilter", json!({})),
+        filter(
+            "RegexFilter",
+            json!({ "target": "subject", "patterns": ["(?i)make money fast"] }),
+        ),
+    ];
+
+    let spam = post("<spam@test>", "Make money fast", "hello")
+        .replace("\r\n\r\n", "\r\nPath: peer!not-for-mail\r\n\r\n");
+    ClientMock::new()
+        .expect("IHAVE <spam@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect(&spam, "437 article rejected")
+        .run_as_peer_with_cfg(cfg, storage.clone(), auth)
+        .await;
+
+    let kept: Vec<_> = storage
+        .list_rejected_articles()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(kept.len(), 1);
+    assert_eq!(
+        get_header_value(&kept[0].message, "Subject").as_deref(),
+        Some("Make money fast")
+    );
+    assert_eq!(kept[0].reason, "article matches a blacklisted pattern");
+
+    let article = renews::rejected::reinject(&*storage, kept[0].id)
+        .await
+        .unwrap()
+        .expect("kept article");
+    assert_eq!(
+        get_header_value(&article, "Message-ID").as_deref(),
+        Some("<spam@test>")
+    );
+    assert!(
+        storage
+            .get_article_by_id("<spam@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        storage
+            .get_rejected_article(kept[0].id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        renews::rejected::reinject(&*storage, kept[0].id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
@@ -1,3 +1,4 @@
+use futures_util::TryStreamExt;
 use renews::control::canonical_text;
 use renews::parse_message;
 
@@ -43,7 +44,7 @@ async fn control_newgroup_and_rmgroup() {
             utils::request_lines(article.trim_end_matches("\r\n")),
             vec!["235 Article transferred OK"],
         )
-        .run(storage.clone(), auth.clone())
+        .run_as_peer(storage.clone(), auth.clone())
         .await;
     let groups = collect_groups(&*storage).await;
     assert!(groups.contains(&"test.group".to_string()));
@@ -58,7 +59,7 @@ async fn control_newgroup_and_rmgroup() {
             utils::request_lines(article.trim_end_matches("\r\n")),
             vec!["235 Article transferred OK"],
         )
-        .run(storage.clone(), auth.clone())
+        .run_as_peer(storage.clone(), auth.clone())
         .await;
     let groups = collect_groups(&*storage).await;
     assert!(!groups.contains(&"test.group".to_string()));
@@ -84,7 +85,7 @@ async fn control_cancel_removes_article() {
             utils::request_lines(article.trim_end_matches("\r\n")),
             vec!["235 Article transferred OK"],
         )
-        .run(storage.clone(), auth)
+        .run_as_peer(storage.clone(), auth)
         .await;
     assert!(
         storage
@@ -125,7 +126,7 @@ async fn admin_cancel_ignores_lock() {
             utils::request_lines(article.trim_end_matches("\r\n")),
             vec!["235 Article transferred OK"],
         )
-        .run(storage.clone(), auth)
+        .run_as_peer(storage.clone(), auth)
         .await;
     assert!(
         storage
@@ -135,3 +136,306 @@ async fn admin_cancel_ignores_lock() {
             .is_none()
     );
 }
+
+#[tokio::test]
+async fn control_newgroup_takes_configured_moderation() {
+    let (storage, auth) = utils::setup().await;
+    auth.add_user("admin@example.org", "x").await.unwrap();
+    auth.add_admin("admin@example.org", ADMIN_PUB)
+        .await
+        .unwrap();
+    let cfg: renews::config::Config = toml::from_str(
+        r#"
+addr = ":119"
+[[group_settings]]
+pattern = "test.*"
+moderated = true
+"#,
+    )
+    .unwrap();
+
+    let article = build_control_article("newgroup test.group", "test group body\n");
+    ClientMock::new()
+        .expect("IHAVE <ctrl@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect_request_multi(
+            utils::request_lines(article.trim_end_matches("\r\n")),
+            vec!["235 Article transferred OK"],
+        )
+        .run_as_peer_with_cfg(cfg, storage.clone(), auth)
+        .await;
+    assert!(storage.is_group_moderated("test.group").await.unwrap());
+}
+
+fn admin_key_fingerprint() -> String {
+    use pgp::native::types::KeyTrait;
+    use pgp::native::{Deserializable, SignedPublicKey};
+
+    let (key, _) = SignedPublicKey::from_string(ADMIN_PUB).unwrap();
+    key.fingerprint()
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect()
+}
+
+async fn send_control(
+    article: &str,
+    cfg: renews::config::Config,
+    storage: std::sync::Arc<dyn renews::storage::Storage>,
+    auth: std::sync::Arc<dyn renews::auth::AuthProvider>,
+) {
+    ClientMock::new()
+        .expect("IHAVE <ctrl@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect_request_multi(
+            utils::request_lines(article.trim_end_matches("\r\n")),
+            vec!["235 Article transferred OK"],
+        )
+        .run_as_peer_with_cfg(cfg, storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn control_policy_admits_listed_keys_for_their_hierarchy() {
+    let (storage, auth) = utils::setup().await;
+    // The sender is not an administrator; the policy trusts its key
+    auth.add_user_with_key("admin@example.org", "x", Some(ADMIN_PUB))
+        .await
+        .unwrap();
+    let fingerprint = admin_key_fingerprint();
+    let policy = format!(
+        r#"
+addr = ":119"
+[[control_policy]]
+groups = ["test.*"]
+key_ids = ["0x{}"]
+[[control_policy]]
+groups = ["test.drop.*"]
+action = "drop"
+"#,
+        &fingerprint[fingerprint.len() - 16..]
+    );
+
+    let article = build_control_article("newgroup test.group", "test group body\n");
+    send_control(
+        &article,
+        toml::from_str(&policy).unwrap(),
+        storage.clone(),
+        auth.clone(),
+    )
+    .await;
+    assert!(
+        collect_groups(&*storage)
+            .await
+            .contains(&"test.group".to_string())
+    );
+
+    let article = build_control_article("newgroup test.drop.group", "dropped\n");
+    send_control(
+        &article,
+        toml::from_str(&policy).unwrap(),
+        storage.clone(),
+        auth.clone(),
+    )
+    .await;
+    let article = build_control_article("newgroup other.group", "unmatched\n");
+    send_control(
+        &article,
+        toml::from_str(&policy).unwrap(),
+        storage.clone(),
+        auth.clone(),
+    )
+    .await;
+    let groups = collect_groups(&*storage).await;
+    assert!(!groups.contains(&"test.drop.group".to_string()));
+    assert!(!groups.contains(&"other.group".to_string()));
+}
+
+#[tokio::test]
+async fn control_policy_refuses_other_keys() {
+    let (storage, auth) = utils::setup().await;
+    auth.add_user("admin@example.org", "x").await.unwrap();
+    auth.add_admin("admin@example.org", ADMIN_PUB)
+        .await
+        .unwrap();
+    let cfg: renews::config::Config = toml::from_str(
+        r#"
+addr = ":119"
+[[control_policy]]
+commands = ["newgroup"]
+groups = ["test.*"]
+key_ids = ["DEADBEEFDEADBEEF"]
+"#,
+    )
+    .unwrap();
+
+    // Even an administrator needs a listed key once a rule matches
+    let article = build_control_article("newgroup test.group", "test group body\n");
+    send_control(&article, cfg, storage.clone(), auth).await;
+    assert!(
+        !collect_groups(&*storage)
+            .await
+            .contains(&"test.group".to_string())
+    );
+}
+
+#[tokio::test]
+async fn control_policy_checks_key_files() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("test.group", false).await.unwrap();
+    storage.add_group("other.group", false).await.unwrap();
+    let cfg: renews::config::Config = toml::from_str(&format!(
+        r#"
+addr = ":119"
+[[control_policy]]
+commands = ["checkgroups"]
+groups = ["test.*"]
+key_ids = ["{}"]
+key_file = "{}/tests/data/admin.pub.asc"
+"#,
+        admin_key_fingerprint(),
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap();
+
+    let article = build_control_article(
+        "checkgroups",
+        "test.group\tDescribed by policy.\nother.group\tNot covered.\n",
+    );
+    send_control(&article, cfg, storage.clone(), auth).await;
+    let mut groups: Vec<(String, String)> = storage
+        .list_groups_with_descriptions()
+        .try_collect()
+        .await
+        .unwrap();
+    groups.sort();
+    assert_eq!(
+        groups,
+        vec![
+            ("other.group".to_string(), String::new()),
+            ("test.group".to_string(), "Described by policy.".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn checkgroups_changes_are_held_for_review() {
+    let (storage, auth) = utils::setup().await;
+    auth.add_user("admin@example.org", "x").await.unwrap();
+    auth.add_admin("admin@example.org", ADMIN_PUB)
+        .await
+        .unwrap();
+    storage.add_group("test.group", true).await.unwrap();
+    storage.add_group("test.obsolete", false).await.unwrap();
+    storage.add_group("other.group", false).await.unwrap();
+
+    let article = build_control_article(
+        "checkgroups test #7",
+        "test.group\tStill here.\ntest.new\tJust created. (Moderated)\n",
+    );
+    send_control(
+        &article,
+        toml::from_str("addr = \":119\"").unwrap(),
+        storage.clone(),
+        auth.clone(),
+    )
+    .await;
+
+    // Only the description changes until the diff is applied
+    let groups = collect_groups(&*storage).await;
+    assert!(groups.contains(&"test.obsolete".to_string()));
+    assert!(!groups.contains(&"test.new".to_string()));
+    let pending = storage.list_pending_checkgroups().await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].signer, "admin@example.org");
+    assert_eq!(
+        pending[0].diff,
+        "newgroup test.new moderated\tJust created.\n\
+         rmgroup test.obsolete\n\
+         unmoderate test.group\n"
+    );
+
+    let diff = renews::checkgroups::apply_pending(&*storage, pending[0].id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(diff.add.len(), 1);
+    let mut groups: Vec<(String, String)> = storage
+        .list_groups_with_descriptions()
+        .try_collect()
+        .await
+        .unwrap();
+    groups.sort();
+    assert_eq!(
+        groups,
+        vec![
+            ("other.group".to_string(), String::new()),
+            ("test.group".to_string(), "Still here.".to_string()),
+            ("test.new".to_string(), "Just created.".to_string()),
+        ]
+    );
+    assert!(storage.is_group_moderated("test.new").await.unwrap());
+    assert!(!storage.is_group_moderated("test.group").await.unwrap());
+    assert!(storage.list_pending_checkgroups().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn checkgroups_applies_at_once_within_the_policy() {
+    let (storage, auth) = utils::setup().await;
+    auth.add_user_with_key("admin@example.org", "x", Some(ADMIN_PUB))
+        .await
+        .unwrap();
+    storage.add_group("test.obsolete", false).await.unwrap();
+    storage
+        .add_group("test.kept.obsolete", false)
+        .await
+        .unwrap();
+    let cfg: renews::config::Config = toml::from_str(&format!(
+        r#"
+addr = ":119"
+checkgroups_mode = "apply"
+[[control_policy]]
+groups = ["test.*", "!test.kept.*"]
+key_ids = ["{}"]
+"#,
+        admin_key_fingerprint()
+    ))
+    .unwrap();
+
+    let article = build_control_article(
+        "checkgroups",
+        "test.group\tA test group.\ntest.kept.new\tOutside the policy.\n",
+    );
+    send_control(&article, cfg, storage.clone(), auth).await;
+
+    let mut groups = collect_groups(&*storage).await;
+    groups.sort();
+    assert_eq!(groups, vec!["test.group", "test.kept.obsolete"]);
+    assert!(storage.list_pending_checkgroups().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn control_policy_archives_instead_of_removing() {
+    let (storage, auth) = utils::setup().await;
+    auth.add_user("admin@example.org", "x").await.unwrap();
+    auth.add_admin("admin@example.org", ADMIN_PUB)
+        .await
+        .unwrap();
+    storage.add_group("test.group", false).await.unwrap();
+    let cfg: renews::config::Config = toml::from_str(
+        r#"
+addr = ":119"
+[[control_policy]]
+groups = ["test.*"]
+action = "archive"
+"#,
+    )
+    .unwrap();
+
+    let article = build_control_article("rmgroup test.group", "rm body\n");
+    send_control(&article, cfg, storage.clone(), auth).await;
+    assert!(
+        collect_groups(&*storage)
+            .await
+            .contains(&"test.group".to_string())
+    );
+    assert!(storage.is_group_archived("test.group").await.unwrap());
+}
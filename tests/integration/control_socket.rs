@@ -0,0 +1,207 @@
+use renews::ConnectionInfo;
+use renews::control_socket::{self, ControlSocket, ReloadRequest};
+use renews::queue::ArticleQueue;
+use renews::server::ConnectionTracker;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Serve a control socket in a new directory, returning the directory, the
+/// socket path, the tracker it manages and the reload requests it sends.
+async fn start_control() -> (
+    tempfile::TempDir,
+    String,
+    Arc<ConnectionTracker>,
+    mpsc::Receiver<ReloadRequest>,
+) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("control.sock").display().to_string();
+    let tracker = Arc::new(ConnectionTracker::default());
+    let (reload_tx, reload_rx) = mpsc::channel(1);
+    let control = ControlSocket::new(tracker.clone(), ArticleQueue::new(4), reload_tx);
+    control_socket::serve(&path, Arc::new(control)).unwrap();
+    (dir, path, tracker, reload_rx)
+}
+
+#[tokio::test]
+async fn status_and_drain() {
+    let (_dir, path, tracker, _reload) = start_control().await;
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let status = control_socket::send_command(&path, "status").await.unwrap();
+    assert!(status.contains(&"connections 0".to_string()), "{status:?}");
+    assert!(status.contains(&"draining no".to_string()), "{status:?}");
+
+    control_socket::send_command(&path, "drain").await.unwrap();
+    assert!(tracker.is_draining());
+    let status = control_socket::send_command(&path, "status").await.unwrap();
+    assert!(status.contains(&"draining yes".to_string()), "{status:?}");
+    control_socket::send_command(&path, "resume").await.unwrap();
+    assert!(!tracker.is_draining());
+
+    let err = control_socket::send_command(&path, "frobnicate")
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "unknown command 'frobnicate'");
+}
+
+#[tokio::test]
+async fn list_and_close_connections() {
+    let (_dir, path, tracker, _reload) = start_control().await;
+    let info = ConnectionInfo {
+        is_tls: true,
+        peer_ip: Some("192.0.2.7".parse().unwrap()),
+        ..ConnectionInfo::default()
+    };
+    let guard = tracker.connection_started(&info);
+    let id = guard.id();
+    let task = tokio::spawn(async move {
+        let _guard = guard;
+        std::future::pending::<()>().await;
+    });
+    tracker.set_abort_handle(id, task.abort_handle());
+
+    let list = control_socket::send_command(&path, "list-connections")
+        .await
+        .unwrap();
+    assert_eq!(list.len(), 1);
+    assert!(list[0].starts_with(&format!("{id}\t192.0.2.7\ttls\t")));
+
+    control_socket::send_command(&path, &format!("close-connection {id}"))
+        .await
+        .unwrap();
+    assert!(task.await.unwrap_err().is_cancelled());
+    assert_eq!(tracker.active_connections(), 0);
+
+    let err = control_socket::send_command(&path, &format!("close-connection {id}"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "no such connection");
+}
+
+#[tokio::test]
+async fn reload_reports_outcome() {
+    let (_dir, path, _tracker, mut reload) = start_control().await;
+    tokio::spawn(async move {
+        reload.recv().await.unwrap().send(Ok(())).unwrap();
+        reload
+            .recv()
+            .await
+            .unwrap()
+            .send(Err(anyhow::anyhow!("Failed to parse\nconfiguration")))
+            .unwrap();
+    });
+
+    assert!(
+        control_socket::send_command(&path, "reload")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let err = control_socket::send_command(&path, "reload")
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Failed to parse configuration");
+}
+
+#[tokio::test]
+async fn stale_socket_is_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("control.sock");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let control = ControlSocket::new(
+        Arc::new(ConnectionTracker::default()),
+        ArticleQueue::new(4),
+        mpsc::channel(1).0,
+    );
+    let path = path.display().to_string();
+    control_socket::serve(&path, Arc::new(control)).unwrap();
+    assert_eq!(
+        control_socket::send_command(&path, "help").await.unwrap()[0],
+        "reload"
+    );
+}
+
+#[tokio::test]
+async fn traced_sessions_are_written_without_passwords() {
+    use renews::protocol_trace::ProtocolTracer;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let trace_file = dir.path().join("trace.log");
+    let trace_cfg = renews::config::TraceConfig {
+        file: Some(trace_file.display().to_string()),
+        ..Default::default()
+    };
+    let tracer = ProtocolTracer::start(&trace_cfg).await.unwrap().unwrap();
+    let path = dir.path().join("control.sock").display().to_string();
+    let control = ControlSocket::new(
+        Arc::new(ConnectionTracker::default()),
+        ArticleQueue::new(4),
+        mpsc::channel(1).0,
+    )
+    .with_tracer(tracer.clone());
+    control_socket::serve(&path, Arc::new(control)).unwrap();
+
+    control_socket::send_command(&path, "trace 192.0.2.7")
+        .await
+        .unwrap();
+    assert_eq!(
+        control_socket::send_command(&path, "list-traces")
+            .await
+            .unwrap(),
+        vec!["address 192.0.2.7"]
+    );
+
+    let (storage, auth) = crate::utils::setup().await;
+    auth.add_user("bob", "hunter2").await.unwrap();
+    let cfg = Arc::new(tokio::sync::RwLock::new(
+        toml::from_str("addr = \":119\"\nallow_auth_insecure_connections = true").unwrap(),
+    ));
+    let usage_tracker = Arc::new(renews::limits::UsageTracker::new(
+        auth.clone(),
+        Default::default(),
+    ));
+    let info = ConnectionInfo {
+        peer_ip: Some("192.0.2.7".parse().unwrap()),
+        trace: Some(tracer),
+        ..ConnectionInfo::default()
+    };
+    let (client, server) = tokio::io::duplex(4096);
+    let session = tokio::spawn(renews::handle_client_with_info(
+        server,
+        storage,
+        auth,
+        cfg,
+        info,
+        ArticleQueue::new(4),
+        usage_tracker,
+    ));
+    let (reader, mut writer) = tokio::io::split(client);
+    let mut lines = BufReader::new(reader).lines();
+    lines.next_line().await.unwrap();
+    for command in ["AUTHINFO USER bob", "AUTHINFO PASS hunter2", "QUIT"] {
+        writer
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await
+            .unwrap();
+        lines.next_line().await.unwrap();
+    }
+    session.await.unwrap().unwrap();
+
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let trace = std::fs::read_to_string(&trace_file).unwrap();
+    assert!(trace.contains(" C AUTHINFO USER bob\n"), "{trace}");
+    assert!(trace.contains(" C AUTHINFO PASS [redacted]\n"), "{trace}");
+    assert!(trace.contains(" S 205 "), "{trace}");
+    assert!(!trace.contains("hunter2"), "{trace}");
+
+    control_socket::send_command(&path, "untrace 192.0.2.7")
+        .await
+        .unwrap();
+    let err = control_socket::send_command(&path, "untrace 192.0.2.7")
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "address 192.0.2.7 is not traced");
+}
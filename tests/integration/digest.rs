@@ -0,0 +1,109 @@
+use crate::utils::{get_header, store_test_article};
+use renews::config::Config;
+use renews::digest::run_digests;
+use renews::storage::{Storage, sqlite::SqliteStorage};
+use std::sync::Arc;
+
+fn digest_config(extra: &str) -> Config {
+    toml::from_str(&format!(
+        "addr = \":119\"\nsite_name = \"test\"\n[[digests]]\ngroup = \"misc\"\n{extra}"
+    ))
+    .unwrap()
+}
+
+async fn storage_with_articles() -> Arc<dyn Storage> {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("misc", false).await.unwrap();
+    storage.add_group("misc.digest", false).await.unwrap();
+    for n in 1..=2 {
+        store_test_article(
+            &*storage,
+            &format!(
+                "Message-ID: <{n}@test>\r\nNewsgroups: misc\r\nFrom: poster{n}@example.com\r\nSubject: Topic {n}\r\n\r\nBody"
+            ),
+        )
+        .await;
+    }
+    storage
+}
+
+#[tokio::test]
+async fn digest_is_posted_to_companion_group() {
+    let storage = storage_with_articles().await;
+    let cfg = digest_config("post_to = \"misc.digest\"");
+    let now = chrono::Utc::now();
+    run_digests(&*storage, &cfg, now).await.unwrap();
+
+    let day = (now - chrono::Duration::days(1)).format("%Y%m%d");
+    let digest = storage
+        .get_article_by_id(&format!("<digest.misc.{day}@test>"))
+        .await
+        .unwrap()
+        .expect("digest stored");
+    assert_eq!(
+        get_header(&digest, "Newsgroups").as_deref(),
+        Some("misc.digest")
+    );
+    assert!(digest.body.starts_with("2 new articles in misc"));
+    for n in 1..=2 {
+        assert!(digest.body.contains(&format!("Subject: Topic {n}\r\n")));
+        assert!(
+            digest
+                .body
+                .contains(&format!("From: poster{n}@example.com\r\n"))
+        );
+        assert!(digest.body.contains(&format!("Message-ID: <{n}@test>\r\n")));
+    }
+
+    // Running again the same day does not post a second digest
+    run_digests(&*storage, &cfg, now).await.unwrap();
+    let mut numbers = storage.list_article_numbers("misc.digest");
+    let mut count = 0;
+    while let Some(n) = futures_util::StreamExt::next(&mut numbers).await {
+        n.unwrap();
+        count += 1;
+    }
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn digest_is_emailed_with_sendmail() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("mail.txt");
+    let sendmail = dir.path().join("sendmail");
+    std::fs::write(
+        &sendmail,
+        format!("#!/bin/sh\necho \"$@\" > {0}\ncat >> {0}\n", out.display()),
+    )
+    .unwrap();
+    std::fs::set_permissions(&sendmail, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let storage = storage_with_articles().await;
+    let mut cfg = digest_config("email_to = [\"list@example.com\", \"other@example.com\"]");
+    cfg.sendmail_path = sendmail.display().to_string();
+    run_digests(&*storage, &cfg, chrono::Utc::now())
+        .await
+        .unwrap();
+
+    let mail = std::fs::read_to_string(&out).unwrap();
+    assert!(mail.starts_with("-t -i\nTo: list@example.com, other@example.com\n"));
+    assert!(mail.contains("\nSubject: Daily digest for misc, "));
+    assert!(mail.contains("\nMessage-ID: <2@test>\n"));
+    assert!(!mail.contains('\r'));
+}
+
+#[tokio::test]
+async fn no_digest_without_new_articles() {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("misc", false).await.unwrap();
+    storage.add_group("misc.digest", false).await.unwrap();
+    let cfg = digest_config("post_to = \"misc.digest\"");
+    run_digests(&*storage, &cfg, chrono::Utc::now())
+        .await
+        .unwrap();
+
+    let mut numbers = storage.list_article_numbers("misc.digest");
+    assert!(futures_util::StreamExt::next(&mut numbers).await.is_none());
+}
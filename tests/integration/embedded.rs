@@ -0,0 +1,64 @@
+use renews::embedded::Server;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::utils;
+
+const ARTICLE: &str = "Message-ID: <embedded@test>\r\nNewsgroups: misc.test\r\n\
+     From: user@example.com\r\nSubject: t\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\
+     Path: peer!not-for-mail\r\n\r\nBody\r\n.\r\n";
+
+#[tokio::test]
+async fn embedded_server_serves_and_stores_articles() {
+    let server = Server::builder()
+        .config(
+            toml::from_str("addr = \"127.0.0.1:0\"\nallow_auth_insecure_connections = true")
+                .unwrap(),
+        )
+        .group("misc.test", false)
+        .start()
+        .await
+        .unwrap();
+    server.auth().add_user("feeder", "pass").await.unwrap();
+    let storage = server.storage().clone();
+
+    let (mut reader, mut writer) = utils::connect(server.local_addr()).await;
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    assert!(line.starts_with("201") || line.starts_with("200"), "{line}");
+
+    for (command, expected) in [
+        ("GROUP misc.test\r\n", "211 0 0 0 misc.test"),
+        ("AUTHINFO USER feeder\r\n", "381"),
+        ("AUTHINFO PASS pass\r\n", "281"),
+        ("IHAVE <embedded@test>\r\n", "335"),
+        (ARTICLE, "235"),
+    ] {
+        writer.write_all(command.as_bytes()).await.unwrap();
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        assert!(line.starts_with(expected), "{command}: {line}");
+    }
+
+    // Shutting down waits for the queued article to be stored
+    server.shutdown().await;
+    assert!(
+        storage
+            .get_article_by_id("<embedded@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert_eq!(
+        utils::collect_article_numbers(&*storage, "misc.test").await,
+        vec![1]
+    );
+}
+
+#[tokio::test]
+async fn embedded_server_stops_listening_on_shutdown() {
+    let server = Server::builder().start().await.unwrap();
+    let addr = server.local_addr();
+    assert!(tokio::net::TcpStream::connect(addr).await.is_ok());
+    server.shutdown().await;
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+}
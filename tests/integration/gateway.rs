@@ -0,0 +1,269 @@
+use futures_util::TryStreamExt;
+use renews::auth::{DynAuth, sqlite::SqliteAuth};
+use renews::config::Config;
+use renews::gateway::{MailServer, Received, receive};
+use renews::storage::{DynStorage, sqlite::SqliteStorage};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+
+const MAIL: &str = "Received: from mx.example.com\nFrom: alice@example.com\nTo: list@lists.example.com\n\
+                    Subject: Hello list\nMessage-ID: <mail1@example.com>\n\
+                    In-Reply-To: <parent@example.com>\n\nFirst line\n";
+
+fn gateway_config(extra: &str) -> Config {
+    toml::from_str(&format!(
+        "addr = \":119\"\nsite_name = \"news.example.org\"\n\
+         [[gateway]]\ngroup = \"misc.list\"\naddress = \"misc-list@news.example.org\"\n{extra}"
+    ))
+    .unwrap()
+}
+
+async fn setup() -> (DynStorage, DynAuth) {
+    let storage: DynStorage = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("misc.list", false).await.unwrap();
+    let auth: DynAuth = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    (storage, auth)
+}
+
+#[tokio::test]
+async fn mail_is_posted_to_the_group() {
+    let (storage, auth) = setup().await;
+    let cfg = gateway_config("");
+    let received = receive(&storage, &auth, &cfg, "Misc-List@news.example.org", MAIL)
+        .await
+        .unwrap();
+    assert_eq!(received, Received::Posted("<mail1@example.com>".into()));
+
+    let article = storage
+        .get_article_by_id("<mail1@example.com>")
+        .await
+        .unwrap()
+        .unwrap();
+    let header = |name: &str| renews::handlers::utils::get_header_value(&article, name);
+    assert_eq!(header("Newsgroups").as_deref(), Some("misc.list"));
+    assert_eq!(
+        header("References").as_deref(),
+        Some("<parent@example.com>")
+    );
+    assert_eq!(
+        header("X-Gateway").as_deref(),
+        Some("news.example.org mail-to-news misc-list@news.example.org")
+    );
+    assert!(header("Received").is_none());
+    assert!(header("To").is_none());
+    assert_eq!(article.body, "First line\r\n");
+
+    // The list sends it again
+    let again = receive(&storage, &auth, &cfg, "misc-list@news.example.org", MAIL)
+        .await
+        .unwrap();
+    assert_eq!(again, Received::Duplicate("<mail1@example.com>".into()));
+}
+
+#[tokio::test]
+async fn mail_to_a_moderated_group_is_held() {
+    let storage: DynStorage = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("misc.list", true).await.unwrap();
+    let auth: DynAuth = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    let cfg = gateway_config("");
+    let received = receive(&storage, &auth, &cfg, "misc-list@news.example.org", MAIL)
+        .await
+        .unwrap();
+    assert_eq!(received, Received::Held("<mail1@example.com>".into()));
+
+    let pending: Vec<_> = storage.list_pending_articles().try_collect().await.unwrap();
+    assert_eq!(pending.len(), 1);
+    let held = &pending[0].message;
+    let header = |name: &str| renews::handlers::utils::get_header_value(held, name);
+    assert_eq!(
+        header("Path").as_deref(),
+        Some("news.example.org!not-for-mail")
+    );
+    assert!(header("Received").is_none());
+    assert!(
+        storage
+            .get_article_by_id("<mail1@example.com>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn mail_sent_by_this_gateway_is_dropped() {
+    let (storage, auth) = setup().await;
+    let cfg = gateway_config("");
+    let mail = format!("X-Gateway: news.example.org news-to-mail misc.list\n{MAIL}");
+    let received = receive(&storage, &auth, &cfg, "misc-list@news.example.org", &mail)
+        .await
+        .unwrap();
+    assert_eq!(received, Received::Looped);
+    assert!(
+        storage
+            .get_article_by_id("<mail1@example.com>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    assert!(
+        receive(&storage, &auth, &cfg, "nobody@news.example.org", MAIL)
+            .await
+            .is_err()
+    );
+}
+
+async fn expect(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>, code: &str) -> String {
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        assert!(line.starts_with(code), "expected {code}, got {line:?}");
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return line;
+        }
+    }
+}
+
+#[tokio::test]
+async fn smtp_and_lmtp_sessions_deliver_mail() {
+    let (storage, auth) = setup().await;
+    let cfg = Arc::new(RwLock::new(gateway_config("")));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Arc::new(MailServer::new(storage.clone(), auth, cfg));
+    tokio::spawn(server.serve(listener));
+
+    let (read, mut write) = TcpStream::connect(addr).await.unwrap().into_split();
+    let mut reader = BufReader::new(read);
+    expect(&mut reader, "220").await;
+    write.write_all(b"EHLO mx.example.com\r\n").await.unwrap();
+    expect(&mut reader, "250").await;
+    write
+        .write_all(b"MAIL FROM:<alice@example.com>\r\n")
+        .await
+        .unwrap();
+    expect(&mut reader, "250").await;
+    write
+        .write_all(b"RCPT TO:<other@news.example.org>\r\n")
+        .await
+        .unwrap();
+    expect(&mut reader, "550").await;
+    write
+        .write_all(b"RCPT TO:<misc-list@news.example.org>\r\n")
+        .await
+        .unwrap();
+    expect(&mut reader, "250").await;
+    write.write_all(b"DATA\r\n").await.unwrap();
+    expect(&mut reader, "354").await;
+    write
+        .write_all(
+            b"From: alice@example.com\r\nSubject: By SMTP\r\nMessage-ID: <smtp@example.com>\r\n\r\n\
+              ..dotted\r\n.\r\n",
+        )
+        .await
+        .unwrap();
+    expect(&mut reader, "250").await;
+
+    // LMTP answers once per recipient after DATA
+    write.write_all(b"LHLO mx.example.com\r\n").await.unwrap();
+    expect(&mut reader, "250").await;
+    write.write_all(b"MAIL FROM:<>\r\n").await.unwrap();
+    expect(&mut reader, "250").await;
+    for _ in 0..2 {
+        write
+            .write_all(b"RCPT TO:<misc-list@news.example.org>\r\n")
+            .await
+            .unwrap();
+        expect(&mut reader, "250").await;
+    }
+    write.write_all(b"DATA\r\n").await.unwrap();
+    expect(&mut reader, "354").await;
+    write
+        .write_all(b"From: bob@example.com\r\nMessage-ID: <lmtp@example.com>\r\n\r\nHi\r\n.\r\n")
+        .await
+        .unwrap();
+    expect(&mut reader, "250").await;
+    expect(&mut reader, "250").await;
+    write.write_all(b"QUIT\r\n").await.unwrap();
+    expect(&mut reader, "221").await;
+
+    let article = storage
+        .get_article_by_id("<smtp@example.com>")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(article.body, ".dotted\r\n");
+    let lmtp = storage
+        .get_article_by_id("<lmtp@example.com>")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        renews::handlers::utils::get_header_value(&lmtp, "Subject").as_deref(),
+        Some("(no subject)")
+    );
+}
+
+#[tokio::test]
+async fn new_articles_are_mailed_to_the_list() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("mail.txt");
+    let sendmail = dir.path().join("sendmail");
+    std::fs::write(
+        &sendmail,
+        format!(
+            "#!/bin/sh\necho \"$@\" > {0}.tmp\ncat >> {0}.tmp\nmv {0}.tmp {0}\n",
+            out.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&sendmail, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut cfg = gateway_config("list = \"list@lists.example.com\"");
+    cfg.sendmail_path = sendmail.display().to_string();
+    let cfg = Arc::new(RwLock::new(cfg));
+    let (_, article) = renews::parse_message(
+        "Path: news.example.org!bob\r\nNewsgroups: misc.list\r\nFrom: bob@example.com\r\n\
+         Subject: From news\r\nMessage-ID: <news@example.org>\r\nNNTP-Posting-Host: 192.0.2.1\r\n\r\nBody\r\n",
+    )
+    .unwrap();
+
+    // Articles that came from mail are not sent back
+    let (_, gated) = renews::parse_message(
+        "Newsgroups: misc.list\r\nFrom: alice@example.com\r\nMessage-ID: <mail@example.com>\r\n\
+         X-Gateway: news.example.org mail-to-news misc-list@news.example.org\r\n\r\nBody\r\n",
+    )
+    .unwrap();
+    renews::gateway::forward(&cfg, &gated).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!out.exists());
+
+    renews::gateway::forward(&cfg, &article).await;
+    let mut waited = 0;
+    while !out.exists() && waited < 50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        waited += 1;
+    }
+    let mail = std::fs::read_to_string(&out).unwrap();
+    assert!(mail.starts_with("-i -- list@lists.example.com\n"));
+    assert!(mail.contains("\nMessage-ID: <news@example.org>\n"));
+    assert!(mail.contains("\nTo: list@lists.example.com\n"));
+    assert!(mail.contains("\nX-Gateway: news.example.org news-to-mail misc.list\n"));
+    assert!(!mail.contains("Path:"));
+    assert!(!mail.contains("NNTP-Posting-Host"));
+    assert!(mail.ends_with("\nBody\n"));
+    assert!(!mail.contains('\r'));
+}
+
+#[test]
+fn gateway_needs_an_address_or_list() {
+    let result: Result<Config, _> =
+        toml::from_str("addr = \":119\"\n[[gateway]]\ngroup = \"misc.list\"\n");
+    let cfg = result.unwrap();
+    assert!(cfg.gateways[0].validate().is_err());
+}
@@ -0,0 +1,172 @@
+use renews::config::GroupRule;
+
+use crate::utils::{self, ClientMock};
+
+const SEND: &str = "340 send article to be posted. End with <CR-LF>.<CR-LF>";
+
+fn rule(pattern: &str, post_users: Option<&[&str]>, read_users: Option<&[&str]>) -> GroupRule {
+    let users = |list: &[&str]| list.iter().map(|u| u.to_string()).collect();
+    GroupRule {
+        group: None,
+        pattern: Some(pattern.to_string()),
+        retention_days: None,
+        max_article_bytes: None,
+        post_users: post_users.map(users),
+        read_users: read_users.map(users),
+        compress: None,
+        moderated: None,
+    }
+}
+
+fn post(id: &str, group: &str) -> String {
+    format!(
+        "Message-ID: {id}\r\nNewsgroups: {group}\r\nFrom: poster@example.com\r\n\
+         Subject: acl\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\r\nBody\r\n."
+    )
+}
+
+#[tokio::test]
+async fn posting_is_limited_to_listed_users() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    storage.add_group("staff.internal", false).await.unwrap();
+    auth.add_user("alice", "pass").await.unwrap();
+    auth.add_user("bob", "pass").await.unwrap();
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.group_settings = vec![rule("staff.*", Some(&["alice", "ops.*"]), None)];
+
+    ClientMock::with_auth("bob", "pass")
+        .expect("POST", SEND)
+        .expect(&post("<bob1@test>", "staff.internal"), "441 posting failed")
+        .expect("POST", SEND)
+        .expect(
+            &post("<bob2@test>", "misc,staff.internal"),
+            "441 posting failed",
+        )
+        .expect("POST", SEND)
+        .expect(&post("<bob3@test>", "misc"), "240 article received")
+        .expect(
+            "XPOSTCHECK",
+            "345 send headers to be checked. End with <CR-LF>.<CR-LF>",
+        )
+        .expect(
+            "Newsgroups: staff.internal\r\nFrom: bob@example.com\r\nSubject: acl\r\n.",
+            "441 posting to staff.internal is not permitted",
+        )
+        .run_with_cfg_tls(cfg.clone(), storage.clone(), auth.clone())
+        .await;
+
+    ClientMock::with_auth("alice", "pass")
+        .expect("POST", SEND)
+        .expect(
+            &post("<alice1@test>", "staff.internal"),
+            "240 article received",
+        )
+        .run_with_cfg_tls(cfg, storage.clone(), auth)
+        .await;
+
+    let mut stored = false;
+    for _ in 0..50 {
+        if storage
+            .get_article_by_id("<alice1@test>")
+            .await
+            .unwrap()
+            .is_some()
+        {
+            stored = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(stored);
+    assert!(
+        storage
+            .get_article_by_id("<bob1@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn read_restricted_groups_are_hidden() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    storage.add_group("staff.internal", false).await.unwrap();
+    auth.add_user("alice", "pass").await.unwrap();
+    auth.add_user("bob", "pass").await.unwrap();
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.group_settings = vec![rule("staff.*", None, Some(&["alice"]))];
+
+    ClientMock::new()
+        .expect_multi(
+            "LIST ACTIVE",
+            vec!["215 list of newsgroups follows", "misc 0 0 y", "."],
+        )
+        .expect("GROUP staff.internal", "411 no such newsgroup")
+        .expect("LISTGROUP staff.internal", "411 no such newsgroup")
+        .expect("GROUP misc", "211 0 0 0 misc")
+        .run_with_cfg(cfg.clone(), storage.clone(), auth.clone())
+        .await;
+
+    ClientMock::with_auth("bob", "pass")
+        .expect("GROUP staff.internal", "411 no such newsgroup")
+        .run_with_cfg_tls(cfg.clone(), storage.clone(), auth.clone())
+        .await;
+
+    ClientMock::with_auth("alice", "pass")
+        .expect_multi(
+            "LIST ACTIVE",
+            vec![
+                "215 list of newsgroups follows",
+                "misc 0 0 y",
+                "staff.internal 0 0 y",
+                ".",
+            ],
+        )
+        .expect("GROUP staff.internal", "211 0 0 0 staff.internal")
+        .run_with_cfg_tls(cfg, storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn read_restricted_articles_are_not_found_by_message_id() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    storage.add_group("staff.internal", false).await.unwrap();
+    auth.add_user("alice", "pass").await.unwrap();
+    auth.add_user("bob", "pass").await.unwrap();
+    for (id, groups) in [
+        ("<secret@test>", "staff.internal"),
+        ("<shared@test>", "misc,staff.internal"),
+    ] {
+        let text = post(id, groups);
+        let (_, msg) = renews::parse_message(text.trim_end_matches('.')).unwrap();
+        storage.store_article(&msg).await.unwrap();
+    }
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.group_settings = vec![rule("staff.*", None, Some(&["alice"]))];
+
+    ClientMock::with_auth("bob", "pass")
+        .expect("ARTICLE <secret@test>", "430 no such article")
+        .expect("HEAD <secret@test>", "430 no such article")
+        .expect("BODY <secret@test>", "430 no such article")
+        .expect("STAT <secret@test>", "430 no such article")
+        .expect("BODY <secret@test> 0-", "430 no such article")
+        .expect("HDR Subject <secret@test>", "430 no such article")
+        .expect("XPAT Subject <secret@test> *", "430 no such article")
+        .expect("GROUP misc", "211 1 1 1 misc")
+        .expect("XTHREAD <secret@test>", "430 no such article")
+        // Cross-posted to a group bob may read
+        .expect("STAT <shared@test>", "223 0 <shared@test> article exists")
+        .run_with_cfg_tls(cfg.clone(), storage.clone(), auth.clone())
+        .await;
+
+    ClientMock::with_auth("alice", "pass")
+        .expect("STAT <secret@test>", "223 0 <secret@test> article exists")
+        .run_with_cfg_tls(cfg, storage, auth)
+        .await;
+}
@@ -205,11 +205,8 @@ async fn test_listgroup_command_nonexistent_group() {
     let (storage, auth) = setup().await;
 
     ClientMock::new()
-        // LISTGROUP creates empty listing for nonexistent group
-        .expect_multi(
-            "LISTGROUP nonexistent.group",
-            vec!["211 article numbers follow", "."],
-        )
+        // LISTGROUP refuses a nonexistent group
+        .expect("LISTGROUP nonexistent.group", "411 no such newsgroup")
         .expect("QUIT", "205 closing connection")
         .run(storage, auth)
         .await;
@@ -233,7 +230,10 @@ async fn test_newgroups_command_invalid_date() {
 
     ClientMock::new()
         // Invalid date format
-        .expect("NEWGROUPS invalid-date 000000", "501 invalid date")
+        .expect(
+            "NEWGROUPS invalid-date 000000",
+            "501 invalid date: invalid-date",
+        )
         .expect("NEWGROUPS 20241301 000000", "501 invalid date") // Invalid month
         .expect("NEWGROUPS 20241201 250000", "501 invalid date") // Invalid hour
         .expect("QUIT", "205 closing connection")
@@ -261,7 +261,10 @@ async fn test_newnews_command_invalid_args() {
         .expect("NEWNEWS", "501 not enough arguments")
         .expect("NEWNEWS wildmat", "501 not enough arguments")
         .expect("NEWNEWS wildmat 20241201", "501 not enough arguments")
-        .expect("NEWNEWS wildmat invalid-date 000000", "501 invalid date")
+        .expect(
+            "NEWNEWS wildmat invalid-date 000000",
+            "501 invalid date: invalid-date",
+        )
         .expect("QUIT", "205 closing connection")
         .run(storage, auth)
         .await;
@@ -314,7 +317,8 @@ async fn test_over_command_invalid_range() {
 
     ClientMock::new()
         .expect("GROUP test.group", "211 0 0 0 test.group")
-        .expect("OVER invalid-range", "423 no articles in that range")
+        .expect("OVER invalid-range", "501 invalid article: invalid-range")
+        .expect("OVER 10-1", "423 no articles in that range")
         .expect("OVER 999-1000", "423 no such article number in this group")
         .expect("QUIT", "205 closing connection")
         .run(storage, auth)
@@ -329,7 +333,7 @@ async fn test_streaming_commands_without_stream_mode() {
         // CHECK command should work without streaming mode
         .expect("CHECK <test@example.com>", "238 <test@example.com>")
         .expect("QUIT", "205 closing connection")
-        .run(storage, auth)
+        .run_as_peer(storage, auth)
         .await;
 }
 
@@ -341,11 +345,10 @@ async fn test_ihave_command_invalid_message_id() {
         .expect("IHAVE", "501 message-id required")
         .expect(
             "IHAVE invalid-message-id",
-            "335 Send it; end with <CR-LF>.<CR-LF>",
+            "501 invalid message-id: invalid-message-id",
         )
-        .expect("Invalid article content\r\n.", "437 article rejected")
         .expect("QUIT", "205 closing connection")
-        .run(storage, auth)
+        .run_as_peer(storage, auth)
         .await;
 }
 
@@ -403,3 +406,30 @@ async fn test_hdr_command_large_header_values() {
         .run(storage, auth)
         .await;
 }
+
+#[tokio::test]
+async fn test_storage_failure_is_answered() {
+    use renews::storage::Storage;
+    use renews::storage::sqlite::SqliteStorage;
+    use std::sync::Arc;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = format!("sqlite:///{}/news.db", dir.path().to_str().unwrap());
+    let storage = SqliteStorage::new(&path).await.unwrap();
+    storage.add_group("misc", false).await.unwrap();
+    let (_, auth) = setup().await;
+
+    // Break the database behind the server's back
+    let pool = sqlx::SqlitePool::connect(&path).await.unwrap();
+    sqlx::query("DROP TABLE group_articles")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    ClientMock::new()
+        .expect("GROUP misc", "403 internal fault")
+        .expect("LISTGROUP", "412 no newsgroup selected")
+        .expect("QUIT", "205 closing connection")
+        .run(Arc::new(storage), auth)
+        .await;
+}
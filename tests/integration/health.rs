@@ -0,0 +1,183 @@
+use renews::health::{self, HealthCheck};
+use renews::queue::{ArticleQueue, QueuedArticle};
+use renews::server::ConnectionTracker;
+use std::sync::Arc;
+
+use crate::utils;
+
+/// Serve the health endpoints on a free port, returning its address and the
+/// queue and tracker they watch.
+async fn start_health() -> (String, ArticleQueue, Arc<ConnectionTracker>) {
+    let (storage, auth) = utils::setup().await;
+    let queue = ArticleQueue::new(1);
+    let tracker = Arc::new(ConnectionTracker::default());
+    let listener = health::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let check = Arc::new(HealthCheck::new(
+        storage,
+        auth,
+        queue.clone(),
+        tracker.clone(),
+    ));
+    tokio::spawn(check.serve(listener));
+    (addr, queue, tracker)
+}
+
+#[tokio::test]
+async fn readiness_follows_listeners_draining_and_queue() {
+    let (addr, queue, tracker) = start_health().await;
+
+    let (status, body) = health::probe(&addr, "/healthz").await.unwrap();
+    assert_eq!(status, 200);
+    assert!(body.contains("renews_storage_up 1\n"), "{body}");
+    assert!(body.contains("renews_auth_up 1\n"), "{body}");
+    assert!(body.ends_with("# EOF\n"), "{body}");
+
+    // No NNTP listener is accepting connections yet
+    let (status, body) = health::probe(&addr, "/readyz").await.unwrap();
+    assert_eq!(status, 503);
+    assert!(body.contains("renews_listeners 0\n"), "{body}");
+
+    let listening = tracker.listener_started();
+    assert_eq!(health::probe(&addr, "/readyz").await.unwrap().0, 200);
+
+    tracker.set_draining(true);
+    assert_eq!(health::probe(&addr, "/readyz").await.unwrap().0, 503);
+    tracker.set_draining(false);
+
+    let (_, message) =
+        renews::parse_message("Message-ID: <full@test>\r\nNewsgroups: misc.test\r\n\r\nBody\r\n")
+            .unwrap();
+    queue
+        .submit(QueuedArticle {
+            message,
+            size: 0,
+            is_control: false,
+            already_validated: true,
+        })
+        .await
+        .unwrap();
+    let (status, body) = health::probe(&addr, "/readyz").await.unwrap();
+    assert_eq!(status, 503);
+    assert!(body.contains("renews_queue_length 1\n"), "{body}");
+    // A full queue leaves the server alive
+    assert_eq!(health::probe(&addr, "/healthz").await.unwrap().0, 200);
+
+    drop(listening);
+    assert_eq!(tracker.active_listeners(), 0);
+}
+
+#[tokio::test]
+async fn unknown_paths_are_not_found() {
+    let (addr, _queue, _tracker) = start_health().await;
+    assert_eq!(health::probe(&addr, "/metrics").await.unwrap().0, 404);
+}
+
+#[test]
+fn article_cache_counters_are_reported() {
+    use renews::health::HealthStatus;
+    use renews::storage::cache::CacheStats;
+
+    let mut status = HealthStatus {
+        storage_up: true,
+        auth_up: true,
+        queue_length: 0,
+        queue_capacity: 1,
+        listeners: 1,
+        draining: false,
+        article_cache: None,
+        workers: 1,
+        worker_histograms: Vec::new(),
+        ingest: Default::default(),
+    };
+    assert!(!status.to_openmetrics().contains("article_cache"));
+    status.ingest.duplicates = 2;
+    assert!(
+        status
+            .to_openmetrics()
+            .contains("renews_ingest_duplicates_total 2\n")
+    );
+
+    status.article_cache = Some(CacheStats {
+        hits: 3,
+        misses: 1,
+        expirations: 1,
+        bytes: 512,
+        ..CacheStats::default()
+    });
+    let body = status.to_openmetrics();
+    assert!(
+        body.contains("# TYPE renews_article_cache_hits counter\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("renews_article_cache_hits_total 3\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("renews_article_cache_misses_total 1\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("renews_article_cache_expirations_total 1\n"),
+        "{body}"
+    );
+    assert!(body.contains("renews_article_cache_bytes 512\n"), "{body}");
+    assert!(body.ends_with("# EOF\n"), "{body}");
+}
+
+#[test]
+fn worker_histograms_are_cumulative() {
+    use renews::health::HealthStatus;
+    use renews::queue::WorkerHistogram;
+
+    let mut buckets = [0; 8];
+    buckets[0] = 2;
+    buckets[4] = 1;
+    let status = HealthStatus {
+        storage_up: true,
+        auth_up: true,
+        queue_length: 0,
+        queue_capacity: 1,
+        listeners: 1,
+        draining: false,
+        article_cache: None,
+        workers: 2,
+        worker_histograms: vec![(
+            1,
+            WorkerHistogram {
+                buckets,
+                count: 3,
+                sum_micros: 250_000,
+            },
+        )],
+        ingest: Default::default(),
+    };
+    let body = status.to_openmetrics();
+    assert!(body.contains("renews_queue_workers 2\n"), "{body}");
+    assert!(
+        body.contains("# TYPE renews_worker_batch_seconds histogram\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("renews_worker_batch_seconds_bucket{worker=\"1\",le=\"0.005\"} 2\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("renews_worker_batch_seconds_bucket{worker=\"1\",le=\"0.1\"} 2\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("renews_worker_batch_seconds_bucket{worker=\"1\",le=\"0.5\"} 3\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("renews_worker_batch_seconds_bucket{worker=\"1\",le=\"+Inf\"} 3\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("renews_worker_batch_seconds_sum{worker=\"1\"} 0.25\n"),
+        "{body}"
+    );
+    assert!(body.ends_with("# EOF\n"), "{body}");
+}
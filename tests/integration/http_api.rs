@@ -0,0 +1,256 @@
+use crate::utils;
+use renews::config::Config;
+use renews::http_api::HttpApi;
+use renews::posting_account::{IdObfuscator, RotatingHash};
+use renews::queue::{ArticleQueue, QueuedArticle};
+use renews::storage::Storage;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+
+async fn start_api(
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn renews::auth::AuthProvider>,
+    cfg: Config,
+) -> std::net::SocketAddr {
+    let config = Arc::new(RwLock::new(cfg));
+    let queue =
+        utils::create_test_queue_with_workers(storage.clone(), auth.clone(), config.clone()).await;
+    start_api_with_queue(storage, auth, config, queue).await
+}
+
+async fn start_api_with_queue(
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn renews::auth::AuthProvider>,
+    config: Arc<RwLock<Config>>,
+    queue: ArticleQueue,
+) -> std::net::SocketAddr {
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &*config.read().await);
+    let api = Arc::new(HttpApi::new(storage, auth, config, queue, usage_tracker));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(api.serve(listener));
+    addr
+}
+
+/// Send a raw request and return the status code and JSON body.
+async fn request(addr: std::net::SocketAddr, raw: &str) -> (u16, Value) {
+    let (head, body) = request_with_head(addr, raw).await;
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body)
+}
+
+/// Send a raw request and return the response head and JSON body.
+async fn request_with_head(addr: std::net::SocketAddr, raw: &str) -> (String, Value) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(raw.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.to_string(), serde_json::from_str(body).unwrap())
+}
+
+fn post(article: &str, credentials: Option<&str>) -> String {
+    let auth = credentials
+        .map(|c| format!("Authorization: Basic {c}\r\n"))
+        .unwrap_or_default();
+    format!(
+        "POST /articles HTTP/1.1\r\nHost: test\r\n{auth}Content-Length: {}\r\n\r\n{article}",
+        article.len()
+    )
+}
+
+#[tokio::test]
+async fn posts_and_fetches_articles_over_http() {
+    let (storage, auth) = utils::setup().await;
+    storage
+        .add_group_with_description("misc", false, "Miscellaneous")
+        .await
+        .unwrap();
+    auth.add_user("user", "pass").await.unwrap();
+    let addr = start_api(storage.clone(), auth, utils::create_minimal_config()).await;
+
+    let (status, groups) = request(addr, "GET /groups HTTP/1.1\r\nHost: test\r\n\r\n").await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        groups,
+        serde_json::json!([{ "name": "misc", "description": "Miscellaneous" }])
+    );
+
+    let article = "Message-ID: <http@test>\nNewsgroups: misc\nFrom: user@example.com\n\
+                   Subject: via http\n\nhello\n";
+    let (status, _) = request(addr, &post(article, None)).await;
+    assert_eq!(status, 401);
+    // "user:wrong"
+    let (status, _) = request(addr, &post(article, Some("dXNlcjp3cm9uZw=="))).await;
+    assert_eq!(status, 401);
+    // "user:pass"
+    let (status, body) = request(addr, &post(article, Some("dXNlcjpwYXNz"))).await;
+    assert_eq!(status, 202);
+    assert_eq!(body["message_id"], "<http@test>");
+    assert_eq!(body["status"], "queued");
+
+    let mut fetched = (404, Value::Null);
+    for _ in 0..50 {
+        fetched = request(
+            addr,
+            "GET /articles/%3Chttp%40test%3E HTTP/1.1\r\nHost: test\r\n\r\n",
+        )
+        .await;
+        if fetched.1["groups"]
+            .as_array()
+            .is_some_and(|g| !g.is_empty())
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let (status, body) = fetched;
+    assert_eq!(status, 200);
+    assert_eq!(body["message_id"], "<http@test>");
+    assert_eq!(
+        body["groups"],
+        serde_json::json!([{ "group": "misc", "number": 1 }])
+    );
+    assert_eq!(body["body"], "hello\r\n");
+    assert!(
+        body["headers"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!(["Path", "test!not-for-mail"]))
+    );
+
+    let (status, _) = request(
+        addr,
+        "GET /articles/missing@test HTTP/1.1\r\nHost: test\r\n\r\n",
+    )
+    .await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn rejects_invalid_posts_over_http() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    auth.add_user("user", "pass").await.unwrap();
+    let mut cfg = utils::create_minimal_config();
+    cfg.max_message_bytes = Some(200);
+    let addr = start_api(storage.clone(), auth, cfg).await;
+
+    // No such group, so the filter chain refuses the article
+    let article = "Message-ID: <bad@test>\r\nNewsgroups: nowhere\r\nFrom: user@example.com\r\n\
+                   Subject: s\r\n\r\nbody\r\n";
+    let (status, body) = request(addr, &post(article, Some("dXNlcjpwYXNz"))).await;
+    assert_eq!(status, 422);
+    assert!(body["error"].is_string());
+
+    let large = format!(
+        "Newsgroups: misc\r\nFrom: user@example.com\r\nSubject: s\r\n\r\n{}\r\n",
+        "x".repeat(300)
+    );
+    let (status, _) = request(addr, &post(&large, Some("dXNlcjpwYXNz"))).await;
+    assert_eq!(status, 413);
+
+    let (status, _) = request(addr, "POST /articles HTTP/1.1\r\nHost: test\r\n\r\n").await;
+    assert_eq!(status, 411);
+    let (status, _) = request(addr, "DELETE /groups HTTP/1.1\r\nHost: test\r\n\r\n").await;
+    assert_eq!(status, 405);
+    let (status, _) = request(addr, "GET /nothing HTTP/1.1\r\nHost: test\r\n\r\n").await;
+    assert_eq!(status, 404);
+    assert!(
+        storage
+            .get_article_by_id("<bad@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn anonymous_posts_carry_the_posting_account() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let mut cfg = utils::create_minimal_config();
+    cfg.allow_anonymous_posting = true;
+    cfg.posting_accounts.salt = Some("test-salt".into());
+    cfg.posting_accounts.rotation = None;
+    let token = RotatingHash::from_config(&cfg.posting_accounts)
+        .posting_account("127.0.0.1".parse().unwrap(), 0);
+    let addr = start_api(storage.clone(), auth.clone(), cfg.clone()).await;
+
+    let article = "Message-ID: <anon@test>\r\nNewsgroups: misc\r\nFrom: anon@example.com\r\n\
+                   Subject: s\r\nInjection-Info: forged\r\n\r\nbody\r\n";
+    let (status, _) = request(addr, &post(article, None)).await;
+    assert_eq!(status, 202);
+    let mut stored = None;
+    for _ in 0..50 {
+        stored = storage.get_article_by_id("<anon@test>").await.unwrap();
+        if stored.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let injection_info: Vec<_> = stored
+        .unwrap()
+        .headers
+        .into_iter()
+        .filter(|(k, _)| k.eq_ignore_ascii_case("Injection-Info"))
+        .map(|(_, v)| v)
+        .collect();
+    assert_eq!(
+        injection_info,
+        vec![format!("test; posting-account=\"{token}\"")]
+    );
+
+    // A banned posting account may not post
+    cfg.posting_accounts.banned = vec![token];
+    let addr = start_api(storage.clone(), auth, cfg).await;
+    let article = article.replace("<anon@test>", "<banned@test>");
+    let (status, _) = request(addr, &post(&article, None)).await;
+    assert_eq!(status, 403);
+    assert!(
+        storage
+            .get_article_by_id("<banned@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn full_queue_asks_the_client_to_retry() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    auth.add_user("user", "pass").await.unwrap();
+    // No workers drain the queue, so one article fills it
+    let queue = ArticleQueue::new(1);
+    let (_, filler) = renews::parse_message(
+        "Message-ID: <filler@test>\r\nNewsgroups: misc\r\nFrom: a@test\r\nSubject: s\r\n\r\nx\r\n",
+    )
+    .unwrap();
+    queue
+        .try_submit(QueuedArticle {
+            message: filler,
+            size: 1,
+            is_control: false,
+            already_validated: true,
+        })
+        .await
+        .unwrap();
+    let config = Arc::new(RwLock::new(utils::create_minimal_config()));
+    let addr = start_api_with_queue(storage, auth, config, queue).await;
+
+    let article = "Message-ID: <full@test>\r\nNewsgroups: misc\r\nFrom: user@example.com\r\n\
+                   Subject: s\r\n\r\nbody\r\n";
+    let (head, body) = request_with_head(addr, &post(article, Some("dXNlcjpwYXNz"))).await;
+    assert!(head.starts_with("HTTP/1.1 503 "), "{head}");
+    let retry_after = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Retry-After: "))
+        .and_then(|secs| secs.parse::<u64>().ok());
+    assert!(retry_after.is_some_and(|secs| secs >= 1), "{head}");
+    assert_eq!(body["error"], "article queue full");
+}
@@ -0,0 +1,281 @@
+use crate::utils::{self, ClientMock};
+use renews::config::{Config, ListenerPolicy, ListenerRole};
+use renews::{ConnectionInfo, handle_client_with_info};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+use tokio::time::timeout;
+
+/// Start a server for one connection accepted under `policy`.
+async fn start_listener(
+    policy: ListenerPolicy,
+    idle_timeout_secs: u64,
+) -> (std::net::SocketAddr, tokio::task::JoinHandle<()>) {
+    let mut config = utils::create_minimal_config();
+    config.idle_timeout_secs = idle_timeout_secs;
+    let info = ConnectionInfo {
+        policy,
+        ..ConnectionInfo::default()
+    };
+    start_connection(config, info).await
+}
+
+/// Start a server configured with `config` for one connection described
+/// by `info`.
+async fn start_connection(
+    config: Config,
+    info: ConnectionInfo,
+) -> (std::net::SocketAddr, tokio::task::JoinHandle<()>) {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &config);
+    let cfg = Arc::new(RwLock::new(config));
+    let queue =
+        utils::create_test_queue_with_workers(storage.clone(), auth.clone(), cfg.clone()).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        handle_client_with_info(sock, storage, auth, cfg, info, queue, usage_tracker)
+            .await
+            .unwrap();
+    });
+    (addr, handle)
+}
+
+fn role(role: ListenerRole) -> ListenerPolicy {
+    ListenerPolicy {
+        role,
+        ..ListenerPolicy::default()
+    }
+}
+
+#[tokio::test]
+async fn transit_listener_only_accepts_feeds() {
+    let (addr, handle) = start_listener(role(ListenerRole::Transit), 600).await;
+
+    ClientMock::new()
+        .expect_multi(
+            "CAPABILITIES",
+            vec![
+                "101 Capability list follows".to_string(),
+                "VERSION 2".into(),
+                format!("IMPLEMENTATION Renews {}", env!("CARGO_PKG_VERSION")),
+                "IHAVE".into(),
+                "STREAMING".into(),
+                ".".into(),
+            ],
+        )
+        .expect("MODE READER", "502 command not available on this port")
+        .expect("GROUP misc", "502 command not available on this port")
+        .expect("POST", "502 command not available on this port")
+        .expect("MODE STREAM", "203 Streaming permitted")
+        .expect("IHAVE <feed@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn reader_listener_rejects_feeds() {
+    let (addr, handle) = start_listener(role(ListenerRole::Reader), 600).await;
+
+    ClientMock::new()
+        .expect(
+            "IHAVE <feed@test>",
+            "502 command not available on this port",
+        )
+        .expect(
+            "CHECK <feed@test>",
+            "502 command not available on this port",
+        )
+        // The article following TAKETHIS is discarded, not run as commands
+        .expect_request_multi(
+            vec![
+                "TAKETHIS <feed@test>",
+                "Newsgroups: misc",
+                "Subject: QUIT",
+                "",
+                "GROUP misc",
+                ".",
+            ],
+            vec!["502 command not available on this port"],
+        )
+        .expect("MODE STREAM", "502 command not available on this port")
+        .expect("MODE READER", "201 Posting prohibited")
+        .expect("GROUP misc", "211 0 0 0 misc")
+        .expect("QUIT", "205 closing connection")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+}
+
+/// A connection to a listener of role `all` from `ip`, with a peer
+/// configured at 192.0.2.7.
+async fn start_connection_from(ip: &str) -> (std::net::SocketAddr, tokio::task::JoinHandle<()>) {
+    start_connection_from_peer(ip, "192.0.2.7:119").await
+}
+
+/// A connection to a listener of role `all` from `ip`, with a peer
+/// configured as `sitename`.
+async fn start_connection_from_peer(
+    ip: &str,
+    sitename: &str,
+) -> (std::net::SocketAddr, tokio::task::JoinHandle<()>) {
+    let mut config = utils::create_minimal_config();
+    config.peers = toml::from_str::<Config>(&format!(
+        "addr = \":119\"\n[[peers]]\nsitename = \"{sitename}\"\n"
+    ))
+    .unwrap()
+    .peers;
+    let info = ConnectionInfo {
+        peer_ip: Some(ip.parse().unwrap()),
+        ..ConnectionInfo::default()
+    };
+    start_connection(config, info).await
+}
+
+#[tokio::test]
+async fn configured_peers_feed_without_authenticating() {
+    let (addr, handle) = start_connection_from("192.0.2.7").await;
+
+    let mut capabilities = utils::capabilities_lines();
+    capabilities.insert(5, "IHAVE".into());
+    capabilities.insert(6, "STREAMING".into());
+    ClientMock::new()
+        .expect_multi("CAPABILITIES", capabilities)
+        .expect("MODE STREAM", "203 Streaming permitted")
+        .expect("CHECK <feed@test>", "238 <feed@test>")
+        .expect_request_multi(
+            vec![
+                "TAKETHIS <feed@test>",
+                "Message-ID: <feed@test>",
+                "Newsgroups: misc",
+                "From: peer@test",
+                "Subject: fed",
+                "",
+                "body",
+                ".",
+            ],
+            vec!["239 <feed@test>"],
+        )
+        .expect("QUIT", "205 closing connection")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn peers_named_by_host_name_are_resolved() {
+    let (addr, handle) = start_connection_from_peer("127.0.0.1", "localhost:119").await;
+
+    ClientMock::new()
+        .expect("MODE STREAM", "203 Streaming permitted")
+        .expect("QUIT", "205 closing connection")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn other_addresses_must_authenticate_to_feed() {
+    let (addr, handle) = start_connection_from("198.51.100.1").await;
+
+    ClientMock::new()
+        .expect_multi("CAPABILITIES", utils::capabilities_lines())
+        .expect("CHECK <feed@test>", "480 authentication required")
+        // The article following TAKETHIS is discarded, not run as commands
+        .expect_request_multi(
+            vec![
+                "TAKETHIS <feed@test>",
+                "Newsgroups: misc",
+                "Subject: QUIT",
+                "",
+                "GROUP misc",
+                ".",
+            ],
+            vec!["480 authentication required"],
+        )
+        .expect("MODE STREAM", "480 authentication required")
+        .expect("QUIT", "205 closing connection")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn listener_idle_timeout_overrides_global() {
+    let policy = ListenerPolicy {
+        idle_timeout_secs: Some(1),
+        ..ListenerPolicy::default()
+    };
+    let (addr, handle) = start_listener(policy, 600).await;
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    assert!(line.starts_with("201"));
+
+    // The server closes the connection long before the global timeout
+    line.clear();
+    let n = timeout(Duration::from_secs(5), reader.read_line(&mut line))
+        .await
+        .expect("listener idle timeout not applied")
+        .unwrap();
+    assert_eq!(n, 0);
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn unix_socket_listener_serves_nntp() {
+    use renews::config::UnixSocketConfig;
+    use renews::listener::{self, NntpListener};
+    use std::os::unix::fs::PermissionsExt;
+
+    let (storage, auth) = utils::setup().await;
+    let config = utils::create_minimal_config();
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &config);
+    let cfg = Arc::new(RwLock::new(config));
+    let queue =
+        utils::create_test_queue_with_workers(storage.clone(), auth.clone(), cfg.clone()).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nntp.sock");
+    let path = path.to_str().unwrap();
+    let settings = UnixSocketConfig {
+        mode: 0o600,
+        ..UnixSocketConfig::default()
+    };
+    // A socket file left behind by an earlier run is replaced
+    drop(listener::bind_unix(path, &settings).unwrap());
+    let unix = NntpListener::Unix(listener::bind_unix(path, &settings).unwrap());
+    let mode = std::fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    tokio::spawn(async move {
+        let (sock, peer_ip) = unix.accept().await.unwrap();
+        assert!(peer_ip.is_none());
+        let info = ConnectionInfo {
+            peer_ip,
+            ..ConnectionInfo::default()
+        };
+        handle_client_with_info(sock, storage, auth, cfg, info, queue, usage_tracker)
+            .await
+            .unwrap();
+    });
+    let stream = listener::connect(&format!("unix://{path}")).await.unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut greeting = String::new();
+    reader.read_line(&mut greeting).await.unwrap();
+    assert!(greeting.starts_with("20"), "{greeting:?}");
+    // While it is served, it is not taken over
+    assert!(listener::bind_unix(path, &settings).is_err());
+
+    let file = dir.path().join("plain");
+    std::fs::write(&file, "").unwrap();
+    assert!(listener::bind_unix(file.to_str().unwrap(), &settings).is_err());
+}
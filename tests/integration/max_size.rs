@@ -26,7 +26,7 @@ max_article_bytes = 10
             "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\nFrom: a@test\r\nSubject: big\r\n\r\n0123456789A\r\n.",
             "437 article rejected",
         )
-        .run_with_cfg(cfg_val, storage.clone(), auth)
+        .run_as_peer_with_cfg(cfg_val, storage.clone(), auth)
         .await;
     assert!(
         storage
@@ -62,7 +62,7 @@ max_article_bytes = "1K"
             ),
             "437 article rejected",
         )
-        .run_with_cfg(cfg_val, storage.clone(), auth)
+        .run_as_peer_with_cfg(cfg_val, storage.clone(), auth)
         .await;
     assert!(
         storage
@@ -72,3 +72,39 @@ max_article_bytes = "1K"
             .is_none()
     );
 }
+
+#[tokio::test]
+async fn ihave_discards_article_over_message_limit() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    let cfg: Config = toml::from_str(
+        r#"
+addr = ":119"
+max_message_bytes = 128
+"#,
+    )
+    .unwrap();
+    ClientMock::new()
+        .expect("IHAVE <3@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect(
+            &format!(
+                "Message-ID: <3@test>\r\nNewsgroups: misc.test\r\nFrom: c@test\r\nSubject: big\r\n\r\n{}\r\n.",
+                "A".repeat(200)
+            ),
+            "437 article rejected",
+        )
+        // The oversized block was drained, so the connection is still in sync
+        .expect("IHAVE <3@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect(
+            "Message-ID: <3@test>\r\nNewsgroups: misc.test\r\nFrom: c@test\r\nSubject: ok\r\n\r\nsmall\r\n.",
+            "235 Article transferred OK",
+        )
+        .run_as_peer_with_cfg(cfg, storage.clone(), auth)
+        .await;
+    let stored = storage
+        .get_article_by_id("<3@test>")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.body, "small\r\n");
+}
@@ -84,7 +84,7 @@ async fn post_requires_approval_for_moderated_group() {
                 "Body\r\n",
                 ".",
             ),
-            "441 posting failed",
+            "240 article received",
         )
         .expect("QUIT", "205 closing connection")
         .run_tls(storage.clone(), auth)
@@ -96,6 +96,176 @@ async fn post_requires_approval_for_moderated_group() {
             .unwrap()
             .is_none()
     );
+    let pending = storage.get_pending_article(1).await.unwrap().unwrap();
+    assert_eq!(
+        utils::get_message_id(&pending.message).as_deref(),
+        Some("<p@test>")
+    );
+}
+
+fn unapproved_article(message_id: &str) -> String {
+    format!(
+        "Message-ID: {message_id}\r\nNewsgroups: mod.test\r\nFrom: user@example.com\r\n\
+         Subject: held\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\r\nBody\r\n"
+    )
+}
+
+#[tokio::test]
+async fn moderator_approves_pending_article() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("mod.test", true).await.unwrap();
+    auth.add_user("mod", "pass").await.unwrap();
+    auth.add_moderator("mod", "mod.test").await.unwrap();
+    let (_, msg) = parse_message(&unapproved_article("<held@test>")).unwrap();
+    let id = storage.add_pending_article(&msg).await.unwrap();
+
+    ClientMock::new()
+        .expect("AUTHINFO USER mod", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect_multi(
+            "XMODERATE LIST",
+            vec![
+                "215 pending articles follow".to_string(),
+                format!("{id}\t<held@test>\tmod.test\theld"),
+                ".".to_string(),
+            ],
+        )
+        .expect(&format!("XMODERATE APPROVE {id}"), "240 article approved")
+        .expect(
+            &format!("XMODERATE APPROVE {id}"),
+            "430 no such pending article",
+        )
+        .expect("QUIT", "205 closing connection")
+        .run_tls(storage.clone(), auth)
+        .await;
+
+    let stored = storage
+        .get_article_by_id("<held@test>")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        utils::get_header(&stored, "Approved").as_deref(),
+        Some("mod")
+    );
+    assert_eq!(
+        collect_article_numbers(&*storage, "mod.test").await,
+        vec![1]
+    );
+}
+
+#[tokio::test]
+async fn moderator_lists_by_group_and_reads_pending_article() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("mod.test", true).await.unwrap();
+    storage.add_group("other.test", true).await.unwrap();
+    auth.add_user("mod", "pass").await.unwrap();
+    auth.add_moderator("mod", "*").await.unwrap();
+    let (_, msg) = parse_message(&unapproved_article("<held@test>")).unwrap();
+    let id = storage.add_pending_article(&msg).await.unwrap();
+    let other = unapproved_article("<other@test>").replace("mod.test", "other.test");
+    let (_, msg) = parse_message(&other).unwrap();
+    let other_id = storage.add_pending_article(&msg).await.unwrap();
+
+    ClientMock::new()
+        .expect("AUTHINFO USER mod", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect_multi(
+            "XMODERATE LIST other.*",
+            vec![
+                "215 pending articles follow".to_string(),
+                format!("{other_id}\t<other@test>\tother.test\theld"),
+                ".".to_string(),
+            ],
+        )
+        .expect_multi(
+            &format!("XMODERATE GET {id}"),
+            vec![
+                format!("220 {id} <held@test> pending article follows"),
+                "Message-ID: <held@test>".to_string(),
+                "Newsgroups: mod.test".to_string(),
+                "From: user@example.com".to_string(),
+                "Subject: held".to_string(),
+                "Date: Wed, 05 Oct 2022 00:00:00 GMT".to_string(),
+                String::new(),
+                "Body".to_string(),
+                ".".to_string(),
+            ],
+        )
+        .expect("XMODERATE GET 999", "430 no such pending article")
+        .expect("XMODERATE GET", "501 not enough arguments")
+        .expect("QUIT", "205 closing connection")
+        .run_tls(storage.clone(), auth)
+        .await;
+
+    // Reading an entry leaves it queued
+    assert!(storage.get_pending_article(id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn non_moderator_cannot_act_on_pending_article() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("mod.test", true).await.unwrap();
+    auth.add_user("user", "pass").await.unwrap();
+    let (_, msg) = parse_message(&unapproved_article("<held@test>")).unwrap();
+    let id = storage.add_pending_article(&msg).await.unwrap();
+
+    ClientMock::new()
+        .expect("XMODERATE LIST", "480 authentication required")
+        .expect("AUTHINFO USER user", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect_multi("XMODERATE LIST", vec!["215 pending articles follow", "."])
+        .expect(
+            &format!("XMODERATE GET {id}"),
+            "502 not a moderator for this article",
+        )
+        .expect(
+            &format!("XMODERATE REJECT {id}"),
+            "502 not a moderator for this article",
+        )
+        .expect("QUIT", "205 closing connection")
+        .run_tls(storage.clone(), auth)
+        .await;
+
+    assert!(storage.get_pending_article(id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn moderator_pins_articles_in_own_groups() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("mod.test", true).await.unwrap();
+    storage.add_group("misc", false).await.unwrap();
+    auth.add_user("mod", "pass").await.unwrap();
+    auth.add_moderator("mod", "mod.*").await.unwrap();
+    utils::store_test_article(
+        &*storage,
+        "Message-ID: <pin@test>\r\nNewsgroups: mod.test,misc\r\n\r\nB",
+    )
+    .await;
+
+    ClientMock::new()
+        .expect("AUTHINFO USER mod", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect("XMODERATE PIN mod.test <pin@test>", "242 article pinned")
+        .expect(
+            "XMODERATE PIN misc <pin@test>",
+            "502 not a moderator for this group",
+        )
+        .expect("XMODERATE PIN mod.test <none@test>", "430 no such article")
+        .expect("XMODERATE PIN mod.test", "501 not enough arguments")
+        .expect_multi(
+            "LIST PINNED",
+            vec!["215 pinned articles follow", "mod.test 1 <pin@test>", "."],
+        )
+        .expect_multi("LIST PINNED misc", vec!["215 pinned articles follow", "."])
+        .expect(
+            "XMODERATE UNPIN mod.test <pin@test>",
+            "243 article unpinned",
+        )
+        .expect_multi("LIST PINNED", vec!["215 pinned articles follow", "."])
+        .expect("QUIT", "205 closing connection")
+        .run_tls(storage, auth)
+        .await;
 }
 
 #[tokio::test]
@@ -0,0 +1,126 @@
+use renews::config::NocemIssuer;
+use renews::storage::DynStorage;
+use std::time::Duration;
+
+use crate::utils::{self, ClientMock, build_sig, store_test_article};
+
+const ADMIN_PUB: &str = include_str!("../data/admin.pub.asc");
+
+/// Build a clearsigned NoCeM notice listing `ids`, signed with the test key.
+fn notice_body(issuer: &str, ids: &[&str]) -> String {
+    let mut text = format!(
+        "@@BEGIN NCM HEADERS\r\nVersion: 0.93\r\nIssuer: {issuer}\r\nType: spam\r\n\
+         Action: hide\r\nNotice-ID: test-1\r\nCount: {}\r\n@@BEGIN NCM BODY\r\n",
+        ids.len()
+    );
+    for id in ids {
+        text.push_str(&format!("{id} misc.test\r\n"));
+    }
+    text.push_str("@@END NCM BODY");
+    let (version, sig) = build_sig(&text);
+    format!(
+        "-----BEGIN PGP SIGNED MESSAGE-----\r\nHash: SHA256\r\n\r\n{text}\r\n\
+         -----BEGIN PGP SIGNATURE-----\r\nVersion: {version}\r\n\r\n{}\r\n\
+         -----END PGP SIGNATURE-----",
+        sig.join("\r\n")
+    )
+}
+
+fn notice_article(id: &str, body: &str) -> String {
+    format!(
+        "Message-ID: {id}\r\nNewsgroups: alt.nocem.misc\r\nFrom: hunter@example.org\r\n\
+         Subject: @@NCM NoCeM notice\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\
+         Path: peer.example!hunter\r\n\r\n{body}\r\n."
+    )
+}
+
+async fn setup(key_file: &std::path::Path) -> (DynStorage, renews::auth::DynAuth) {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    storage.add_group("alt.nocem.misc", false).await.unwrap();
+    for id in ["<spam@test>", "<ham@test>"] {
+        store_test_article(
+            &*storage,
+            &format!("Message-ID: {id}\r\nNewsgroups: misc.test\r\nFrom: u@test\r\nSubject: t\r\n\r\nBody"),
+        )
+        .await;
+    }
+    std::fs::write(key_file, ADMIN_PUB).unwrap();
+    (storage, auth)
+}
+
+fn config(key_file: &std::path::Path) -> renews::config::Config {
+    let mut cfg = utils::create_minimal_config();
+    cfg.nocem.issuers = vec![NocemIssuer {
+        name: "hunter@example.org".to_string(),
+        key_file: key_file.to_path_buf(),
+        types: vec!["spam".to_string()],
+    }];
+    cfg
+}
+
+async fn wait_until_removed(storage: &DynStorage, id: &str) {
+    for _ in 0..50 {
+        if storage.get_article_by_id(id).await.unwrap().is_none() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+async fn exists(storage: &DynStorage, id: &str) -> bool {
+    storage.get_article_by_id(id).await.unwrap().is_some()
+}
+
+#[tokio::test]
+async fn nocem_notice_removes_listed_articles() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_file = dir.path().join("hunter.asc");
+    let (storage, auth) = setup(&key_file).await;
+
+    let body = notice_body("hunter@example.org", &["<spam@test>", "<gone@test>"]);
+    ClientMock::new()
+        .expect("IHAVE <n1@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect_request_multi(
+            utils::request_lines(&notice_article("<n1@test>", &body)),
+            vec!["235 Article transferred OK"],
+        )
+        .run_as_peer_with_cfg(config(&key_file), storage.clone(), auth)
+        .await;
+
+    wait_until_removed(&storage, "<spam@test>").await;
+    assert!(!exists(&storage, "<spam@test>").await);
+    assert!(exists(&storage, "<ham@test>").await);
+    assert!(exists(&storage, "<n1@test>").await);
+}
+
+#[tokio::test]
+async fn nocem_ignores_unsubscribed_and_forged_notices() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_file = dir.path().join("hunter.asc");
+    let (storage, auth) = setup(&key_file).await;
+
+    let stranger = notice_body("stranger@example.org", &["<spam@test>"]);
+    let forged =
+        notice_body("hunter@example.org", &["<spam@test>"]).replace("<spam@test>", "<ham@test>");
+    ClientMock::new()
+        .expect("IHAVE <n2@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect_request_multi(
+            utils::request_lines(&notice_article("<n2@test>", &stranger)),
+            vec!["235 Article transferred OK"],
+        )
+        .expect("IHAVE <n3@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect_request_multi(
+            utils::request_lines(&notice_article("<n3@test>", &forged)),
+            vec!["235 Article transferred OK"],
+        )
+        .run_as_peer_with_cfg(config(&key_file), storage.clone(), auth)
+        .await;
+
+    // IHAVE stores the notices at once; give the queue time to process them
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(exists(&storage, "<n2@test>").await);
+    assert!(exists(&storage, "<n3@test>").await);
+    assert!(exists(&storage, "<spam@test>").await);
+    assert!(exists(&storage, "<ham@test>").await);
+}
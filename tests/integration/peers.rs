@@ -1,13 +1,24 @@
 use crate::utils::{self as common, ClientMock};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
 use renews::auth::AuthProvider;
-use renews::peers::{PeerConfig, PeerDb, add_peer_job};
+use renews::config::Config;
+use renews::config::{ListenerRole, PeerFilter, PeerMode, PeerTransport};
+use renews::feed::Feeder;
+use renews::peers::{
+    PeerConfig, PeerDb, PeerSyncReport, PeerTraffic, Transfer, add_peer_job, sync_peer,
+};
+use renews::queue::ArticleQueue;
 use renews::storage::Storage;
 use renews::storage::sqlite::SqliteStorage;
 use serial_test::serial;
 use std::fs;
 use std::sync::Arc;
 use tempfile::NamedTempFile;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
 use tokio_cron_scheduler::JobScheduler;
+use tokio_rustls::{TlsAcceptor, rustls};
 
 #[tokio::test]
 async fn add_and_remove_peers() {
@@ -29,8 +40,13 @@ async fn peer_task_updates_last_sync() {
     let storage: Arc<dyn Storage> = Arc::new(storage);
     let peer = PeerConfig {
         sitename: "127.0.0.1:9".into(),
-        patterns: vec![],
+        filter: PeerFilter {
+            patterns: vec![],
+            ..PeerFilter::default()
+        },
         sync_schedule: Some("* * * * * *".into()), // Every second for testing
+        mode: PeerMode::Push,
+        transport: PeerTransport::default(),
     };
 
     // Create shared scheduler
@@ -43,8 +59,9 @@ async fn peer_task_updates_last_sync() {
         peer,
         "* * * * * *".to_string(),
         db.clone(),
-        storage,
-        "local".into(),
+        storage.clone(),
+        common::create_test_queue(),
+        site("local"),
     )
     .await
     .unwrap();
@@ -70,14 +87,24 @@ async fn shared_scheduler_handles_multiple_peers() {
     // Add multiple peer jobs to the same shared scheduler
     let peer1 = PeerConfig {
         sitename: "peer1:9".into(),
-        patterns: vec![],
+        filter: PeerFilter {
+            patterns: vec![],
+            ..PeerFilter::default()
+        },
         sync_schedule: Some("* * * * * *".into()), // Every second for testing
+        mode: PeerMode::Push,
+        transport: PeerTransport::default(),
     };
 
     let peer2 = PeerConfig {
         sitename: "peer2:9".into(),
-        patterns: vec![],
+        filter: PeerFilter {
+            patterns: vec![],
+            ..PeerFilter::default()
+        },
         sync_schedule: Some("* * * * * *".into()), // Every second for testing
+        mode: PeerMode::Push,
+        transport: PeerTransport::default(),
     };
 
     let _job1_uuid = add_peer_job(
@@ -86,7 +113,8 @@ async fn shared_scheduler_handles_multiple_peers() {
         "* * * * * *".to_string(),
         db.clone(),
         storage.clone(),
-        "local".into(),
+        common::create_test_queue(),
+        site("local"),
     )
     .await
     .unwrap();
@@ -96,8 +124,9 @@ async fn shared_scheduler_handles_multiple_peers() {
         peer2,
         "* * * * * *".to_string(),
         db.clone(),
-        storage,
-        "local".into(),
+        storage.clone(),
+        common::create_test_queue(),
+        site("local"),
     )
     .await
     .unwrap();
@@ -128,8 +157,14 @@ async fn peer_transfer_helper(schedule: &str) {
 
     let cfg_a: renews::config::Config = toml::from_str("addr=\":119\"\nsite_name='A'").unwrap();
     let cfg_b: renews::config::Config = toml::from_str("addr=\":119\"").unwrap();
-    let (addr_b, cert_b, handle_b) =
-        common::start_server(storage_b.clone(), auth.clone(), cfg_b.clone(), true).await;
+    let (addr_b, cert_b, handle_b) = common::start_server_with_role(
+        storage_b.clone(),
+        auth.clone(),
+        cfg_b.clone(),
+        true,
+        ListenerRole::Transit,
+    )
+    .await;
     let ca_file = NamedTempFile::new().unwrap();
     if let Some((_, pem)) = &cert_b {
         fs::write(ca_file.path(), pem).unwrap();
@@ -166,11 +201,18 @@ async fn peer_transfer_helper(schedule: &str) {
 
     let db = PeerDb::new("sqlite::memory:").await.unwrap();
     let peer_name = format!("localhost:{}", addr_b.port());
-    db.sync_config(&[peer_name.clone()]).await.unwrap();
+    db.sync_config(std::slice::from_ref(&peer_name))
+        .await
+        .unwrap();
     let peer = PeerConfig {
         sitename: peer_name.clone(),
-        patterns: vec!["*".into()],
+        filter: PeerFilter {
+            patterns: vec!["*".into()],
+            ..PeerFilter::default()
+        },
         sync_schedule: Some(schedule.to_string()),
+        mode: PeerMode::Push,
+        transport: PeerTransport::default(),
     };
 
     // Create shared scheduler
@@ -184,7 +226,8 @@ async fn peer_transfer_helper(schedule: &str) {
         "* * * * * *".to_string(),
         db.clone(),
         storage_a.clone(),
-        "A".into(),
+        common::create_test_queue(),
+        site("A"),
     )
     .await
     .unwrap();
@@ -205,7 +248,8 @@ async fn peer_transfer_helper(schedule: &str) {
                 "From: a@test",
                 "Subject: hello",
                 "Date: Wed, 05 Oct 2022 00:00:00 GMT",
-                "Path: A",
+                "Lines: 1",
+                "Path: localhost!A!not-for-mail",
                 "",
                 "body",
                 ".",
@@ -228,3 +272,716 @@ async fn peer_transfer_fast_schedule() {
 async fn peer_transfer_default_schedule() {
     peer_transfer_helper("*/2 * * * * *").await; // Every 2 seconds
 }
+
+#[tokio::test]
+async fn peer_db_tracks_pull_watermark_and_history() {
+    let db_file = NamedTempFile::new().unwrap();
+    let db_path = format!("sqlite://{}", db_file.path().display());
+
+    // A database from before pulling was supported
+    let pool = sqlx::SqlitePool::connect(&db_path).await.unwrap();
+    sqlx::query("CREATE TABLE peers (sitename TEXT PRIMARY KEY, last_sync INTEGER)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO peers (sitename, last_sync) VALUES ('a', 0)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+
+    let db = PeerDb::new(&db_path).await.unwrap();
+    assert_eq!(db.get_last_pull("a").await.unwrap(), None);
+    let when = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    db.update_last_pull("a", when).await.unwrap();
+    assert_eq!(db.get_last_pull("a").await.unwrap(), Some(when));
+    assert_eq!(db.get_last_sync("a").await.unwrap(), None);
+
+    db.record_peer_has("a", "<x@test>").await.unwrap();
+    assert!(db.peer_has("a", "<x@test>").await.unwrap());
+    assert!(!db.peer_has("b", "<x@test>").await.unwrap());
+    db.prune_history("a", chrono::Utc::now() + chrono::Duration::seconds(1))
+        .await
+        .unwrap();
+    assert!(!db.peer_has("a", "<x@test>").await.unwrap());
+}
+
+#[tokio::test]
+#[serial]
+async fn bidirectional_peers_federate_without_echo() {
+    let auth = Arc::new(
+        renews::auth::sqlite::SqliteAuth::new("sqlite::memory:")
+            .await
+            .unwrap(),
+    );
+    auth.add_user("feeder", "pass").await.unwrap();
+    let storage_a: Arc<dyn Storage> =
+        Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    let storage_b: Arc<dyn Storage> =
+        Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    for (storage, id, site) in [(&storage_a, "a", "A"), (&storage_b, "b", "B")] {
+        storage.add_group("misc.test", false).await.unwrap();
+        common::store_test_article(
+            &**storage,
+            &format!(
+                "Message-ID: <{id}@test>\r\nNewsgroups: misc.test\r\nFrom: {id}@test\r\n\
+                 Subject: from {site}\r\nPath: {site}!not-for-mail\r\n\r\nbody\r\n"
+            ),
+        )
+        .await;
+    }
+
+    let cfg_a: renews::config::Config = toml::from_str("addr=\":119\"\nsite_name='A'").unwrap();
+    let cfg_b: renews::config::Config = toml::from_str("addr=\":119\"\nsite_name='B'").unwrap();
+    let (addr_a, pem_a, server_a) =
+        common::start_tls_server_loop(storage_a.clone(), auth.clone(), cfg_a).await;
+    let (addr_b, pem_b, server_b) =
+        common::start_tls_server_loop(storage_b.clone(), auth.clone(), cfg_b).await;
+    let ca_file = NamedTempFile::new().unwrap();
+    fs::write(ca_file.path(), format!("{pem_a}{pem_b}")).unwrap();
+    unsafe { std::env::set_var("SSL_CERT_FILE", ca_file.path()) };
+
+    let name_a = format!("localhost:{}", addr_a.port());
+    let name_b = format!("localhost:{}", addr_b.port());
+    let peer = |sitename: &str| PeerConfig {
+        sitename: sitename.to_string(),
+        filter: PeerFilter {
+            patterns: vec!["misc.*".into()],
+            ..PeerFilter::default()
+        },
+        sync_schedule: None,
+        mode: PeerMode::Both,
+        transport: PeerTransport {
+            username: Some("feeder".into()),
+            password: Some("pass".into()),
+            ..PeerTransport::default()
+        },
+    };
+    let db_a = PeerDb::new("sqlite::memory:").await.unwrap();
+    db_a.sync_config(std::slice::from_ref(&name_b))
+        .await
+        .unwrap();
+    let db_b = PeerDb::new("sqlite::memory:").await.unwrap();
+    db_b.sync_config(std::slice::from_ref(&name_a))
+        .await
+        .unwrap();
+
+    let config_a = site("A");
+    let queue_a = workers(&storage_a, &config_a).await;
+    let config_b = site("B");
+    let queue_b = workers(&storage_b, &config_b).await;
+
+    // A pulls <b> and pushes <a>; <b> came from B and is not offered back
+    // if the workers have stored it by then
+    let report = sync_peer(&peer(&name_b), &db_a, &storage_a, &queue_a, &config_a).await;
+    assert_eq!((report.sent, report.pulled, report.errors), (1, 1, 0));
+    drained(&queue_a).await;
+    for storage in [&storage_a, &storage_b] {
+        for id in ["<a@test>", "<b@test>"] {
+            assert!(storage.get_article_by_id(id).await.unwrap().is_some());
+        }
+    }
+
+    // B learns that A has both articles and offers it nothing
+    let report = sync_peer(&peer(&name_a), &db_b, &storage_b, &queue_b, &config_b).await;
+    assert_eq!(
+        report,
+        PeerSyncReport {
+            skipped: 2,
+            ..PeerSyncReport::default()
+        }
+    );
+    assert!(db_b.get_last_pull(&name_a).await.unwrap().is_some());
+    assert!(db_b.get_last_sync(&name_a).await.unwrap().is_some());
+
+    // Nothing new on either side
+    let report = sync_peer(&peer(&name_b), &db_a, &storage_a, &queue_a, &config_a).await;
+    assert_eq!((report.sent, report.pulled, report.errors), (0, 0, 0));
+
+    server_a.abort();
+    server_b.abort();
+}
+
+#[tokio::test]
+#[serial]
+async fn pulled_articles_are_validated_like_transit_articles() {
+    let auth = common::create_test_auth().await;
+    let local: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    let remote: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    local.add_group("misc.test", false).await.unwrap();
+    local.add_group("misc.moderated", true).await.unwrap();
+    for group in ["misc.test", "misc.moderated"] {
+        remote.add_group(group, false).await.unwrap();
+    }
+    for (id, group, path) in [
+        ("<plain@test>", "misc.test", "origin!not-for-mail"),
+        ("<unapproved@test>", "misc.moderated", "origin!not-for-mail"),
+        ("<looped@test>", "misc.test", "origin!A!not-for-mail"),
+    ] {
+        common::store_test_article(
+            &*remote,
+            &format!(
+                "Message-ID: {id}\r\nNewsgroups: {group}\r\nFrom: a@test\r\n\
+                 Subject: pulled\r\nPath: {path}\r\n\r\nbody\r\n"
+            ),
+        )
+        .await;
+    }
+
+    let cfg: Config = toml::from_str("addr=\":119\"\nsite_name='B'").unwrap();
+    let (addr, pem, server) = common::start_tls_server_loop(remote, auth, cfg).await;
+    let ca_file = NamedTempFile::new().unwrap();
+    fs::write(ca_file.path(), pem).unwrap();
+    unsafe { std::env::set_var("SSL_CERT_FILE", ca_file.path()) };
+
+    let sitename = format!("localhost:{}", addr.port());
+    let peer = PeerConfig {
+        sitename: sitename.clone(),
+        filter: PeerFilter {
+            patterns: vec!["misc.*".into()],
+            ..PeerFilter::default()
+        },
+        sync_schedule: None,
+        mode: PeerMode::Pull,
+        transport: PeerTransport::default(),
+    };
+    let db = PeerDb::new("sqlite::memory:").await.unwrap();
+    db.sync_config(&[sitename]).await.unwrap();
+    let config = site("A");
+    let queue = workers(&local, &config).await;
+
+    let report = sync_peer(&peer, &db, &local, &queue, &config).await;
+    assert_eq!((report.pulled, report.errors), (2, 0));
+    drained(&queue).await;
+
+    let plain = local
+        .get_article_by_id("<plain@test>")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        common::get_header(&plain, "Path").as_deref(),
+        Some("A!origin!not-for-mail")
+    );
+    for id in ["<unapproved@test>", "<looped@test>"] {
+        assert!(local.get_article_by_id(id).await.unwrap().is_none(), "{id}");
+        assert!(local.in_history(id).await.unwrap(), "{id}");
+    }
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn peer_db_keeps_stream_backlog() {
+    let db_file = NamedTempFile::new().unwrap();
+    let db_path = format!("sqlite://{}", db_file.path().display());
+    let db = PeerDb::new(&db_path).await.unwrap();
+    db.sync_config(&["a".into(), "b".into()]).await.unwrap();
+    for id in ["<1@test>", "<2@test>", "<1@test>"] {
+        db.push_backlog("a", id).await.unwrap();
+    }
+    db.push_backlog("b", "<1@test>").await.unwrap();
+
+    // The backlog survives a restart
+    let db = PeerDb::new(&db_path).await.unwrap();
+    assert_eq!(db.backlog_len("a").await.unwrap(), 2);
+    assert_eq!(
+        db.due_backlog("a", 10).await.unwrap(),
+        vec!["<1@test>", "<2@test>"]
+    );
+
+    let later = chrono::Utc::now() + chrono::Duration::seconds(60);
+    db.defer_backlog("a", "<1@test>", later).await.unwrap();
+    assert_eq!(db.due_backlog("a", 10).await.unwrap(), vec!["<2@test>"]);
+    db.remove_backlog("a", "<2@test>").await.unwrap();
+    assert!(db.due_backlog("a", 10).await.unwrap().is_empty());
+    assert_eq!(db.backlog_len("a").await.unwrap(), 1);
+
+    // Removing a peer drops its backlog
+    db.sync_config(&["a".into()]).await.unwrap();
+    assert_eq!(db.backlog_len("b").await.unwrap(), 0);
+}
+
+#[tokio::test]
+#[serial]
+async fn streaming_feed_delivers_backlog() {
+    let auth = Arc::new(
+        renews::auth::sqlite::SqliteAuth::new("sqlite::memory:")
+            .await
+            .unwrap(),
+    );
+    let storage_a: Arc<dyn Storage> =
+        Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    let storage_b: Arc<dyn Storage> =
+        Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage_a.add_group("misc.test", false).await.unwrap();
+    storage_b.add_group("misc.test", false).await.unwrap();
+    let article = |id: &str| {
+        format!(
+            "Message-ID: <{id}@test>\r\nNewsgroups: misc.test\r\nFrom: a@test\r\n\
+             Subject: {id}\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\
+             Path: A!not-for-mail\r\n\r\nbody\r\n"
+        )
+    };
+    let mut articles = Vec::new();
+    for id in ["s1", "s2", "s3"] {
+        articles.push(common::store_test_article(&*storage_a, &article(id)).await);
+    }
+    // B already has one of them
+    common::store_test_article(&*storage_b, &article("s2")).await;
+    auth.add_user("feeder", "pass").await.unwrap();
+
+    let cfg_b: renews::config::Config = toml::from_str("addr=\":119\"\nsite_name='B'").unwrap();
+    let (addr_b, pem_b, server_b) =
+        common::start_tls_server_loop(storage_b.clone(), auth.clone(), cfg_b).await;
+    let ca_file = NamedTempFile::new().unwrap();
+    fs::write(ca_file.path(), pem_b).unwrap();
+    unsafe { std::env::set_var("SSL_CERT_FILE", ca_file.path()) };
+
+    let name_b = format!("localhost:{}", addr_b.port());
+    let cfg_a: renews::config::Config = toml::from_str(&format!(
+        "addr=\":119\"\nsite_name='A'\n\
+         [[peers]]\nsitename='{name_b}'\npatterns=['misc.*']\nstream=true\nstream_window=2\n\
+         username='feeder'\npassword='pass'"
+    ))
+    .unwrap();
+    let db = PeerDb::new("sqlite::memory:").await.unwrap();
+    db.sync_config(std::slice::from_ref(&name_b)).await.unwrap();
+    let feeder = Feeder::new(db.clone(), storage_a.clone());
+    feeder.update(&cfg_a);
+    for article in &articles {
+        feeder.offer(article).await;
+    }
+
+    for _ in 0..100 {
+        if db.backlog_len(&name_b).await.unwrap() == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(db.backlog_len(&name_b).await.unwrap(), 0);
+    for id in ["<s1@test>", "<s2@test>", "<s3@test>"] {
+        assert!(storage_b.get_article_by_id(id).await.unwrap().is_some());
+        assert!(db.peer_has(&name_b, id).await.unwrap());
+    }
+
+    // Articles the peer is known to have are not queued again
+    feeder.offer(&articles[0]).await;
+    assert_eq!(db.backlog_len(&name_b).await.unwrap(), 0);
+
+    drop(feeder);
+    server_b.abort();
+}
+
+/// Greet a client as a peer would and answer its commands with `replies` in
+/// turn, returning the commands received.
+async fn scripted_peer<S>(stream: S, replies: &[&str]) -> Vec<String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    writer.write_all(b"200 ready\r\n").await.unwrap();
+    let mut commands = Vec::new();
+    for reply in replies {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.unwrap() == 0 {
+            break;
+        }
+        commands.push(line.trim_end().to_string());
+        writer
+            .write_all(format!("{reply}\r\n").as_bytes())
+            .await
+            .unwrap();
+    }
+    commands
+}
+
+/// The configuration of a site named `site_name`.
+fn site(site_name: &str) -> Arc<RwLock<Config>> {
+    let mut cfg = common::create_minimal_config();
+    cfg.site_name = site_name.to_string();
+    Arc::new(RwLock::new(cfg))
+}
+
+/// A queue whose workers validate articles and store them in `storage`.
+async fn workers(storage: &Arc<dyn Storage>, config: &Arc<RwLock<Config>>) -> ArticleQueue {
+    common::create_test_queue_with_workers(
+        storage.clone(),
+        common::create_test_auth().await,
+        config.clone(),
+    )
+    .await
+}
+
+/// Wait until the workers are done with every queued article.
+async fn drained(queue: &ArticleQueue) {
+    while !queue.is_idle() {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+/// A storage holding one article in misc.test.
+async fn storage_with_article(id: &str) -> Arc<dyn Storage> {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("misc.test", false).await.unwrap();
+    common::store_test_article(
+        &*storage,
+        &format!(
+            "Message-ID: {id}\r\nNewsgroups: misc.test\r\nFrom: a@test\r\n\
+             Subject: hello\r\nPath: A!not-for-mail\r\n\r\nbody\r\n"
+        ),
+    )
+    .await;
+    storage
+}
+
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = STANDARD.encode(der);
+    let mut text = format!("-----BEGIN {label}-----\n");
+    for chunk in encoded.as_bytes().chunks(64) {
+        text.push_str(std::str::from_utf8(chunk).unwrap());
+        text.push('\n');
+    }
+    text.push_str(&format!("-----END {label}-----\n"));
+    text
+}
+
+#[tokio::test]
+async fn plain_peer_uses_configured_credentials() {
+    let storage = storage_with_article("<p1@test>").await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        scripted_peer(
+            sock,
+            &["381 password required", "281 accepted", "435 not wanted"],
+        )
+        .await
+    });
+
+    // The configured credentials replace those in the sitename
+    let sitename = format!("old:creds@127.0.0.1:{}", addr.port());
+    let peer = PeerConfig {
+        sitename: sitename.clone(),
+        filter: PeerFilter {
+            patterns: vec!["misc.*".into()],
+            ..PeerFilter::default()
+        },
+        sync_schedule: None,
+        mode: PeerMode::Push,
+        transport: PeerTransport {
+            tls: false,
+            username: Some("feed".into()),
+            password: Some("secret".into()),
+            ..PeerTransport::default()
+        },
+    };
+    let db = PeerDb::new("sqlite::memory:").await.unwrap();
+    db.sync_config(&[sitename]).await.unwrap();
+
+    let report = sync_peer(
+        &peer,
+        &db,
+        &storage,
+        &common::create_test_queue(),
+        &site("A"),
+    )
+    .await;
+    assert_eq!((report.sent, report.errors), (1, 0));
+    assert_eq!(
+        server.await.unwrap(),
+        vec![
+            "AUTHINFO USER feed",
+            "AUTHINFO PASS secret",
+            "IHAVE <p1@test>"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn peer_filter_limits_articles_sent() {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    for group in ["misc.test", "misc.binaries", "alt.spam"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+    let big = "x".repeat(2048);
+    for (id, groups, extra, body) in [
+        ("<wanted@test>", "misc.test", "", "body"),
+        ("<binary@test>", "misc.binaries", "", "body"),
+        ("<spam@test>", "misc.test,alt.spam", "", "body"),
+        (
+            "<local@test>",
+            "misc.test",
+            "Distribution: local\r\n",
+            "body",
+        ),
+        ("<big@test>", "misc.test", "", big.as_str()),
+    ] {
+        common::store_test_article(
+            &*storage,
+            &format!(
+                "Message-ID: {id}\r\nNewsgroups: {groups}\r\nFrom: a@test\r\n\
+                 Subject: hello\r\n{extra}Path: A!not-for-mail\r\n\r\n{body}\r\n"
+            ),
+        )
+        .await;
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        scripted_peer(sock, &["435 not wanted"]).await
+    });
+
+    let sitename = format!("127.0.0.1:{}", addr.port());
+    let peer = PeerConfig {
+        sitename: sitename.clone(),
+        filter: PeerFilter {
+            patterns: vec!["*".into(), "!misc.binaries".into(), "@alt.spam".into()],
+            distributions: vec!["*".into(), "!local".into()],
+            max_size_bytes: Some(1024),
+        },
+        sync_schedule: None,
+        mode: PeerMode::Push,
+        transport: PeerTransport {
+            tls: false,
+            ..PeerTransport::default()
+        },
+    };
+    let db = PeerDb::new("sqlite::memory:").await.unwrap();
+    db.sync_config(&[sitename]).await.unwrap();
+
+    let report = sync_peer(
+        &peer,
+        &db,
+        &storage,
+        &common::create_test_queue(),
+        &site("A"),
+    )
+    .await;
+    assert_eq!((report.sent, report.skipped, report.errors), (1, 3, 0));
+    assert_eq!(server.await.unwrap(), vec!["IHAVE <wanted@test>"]);
+
+    // The refusal is counted against the peer
+    let stats = db.peer_stats().await.unwrap();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(
+        stats[0].traffic,
+        PeerTraffic {
+            offered: 1,
+            refused: 1,
+            ..PeerTraffic::default()
+        }
+    );
+    assert!(stats[0].last_contact.is_some());
+}
+
+#[tokio::test]
+async fn list_peers_shows_statistics_to_admins() {
+    let db_file = NamedTempFile::new().unwrap();
+    let db_path = format!("sqlite://{}", db_file.path().display());
+    let db = PeerDb::new(&db_path).await.unwrap();
+    db.sync_config(&["feed.example".into(), "quiet.example".into()])
+        .await
+        .unwrap();
+    let mut traffic = PeerTraffic {
+        offered: 3,
+        ..PeerTraffic::default()
+    };
+    traffic.count(Transfer::Accepted, 1200);
+    traffic.count(Transfer::Rejected, 0);
+    traffic.count(Transfer::Deferred, 0);
+    db.record_traffic("feed.example", &traffic).await.unwrap();
+    let stats = db.peer_stats().await.unwrap();
+    let last_contact = stats[0]
+        .last_contact
+        .unwrap()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+    let (storage, auth) = common::setup().await;
+    auth.add_user("admin", "pass").await.unwrap();
+    auth.add_admin("admin", "key").await.unwrap();
+    auth.add_user("user", "pass").await.unwrap();
+    let mut cfg = common::create_minimal_config();
+    cfg.peer_db_path = db_path;
+
+    ClientMock::with_auth("user", "pass")
+        .expect("LIST PEERS", "502 administrator access required")
+        .run_with_cfg_tls(cfg.clone(), storage.clone(), auth.clone())
+        .await;
+    ClientMock::with_auth("admin", "pass")
+        .expect_multi(
+            "LIST PEERS",
+            vec![
+                "215 peer statistics follow".to_string(),
+                format!("feed.example 3 1 0 1 1 1200 {last_contact}"),
+                "quiet.example 0 0 0 0 0 0 -".to_string(),
+                ".".to_string(),
+            ],
+        )
+        .run_with_cfg_tls(cfg, storage, auth)
+        .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn tls_peer_presents_client_certificate() {
+    let storage = storage_with_article("<c1@test>").await;
+    let (server_cert, server_key, server_pem) = common::generate_self_signed_cert();
+    let (ca_pem, client_cert, client_key) = common::generate_client_cert("feeder");
+
+    // The peer refuses connections without a certificate from its CA
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_parsable_certificates(&rustls_pemfile::certs(&mut ca_pem.as_bytes()).unwrap());
+    let tls_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(rustls::server::AllowAnyAuthenticatedClient::new(roots).boxed())
+        .with_single_cert(vec![server_cert], server_key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        let stream = acceptor.accept(sock).await.unwrap();
+        let names = renews::client_cert::peer_names(&stream);
+        (names, scripted_peer(stream, &["435 not wanted"]).await)
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    let ca_file = dir.path().join("ca.pem");
+    let cert_file = dir.path().join("client.pem");
+    let key_file = dir.path().join("client.key");
+    fs::write(&ca_file, server_pem).unwrap();
+    fs::write(&cert_file, pem("CERTIFICATE", &client_cert.0)).unwrap();
+    fs::write(&key_file, pem("PRIVATE KEY", &client_key.0)).unwrap();
+    unsafe { std::env::set_var("SSL_CERT_FILE", &ca_file) };
+
+    let sitename = format!("localhost:{}", addr.port());
+    let peer = PeerConfig {
+        sitename: sitename.clone(),
+        filter: PeerFilter {
+            patterns: vec!["misc.*".into()],
+            ..PeerFilter::default()
+        },
+        sync_schedule: None,
+        mode: PeerMode::Push,
+        transport: PeerTransport {
+            tls_client_cert: Some(cert_file.display().to_string()),
+            tls_client_key: Some(key_file.display().to_string()),
+            ..PeerTransport::default()
+        },
+    };
+    let db = PeerDb::new("sqlite::memory:").await.unwrap();
+    db.sync_config(&[sitename]).await.unwrap();
+
+    let report = sync_peer(
+        &peer,
+        &db,
+        &storage,
+        &common::create_test_queue(),
+        &site("A"),
+    )
+    .await;
+    assert_eq!((report.sent, report.errors), (1, 0));
+    let (names, commands) = server.await.unwrap();
+    assert_eq!(names, vec!["feeder"]);
+    assert_eq!(commands, vec!["IHAVE <c1@test>"]);
+}
+
+#[tokio::test]
+async fn transit_articles_naming_us_in_path_are_refused() {
+    let (storage, auth) = common::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    let cfg: renews::config::Config = toml::from_str(
+        r#"
+addr = ":119"
+site_name = "news.example"
+path_aliases = ["old.example"]
+"#,
+    )
+    .unwrap();
+    let looped = concat!(
+        "Path: peer.example!OLD.example!origin!not-for-mail\r\n",
+        "From: a@test\r\n",
+        "Newsgroups: misc.test\r\n",
+        "Subject: loop\r\n",
+        "Message-ID: <loop@test>\r\n",
+        "\r\n",
+        "body\r\n",
+        "."
+    );
+    let streamed = concat!(
+        "TAKETHIS <streamed@test>\r\n",
+        "Path: peer.example!news.example!not-for-mail\r\n",
+        "From: a@test\r\n",
+        "Newsgroups: misc.test\r\n",
+        "Subject: loop\r\n",
+        "Message-ID: <streamed@test>\r\n",
+        "\r\n",
+        "body\r\n",
+        "."
+    );
+    ClientMock::new()
+        .expect("IHAVE <loop@test>", "335 Send it; end with <CR-LF>.<CR-LF>")
+        .expect_request_multi(common::request_lines(looped), vec!["437 article rejected"])
+        .expect("IHAVE <loop@test>", "435 article not wanted")
+        .expect_request_multi(common::request_lines(streamed), vec!["439 <streamed@test>"])
+        .run_as_peer_with_cfg(cfg, storage.clone(), auth)
+        .await;
+    assert!(
+        storage
+            .get_article_by_id("<loop@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        storage
+            .get_article_by_id("<streamed@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn accepted_transit_articles_gain_our_path_element() {
+    let (storage, auth) = common::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    let cfg: renews::config::Config = toml::from_str(
+        r#"
+addr = ":119"
+site_name = "news.example"
+"#,
+    )
+    .unwrap();
+    let article = concat!(
+        "TAKETHIS <transit@test>\r\n",
+        "Path: peer.example!origin!not-for-mail\r\n",
+        "From: a@test\r\n",
+        "Newsgroups: misc.test\r\n",
+        "Subject: transit\r\n",
+        "Message-ID: <transit@test>\r\n",
+        "\r\n",
+        "body\r\n",
+        "."
+    );
+    ClientMock::new()
+        .expect_request_multi(common::request_lines(article), vec!["239 <transit@test>"])
+        .run_as_peer_with_cfg(cfg, storage.clone(), auth)
+        .await;
+    let stored = storage
+        .get_article_by_id("<transit@test>")
+        .await
+        .unwrap()
+        .unwrap();
+    let path = stored
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Path"))
+        .map(|(_, v)| v.as_str());
+    assert_eq!(path, Some("news.example!peer.example!origin!not-for-mail"));
+}
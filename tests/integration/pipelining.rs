@@ -0,0 +1,111 @@
+use crate::utils;
+use renews::config::Config;
+use renews::handle_client;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+use tokio::sync::RwLock;
+
+/// Server end of a connection that counts the writes made to it.
+struct CountingStream {
+    inner: DuplexStream,
+    writes: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Run a session over a counting stream, sending `script` in one segment,
+/// and return what the client received and the number of server writes.
+async fn pipelined_session(script: &[u8]) -> (String, usize) {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc.test", false).await.unwrap();
+    for n in 1..=3 {
+        utils::store_test_article(
+            &*storage,
+            &format!(
+                "Message-ID: <p{n}@test>\r\nNewsgroups: misc.test\r\nFrom: a@test\r\n\
+                 Subject: pipelined {n}\r\n\r\nBody {n}\r\n"
+            ),
+        )
+        .await;
+    }
+    let cfg: Config = toml::from_str("addr=\":119\"").unwrap();
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &cfg);
+
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let writes = Arc::new(AtomicUsize::new(0));
+    let server = CountingStream {
+        inner: server,
+        writes: writes.clone(),
+    };
+    client.write_all(script).await.unwrap();
+    client.shutdown().await.unwrap();
+    let task = tokio::spawn(handle_client(
+        server,
+        storage,
+        auth,
+        Arc::new(RwLock::new(cfg)),
+        false,
+        utils::create_test_queue(),
+        usage_tracker,
+    ));
+
+    let mut received = String::new();
+    client.read_to_string(&mut received).await.unwrap();
+    task.await.unwrap().unwrap();
+    (received, writes.load(Ordering::SeqCst))
+}
+
+#[tokio::test]
+async fn pipelined_commands_are_answered_in_one_write() {
+    let (received, writes) =
+        pipelined_session(b"GROUP misc.test\r\nOVER 1-3\r\nHDR Subject 1-3\r\nDATE\r\nQUIT\r\n")
+            .await;
+
+    let codes: Vec<&str> = received
+        .lines()
+        .filter(|l| l.len() > 3 && l.as_bytes()[3] == b' ' && l[..3].parse::<u16>().is_ok())
+        .map(|l| &l[..3])
+        .collect();
+    assert_eq!(codes, ["201", "211", "224", "225", "111", "205"]);
+    assert!(received.contains("pipelined 3"));
+    // The greeting goes out before the first read, then one write per batch
+    assert_eq!(writes, 2);
+}
+
+#[tokio::test]
+async fn session_ending_without_quit_still_sends_responses() {
+    let (received, _) = pipelined_session(b"GROUP misc.test\r\nDATE\r\n").await;
+    assert!(received.contains("211 3 1 3 misc.test"));
+    assert!(received.lines().last().unwrap().starts_with("111 "));
+}
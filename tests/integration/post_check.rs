@@ -0,0 +1,51 @@
+use crate::utils::{self, ClientMock};
+
+const SEND_HEADERS: &str = "345 send headers to be checked. End with <CR-LF>.<CR-LF>";
+
+fn headers(groups: &str) -> String {
+    format!("Newsgroups: {groups}\r\nFrom: user@example.com\r\nSubject: big upload\r\n.")
+}
+
+#[tokio::test]
+async fn xpostcheck_reports_the_fate_of_a_post() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    storage.add_group("mod.test", true).await.unwrap();
+    auth.add_user("user", "pass").await.unwrap();
+
+    let mut cfg = utils::create_minimal_config();
+    cfg.max_message_bytes = Some(1_000_000);
+
+    ClientMock::new()
+        .expect("XPOSTCHECK 100", "480 authentication required")
+        .expect("AUTHINFO USER user", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect("XPOSTCHECK 500000", SEND_HEADERS)
+        .expect(&headers("misc"), "244 article would be accepted")
+        .expect("XPOSTCHECK", SEND_HEADERS)
+        .expect(
+            &headers("mod.test"),
+            "245 article would be held for moderation",
+        )
+        .expect("XPOSTCHECK 100", SEND_HEADERS)
+        .expect(&headers("no.such.group"), "441 group does not exist")
+        .expect("XPOSTCHECK 2000000", SEND_HEADERS)
+        .expect(&headers("misc"), "441 article too large")
+        .expect("XPOSTCHECK 100", SEND_HEADERS)
+        .expect("Newsgroups: misc\r\n.", "441 missing required headers")
+        .expect("XPOSTCHECK big", "501 invalid number: big")
+        .run_with_cfg_tls(cfg, storage.clone(), auth)
+        .await;
+
+    // Nothing was posted
+    assert!(
+        utils::collect_article_numbers(&*storage, "misc")
+            .await
+            .is_empty()
+    );
+    assert!(
+        utils::collect_article_numbers(&*storage, "mod.test")
+            .await
+            .is_empty()
+    );
+}
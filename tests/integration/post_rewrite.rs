@@ -0,0 +1,71 @@
+use crate::utils::{self, ClientMock};
+use renews::handlers::utils::get_header_value;
+use std::time::Duration;
+
+const SUBJECT: &str = "a subject line that is long enough to need folding when it is sent to peers";
+
+#[tokio::test]
+async fn post_rewrites_headers_and_folds_output() {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    auth.add_user("user", "pass").await.unwrap();
+
+    ClientMock::new()
+        .expect("AUTHINFO USER user", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect(
+            "POST",
+            "340 send article to be posted. End with <CR-LF>.<CR-LF>",
+        )
+        .expect(
+            &format!(
+                "Message-ID: <rw@test>\r\nNewsgroups: misc\r\nFrom: user@example.com\r\n\
+                 Subject: {SUBJECT}\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\
+                 Xref: elsewhere misc:99\r\nNNTP-Posting-Host: client.example\r\n\
+                 \r\nline one\r\nline two\r\n."
+            ),
+            "240 article received",
+        )
+        .run_tls(storage.clone(), auth.clone())
+        .await;
+
+    let mut article = None;
+    for _ in 0..50 {
+        article = storage.get_article_by_id("<rw@test>").await.unwrap();
+        if article.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let article = article.expect("article not stored");
+    assert_eq!(get_header_value(&article, "Xref"), None);
+    assert_eq!(get_header_value(&article, "NNTP-Posting-Host"), None);
+    assert_eq!(get_header_value(&article, "Lines").as_deref(), Some("2"));
+    assert_eq!(
+        get_header_value(&article, "Path").as_deref(),
+        Some("localhost!not-for-mail")
+    );
+    assert_eq!(
+        get_header_value(&article, "Subject").as_deref(),
+        Some(SUBJECT)
+    );
+
+    ClientMock::new()
+        .expect_multi(
+            "HEAD <rw@test>",
+            vec![
+                "221 0 <rw@test> article headers follow",
+                "Message-ID: <rw@test>",
+                "Newsgroups: misc",
+                "From: user@example.com",
+                "Subject: a subject line that is long enough to need folding when it is sent to",
+                " peers",
+                "Date: Wed, 05 Oct 2022 00:00:00 GMT",
+                "Lines: 2",
+                "Path: localhost!not-for-mail",
+                ".",
+            ],
+        )
+        .run(storage, auth)
+        .await;
+}
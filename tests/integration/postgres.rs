@@ -0,0 +1,47 @@
+//! PostgreSQL databases for tests of the PostgreSQL storage.
+//!
+//! Tests using them need a server: set `RENEWS_TEST_POSTGRES_URL` to the URL
+//! of one without a database name, e.g. `postgres://postgres@localhost`, and
+//! a database is created for each test and dropped after it. Without the
+//! variable they pass without checking anything, as there is no server in
+//! the default test environment.
+
+use renews::storage::{self, DynStorage};
+use sqlx::{Connection, Executor, PgConnection};
+
+/// A database created for one test and dropped after it.
+pub(crate) struct Database {
+    server: String,
+    name: String,
+    pub(crate) storage: DynStorage,
+}
+
+impl Database {
+    /// A fresh database on the test server, or `None` without one.
+    pub(crate) async fn create() -> Option<Self> {
+        let server = std::env::var("RENEWS_TEST_POSTGRES_URL").ok()?;
+        let name = format!("renews_test_{}", uuid::Uuid::new_v4().simple());
+        let mut conn = PgConnection::connect(&format!("{server}/postgres"))
+            .await
+            .unwrap();
+        conn.execute(format!("CREATE DATABASE {name}").as_str())
+            .await
+            .unwrap();
+        let storage = storage::open(&format!("{server}/{name}")).await.unwrap();
+        Some(Self {
+            server,
+            name,
+            storage,
+        })
+    }
+
+    pub(crate) async fn drop(self) {
+        drop(self.storage);
+        let mut conn = PgConnection::connect(&format!("{}/postgres", self.server))
+            .await
+            .unwrap();
+        conn.execute(format!("DROP DATABASE {} WITH (FORCE)", self.name).as_str())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Bulk loading of article batches with `COPY` on PostgreSQL.
+//!
+//! These tests need a server; see [`Database`] for how one is given.
+
+use crate::postgres::Database;
+use futures_util::TryStreamExt;
+use renews::Message;
+use renews::storage::{DynStorage, GroupWatermarks};
+
+fn article(n: usize, groups: &str, extra: &str) -> Message {
+    let text = format!(
+        "Message-ID: <{n}@copy.test>\r\nNewsgroups: {groups}\r\nFrom: a@test\r\n\
+         Subject: tab\there back\\slash {n}\r\nReferences: <{}@copy.test>\r\n\
+         Date: Mon, 1 Jan 2024 12:00:00 +0000\r\n\r\n\
+         Body with\ttab\r\nand back\\slash and \\N\r\n{extra}",
+        n.saturating_sub(1)
+    );
+    renews::parse_message(&text).unwrap().1
+}
+
+/// Everything stored for the articles of `group`, as the backend reads it.
+async fn snapshot(storage: &DynStorage, group: &str) -> Vec<String> {
+    let numbers: Vec<u64> = storage
+        .list_article_numbers(group)
+        .try_collect()
+        .await
+        .unwrap();
+    let mut lines = vec![format!(
+        "{:?}",
+        storage.get_group_watermarks(group).await.unwrap()
+    )];
+    lines.extend(
+        storage
+            .get_overview_range(group, 0, u64::MAX)
+            .await
+            .unwrap(),
+    );
+    for n in numbers {
+        let article = storage
+            .get_article_by_number(group, n)
+            .await
+            .unwrap()
+            .unwrap();
+        let id = renews::handlers::utils::get_header_value(&article, "Message-ID").unwrap();
+        lines.push(format!(
+            "{n} {:?} {:?} {:?} {:?} {:?}",
+            article.headers,
+            article.body,
+            storage.get_message_size(&id).await.unwrap(),
+            storage.get_article_numbers(&id).await.unwrap(),
+            storage.list_replies(group, &id).await.unwrap(),
+        ));
+    }
+    lines
+}
+
+#[tokio::test]
+async fn copied_batches_match_row_inserts() {
+    let (Some(copied), Some(inserted)) = (Database::create().await, Database::create().await)
+    else {
+        return;
+    };
+    for db in [&copied, &inserted] {
+        for group in ["misc.one", "misc.two"] {
+            db.storage.add_group(group, false).await.unwrap();
+        }
+    }
+    // Some cross-posted, one a duplicate of an earlier one
+    let mut batch: Vec<Message> = (1..=24)
+        .map(|n| {
+            let groups = if n % 3 == 0 {
+                "misc.one,misc.two"
+            } else {
+                "misc.one"
+            };
+            article(n, groups, "")
+        })
+        .collect();
+    batch.push(article(5, "misc.one", ""));
+
+    copied.storage.store_articles(&batch).await.unwrap();
+    for article in &batch {
+        inserted.storage.store_article(article).await.unwrap();
+    }
+    for group in ["misc.one", "misc.two"] {
+        assert_eq!(
+            snapshot(&copied.storage, group).await,
+            snapshot(&inserted.storage, group).await,
+            "{group}"
+        );
+    }
+    copied.drop().await;
+    inserted.drop().await;
+}
+
+#[tokio::test]
+async fn concurrent_batches_take_distinct_numbers() {
+    let Some(db) = Database::create().await else {
+        return;
+    };
+    db.storage.add_group("misc", false).await.unwrap();
+    db.storage
+        .store_article(&article(0, "misc", ""))
+        .await
+        .unwrap();
+
+    let tasks: Vec<_> = (0..4)
+        .map(|task| {
+            let storage = db.storage.clone();
+            tokio::spawn(async move {
+                let batch: Vec<Message> = (1..=20)
+                    .map(|n| article(task * 100 + n, "misc", ""))
+                    .collect();
+                storage.store_articles(&batch).await
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+
+    let numbers: Vec<u64> = db
+        .storage
+        .list_article_numbers("misc")
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(numbers, (1..=81).collect::<Vec<_>>());
+    assert_eq!(
+        db.storage.get_group_watermarks("misc").await.unwrap(),
+        Some(GroupWatermarks {
+            count: 81,
+            low: 1,
+            high: 81,
+        })
+    );
+    db.drop().await;
+}
+
+#[tokio::test]
+async fn refused_batches_leave_nothing_behind() {
+    let Some(db) = Database::create().await else {
+        return;
+    };
+    db.storage.add_group("misc", false).await.unwrap();
+    // PostgreSQL text cannot hold NUL, whether copied or inserted
+    let mut batch: Vec<Message> = (1..=19).map(|n| article(n, "misc", "")).collect();
+    batch.push(article(20, "misc", "nul\0byte\r\n"));
+
+    assert!(db.storage.store_articles(&batch).await.is_err());
+    assert_eq!(
+        db.storage.get_group_watermarks("misc").await.unwrap(),
+        Some(GroupWatermarks::default())
+    );
+
+    // The importer stores the others one at a time
+    let messages = batch.iter().map(|article| {
+        let mut text = String::new();
+        for (name, value) in &article.headers {
+            text.push_str(&format!("{name}: {value}\r\n"));
+        }
+        text.push_str("\r\n");
+        text.push_str(&article.body);
+        Ok(text)
+    });
+    let summary = renews::import::import_articles(db.storage.as_ref(), messages)
+        .await
+        .unwrap();
+    assert_eq!((summary.imported, summary.skipped), (19, 1));
+    assert_eq!(
+        db.storage
+            .get_group_watermarks("misc")
+            .await
+            .unwrap()
+            .map(|marks| marks.count),
+        Some(19)
+    );
+    db.drop().await;
+}
@@ -0,0 +1,211 @@
+use futures_util::TryStreamExt;
+use renews::replication::{self, Change, ReplicationServer};
+use renews::storage::Storage;
+use renews::storage::replicated::ReplicatedStorage;
+use renews::storage::sqlite::SqliteStorage;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::utils::{self, store_test_article};
+
+const SECRET: &str = "standby secret";
+
+/// Serve the replication log of a new primary, returning its storage and
+/// the address standbys follow.
+async fn start_primary() -> (Arc<dyn Storage>, String) {
+    let storage: Arc<dyn Storage> =
+        Arc::new(ReplicatedStorage::new(utils::create_test_storage().await));
+    let listener = replication::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let server = Arc::new(ReplicationServer::new(storage.clone(), SECRET.into()));
+    tokio::spawn(server.serve(listener));
+    (storage, addr)
+}
+
+/// Send a FOLLOW request and return the first line of the reply.
+async fn follow_reply(addr: &str, request: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).await.unwrap();
+    line
+}
+
+#[tokio::test]
+async fn standby_follows_articles_groups_and_deletions() {
+    let (primary, addr) = start_primary().await;
+    primary
+        .add_group_with_description("misc.test", false, "Testing")
+        .await
+        .unwrap();
+    primary.add_group("misc.gone", false).await.unwrap();
+    store_test_article(
+        &*primary,
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\nSubject: one\r\n\r\nBody\r\n",
+    )
+    .await;
+    store_test_article(
+        &*primary,
+        "Message-ID: <2@test>\r\nNewsgroups: misc.test\r\nSubject: two\r\n\r\nBody\r\n",
+    )
+    .await;
+
+    let standby = utils::create_test_storage().await;
+    tokio::spawn(replication::follow(
+        standby.clone(),
+        addr.clone(),
+        SECRET.into(),
+    ));
+
+    // Changes made while the standby is connected follow too
+    primary
+        .set_group_moderated("misc.test", true)
+        .await
+        .unwrap();
+    primary.remove_group("misc.gone").await.unwrap();
+    primary.delete_article_by_id("<1@test>").await.unwrap();
+    primary
+        .set_article_pinned("misc.test", "<2@test>", true)
+        .await
+        .unwrap();
+
+    let last = primary.last_change_seq().await.unwrap();
+    assert_eq!(last, 8);
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while standby.last_change_seq().await.unwrap() < last {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("standby caught up");
+
+    let groups: Vec<(String, String)> = standby
+        .list_groups_with_descriptions()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(groups, vec![("misc.test".into(), "Testing".into())]);
+    assert!(standby.is_group_moderated("misc.test").await.unwrap());
+    assert!(
+        standby
+            .get_article_by_id("<1@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(
+        standby.get_article_numbers("<2@test>").await.unwrap(),
+        primary.get_article_numbers("<2@test>").await.unwrap()
+    );
+    let pinned: Vec<(String, u64, String)> =
+        standby.list_pinned_articles().try_collect().await.unwrap();
+    assert_eq!(pinned, vec![("misc.test".into(), 2, "<2@test>".into())]);
+}
+
+#[tokio::test]
+async fn primary_refuses_wrong_secret_and_lost_changes() {
+    let (primary, addr) = start_primary().await;
+    primary.add_group("misc.test", false).await.unwrap();
+    primary.add_group("misc.other", false).await.unwrap();
+
+    assert_eq!(
+        follow_reply(&addr, "FOLLOW 0 guess\n").await,
+        "error authentication failed\n"
+    );
+    assert_eq!(
+        follow_reply(&addr, &format!("FOLLOW 0 {SECRET}\n")).await,
+        "ok\n"
+    );
+
+    // Only the newest change survives purging, so a new standby cannot
+    // start from an empty store
+    primary
+        .purge_changes_before(chrono::Utc::now() + chrono::Duration::hours(1))
+        .await
+        .unwrap();
+    let mut stream = TcpStream::connect(&addr).await.unwrap();
+    stream
+        .write_all(format!("FOLLOW 0 {SECRET}\n").as_bytes())
+        .await
+        .unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+    let error = lines.next_line().await.unwrap().unwrap();
+    assert!(
+        error.starts_with("error change 1 is no longer in the replication log"),
+        "{error}"
+    );
+}
+
+async fn add_groups(storage: &ReplicatedStorage, prefix: &str) {
+    for i in 0..10 {
+        storage
+            .add_group(&format!("{prefix}.{i}"), false)
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn writers_sharing_a_database_number_changes_in_turn() {
+    // The admin CLI records changes in the database of a running server
+    let dir = tempfile::tempdir().unwrap();
+    let uri = format!("sqlite://{}", dir.path().join("news.db").display());
+    let server = ReplicatedStorage::new(Arc::new(SqliteStorage::new(&uri).await.unwrap()));
+    let cli = ReplicatedStorage::new(Arc::new(SqliteStorage::new(&uri).await.unwrap()));
+
+    tokio::join!(add_groups(&server, "server"), add_groups(&cli, "cli"));
+
+    let seqs: Vec<u64> = server
+        .list_changes_since(0)
+        .map_ok(|(seq, _)| seq)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(seqs, (1..=20).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn standby_keeps_the_insertion_time_of_the_primary() {
+    let (primary, _) = start_primary().await;
+    primary.add_group("misc.test", false).await.unwrap();
+    store_test_article(
+        &*primary,
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\nSubject: one\r\n\r\nBody\r\n",
+    )
+    .await;
+    let changes: Vec<(u64, String)> = primary.list_changes_since(0).try_collect().await.unwrap();
+    let Change::Store { inserted_at, .. } = serde_json::from_str(&changes[1].1).unwrap() else {
+        panic!("expected a stored article, got {}", changes[1].1);
+    };
+    assert!(inserted_at.is_some());
+
+    // Applied a day later, the article is still as old as on the primary
+    let standby = utils::create_test_storage().await;
+    standby.add_group("misc.test", false).await.unwrap();
+    let day_ago = chrono::Utc::now() - chrono::Duration::days(1);
+    let Change::Store { headers, body, .. } = serde_json::from_str(&changes[1].1).unwrap() else {
+        unreachable!();
+    };
+    Change::Store {
+        headers,
+        body,
+        inserted_at: Some(day_ago.timestamp()),
+    }
+    .apply(&*standby)
+    .await
+    .unwrap();
+    standby
+        .purge_group_before("misc.test", day_ago + chrono::Duration::hours(1))
+        .await
+        .unwrap();
+    assert!(
+        standby
+            .get_article_numbers("<1@test>")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
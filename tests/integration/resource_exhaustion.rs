@@ -294,7 +294,7 @@ async fn test_null_bytes_in_article() {
         )
         .expect_request_multi(
             utils::request_lines(article_with_nulls),
-            vec!["240 article received"], // Behavior depends on implementation
+            vec!["441 posting failed"], // NUL bytes are refused while reading
         )
         .expect("QUIT", "205 closing connection")
         .run_tls(storage, auth)
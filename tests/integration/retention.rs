@@ -1,4 +1,5 @@
 use crate::utils::store_test_article;
+use renews::maintenance::run_maintenance;
 use renews::retention::cleanup_expired_articles;
 use renews::{
     config::Config,
@@ -63,3 +64,198 @@ retention_days = 10
             .is_none()
     );
 }
+
+#[tokio::test]
+async fn cleanup_expires_header_keeps_pinned_articles() {
+    use chrono::Duration as ChronoDuration;
+    let cfg: Config = toml::from_str("addr = \":119\"").unwrap();
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("misc", false).await.unwrap();
+    storage.add_group("other", false).await.unwrap();
+    let past = (chrono::Utc::now() - ChronoDuration::days(1)).to_rfc2822();
+    let text =
+        format!("Message-ID: <3@test>\r\nNewsgroups: misc,other\r\nExpires: {past}\r\n\r\nB");
+    store_test_article(&*storage, &text).await;
+    storage
+        .set_article_pinned("other", "<3@test>", true)
+        .await
+        .unwrap();
+    cleanup_expired_articles(&*storage, &cfg).await.unwrap();
+    assert!(
+        storage
+            .get_article_by_id("<3@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[tokio::test]
+async fn expired_group_keeps_its_watermarks() {
+    let cfg: Config = toml::from_str(
+        r#"
+addr = ":119"
+[[group_settings]]
+pattern = "*"
+retention_days = 10
+"#,
+    )
+    .unwrap();
+    let (storage, auth) = crate::utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    let past = (chrono::Utc::now() - chrono::Duration::days(1)).to_rfc2822();
+    for i in 1..=2 {
+        let text =
+            format!("Message-ID: <{i}@test>\r\nNewsgroups: misc\r\nExpires: {past}\r\n\r\nB");
+        store_test_article(&*storage, &text).await;
+    }
+    cleanup_expired_articles(&*storage, &cfg).await.unwrap();
+    crate::utils::ClientMock::new()
+        .expect("GROUP misc", "211 0 3 2 misc")
+        .expect_multi("LISTGROUP misc", vec!["211 0 3 2 misc list follows", "."])
+        .expect_multi(
+            "LIST ACTIVE misc",
+            vec!["215 list of newsgroups follows", "misc 2 3 y", "."],
+        )
+        .run(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn maintenance_purges_orphans_and_shrinks_the_database() {
+    let cfg: Config = toml::from_str("addr = \":119\"").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = format!("sqlite://{}", dir.path().join("news.db").display());
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new(&path).await.unwrap());
+    storage.add_group("misc", false).await.unwrap();
+    let body = "x".repeat(64 * 1024);
+    for i in 0..32 {
+        store_test_article(
+            &*storage,
+            &format!("Message-ID: <{i}@test>\r\nNewsgroups: misc\r\n\r\n{body}"),
+        )
+        .await;
+    }
+    storage.remove_group("misc").await.unwrap();
+    let size = storage.database_size().await.unwrap();
+
+    let reclaimed = run_maintenance(&*storage, &cfg).await.unwrap();
+    // Nearly all of the 2 MiB of bodies comes back; the compacted schema
+    // and statistics may take a few pages of it
+    assert!(reclaimed >= 31 * 64 * 1024, "reclaimed {reclaimed} bytes");
+    assert_eq!(storage.database_size().await.unwrap(), size - reclaimed);
+    assert!(
+        storage
+            .get_article_by_id("<0@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn archive_until_follows_the_longest_group_retention() {
+    use renews::handlers::utils::{add_archive_until_header, get_header_value};
+    let cfg: Config = toml::from_str(
+        r#"
+addr = ":119"
+[[group_settings]]
+group = "short"
+retention_days = 10
+[[group_settings]]
+group = "long"
+retention_days = 30
+"#,
+    )
+    .unwrap();
+    let config = tokio::sync::RwLock::new(cfg);
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    for group in ["short", "long", "kept"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+    let before = chrono::Utc::now() - chrono::Duration::seconds(1);
+    let mut both = store_test_article(
+        &*storage,
+        "Message-ID: <both@test>\r\nNewsgroups: short,long\r\n\r\nB",
+    )
+    .await;
+    add_archive_until_header(&storage, &config, &mut both)
+        .await
+        .unwrap();
+    let until = get_header_value(&both, "X-Archive-Until").unwrap();
+    let until = chrono::DateTime::parse_from_rfc2822(&until).unwrap();
+    assert!(until >= before + chrono::Duration::days(30));
+    assert!(until <= chrono::Utc::now() + chrono::Duration::days(30));
+
+    let mut kept = store_test_article(
+        &*storage,
+        "Message-ID: <kept@test>\r\nNewsgroups: short,kept\r\nX-Archive-Until: forged\r\n\r\nB",
+    )
+    .await;
+    add_archive_until_header(&storage, &config, &mut kept)
+        .await
+        .unwrap();
+    assert_eq!(get_header_value(&kept, "X-Archive-Until"), None);
+
+    storage
+        .set_article_pinned("long", "<both@test>", true)
+        .await
+        .unwrap();
+    add_archive_until_header(&storage, &config, &mut both)
+        .await
+        .unwrap();
+    assert_eq!(get_header_value(&both, "X-Archive-Until"), None);
+}
+
+#[tokio::test]
+async fn list_expire_and_head_show_group_retention() {
+    let cfg: Config = toml::from_str(
+        r#"
+addr = ":119"
+[[group_settings]]
+pattern = "misc.*"
+retention_days = 10
+[[group_settings]]
+group = "misc.keep"
+retention_days = 0
+"#,
+    )
+    .unwrap();
+    let (storage, auth) = crate::utils::setup().await;
+    for group in ["misc.keep", "misc.test", "other"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+    store_test_article(
+        &*storage,
+        "Message-ID: <1@test>\r\nNewsgroups: misc.test\r\nExpires: Mon, 1 Jan 2024 00:00:00 +0000\r\n\r\nB",
+    )
+    .await;
+    crate::utils::ClientMock::new()
+        .expect_multi(
+            "LIST EXPIRE",
+            vec![
+                "215 group retention follows",
+                "misc.keep never",
+                "misc.test 10",
+                "other never",
+                ".",
+            ],
+        )
+        .expect_multi(
+            "LIST EXPIRE misc.t*",
+            vec!["215 group retention follows", "misc.test 10", "."],
+        )
+        .expect_multi(
+            "HEAD <1@test>",
+            vec![
+                "221 0 <1@test> article headers follow",
+                "Message-ID: <1@test>",
+                "Newsgroups: misc.test",
+                "Expires: Mon, 1 Jan 2024 00:00:00 +0000",
+                "X-Archive-Until: Mon, 1 Jan 2024 00:00:00 +0000",
+                ".",
+            ],
+        )
+        .run_with_cfg(cfg, storage, auth)
+        .await;
+}
@@ -1,5 +1,5 @@
 use crate::utils::{collect_groups, get_header, get_message_id, store_test_article};
-use renews::storage::{Storage, sqlite::SqliteStorage};
+use renews::storage::{DynStorage, Storage, sqlite::SqliteStorage};
 
 #[tokio::test]
 async fn store_and_retrieve_article() {
@@ -95,6 +95,46 @@ async fn add_and_list_groups() {
     assert_eq!(groups, vec!["g2".to_string()]);
 }
 
+#[tokio::test]
+async fn group_counts_are_listed_in_one_pass() {
+    use futures_util::TryStreamExt;
+    use renews::storage::GroupWatermarks;
+
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    storage.add_group("misc.empty", false).await.unwrap();
+    storage.add_group("misc.test", false).await.unwrap();
+    storage.add_group("mod.test", true).await.unwrap();
+    for id in ["<a@test>", "<b@test>"] {
+        store_test_article(
+            &storage,
+            &format!("Message-ID: {id}\r\nNewsgroups: misc.test,mod.test\r\n\r\nBody"),
+        )
+        .await;
+    }
+    storage.delete_article_by_id("<a@test>").await.unwrap();
+
+    let counts: Vec<_> = storage
+        .list_groups_with_counts()
+        .try_collect()
+        .await
+        .unwrap();
+    let marks = |count, low, high| GroupWatermarks { count, low, high };
+    assert_eq!(
+        counts,
+        [
+            ("misc.empty".to_string(), marks(0, 0, 0), false),
+            ("misc.test".to_string(), marks(1, 2, 2), false),
+            ("mod.test".to_string(), marks(1, 2, 2), true),
+        ]
+    );
+    for (group, listed, _) in counts {
+        assert_eq!(
+            storage.get_group_watermarks(&group).await.unwrap(),
+            Some(listed)
+        );
+    }
+}
+
 #[tokio::test]
 async fn purge_old_articles() {
     use chrono::Utc;
@@ -225,3 +265,1013 @@ async fn store_article_multiple_groups_comma_separated() {
         );
     }
 }
+
+#[tokio::test]
+async fn article_numbers_cover_every_group() {
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    store_test_article(
+        &storage,
+        "Message-ID: <first@test>\r\nNewsgroups: b.test\r\n\r\nBody",
+    )
+    .await;
+    store_test_article(
+        &storage,
+        "Message-ID: <cross@test>\r\nNewsgroups: b.test,a.test\r\n\r\nBody",
+    )
+    .await;
+
+    assert_eq!(
+        storage.get_article_numbers("<cross@test>").await.unwrap(),
+        vec![("a.test".to_string(), 1), ("b.test".to_string(), 2)]
+    );
+    assert!(
+        storage
+            .get_article_numbers("<missing@test>")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Looked up together, articles in no group are left out
+    let ids = ["<first@test>", "<cross@test>", "<missing@test>"].map(String::from);
+    let batch = storage.get_article_numbers_batch(&ids).await.unwrap();
+    assert_eq!(batch.len(), 2);
+    assert_eq!(batch["<first@test>"], vec![("b.test".to_string(), 1)]);
+    assert_eq!(
+        batch["<cross@test>"],
+        storage.get_article_numbers("<cross@test>").await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn rebuild_overview_repairs_drift() {
+    use renews::storage::OverviewRepair;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+    use std::str::FromStr;
+
+    let db_file = tempfile::NamedTempFile::new().unwrap();
+    let db_path = format!("sqlite://{}", db_file.path().display());
+    let storage = SqliteStorage::new(&db_path).await.expect("init");
+    for id in ["a", "b", "c"] {
+        store_test_article(
+            &storage,
+            &format!("Message-ID: <{id}@test>\r\nNewsgroups: misc\r\nSubject: {id}\r\n\r\nBody"),
+        )
+        .await;
+    }
+    let expected = storage.get_overview_range("misc", 1, 1).await.unwrap();
+
+    // Corrupt the tables behind the storage layer's back
+    let options = SqliteConnectOptions::from_str(&db_path)
+        .unwrap()
+        .foreign_keys(false);
+    let pool = SqlitePool::connect_with(options).await.unwrap();
+    for sql in [
+        "UPDATE overview SET overview_data = 'garbage' WHERE article_number = 1",
+        "DELETE FROM messages WHERE message_id = '<b@test>'",
+        "INSERT INTO overview (group_name, article_number, overview_data) VALUES ('misc', 9, 'stale')",
+    ] {
+        sqlx::query(sql).execute(&pool).await.unwrap();
+    }
+
+    assert_eq!(
+        storage.rebuild_overview("misc").await.unwrap(),
+        OverviewRepair {
+            checked: 2,
+            rebuilt: 1,
+            stale_removed: 2,
+            dangling_removed: 1,
+        }
+    );
+    assert_eq!(
+        storage.get_overview_range("misc", 1, 1).await.unwrap(),
+        expected
+    );
+    let numbers: Vec<u64> = storage
+        .get_overview_range("misc", 1, 10)
+        .await
+        .unwrap()
+        .iter()
+        .map(|line| line.split('\t').next().unwrap().parse().unwrap())
+        .collect();
+    assert_eq!(numbers, [1, 3]);
+
+    // A consistent group is left untouched
+    assert_eq!(
+        storage.rebuild_overview("misc").await.unwrap(),
+        OverviewRepair {
+            checked: 2,
+            ..OverviewRepair::default()
+        }
+    );
+}
+
+#[tokio::test]
+async fn check_store_reports_and_repairs_corruption() {
+    use renews::integrity::{self, GroupCheck};
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+    use std::str::FromStr;
+
+    let db_file = tempfile::NamedTempFile::new().unwrap();
+    let db_path = format!("sqlite://{}", db_file.path().display());
+    let storage = SqliteStorage::new(&db_path).await.expect("init");
+    storage.add_group("misc", false).await.unwrap();
+    for id in ["a", "b", "c", "d"] {
+        store_test_article(
+            &storage,
+            &format!("Message-ID: <{id}@test>\r\nNewsgroups: misc\r\nSubject: {id}\r\n\r\nBody"),
+        )
+        .await;
+    }
+    let clean = integrity::check_group(&storage, "misc", &[]).await.unwrap();
+    assert!(clean.is_clean(), "{clean}");
+    let size = storage.get_message_size("<d@test>").await.unwrap().unwrap();
+
+    // Corrupt the tables behind the storage layer's back
+    let options = SqliteConnectOptions::from_str(&db_path)
+        .unwrap()
+        .foreign_keys(false);
+    let pool = SqlitePool::connect_with(options).await.unwrap();
+    for sql in [
+        "DELETE FROM messages WHERE message_id = '<a@test>'",
+        "UPDATE overview SET overview_data = 'garbage' WHERE article_number = 2",
+        "DELETE FROM overview WHERE article_number = 3",
+        "UPDATE messages SET size = 1 WHERE message_id = '<d@test>'",
+        "INSERT INTO overview (group_name, article_number, overview_data) VALUES ('misc', 9, '9\tstale')",
+    ] {
+        sqlx::query(sql).execute(&pool).await.unwrap();
+    }
+
+    let results = integrity::check_store(&storage, "*", &[], true)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    let (before, after) = &results[0];
+    assert_eq!(
+        *before,
+        GroupCheck {
+            group: "misc".into(),
+            checked: 4,
+            dangling: vec![1],
+            missing_overview: vec![3],
+            stale_overview: vec![2],
+            orphan_overview: vec![9],
+            size_mismatches: vec![(4, 1, size)],
+            low_water_lags: false,
+            outside_watermarks: vec![],
+        }
+    );
+    // Only the size mismatch is beyond a rebuild of the overview
+    assert_eq!(
+        *after,
+        Some(GroupCheck {
+            group: "misc".into(),
+            checked: 3,
+            size_mismatches: vec![(4, 1, size)],
+            ..GroupCheck::default()
+        })
+    );
+}
+
+#[tokio::test]
+async fn renumber_group_closes_gaps() {
+    use futures_util::TryStreamExt;
+    use renews::storage::GroupWatermarks;
+
+    let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+    for group in ["misc", "other", "empty"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+    for (id, groups, references) in [
+        ("a", "misc", ""),
+        ("b", "misc,other", ""),
+        ("c", "misc", ""),
+        ("d", "misc", ""),
+        ("e", "misc", "References: <d@test>\r\n"),
+    ] {
+        store_test_article(
+            &storage,
+            &format!(
+                "Message-ID: <{id}@test>\r\nNewsgroups: {groups}\r\nSubject: {id}\r\n{references}\r\nBody"
+            ),
+        )
+        .await;
+    }
+    storage
+        .set_article_pinned("misc", "<d@test>", true)
+        .await
+        .unwrap();
+    storage.delete_article_by_id("<a@test>").await.unwrap();
+    storage.delete_article_by_id("<c@test>").await.unwrap();
+    assert_eq!(
+        storage.get_group_watermarks("misc").await.unwrap(),
+        Some(GroupWatermarks {
+            count: 3,
+            low: 2,
+            high: 5,
+        })
+    );
+
+    let renumbered = GroupWatermarks {
+        count: 3,
+        low: 1,
+        high: 3,
+    };
+    assert_eq!(
+        storage.renumber_group("misc").await.unwrap(),
+        Some(renumbered)
+    );
+    let overview = storage.get_overview_range("misc", 1, 10).await.unwrap();
+    let numbered: Vec<(&str, &str)> = overview
+        .iter()
+        .map(|line| {
+            let mut fields = line.split('\t');
+            (fields.next().unwrap(), fields.nth(3).unwrap())
+        })
+        .collect();
+    assert_eq!(
+        numbered,
+        [("1", "<b@test>"), ("2", "<d@test>"), ("3", "<e@test>")]
+    );
+    let pinned: Vec<_> = storage.list_pinned_articles().try_collect().await.unwrap();
+    assert_eq!(pinned, [("misc".to_string(), 2, "<d@test>".to_string())]);
+    assert_eq!(
+        storage.list_replies("misc", "<d@test>").await.unwrap(),
+        [(3, "<e@test>".to_string())]
+    );
+    // Other groups keep their numbers
+    assert_eq!(
+        storage.get_group_watermarks("other").await.unwrap(),
+        Some(GroupWatermarks {
+            count: 1,
+            low: 1,
+            high: 1,
+        })
+    );
+
+    // Numbering continues after the new high watermark, and the low
+    // watermark follows the first article again
+    store_test_article(
+        &storage,
+        "Message-ID: <f@test>\r\nNewsgroups: misc\r\nSubject: f\r\n\r\nBody",
+    )
+    .await;
+    storage.delete_article_by_id("<b@test>").await.unwrap();
+    assert_eq!(
+        storage.get_group_watermarks("misc").await.unwrap(),
+        Some(GroupWatermarks {
+            count: 3,
+            low: 2,
+            high: 4,
+        })
+    );
+    // Renumbering again closes the gap left at the start
+    assert_eq!(
+        storage.renumber_group("misc").await.unwrap(),
+        Some(renumbered)
+    );
+
+    // An emptied group starts again from 1
+    store_test_article(
+        &storage,
+        "Message-ID: <g@test>\r\nNewsgroups: empty\r\nSubject: g\r\n\r\nBody",
+    )
+    .await;
+    storage.delete_article_by_id("<g@test>").await.unwrap();
+    assert_eq!(
+        storage.renumber_group("empty").await.unwrap(),
+        Some(GroupWatermarks::default())
+    );
+    store_test_article(
+        &storage,
+        "Message-ID: <h@test>\r\nNewsgroups: empty\r\nSubject: h\r\n\r\nBody",
+    )
+    .await;
+    assert_eq!(
+        storage
+            .get_article_by_number("empty", 1)
+            .await
+            .unwrap()
+            .map(|a| get_message_id(&a)),
+        Some(Some("<h@test>".to_string()))
+    );
+
+    assert_eq!(storage.renumber_group("nowhere").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn compressed_bodies_are_read_transparently() {
+    use renews::config::Config;
+    use sqlx::sqlite::SqlitePool;
+
+    let db_file = tempfile::NamedTempFile::new().unwrap();
+    let db_path = format!("sqlite://{}", db_file.path().display());
+    let storage = SqliteStorage::new(&db_path).await.expect("init");
+    let cfg: Config = toml::from_str(
+        "addr = \":119\"\n[[group_settings]]\npattern = \"comp.*\"\ncompress = true",
+    )
+    .unwrap();
+    storage.set_compression(cfg.article_compression());
+
+    let body = "All work and no play makes Jack a dull boy.\r\n".repeat(50);
+    for (id, group) in [("packed", "comp.lang"), ("plain", "misc.test")] {
+        store_test_article(
+            &storage,
+            &format!("Message-ID: <{id}@test>\r\nNewsgroups: {group}\r\n\r\n{body}"),
+        )
+        .await;
+        let article = storage
+            .get_article_by_id(&format!("<{id}@test>"))
+            .await
+            .unwrap()
+            .expect("article");
+        assert_eq!(article.body, body);
+    }
+    let article = storage
+        .get_article_by_number("comp.lang", 1)
+        .await
+        .unwrap()
+        .expect("article");
+    assert_eq!(article.body, body);
+    let (total, chunk) = storage
+        .get_body_range("<packed@test>", 4, 4)
+        .await
+        .unwrap()
+        .expect("range");
+    assert_eq!((total, chunk.as_slice()), (body.len() as u64, &b"work"[..]));
+
+    let pool = SqlitePool::connect(&db_path).await.unwrap();
+    let rows: Vec<(String, String, Option<Vec<u8>>)> =
+        sqlx::query_as("SELECT message_id, body, body_zstd FROM messages ORDER BY message_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    let (_, packed_body, packed) = &rows[0];
+    assert!(packed_body.is_empty());
+    assert!(packed.as_ref().is_some_and(|p| p.len() < body.len() / 3));
+    let (_, plain_body, plain) = &rows[1];
+    assert_eq!((plain_body.as_str(), plain), (body.as_str(), &None));
+}
+
+#[tokio::test]
+async fn body_range_reads_bytes() {
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    let text = "Message-ID: <range@test>\r\nNewsgroups: group.test\r\n\r\nh\u{e9}llo world";
+    store_test_article(&storage, text).await;
+
+    // Offsets count bytes, so a multi-byte character may be split
+    let (total, chunk) = storage
+        .get_body_range("<range@test>", 1, 2)
+        .await
+        .unwrap()
+        .expect("range");
+    assert_eq!(total, 12);
+    assert_eq!(chunk, "\u{e9}".as_bytes());
+
+    let (_, rest) = storage
+        .get_body_range("<range@test>", 7, u64::MAX)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(rest, b"world");
+    let (_, past_end) = storage
+        .get_body_range("<range@test>", 20, 5)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(past_end.is_empty());
+    assert!(
+        storage
+            .get_body_range("<missing@test>", 0, 5)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn resume_tokens_expire() {
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    let now = chrono::Utc::now();
+    storage
+        .add_resume_token("rtoken", "<a@test>")
+        .await
+        .unwrap();
+
+    let since = now - chrono::Duration::hours(1);
+    assert_eq!(
+        storage.get_resume_token("rtoken", since).await.unwrap(),
+        Some("<a@test>".to_string())
+    );
+    let later = now + chrono::Duration::hours(1);
+    assert_eq!(
+        storage.get_resume_token("rtoken", later).await.unwrap(),
+        None
+    );
+
+    storage.purge_resume_tokens_before(later).await.unwrap();
+    assert_eq!(
+        storage.get_resume_token("rtoken", since).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn removed_articles_are_remembered_in_history() {
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    storage.add_group("misc.test", false).await.unwrap();
+    for id in ["<gone@test>", "<expired@test>"] {
+        store_test_article(
+            &storage,
+            &format!("Message-ID: {id}\r\nNewsgroups: misc.test\r\n\r\nBody\r\n"),
+        )
+        .await;
+    }
+    assert!(!storage.in_history("<gone@test>").await.unwrap());
+
+    storage.delete_article_by_id("<gone@test>").await.unwrap();
+    let future = chrono::Utc::now() + chrono::Duration::hours(1);
+    storage
+        .purge_group_before("misc.test", future)
+        .await
+        .unwrap();
+    storage.purge_orphan_messages().await.unwrap();
+    storage.remember_message_id("<refused@test>").await.unwrap();
+    for id in ["<gone@test>", "<expired@test>", "<refused@test>"] {
+        assert!(storage.in_history(id).await.unwrap(), "{id} not remembered");
+    }
+
+    storage.purge_history_before(future).await.unwrap();
+    assert!(!storage.in_history("<gone@test>").await.unwrap());
+}
+
+#[tokio::test]
+async fn audit_log_records_and_forgets_entries() {
+    use futures_util::TryStreamExt;
+    use renews::audit::{AuditAction, AuditEntry};
+
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    let mut old = AuditEntry::new(AuditAction::AddGroup, "misc.test").by_cli();
+    old.recorded_at -= 7200;
+    let post = AuditEntry::new(AuditAction::Post, "<1@test>")
+        .by(Some("alice"))
+        .from_addr(Some("192.0.2.1".parse().unwrap()))
+        .with_detail("misc.test");
+    for entry in [&old, &post] {
+        storage.record_audit(entry).await.unwrap();
+    }
+
+    let hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+    let recent: Vec<AuditEntry> = storage
+        .list_audit_since(hour_ago)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(recent, vec![post.clone()]);
+    assert_eq!(recent[0].source.as_deref(), Some("192.0.2.1"));
+
+    storage.purge_audit_before(hour_ago).await.unwrap();
+    let all: Vec<AuditEntry> = storage
+        .list_audit_since(chrono::DateTime::UNIX_EPOCH)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(all, vec![post]);
+}
+
+#[tokio::test]
+async fn article_cache_serves_repeated_lookups() {
+    use renews::storage::cache::{ArticleCache, CachedStorage};
+    use std::sync::Arc;
+
+    let inner = Arc::new(SqliteStorage::new("sqlite::memory:").await.expect("init"));
+    let cache = Arc::new(ArticleCache::new(1024));
+    let storage = CachedStorage::new(inner.clone(), cache.clone());
+    store_test_article(
+        &storage,
+        "Message-ID: <hot@test>\r\nNewsgroups: group.test\r\n\r\nBody",
+    )
+    .await;
+
+    for _ in 0..3 {
+        let article = storage.get_article_by_id("<hot@test>").await.unwrap();
+        assert_eq!(article.expect("article").body, "Body");
+    }
+    assert!(
+        storage
+            .get_article_by_id("<cold@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (2, 2, 1));
+    assert!((stats.hit_rate() - 0.5).abs() < f64::EPSILON);
+
+    // Deleting through the cache invalidates the entry
+    storage.delete_article_by_id("<hot@test>").await.unwrap();
+    assert!(
+        storage
+            .get_article_by_id("<hot@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(cache.stats().entries, 0);
+}
+
+#[test]
+fn article_cache_evicts_least_recently_used() {
+    use renews::storage::cache::ArticleCache;
+
+    let article = |body: &str| renews::Message {
+        headers: smallvec::smallvec![("Message-ID".into(), "<x@test>".into())],
+        body: body.into(),
+    };
+    // Each entry takes 22 bytes of headers plus its body
+    let cache = ArticleCache::new(100);
+    cache.insert("<a@test>", &article(&"a".repeat(20)));
+    cache.insert("<b@test>", &article(&"b".repeat(20)));
+    assert!(cache.get("<a@test>").is_some());
+    cache.insert("<c@test>", &article(&"c".repeat(20)));
+
+    assert!(cache.get("<a@test>").is_some());
+    assert!(cache.get("<b@test>").is_none());
+    assert!(cache.get("<c@test>").is_some());
+    assert_eq!(cache.stats().evictions, 1);
+
+    // Articles larger than the cache are never stored
+    cache.insert("<big@test>", &article(&"x".repeat(200)));
+    assert!(cache.get("<big@test>").is_none());
+}
+
+#[test]
+fn article_cache_expires_entries_after_their_ttl() {
+    use renews::storage::cache::ArticleCache;
+    use std::time::Duration;
+
+    let article = renews::Message {
+        headers: smallvec::smallvec![("Message-ID".into(), "<ttl@test>".into())],
+        body: "Body".into(),
+    };
+    let cache = ArticleCache::new(1024).with_ttl(Duration::from_millis(50));
+    cache.insert("<ttl@test>", &article);
+    assert!(cache.get("<ttl@test>").is_some());
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(cache.get("<ttl@test>").is_none());
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.expirations), (1, 1, 1));
+    assert_eq!((stats.entries, stats.bytes), (0, 0));
+}
+
+#[tokio::test]
+async fn purge_keeps_pinned_articles() {
+    use chrono::Utc;
+    use futures_util::TryStreamExt;
+    use std::time::Duration as StdDuration;
+    use tokio::time::sleep;
+
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    storage.add_group("g1", false).await.unwrap();
+    storage.add_group("g2", false).await.unwrap();
+    store_test_article(
+        &storage,
+        "Message-ID: <1@test>\r\nNewsgroups: g1,g2\r\n\r\nB",
+    )
+    .await;
+    store_test_article(&storage, "Message-ID: <2@test>\r\nNewsgroups: g1\r\n\r\nB").await;
+
+    assert!(
+        storage
+            .set_article_pinned("g1", "<1@test>", true)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !storage
+            .set_article_pinned("g1", "<3@test>", true)
+            .await
+            .unwrap()
+    );
+    let pinned: Vec<_> = storage.list_pinned_articles().try_collect().await.unwrap();
+    assert_eq!(pinned, vec![("g1".to_string(), 1, "<1@test>".to_string())]);
+
+    sleep(StdDuration::from_secs(1)).await;
+    storage.purge_group_before("g1", Utc::now()).await.unwrap();
+    storage.purge_group_before("g2", Utc::now()).await.unwrap();
+    storage.purge_orphan_messages().await.unwrap();
+    assert!(
+        storage
+            .get_article_by_number("g1", 1)
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        storage
+            .get_article_by_number("g1", 2)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        storage
+            .get_article_by_number("g2", 1)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    assert!(
+        storage
+            .set_article_pinned("g1", "<1@test>", false)
+            .await
+            .unwrap()
+    );
+    storage.purge_group_before("g1", Utc::now()).await.unwrap();
+    assert!(
+        storage
+            .get_article_by_number("g1", 1)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn snapshot_copies_the_live_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let uri = format!("sqlite://{}/news.db", dir.path().display());
+    let storage = SqliteStorage::new(&uri).await.expect("init");
+    store_test_article(
+        &storage,
+        "Message-ID: <snap@test>\r\nNewsgroups: group.test\r\nSubject: Hello\r\n\r\nBody",
+    )
+    .await;
+
+    let path = dir.path().join("snapshot.db");
+    storage.snapshot_to(&path).await.unwrap();
+    // The source keeps taking writes after the snapshot
+    store_test_article(
+        &storage,
+        "Message-ID: <later@test>\r\nNewsgroups: group.test\r\nSubject: Later\r\n\r\nBody",
+    )
+    .await;
+
+    let snapshot = SqliteStorage::new(&format!("sqlite://{}", path.display()))
+        .await
+        .expect("open snapshot");
+    assert!(
+        snapshot
+            .get_article_by_id("<snap@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        snapshot
+            .get_article_by_id("<later@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // An existing file is never overwritten
+    assert!(storage.snapshot_to(&path).await.is_err());
+}
+
+/// A SQLite database in a file, so that concurrent stores use separate
+/// connections.
+async fn sqlite_file(dir: &tempfile::TempDir) -> DynStorage {
+    let uri = format!("sqlite://{}/news.db", dir.path().display());
+    std::sync::Arc::new(SqliteStorage::new(&uri).await.expect("init"))
+}
+
+#[tokio::test]
+async fn concurrent_stores_get_distinct_numbers() {
+    let dir = tempfile::tempdir().unwrap();
+    stores_get_distinct_numbers(sqlite_file(&dir).await).await;
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn concurrent_stores_get_distinct_numbers_postgres() {
+    let Some(db) = crate::postgres::Database::create().await else {
+        return;
+    };
+    stores_get_distinct_numbers(db.storage.clone()).await;
+    db.drop().await;
+}
+
+/// Store crossposts from several tasks at once to groups that have not been
+/// created, numbering them as they go.
+async fn stores_get_distinct_numbers(storage: DynStorage) {
+    let mut tasks = Vec::new();
+    for worker in 0..8 {
+        let storage = storage.clone();
+        tasks.push(tokio::spawn(async move {
+            for n in 0..25 {
+                let text = format!(
+                    "Message-ID: <{worker}.{n}@test>\r\nNewsgroups: group.test,group.other\r\nSubject: Hi\r\n\r\nBody"
+                );
+                let (_, msg) = renews::parse_message(&text).unwrap();
+                storage.store_article(&msg).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    for group in ["group.test", "group.other"] {
+        let overview = storage.get_overview_range(group, 1, 300).await.unwrap();
+        let numbers: Vec<u64> = overview
+            .iter()
+            .map(|line| line.split('\t').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(numbers, (1..=200).collect::<Vec<u64>>());
+    }
+}
+
+#[tokio::test]
+async fn concurrent_stores_advance_the_group_counter() {
+    let dir = tempfile::tempdir().unwrap();
+    stores_advance_the_group_counter(sqlite_file(&dir).await).await;
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn concurrent_stores_advance_the_group_counter_postgres() {
+    let Some(db) = crate::postgres::Database::create().await else {
+        return;
+    };
+    stores_advance_the_group_counter(db.storage.clone()).await;
+    db.drop().await;
+}
+
+/// Store posts to existing groups from several tasks at once, crossposting
+/// in both orders.
+async fn stores_advance_the_group_counter(storage: DynStorage) {
+    storage.add_group("group.a", false).await.unwrap();
+    storage.add_group("group.b", false).await.unwrap();
+
+    let mut tasks = Vec::new();
+    for worker in 0..8 {
+        let storage = storage.clone();
+        tasks.push(tokio::spawn(async move {
+            for n in 0..25 {
+                // Alternate crossposts in both orders with single-group posts
+                let groups = match n % 3 {
+                    0 => "group.a,group.b",
+                    1 => "group.b,group.a",
+                    _ => "group.a",
+                };
+                let text = format!(
+                    "Message-ID: <{worker}.{n}@test>\r\nNewsgroups: {groups}\r\nSubject: Hi\r\n\r\nBody"
+                );
+                let (_, msg) = renews::parse_message(&text).unwrap();
+                storage.store_article(&msg).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    // group.b only receives the crossposts, 17 of every 25 posts
+    for (group, total) in [("group.a", 200u64), ("group.b", 136)] {
+        let overview = storage.get_overview_range(group, 1, 300).await.unwrap();
+        let numbers: Vec<u64> = overview
+            .iter()
+            .map(|line| line.split('\t').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(numbers, (1..=total).collect::<Vec<u64>>());
+        let marks = storage.get_group_watermarks(group).await.unwrap().unwrap();
+        assert_eq!((marks.low, marks.high, marks.count), (1, total, total));
+    }
+}
+
+#[tokio::test]
+async fn store_articles_numbers_batch_in_order() {
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    let articles: Vec<_> = (1..=3)
+        .map(|n| {
+            let text = format!(
+                "Message-ID: <{n}@batch>\r\nNewsgroups: g1,g2\r\nSubject: Batch {n}\r\n\r\nBody {n}"
+            );
+            renews::parse_message(&text).unwrap().1
+        })
+        .collect();
+    storage.store_articles(&articles).await.unwrap();
+
+    for group in ["g1", "g2"] {
+        for n in 1..=3u64 {
+            let fetched = storage
+                .get_article_by_number(group, n)
+                .await
+                .unwrap()
+                .expect("article by number");
+            assert_eq!(fetched.body, format!("Body {n}"));
+        }
+        let overview = storage.get_overview_range(group, 1, 3).await.unwrap();
+        assert_eq!(overview.len(), 3);
+        assert!(overview[2].starts_with("3\tBatch 3\t"));
+    }
+}
+
+#[tokio::test]
+async fn store_articles_is_all_or_nothing() {
+    let storage = SqliteStorage::new("sqlite::memory:").await.expect("init");
+    let (_, good) =
+        renews::parse_message("Message-ID: <good@batch>\r\nNewsgroups: g1\r\n\r\nA").unwrap();
+    let (_, bad) = renews::parse_message("Newsgroups: g1\r\n\r\nNo Message-ID").unwrap();
+
+    assert!(storage.store_articles(&[good, bad]).await.is_err());
+    assert!(
+        storage
+            .get_article_by_id("<good@batch>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        storage
+            .get_article_by_number("g1", 1)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn watermarks_never_fall_and_numbers_are_not_reused() {
+    use renews::storage::GroupWatermarks;
+    let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+    storage.add_group("misc", false).await.unwrap();
+    assert_eq!(
+        storage.get_group_watermarks("misc").await.unwrap(),
+        Some(GroupWatermarks::default())
+    );
+    assert_eq!(storage.get_group_watermarks("nope").await.unwrap(), None);
+
+    for i in 1..=3 {
+        store_test_article(
+            &storage,
+            &format!("Message-ID: <{i}@test>\r\nNewsgroups: misc\r\n\r\nB"),
+        )
+        .await;
+    }
+    let marks = |count, low, high| Some(GroupWatermarks { count, low, high });
+    assert_eq!(
+        storage.get_group_watermarks("misc").await.unwrap(),
+        marks(3, 1, 3)
+    );
+
+    // Deleting the newest article leaves the high watermark alone
+    storage.delete_article_by_id("<3@test>").await.unwrap();
+    storage.delete_article_by_id("<1@test>").await.unwrap();
+    assert_eq!(
+        storage.get_group_watermarks("misc").await.unwrap(),
+        marks(1, 2, 3)
+    );
+
+    // An expired group reports a low watermark above its high watermark
+    storage
+        .purge_group_before("misc", chrono::Utc::now() + chrono::Duration::seconds(1))
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.get_group_watermarks("misc").await.unwrap(),
+        marks(0, 4, 3)
+    );
+
+    store_test_article(
+        &storage,
+        "Message-ID: <4@test>\r\nNewsgroups: misc\r\n\r\nB",
+    )
+    .await;
+    assert_eq!(
+        storage.get_article_numbers("<4@test>").await.unwrap(),
+        vec![("misc".to_string(), 4)]
+    );
+    assert_eq!(
+        storage.get_group_watermarks("misc").await.unwrap(),
+        marks(1, 4, 4)
+    );
+}
+
+#[tokio::test]
+async fn shards_hold_their_groups_and_their_share_of_crossposts() {
+    use renews::config::ShardConfig;
+    use renews::storage::DynStorage;
+    use renews::storage::sharded::ShardedStorage;
+    use std::sync::Arc;
+
+    let main: DynStorage = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    let binaries: DynStorage = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    let shard = ShardConfig {
+        name: "binaries".into(),
+        groups: vec!["alt.binaries.*".into(), "!alt.binaries.d".into()],
+        db_path: "sqlite::memory:".into(),
+    };
+    let storage = ShardedStorage::new(main.clone(), vec![(shard, binaries.clone())]);
+    for group in ["misc.test", "alt.binaries.pics", "alt.binaries.d"] {
+        storage.add_group(group, false).await.unwrap();
+    }
+    assert_eq!(
+        collect_groups(&storage).await,
+        ["alt.binaries.d", "alt.binaries.pics", "misc.test"]
+    );
+    assert_eq!(collect_groups(&*binaries).await, ["alt.binaries.pics"]);
+
+    store_test_article(
+        &storage,
+        "Message-ID: <pic@test>\r\nNewsgroups: alt.binaries.pics\r\n\r\nA",
+    )
+    .await;
+    store_test_article(
+        &storage,
+        "Message-ID: <both@test>\r\nNewsgroups: misc.test,alt.binaries.pics\r\n\r\nB",
+    )
+    .await;
+
+    // Each database numbers the crosspost in its own groups only
+    assert_eq!(
+        storage.get_article_numbers("<both@test>").await.unwrap(),
+        [
+            ("alt.binaries.pics".to_string(), 2),
+            ("misc.test".to_string(), 1)
+        ]
+    );
+    assert_eq!(
+        main.get_article_numbers("<both@test>").await.unwrap(),
+        [("misc.test".to_string(), 1)]
+    );
+    assert!(
+        main.get_article_by_id("<pic@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let counts: Vec<_> = futures_util::TryStreamExt::try_collect(storage.list_groups_with_counts())
+        .await
+        .unwrap();
+    let counts: Vec<_> = counts
+        .iter()
+        .map(|(g, m, _)| (g.as_str(), m.count))
+        .collect();
+    assert_eq!(
+        counts,
+        [
+            ("alt.binaries.d", 0),
+            ("alt.binaries.pics", 2),
+            ("misc.test", 1)
+        ]
+    );
+    let pic = storage
+        .get_article_by_number("alt.binaries.pics", 1)
+        .await
+        .unwrap()
+        .expect("article in shard");
+    assert_eq!(pic.body, "A");
+
+    // Deleting removes the article from every database
+    storage.delete_article_by_id("<both@test>").await.unwrap();
+    assert!(
+        storage
+            .get_article_by_id("<both@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(storage.in_history("<both@test>").await.unwrap());
+}
+
+#[tokio::test]
+async fn rejected_articles_are_purged_after_retention() {
+    let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+    let (_, article) = renews::parse_message(
+        "Message-ID: <refused@test>\r\nNewsgroups: misc\r\nSubject: t\r\n\r\nBody\r\n",
+    )
+    .unwrap();
+    let id = storage
+        .add_rejected_article(&article, "too large")
+        .await
+        .unwrap();
+    let kept = storage.get_rejected_article(id).await.unwrap().unwrap();
+    assert_eq!(kept.reason, "too large");
+    assert_eq!(kept.message.body, article.body);
+
+    storage
+        .purge_rejected_before(chrono::Utc::now() - chrono::Duration::hours(1))
+        .await
+        .unwrap();
+    assert!(storage.get_rejected_article(id).await.unwrap().is_some());
+    storage
+        .purge_rejected_before(chrono::Utc::now() + chrono::Duration::hours(1))
+        .await
+        .unwrap();
+    assert!(storage.get_rejected_article(id).await.unwrap().is_none());
+    assert!(!storage.remove_rejected_article(id).await.unwrap());
+}
@@ -0,0 +1,703 @@
+//! Tests for transfers interrupted by storage failures.
+//!
+//! [`FailingStorage`] wraps an in-memory database and fails article lookups
+//! or stores on demand. Peers must be asked to retry such transfers, and
+//! nothing may be recorded in the history, so the article is accepted once
+//! the storage recovers.
+
+use crate::utils::{self, ClientMock};
+use async_trait::async_trait;
+use futures_core::Stream;
+use futures_util::StreamExt;
+use renews::Message;
+use renews::audit::AuditEntry;
+use renews::config::ListenerRole;
+use renews::queue::ArticleQueue;
+use renews::storage::{
+    DynStorage, GroupActivity, GroupWatermarks, OverviewRepair, PendingArticle, PendingCheckgroups,
+    RejectedArticle, Storage,
+};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::BufReader;
+use tokio::sync::RwLock;
+
+type Result<T> = anyhow::Result<T>;
+type BoxStream<'a, T> = Pin<Box<dyn Stream<Item = Result<T>> + Send + 'a>>;
+type StringStream<'a> = BoxStream<'a, String>;
+type U64Stream<'a> = BoxStream<'a, u64>;
+type StringTimestampStream<'a> = BoxStream<'a, (String, i64)>;
+type ArticleStream<'a> = BoxStream<'a, (String, Message)>;
+type GroupDescriptionStream<'a> = BoxStream<'a, (String, String)>;
+type PendingArticleStream<'a> = BoxStream<'a, PendingArticle>;
+type RejectedArticleStream<'a> = BoxStream<'a, RejectedArticle>;
+type PinnedArticleStream<'a> = BoxStream<'a, (String, u64, String)>;
+type AuditStream<'a> = BoxStream<'a, AuditEntry>;
+type ChangeStream<'a> = BoxStream<'a, (u64, String)>;
+type GroupCountStream<'a> = BoxStream<'a, (String, GroupWatermarks, bool)>;
+type GroupActivityStream<'a> = BoxStream<'a, (String, GroupActivity)>;
+
+/// Storage whose article and group lookups and stores fail while switched
+/// on.
+struct FailingStorage {
+    inner: DynStorage,
+    fail_lookups: AtomicBool,
+    fail_stores: AtomicBool,
+}
+
+impl FailingStorage {
+    async fn new() -> Arc<Self> {
+        let inner = utils::create_test_storage().await;
+        inner.add_group("test.group", false).await.unwrap();
+        Arc::new(Self {
+            inner,
+            fail_lookups: AtomicBool::new(false),
+            fail_stores: AtomicBool::new(false),
+        })
+    }
+
+    fn check(flag: &AtomicBool) -> Result<()> {
+        if flag.load(Ordering::SeqCst) {
+            anyhow::bail!("simulated storage failure");
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for FailingStorage {
+    async fn store_article(&self, article: &Message) -> Result<()> {
+        Self::check(&self.fail_stores)?;
+        self.inner.store_article(article).await
+    }
+
+    async fn store_article_in(&self, article: &Message, groups: &[String]) -> Result<()> {
+        Self::check(&self.fail_stores)?;
+        self.inner.store_article_in(article, groups).await
+    }
+
+    async fn store_articles(&self, articles: &[Message]) -> Result<()> {
+        Self::check(&self.fail_stores)?;
+        self.inner.store_articles(articles).await
+    }
+
+    async fn get_article_by_number(&self, group: &str, number: u64) -> Result<Option<Message>> {
+        self.inner.get_article_by_number(group, number).await
+    }
+
+    async fn get_article_by_id(&self, message_id: &str) -> Result<Option<Message>> {
+        Self::check(&self.fail_lookups)?;
+        self.inner.get_article_by_id(message_id).await
+    }
+
+    fn get_articles_by_ids<'a>(&'a self, message_ids: &'a [String]) -> ArticleStream<'a> {
+        self.inner.get_articles_by_ids(message_ids)
+    }
+
+    async fn get_overview_range(&self, group: &str, start: u64, end: u64) -> Result<Vec<String>> {
+        self.inner.get_overview_range(group, start, end).await
+    }
+
+    async fn add_group(&self, group: &str, moderated: bool) -> Result<()> {
+        self.inner.add_group(group, moderated).await
+    }
+
+    async fn set_group_moderated(&self, group: &str, moderated: bool) -> Result<()> {
+        self.inner.set_group_moderated(group, moderated).await
+    }
+
+    async fn set_group_archived(&self, group: &str, archived: bool) -> Result<()> {
+        self.inner.set_group_archived(group, archived).await
+    }
+
+    async fn remove_group(&self, group: &str) -> Result<()> {
+        self.inner.remove_group(group).await
+    }
+
+    async fn remove_groups_by_pattern(&self, pattern: &str) -> Result<()> {
+        self.inner.remove_groups_by_pattern(pattern).await
+    }
+
+    fn list_groups(&self) -> StringStream<'_> {
+        if let Err(e) = Self::check(&self.fail_lookups) {
+            return Box::pin(futures_util::stream::once(async { Err(e) }));
+        }
+        self.inner.list_groups()
+    }
+
+    fn list_groups_since(&self, since: chrono::DateTime<chrono::Utc>) -> StringStream<'_> {
+        self.inner.list_groups_since(since)
+    }
+
+    fn list_groups_with_times(&self) -> StringTimestampStream<'_> {
+        self.inner.list_groups_with_times()
+    }
+
+    async fn get_group_watermarks(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        self.inner.get_group_watermarks(group).await
+    }
+
+    fn list_groups_with_counts(&self) -> GroupCountStream<'_> {
+        self.inner.list_groups_with_counts()
+    }
+
+    fn list_group_activity(&self) -> GroupActivityStream<'_> {
+        self.inner.list_group_activity()
+    }
+
+    fn list_article_numbers(&self, group: &str) -> U64Stream<'_> {
+        // Fail after the first number, once a response is under way
+        let numbers = self.inner.list_article_numbers(group);
+        match Self::check(&self.fail_lookups) {
+            Ok(()) => numbers,
+            Err(e) => Box::pin(
+                numbers
+                    .take(1)
+                    .chain(futures_util::stream::once(async { Err(e) })),
+            ),
+        }
+    }
+
+    fn list_article_ids(&self, group: &str) -> StringStream<'_> {
+        self.inner.list_article_ids(group)
+    }
+
+    fn list_article_ids_since(
+        &self,
+        group: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> StringStream<'_> {
+        self.inner.list_article_ids_since(group, since)
+    }
+
+    async fn purge_group_before(
+        &self,
+        group: &str,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        self.inner.purge_group_before(group, before).await
+    }
+
+    async fn purge_orphan_messages(&self) -> Result<()> {
+        self.inner.purge_orphan_messages().await
+    }
+
+    async fn database_size(&self) -> Result<u64> {
+        self.inner.database_size().await
+    }
+
+    async fn compact(&self) -> Result<()> {
+        self.inner.compact().await
+    }
+
+    async fn rebuild_overview(&self, group: &str) -> Result<OverviewRepair> {
+        self.inner.rebuild_overview(group).await
+    }
+
+    async fn renumber_group(&self, group: &str) -> Result<Option<GroupWatermarks>> {
+        self.inner.renumber_group(group).await
+    }
+
+    async fn list_replies(&self, group: &str, message_id: &str) -> Result<Vec<(u64, String)>> {
+        self.inner.list_replies(group, message_id).await
+    }
+
+    async fn get_message_size(&self, message_id: &str) -> Result<Option<u64>> {
+        self.inner.get_message_size(message_id).await
+    }
+
+    async fn get_article_numbers(&self, message_id: &str) -> Result<Vec<(String, u64)>> {
+        self.inner.get_article_numbers(message_id).await
+    }
+
+    async fn get_article_numbers_batch(
+        &self,
+        message_ids: &[String],
+    ) -> Result<std::collections::HashMap<String, Vec<(String, u64)>>> {
+        self.inner.get_article_numbers_batch(message_ids).await
+    }
+
+    async fn get_article_arrivals(
+        &self,
+        message_id: &str,
+    ) -> Result<Vec<(String, chrono::DateTime<chrono::Utc>, bool)>> {
+        self.inner.get_article_arrivals(message_id).await
+    }
+
+    async fn get_body_range(
+        &self,
+        message_id: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Option<(u64, Vec<u8>)>> {
+        self.inner.get_body_range(message_id, offset, len).await
+    }
+
+    async fn set_article_pinned(
+        &self,
+        group: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> Result<bool> {
+        self.inner
+            .set_article_pinned(group, message_id, pinned)
+            .await
+    }
+
+    fn list_pinned_articles(&self) -> PinnedArticleStream<'_> {
+        self.inner.list_pinned_articles()
+    }
+
+    async fn delete_article_by_id(&self, message_id: &str) -> Result<()> {
+        self.inner.delete_article_by_id(message_id).await
+    }
+
+    async fn withdraw_article(&self, message_id: &str) -> Result<()> {
+        self.inner.withdraw_article(message_id).await
+    }
+
+    async fn supersede_article(&self, old_id: &str, article: &Message) -> Result<()> {
+        self.inner.supersede_article(old_id, article).await
+    }
+
+    async fn is_group_moderated(&self, group: &str) -> Result<bool> {
+        self.inner.is_group_moderated(group).await
+    }
+
+    async fn is_group_archived(&self, group: &str) -> Result<bool> {
+        self.inner.is_group_archived(group).await
+    }
+
+    fn list_archived_groups(&self) -> StringStream<'_> {
+        self.inner.list_archived_groups()
+    }
+
+    async fn group_exists(&self, group: &str) -> Result<bool> {
+        self.inner.group_exists(group).await
+    }
+
+    async fn add_group_with_description(
+        &self,
+        group: &str,
+        moderated: bool,
+        description: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_group_with_description(group, moderated, description)
+            .await
+    }
+
+    async fn set_group_description(&self, group: &str, description: &str) -> Result<bool> {
+        self.inner.set_group_description(group, description).await
+    }
+
+    fn list_groups_with_descriptions(&self) -> GroupDescriptionStream<'_> {
+        self.inner.list_groups_with_descriptions()
+    }
+
+    async fn add_pending_article(&self, article: &Message) -> Result<u64> {
+        self.inner.add_pending_article(article).await
+    }
+
+    async fn get_pending_article(&self, id: u64) -> Result<Option<PendingArticle>> {
+        self.inner.get_pending_article(id).await
+    }
+
+    fn list_pending_articles(&self) -> PendingArticleStream<'_> {
+        self.inner.list_pending_articles()
+    }
+
+    async fn remove_pending_article(&self, id: u64) -> Result<()> {
+        self.inner.remove_pending_article(id).await
+    }
+
+    async fn add_pending_checkgroups(&self, signer: &str, diff: &str) -> Result<u64> {
+        self.inner.add_pending_checkgroups(signer, diff).await
+    }
+
+    async fn list_pending_checkgroups(&self) -> Result<Vec<PendingCheckgroups>> {
+        self.inner.list_pending_checkgroups().await
+    }
+
+    async fn remove_pending_checkgroups(&self, id: u64) -> Result<bool> {
+        self.inner.remove_pending_checkgroups(id).await
+    }
+
+    async fn add_rejected_article(&self, article: &Message, reason: &str) -> Result<u64> {
+        self.inner.add_rejected_article(article, reason).await
+    }
+
+    async fn get_rejected_article(&self, id: u64) -> Result<Option<RejectedArticle>> {
+        self.inner.get_rejected_article(id).await
+    }
+
+    fn list_rejected_articles(&self) -> RejectedArticleStream<'_> {
+        self.inner.list_rejected_articles()
+    }
+
+    async fn remove_rejected_article(&self, id: u64) -> Result<bool> {
+        self.inner.remove_rejected_article(id).await
+    }
+
+    async fn purge_rejected_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_rejected_before(before).await
+    }
+
+    async fn add_resume_token(&self, token: &str, message_id: &str) -> Result<()> {
+        self.inner.add_resume_token(token, message_id).await
+    }
+
+    async fn get_resume_token(
+        &self,
+        token: &str,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<String>> {
+        self.inner.get_resume_token(token, since).await
+    }
+
+    async fn purge_resume_tokens_before(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        self.inner.purge_resume_tokens_before(before).await
+    }
+
+    async fn remember_message_id(&self, message_id: &str) -> Result<()> {
+        self.inner.remember_message_id(message_id).await
+    }
+
+    async fn in_history(&self, message_id: &str) -> Result<bool> {
+        Self::check(&self.fail_lookups)?;
+        self.inner.in_history(message_id).await
+    }
+
+    async fn purge_history_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_history_before(before).await
+    }
+
+    async fn record_audit(&self, entry: &AuditEntry) -> Result<()> {
+        self.inner.record_audit(entry).await
+    }
+
+    fn list_audit_since(&self, since: chrono::DateTime<chrono::Utc>) -> AuditStream<'_> {
+        self.inner.list_audit_since(since)
+    }
+
+    async fn purge_audit_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_audit_before(before).await
+    }
+
+    async fn record_change(&self, seq: u64, change: &str) -> Result<()> {
+        self.inner.record_change(seq, change).await
+    }
+
+    async fn append_changes(&self, changes: &[String]) -> Result<()> {
+        self.inner.append_changes(changes).await
+    }
+
+    async fn last_change_seq(&self) -> Result<u64> {
+        self.inner.last_change_seq().await
+    }
+
+    fn list_changes_since(&self, after: u64) -> ChangeStream<'_> {
+        self.inner.list_changes_since(after)
+    }
+
+    async fn purge_changes_before(&self, before: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        self.inner.purge_changes_before(before).await
+    }
+
+    async fn snapshot_to(&self, path: &std::path::Path) -> Result<()> {
+        self.inner.snapshot_to(path).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+}
+
+const ARTICLE: &str = "Message-ID: <retry@test>\r\nNewsgroups: test.group\r\nFrom: a@test\r\n\
+                       Subject: retry\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\r\nBody\r\n.";
+
+/// Drive `client` through a peer's session with `storage`, answered through
+/// `queue`.
+async fn run(client: ClientMock, storage: Arc<FailingStorage>, queue: &ArticleQueue) {
+    run_on(ListenerRole::Transit, client, storage, queue).await;
+}
+
+/// Drive `client` through a session on a listener of `role`.
+async fn run_on(
+    role: ListenerRole,
+    client: ClientMock,
+    storage: Arc<FailingStorage>,
+    queue: &ArticleQueue,
+) {
+    let (_, auth) = utils::setup().await;
+    let cfg = utils::create_minimal_config();
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &cfg);
+    let (end, server) = tokio::io::duplex(64 * 1024);
+    let task = tokio::spawn(renews::handle_client_with_info(
+        server,
+        storage,
+        auth,
+        Arc::new(RwLock::new(cfg)),
+        utils::listener_connection(false, role),
+        queue.clone(),
+        usage_tracker,
+    ));
+    let (reader, writer) = tokio::io::split(end);
+    client.drive(BufReader::new(reader), writer).await;
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn ihave_store_failure_asks_peer_to_retry() {
+    let storage = FailingStorage::new().await;
+    let queue = utils::create_test_queue();
+    storage.fail_stores.store(true, Ordering::SeqCst);
+    run(
+        ClientMock::new()
+            .expect(
+                "IHAVE <retry@test>",
+                "335 Send it; end with <CR-LF>.<CR-LF>",
+            )
+            .expect(ARTICLE, "436 transfer not possible; try again later"),
+        storage.clone(),
+        &queue,
+    )
+    .await;
+    assert_eq!(queue.ingest_stats().storage_deferrals, 1);
+    // The failed transfer is not remembered as a refusal
+    assert!(!storage.inner.in_history("<retry@test>").await.unwrap());
+
+    storage.fail_stores.store(false, Ordering::SeqCst);
+    run(
+        ClientMock::new()
+            .expect(
+                "IHAVE <retry@test>",
+                "335 Send it; end with <CR-LF>.<CR-LF>",
+            )
+            .expect(ARTICLE, "235 Article transferred OK")
+            .expect("IHAVE <retry@test>", "435 article not wanted"),
+        storage.clone(),
+        &queue,
+    )
+    .await;
+    assert!(
+        storage
+            .inner
+            .get_article_by_id("<retry@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+    let stats = queue.ingest_stats();
+    assert_eq!(stats.storage_deferrals, 1);
+    assert_eq!(stats.duplicates, 1);
+}
+
+#[tokio::test]
+async fn takethis_store_failure_asks_peer_to_retry() {
+    let storage = FailingStorage::new().await;
+    let queue = utils::create_test_queue();
+    storage.fail_stores.store(true, Ordering::SeqCst);
+    run(
+        ClientMock::new()
+            .expect("MODE STREAM", "203 Streaming permitted")
+            .expect(
+                &format!("TAKETHIS <retry@test>\r\n{ARTICLE}"),
+                "431 <retry@test>",
+            )
+            .expect("CHECK <retry@test>", "238 <retry@test>"),
+        storage.clone(),
+        &queue,
+    )
+    .await;
+    assert_eq!(queue.ingest_stats().storage_deferrals, 1);
+    assert!(!storage.inner.in_history("<retry@test>").await.unwrap());
+}
+
+#[tokio::test]
+async fn lookup_failure_asks_peer_to_retry() {
+    let storage = FailingStorage::new().await;
+    let queue = utils::create_test_queue();
+    storage.fail_lookups.store(true, Ordering::SeqCst);
+    run(
+        ClientMock::new()
+            .expect(
+                "IHAVE <retry@test>",
+                "436 transfer not possible; try again later",
+            )
+            .expect("MODE STREAM", "203 Streaming permitted")
+            .expect("CHECK <retry@test>", "431 <retry@test>")
+            .expect(
+                &format!("TAKETHIS <retry@test>\r\n{ARTICLE}"),
+                "431 <retry@test>",
+            ),
+        storage.clone(),
+        &queue,
+    )
+    .await;
+    assert_eq!(queue.ingest_stats().storage_deferrals, 3);
+
+    storage.fail_lookups.store(false, Ordering::SeqCst);
+    assert!(!storage.inner.in_history("<retry@test>").await.unwrap());
+    assert!(
+        storage
+            .inner
+            .get_article_by_id("<retry@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn postcheck_hides_storage_failures() {
+    let storage = FailingStorage::new().await;
+    let (_, auth) = utils::setup().await;
+    auth.add_user("user", "pass").await.unwrap();
+    storage.fail_lookups.store(true, Ordering::SeqCst);
+    // The storage error is logged, not shown to the client
+    ClientMock::new()
+        .expect("AUTHINFO USER user", "381 password required")
+        .expect("AUTHINFO PASS pass", "281 authentication accepted")
+        .expect(
+            "XPOSTCHECK",
+            "345 send headers to be checked. End with <CR-LF>.<CR-LF>",
+        )
+        .expect(
+            "Newsgroups: test.group\r\nFrom: a@test\r\nSubject: s\r\n.",
+            "441 posting failed",
+        )
+        .run_with_cfg_tls(utils::create_minimal_config(), storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn failure_during_a_listing_ends_the_session() {
+    let storage = FailingStorage::new().await;
+    let queue = utils::create_test_queue();
+    for n in 1..=2 {
+        let text = format!("Message-ID: <{n}@test>\r\nNewsgroups: test.group\r\n\r\nBody");
+        utils::store_test_article(&*storage.inner, &text).await;
+    }
+    storage.fail_lookups.store(true, Ordering::SeqCst);
+    // No status line is sent inside the open list; the connection is closed
+    run_on(
+        ListenerRole::All,
+        ClientMock::new().expect_multi(
+            "LISTGROUP test.group",
+            vec!["211 2 1 2 test.group list follows", "1", ""],
+        ),
+        storage,
+        &queue,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn crosspost_failing_in_one_shard_is_stored_in_none() {
+    use renews::config::ShardConfig;
+    use renews::storage::sharded::ShardedStorage;
+
+    let main = utils::create_test_storage().await;
+    main.add_group("misc.test", false).await.unwrap();
+    let alt = utils::create_test_storage().await;
+    alt.add_group("alt.test", false).await.unwrap();
+    let failing = FailingStorage::new().await;
+    let shard = |name: &str, pattern: &str| ShardConfig {
+        name: name.into(),
+        groups: vec![pattern.into()],
+        db_path: "sqlite::memory:".into(),
+    };
+    let storage = ShardedStorage::new(
+        main.clone(),
+        vec![
+            (shard("alt", "alt.*"), alt.clone()),
+            (shard("tests", "test.*"), failing.clone()),
+        ],
+    );
+    let (_, article) = renews::parse_message(
+        "Message-ID: <all@test>\r\nNewsgroups: misc.test,alt.test,test.group\r\n\r\nBody\r\n",
+    )
+    .unwrap();
+
+    // The first shard takes its share before the second fails
+    failing.fail_stores.store(true, Ordering::SeqCst);
+    assert!(storage.store_article(&article).await.is_err());
+    assert!(
+        storage
+            .store_articles(std::slice::from_ref(&article))
+            .await
+            .is_err()
+    );
+    assert!(alt.get_article_by_id("<all@test>").await.unwrap().is_none());
+    assert!(
+        main.get_article_by_id("<all@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(!storage.in_history("<all@test>").await.unwrap());
+
+    // Once the shard recovers the article is stored in all three, after
+    // the numbers the withdrawn stores took
+    failing.fail_stores.store(false, Ordering::SeqCst);
+    storage.store_article(&article).await.unwrap();
+    assert_eq!(
+        storage.get_article_numbers("<all@test>").await.unwrap(),
+        [
+            ("alt.test".to_string(), 3),
+            ("misc.test".to_string(), 1),
+            ("test.group".to_string(), 1)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn crosspost_is_logged_by_the_main_database_completing_it() {
+    use renews::config::ShardConfig;
+    use renews::storage::replicated::ReplicatedStorage;
+    use renews::storage::sharded::ShardedStorage;
+
+    let main = FailingStorage::new().await;
+    let shard = utils::create_test_storage().await;
+    shard.add_group("alt.test", false).await.unwrap();
+    let config = ShardConfig {
+        name: "alt".into(),
+        groups: vec!["alt.*".into()],
+        db_path: "sqlite::memory:".into(),
+    };
+    let sharded = ShardedStorage::new(main.clone(), vec![(config, shard.clone())]);
+    let storage = ReplicatedStorage::new(Arc::new(sharded));
+    let (_, article) = renews::parse_message(
+        "Message-ID: <both@test>\r\nNewsgroups: test.group,alt.test\r\n\r\nBody\r\n",
+    )
+    .unwrap();
+
+    // The shard is written first; the main database failing undoes it and
+    // nothing is logged
+    main.fail_stores.store(true, Ordering::SeqCst);
+    assert!(storage.store_article(&article).await.is_err());
+    assert!(
+        shard
+            .get_article_by_id("<both@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(storage.last_change_seq().await.unwrap(), 0);
+
+    main.fail_stores.store(false, Ordering::SeqCst);
+    storage.store_article(&article).await.unwrap();
+    assert_eq!(storage.last_change_seq().await.unwrap(), 1);
+    assert!(
+        shard
+            .get_article_by_id("<both@test>")
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
@@ -1,4 +1,6 @@
 use crate::utils::{self, ClientMock};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
 
 #[tokio::test]
 async fn tls_quit() {
@@ -179,3 +181,128 @@ async fn post_without_date_adds_header() {
     assert!(date.is_some());
     chrono::DateTime::parse_from_rfc2822(&date.unwrap()).unwrap();
 }
+
+/// Send `cmd` and read lines until one equals `last` (or after one line if `last` is empty).
+async fn exchange<S>(stream: &mut BufReader<S>, cmd: &str, last: &str) -> Vec<String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream
+        .get_mut()
+        .write_all(format!("{cmd}\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        let done = last.is_empty() || line == last;
+        lines.push(line);
+        if done {
+            return lines;
+        }
+    }
+}
+
+#[tokio::test]
+async fn starttls_upgrades_plaintext_connection() {
+    let (storage, auth) = utils::setup().await;
+    auth.add_user("user", "pass").await.unwrap();
+    let (addr, cert, handle) = utils::setup_starttls_server(storage, auth).await;
+
+    let mut plain = BufReader::new(TcpStream::connect(addr).await.unwrap());
+    let mut greeting = String::new();
+    plain.read_line(&mut greeting).await.unwrap();
+    assert!(greeting.starts_with("201"));
+
+    let caps = exchange(&mut plain, "CAPABILITIES", ".").await;
+    assert!(caps.iter().any(|l| l == "STARTTLS"));
+    assert!(!caps.iter().any(|l| l.starts_with("AUTHINFO")));
+    assert_eq!(
+        exchange(&mut plain, "AUTHINFO USER user", "").await,
+        vec!["483 Secure connection required"]
+    );
+    assert_eq!(
+        exchange(&mut plain, "STARTTLS", "").await,
+        vec!["382 Continue with TLS negotiation"]
+    );
+
+    let tls = utils::client_tls_handshake(plain.into_inner(), cert).await;
+    let mut tls = BufReader::new(tls);
+    let caps = exchange(&mut tls, "CAPABILITIES", ".").await;
+    assert!(!caps.iter().any(|l| l == "STARTTLS"));
+    assert!(caps.iter().any(|l| l == "AUTHINFO USER"));
+    assert_eq!(
+        exchange(&mut tls, "STARTTLS", "").await,
+        vec!["502 TLS already active or session authenticated"]
+    );
+    assert_eq!(
+        exchange(&mut tls, "AUTHINFO USER user", "").await,
+        vec!["381 password required"]
+    );
+    assert_eq!(
+        exchange(&mut tls, "AUTHINFO PASS pass", "").await,
+        vec!["281 authentication accepted"]
+    );
+    assert_eq!(
+        exchange(&mut tls, "QUIT", "").await,
+        vec!["205 closing connection"]
+    );
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn starttls_unavailable_without_certificate() {
+    let (storage, auth) = utils::setup().await;
+    ClientMock::new()
+        .expect("STARTTLS", "580 Can not initiate TLS negotiation")
+        .expect("QUIT", "205 closing connection")
+        .run(storage, auth)
+        .await;
+}
+
+/// Connect with a client certificate for `name` and return the greeting,
+/// the capabilities and the reply to MODE READER.
+async fn client_cert_session(
+    name: &str,
+    auth: std::sync::Arc<dyn renews::auth::AuthProvider>,
+) -> (String, Vec<String>, Vec<String>) {
+    let (storage, _) = utils::setup().await;
+    let (ca_pem, client_cert, client_key) = utils::generate_client_cert(name);
+    let dir = tempfile::tempdir().unwrap();
+    let ca_file = dir.path().join("ca.pem");
+    std::fs::write(&ca_file, ca_pem).unwrap();
+    let (addr, cert, handle) =
+        utils::setup_client_cert_server(storage, auth, ca_file.to_str().unwrap()).await;
+
+    let mut tls = utils::connect_tls_with_client_cert(addr, cert, (client_cert, client_key)).await;
+    let mut greeting = String::new();
+    tls.read_line(&mut greeting).await.unwrap();
+    let caps = exchange(&mut tls, "CAPABILITIES", ".").await;
+    let mode = exchange(&mut tls, "MODE READER", "").await;
+    exchange(&mut tls, "QUIT", "").await;
+    handle.await.unwrap();
+    (greeting, caps, mode)
+}
+
+#[tokio::test]
+async fn client_certificate_authenticates_known_user() {
+    let (_, auth) = utils::setup().await;
+    auth.add_user("feeder", "unused").await.unwrap();
+
+    let (greeting, caps, mode) = client_cert_session("feeder", auth).await;
+    assert!(greeting.starts_with("200"));
+    assert!(!caps.iter().any(|l| l.starts_with("AUTHINFO")));
+    assert_eq!(mode, vec!["200 Posting allowed"]);
+}
+
+#[tokio::test]
+async fn client_certificate_for_unknown_user_does_not_authenticate() {
+    let (_, auth) = utils::setup().await;
+
+    let (greeting, caps, mode) = client_cert_session("stranger", auth).await;
+    assert!(greeting.starts_with("201"));
+    assert!(caps.iter().any(|l| l == "AUTHINFO USER"));
+    assert_eq!(mode, vec!["201 Posting prohibited"]);
+}
@@ -0,0 +1,100 @@
+use crate::utils::{self, ClientMock};
+use renews::config::{ListenerPolicy, VhostConfig};
+use renews::vhost::{Site, VirtualHost, VirtualHosts, site_config};
+use renews::{ConnectionInfo, handle_client_with_info};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+
+/// Start a server for one connection accepted under `policy`, serving the
+/// main site `test` with group `misc` and the virtual host `community`,
+/// known as `news.community.example`, with group `local.chat`.
+async fn start_vhost_server(
+    policy: ListenerPolicy,
+) -> (std::net::SocketAddr, tokio::task::JoinHandle<()>) {
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    auth.add_user("alice", "secret").await.unwrap();
+    let mut config = utils::create_minimal_config();
+    config.allow_auth_insecure_connections = true;
+    let vhost_config = VhostConfig {
+        name: "community".into(),
+        hostnames: vec!["news.community.example".into()],
+        site_name: None,
+        db_path: "sqlite::memory:".into(),
+    };
+    let vhost_storage = utils::create_test_storage().await;
+    vhost_storage.add_group("local.chat", false).await.unwrap();
+    let vhosts = Arc::new(VirtualHosts::new(vec![VirtualHost {
+        site: Site {
+            storage: vhost_storage,
+            config: Arc::new(RwLock::new(site_config(&config, &vhost_config))),
+            queue: utils::create_test_queue(),
+        },
+        config: vhost_config,
+    }]));
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &config);
+    let cfg = Arc::new(RwLock::new(config));
+    let queue = utils::create_test_queue();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        let info = ConnectionInfo {
+            policy,
+            vhosts,
+            ..ConnectionInfo::default()
+        };
+        handle_client_with_info(sock, storage, auth, cfg, info, queue, usage_tracker)
+            .await
+            .unwrap();
+    });
+    (addr, handle)
+}
+
+#[tokio::test]
+async fn xhost_switches_between_sites() {
+    let (addr, handle) = start_vhost_server(ListenerPolicy::default()).await;
+
+    let mut capabilities = utils::capabilities_lines();
+    capabilities.insert(4, "AUTHINFO USER".into());
+    capabilities.insert(5, "XHOST".into());
+    ClientMock::new()
+        .expect_multi("CAPABILITIES", capabilities)
+        .expect("GROUP misc", "211 0 0 0 misc")
+        .expect("XHOST news.community.example", "290 virtual host selected")
+        .expect("GROUP misc", "411 no such newsgroup")
+        .expect("GROUP local.chat", "211 0 0 0 local.chat")
+        .expect("XHOST elsewhere.example", "501 unknown virtual host")
+        .expect("XHOST TEST", "290 virtual host selected")
+        .expect("GROUP local.chat", "411 no such newsgroup")
+        .expect("XHOST", "501 Syntax error")
+        .expect("AUTHINFO USER alice", "381 password required")
+        .expect("AUTHINFO PASS secret", "281 authentication accepted")
+        .expect(
+            "XHOST community",
+            "502 virtual host cannot be changed after authentication",
+        )
+        .expect("QUIT", "205 closing connection")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn listener_serves_its_virtual_host() {
+    let policy = ListenerPolicy {
+        vhost: Some("community".into()),
+        ..ListenerPolicy::default()
+    };
+    let (addr, handle) = start_vhost_server(policy).await;
+
+    ClientMock::new()
+        .expect("GROUP local.chat", "211 0 0 0 local.chat")
+        .expect("GROUP misc", "411 no such newsgroup")
+        .expect("QUIT", "205 closing connection")
+        .run_tcp_at(addr)
+        .await;
+    handle.await.unwrap();
+}
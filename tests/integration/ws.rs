@@ -48,4 +48,297 @@ mod websocket_bridge {
         ws_handle.abort();
         nntp_handle.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn multiplexed_channels_have_independent_sessions() {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+        let (storage, auth) = utils::setup().await;
+        storage.add_group("test.group", false).await.unwrap();
+
+        // Accept any number of NNTP connections, one per channel
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let nntp_port = listener.local_addr().unwrap().port();
+        let nntp_cfg: Arc<RwLock<Config>> =
+            Arc::new(RwLock::new(toml::from_str("addr=\":119\"").unwrap()));
+        let nntp_handle = tokio::spawn(async move {
+            loop {
+                let (sock, _) = listener.accept().await.unwrap();
+                let usage_tracker =
+                    utils::create_test_usage_tracker(auth.clone(), &*nntp_cfg.read().await);
+                tokio::spawn(renews::handle_client(
+                    sock,
+                    storage.clone(),
+                    auth.clone(),
+                    nntp_cfg.clone(),
+                    false,
+                    utils::create_test_queue(),
+                    usage_tracker,
+                ));
+            }
+        });
+
+        let ws_port = free_port();
+        let cfg: Config = toml::from_str(&format!(
+            "addr=\"127.0.0.1:{nntp_port}\"\nws_addr=\":{ws_port}\""
+        ))
+        .unwrap();
+        let ws_handle = tokio::spawn(ws::run_ws_bridge(Arc::new(RwLock::new(cfg))));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let mut request = format!("ws://127.0.0.1:{ws_port}")
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(ws::MULTIPLEX_PROTOCOL),
+        );
+        let (mut stream, response) = connect_async(request).await.unwrap();
+        assert_eq!(
+            response.headers().get("Sec-WebSocket-Protocol").unwrap(),
+            ws::MULTIPLEX_PROTOCOL
+        );
+
+        // Read frames for `channel` until `lines` full lines have arrived
+        async fn recv_lines(
+            stream: &mut (
+                     impl StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>>
+                     + Unpin
+                 ),
+            channel: u32,
+            lines: usize,
+        ) -> String {
+            let mut text = String::new();
+            while text.matches("\r\n").count() < lines {
+                match stream.next().await.unwrap().unwrap() {
+                    Message::Binary(b) => {
+                        let (id, payload) = ws::decode_frame(&b).unwrap();
+                        assert_eq!(id, channel);
+                        text.push_str(std::str::from_utf8(payload).unwrap());
+                    }
+                    other => panic!("unexpected message {other:?}"),
+                }
+            }
+            text
+        }
+
+        // Opening a channel is implicit in its first frame
+        stream
+            .send(Message::Binary(ws::encode_frame(
+                1,
+                b"GROUP test.group\r\n",
+            )))
+            .await
+            .unwrap();
+        assert_eq!(
+            recv_lines(&mut stream, 1, 2).await,
+            "201 NNTP Service Ready - no posting allowed\r\n211 0 0 0 test.group\r\n"
+        );
+
+        // A second channel has its own session with no group selected
+        stream
+            .send(Message::Binary(ws::encode_frame(2, b"OVER\r\n")))
+            .await
+            .unwrap();
+        assert_eq!(
+            recv_lines(&mut stream, 2, 2).await,
+            "201 NNTP Service Ready - no posting allowed\r\n412 no newsgroup selected\r\n"
+        );
+
+        // QUIT closes only that channel, signalled by an empty frame
+        stream
+            .send(Message::Binary(ws::encode_frame(2, b"QUIT\r\n")))
+            .await
+            .unwrap();
+        assert_eq!(
+            recv_lines(&mut stream, 2, 1).await,
+            "205 closing connection\r\n"
+        );
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            Message::Binary(ws::encode_frame(2, &[]))
+        );
+
+        stream
+            .send(Message::Binary(ws::encode_frame(1, b"OVER\r\n")))
+            .await
+            .unwrap();
+        assert_eq!(
+            recv_lines(&mut stream, 1, 1).await,
+            "420 no current article selected\r\n"
+        );
+
+        ws_handle.abort();
+        nntp_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn multiplexed_socket_outlives_failed_channels() {
+        use tokio::io::AsyncWriteExt;
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+        // A server that takes one connection, then stops listening and
+        // keeps that connection open whatever the bridge does
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let nntp_port = listener.local_addr().unwrap().port();
+        let nntp_handle = tokio::spawn(async move {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            drop(listener);
+            sock.write_all(b"201 hello\r\n").await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let ws_port = free_port();
+        let cfg: Config = toml::from_str(&format!(
+            "addr=\"127.0.0.1:{nntp_port}\"\nws_addr=\":{ws_port}\""
+        ))
+        .unwrap();
+        let ws_handle = tokio::spawn(ws::run_ws_bridge(Arc::new(RwLock::new(cfg))));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let mut request = format!("ws://127.0.0.1:{ws_port}")
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(ws::MULTIPLEX_PROTOCOL),
+        );
+        let (mut stream, _) = connect_async(request).await.unwrap();
+        let wait = std::time::Duration::from_secs(5);
+
+        stream
+            .send(Message::Binary(ws::encode_frame(1, b"DATE\r\n")))
+            .await
+            .unwrap();
+        assert_eq!(
+            tokio::time::timeout(wait, stream.next())
+                .await
+                .expect("bridge answers")
+                .unwrap()
+                .unwrap(),
+            Message::Binary(ws::encode_frame(1, b"201 hello\r\n"))
+        );
+
+        // The second channel cannot be opened and is closed on its own
+        stream
+            .send(Message::Binary(ws::encode_frame(2, b"DATE\r\n")))
+            .await
+            .unwrap();
+        assert_eq!(
+            tokio::time::timeout(wait, stream.next())
+                .await
+                .expect("bridge answers")
+                .unwrap()
+                .unwrap(),
+            Message::Binary(ws::encode_frame(2, &[]))
+        );
+
+        // Closing the socket does not wait for the NNTP side of channel 1
+        stream.send(Message::Close(None)).await.unwrap();
+        let closed = tokio::time::timeout(wait, async {
+            while let Some(Ok(msg)) = stream.next().await {
+                if msg.is_close() {
+                    break;
+                }
+            }
+        })
+        .await;
+        assert!(closed.is_ok(), "bridge kept the socket open");
+
+        ws_handle.abort();
+        nntp_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn json_session_returns_structured_responses() {
+        use serde_json::{Value, json};
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+        let (storage, auth) = utils::setup().await;
+        storage.add_group("misc.test", false).await.unwrap();
+        utils::store_test_article(
+            &*storage,
+            "Message-ID: <json@test>\r\nNewsgroups: misc.test\r\nFrom: a@test\r\n\
+             Subject: hello\r\n\r\nFirst line\r\n.dotted\r\n",
+        )
+        .await;
+        let (nntp_addr, _, nntp_handle) = utils::start_server(
+            storage,
+            auth,
+            toml::from_str("addr=\":119\"").unwrap(),
+            false,
+        )
+        .await;
+        let ws_port = free_port();
+        let cfg: Config = toml::from_str(&format!(
+            "addr=\"127.0.0.1:{}\"\nws_addr=\":{ws_port}\"",
+            nntp_addr.port()
+        ))
+        .unwrap();
+        let ws_handle = tokio::spawn(ws::run_ws_bridge(Arc::new(RwLock::new(cfg))));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let mut request = format!("ws://127.0.0.1:{ws_port}")
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(ws::JSON_PROTOCOL),
+        );
+        let (mut stream, response) = connect_async(request).await.unwrap();
+        assert_eq!(
+            response.headers().get("Sec-WebSocket-Protocol").unwrap(),
+            ws::JSON_PROTOCOL
+        );
+
+        async fn recv(
+            stream: &mut (
+                     impl StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>>
+                     + Unpin
+                 ),
+        ) -> Value {
+            match stream.next().await.unwrap().unwrap() {
+                Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                other => panic!("unexpected message {other:?}"),
+            }
+        }
+
+        assert_eq!(recv(&mut stream).await["code"], 201);
+
+        let request = |value: Value| Message::Text(value.to_string());
+        stream
+            .send(request(json!({"cmd": "group", "name": "misc.test"})))
+            .await
+            .unwrap();
+        assert_eq!(
+            recv(&mut stream).await,
+            json!({"code": 211, "text": "1 1 1 misc.test"})
+        );
+
+        stream
+            .send(request(json!({"cmd": "article", "id": 1})))
+            .await
+            .unwrap();
+        let article = recv(&mut stream).await;
+        assert_eq!(article["code"], 220);
+        assert!(
+            article["headers"]
+                .as_array()
+                .unwrap()
+                .contains(&json!(["Subject", "hello"]))
+        );
+        assert_eq!(article["body"], "First line\n.dotted\n");
+
+        stream.send(Message::Text("not json".into())).await.unwrap();
+        assert!(recv(&mut stream).await["error"].is_string());
+
+        stream.send(request(json!({"cmd": "quit"}))).await.unwrap();
+        assert_eq!(recv(&mut stream).await["code"], 205);
+
+        ws_handle.abort();
+        nntp_handle.await.unwrap();
+    }
 }
@@ -0,0 +1,127 @@
+//! Localized status texts.
+
+use renews::config::{Config, ListenerPolicy};
+use renews::{ConnectionInfo, handle_client_with_info};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+use utils::ClientMock;
+
+mod utils;
+
+#[tokio::test]
+async fn status_lines_use_the_configured_locale() {
+    let cfg: Config = toml::from_str(
+        r#"addr = ":119"
+[responses]
+locale = "fr"
+[responses.fr]
+211 = "liste suit"
+411 = "groupe inconnu"
+500 = "commande inconnue"
+501 = "argument invalide"
+"#,
+    )
+    .unwrap();
+
+    let (storage, auth) = utils::setup().await;
+    storage.add_group("misc", false).await.unwrap();
+    ClientMock::new()
+        .expect("GROUP nowhere", "411 groupe inconnu")
+        .expect("FROB", "500 commande inconnue")
+        .expect("GROUP misc", "211 0 0 0 misc")
+        .expect_multi("LISTGROUP misc", vec!["211 0 0 0 misc liste suit", "."])
+        .expect("NEXT 1", "501 argument invalide: 1")
+        .expect("OVER 5-x", "501 argument invalide: 5-x")
+        .run_with_cfg(cfg, storage.clone(), auth.clone())
+        .await;
+
+    // Sessions of a site without a locale keep the built-in texts
+    ClientMock::new()
+        .expect("GROUP nowhere", "411 no such newsgroup")
+        .run(storage, auth)
+        .await;
+}
+
+#[tokio::test]
+async fn listener_locale_applies_texts_from_files() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("de.toml"),
+        r#"[de]
+200 = "Willkommen"
+201 = "Willkommen"
+411 = "Newsgroup unbekannt"
+help = """
+Befehle:
+.ARTICLE
+"""
+"#,
+    )
+    .unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(
+        &path,
+        r#"addr = ":119"
+[responses]
+files = ["de.toml"]
+[responses.de]
+411 = "Gruppe unbekannt"
+"#,
+    )
+    .unwrap();
+    let config = Config::from_file(path.to_str().unwrap()).unwrap();
+
+    let (storage, auth) = utils::setup().await;
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &config);
+    let cfg = Arc::new(RwLock::new(config));
+    let queue =
+        utils::create_test_queue_with_workers(storage.clone(), auth.clone(), cfg.clone()).await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        let info = ConnectionInfo {
+            policy: ListenerPolicy {
+                locale: Some("de-CH".into()),
+                ..ListenerPolicy::default()
+            },
+            ..ConnectionInfo::default()
+        };
+        handle_client_with_info(sock, storage, auth, cfg, info, queue, usage_tracker)
+            .await
+            .unwrap();
+    });
+
+    let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+    let mut greeting = String::new();
+    stream.read_line(&mut greeting).await.unwrap();
+    assert_eq!(&greeting[3..], " Willkommen\r\n");
+
+    stream
+        .get_mut()
+        .write_all(b"GROUP nowhere\r\nHELP\r\nQUIT\r\n")
+        .await
+        .unwrap();
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.unwrap() == 0 {
+            break;
+        }
+        lines.push(line);
+    }
+    assert_eq!(
+        lines,
+        [
+            "411 Gruppe unbekannt\r\n",
+            "100 help text follows\r\n",
+            "Befehle:\r\n",
+            "..ARTICLE\r\n",
+            ".\r\n",
+            "205 closing connection\r\n",
+        ]
+    );
+    handle.await.unwrap();
+}
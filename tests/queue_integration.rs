@@ -4,7 +4,7 @@ mod utils;
 
 use renews::{
     auth::{AuthProvider, sqlite::SqliteAuth},
-    config::Config,
+    config::{Config, ListenerRole},
     limits::UsageTracker,
     queue::{ArticleQueue, WorkerPool},
     storage::{Storage, sqlite::SqliteStorage},
@@ -25,28 +25,76 @@ async fn setup_queue_enabled_server() -> (std::net::SocketAddr, Arc<dyn Storage>
 
     // Create config with queue settings
     let config = Config {
-        addr: "127.0.0.1:0".to_string(),
+        addr: vec!["127.0.0.1:0".to_string()],
         site_name: "test".to_string(),
+        path_aliases: Vec::new(),
         db_path: "sqlite::memory:".to_string(),
         auth_db_path: "sqlite::memory:".to_string(),
         peer_db_path: "sqlite::memory:".to_string(),
         peer_sync_schedule: "0 0 * * * *".to_string(),
         idle_timeout_secs: 600,
+        clock_skew_secs: 300,
+        max_message_bytes: Some(64 * 1024 * 1024),
+        max_over_range: None,
+        overview_headers: Vec::new(),
         peers: vec![],
         tls_addr: Some("127.0.0.1:0".to_string()),
         tls_cert: None,
         tls_key: None,
+        tls_client_ca: None,
+        tls: Default::default(),
         ws_addr: None,
+        http_addr: None,
+        control_socket: None,
+        health_addr: None,
+        gateway_addr: None,
+        listeners: vec![],
         article_queue_capacity: 100,
         article_worker_count: 2,
+        article_worker_max_count: None,
+        article_queue_journal: None,
+        transit_validation: Default::default(),
+        article_cache_bytes: None,
+        article_cache_ttl_secs: None,
+        overview_cache_bytes: None,
         runtime_threads: 1,
         group_settings: vec![],
+        default_subscriptions: vec![],
         filters: vec![],
+        digests: vec![],
+        digest_schedule: "0 0 0 * * *".to_string(),
+        bridges: vec![],
+        gateways: vec![],
+        bridge_schedule: "0 */15 * * * *".to_string(),
+        sendmail_path: "/usr/sbin/sendmail".to_string(),
         pgp_key_servers: renews::config::default_pgp_key_servers(),
+        pgp_key_refresh_secs: 0,
         allow_auth_insecure_connections: false,
+        require_tls_for_auth: false,
         allow_anonymous_posting: false,
+        cancel_lock_secret: None,
+        detect_binaries: false,
+        history_retention_days: 10,
+        audit_retention_days: 365,
+        rejected_retention_days: 0,
+        maintenance_schedule: None,
+        max_connections_per_ip: 0,
         logging: Default::default(),
         user_limits: Default::default(),
+        posting_accounts: Default::default(),
+        address_bans: Default::default(),
+        login_throttle: Default::default(),
+        responses: Default::default(),
+        nocem: Default::default(),
+        trace: Default::default(),
+        control_policy: Vec::new(),
+        checkgroups_mode: Default::default(),
+        sqlite: Default::default(),
+        shards: Vec::new(),
+        replication: Default::default(),
+        unix_socket: Default::default(),
+        auth_program: None,
+        vhosts: vec![],
     };
 
     // Since we can't easily test with TLS in this setup, we'll create a simplified server
@@ -203,3 +251,326 @@ async fn test_queue_validation_failure() {
 
     writer.write_all(b"QUIT\r\n").await.unwrap();
 }
+
+#[tokio::test]
+async fn test_queue_journal_replays_pending_articles() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal_path = dir.path().join("queue.journal");
+
+    // Submit an article with no workers running, simulating a crash before
+    // the article was processed
+    let queue = ArticleQueue::with_journal(10, &journal_path).await.unwrap();
+    let mut article = utils::create_test_queued_article(
+        "<journal@test>",
+        "test.group",
+        "Replayed after restart\r\n",
+    );
+    article.already_validated = true;
+    queue.submit(article).await.unwrap();
+    drop(queue);
+
+    // Reopen the journal and start workers; the article should be stored
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("test.group", false).await.unwrap();
+    let auth: Arc<dyn AuthProvider> = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    let config = Arc::new(RwLock::new(utils::create_minimal_config()));
+
+    let queue = ArticleQueue::with_journal(10, &journal_path).await.unwrap();
+    let pool = WorkerPool::new(queue.clone(), storage.clone(), auth, config, 1);
+    let _handles = pool.start().await;
+
+    let mut stored = None;
+    for _ in 0..50 {
+        stored = storage.get_article_by_id("<journal@test>").await.unwrap();
+        if stored.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(stored.is_some(), "journaled article was not replayed");
+
+    // Once processed the article is marked done and not replayed again
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let journal = renews::queue::QueueJournal::open(&journal_path)
+        .await
+        .unwrap();
+    assert!(journal.take_pending().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_queue_stores_pending_articles_in_order() {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("test.group", false).await.unwrap();
+    let auth: Arc<dyn AuthProvider> = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    let config = Arc::new(RwLock::new(utils::create_minimal_config()));
+
+    // Queue articles before any worker runs, so they are taken as one batch;
+    // the repeated Message-ID must only be stored once
+    let queue = ArticleQueue::new(100);
+    for n in (0..20).chain([5]) {
+        let mut article = utils::create_test_queued_article(
+            &format!("<batch{n}@test>"),
+            "test.group",
+            &format!("Batch article {n}\r\n"),
+        );
+        article.already_validated = true;
+        queue.submit(article).await.unwrap();
+    }
+
+    let pool = WorkerPool::new(queue.clone(), storage.clone(), auth, config, 1);
+    let _handles = pool.start().await;
+
+    for _ in 0..50 {
+        if queue.is_empty()
+            && storage
+                .get_article_by_id("<batch19@test>")
+                .await
+                .unwrap()
+                .is_some()
+        {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    for n in 0..20u64 {
+        let article = storage
+            .get_article_by_number("test.group", n + 1)
+            .await
+            .unwrap()
+            .expect("article by number");
+        assert_eq!(article.body, format!("Batch article {n}\r\n"));
+    }
+    assert!(
+        storage
+            .get_article_by_number("test.group", 21)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn test_full_queue_defers_streaming_articles() {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("test.group", false).await.unwrap();
+    let auth: Arc<dyn AuthProvider> = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    let cfg = utils::create_minimal_config();
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &cfg);
+
+    // Fill the queue with no workers running
+    let queue = ArticleQueue::new(1);
+    queue
+        .submit(utils::create_test_queued_article(
+            "<filler@test>",
+            "test.group",
+            "Filler\r\n",
+        ))
+        .await
+        .unwrap();
+    assert!(queue.is_full());
+    assert!(
+        queue
+            .try_submit(utils::create_test_queued_article(
+                "<extra@test>",
+                "test.group",
+                "Extra\r\n",
+            ))
+            .await
+            .is_err()
+    );
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let task = tokio::spawn(renews::handle_client_with_info(
+        server,
+        storage.clone(),
+        auth,
+        Arc::new(RwLock::new(cfg)),
+        utils::listener_connection(false, ListenerRole::Transit),
+        queue.clone(),
+        usage_tracker,
+    ));
+    let (reader, writer) = tokio::io::split(client);
+    let article = "Message-ID: <later@test>\r\nNewsgroups: test.group\r\nFrom: a@test\r\n\
+                   Subject: later\r\nDate: Wed, 05 Oct 2022 00:00:00 GMT\r\n\r\nBody\r\n.";
+    utils::ClientMock::new()
+        .expect(
+            "IHAVE <later@test>",
+            "436 transfer not possible; try again later",
+        )
+        .expect("MODE STREAM", "203 Streaming permitted")
+        .expect("CHECK <later@test>", "431 <later@test>")
+        .expect(
+            &format!("TAKETHIS <later@test>\r\n{article}"),
+            "431 <later@test>",
+        )
+        .drive(BufReader::new(reader), writer)
+        .await;
+    task.await.unwrap().unwrap();
+
+    // Deferred articles are neither stored nor remembered as refused
+    assert!(
+        storage
+            .get_article_by_id("<later@test>")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        !renews::history::seen(&*storage, "<later@test>")
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_full_queue_refuses_posts_with_retry_hint() {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("test.group", false).await.unwrap();
+    let auth: Arc<dyn AuthProvider> = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    let mut cfg = utils::create_minimal_config();
+    cfg.allow_anonymous_posting = true;
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &cfg);
+
+    // Fill the queue with no workers running, so no drain rate is known yet
+    let queue = ArticleQueue::new(1);
+    queue
+        .submit(utils::create_test_queued_article(
+            "<filler@test>",
+            "test.group",
+            "Filler\r\n",
+        ))
+        .await
+        .unwrap();
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let task = tokio::spawn(renews::handle_client(
+        server,
+        storage,
+        auth,
+        Arc::new(RwLock::new(cfg)),
+        true,
+        queue,
+        usage_tracker,
+    ));
+    let (reader, writer) = tokio::io::split(client);
+    utils::ClientMock::new()
+        .expect("POST", "440 posting not allowed, retry in 30s")
+        .drive(BufReader::new(reader), writer)
+        .await;
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_bandwidth_limited_posts_are_told_when_to_retry() {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("test.group", false).await.unwrap();
+    let auth: Arc<dyn AuthProvider> = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    auth.add_user("testuser", "password").await.unwrap();
+    let mut cfg = utils::create_minimal_config();
+    cfg.user_limits.bandwidth_limit = Some(2000);
+    cfg.user_limits.bandwidth_period = Some(3600);
+    let usage_tracker = utils::create_test_usage_tracker(auth.clone(), &cfg);
+    usage_tracker
+        .record_bandwidth("testuser", 1500, false)
+        .await;
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let task = tokio::spawn(renews::handle_client(
+        server,
+        storage,
+        auth,
+        Arc::new(RwLock::new(cfg)),
+        true,
+        ArticleQueue::new(10),
+        usage_tracker.clone(),
+    ));
+    let (mut reader, mut writer) = tokio::io::split(client);
+    let mut reader = BufReader::new(&mut reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    for command in ["AUTHINFO USER testuser", "AUTHINFO PASS password"] {
+        writer
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await
+            .unwrap();
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+    }
+    assert!(line.starts_with("281"), "{line}");
+    usage_tracker
+        .record_bandwidth("testuser", 1500, false)
+        .await;
+
+    writer.write_all(b"POST\r\n").await.unwrap();
+    line.clear();
+    reader.read_line(&mut line).await.unwrap();
+    assert!(line.starts_with("340"), "{line}");
+    let article = format!(
+        "From: test@example.com\r\nSubject: Big\r\nNewsgroups: test.group\r\n\
+         Message-ID: <big@example.com>\r\n\r\n{}\r\n.\r\n",
+        "x".repeat(600)
+    );
+    writer.write_all(article.as_bytes()).await.unwrap();
+    line.clear();
+    reader.read_line(&mut line).await.unwrap();
+    let secs = renews::responses::retry_after(&line).expect("retry hint");
+    assert!(line.starts_with("441 posting failed, retry in "), "{line}");
+    assert!((3500..=3600).contains(&secs), "{line}");
+
+    // The refused post still crossed the limit, which ends the session
+    line.clear();
+    reader.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "502 bandwidth limit exceeded\r\n");
+    drop(writer);
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_worker_pool_grows_while_articles_wait() {
+    let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::new("sqlite::memory:").await.unwrap());
+    storage.add_group("test.group", false).await.unwrap();
+    let auth: Arc<dyn AuthProvider> = Arc::new(SqliteAuth::new("sqlite::memory:").await.unwrap());
+    let config = Arc::new(RwLock::new(utils::create_minimal_config()));
+
+    let queue = ArticleQueue::new(1000);
+    for n in 0..300 {
+        let mut article = utils::create_test_queued_article(
+            &format!("<scale{n}@test>"),
+            "test.group",
+            "Scaling\r\n",
+        );
+        article.already_validated = true;
+        queue.submit(article).await.unwrap();
+    }
+
+    // Workers wait for the configuration while it is locked, so the
+    // articles they have not taken are left waiting
+    let guard = config.write().await;
+    let pool = WorkerPool::new(queue.clone(), storage.clone(), auth, config.clone(), 1)
+        .with_max_workers(3);
+    let _handles = pool.start().await;
+    for _ in 0..100 {
+        if queue.worker_count() == 3 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(queue.worker_count(), 3);
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert_eq!(queue.worker_count(), 3, "grew beyond the maximum");
+    drop(guard);
+
+    // Each worker records its batch once it has stored it
+    for _ in 0..200 {
+        if queue.is_empty() && queue.worker_histograms().len() == 3 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(queue.is_empty());
+    let histograms = queue.worker_histograms();
+    assert_eq!(histograms.len(), 3);
+    assert!(histograms.iter().all(|(_, h)| h.count > 0));
+}
@@ -2,10 +2,14 @@
 mod config;
 #[path = "unit/config_failures.rs"]
 mod config_failures;
+#[path = "unit/datetime.rs"]
+mod datetime;
 #[path = "unit/filters.rs"]
 mod filters;
 #[path = "unit/parse_failures.rs"]
 mod parse_failures;
+#[path = "unit/read_article.rs"]
+mod read_article;
 #[path = "unit/storage_common.rs"]
 mod storage_common;
 #[path = "unit/wildmat.rs"]
@@ -57,7 +57,7 @@ retention_days = 1
     let new_cfg: Config = toml::from_str(updated).unwrap();
     cfg.update_runtime(new_cfg);
 
-    assert_eq!(cfg.addr, ":119");
+    assert_eq!(cfg.addr, [":119"]);
     assert_eq!(cfg.db_path, "/tmp/db1");
     assert_eq!(cfg.auth_db_path, "sqlite:///tmp/auth1");
     assert_eq!(cfg.peer_db_path, "/tmp/peer1");
@@ -101,11 +101,19 @@ idle_timeout_secs = 1200
     cfg.update_runtime(new_cfg);
 
     // Addr should be preserved (immutable)
-    assert_eq!(cfg.addr, ":119");
+    assert_eq!(cfg.addr, [":119"]);
     // Idle timeout should be updated (runtime-adjustable)
     assert_eq!(cfg.idle_timeout_secs, 1200);
 }
 
+#[test]
+fn multiple_listen_addresses() {
+    let cfg: Config = toml::from_str(r#"addr = [":119", "[::]:119"]"#).unwrap();
+    assert_eq!(cfg.addr, [":119", "[::]:119"]);
+    assert!(toml::from_str::<Config>("addr = []").is_err());
+    assert!(toml::from_str::<Config>("addr = 119").is_err());
+}
+
 #[test]
 fn peer_connection_string_allows_credentials() {
     let toml = r#"addr = ":119"
@@ -127,7 +135,7 @@ fn env_substitution() {
     let cfg_path = dir.path().join("cfg.toml");
     write(&cfg_path, "addr = \"$ENV{TEST_ADDR}\"").unwrap();
     let cfg = Config::from_file(cfg_path.to_str().unwrap()).unwrap();
-    assert_eq!(cfg.addr, ":4242");
+    assert_eq!(cfg.addr, [":4242"]);
 }
 
 #[test]
@@ -143,7 +151,7 @@ fn file_substitution() {
     let mut f = File::create(&cfg_path).unwrap();
     write!(f, "addr = \"$FILE{{{}}}\"", val_path.display()).unwrap();
     let cfg = Config::from_file(cfg_path.to_str().unwrap()).unwrap();
-    assert_eq!(cfg.addr, ":5050");
+    assert_eq!(cfg.addr, [":5050"]);
 }
 
 #[test]
@@ -172,6 +180,68 @@ patterns = ["misc.*"]
     assert_eq!(cfg.peers[1].sync_schedule, None);
 }
 
+#[test]
+fn peer_modes() {
+    use renews::config::PeerMode;
+
+    let cfg_str = r#"addr = ":119"
+[[peers]]
+sitename = "push.example.com"
+
+[[peers]]
+sitename = "pull.example.com"
+mode = "pull"
+
+[[peers]]
+sitename = "both.example.com"
+mode = "both"
+"#;
+    let cfg: Config = toml::from_str(cfg_str).unwrap();
+    let modes: Vec<PeerMode> = cfg.peers.iter().map(|p| p.mode).collect();
+    assert_eq!(modes, [PeerMode::Push, PeerMode::Pull, PeerMode::Both]);
+    assert!(PeerMode::Both.pushes() && PeerMode::Both.pulls());
+    assert!(!PeerMode::Pull.pushes());
+
+    let bad = "addr = \":119\"\n[[peers]]\nsitename = \"x\"\nmode = \"sideways\"";
+    assert!(toml::from_str::<Config>(bad).is_err());
+}
+
+#[test]
+fn peer_transport_settings() {
+    let cfg_str = r#"addr = ":119"
+[[peers]]
+sitename = "default.example.com"
+
+[[peers]]
+sitename = "hub.example.com"
+tls = false
+username = "feed"
+password = "secret"
+
+[[peers]]
+sitename = "upstream.example.com"
+tls_client_cert = "/etc/renews/feed.pem"
+tls_client_key = "/etc/renews/feed.key"
+"#;
+    let cfg: Config = toml::from_str(cfg_str).unwrap();
+    let [default, plain, cert] = &cfg.peers[..] else {
+        panic!("expected three peers");
+    };
+    assert!(default.transport.tls);
+    assert_eq!(default.transport.username, None);
+    assert!(!plain.transport.tls);
+    assert_eq!(plain.transport.username.as_deref(), Some("feed"));
+    assert_eq!(plain.transport.password.as_deref(), Some("secret"));
+    assert!(cert.transport.tls);
+    assert_eq!(
+        cert.transport.tls_client_key.as_deref(),
+        Some("/etc/renews/feed.key")
+    );
+    for peer in &cfg.peers {
+        peer.transport.validate().unwrap();
+    }
+}
+
 #[test]
 fn group_pattern_specificity_with_non_overlapping_settings() {
     let toml = r#"addr = ":119"
@@ -265,7 +335,253 @@ runtime_threads = 8
     cfg.update_runtime(new_cfg);
 
     // Addr should be preserved (immutable)
-    assert_eq!(cfg.addr, ":119");
+    assert_eq!(cfg.addr, [":119"]);
     // Runtime threads should be updated (runtime-adjustable)
     assert_eq!(cfg.runtime_threads, 8);
 }
+
+#[test]
+fn include_merges_conf_d() {
+    use std::fs::{create_dir, write};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let cfg_path = dir.path().join("cfg.toml");
+    write(
+        &cfg_path,
+        r#"addr = ":119"
+include = ["conf.d/*.toml", "missing.d/*.toml"]
+idle_timeout_secs = 600
+[[group]]
+group = "misc"
+retention_days = 5
+"#,
+    )
+    .unwrap();
+    create_dir(dir.path().join("conf.d")).unwrap();
+    write(
+        dir.path().join("conf.d/10-groups.toml"),
+        r#"[[group]]
+pattern = "*"
+retention_days = 10
+"#,
+    )
+    .unwrap();
+    write(
+        dir.path().join("conf.d/20-timeout.toml"),
+        "idle_timeout_secs = 30\n",
+    )
+    .unwrap();
+    write(dir.path().join("conf.d/notes.txt"), "not toml").unwrap();
+
+    let cfg = Config::from_file(cfg_path.to_str().unwrap()).unwrap();
+    assert_eq!(cfg.idle_timeout_secs, 30);
+    assert_eq!(cfg.group_settings.len(), 2);
+    assert_eq!(cfg.retention_for_group("misc").unwrap().num_days(), 5);
+    assert_eq!(cfg.retention_for_group("other").unwrap().num_days(), 10);
+}
+
+#[test]
+fn include_cycle_is_rejected() {
+    use std::fs::write;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let cfg_path = dir.path().join("cfg.toml");
+    write(&cfg_path, "addr = \":119\"\ninclude = \"cfg.toml\"\n").unwrap();
+    let err = Config::from_file(cfg_path.to_str().unwrap()).err().unwrap();
+    assert!(err.to_string().contains("nested"));
+}
+
+#[test]
+fn article_cache_size() {
+    let cfg: Config = toml::from_str("addr = \":119\"").unwrap();
+    assert_eq!(cfg.article_cache_bytes, None);
+    assert_eq!(cfg.article_cache_ttl_secs, None);
+    let cfg: Config = toml::from_str("addr = \":119\"\narticle_cache_bytes = \"64M\"").unwrap();
+    assert_eq!(cfg.article_cache_bytes, Some(64 * 1024 * 1024));
+    let cfg: Config = toml::from_str("addr = \":119\"\narticle_cache_ttl_secs = 300").unwrap();
+    assert_eq!(cfg.article_cache_ttl_secs, Some(300));
+    let cfg: Config = toml::from_str("addr = \":119\"\noverview_cache_bytes = \"8M\"").unwrap();
+    assert_eq!(cfg.overview_cache_bytes, Some(8 * 1024 * 1024));
+}
+
+#[test]
+fn sqlite_tuning() {
+    use renews::config::SqliteJournal;
+    let cfg: Config = toml::from_str("addr = \":119\"").unwrap();
+    assert_eq!(cfg.sqlite.journal_mode, SqliteJournal::Wal);
+    assert_eq!(cfg.sqlite.busy_timeout_ms, 5000);
+    assert_eq!(cfg.sqlite.max_connections, 5);
+    let cfg: Config = toml::from_str(
+        "addr = \":119\"\n[sqlite]\njournal_mode = \"delete\"\nbusy_timeout_ms = 250\nmax_connections = 2",
+    )
+    .unwrap();
+    assert_eq!(cfg.sqlite.journal_mode, SqliteJournal::Delete);
+    assert_eq!(cfg.sqlite.busy_timeout_ms, 250);
+    assert_eq!(cfg.sqlite.max_connections, 2);
+}
+
+#[test]
+fn tls_settings() {
+    use renews::config::{CipherPolicy, TlsVersion};
+    let cfg: Config = toml::from_str("addr = \":119\"").unwrap();
+    assert_eq!(cfg.tls.min_version, TlsVersion::Tls12);
+    assert_eq!(cfg.tls.cipher_policy, CipherPolicy::Default);
+    assert_eq!(cfg.tls.ticket_lifetime_secs, 21600);
+    assert_eq!(cfg.tls.session_cache_size, 256);
+    assert_eq!(cfg.tls.handshakes_per_minute, 0);
+    let cfg: Config = toml::from_str(
+        "addr = \":119\"\n[tls]\nmin_version = \"1.3\"\ncipher_policy = \"strict\"\nticket_lifetime_secs = 0\nhandshakes_per_minute = 30",
+    )
+    .unwrap();
+    assert_eq!(cfg.tls.min_version, TlsVersion::Tls13);
+    assert_eq!(cfg.tls.cipher_policy, CipherPolicy::Strict);
+    assert_eq!(cfg.tls.ticket_lifetime_secs, 0);
+    assert_eq!(cfg.tls.handshakes_per_minute, 30);
+}
+
+#[test]
+fn listener_blocks() {
+    use renews::config::ListenerRole;
+
+    let toml = r#"addr = ":119"
+idle_timeout_secs = 600
+[[listener]]
+addr = ":433"
+role = "transit"
+idle_timeout_secs = 3600
+[[listener]]
+addr = ":563"
+tls = true
+tls_cert = "reader.pem"
+tls_key = "reader.key"
+role = "reader"
+allow_anonymous_posting = true
+"#;
+    let cfg: Config = toml::from_str(toml).unwrap();
+    assert_eq!(cfg.listeners.len(), 2);
+
+    let transit = &cfg.listeners[0];
+    assert!(!transit.tls);
+    assert_eq!(transit.policy.role, ListenerRole::Transit);
+    assert_eq!(transit.policy.idle_timeout_secs, Some(3600));
+    assert_eq!(transit.policy.allow_anonymous_posting, None);
+
+    let reader = &cfg.listeners[1];
+    assert!(reader.tls);
+    assert_eq!(reader.tls_cert.as_deref(), Some("reader.pem"));
+    assert_eq!(reader.policy.role, ListenerRole::Reader);
+    assert_eq!(reader.policy.idle_timeout_secs, None);
+    assert_eq!(reader.policy.allow_anonymous_posting, Some(true));
+
+    assert!(ListenerRole::Transit.permits("TAKETHIS"));
+    assert!(ListenerRole::Transit.permits("AUTHINFO"));
+    assert!(!ListenerRole::Transit.permits("ARTICLE"));
+    assert!(!ListenerRole::Reader.permits("IHAVE"));
+    assert!(ListenerRole::Reader.permits("POST"));
+}
+
+#[test]
+fn localized_responses_fall_back_to_parent_locale() {
+    use renews::responses::{RESP_411_NO_SUCH_GROUP, RESP_480_AUTH_REQUIRED, ResponseCatalog};
+
+    let toml = r#"addr = ":119"
+[responses]
+locale = "de-AT"
+[responses.de]
+411 = "Newsgroup unbekannt"
+480 = "Anmeldung erforderlich"
+[responses.de-AT]
+411 = "Die Newsgruppn gibt's ned"
+"#;
+    let cfg: Config = toml::from_str(toml).unwrap();
+    assert_eq!(cfg.responses.locale.as_deref(), Some("de-AT"));
+    assert_eq!(cfg.responses.locales.len(), 2);
+    cfg.responses.validate().unwrap();
+
+    let catalog = ResponseCatalog::from_config(&cfg.responses);
+    assert_eq!(
+        catalog.localize(RESP_411_NO_SUCH_GROUP),
+        "411 Die Newsgruppn gibt's ned\r\n"
+    );
+    assert_eq!(
+        catalog.localize(RESP_480_AUTH_REQUIRED),
+        "480 Anmeldung erforderlich\r\n"
+    );
+    // Lines carrying arguments keep their text
+    assert_eq!(catalog.localize("411 alt.test\r\n"), "411 alt.test\r\n");
+    assert_eq!(
+        catalog.localize("205 closing connection\r\n"),
+        "205 closing connection\r\n"
+    );
+}
+
+#[test]
+fn peer_filter_settings() {
+    let cfg_str = r#"addr = ":119"
+[[peers]]
+sitename = "hub.example.com"
+patterns = ["comp.*", "!comp.binaries.*", "@alt.spam"]
+distributions = ["*", "!local"]
+max_size_bytes = "1K"
+"#;
+    let cfg: Config = toml::from_str(cfg_str).unwrap();
+    let filter = &cfg.peers[0].filter;
+    assert_eq!(filter.max_size_bytes, Some(1024));
+    assert!(filter.wants_group("comp.lang.rust"));
+    assert!(!filter.wants_group("comp.binaries.misc"));
+    assert!(!filter.wants_group("alt.spam"));
+    assert_eq!(filter.included_patterns().collect::<Vec<_>>(), ["comp.*"]);
+
+    let article = |headers: &str, body: &str| {
+        renews::parse_message(&format!(
+            "Message-ID: <f@test>\r\n{headers}\r\n\r\n{body}\r\n"
+        ))
+        .unwrap()
+        .1
+    };
+    assert!(filter.accepts(&article("Newsgroups: comp.lang.rust", "hi")));
+    assert!(filter.accepts(&article(
+        "Newsgroups: comp.lang.rust,comp.binaries.misc",
+        "hi"
+    )));
+    assert!(!filter.accepts(&article("Newsgroups: comp.lang.rust,alt.spam", "hi")));
+    assert!(!filter.accepts(&article("Newsgroups: comp.binaries.misc", "hi")));
+    assert!(!filter.accepts(&article(
+        "Newsgroups: comp.lang.rust\r\nDistribution: local",
+        "hi"
+    )));
+    assert!(filter.accepts(&article(
+        "Newsgroups: comp.lang.rust\r\nDistribution: local, world",
+        "hi"
+    )));
+    assert!(!filter.accepts(&article("Newsgroups: comp.lang.rust", &"x".repeat(2048))));
+}
+
+#[test]
+fn article_compression_follows_group_rules() {
+    let cfg: Config = toml::from_str(
+        r#"
+addr = ":119"
+
+[[group_settings]]
+pattern = "alt.binaries.*"
+compress = true
+
+[[group_settings]]
+group = "alt.binaries.raw"
+compress = false
+"#,
+    )
+    .unwrap();
+    let compression = cfg.article_compression();
+    assert!(compression.compresses("alt.binaries.pictures"));
+    assert!(!compression.compresses("alt.binaries.raw"));
+    assert!(!compression.compresses("misc.test"));
+    let cfg: Config = toml::from_str("addr = \":119\"").unwrap();
+    assert!(
+        !cfg.article_compression()
+            .compresses("alt.binaries.pictures")
+    );
+}
@@ -26,7 +26,7 @@ fn test_config_missing_required_fields() {
     match empty_config {
         Ok(config) => {
             // If it succeeds, check that defaults are applied
-            assert!(config.addr.contains(":119") || config.addr.is_empty());
+            assert!(config.addr.iter().any(|a| a.contains(":119")) || config.addr.is_empty());
             assert!(config.db_path.contains("news.db"));
         }
         Err(_) => {
@@ -171,7 +171,7 @@ fn test_config_invalid_addresses() {
     // This might parse successfully but fail during binding
     let result: Result<Config, _> = toml::from_str(invalid_config);
     if let Ok(config) = result {
-        assert_eq!(config.addr, ":99999");
+        assert_eq!(config.addr, [":99999"]);
     }
 
     // Invalid address format
@@ -181,7 +181,7 @@ fn test_config_invalid_addresses() {
 
     let result: Result<Config, _> = toml::from_str(invalid_config);
     if let Ok(config) = result {
-        assert_eq!(config.addr, "invalid_address");
+        assert_eq!(config.addr, ["invalid_address"]);
     }
 }
 
@@ -290,3 +290,98 @@ fn test_config_invalid_group_patterns() {
         assert_eq!(config.group_settings[0].pattern, Some("[".to_string()));
     }
 }
+
+#[test]
+fn test_config_invalid_localized_responses() {
+    for table in [
+        "[responses.de]\nabc = \"kein Code\"\n",
+        "[responses.de]\n4110 = \"zu lang\"\n",
+        "[responses.de]\n411 = \"zwei\\nZeilen\"\n",
+    ] {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "addr = \":119\"\n{table}").unwrap();
+        let err = Config::from_file(temp_file.path().to_str().unwrap())
+            .err()
+            .unwrap_or_else(|| panic!("accepted {table}"));
+        assert!(err.to_string().contains("responses"), "{err}");
+    }
+}
+
+#[test]
+fn test_config_invalid_peer_transport() {
+    for settings in [
+        "tls_client_cert = \"feed.pem\"\n",
+        "tls_client_key = \"feed.key\"\n",
+        "tls = false\ntls_client_cert = \"feed.pem\"\ntls_client_key = \"feed.key\"\n",
+        "password = \"secret\"\n",
+    ] {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(
+            temp_file,
+            "addr = \":119\"\n[[peers]]\nsitename = \"hub.example.com\"\n{settings}"
+        )
+        .unwrap();
+        let err = Config::from_file(temp_file.path().to_str().unwrap())
+            .err()
+            .unwrap_or_else(|| panic!("accepted {settings}"));
+        assert!(err.to_string().contains("hub.example.com"), "{err}");
+    }
+}
+
+#[test]
+fn test_config_replication_without_secret() {
+    for settings in [
+        "listen = \":1190\"\n",
+        "primary = \"primary:1190\"\nsecret = \"\"\n",
+    ] {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "addr = \":119\"\n[replication]\n{settings}").unwrap();
+        let err = Config::from_file(temp_file.path().to_str().unwrap())
+            .err()
+            .unwrap_or_else(|| panic!("accepted {settings}"));
+        assert!(err.to_string().contains("[replication]"), "{err}");
+    }
+}
+
+#[test]
+fn test_config_invalid_auth_program() {
+    for settings in ["path = \" \"\n", "path = \"/bin/true\"\ntimeout_secs = 0\n"] {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "addr = \":119\"\n[auth_program]\n{settings}").unwrap();
+        let err = Config::from_file(temp_file.path().to_str().unwrap())
+            .err()
+            .unwrap_or_else(|| panic!("accepted {settings}"));
+        assert!(err.to_string().contains("[auth_program]"), "{err}");
+    }
+}
+
+#[test]
+fn test_config_ticket_lifetime_too_long() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(
+        temp_file,
+        "addr = \":119\"\n[tls]\nticket_lifetime_secs = 86400\n"
+    )
+    .unwrap();
+    let err = Config::from_file(temp_file.path().to_str().unwrap())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("[tls]"), "{err}");
+}
+
+#[test]
+fn test_config_invalid_shards() {
+    for shards in [
+        "[[shard]]\nname = \"a\"\ngroups = []\ndb_path = \"sqlite:///a.db\"\n",
+        "[[shard]]\nname = \"a\"\ngroups = [\"alt.*\"]\ndb_path = \"sqlite:///var/lib/renews/news.db\"\n",
+        "[[shard]]\nname = \"a\"\ngroups = [\"alt.*\"]\ndb_path = \"sqlite:///a.db\"\n\
+         [[shard]]\nname = \"b\"\ngroups = [\"comp.*\"]\ndb_path = \"sqlite:///a.db\"\n",
+    ] {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "addr = \":119\"\n{shards}").unwrap();
+        let err = Config::from_file(temp_file.path().to_str().unwrap())
+            .err()
+            .unwrap_or_else(|| panic!("accepted {shards}"));
+        assert!(err.to_string().contains("[[shard]]"), "{err}");
+    }
+}
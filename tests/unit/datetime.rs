@@ -0,0 +1,62 @@
+use chrono::{Datelike, TimeZone, Utc};
+use proptest::prelude::*;
+use renews::parse_datetime_at;
+
+fn now() -> chrono::DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 6, 15, 12, 0, 0).unwrap()
+}
+
+#[test]
+fn two_digit_years_near_the_boundary() {
+    let parse = |date: &str| parse_datetime_at(date, "000000", true, now()).unwrap();
+    assert_eq!(parse("260101").year(), 2026);
+    assert_eq!(parse("270101").year(), 1927);
+    assert_eq!(parse("000101").year(), 2000);
+    assert_eq!(parse("991231").year(), 1999);
+}
+
+#[test]
+fn rejects_impossible_dates() {
+    assert!(parse_datetime_at("20250230", "000000", true, now()).is_err());
+    assert!(parse_datetime_at("250230", "000000", true, now()).is_err());
+    assert!(parse_datetime_at("20250101", "246000", true, now()).is_err());
+}
+
+proptest! {
+    #[test]
+    fn gmt_round_trips_eight_digit_dates(secs in 0i64..4_102_444_800) {
+        let when = Utc.timestamp_opt(secs, 0).unwrap();
+        let date = when.format("%Y%m%d").to_string();
+        let time = when.format("%H%M%S").to_string();
+        prop_assert_eq!(parse_datetime_at(&date, &time, true, now()).unwrap(), when);
+    }
+
+    #[test]
+    fn six_digit_dates_resolve_within_a_century_of_now(
+        yy in 0u32..100,
+        month in 1u32..=12,
+        day in 1u32..=28,
+        now_year in 1971i32..2200,
+    ) {
+        let now = Utc.with_ymd_and_hms(now_year, 6, 15, 12, 0, 0).unwrap();
+        let date = format!("{yy:02}{month:02}{day:02}");
+        let parsed = parse_datetime_at(&date, "000000", true, now).unwrap();
+        prop_assert_eq!(parsed.year().rem_euclid(100) as u32, yy);
+        prop_assert!(parsed.year() <= now.year());
+        prop_assert!(parsed.year() > now.year() - 100);
+        prop_assert_eq!((parsed.month(), parsed.day()), (month, day));
+    }
+
+    #[test]
+    fn six_and_eight_digit_forms_agree(secs in 0i64..4_102_444_800) {
+        let when = Utc.timestamp_opt(secs, 0).unwrap();
+        let now = when + chrono::Duration::days(1);
+        let short = when.format("%y%m%d").to_string();
+        let long = when.format("%Y%m%d").to_string();
+        let time = when.format("%H%M%S").to_string();
+        prop_assert_eq!(
+            parse_datetime_at(&short, &time, true, now).unwrap(),
+            parse_datetime_at(&long, &time, true, now).unwrap()
+        );
+    }
+}
@@ -27,6 +27,8 @@ async fn test_header_filter_valid() {
         cfg: &cfg,
         article: &article,
         size: 100,
+        metadata: None,
+        poster: None,
     };
     let result = filter.validate(&ctx).await;
     assert!(result.is_ok());
@@ -53,6 +55,8 @@ async fn test_header_filter_missing_from() {
         cfg: &cfg,
         article: &article,
         size: 100,
+        metadata: None,
+        poster: None,
     };
     let result = filter.validate(&ctx).await;
     assert!(result.is_err());
@@ -70,6 +74,10 @@ async fn test_size_filter_within_limit() {
         pattern: Some("*".to_string()),
         retention_days: None,
         max_article_bytes: Some(1000),
+        post_users: None,
+        read_users: None,
+        compress: None,
+        moderated: None,
     });
 
     let article = Message {
@@ -83,6 +91,8 @@ async fn test_size_filter_within_limit() {
         cfg: &cfg,
         article: &article,
         size: 500,
+        metadata: None,
+        poster: None,
     };
     let result = filter.validate(&ctx).await;
     assert!(result.is_ok());
@@ -99,6 +109,10 @@ async fn test_size_filter_exceeds_limit() {
         pattern: Some("*".to_string()),
         retention_days: None,
         max_article_bytes: Some(1000),
+        post_users: None,
+        read_users: None,
+        compress: None,
+        moderated: None,
     });
 
     let article = Message {
@@ -112,6 +126,8 @@ async fn test_size_filter_exceeds_limit() {
         cfg: &cfg,
         article: &article,
         size: 1500,
+        metadata: None,
+        poster: None,
     };
     let result = filter.validate(&ctx).await;
     assert!(result.is_err());
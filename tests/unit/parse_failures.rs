@@ -14,8 +14,7 @@ fn test_parse_command_malformed() {
     // Command with null bytes (nom should handle this)
     let result = parse_command("COMMAND\0ARG");
     // This might succeed or fail depending on nom's behavior, let's test the actual result
-    if result.is_ok() {
-        let (_, cmd) = result.unwrap();
+    if let Ok((_, cmd)) = result {
         assert_eq!(cmd.name, "COMMAND");
         // The null byte might be in the argument
     }
@@ -25,8 +24,7 @@ fn test_parse_command_malformed() {
 fn test_parse_command_edge_cases() {
     // Command with special characters (nom will accept alphabetic chars)
     let result = parse_command("COMMAND@INVALID");
-    if result.is_ok() {
-        let (_, cmd) = result.unwrap();
+    if let Ok((_, cmd)) = result {
         assert_eq!(cmd.name, "COMMAND");
         // @ and everything after should be in args or remaining input
     }
@@ -37,16 +35,14 @@ fn test_parse_command_edge_cases() {
     // Very long command name (should handle gracefully)
     let long_cmd = "A".repeat(1000);
     let result = parse_command(&long_cmd);
-    if result.is_ok() {
-        let (_, cmd) = result.unwrap();
+    if let Ok((_, cmd)) = result {
         assert_eq!(cmd.name, long_cmd.to_uppercase());
     }
 
     // Command with many arguments
     let many_args = format!("CMD {}", vec!["arg"; 100].join(" "));
     let result = parse_command(&many_args);
-    if result.is_ok() {
-        let (_, cmd) = result.unwrap();
+    if let Ok((_, cmd)) = result {
         assert_eq!(cmd.args.len(), 100);
     }
 }
@@ -68,8 +64,7 @@ fn test_parse_response_malformed() {
 
     // Response code too long (digit1 will take all digits)
     let result = parse_response("1234 test");
-    if result.is_ok() {
-        let (_, resp) = result.unwrap();
+    if let Ok((_, resp)) = result {
         assert_eq!(resp.code, 1234);
     }
 }
@@ -0,0 +1,178 @@
+//! Tests for the bounded dot-terminated article reader
+
+use proptest::prelude::*;
+use renews::article_reader::{
+    ArticleLimits, ArticleReader, MAX_HEADER_BYTES, MAX_HEADERS, MalformedArticle,
+};
+use renews::handlers::utils::{ArticleBlock, read_article_block, read_message};
+use sha2::{Digest, Sha256};
+use tokio::io::BufReader;
+
+#[tokio::test]
+async fn test_read_article_block_metadata() {
+    let input = "Subject: T\r\nFrom: a@test\r\n\r\nline one\r\n..dotted\r\n.\r\nQUIT\r\n";
+    let mut reader = BufReader::new(input.as_bytes());
+
+    let ArticleBlock::Complete { text, metadata } =
+        read_article_block(&mut reader, None).await.unwrap()
+    else {
+        panic!("expected a complete block");
+    };
+
+    let expected = "Subject: T\r\nFrom: a@test\r\n\r\nline one\r\n.dotted\r\n";
+    assert_eq!(text, expected);
+    assert_eq!(metadata.size, expected.len() as u64);
+    assert_eq!(metadata.lines, 2);
+    assert_eq!(
+        metadata.sha256,
+        <[u8; 32]>::from(Sha256::digest(expected.as_bytes()))
+    );
+    assert_eq!(metadata.sha256_hex().len(), 64);
+}
+
+#[tokio::test]
+async fn test_read_article_block_too_large_drains_block() {
+    let input = format!("Subject: T\r\n\r\n{}\r\n.\r\nQUIT\r\n", "A".repeat(100));
+    let mut reader = BufReader::new(input.as_bytes());
+
+    match read_article_block(&mut reader, Some(32)).await.unwrap() {
+        ArticleBlock::TooLarge { size } => assert_eq!(size, 116),
+        other => panic!("expected the block to be rejected, got {other:?}"),
+    }
+
+    // The terminator was consumed, so the next command is readable
+    let mut rest = String::new();
+    tokio::io::AsyncBufReadExt::read_line(&mut reader, &mut rest)
+        .await
+        .unwrap();
+    assert_eq!(rest, "QUIT\r\n");
+}
+
+#[tokio::test]
+async fn test_read_article_block_at_limit() {
+    let input = "Subject: T\r\n\r\nbody\r\n.\r\n";
+    let mut reader = BufReader::new(input.as_bytes());
+    let result = read_article_block(&mut reader, Some(20)).await.unwrap();
+    assert!(matches!(result, ArticleBlock::Complete { .. }));
+}
+
+#[tokio::test]
+async fn test_read_article_block_eof_is_error() {
+    let mut reader = BufReader::new("Subject: T\r\n\r\nbody\r\n".as_bytes());
+    assert!(read_article_block(&mut reader, None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_read_message_unbounded() {
+    let mut reader = BufReader::new("Subject: T\r\n\r\n..x\r\n.\r\n".as_bytes());
+    let text = read_message(&mut reader).await.unwrap();
+    assert_eq!(text, "Subject: T\r\n\r\n.x\r\n");
+}
+
+async fn read(input: &[u8]) -> ArticleBlock {
+    read_article_block(&mut BufReader::new(input), None)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_read_article_block_refuses_bare_line_endings_and_nul() {
+    let cases: [(&[u8], MalformedArticle); 4] = [
+        (b"Subject: T\n\r\nbody\r\n.\r\n", MalformedArticle::BareLf),
+        (
+            b"Subject: T\r\n\r\nbo\rdy\r\n.\r\n",
+            MalformedArticle::BareCr,
+        ),
+        (b"Subject: T\r\n\r\nbo\0dy\r\n.\r\n", MalformedArticle::Nul),
+        (b"Subject: T\r\n\r\nbody\r\n.\n", MalformedArticle::BareLf),
+    ];
+    for (input, expected) in cases {
+        match read(input).await {
+            ArticleBlock::Malformed { reason } => assert_eq!(reason, expected),
+            other => panic!("expected {expected:?}, got {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_read_article_block_limits_headers() {
+    let many = format!("{}\r\nbody\r\n.\r\n", "X-H: v\r\n".repeat(MAX_HEADERS + 1));
+    assert!(matches!(
+        read(many.as_bytes()).await,
+        ArticleBlock::Malformed {
+            reason: MalformedArticle::TooManyHeaders
+        }
+    ));
+
+    // Continuation lines count towards the length of their field
+    let folded = format!(
+        "References: <a@b>\r\n{}\r\nbody\r\n.\r\n",
+        " <c@d>\r\n".repeat(MAX_HEADER_BYTES / 8)
+    );
+    assert!(matches!(
+        read(folded.as_bytes()).await,
+        ArticleBlock::Malformed {
+            reason: MalformedArticle::HeaderTooLong
+        }
+    ));
+
+    // Long body lines are only limited by the article size
+    let long_body = format!(
+        "Subject: T\r\n\r\n{}\r\n.\r\n",
+        "A".repeat(MAX_HEADER_BYTES * 2)
+    );
+    assert!(matches!(
+        read(long_body.as_bytes()).await,
+        ArticleBlock::Complete { .. }
+    ));
+}
+
+#[test]
+fn test_article_reader_stops_buffering_an_endless_header() {
+    let mut reader = ArticleReader::new(ArticleLimits::default());
+    let chunk = [b'x'; 4096];
+    for _ in 0..64 {
+        assert_eq!(reader.feed(&chunk), None);
+    }
+    assert_eq!(reader.buffered(), 0);
+    assert_eq!(reader.feed(b"\r\n\r\n.\r\nQUIT\r\n"), Some(7));
+    assert!(matches!(
+        reader.finish().unwrap(),
+        ArticleBlock::Malformed {
+            reason: MalformedArticle::HeaderTooLong
+        }
+    ));
+}
+
+fn read_in_chunks(input: &[u8], sizes: &[usize]) -> (Option<usize>, String) {
+    let mut reader = ArticleReader::new(ArticleLimits::with_max_bytes(Some(256)));
+    let mut offset = 0;
+    let mut sizes = sizes.iter().cycle();
+    while offset < input.len() {
+        let end = (offset + sizes.next().copied().unwrap_or(1)).min(input.len());
+        if let Some(used) = reader.feed(&input[offset..end]) {
+            offset += used;
+            return (Some(offset), format!("{:?}", reader.finish().ok()));
+        }
+        offset = end;
+    }
+    (None, String::new())
+}
+
+proptest! {
+    #[test]
+    fn chunking_does_not_change_the_result(
+        input in proptest::collection::vec(
+            prop_oneof![
+                Just(b'\r'), Just(b'\n'), Just(b'.'), Just(b' '), Just(b':'),
+                Just(0u8), b'a'..=b'z'
+            ],
+            0..400,
+        ),
+        sizes in proptest::collection::vec(1usize..16, 1..8),
+    ) {
+        let mut input = input;
+        input.extend_from_slice(b"\r\n.\r\n");
+        prop_assert_eq!(read_in_chunks(&input, &[input.len()]), read_in_chunks(&input, &sizes));
+    }
+}
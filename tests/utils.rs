@@ -2,11 +2,11 @@
 
 use rcgen::{CertifiedKey, generate_simple_self_signed};
 use renews::auth::AuthProvider;
-use renews::config::Config;
-use renews::handle_client;
+use renews::config::{Config, ListenerPolicy, ListenerRole};
 use renews::limits::UsageTracker;
 use renews::queue::ArticleQueue;
 use renews::storage::Storage;
+use renews::{ConnectionInfo, handle_client, handle_client_with_info};
 use std::sync::Arc;
 use tokio::io::BufReader;
 use tokio::io::{self, ReadHalf, WriteHalf};
@@ -56,11 +56,14 @@ pub fn capabilities_lines() -> Vec<String> {
         format!("IMPLEMENTATION Renews {}", env!("CARGO_PKG_VERSION")),
         "READER".into(),
         "NEWNEWS".into(),
-        "IHAVE".into(),
-        "STREAMING".into(),
         "OVER MSGID".into(),
         "HDR".into(),
-        "LIST ACTIVE NEWSGROUPS ACTIVE.TIMES OVERVIEW.FMT HEADERS".into(),
+        "LIST ACTIVE COUNTS NEWSGROUPS ACTIVE.TIMES OVERVIEW.FMT HEADERS PINNED EXPIRE ACTIVITY SUBSCRIPTIONS"
+            .into(),
+        "XZVER".into(),
+        "XTHREAD".into(),
+        "XBODYRANGE".into(),
+        "XFEATURE-COMPRESS GZIP TERMINATOR".into(),
         ".".into(),
     ]
 }
@@ -280,6 +283,151 @@ pub async fn setup_tls_server(
     (addr, cert, pem, handle)
 }
 
+/// Generate a CA and a client certificate it issued with common name `name`.
+///
+/// Returns the CA certificate in PEM format and the client certificate and key.
+pub fn generate_client_cert(name: &str) -> (String, rustls::Certificate, rustls::PrivateKey) {
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+    };
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "Test CA");
+    let ca_key = KeyPair::generate().unwrap();
+    let ca_pem = ca_params.self_signed(&ca_key).unwrap().pem();
+    let issuer = Issuer::new(ca_params, ca_key);
+
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let key = KeyPair::generate().unwrap();
+    let cert = params.signed_by(&key, &issuer).unwrap();
+    (
+        ca_pem,
+        rustls::Certificate(cert.der().to_vec()),
+        rustls::PrivateKey(key.serialize_der()),
+    )
+}
+
+/// Start a TLS server that accepts client certificates issued by the CA in
+/// `ca_file`, as the server does when `tls_client_ca` is set.
+pub async fn setup_client_cert_server(
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn AuthProvider>,
+    ca_file: &str,
+) -> (
+    std::net::SocketAddr,
+    rustls::Certificate,
+    tokio::task::JoinHandle<()>,
+) {
+    let (cert, key, _) = generate_self_signed_cert();
+    let tls_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(renews::client_cert::load_verifier(ca_file).unwrap())
+        .with_single_cert(vec![cert.clone()], key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let cfg: Arc<RwLock<Config>> = Arc::new(RwLock::new(toml::from_str("addr=\":119\"").unwrap()));
+    let queue = create_test_queue();
+    let cfg_read = cfg.read().await;
+    let usage_tracker = create_test_usage_tracker(auth.clone(), &cfg_read);
+    drop(cfg_read);
+
+    let handle = tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        let stream = acceptor.accept(sock).await.unwrap();
+        let info = renews::ConnectionInfo {
+            is_tls: true,
+            client_names: renews::client_cert::peer_names(&stream),
+            ..renews::ConnectionInfo::default()
+        };
+        renews::handle_client_with_info(stream, storage, auth, cfg, info, queue, usage_tracker)
+            .await
+            .unwrap();
+    });
+    (addr, cert, handle)
+}
+
+/// Connect to a TLS server presenting `client` as the client certificate.
+pub async fn connect_tls_with_client_cert(
+    addr: std::net::SocketAddr,
+    cert: rustls::Certificate,
+    client: (rustls::Certificate, rustls::PrivateKey),
+) -> BufReader<tokio_rustls::client::TlsStream<TcpStream>> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(&cert).unwrap();
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(vec![client.0], client.1)
+        .unwrap();
+    let connector = TlsConnector::from(Arc::new(config));
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let server_name = rustls::ServerName::try_from("localhost").unwrap();
+    BufReader::new(connector.connect(server_name, stream).await.unwrap())
+}
+
+/// Start a plaintext server that offers STARTTLS with a self-signed certificate.
+pub async fn setup_starttls_server(
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn AuthProvider>,
+) -> (
+    std::net::SocketAddr,
+    rustls::Certificate,
+    tokio::task::JoinHandle<()>,
+) {
+    let (cert, key, _) = generate_self_signed_cert();
+    let tls_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.clone()], key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let cfg: Arc<RwLock<Config>> = Arc::new(RwLock::new(toml::from_str("addr=\":119\"").unwrap()));
+    let queue = create_test_queue();
+    let cfg_read = cfg.read().await;
+    let usage_tracker = create_test_usage_tracker(auth.clone(), &cfg_read);
+    drop(cfg_read);
+
+    let handle = tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        renews::handle_client_with_starttls(
+            sock,
+            storage,
+            auth,
+            cfg,
+            acceptor,
+            queue,
+            usage_tracker,
+        )
+        .await
+        .unwrap();
+    });
+    (addr, cert, handle)
+}
+
+/// Negotiate TLS as a client over an existing connection after STARTTLS.
+pub async fn client_tls_handshake(
+    stream: TcpStream,
+    cert: rustls::Certificate,
+) -> tokio_rustls::client::TlsStream<TcpStream> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(&cert).unwrap();
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(config));
+    let server_name = rustls::ServerName::try_from("localhost").unwrap();
+    connector.connect(server_name, stream).await.unwrap()
+}
+
 pub async fn connect_tls(
     addr: std::net::SocketAddr,
     cert: rustls::Certificate,
@@ -312,6 +460,34 @@ pub async fn start_server(
     Option<(rustls::Certificate, String)>,
     tokio::task::JoinHandle<()>,
 ) {
+    start_server_with_role(storage, auth, cfg, tls, ListenerRole::All).await
+}
+
+/// A connection accepted by a listener of `role`.
+pub fn listener_connection(is_tls: bool, role: ListenerRole) -> ConnectionInfo {
+    ConnectionInfo {
+        is_tls,
+        policy: ListenerPolicy {
+            role,
+            ..ListenerPolicy::default()
+        },
+        ..ConnectionInfo::default()
+    }
+}
+
+/// Start a server for one connection accepted by a listener of `role`.
+pub async fn start_server_with_role(
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn AuthProvider>,
+    cfg: Config,
+    tls: bool,
+    role: ListenerRole,
+) -> (
+    std::net::SocketAddr,
+    Option<(rustls::Certificate, String)>,
+    tokio::task::JoinHandle<()>,
+) {
+    let info = move |is_tls| listener_connection(is_tls, role);
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
     let cfg = Arc::new(RwLock::new(cfg));
@@ -343,12 +519,12 @@ pub async fn start_server(
         let handle = tokio::spawn(async move {
             let (sock, _) = listener.accept().await.unwrap();
             let stream = acceptor.accept(sock).await.unwrap();
-            handle_client(
+            handle_client_with_info(
                 stream,
                 store_clone,
                 auth_clone,
                 cfg,
-                true,
+                info(true),
                 queue,
                 usage_tracker,
             )
@@ -374,12 +550,12 @@ pub async fn start_server(
 
         let handle = tokio::spawn(async move {
             let (sock, _) = listener.accept().await.unwrap();
-            handle_client(
+            handle_client_with_info(
                 sock,
                 store_clone,
                 auth_clone,
                 cfg,
-                false,
+                info(false),
                 queue,
                 usage_tracker,
             )
@@ -390,6 +566,54 @@ pub async fn start_server(
     }
 }
 
+/// Start a TLS server that keeps accepting connections until the returned
+/// handle is aborted, for peers that connect more than once.
+pub async fn start_tls_server_loop(
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn AuthProvider>,
+    cfg: Config,
+) -> (std::net::SocketAddr, String, tokio::task::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let usage_tracker = create_test_usage_tracker(auth.clone(), &cfg);
+    let cfg = Arc::new(RwLock::new(cfg));
+    let (cert, key, pem) = generate_self_signed_cert();
+    let tls_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+    let queue = create_test_queue();
+    let worker_pool = renews::queue::WorkerPool::new(
+        queue.clone(),
+        storage.clone(),
+        auth.clone(),
+        cfg.clone(),
+        2,
+    );
+    let _worker_handles = worker_pool.start().await;
+
+    let handle = tokio::spawn(async move {
+        loop {
+            let (sock, _) = listener.accept().await.unwrap();
+            let Ok(stream) = acceptor.accept(sock).await else {
+                continue;
+            };
+            tokio::spawn(handle_client(
+                stream,
+                storage.clone(),
+                auth.clone(),
+                cfg.clone(),
+                true,
+                queue.clone(),
+                usage_tracker.clone(),
+            ));
+        }
+    });
+    (addr, pem, handle)
+}
+
 pub async fn run_client(
     client: ClientMock,
     storage: Arc<dyn Storage>,
@@ -427,7 +651,19 @@ pub async fn run_client_with_cfg(
     auth: Arc<dyn AuthProvider>,
     tls: bool,
 ) {
-    let (addr, cert, handle) = start_server(storage, auth, cfg, tls).await;
+    run_client_with_role(client, cfg, storage, auth, tls, ListenerRole::All).await;
+}
+
+/// Run `client` against a listener of `role`.
+pub async fn run_client_with_role(
+    client: ClientMock,
+    cfg: Config,
+    storage: Arc<dyn Storage>,
+    auth: Arc<dyn AuthProvider>,
+    tls: bool,
+    role: ListenerRole,
+) {
+    let (addr, cert, handle) = start_server_with_role(storage, auth, cfg, tls, role).await;
     if let Some((c, _)) = cert {
         client.run_tls_at(addr, c).await;
     } else {
@@ -471,6 +707,23 @@ impl ClientMock {
     ) {
         run_client_with_cfg_tls(self, cfg, storage, auth).await;
     }
+
+    /// Run as a peer feeding articles to a transit listener.
+    pub async fn run_as_peer(self, storage: Arc<dyn Storage>, auth: Arc<dyn AuthProvider>) {
+        self.run_as_peer_with_cfg(toml::from_str("addr=\":119\"").unwrap(), storage, auth)
+            .await;
+    }
+
+    /// Run as a peer feeding articles to a transit listener of a server
+    /// configured with `cfg`.
+    pub async fn run_as_peer_with_cfg(
+        self,
+        cfg: Config,
+        storage: Arc<dyn Storage>,
+        auth: Arc<dyn AuthProvider>,
+    ) {
+        run_client_with_role(self, cfg, storage, auth, false, ListenerRole::Transit).await;
+    }
 }
 
 /// Builder to mock a client connection using `tokio_test::io`.
@@ -649,28 +902,76 @@ pub async fn create_test_queue_with_workers(
 /// Create a test configuration with minimal settings
 pub fn create_minimal_config() -> Config {
     Config {
-        addr: "127.0.0.1:0".to_string(),
+        addr: vec!["127.0.0.1:0".to_string()],
         site_name: "test".to_string(),
+        path_aliases: Vec::new(),
         db_path: "sqlite::memory:".to_string(),
         auth_db_path: "sqlite::memory:".to_string(),
         peer_db_path: "sqlite::memory:".to_string(),
         peer_sync_schedule: "0 0 * * * *".to_string(),
         idle_timeout_secs: 600,
+        clock_skew_secs: 300,
+        max_message_bytes: Some(64 * 1024 * 1024),
+        max_over_range: None,
+        overview_headers: Vec::new(),
         peers: vec![],
         tls_addr: None,
         tls_cert: None,
         tls_key: None,
+        tls_client_ca: None,
+        tls: Default::default(),
         ws_addr: None,
+        http_addr: None,
+        control_socket: None,
+        health_addr: None,
+        gateway_addr: None,
+        listeners: vec![],
         article_queue_capacity: 10,
         article_worker_count: 2,
+        article_worker_max_count: None,
+        article_queue_journal: None,
+        transit_validation: Default::default(),
+        article_cache_bytes: None,
+        article_cache_ttl_secs: None,
+        overview_cache_bytes: None,
         group_settings: vec![],
+        default_subscriptions: vec![],
         filters: vec![],
+        digests: vec![],
+        digest_schedule: "0 0 0 * * *".to_string(),
+        bridges: vec![],
+        gateways: vec![],
+        bridge_schedule: "0 */15 * * * *".to_string(),
+        sendmail_path: "/usr/sbin/sendmail".to_string(),
         pgp_key_servers: renews::config::default_pgp_key_servers(),
+        pgp_key_refresh_secs: 0,
         allow_auth_insecure_connections: false,
+        require_tls_for_auth: false,
         allow_anonymous_posting: false,
+        cancel_lock_secret: None,
+        detect_binaries: false,
+        history_retention_days: 10,
+        audit_retention_days: 365,
+        rejected_retention_days: 0,
+        maintenance_schedule: None,
+        max_connections_per_ip: 0,
         runtime_threads: 4,
         logging: Default::default(),
         user_limits: Default::default(),
+        posting_accounts: Default::default(),
+        address_bans: Default::default(),
+        login_throttle: Default::default(),
+        responses: Default::default(),
+        nocem: Default::default(),
+        trace: Default::default(),
+        control_policy: Vec::new(),
+        checkgroups_mode: Default::default(),
+        sqlite: Default::default(),
+        shards: Vec::new(),
+        replication: Default::default(),
+        unix_socket: Default::default(),
+        auth_program: None,
+        vhosts: vec![],
     }
 }
 
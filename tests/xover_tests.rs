@@ -123,6 +123,7 @@ async fn test_xover_command_basic() {
         },
         queue,
         usage_tracker,
+        progress: Default::default(),
     };
 
     // Test XOVER command with range
@@ -171,6 +172,7 @@ async fn test_xover_without_group() {
         session: Session::new(false, false, false),
         queue,
         usage_tracker,
+        progress: Default::default(),
     };
 
     // Test XOVER command without current group
@@ -227,6 +229,7 @@ async fn test_xover_single_article() {
         },
         queue,
         usage_tracker,
+        progress: Default::default(),
     };
 
     // Test XOVER command with single article
@@ -287,6 +290,7 @@ async fn test_xover_current_article() {
         },
         queue,
         usage_tracker,
+        progress: Default::default(),
     };
 
     // Test XOVER command without arguments (current article)
@@ -302,3 +306,105 @@ async fn test_xover_current_article() {
     assert!(output.contains("<current@example.com>"));
     assert!(output.ends_with(".\r\n"));
 }
+
+// Build a context with two stored articles and "test.group" selected
+async fn compressed_overview_context(
+    buffer: Arc<Mutex<Vec<u8>>>,
+) -> (HandlerContext, NamedTempFile) {
+    let db_file = NamedTempFile::new().unwrap();
+    let db_path = format!("sqlite://{}", db_file.path().display());
+    let storage = open(&db_path).await.unwrap();
+    storage.add_group("test.group", false).await.unwrap();
+    for n in 1..=2 {
+        let article = create_test_article(
+            &format!("Test Subject {n}"),
+            "user@example.com",
+            &format!("<msg{n}@example.com>"),
+            "test.group",
+        );
+        storage.store_article(&article).await.unwrap();
+    }
+
+    let config = Arc::new(RwLock::new(toml::from_str("addr=\":119\"").unwrap()));
+    let auth = Arc::new(SqliteAuth::new(":memory:").await.unwrap());
+    let usage_tracker = Arc::new(UsageTracker::new(auth.clone(), Default::default()));
+    let mut session = Session::new(false, false, false);
+    session.select_group("test.group".to_string(), Some(1));
+
+    let ctx = HandlerContext {
+        reader: Box::pin(io::empty()),
+        writer: Box::pin(MockWriter::new(buffer)),
+        storage,
+        auth,
+        config,
+        session,
+        queue: ArticleQueue::new(1000),
+        usage_tracker,
+        progress: Default::default(),
+    };
+    (ctx, db_file)
+}
+
+fn inflate(data: &[u8]) -> String {
+    use std::io::Read;
+    let mut out = String::new();
+    flate2::read::ZlibDecoder::new(data)
+        .read_to_string(&mut out)
+        .unwrap();
+    out
+}
+
+#[tokio::test]
+async fn test_xzver_returns_yenc_compressed_overview() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let (mut ctx, _db) = compressed_overview_context(buffer.clone()).await;
+
+    let (_, cmd) = parse_command("XZVER 1-2").unwrap();
+    dispatch_command(&mut ctx, &cmd).await.unwrap();
+
+    let output = buffer.lock().await.clone();
+    let header = b"224 compressed overview information follows\r\n";
+    assert!(output.starts_with(header));
+    assert!(output.ends_with(b".\r\n"));
+
+    let encoded = &output[header.len()..output.len() - 3];
+    let compressed = renews::compress::yenc_decode(encoded).unwrap();
+    let text = inflate(&compressed);
+    assert!(text.starts_with("1\tTest Subject 1\t"));
+    assert!(text.contains("2\tTest Subject 2\t"));
+    assert!(!text.contains(".\r\n"));
+}
+
+#[tokio::test]
+async fn test_xfeature_compress_gzip_terminator() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let (mut ctx, _db) = compressed_overview_context(buffer.clone()).await;
+
+    let (_, cmd) = parse_command("XFEATURE COMPRESS GZIP TERMINATOR").unwrap();
+    dispatch_command(&mut ctx, &cmd).await.unwrap();
+    assert_eq!(buffer.lock().await.as_slice(), b"290 feature enabled\r\n");
+    buffer.lock().await.clear();
+
+    let (_, cmd) = parse_command("XOVER 1-2").unwrap();
+    dispatch_command(&mut ctx, &cmd).await.unwrap();
+
+    let output = buffer.lock().await.clone();
+    let header = b"224 Overview information follows\r\n";
+    assert!(output.starts_with(header));
+    let text = inflate(&output[header.len()..]);
+    assert!(text.starts_with("1\tTest Subject 1\t"));
+    assert!(text.ends_with("\r\n.\r\n"));
+}
+
+#[tokio::test]
+async fn test_xfeature_rejects_unknown_feature() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let (mut ctx, _db) = compressed_overview_context(buffer.clone()).await;
+
+    let (_, cmd) = parse_command("XFEATURE COMPRESS BZIP2").unwrap();
+    dispatch_command(&mut ctx, &cmd).await.unwrap();
+    assert_eq!(
+        buffer.lock().await.as_slice(),
+        b"503 feature not supported\r\n"
+    );
+}